@@ -0,0 +1,67 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::runtime::Runtime;
+
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::mem::Frame;
+use cobra_rs::transport::tcp::{Conn, ConnConfig, Listener};
+
+const KIND: u8 = 1;
+const FRAMES: usize = 256;
+const FRAME_LENS: [usize; 3] = [64, 4 * 1024, 60 * 1024];
+
+async fn connect(addr: &str, config: ConnConfig) -> (Conn, Conn) {
+    let listener = Listener::listen_with_config(addr, config.clone()).await.unwrap();
+    let client = Conn::connect_with_config(addr, config).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    (client, server)
+}
+
+async fn transfer(client: &Conn, server: &Conn, body: &[u8]) {
+    let writes = async {
+        for _ in 0..FRAMES {
+            client.write(Frame::create(KIND, body)).await.ok().unwrap();
+        }
+    };
+    let reads = async {
+        for _ in 0..FRAMES {
+            server.read(KIND).await.unwrap();
+        }
+    };
+    tokio::join!(writes, reads);
+}
+
+fn configs() -> Vec<(&'static str, ConnConfig)> {
+    #[allow(unused_mut)]
+    let mut configs = vec![("reactor", ConnConfig::new())];
+
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    {
+        configs[0].1 = ConnConfig::new().set_uring(false);
+        configs.push(("uring", ConnConfig::new()));
+    }
+
+    configs
+}
+
+fn throughput(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("throughput");
+
+    for (port, (name, config)) in (5610..).zip(configs()) {
+        let addr = format!("127.0.0.1:{}", port);
+        let (client, server) = runtime.block_on(connect(&addr, config));
+
+        for len in FRAME_LENS {
+            let body = vec![0; len];
+            group.throughput(Throughput::Bytes((FRAMES * len) as u64));
+            group.bench_with_input(BenchmarkId::new(name, len), &body, |b, body| {
+                b.to_async(&runtime).iter(|| transfer(&client, &server, body));
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
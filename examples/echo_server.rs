@@ -0,0 +1,46 @@
+//! Echoes every package back to the sender
+//!
+//! ```text
+//! cargo run --example echo_server -- [--addr 127.0.0.1:5000]
+//! ```
+//!
+//! Serves `throughput` and `latency` examples
+
+use std::env;
+use std::time::Duration;
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::providers::default_ping_provider::DefaultPingProvider;
+use cobra_rs::transport::tcp::Listener;
+
+#[tokio::main]
+async fn main() {
+    let addr = arg("--addr").unwrap_or_else(|| "127.0.0.1:5000".to_string());
+    let listener = Listener::listen(&addr).await.unwrap();
+    println!("Echoing on {}", addr);
+
+    while let Some((conn, peer)) = listener.accept().await {
+        tokio::spawn(async move {
+            let ping = DefaultPingProvider::new(Duration::from_secs(6), Duration::from_secs(2));
+            let conn = match Builder::new().set_conn(conn).set_ping(ping).run().await {
+                Ok(conn) => conn,
+                Err(error) => {
+                    eprintln!("Handshake with {} failed: {:?}", peer, error);
+                    return;
+                }
+            };
+
+            while let Some(package) = conn.read().await {
+                if conn.write(package).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+fn arg(name: &str) -> Option<String> {
+    let mut args = env::args().skip_while(|arg| arg != name);
+    args.next()?;
+    args.next()
+}
@@ -0,0 +1,66 @@
+//! Probes round-trip latency of packages echoed by `echo_server`
+//!
+//! ```text
+//! cargo run --release --example latency -- [--addr 127.0.0.1:5000]
+//!     [--size 64] [--count 1000] [--interval 10]
+//! ```
+//!
+//! Writes `--count` packages of `--size` bytes one at a time, waiting
+//! `--interval` milliseconds between them, and prints percentiles
+
+use std::env;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::builder::kind_conn::close_code::CLOSED_BY_USER;
+use cobra_rs::providers::default_ping_provider::DefaultPingProvider;
+use cobra_rs::transport::tcp::Conn;
+
+// Frame length is two bytes, so packages stay below it
+const MAX_SIZE: usize = 60 * 1024;
+
+#[tokio::main]
+async fn main() {
+    let addr = arg("--addr").unwrap_or_else(|| "127.0.0.1:5000".to_string());
+    let size = parsed_arg("--size", 64).min(MAX_SIZE);
+    let count = parsed_arg("--count", 1000).max(1);
+    let interval = Duration::from_millis(parsed_arg("--interval", 10));
+
+    let conn = Builder::new()
+        .set_conn(Conn::connect(&addr).await.unwrap())
+        .set_ping(DefaultPingProvider::new(Duration::from_secs(6), Duration::from_secs(2)))
+        .run()
+        .await
+        .unwrap();
+    let package = vec![0; size];
+
+    let mut samples = Vec::with_capacity(count);
+    for _ in 0..count {
+        let started = Instant::now();
+        conn.write(package.clone()).await.ok().unwrap();
+        conn.read().await.unwrap();
+        samples.push(started.elapsed());
+
+        tokio::time::sleep(interval).await;
+    }
+    conn.close(CLOSED_BY_USER).await;
+
+    samples.sort_unstable();
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+    println!("{} packages of {} bytes", count, size);
+    println!("min {:?}", samples[0]);
+    println!("p50 {:?}", percentile(50));
+    println!("p99 {:?}", percentile(99));
+    println!("max {:?}", samples[samples.len() - 1]);
+}
+
+fn arg(name: &str) -> Option<String> {
+    let mut args = env::args().skip_while(|arg| arg != name);
+    args.next()?;
+    args.next()
+}
+
+fn parsed_arg<T: FromStr>(name: &str, default: T) -> T {
+    arg(name).and_then(|value| value.parse().ok()).unwrap_or(default)
+}
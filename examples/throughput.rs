@@ -0,0 +1,87 @@
+//! Measures throughput of packages echoed by `echo_server`
+//!
+//! ```text
+//! cargo run --release --example throughput -- [--addr 127.0.0.1:5000]
+//!     [--size 4096] [--concurrency 4] [--duration 10]
+//! ```
+//!
+//! Every connection writes packages of `--size` bytes for `--duration`
+//! seconds and counts packages echoed back in that time
+
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tokio::time::{timeout_at, Instant};
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::builder::kind_conn::close_code::CLOSED_BY_USER;
+use cobra_rs::providers::default_ping_provider::DefaultPingProvider;
+use cobra_rs::transport::tcp::Conn;
+
+// Frame length is two bytes, so packages stay below it
+const MAX_SIZE: usize = 60 * 1024;
+
+#[tokio::main]
+async fn main() {
+    let addr = arg("--addr").unwrap_or_else(|| "127.0.0.1:5000".to_string());
+    let size = parsed_arg("--size", 4096).min(MAX_SIZE);
+    let concurrency = parsed_arg("--concurrency", 4).max(1);
+    let duration = Duration::from_secs(parsed_arg("--duration", 10));
+
+    println!("{} connections, {} byte packages, {:?}", concurrency, size, duration);
+    let deadline = Instant::now() + duration;
+
+    let workers: Vec<_> = (0..concurrency)
+        .map(|_| tokio::spawn(worker(addr.clone(), size, deadline)))
+        .collect();
+
+    let mut echoed = 0;
+    for worker in workers {
+        echoed += worker.await.unwrap();
+    }
+
+    let seconds = duration.as_secs_f64();
+    println!("{:.0} packages/s", echoed as f64 / seconds);
+    println!("{:.2} MiB/s", (echoed * size) as f64 / seconds / (1024.0 * 1024.0));
+}
+
+// Returns number of packages echoed before the deadline
+async fn worker(addr: String, size: usize, deadline: Instant) -> usize {
+    let conn = Builder::new()
+        .set_conn(Conn::connect(&addr).await.unwrap())
+        .set_ping(DefaultPingProvider::new(Duration::from_secs(6), Duration::from_secs(2)))
+        .run()
+        .await
+        .unwrap();
+    let package = vec![0; size];
+
+    let writes = async {
+        while Instant::now() < deadline {
+            if conn.write(package.clone()).await.is_err() {
+                break;
+            }
+        }
+    };
+    let reads = async {
+        let mut echoed = 0;
+        while let Ok(Some(_)) = timeout_at(deadline, conn.read()).await {
+            echoed += 1;
+        }
+        echoed
+    };
+
+    let (_, echoed) = tokio::join!(writes, reads);
+    conn.close(CLOSED_BY_USER).await;
+    echoed
+}
+
+fn arg(name: &str) -> Option<String> {
+    let mut args = env::args().skip_while(|arg| arg != name);
+    args.next()?;
+    args.next()
+}
+
+fn parsed_arg<T: FromStr>(name: &str, default: T) -> T {
+    arg(name).and_then(|value| value.parse().ok()).unwrap_or(default)
+}
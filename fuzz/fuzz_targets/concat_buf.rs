@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use cobra_rs::mem::{Chunk, ConcatBuf, Frame, GrowthPolicy};
+use cobra_rs::sync::Kind;
+
+// The first byte picks read sizes, the rest is the stream
+fuzz_target!(|data: &[u8]| {
+    let (read_len, stream) = match data.split_first() {
+        Some((len, stream)) => (*len as usize + 1, stream),
+        None => return,
+    };
+
+    let mut buf: ConcatBuf<Frame> = ConcatBuf::with_policy(GrowthPolicy::new(16, 1024));
+    for read in stream.chunks(read_len) {
+        buf.extend_from_slice(read);
+        buf.adapt_capacity();
+
+        while let Some(frame) = buf.try_read_chunk() {
+            assert!(frame.len() <= Frame::header_len() + Frame::max_body_len());
+            let _ = frame.kind();
+            let _ = frame.get_body();
+        }
+    }
+    assert!(buf.buffered() <= stream.len());
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use cobra_rs::mem::{ConcatBuf, Frame};
+use cobra_rs::transport::control::ControlFrame;
+
+fuzz_target!(|data: &[u8]| {
+    let mut buf: ConcatBuf<Frame> = ConcatBuf::default();
+    buf.extend_from_slice(data);
+
+    while let Some(frame) = buf.try_read_chunk() {
+        if let Some(control) = ControlFrame::decode(&frame) {
+            assert_eq!(ControlFrame::decode(&control.encode()), Some(control));
+        }
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use cobra_rs::protocol::{ControlMessage, Encoding};
+
+fuzz_target!(|data: &[u8]| {
+    for encoding in [Encoding::Fixed, Encoding::Cbor] {
+        if let Some(message) = ControlMessage::decode(data, encoding) {
+            assert_eq!(ControlMessage::decode(&message.encode(encoding), encoding), Some(message));
+        }
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use cobra_rs::builder::version::{VersionFrame, MIN_PROTOCOL_VERSION};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(frame) = VersionFrame::decode(data) {
+        assert!(frame.version >= MIN_PROTOCOL_VERSION);
+
+        // Known fields survive encoding
+        let decoded = VersionFrame::decode(&frame.encode()).unwrap();
+        assert_eq!(decoded.version, frame.version);
+        assert_eq!(decoded.kind_width, frame.kind_width);
+    }
+});
@@ -0,0 +1,75 @@
+#ifndef COBRA_H
+#define COBRA_H
+
+#include <stddef.h>
+#include <stdint.h>
+#include <sys/types.h>
+
+/*
+ * Frame layout:
+ *
+ *   [len: 2 bytes, big-endian][kind: 1 byte][body: len - 1 bytes]
+ *
+ * Length field covers kind and body.
+ *
+ * Kinds COBRA_WIDE_KIND_U16 and COBRA_WIDE_KIND_U32 escape wide kinds,
+ * their body starts with a 2- or 4-byte big-endian kind:
+ *
+ *   [len: 2 bytes][escape kind: 1 byte][kind: 2 or 4 bytes][body]
+ *
+ * The widest supported kind width is sent in the version frame
+ * as one of COBRA_KIND_WIDTH_* codes.
+ *
+ * Negotiation:
+ *
+ * Both peers start the handshake with a frame of COBRA_VERSION_KIND:
+ *
+ *   [version: 1 byte][kind width: 1 byte][tie-breaker: 8 bytes, big-endian]
+ *
+ * and use the lower of two versions. Versions older than
+ * COBRA_MIN_PROTOCOL_VERSION are refused. Close handshake, write
+ * shutdown and round trip probes go in frames of COBRA_CONTROL_KIND.
+ * The gzip compression provider offers deflate containers as
+ * COBRA_FLATE_* codes.
+ */
+
+#define COBRA_OK 0
+#define COBRA_ERR_NULL (-1)
+#define COBRA_ERR_TOO_LARGE (-2)
+#define COBRA_ERR_BUFFER (-3)
+#define COBRA_ERR_MALFORMED (-4)
+
+#define COBRA_WIDE_KIND_U16 254
+#define COBRA_WIDE_KIND_U32 255
+
+#define COBRA_KIND_WIDTH_U8 1
+#define COBRA_KIND_WIDTH_U16 2
+#define COBRA_KIND_WIDTH_U32 4
+
+#define COBRA_CONTROL_KIND 0
+#define COBRA_VERSION_KIND 12
+#define COBRA_PROTOCOL_VERSION 10
+#define COBRA_MIN_PROTOCOL_VERSION 1
+
+#define COBRA_FLATE_GZIP 1
+#define COBRA_FLATE_ZLIB 2
+#define COBRA_FLATE_DEFLATE 3
+
+size_t cobra_frame_len_bytes(void);
+size_t cobra_frame_kind_bytes(void);
+size_t cobra_frame_header_len(void);
+size_t cobra_frame_max_body_len(void);
+
+ssize_t cobra_frame_encode(uint8_t kind,
+                           const uint8_t *body,
+                           size_t body_len,
+                           uint8_t *out,
+                           size_t out_len);
+
+ssize_t cobra_frame_decode(const uint8_t *data,
+                           size_t data_len,
+                           uint8_t *kind,
+                           size_t *body_offset,
+                           size_t *body_len);
+
+#endif /* COBRA_H */
@@ -0,0 +1,90 @@
+//! Inspects live cobra servers
+//!
+//! ```text
+//! cobra-admin <socket> list               connections of the server
+//! cobra-admin <socket> stats <id>         stats of the connection
+//! cobra-admin <socket> kick <id> [code]   closes the connection
+//! cobra-admin peer <addr>                 stats the server exposes over the wire
+//! ```
+//!
+//! The socket is served by `AdminSocket`, stats over the wire are
+//! answered by servers built with `Builder::expose_stats()`
+
+use std::env;
+use std::process;
+use std::time::Duration;
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::builder::kind_conn::close_code::CLOSED_BY_USER;
+use cobra_rs::transport::tcp::Conn;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+const USAGE: &str = "usage: cobra-admin <socket> list | stats <id> | kick <id> [code]\n       cobra-admin peer <addr>";
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["peer", addr] => peer(addr).await,
+        [socket, ref command @ ..] if !command.is_empty() => admin(socket, &command.join(" ")).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    match result {
+        Ok(answer) => {
+            print!("{}", answer);
+            if answer.starts_with("error:") {
+                process::exit(1);
+            }
+        }
+        Err(error) => {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+async fn admin(socket: &str, command: &str) -> Result<String, String> {
+    let mut stream = UnixStream::connect(socket).await.map_err(|error| error.to_string())?;
+    stream.write_all(format!("{}\n", command).as_bytes()).await.map_err(|error| error.to_string())?;
+
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer).await.map_err(|error| error.to_string())?;
+    Ok(answer)
+}
+
+async fn peer(addr: &str) -> Result<String, String> {
+    let conn = Conn::connect_timeout(addr, TIMEOUT).await.map_err(|error| error.to_string())?;
+    let conn = Builder::new()
+        .set_conn(conn)
+        .handshake_timeout(TIMEOUT)
+        .run()
+        .await
+        .map_err(|error| format!("{:?}", error))?;
+
+    let stats = conn.peer_stats(TIMEOUT).await.map_err(|error| format!("{:?}", error));
+    conn.close(CLOSED_BY_USER).await;
+    let stats = stats?;
+
+    Ok(format!("protocol version: {}\n\
+                kind width: {} bytes\n\
+                open kinds: {}\n\
+                queued bytes: {}\n\
+                queued frames: {}\n\
+                pending writes: {}\n\
+                rtt: {}\n",
+               stats.protocol_version,
+               stats.kind_width.bytes(),
+               stats.open_kinds,
+               stats.transport.queued_bytes,
+               stats.transport.queued_frames,
+               stats.transport.pending_writes,
+               stats.transport.rtt.map_or_else(|| "unknown".to_string(), |rtt| format!("{:?}", rtt))))
+}
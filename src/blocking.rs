@@ -0,0 +1,153 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::ToSocketAddrs;
+use tokio::runtime::Runtime;
+
+use crate::builder::builder::{BuildError, Builder};
+use crate::builder::kind_conn::KindConn;
+use crate::runtime;
+use crate::sync::WriteError;
+use crate::transport::tcp::Conn;
+
+/// Error returned by [`Client::connect()`]
+///
+/// [`Client::connect()`]: crate::blocking::Client::connect
+#[derive(Debug)]
+pub enum ConnectError {
+    /// Runtime couldn't be started or the peer wasn't reached
+    Io(io::Error),
+
+    /// Connection was established, but the handshake failed
+    Build(BuildError),
+}
+
+/// Synchronous connection for code which doesn't run an async runtime
+///
+/// Every client starts a tokio runtime with one worker thread, which
+/// drives the connection between calls (e.g. answers pings)
+///
+/// # Note
+///
+/// Methods block the current thread, so they must not be called
+/// from async code, use [`KindConn`] there
+///
+/// # Example
+///
+/// ```no_run
+/// use cobra_rs::blocking::Client;
+///
+/// let client = Client::connect("127.0.0.1:5000").unwrap();
+/// client.write(b"hello".to_vec()).unwrap();
+/// println!("{:?}", client.read());
+/// ```
+///
+/// [`KindConn`]: crate::builder::kind_conn::KindConn
+pub struct Client {
+    // Dropped inside the runtime, see Drop
+    conn: Option<KindConn>,
+    runtime: Runtime,
+}
+
+impl From<io::Error> for ConnectError {
+    fn from(error: io::Error) -> Self {
+        ConnectError::Io(error)
+    }
+}
+
+impl From<BuildError> for ConnectError {
+    fn from(error: BuildError) -> Self {
+        ConnectError::Build(error)
+    }
+}
+
+impl Client {
+    /// Connects to the address and builds the connection with default providers
+    pub fn connect<T: ToSocketAddrs>(addr: T) -> Result<Self, ConnectError> {
+        Client::connect_with(addr, Builder::new())
+    }
+
+    /// Connects to the address and builds the connection with providers of `builder`
+    pub fn connect_with<T: ToSocketAddrs>(addr: T, builder: Builder) -> Result<Self, ConnectError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+
+        let conn = runtime.block_on(async {
+            let conn = Conn::connect(addr).await?;
+            Ok::<_, ConnectError>(builder.set_conn(conn).run().await?)
+        })?;
+
+        Ok(Client { conn: Some(conn), runtime })
+    }
+
+    /// Returns connection of the client, its async methods can be
+    /// run with [`block_on()`]
+    ///
+    /// [`block_on()`]: crate::blocking::Client::block_on
+    pub fn conn(&self) -> &KindConn {
+        self.conn.as_ref().unwrap()
+    }
+
+    /// Runs the future on the runtime of the client
+    pub fn block_on<F: std::future::Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Blocks until a package is received, returns [`None`] if the connection is closed
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn read(&self) -> Option<Vec<u8>> {
+        self.block_on(self.conn().read())
+    }
+
+    /// The same as [`read()`], but fails with [`TimedOut`] error
+    /// if no package is received in time
+    ///
+    /// [`read()`]: crate::blocking::Client::read
+    /// [`TimedOut`]: std::io::ErrorKind::TimedOut
+    pub fn read_timeout(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        self.block_on(runtime::timeout(timeout, self.conn().read()))
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no package received"))
+    }
+
+    /// Blocks until the package is accepted by the connection
+    pub fn write(&self, package: Vec<u8>) -> Result<(), WriteError<Vec<u8>>> {
+        self.block_on(self.conn().write(package))
+    }
+
+    /// Blocks until written packages are sent
+    pub fn flush(&self) {
+        self.block_on(self.conn().flush())
+    }
+
+    /// Closes the connection with the code, see [`close_code`]
+    ///
+    /// [`close_code`]: crate::builder::kind_conn::close_code
+    pub fn close(&self, code: u8) {
+        self.block_on(self.conn().close(code))
+    }
+
+    /// Returns close code if the connection is closed
+    pub fn is_close(&self) -> Option<u8> {
+        self.block_on(self.conn().is_close())
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.conn().local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.conn().peer_addr()
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        // Connection spawns its cleanup on the runtime
+        let _guard = self.runtime.enter();
+        self.conn.take();
+    }
+}
@@ -1,14 +1,36 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
 use std::net::SocketAddr;
+use std::ops::Range;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 
 use async_trait::async_trait;
 
-use crate::builder::context::{Context, ContextMode};
+use crate::builder::dependency::{InitOrder, Provider};
+use crate::builder::context::{panic_reason, Context, ContextMode, KindProviders, ProviderSlot, FIRST_APPLICATION_KIND, LAST_APPLICATION_KIND};
 use crate::builder::empty_realisations::EmptyRealisation;
+use crate::builder::handshake::{Handshake, HandshakeExtension};
+use crate::builder::identity::{IdentityVerifier, PeerIdentity};
+use crate::builder::kind_conn::close_code::{CANCELLED, HANDSHAKE_TIMEOUT, IDENTITY_REJECTED, PROVIDER_PANIC, UNSUPPORTED_VERSION};
 use crate::builder::kind_conn::KindConn;
-use crate::mem::Frame;
-use crate::sync::WriteError;
+use crate::builder::kind_refs::{KindRefs, DEFAULT_KIND_QUARANTINE};
+use crate::builder::profile::Profile;
+use crate::builder::rekey::{KeyRotation, Rekey};
+use crate::builder::stats::{CompressionStats, TransportStats};
+use crate::builder::validation::{Capabilities, ConfigProblem};
+use crate::builder::version::{Features, Version, VersionFrame, DEFAULT_VERSION_TIMEOUT, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use crate::builder::wide_kind::WideKinds;
+use crate::config::PartialConfig;
+use crate::debug::{FrameHook, FrameRecorder, ObservedConn};
+use crate::mem::{Frame, KindWidth};
+use crate::protocol::Encoding;
+use crate::runtime;
+use crate::sync::{CancelToken, WriteError};
+use crate::transport::close_cause::CloseCause;
+use crate::transport::file::write_file_frames;
 use std::io;
+use std::time::Duration;
 
 #[async_trait]
 pub trait ConnProvider: Send + Sync {
@@ -16,6 +38,55 @@ pub trait ConnProvider: Send + Sync {
 
     async fn write(&self, frame: Frame) -> Result<(), WriteError<Frame>>;
 
+    /// Writes a frame ahead of already queued ones
+    ///
+    /// Intended for small control and cancellation messages.
+    /// By default the same as [`write()`]
+    ///
+    /// [`write()`]: crate::builder::builder::ConnProvider::write
+    async fn write_urgent(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        self.write(frame).await
+    }
+
+    async fn flush(&self) {}
+
+    /// Returns counters of the transport, see [`KindConn::stats()`]
+    ///
+    /// By default all of them are zero and the round-trip time is unknown
+    ///
+    /// [`KindConn::stats()`]: crate::builder::kind_conn::KindConn::stats
+    fn stats(&self) -> TransportStats {
+        TransportStats::default()
+    }
+
+    /// Writes region of the file as frames of the kind
+    ///
+    /// Region is split into frames carrying at most [`MAX_FILE_CHUNK`] bytes.
+    /// By default the file is read into frames, transports may send
+    /// it without copying through userspace
+    ///
+    /// [`MAX_FILE_CHUNK`]: crate::transport::file::MAX_FILE_CHUNK
+    async fn write_file(&self, kind: u8, file: &File, range: Range<u64>) -> io::Result<()> {
+        write_file_frames(self, kind, file, range).await
+    }
+
+    /// Called by [`Builder`] once providers are initialized
+    ///
+    /// [`Builder`]: crate::builder::builder::Builder
+    fn handshake_complete(&self) {}
+
+    /// Called by [`Builder`] once the protocol version is agreed, transports
+    /// encode close and shutdown control frames with it, see [`ControlFrame::encode_as()`]
+    ///
+    /// [`Builder`]: crate::builder::builder::Builder
+    /// [`ControlFrame::encode_as()`]: crate::transport::control::ControlFrame::encode_as
+    fn set_control_encoding(&self, _encoding: Encoding) {}
+
+    /// Applies settings of a live connection, see [`KindConn::reconfigure()`]
+    ///
+    /// [`KindConn::reconfigure()`]: crate::builder::kind_conn::KindConn::reconfigure
+    fn reconfigure(&self, _config: &PartialConfig) {}
+
     fn local_addr(&self) -> io::Result<SocketAddr>;
 
     fn peer_addr(&self) -> io::Result<SocketAddr>;
@@ -24,13 +95,125 @@ pub trait ConnProvider: Send + Sync {
 
     async fn close(&self, code: u8);
 
+    async fn close_with_reason(&self, code: u8, _reason: &str) {
+        self.close(code).await
+    }
+
+    async fn shutdown_write(&self);
+
     // Return None if conn is able, else return close code
     async fn is_close(&self) -> Option<u8>;
+
+    async fn close_reason(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns why the connection was closed, see [`CloseCause`]
+    ///
+    /// By default the cause is unknown
+    ///
+    /// [`CloseCause`]: crate::transport::close_cause::CloseCause
+    async fn close_cause(&self) -> Option<CloseCause> {
+        None
+    }
+}
+
+/// Lets transports chosen at runtime, e.g. by [`Endpoint`], be passed to [`Builder::set_conn()`]
+///
+/// [`Endpoint`]: crate::transport::endpoint::Endpoint
+/// [`Builder::set_conn()`]: crate::builder::builder::Builder::set_conn
+#[async_trait]
+impl<T: ConnProvider + ?Sized> ConnProvider for Box<T> {
+    async fn read(&self, kind: u8) -> Option<Frame> {
+        self.as_ref().read(kind).await
+    }
+
+    async fn write(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        self.as_ref().write(frame).await
+    }
+
+    async fn write_urgent(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        self.as_ref().write_urgent(frame).await
+    }
+
+    async fn flush(&self) {
+        self.as_ref().flush().await
+    }
+
+    fn stats(&self) -> TransportStats {
+        self.as_ref().stats()
+    }
+
+    async fn write_file(&self, kind: u8, file: &File, range: Range<u64>) -> io::Result<()> {
+        self.as_ref().write_file(kind, file, range).await
+    }
+
+    fn handshake_complete(&self) {
+        self.as_ref().handshake_complete()
+    }
+
+    fn set_control_encoding(&self, encoding: Encoding) {
+        self.as_ref().set_control_encoding(encoding)
+    }
+
+    fn reconfigure(&self, config: &PartialConfig) {
+        self.as_ref().reconfigure(config)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.as_ref().local_addr()
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.as_ref().peer_addr()
+    }
+
+    async fn readable(&self) {
+        self.as_ref().readable().await
+    }
+
+    async fn close(&self, code: u8) {
+        self.as_ref().close(code).await
+    }
+
+    async fn close_with_reason(&self, code: u8, reason: &str) {
+        self.as_ref().close_with_reason(code, reason).await
+    }
+
+    async fn shutdown_write(&self) {
+        self.as_ref().shutdown_write().await
+    }
+
+    async fn is_close(&self) -> Option<u8> {
+        self.as_ref().is_close().await
+    }
+
+    async fn close_reason(&self) -> Option<String> {
+        self.as_ref().close_reason().await
+    }
+
+    async fn close_cause(&self) -> Option<CloseCause> {
+        self.as_ref().close_cause().await
+    }
 }
 
 #[async_trait]
 pub trait PingProvider: Send + Sync {
     async fn init(&self, context: Context);
+
+    /// Returns providers which must be initialized before this one
+    ///
+    /// By default none, so providers are initialized concurrently. E.g.
+    /// a compression provider whose negotiation must follow the encryption
+    /// handshake depends on [`Provider::Encryption`]. Dependencies in a loop
+    /// are reported by [`Builder::validate()`]. With protocol version 1
+    /// providers are initialized one by one: ping, encryption, compression
+    ///
+    /// [`Provider::Encryption`]: crate::builder::dependency::Provider::Encryption
+    /// [`Builder::validate()`]: crate::builder::builder::Builder::validate
+    fn depends_on(&self) -> Vec<Provider> {
+        Vec::new()
+    }
 }
 
 #[async_trait]
@@ -40,6 +223,59 @@ pub trait EncryptionProvider: Send + Sync {
     fn encrypt(&self, frame: Vec<u8>) -> Vec<u8>;
 
     fn decrypt(&self, frame: Vec<u8>) -> Vec<u8>;
+
+    /// Decrypts the package, returns error if it must be rejected
+    ///
+    /// Connection is closed with [`ENCRYPTION_ERROR`] code on error.
+    /// By default calls [`decrypt()`], see [`ReplayWindow`] for replay protection
+    ///
+    /// [`ENCRYPTION_ERROR`]: crate::builder::kind_conn::close_code::ENCRYPTION_ERROR
+    /// [`decrypt()`]: crate::builder::builder::EncryptionProvider::decrypt
+    /// [`ReplayWindow`]: crate::providers::nonce::ReplayWindow
+    fn try_decrypt(&self, frame: Vec<u8>) -> Result<Vec<u8>, DecryptError> {
+        Ok(self.decrypt(frame))
+    }
+
+    /// Switches encryption of outgoing packages to the next key
+    ///
+    /// Called when [`KindConn::rotate_keys()`] is used by either side. Packages
+    /// encrypted before the switch may still be in flight, so every package
+    /// must identify its key (e.g. by a key generation number) and the
+    /// previous receive key must be kept. Returns `false` if rotation isn't supported
+    ///
+    /// [`KindConn::rotate_keys()`]: crate::builder::kind_conn::KindConn::rotate_keys
+    fn rotate_keys(&self) -> bool {
+        false
+    }
+
+    /// Returns key of the provider settings if encrypted data
+    /// depends only on them (not on per-connection state)
+    ///
+    /// Packages broadcasted to connections with equal keys are encrypted
+    /// once, see [`ConnRegistry::broadcast_frame()`]
+    ///
+    /// [`ConnRegistry::broadcast_frame()`]: crate::server::ConnRegistry::broadcast_frame
+    fn shared_key(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns true if the provider doesn't change data
+    ///
+    /// Lets [`KindConn::write_file()`] send files without copying them
+    ///
+    /// [`KindConn::write_file()`]: crate::builder::kind_conn::KindConn::write_file
+    fn passthrough(&self) -> bool {
+        false
+    }
+
+    /// Returns providers which must be initialized before this one
+    ///
+    /// See [`PingProvider::depends_on()`]
+    ///
+    /// [`PingProvider::depends_on()`]: crate::builder::builder::PingProvider::depends_on
+    fn depends_on(&self) -> Vec<Provider> {
+        Vec::new()
+    }
 }
 
 #[async_trait]
@@ -49,12 +285,82 @@ pub trait CompressionProvider: Send + Sync {
     fn compress(&self, frame: Vec<u8>) -> Vec<u8>;
 
     fn decompress(&self, frame: Vec<u8>) -> Vec<u8>;
+
+    /// Returns counters of the provider, see [`KindConn::stats()`]
+    ///
+    /// By default they aren't counted
+    ///
+    /// [`KindConn::stats()`]: crate::builder::kind_conn::KindConn::stats
+    fn stats(&self) -> Option<CompressionStats> {
+        None
+    }
+
+    /// Returns key of the provider settings if compressed data
+    /// depends only on them (not on per-connection state)
+    ///
+    /// See [`EncryptionProvider::shared_key()`]
+    ///
+    /// [`EncryptionProvider::shared_key()`]: crate::builder::builder::EncryptionProvider::shared_key
+    fn shared_key(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns true if the provider doesn't change data
+    ///
+    /// See [`EncryptionProvider::passthrough()`]
+    ///
+    /// [`EncryptionProvider::passthrough()`]: crate::builder::builder::EncryptionProvider::passthrough
+    fn passthrough(&self) -> bool {
+        false
+    }
+
+    /// Returns providers which must be initialized before this one
+    ///
+    /// See [`PingProvider::depends_on()`]
+    ///
+    /// [`PingProvider::depends_on()`]: crate::builder::builder::PingProvider::depends_on
+    fn depends_on(&self) -> Vec<Provider> {
+        Vec::new()
+    }
 }
 
 #[derive(Debug)]
 pub enum BuildError {
     ConnNotSet,
     EncryptionInitFailed,
+    Timeout,
+    Cancelled,
+
+    /// One of the providers panicked during initialization
+    ProviderPanic(String),
+
+    /// Peer identity wasn't accepted, see [`Builder::verify_peer()`]
+    ///
+    /// [`Builder::verify_peer()`]: crate::builder::builder::Builder::verify_peer
+    IdentityRejected,
+
+    /// Peer supports only the protocol version older than [`MIN_PROTOCOL_VERSION`]
+    ///
+    /// [`MIN_PROTOCOL_VERSION`]: crate::builder::version::MIN_PROTOCOL_VERSION
+    UnsupportedVersion(u8),
+
+    /// Providers depend on each other in a loop, see [`PingProvider::depends_on()`]
+    ///
+    /// [`PingProvider::depends_on()`]: crate::builder::builder::PingProvider::depends_on
+    ProviderCycle,
+}
+
+/// Reason of a rejected encrypted package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecryptError {
+    /// Package with the same nonce was already received
+    Replayed,
+
+    /// Nonce is behind the replay window
+    TooOld,
+
+    /// Package can't be decrypted or authenticated
+    Corrupted,
 }
 
 pub struct Builder {
@@ -62,6 +368,19 @@ pub struct Builder {
     ping: Arc<dyn PingProvider>,
     encryption: Arc<dyn EncryptionProvider>,
     compression: Arc<dyn CompressionProvider>,
+    kind_providers: KindProviders,
+    handshake_extensions: BTreeMap<String, Vec<u8>>,
+    handshake_timeout: Option<Duration>,
+    cancel: Option<CancelToken>,
+    frame_hooks: Vec<FrameHook>,
+    key_rotation: Option<KeyRotation>,
+    verifier: Option<IdentityVerifier>,
+    early_data: Vec<Vec<u8>>,
+    protocol_version: u8,
+    version_timeout: Duration,
+    kind_quarantine: Duration,
+    kind_width: KindWidth,
+    expose_stats: bool,
 }
 
 impl Builder {
@@ -89,20 +408,546 @@ impl Builder {
         self
     }
 
+    /// Encrypts packages of the kind with another provider
+    ///
+    /// E.g. only the kind carrying credentials may be encrypted, the provider
+    /// set with [`set_encryption()`] is used for other kinds. Both sides must
+    /// set the same overrides. Overrides are initialized after the connection
+    /// provider in the order of kinds and take kinds from its block, see [`PROVIDER_KINDS`]
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cobra_rs::builder::builder::Builder;
+    /// use cobra_rs::builder::context::FIRST_APPLICATION_KIND;
+    /// use cobra_rs::builder::empty_realisations::EmptyRealisation;
+    /// use cobra_rs::transport::tcp::Conn;
+    /// # struct Zip;
+    /// # #[async_trait::async_trait]
+    /// # impl cobra_rs::builder::builder::CompressionProvider for Zip {
+    /// #     async fn init(&self, _: cobra_rs::builder::context::Context) {}
+    /// #     fn compress(&self, frame: Vec<u8>) -> Vec<u8> { frame }
+    /// #     fn decompress(&self, frame: Vec<u8>) -> Vec<u8> { frame }
+    /// # }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let conn = Conn::connect("127.0.0.1:5000").await.unwrap();
+    ///     let chat = Builder::new()
+    ///         .set_conn(conn)
+    ///         .set_compression(Zip)
+    ///         .set_kind_compression(FIRST_APPLICATION_KIND + 1, EmptyRealisation {})
+    ///         .run()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     // Media is already compressed, so it is sent as is
+    ///     let media = chat.open_kind().unwrap();
+    ///     media.write(vec![0; 1024]).await.unwrap();
+    /// }
+    /// ```
+    ///
+    /// [`set_encryption()`]: crate::builder::builder::Builder::set_encryption
+    /// [`PROVIDER_KINDS`]: crate::builder::context::PROVIDER_KINDS
+    pub fn set_kind_encryption<T: 'static + EncryptionProvider>(mut self, kind: u8, encryption: T) -> Self {
+        self.kind_providers.encryption.insert(kind, Arc::new(encryption));
+        self
+    }
+
+    /// Compresses packages of the kind with another provider
+    ///
+    /// See [`set_kind_encryption()`]
+    ///
+    /// [`set_kind_encryption()`]: crate::builder::builder::Builder::set_kind_encryption
+    pub fn set_kind_compression<T: 'static + CompressionProvider>(mut self, kind: u8, compression: T) -> Self {
+        self.kind_providers.compression.insert(kind, Arc::new(compression));
+        self
+    }
+
+    /// Sends the extension to the peer during the handshake
+    ///
+    /// Providers read the peer's extension with [`Context::peer_extension()`].
+    /// Extension registered again replaces the previous one
+    ///
+    /// [`Context::peer_extension()`]: crate::builder::context::Context::peer_extension
+    pub fn handshake_extension<E: HandshakeExtension>(mut self, extension: E) -> Self {
+        self.handshake_extensions.insert(E::KEY.to_string(), extension.encode());
+        self
+    }
+
+    /// Applies provider settings of the profile
+    ///
+    /// Transport settings are applied separately, see [`Profile::conn_config()`]
+    ///
+    /// [`Profile::conn_config()`]: crate::builder::profile::Profile::conn_config
+    pub fn profile(self, profile: Profile) -> Self {
+        self.set_ping(profile.ping())
+    }
+
+    /// Limits the time of the whole handshake
+    ///
+    /// If providers don't finish initialization in time, the connection
+    /// is closed with [`HANDSHAKE_TIMEOUT`] code and [`run()`] returns [`BuildError::Timeout`]
+    ///
+    /// [`HANDSHAKE_TIMEOUT`]: crate::builder::kind_conn::close_code::HANDSHAKE_TIMEOUT
+    /// [`run()`]: crate::builder::builder::Builder::run
+    /// [`BuildError::Timeout`]: crate::builder::builder::BuildError::Timeout
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Aborts the handshake when the token is cancelled
+    ///
+    /// The connection is closed with [`CANCELLED`] code and [`run()`]
+    /// returns [`BuildError::Cancelled`]. Built connection is closed
+    /// by the token set with [`ConnConfig::set_cancel_token()`]
+    ///
+    /// [`CANCELLED`]: crate::builder::kind_conn::close_code::CANCELLED
+    /// [`run()`]: crate::builder::builder::Builder::run
+    /// [`BuildError::Cancelled`]: crate::builder::builder::BuildError::Cancelled
+    /// [`ConnConfig::set_cancel_token()`]: crate::transport::tcp::ConnConfig::set_cancel_token
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Rotates encryption keys when any of the limits is reached
+    ///
+    /// See [`KindConn::rotate_keys()`]
+    ///
+    /// [`KindConn::rotate_keys()`]: crate::builder::kind_conn::KindConn::rotate_keys
+    pub fn key_rotation(mut self, rotation: KeyRotation) -> Self {
+        self.key_rotation = Some(rotation);
+        self
+    }
+
+    /// Accepts the peer only if `verify` returns `true` for its identity
+    ///
+    /// Called once the encryption provider is initialized. If the provider
+    /// set no [`PeerIdentity`] or it is rejected, the connection is closed with
+    /// [`IDENTITY_REJECTED`] code and [`run()`] returns [`BuildError::IdentityRejected`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cobra_rs::builder::builder::Builder;
+    ///
+    /// let pinned_key = vec![0x5a; 32];
+    /// let builder = Builder::new()
+    ///     .verify_peer(move |identity| identity.leaf() == Some(pinned_key.as_slice()));
+    /// ```
+    ///
+    /// [`PeerIdentity`]: crate::builder::identity::PeerIdentity
+    /// [`IDENTITY_REJECTED`]: crate::builder::kind_conn::close_code::IDENTITY_REJECTED
+    /// [`run()`]: crate::builder::builder::Builder::run
+    /// [`BuildError::IdentityRejected`]: crate::builder::builder::BuildError::IdentityRejected
+    pub fn verify_peer<F: 'static + Fn(&PeerIdentity) -> bool + Send + Sync>(mut self, verify: F) -> Self {
+        self.verifier = Some(Arc::new(verify));
+        self
+    }
+
+    /// Sends the package as soon as encryption and compression are ready
+    ///
+    /// Early packages don't wait for the rest of the handshake and go before
+    /// packages written to the connection returned by [`run()`]. Intended for
+    /// reconnecting clients: with an encryption provider which can encrypt
+    /// right away (e.g. using a pre-shared or cached key) the packages leave
+    /// right after the transport connect. The peer reads them as usual
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cobra_rs::builder::builder::Builder;
+    /// use cobra_rs::transport::tcp::Conn;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let conn = Builder::new()
+    ///         .set_conn(Conn::connect("127.0.0.1:5000").await.unwrap())
+    ///         .early_data(b"subscribe".to_vec())
+    ///         .run()
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    ///
+    /// [`run()`]: crate::builder::builder::Builder::run
+    pub fn early_data(mut self, package: Vec<u8>) -> Self {
+        self.early_data.push(package);
+        self
+    }
+
+    /// Limits the protocol version used with the peer
+    ///
+    /// By default the newest [`PROTOCOL_VERSION`] is offered and the peer may
+    /// downgrade it, see [`Features`]. Pinning version 1 skips the version frame,
+    /// so the handshake doesn't wait for it. The version is clamped to the
+    /// supported ones
+    ///
+    /// # Note
+    ///
+    /// Peers pinned to version 1 answer the version frame, so they are downgraded
+    /// to without waiting. Peers built before versioning don't answer, version 1
+    /// is agreed with them once [`version_timeout()`] passes. With version 1 kinds
+    /// are issued one by one from `1` like these peers do, see [`Features::version_frame`]
+    ///
+    /// [`PROTOCOL_VERSION`]: crate::builder::version::PROTOCOL_VERSION
+    /// [`Features`]: crate::builder::version::Features
+    /// [`Features::version_frame`]: crate::builder::version::Features::version_frame
+    /// [`version_timeout()`]: crate::builder::builder::Builder::version_timeout
+    pub fn protocol_version(mut self, version: u8) -> Self {
+        self.protocol_version = version.clamp(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION);
+        self
+    }
+
+    /// Sets how long the handshake waits for the peer's version frame
+    ///
+    /// If it doesn't arrive the peer is taken for one built before versioning
+    /// and version 1 is agreed, see [`protocol_version()`]. A late frame would
+    /// leave the sides on different versions, so the connection is closed with
+    /// [`HANDSHAKE_TIMEOUT`] code if a newer version arrives within another timeout.
+    /// By default [`DEFAULT_VERSION_TIMEOUT`]
+    ///
+    /// [`HANDSHAKE_TIMEOUT`]: crate::builder::kind_conn::close_code::HANDSHAKE_TIMEOUT
+    /// [`protocol_version()`]: crate::builder::builder::Builder::protocol_version
+    /// [`DEFAULT_VERSION_TIMEOUT`]: crate::builder::version::DEFAULT_VERSION_TIMEOUT
+    pub fn version_timeout(mut self, timeout: Duration) -> Self {
+        self.version_timeout = timeout;
+        self
+    }
+
+    /// Sets how long a kind released by both sides isn't reissued
+    ///
+    /// Frames of the old channel delayed by the transport are dropped
+    /// once the time is up, see [`KindConn::open_kind()`].
+    /// By default [`DEFAULT_KIND_QUARANTINE`]
+    ///
+    /// [`KindConn::open_kind()`]: crate::builder::kind_conn::KindConn::open_kind
+    /// [`DEFAULT_KIND_QUARANTINE`]: crate::builder::kind_refs::DEFAULT_KIND_QUARANTINE
+    pub fn kind_quarantine(mut self, quarantine: Duration) -> Self {
+        self.kind_quarantine = quarantine;
+        self
+    }
+
+    /// Offers kinds wider than one byte to the peer
+    ///
+    /// The narrower of both widths is agreed if the peer's protocol version
+    /// supports it, see [`Features`]. By default only one-byte kinds are used,
+    /// see [`KindConn::wide_kind()`]
+    ///
+    /// [`Features`]: crate::builder::version::Features
+    /// [`KindConn::wide_kind()`]: crate::builder::kind_conn::KindConn::wide_kind
+    pub fn kind_width(mut self, width: KindWidth) -> Self {
+        self.kind_width = width;
+        self
+    }
+
+    /// Answers stats queries of the peer, see [`KindConn::peer_stats()`]
+    ///
+    /// Stats reveal queue depths and timings of the connection,
+    /// so queries are refused by default
+    ///
+    /// [`KindConn::peer_stats()`]: crate::builder::kind_conn::KindConn::peer_stats
+    pub fn expose_stats(mut self, expose: bool) -> Self {
+        self.expose_stats = expose;
+        self
+    }
+
+    /// Records every frame of the connection, including frames of providers
+    ///
+    /// See [`FrameRecorder`] for the recording format
+    ///
+    /// [`FrameRecorder`]: crate::debug::FrameRecorder
+    pub fn record_frames(mut self, recorder: FrameRecorder) -> Self {
+        self.frame_hooks.push(recorder.into());
+        self
+    }
+
+    /// Calls the hook with every frame of the connection, including frames of providers
+    ///
+    /// Several hooks are called in order they were added, see [`FrameHook`]
+    ///
+    /// [`FrameHook`]: crate::debug::FrameHook
+    pub fn on_frame(mut self, hook: FrameHook) -> Self {
+        self.frame_hooks.push(hook);
+        self
+    }
+
+    /// Checks the configuration without connecting, reports all problems at once
+    ///
+    /// [`run()`] may succeed with some of the problems, but the connection
+    /// wouldn't work as configured, e.g. keys would never be rotated
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cobra_rs::builder::builder::Builder;
+    /// use cobra_rs::builder::validation::ConfigProblem;
+    ///
+    /// let problems = Builder::new()
+    ///     .verify_peer(|_| true)
+    ///     .validate()
+    ///     .unwrap_err();
+    ///
+    /// assert_eq!(problems, [ConfigProblem::ConnNotSet, ConfigProblem::VerificationWithoutEncryption]);
+    /// ```
+    ///
+    /// [`run()`]: crate::builder::builder::Builder::run
+    pub fn validate(&self) -> Result<(), Vec<ConfigProblem>> {
+        let mut problems = Vec::new();
+        if self.conn.is_none() {
+            problems.push(ConfigProblem::ConnNotSet);
+        }
+        problems.extend(self.settings_problems());
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(problems),
+        }
+    }
+
+    /// Returns settings which would be agreed with the peer configured by `peer`
+    ///
+    /// Nothing is connected, so transports don't have to be set. Besides problems
+    /// of this side (see [`validate()`]) settings which both sides must share
+    /// are compared. Providers are compared only by whether they change
+    /// packages, see [`EncryptionProvider::passthrough()`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cobra_rs::builder::builder::Builder;
+    /// use cobra_rs::mem::KindWidth;
+    ///
+    /// let client = Builder::new().kind_width(KindWidth::U16);
+    /// let server = Builder::new().protocol_version(3);
+    ///
+    /// let capabilities = client.dry_run(&server).unwrap();
+    /// assert_eq!(capabilities.version, 3);
+    /// assert_eq!(capabilities.kind_width, KindWidth::U8);
+    /// ```
+    ///
+    /// [`validate()`]: crate::builder::builder::Builder::validate
+    /// [`EncryptionProvider::passthrough()`]: crate::builder::builder::EncryptionProvider::passthrough
+    pub fn dry_run(&self, peer: &Builder) -> Result<Capabilities, Vec<ConfigProblem>> {
+        let mut problems = self.settings_problems();
+        if self.encryption.passthrough() != peer.encryption.passthrough() {
+            problems.push(ConfigProblem::EncryptionMismatch);
+        }
+        if self.compression.passthrough() != peer.compression.passthrough() {
+            problems.push(ConfigProblem::CompressionMismatch);
+        }
+
+        let (kind_encryption, kind_compression) = self.kind_providers.kinds();
+        let (peer_encryption, peer_compression) = peer.kind_providers.kinds();
+        let mismatched: BTreeSet<u8> = kind_encryption.symmetric_difference(&peer_encryption)
+            .chain(kind_compression.symmetric_difference(&peer_compression))
+            .copied()
+            .collect();
+        problems.extend(mismatched.into_iter().map(ConfigProblem::KindOverrideMismatch));
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        // Peer of version 1 sends nothing, which is read as version 1 too
+        let peer_features = Features::of(peer.protocol_version).unwrap();
+        let peer_frame = VersionFrame {
+            version: peer.protocol_version,
+            kind_width: peer_features.wide_kinds.then_some(peer.kind_width),
+            tie_breaker: None,
+        };
+        let (version, kind_width) = Version::agree(self.protocol_version, self.kind_width, &peer_frame);
+
+        Ok(Capabilities {
+            version,
+            features: Features::of(version).unwrap(),
+            kind_width,
+            encryption: !self.encryption.passthrough(),
+            compression: !self.compression.passthrough(),
+            kind_encryption: kind_encryption.into_iter().collect(),
+            kind_compression: kind_compression.into_iter().collect(),
+        })
+    }
+
+    // Problems of settings which don't depend on the transport
+    fn settings_problems(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+        if self.handshake_timeout == Some(Duration::ZERO) {
+            problems.push(ConfigProblem::ZeroHandshakeTimeout);
+        }
+        if self.kind_width > KindWidth::U8 && !Features::of(self.protocol_version).unwrap().wide_kinds {
+            problems.push(ConfigProblem::WideKindsUnsupported { version: self.protocol_version });
+        }
+        if self.key_rotation.is_some() && self.encryption.passthrough() {
+            problems.push(ConfigProblem::KeyRotationWithoutEncryption);
+        }
+        if self.verifier.is_some() && self.encryption.passthrough() {
+            problems.push(ConfigProblem::VerificationWithoutEncryption);
+        }
+        if self.init_order().has_cycle() {
+            problems.push(ConfigProblem::ProviderCycle);
+        }
+
+        let (encryption, compression) = self.kind_providers.kinds();
+        let kinds: BTreeSet<u8> = encryption.union(&compression)
+            .copied()
+            .filter(|kind| !(FIRST_APPLICATION_KIND..=LAST_APPLICATION_KIND).contains(kind))
+            .collect();
+        problems.extend(kinds.into_iter().map(ConfigProblem::NotApplicationKind));
+        problems
+    }
+
+    fn init_order(&self) -> InitOrder {
+        InitOrder::new([self.ping.depends_on(), self.encryption.depends_on(), self.compression.depends_on()])
+    }
+
     pub async fn run(self) -> Result<KindConn, BuildError> {
+        let order = self.init_order();
+        if order.has_cycle() {
+            return Err(BuildError::ProviderCycle);
+        }
         let conn = match self.conn {
             Some(conn) => conn,
             None => return Err(BuildError::ConnNotSet),
         };
+        let conn: Arc<dyn ConnProvider> = if self.frame_hooks.is_empty() {
+            conn
+        } else {
+            Arc::new(ObservedConn::new(conn, self.frame_hooks))
+        };
         let context = Context::new(conn.clone(),
                                    self.encryption.clone(),
-                                   self.compression,
+                                   self.compression.clone(),
+                                   self.kind_providers,
+                                   Handshake::new(self.handshake_extensions),
+                                   self.key_rotation,
+                                   self.kind_quarantine,
                                    ContextMode::Handle);
+        context.state().stats.expose(self.expose_stats);
+
+        // Kinds of providers depend on the agreed version, so it goes first.
+        // Since version 2 providers use their own kind blocks and are
+        // initialized in the order they declare, see InitOrder. Per-kind
+        // overrides follow the provider of their block. Application
+        // connection is returned only after encryption is ready
+        let (ping, encryption, compression) = (self.ping, self.encryption, self.compression);
+        let kind_providers = &context.state().kind_providers;
+        let encryption = async {
+            let context = context.for_provider(ProviderSlot::Encryption, ContextMode::Raw);
+            encryption.init(context.share()).await?;
+            for encryption in kind_providers.encryption.values() {
+                encryption.init(context.share()).await?;
+            }
+            context.state().encryption_ready.store(true, Ordering::SeqCst);
+            Ok(())
+        };
+        let compression = async {
+            let context = context.for_provider(ProviderSlot::Compression, ContextMode::Raw);
+            compression.init(context.share()).await;
+            for compression in kind_providers.compression.values() {
+                compression.init(context.share()).await;
+            }
+        };
+        let (early_data, state) = (self.early_data, context.state().clone());
+        let version = Version::negotiate(state.clone(), self.protocol_version, self.kind_width, self.version_timeout);
+        let init = async {
+            version.await?;
+
+            // Closed connection is reported by the returned connection
+            let write_early_data = |app_conn: KindConn| async move {
+                for package in early_data {
+                    if app_conn.write(package).await.is_err() {
+                        break;
+                    }
+                }
+                app_conn
+            };
 
-        self.ping.init(context.clone(ContextMode::Raw)).await;
-        self.encryption.init(context.clone(ContextMode::Raw)).await?;
+            // Peers of version 1 issue kinds one by one in this order
+            if state.version.sequential_kinds() {
+                state.refs.sequential();
+                ping.init(context.for_provider(ProviderSlot::Ping, ContextMode::Raw)).await;
+                encryption.await?;
+                compression.await;
+                state.refs.start_application();
+                Ok(write_early_data(context.get_kind_conn().await).await)
+            } else {
+                let app_conn = context.get_kind_conn().await;
+                let ping = async {
+                    ping.init(context.for_provider(ProviderSlot::Ping, ContextMode::Raw)).await;
+                    Ok(())
+                };
+                let compression = async {
+                    compression.await;
+                    Ok(())
+                };
+                let (_, app_conn) = tokio::join!(
+                    order.run(Provider::Ping, ping),
+                    async {
+                        let (encryption, compression) = tokio::join!(
+                            order.run(Provider::Encryption, encryption),
+                            order.run(Provider::Compression, compression),
+                        );
+                        encryption.and(compression)?;
+                        Ok(write_early_data(app_conn).await)
+                    },
+                );
+                app_conn
+            }
+        };
+        let init = async {
+            runtime::catch_unwind(init)
+                .await
+                .unwrap_or_else(|message| Err(BuildError::ProviderPanic(message)))
+        };
+        let handshake_timeout = self.handshake_timeout;
+        let init = async {
+            match handshake_timeout {
+                Some(handshake_timeout) => runtime::timeout(handshake_timeout, init)
+                    .await
+                    .unwrap_or(Err(BuildError::Timeout)),
+                None => init.await,
+            }
+        };
+        let result = match &self.cancel {
+            Some(token) => token.run(init).await.unwrap_or(Err(BuildError::Cancelled)),
+            None => init.await,
+        };
+        let result = match (result, &self.verifier) {
+            (Ok(app_conn), Some(verifier)) => {
+                let accepted = context.extensions()
+                    .with(|identity: &PeerIdentity| verifier(identity))
+                    .unwrap_or(false);
+                if accepted { Ok(app_conn) } else { Err(BuildError::IdentityRejected) }
+            }
+            (result, _) => result,
+        };
+
+        match &result {
+            Err(BuildError::Timeout) => conn.close(HANDSHAKE_TIMEOUT).await,
+            Err(BuildError::Cancelled) => conn.close(CANCELLED).await,
+            Err(BuildError::IdentityRejected) => conn.close(IDENTITY_REJECTED).await,
+            Err(BuildError::UnsupportedVersion(_)) => conn.close(UNSUPPORTED_VERSION).await,
+            Err(BuildError::ProviderPanic(message)) => {
+                conn.close_with_reason(PROVIDER_PANIC, panic_reason(message)).await
+            }
+            _ => {}
+        }
+        let app_conn = result?;
+        conn.handshake_complete();
+        if !context.state().version.sequential_kinds() {
+            context.spawn(Rekey::serve(context.state().clone()));
+        }
+        if context.state().version.features().kind_release {
+            context.spawn(KindRefs::serve(context.state().clone()));
+        }
+        if context.state().version.kind_width() > KindWidth::U8 {
+            context.spawn(WideKinds::serve(context.state().clone()));
+        }
 
-        Ok(context.get_kind_conn().await)
+        Ok(app_conn)
     }
 }
 
@@ -114,6 +959,19 @@ impl Default for Builder {
             ping: empty_realisation.clone(),
             encryption: empty_realisation.clone(),
             compression: empty_realisation.clone(),
+            kind_providers: KindProviders::default(),
+            handshake_extensions: BTreeMap::new(),
+            handshake_timeout: None,
+            cancel: None,
+            frame_hooks: Vec::new(),
+            key_rotation: None,
+            verifier: None,
+            early_data: Vec::new(),
+            protocol_version: PROTOCOL_VERSION,
+            version_timeout: DEFAULT_VERSION_TIMEOUT,
+            kind_quarantine: DEFAULT_KIND_QUARANTINE,
+            kind_width: KindWidth::U8,
+            expose_stats: false,
         }
     }
 }
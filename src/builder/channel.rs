@@ -0,0 +1,158 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::sync::Notify;
+
+use crate::builder::context::ContextState;
+use crate::builder::kind_refs::KindRefs;
+use crate::protocol::ControlMessage;
+
+/// Maximum length of a channel name in bytes
+pub const MAX_CHANNEL_NAME_LEN: usize = 255;
+
+/// Error returned by [`KindConn::open_channel()`]
+///
+/// [`KindConn::open_channel()`]: crate::builder::kind_conn::KindConn::open_channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelError {
+    /// Peer's protocol version doesn't support named channels, see [`Features`]
+    ///
+    /// [`Features`]: crate::builder::version::Features
+    Unsupported,
+
+    /// Name is longer than [`MAX_CHANNEL_NAME_LEN`]
+    ///
+    /// [`MAX_CHANNEL_NAME_LEN`]: crate::builder::channel::MAX_CHANNEL_NAME_LEN
+    NameTooLong,
+
+    /// All application kinds are used
+    Exhausted,
+
+    /// Connection was closed
+    Closed,
+}
+
+/// Kinds of named channels
+///
+/// Kinds are assigned by one side only, the directory owner, so both sides
+/// never pick different kinds for a name. The owner is chosen by the
+/// tie-breaker of the version frame, it takes kinds from the top of the
+/// application range and announces them with [`ChannelOpen`]. The other
+/// side asks for unknown names with [`ChannelRequest`]
+///
+/// [`ChannelOpen`]: crate::protocol::ControlMessage::ChannelOpen
+/// [`ChannelRequest`]: crate::protocol::ControlMessage::ChannelRequest
+pub(crate) struct Directory {
+    names: Mutex<HashMap<String, u8>>,
+    exhausted: Mutex<HashSet<String>>,
+    closed: AtomicBool,
+    open_notifier: Notify,
+}
+
+impl Directory {
+    pub(crate) fn new() -> Self {
+        Directory {
+            names: Mutex::new(HashMap::new()),
+            exhausted: Mutex::new(HashSet::new()),
+            closed: AtomicBool::new(false),
+            open_notifier: Notify::new(),
+        }
+    }
+
+    /// Returns kind of the channel, waits for the owner if it isn't known yet
+    pub(crate) async fn open(state: &Arc<ContextState>, name: &str) -> Result<u8, ChannelError> {
+        if !state.version.features().named_channels {
+            return Err(ChannelError::Unsupported);
+        }
+        if name.len() > MAX_CHANNEL_NAME_LEN {
+            return Err(ChannelError::NameTooLong);
+        }
+        // Tie-breakers were equal
+        let owner = state.version.directory_owner().ok_or(ChannelError::Unsupported)?;
+
+        if owner {
+            return Directory::assign(state, name, false).await;
+        }
+
+        let directory = &state.channels;
+        let mut requested = false;
+        loop {
+            let opened = directory.open_notifier.notified();
+            tokio::pin!(opened);
+            opened.as_mut().enable();
+
+            if let Some(&kind) = directory.names.lock().unwrap().get(name) {
+                return Ok(kind);
+            }
+            if requested && directory.exhausted.lock().unwrap().remove(name) {
+                return Err(ChannelError::Exhausted);
+            }
+            if directory.closed.load(Ordering::SeqCst) {
+                return Err(ChannelError::Closed);
+            }
+
+            if !requested {
+                let message = ControlMessage::ChannelRequest { name: name.to_string() };
+                KindRefs::send(state, message).await.map_err(|_| ChannelError::Closed)?;
+                requested = true;
+            }
+            opened.await;
+        }
+    }
+
+    /// Handles directory messages of the peer
+    pub(crate) async fn handle(state: &Arc<ContextState>, message: &ControlMessage) {
+        match message {
+            ControlMessage::ChannelRequest { name } => {
+                if let Err(ChannelError::Exhausted) = Directory::assign(state, name, true).await {
+                    let _ = KindRefs::send(state, ControlMessage::ChannelExhausted { name: name.clone() }).await;
+                }
+            }
+            ControlMessage::ChannelOpen { kind, name } => {
+                state.refs.claim(*kind);
+                state.channels.names.lock().unwrap().insert(name.clone(), *kind);
+                state.channels.open_notifier.notify_waiters();
+            }
+            ControlMessage::ChannelExhausted { name } => {
+                state.channels.exhausted.lock().unwrap().insert(name.clone());
+                state.channels.open_notifier.notify_waiters();
+            }
+            _ => {}
+        }
+    }
+
+    /// Forgets names of the kind once it is reclaimed
+    pub(crate) fn forget(&self, kind: u8) {
+        self.names.lock().unwrap().retain(|_, channel| *channel != kind);
+    }
+
+    /// Wakes waiters once the connection is closed
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.open_notifier.notify_waiters();
+    }
+
+    // Assigns a kind to the name if it has none, announces new
+    // and requested kinds to the peer
+    async fn assign(state: &Arc<ContextState>, name: &str, requested: bool) -> Result<u8, ChannelError> {
+        let (kind, assigned) = {
+            let mut names = state.channels.names.lock().unwrap();
+            match names.get(name) {
+                Some(&kind) => (kind, false),
+                None => {
+                    let kind = state.refs.allocate_top().ok_or(ChannelError::Exhausted)?;
+                    names.insert(name.to_string(), kind);
+                    (kind, true)
+                }
+            }
+        };
+
+        if assigned || requested {
+            let message = ControlMessage::ChannelOpen { kind, name: name.to_string() };
+            KindRefs::send(state, message).await.map_err(|_| ChannelError::Closed)?;
+        }
+
+        Ok(kind)
+    }
+}
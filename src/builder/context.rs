@@ -1,15 +1,108 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use tokio::sync::RwLock;
 
 use crate::builder::builder::{CompressionProvider, ConnProvider, EncryptionProvider};
+use crate::builder::channel::Directory;
+use crate::builder::extensions::Extensions;
+use crate::builder::handshake::{Handshake, HandshakeExtension};
+use crate::builder::kind_conn::close_code::PROVIDER_PANIC;
 use crate::builder::kind_conn::KindConn;
+use crate::builder::kind_refs::KindRefs;
+use crate::builder::peer_error::ERROR_KIND;
+use crate::builder::rekey::{KeyRotation, Rekey, REKEY_KIND};
+use crate::builder::stats::Stats;
+use crate::builder::version::{Version, VERSION_KIND};
+use crate::builder::wide_kind::WideKinds;
+use crate::mem::WIDE_KIND_U16;
+use crate::runtime;
+
+/// Number of kinds reserved for every provider
+pub const PROVIDER_KINDS: u8 = 4;
+
+/// Position of provider's kind block
+///
+/// Providers are initialized concurrently, so every provider allocates
+/// kinds from its own block to get the same kinds on both sides.
+/// With protocol version 1 providers are initialized one by one and
+/// take kinds in order, see [`Features::version_frame`]
+///
+/// [`Features::version_frame`]: crate::builder::version::Features::version_frame
+#[derive(Copy, Clone)]
+pub(crate) enum ProviderSlot {
+    Ping = 0,
+    Encryption = 1,
+    Compression = 2,
+}
+
+impl ProviderSlot {
+    pub(crate) const fn first_kind(self) -> u8 {
+        1 + self as u8 * PROVIDER_KINDS
+    }
+}
+
+/// Maximum length of the panic message sent as a close reason
+const MAX_PANIC_REASON_LEN: usize = 256;
+
+/// First kind available to the application
+///
+/// With protocol version 1 application kinds follow the kinds of providers
+pub const FIRST_APPLICATION_KIND: u8 = 1 + 3 * PROVIDER_KINDS;
+
+/// Last kind available to the application, greater kinds carry wide kinds
+/// (see [`WideFrame`])
+///
+/// [`WideFrame`]: crate::mem::WideFrame
+pub const LAST_APPLICATION_KIND: u8 = WIDE_KIND_U16 - 1;
+
+/// Providers used instead of the connection ones for packages of some kinds,
+/// see [`Builder::set_kind_encryption()`]
+///
+/// [`Builder::set_kind_encryption()`]: crate::builder::builder::Builder::set_kind_encryption
+#[derive(Default)]
+pub(crate) struct KindProviders {
+    pub(crate) encryption: BTreeMap<u8, Arc<dyn EncryptionProvider>>,
+    pub(crate) compression: BTreeMap<u8, Arc<dyn CompressionProvider>>,
+}
+
+/// Error returned by [`Context::raw_kind_conn()`]
+///
+/// [`Context::raw_kind_conn()`]: crate::builder::context::Context::raw_kind_conn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawKindError {
+    /// Encryption is ready, packages of new application kinds must be encrypted
+    EncryptionReady,
+
+    /// All application kinds are used
+    KindsExhausted,
+}
 
 pub(crate) struct ContextState {
-    kind_counter: RwLock<u8>,
     pub(crate) conn: Arc<dyn ConnProvider>,
     pub(crate) encryption: Arc<dyn EncryptionProvider>,
     pub(crate) compression: Arc<dyn CompressionProvider>,
+    pub(crate) kind_providers: KindProviders,
+    pub(crate) handshake: Handshake,
+    // Raw application kinds can't be taken anymore
+    pub(crate) encryption_ready: AtomicBool,
+    pub(crate) extensions: Extensions,
+    pub(crate) rekey: Rekey,
+    pub(crate) activity: Activity,
+    pub(crate) version: Version,
+    pub(crate) refs: KindRefs,
+    pub(crate) wide: WideKinds,
+    pub(crate) channels: Directory,
+    pub(crate) stats: Stats,
+}
+
+/// Time of the last package of application kinds
+pub(crate) struct Activity {
+    started: Instant,
+    last_ms: AtomicU64,
 }
 
 #[derive(Copy, Clone)]
@@ -18,37 +111,233 @@ pub(crate) enum ContextMode {
     Handle,
 }
 
+struct KindRange {
+    next: RwLock<u8>,
+    end: u8,
+}
+
 pub struct Context {
     state: Arc<ContextState>,
     mode: ContextMode,
+    kinds: Option<Arc<KindRange>>,
 }
 
 impl Context {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(conn: Arc<dyn ConnProvider>,
                       encryption: Arc<dyn EncryptionProvider>,
                       compression: Arc<dyn CompressionProvider>,
+                      kind_providers: KindProviders,
+                      handshake: Handshake,
+                      rotation: Option<KeyRotation>,
+                      kind_quarantine: Duration,
                       mode: ContextMode) -> Self {
         Context {
             state: Arc::new(ContextState {
-                kind_counter: RwLock::new(1),
                 conn,
                 encryption,
                 compression,
+                kind_providers,
+                handshake,
+                encryption_ready: AtomicBool::new(false),
+                extensions: Extensions::new(),
+                rekey: Rekey::new(rotation),
+                activity: Activity::new(),
+                version: Version::new(),
+                refs: KindRefs::new(kind_quarantine),
+                wide: WideKinds::new(),
+                channels: Directory::new(),
+                stats: Stats::new(),
             }),
             mode,
+            kinds: None,
         }
     }
 
+    /// Returns connection with a new kind
+    ///
+    /// Every call allocates another kind, clone the connection
+    /// to share its kind, see [`KindConn`]. Providers get kinds of their
+    /// block, packages of these kinds are never passed through providers
+    ///
+    /// # Note
+    ///
+    /// Panics if provider has used all kinds of its block
+    /// (see [`PROVIDER_KINDS`]) or all application kinds are used
+    ///
+    /// [`PROVIDER_KINDS`]: crate::builder::context::PROVIDER_KINDS
+    /// [`KindConn`]: crate::builder::kind_conn::KindConn
     pub async fn get_kind_conn(&self) -> KindConn {
-        *self.state.kind_counter.write().await += 1;
-        let kind = *self.state.kind_counter.read().await - 1;
+        let kind = match &self.kinds {
+            Some(kinds) => {
+                let mut next = kinds.next.write().await;
+                if *next >= kinds.end {
+                    panic!("provider kind block exhausted")
+                }
+                *next += 1;
+                *next - 1
+            }
+            None => self.state.refs.allocate().expect("application kinds exhausted"),
+        };
+
         KindConn::new(kind, self.mode, self.state.clone())
     }
 
-    pub(crate) fn clone(&self, mode: ContextMode) -> Self {
+    /// Returns connection of a new application kind whose packages aren't
+    /// passed through encryption and compression
+    ///
+    /// Intended for providers carrying data protected by other means (e.g.
+    /// already encrypted media) on channels the application reads. Raw kinds
+    /// can be taken only during provider initialization until encryption is
+    /// ready, later kinds are always encrypted, so the peer can't make the
+    /// application read unencrypted packages. Kinds are allocated in the order
+    /// of calls: call it before the provider awaits anything, so both sides get
+    /// the same kinds, see [`KindConn::is_raw()`]
+    ///
+    /// [`KindConn::is_raw()`]: crate::builder::kind_conn::KindConn::is_raw
+    pub fn raw_kind_conn(&self) -> Result<KindConn, RawKindError> {
+        if self.state.encryption_ready.load(Ordering::SeqCst) {
+            return Err(RawKindError::EncryptionReady);
+        }
+
+        let kind = self.state.refs.allocate().ok_or(RawKindError::KindsExhausted)?;
+        Ok(KindConn::new(kind, ContextMode::Raw, self.state.clone()))
+    }
+
+    /// Returns state attached to the connection
+    pub fn extensions(&self) -> &Extensions {
+        &self.state.extensions
+    }
+
+    /// Waits until the version is agreed, returns the peer's handshake extension
+    ///
+    /// [`None`] if the peer didn't register the extension, its payload is
+    /// malformed or the protocol version doesn't support extensions,
+    /// see [`HandshakeExtension`]
+    ///
+    /// [`None`]: std::option::Option::None
+    /// [`HandshakeExtension`]: crate::builder::handshake::HandshakeExtension
+    pub async fn peer_extension<E: HandshakeExtension>(&self) -> Option<E> {
+        E::decode(&self.state.handshake.peer(E::KEY).await?)
+    }
+
+    /// Spawns provider task bound to the connection
+    ///
+    /// If the task panics, the connection is closed with [`PROVIDER_PANIC`]
+    /// code and the panic message as the reason, see [`KindConn::close_reason()`]
+    ///
+    /// [`PROVIDER_PANIC`]: crate::builder::kind_conn::close_code::PROVIDER_PANIC
+    /// [`KindConn::close_reason()`]: crate::builder::kind_conn::KindConn::close_reason
+    pub fn spawn<F: Future<Output=()> + Send + 'static>(&self, future: F) {
+        let conn = self.state.conn.clone();
+
+        runtime::spawn(async move {
+            if let Err(message) = runtime::catch_unwind(future).await {
+                conn.close_with_reason(PROVIDER_PANIC, panic_reason(&message)).await;
+            }
+        });
+    }
+
+    pub(crate) fn state(&self) -> &Arc<ContextState> {
+        &self.state
+    }
+
+    /// Returns context allocating kinds from the same range
+    pub(crate) fn share(&self) -> Self {
+        Context {
+            state: self.state.clone(),
+            mode: self.mode,
+            kinds: self.kinds.clone(),
+        }
+    }
+
+    /// Returns context allocating kinds from the provider block
+    pub(crate) fn for_provider(&self, slot: ProviderSlot, mode: ContextMode) -> Self {
+        if self.state.version.sequential_kinds() {
+            return Context { state: self.state.clone(), mode, kinds: None };
+        }
+
+        let start = slot.first_kind();
+        // The last kind of every block is used by error frames, key rotation and versioning
+        let end = match slot {
+            ProviderSlot::Ping => ERROR_KIND,
+            ProviderSlot::Encryption => REKEY_KIND,
+            ProviderSlot::Compression => VERSION_KIND,
+        };
+
         Context {
             state: self.state.clone(),
             mode,
+            kinds: Some(Arc::new(KindRange {
+                next: RwLock::new(start),
+                end,
+            })),
         }
     }
 }
+
+impl KindProviders {
+    /// Returns kinds with encryption and compression overrides
+    pub(crate) fn kinds(&self) -> (BTreeSet<u8>, BTreeSet<u8>) {
+        (self.encryption.keys().copied().collect(), self.compression.keys().copied().collect())
+    }
+}
+
+impl ContextState {
+    /// Returns encryption provider of packages of the kind
+    pub(crate) fn encryption(&self, kind: u8) -> &dyn EncryptionProvider {
+        match self.kind_providers.encryption.get(&kind) {
+            Some(encryption) => encryption.as_ref(),
+            None => self.encryption.as_ref(),
+        }
+    }
+
+    /// Returns compression provider of packages of the kind
+    pub(crate) fn compression(&self, kind: u8) -> &dyn CompressionProvider {
+        match self.kind_providers.compression.get(&kind) {
+            Some(compression) => compression.as_ref(),
+            None => self.compression.as_ref(),
+        }
+    }
+
+    /// Switches keys of the encryption provider and of its per-kind overrides
+    ///
+    /// Returns `false` if the connection provider can't rotate keys
+    pub(crate) fn rotate_keys(&self) -> bool {
+        for encryption in self.kind_providers.encryption.values() {
+            encryption.rotate_keys();
+        }
+        self.encryption.rotate_keys()
+    }
+}
+
+impl Activity {
+    fn new() -> Self {
+        Activity {
+            started: Instant::now(),
+            last_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Marks the connection as active now
+    pub(crate) fn touch(&self) {
+        let now = self.started.elapsed().as_millis() as u64;
+        self.last_ms.fetch_max(now, Ordering::Relaxed);
+    }
+
+    /// Returns time since the last package
+    pub(crate) fn idle(&self) -> Duration {
+        let last = Duration::from_millis(self.last_ms.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last)
+    }
+}
+
+// Cuts the message to fit into a close frame
+pub(crate) fn panic_reason(message: &str) -> &str {
+    let mut len = message.len().min(MAX_PANIC_REASON_LEN);
+    while !message.is_char_boundary(len) {
+        len -= 1;
+    }
+
+    &message[..len]
+}
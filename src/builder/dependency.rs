@@ -0,0 +1,85 @@
+use std::future::Future;
+
+use tokio::sync::watch;
+
+use crate::builder::builder::BuildError;
+
+/// Provider initialized by [`Builder::run()`], see [`PingProvider::depends_on()`]
+///
+/// [`Builder::run()`]: crate::builder::builder::Builder::run
+/// [`PingProvider::depends_on()`]: crate::builder::builder::PingProvider::depends_on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Provider {
+    Ping = 0,
+    Encryption = 1,
+    Compression = 2,
+}
+
+/// Order of provider initialization declared by the providers
+///
+/// Every provider waits until its dependencies are initialized, providers
+/// without dependencies between them are initialized concurrently. Provider
+/// is skipped if one of its dependencies failed, the failure is reported by it
+pub(crate) struct InitOrder {
+    depends_on: [Vec<Provider>; 3],
+    // None until the provider is initialized, then whether it succeeded
+    done: [watch::Sender<Option<bool>>; 3],
+}
+
+impl InitOrder {
+    /// Creates order from dependencies of the ping, encryption and compression providers
+    pub(crate) fn new(depends_on: [Vec<Provider>; 3]) -> Self {
+        InitOrder {
+            depends_on,
+            done: [(); 3].map(|_| watch::channel(None).0),
+        }
+    }
+
+    /// Returns true if providers depend on each other in a loop,
+    /// so they would wait for each other forever
+    pub(crate) fn has_cycle(&self) -> bool {
+        [Provider::Ping, Provider::Encryption, Provider::Compression]
+            .iter()
+            .any(|&provider| self.reaches(provider, provider, 0))
+    }
+
+    // Every path without loops has at most as many steps as there are providers
+    fn reaches(&self, from: Provider, to: Provider, depth: usize) -> bool {
+        depth < self.depends_on.len() && self.depends_on[from as usize]
+            .iter()
+            .any(|&next| next == to || self.reaches(next, to, depth + 1))
+    }
+
+    /// Runs `init` of the provider once its dependencies are initialized
+    pub(crate) async fn run<F: Future<Output=Result<(), BuildError>>>(&self, provider: Provider, init: F) -> Result<(), BuildError> {
+        // Skipped provider counts as failed for the ones depending on it
+        let (result, succeeded) = match self.ready(provider).await {
+            true => {
+                let result = init.await;
+                let succeeded = result.is_ok();
+                (result, succeeded)
+            }
+            false => (Ok(()), false),
+        };
+        self.done[provider as usize].send_replace(Some(succeeded));
+        result
+    }
+
+    // Returns false if one of the dependencies failed
+    async fn ready(&self, provider: Provider) -> bool {
+        for dependency in &self.depends_on[provider as usize] {
+            let mut done = self.done[*dependency as usize].subscribe();
+            loop {
+                if let Some(succeeded) = *done.borrow_and_update() {
+                    if !succeeded {
+                        return false;
+                    }
+                    break;
+                }
+                // The sender is kept by the order
+                let _ = done.changed().await;
+            }
+        }
+        true
+    }
+}
@@ -31,6 +31,14 @@ impl EncryptionProvider for EmptyRealisation {
     fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
         frame
     }
+
+    fn shared_key(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn passthrough(&self) -> bool {
+        true
+    }
 }
 
 #[async_trait]
@@ -44,4 +52,12 @@ impl CompressionProvider for EmptyRealisation {
     fn decompress(&self, frame: Vec<u8>) -> Vec<u8> {
         frame
     }
+
+    fn shared_key(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn passthrough(&self) -> bool {
+        true
+    }
 }
@@ -0,0 +1,69 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Typed storage for state attached to a connection
+///
+/// Holds at most one value of every type. Shared between all
+/// [`KindConn`]s of a connection and contexts of its providers
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::builder::extensions::Extensions;
+///
+/// #[derive(Clone, Debug, PartialEq)]
+/// struct PeerName(String);
+///
+/// let extensions = Extensions::new();
+/// extensions.insert(PeerName("alice".to_string()));
+///
+/// assert_eq!(extensions.get::<PeerName>(), Some(PeerName("alice".to_string())));
+/// ```
+///
+/// [`KindConn`]: crate::builder::kind_conn::KindConn
+#[derive(Default)]
+pub struct Extensions {
+    map: RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
+}
+
+impl Extensions {
+    /// Creates empty storage
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Inserts value, returns previous value of the same type
+    pub fn insert<T: 'static + Send + Sync>(&self, value: T) -> Option<T> {
+        self.map.write().unwrap()
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    /// Returns copy of the value with the specified type
+    pub fn get<T: 'static + Send + Sync + Clone>(&self) -> Option<T> {
+        self.with(|value: &T| value.clone())
+    }
+
+    /// Calls `op` with a reference to the value with the specified type
+    pub fn with<T: 'static + Send + Sync, R, F: FnOnce(&T) -> R>(&self, op: F) -> Option<R> {
+        self.map.read().unwrap()
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+            .map(op)
+    }
+
+    /// Returns `true` if storage contains value with the specified type
+    pub fn contains<T: 'static + Send + Sync>(&self) -> bool {
+        self.map.read().unwrap().contains_key(&TypeId::of::<T>())
+    }
+
+    /// Removes value with the specified type
+    pub fn remove<T: 'static + Send + Sync>(&self) -> Option<T> {
+        self.map.write().unwrap()
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+}
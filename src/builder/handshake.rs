@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Parameters exchanged by both sides during the handshake
+///
+/// Lets providers negotiate their settings without allocating kinds.
+/// Extensions are registered with [`Builder::handshake_extension()`] and
+/// the peer's ones are read with [`Context::peer_extension()`]. They are
+/// sent once after the version frame if both sides support them, see [`Features`]
+///
+/// # Example
+///
+/// ```
+/// use std::convert::TryInto;
+///
+/// use cobra_rs::builder::handshake::HandshakeExtension;
+///
+/// struct MaxPackage(u32);
+///
+/// impl HandshakeExtension for MaxPackage {
+///     const KEY: &'static str = "example.max-package";
+///
+///     fn encode(&self) -> Vec<u8> {
+///         self.0.to_be_bytes().to_vec()
+///     }
+///
+///     fn decode(payload: &[u8]) -> Option<Self> {
+///         Some(MaxPackage(u32::from_be_bytes(payload.try_into().ok()?)))
+///     }
+/// }
+/// ```
+///
+/// [`Builder::handshake_extension()`]: crate::builder::builder::Builder::handshake_extension
+/// [`Context::peer_extension()`]: crate::builder::context::Context::peer_extension
+/// [`Features`]: crate::builder::version::Features
+pub trait HandshakeExtension: Sized {
+    /// Key of the extension, prefixed by the provider name to avoid collisions
+    const KEY: &'static str;
+
+    fn encode(&self) -> Vec<u8>;
+
+    /// Returns [`None`] if the payload is malformed
+    ///
+    /// [`None`]: std::option::Option::None
+    fn decode(payload: &[u8]) -> Option<Self>;
+}
+
+/// Payloads of extensions by their keys
+type ExtensionMap = BTreeMap<String, Vec<u8>>;
+
+/// Handshake extensions of both sides
+pub(crate) struct Handshake {
+    local: ExtensionMap,
+    // None until the version is agreed
+    peer: watch::Sender<Option<Arc<ExtensionMap>>>,
+}
+
+impl Handshake {
+    pub(crate) fn new(local: ExtensionMap) -> Self {
+        Handshake {
+            local,
+            peer: watch::Sender::new(None),
+        }
+    }
+
+    pub(crate) fn local(&self) -> &ExtensionMap {
+        &self.local
+    }
+
+    /// Stores extensions of the peer, empty if it doesn't send them
+    pub(crate) fn set_peer(&self, extensions: ExtensionMap) {
+        self.peer.send_replace(Some(Arc::new(extensions)));
+    }
+
+    /// Waits for extensions of the peer, returns payload of the key
+    pub(crate) async fn peer(&self, key: &str) -> Option<Vec<u8>> {
+        let mut peer = self.peer.subscribe();
+        let extensions = peer.wait_for(Option::is_some).await.ok()?.clone()?;
+        extensions.get(key).cloned()
+    }
+}
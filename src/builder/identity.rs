@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+/// Identity the peer proved during the encryption handshake
+///
+/// Inserted into connection extensions by encryption providers
+/// which authenticate the peer (TLS, Noise), see [`KindConn::peer_identity()`]
+///
+/// [`KindConn::peer_identity()`]: crate::builder::kind_conn::KindConn::peer_identity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerIdentity {
+    /// DER encoded certificates, the peer certificate goes first
+    CertificateChain(Vec<Vec<u8>>),
+
+    /// Static public key of the peer, e.g. Noise `rs`
+    StaticKey(Vec<u8>),
+}
+
+/// Decides whether the peer identity is accepted, see [`Builder::verify_peer()`]
+///
+/// [`Builder::verify_peer()`]: crate::builder::builder::Builder::verify_peer
+pub(crate) type IdentityVerifier = Arc<dyn Fn(&PeerIdentity) -> bool + Send + Sync>;
+
+impl PeerIdentity {
+    /// Returns bytes identifying the peer itself: its certificate or static key
+    ///
+    /// Usually the hash of these bytes is pinned
+    pub fn leaf(&self) -> Option<&[u8]> {
+        match self {
+            PeerIdentity::CertificateChain(chain) => chain.first().map(Vec::as_slice),
+            PeerIdentity::StaticKey(key) => Some(key),
+        }
+    }
+}
@@ -1,74 +1,759 @@
+use std::fs::File;
 use std::net::SocketAddr;
+use std::ops::Range;
 use std::sync::Arc;
 use std::io;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
 
+use bytes::Bytes;
+use futures_core::Stream;
+
+use crate::builder::builder::DecryptError;
+use crate::builder::channel::{ChannelError, Directory};
 use crate::builder::context::{ContextMode, ContextState};
-use crate::sync::WriteError;
-use crate::mem::Frame;
-
-pub mod close_code {
-    pub const CLOSED_BY_USER: u8 = 1;
-    pub const NOT_FOUND_PING: u8 = 2;
-    pub const NOT_FOUND_ENCRYPTION: u8 = 3;
-    pub const NOT_FOUND_COMPRESSION: u8 = 4;
-    pub const PING_TIMEOUT: u8 = 5;
-    pub const ENCRYPTION_ERROR: u8 = 6;
-    pub const COMPRESSION_ERROR: u8 = 7;
+use crate::builder::extensions::Extensions;
+use crate::builder::identity::PeerIdentity;
+use crate::builder::kind_refs::KindRefs;
+use crate::builder::peer_error::{PeerError, ERROR_KIND};
+use crate::builder::rekey::Rekey;
+use crate::builder::stats::{ConnStats, Stats, StatsError};
+use crate::builder::wide_kind::WideKindConn;
+use crate::protocol::ControlMessage;
+use crate::config::PartialConfig;
+use crate::providers::default_ping_provider::PingIntervals;
+use crate::runtime;
+use crate::sync::{PollSlot, WriteError};
+use crate::mem::{Frame, KindWidth};
+use crate::transport::close_cause::CloseCause;
+use crate::transport::file::{self, FileChunk};
+
+use self::close_code::ENCRYPTION_ERROR;
+
+pub mod close_code;
+
+// Bounds the wait for packages received before the peer closed the kind,
+// when other kinds keep frames queued
+const DRAIN_ATTEMPTS: usize = 16;
+
+/// Connections with equal keys produce identical frames from the same package
+///
+/// Contains kind and keys of encryption and compression providers,
+/// providers aren't used by raw connections
+pub(crate) type EncodingKey = (u8, Option<(u64, u64)>);
+
+/// Error returned by [`KindConn::read_deadline()`] and [`KindConn::write_deadline()`]
+///
+/// [`KindConn::read_deadline()`]: crate::builder::kind_conn::KindConn::read_deadline
+/// [`KindConn::write_deadline()`]: crate::builder::kind_conn::KindConn::write_deadline
+#[derive(Debug)]
+pub enum DeadlineError {
+    /// Deadline passed before the package was read or taken by the transport
+    Elapsed,
+
+    /// Package wasn't written, see [`KindConn::write()`]
+    ///
+    /// [`KindConn::write()`]: crate::builder::kind_conn::KindConn::write
+    Write(WriteError<Vec<u8>>),
+}
+
+/// Error yielded by [`KindConn::frames()`]
+///
+/// [`KindConn::frames()`]: crate::builder::kind_conn::KindConn::frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadError {
+    /// Package was rejected by the encryption provider,
+    /// the connection is closed with [`ENCRYPTION_ERROR`] code
+    ///
+    /// [`ENCRYPTION_ERROR`]: crate::builder::kind_conn::close_code::ENCRYPTION_ERROR
+    Decrypt(DecryptError),
+
+    /// Transport failed, e.g. the peer reset the connection, see [`CloseCause::Io`]
+    ///
+    /// [`CloseCause::Io`]: crate::transport::close_cause::CloseCause::Io
+    Transport(CloseCause),
 }
 
+/// Connection of one kind
+///
+/// Clones share the kind: packages written by any of them are mixed in
+/// the order of writes and each package is read by one of them. Kind is
+/// released once its last handle is dropped, the peer sees it with
+/// [`is_released()`] if its protocol version supports it, see [`Features`].
+/// Released kinds are reissued by [`open_kind()`]
+///
+/// [`is_released()`]: crate::builder::kind_conn::KindConn::is_released
+/// [`open_kind()`]: crate::builder::kind_conn::KindConn::open_kind
+/// [`Features`]: crate::builder::version::Features
 pub struct KindConn {
     kind: u8,
     mode: ContextMode,
     state: Arc<ContextState>,
+
+    // Operations started by poll-based methods
+    read_slot: PollSlot<Option<Vec<u8>>>,
+    write_slot: PollSlot<Result<(), WriteError<Vec<u8>>>>,
 }
 
 impl KindConn {
     pub(crate) fn new(kind: u8, mode: ContextMode, state: Arc<ContextState>) -> Self {
+        if state.refs.is_application(kind) {
+            state.refs.acquire(kind);
+        }
+
         KindConn {
             kind,
             mode,
             state,
+            read_slot: PollSlot::new(),
+            write_slot: PollSlot::new(),
+        }
+    }
+
+    fn detached(&self) -> KindConn {
+        KindConn::new(self.kind, self.mode, self.state.clone())
+    }
+
+    /// Returns protocol version agreed with the peer
+    ///
+    /// See [`Builder::protocol_version()`]
+    ///
+    /// [`Builder::protocol_version()`]: crate::builder::builder::Builder::protocol_version
+    pub fn protocol_version(&self) -> u8 {
+        self.state.version.get()
+    }
+
+    /// Returns kind of the frames carrying packages of this connection
+    pub fn kind(&self) -> u8 {
+        self.kind
+    }
+
+    /// Returns true if packages aren't passed through encryption and compression
+    ///
+    /// Connections of provider kinds and ones returned by [`Context::raw_kind_conn()`] are raw
+    ///
+    /// [`Context::raw_kind_conn()`]: crate::builder::context::Context::raw_kind_conn
+    pub fn is_raw(&self) -> bool {
+        matches!(self.mode, ContextMode::Raw)
+    }
+
+    /// Opens connection of another application kind
+    ///
+    /// The lowest kind reclaimed from both sides is reissued first, then
+    /// kinds never used before. Both sides get the same kind as long as
+    /// they open kinds at the same points of the exchange. Kinds are
+    /// reclaimed only if the peer's protocol version supports it, see [`Features`].
+    /// Returns [`None`] if all application kinds are used
+    ///
+    /// [`Features`]: crate::builder::version::Features
+    /// [`None`]: std::option::Option::None
+    pub fn open_kind(&self) -> Option<KindConn> {
+        let kind = self.state.refs.allocate()?;
+        Some(KindConn::new(kind, ContextMode::Handle, self.state.clone()))
+    }
+
+    /// Returns connection of the application kind, used by routes with agreed kinds
+    pub(crate) fn with_kind(&self, kind: u8) -> Option<KindConn> {
+        self.state.refs.is_application(kind).then(|| KindConn::new(kind, ContextMode::Handle, self.state.clone()))
+    }
+
+    /// Opens the channel with the name, waits until the peer agrees on its kind
+    ///
+    /// Both sides get connections of the same kind for the same name without
+    /// hardcoding kind numbers. Channel is the same until both sides drop its
+    /// connections and the kind is reclaimed, see [`open_kind()`]. The peer
+    /// doesn't have to open the channel first, its packages are queued
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cobra_rs::builder::builder::Builder;
+    /// use cobra_rs::transport::tcp::Conn;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let conn = Conn::connect("127.0.0.1:5000").await.unwrap();
+    ///     let conn = Builder::new().set_conn(conn).run().await.unwrap();
+    ///
+    ///     let chat = conn.open_channel("chat").await.unwrap();
+    ///     chat.write(b"hello".to_vec()).await.unwrap();
+    /// }
+    /// ```
+    ///
+    /// [`open_kind()`]: crate::builder::kind_conn::KindConn::open_kind
+    pub async fn open_channel(&self, name: &str) -> Result<KindConn, ChannelError> {
+        let kind = Directory::open(&self.state, name).await?;
+        Ok(KindConn::new(kind, ContextMode::Handle, self.state.clone()))
+    }
+
+    /// Returns connection of the kind wider than one byte
+    ///
+    /// Wide kinds don't overlap with kinds returned by [`open_kind()`], both
+    /// sides use the same kind number. Returns [`None`] if the kind doesn't
+    /// fit the width agreed with the peer, see [`Builder::kind_width()`]
+    ///
+    /// [`open_kind()`]: crate::builder::kind_conn::KindConn::open_kind
+    /// [`None`]: std::option::Option::None
+    /// [`Builder::kind_width()`]: crate::builder::builder::Builder::kind_width
+    pub fn wide_kind(&self, kind: u32) -> Option<WideKindConn> {
+        match self.kind_width() {
+            KindWidth::U8 => None,
+            width if kind > width.max_kind() => None,
+            _ => Some(WideKindConn::new(kind, self.state.clone())),
         }
     }
 
+    /// Returns width of kinds agreed with the peer
+    pub fn kind_width(&self) -> KindWidth {
+        self.state.version.kind_width()
+    }
+
+    /// Returns number of live handles of the kind, including this one
+    ///
+    /// Internal operations (e.g. started by [`poll_read_frame()`]) hold
+    /// a handle until they complete. Always 1 for kinds of providers
+    ///
+    /// [`poll_read_frame()`]: crate::builder::kind_conn::KindConn::poll_read_frame
+    pub fn handles(&self) -> usize {
+        match self.state.refs.is_application(self.kind) {
+            true => self.state.refs.handles(self.kind),
+            false => 1,
+        }
+    }
+
+    /// Returns true if the peer has dropped all handles of the kind,
+    /// so it won't read or write packages of the kind anymore
+    pub fn is_released(&self) -> bool {
+        self.state.refs.is_released(self.kind)
+    }
+
+    /// Returns stats of the connection on this side
+    pub fn stats(&self) -> ConnStats {
+        ConnStats::collect(&self.state)
+    }
+
+    /// Asks the peer for stats of the connection on its side
+    ///
+    /// Peer answers only if it exposes them, see [`Builder::expose_stats()`].
+    /// Waits for the answer at most `timeout`
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use cobra_rs::builder::builder::Builder;
+    /// use cobra_rs::transport::tcp::Conn;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let conn = Builder::new()
+    ///         .set_conn(Conn::connect("127.0.0.1:5000").await.unwrap())
+    ///         .run()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let stats = conn.peer_stats(Duration::from_secs(1)).await.unwrap();
+    ///     println!("peer queues {} bytes", stats.transport.queued_bytes);
+    /// }
+    /// ```
+    ///
+    /// [`Builder::expose_stats()`]: crate::builder::builder::Builder::expose_stats
+    pub async fn peer_stats(&self, timeout: Duration) -> Result<ConnStats, StatsError> {
+        Stats::query(&self.state, timeout).await
+    }
+
     pub async fn read(&self) -> Option<Vec<u8>> {
-        let package = self.state
+        self.read_package().await.ok()?
+    }
+
+    pub async fn write(&self, package: Vec<u8>) -> Result<(), WriteError<Vec<u8>>> {
+        let package = self.encode(package);
+        self.write_encoded(&package).await
+    }
+
+    /// The same as [`read()`], but gives up at `deadline`
+    ///
+    /// Waiting is cancelled before a package is taken, so a package
+    /// arriving later is returned by the next read. A deadline in the
+    /// past still returns an already received package
+    ///
+    /// [`read()`]: crate::builder::kind_conn::KindConn::read
+    pub async fn read_deadline(&self, deadline: Instant) -> Result<Option<Vec<u8>>, DeadlineError> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        runtime::timeout(timeout, self.read())
+            .await
+            .map_err(|_| DeadlineError::Elapsed)
+    }
+
+    /// The same as [`write()`], but stops waiting for the transport at `deadline`
+    ///
+    /// The package isn't lost on [`DeadlineError::Elapsed`]: it stays queued and
+    /// is written once the transport catches up, [`flush()`] waits for it
+    ///
+    /// [`write()`]: crate::builder::kind_conn::KindConn::write
+    /// [`flush()`]: crate::builder::kind_conn::KindConn::flush
+    /// [`DeadlineError::Elapsed`]: crate::builder::kind_conn::DeadlineError::Elapsed
+    pub async fn write_deadline(&self, package: Vec<u8>, deadline: Instant) -> Result<(), DeadlineError> {
+        if self.is_closed() {
+            return Err(DeadlineError::Write(WriteError::Rejected(package)));
+        }
+        let package = self.encode(package);
+        self.record(package.len());
+
+        let frame = Frame::create(self.kind, &package);
+        let conn = self.state.conn.clone();
+        let mut write = Box::pin(async move { conn.write(frame).await });
+
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match runtime::timeout(timeout, &mut write).await {
+            Ok(written) => written.map_err(|err| DeadlineError::Write(err.map(|frame| frame.get_body().to_vec()))),
+            Err(_) => {
+                // Dropped write would discard the package
+                runtime::spawn(async move {
+                    let _ = write.await;
+                });
+                Err(DeadlineError::Elapsed)
+            }
+        }
+    }
+
+    /// Writes package ahead of packages queued by [`write()`]
+    ///
+    /// Intended for small cancellation and control messages which must not
+    /// wait behind bulk data, see [`ConnProvider::write_urgent()`]
+    ///
+    /// [`write()`]: crate::builder::kind_conn::KindConn::write
+    /// [`ConnProvider::write_urgent()`]: crate::builder::builder::ConnProvider::write_urgent
+    pub async fn write_urgent(&self, package: Vec<u8>) -> Result<(), WriteError<Vec<u8>>> {
+        if self.is_closed() {
+            return Err(WriteError::Rejected(package));
+        }
+        let package = self.encode(package);
+        self.record(package.len());
+        let frame = Frame::create(self.kind, &package);
+
+        self.state
             .conn
-            .read(self.kind)
-            .await?
-            .get_body()
-            .to_vec();
-        let package = self.state
-            .compression
-            .decompress(package);
-        let package = self.state
-            .encryption
-            .decrypt(package);
+            .write_urgent(frame)
+            .await
+            .map_err(|err| err.map(|frame| frame.get_body().to_vec()))
+    }
+
+    /// Writes region of the file as packages
+    ///
+    /// Region is split into packages of at most [`MAX_FILE_CHUNK`] bytes, the peer
+    /// reads them with [`read()`]. If neither encryption nor compression change
+    /// data, the transport may send the file without copying it through userspace
+    /// (TCP uses `sendfile` on Linux), otherwise packages are read into memory
+    ///
+    /// Returns [`ErrorKind::UnexpectedEof`] if the file is shorter than the region,
+    /// [`ErrorKind::BrokenPipe`] if the kind was closed by [`close_kind()`]
+    /// and [`ErrorKind::NotConnected`] if the connection was closed
+    ///
+    /// [`MAX_FILE_CHUNK`]: crate::transport::file::MAX_FILE_CHUNK
+    /// [`read()`]: crate::builder::kind_conn::KindConn::read
+    /// [`ErrorKind::UnexpectedEof`]: std::io::ErrorKind::UnexpectedEof
+    /// [`ErrorKind::BrokenPipe`]: std::io::ErrorKind::BrokenPipe
+    /// [`close_kind()`]: crate::builder::kind_conn::KindConn::close_kind
+    /// [`ErrorKind::NotConnected`]: std::io::ErrorKind::NotConnected
+    pub async fn write_file(&self, file: &File, range: Range<u64>) -> io::Result<()> {
+        if self.is_closed() {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "kind is closed"));
+        }
+        self.touch();
+        if self.passthrough() {
+            return self.state.conn.write_file(self.kind, file, range).await;
+        }
+
+        for chunk in FileChunk::split(self.kind, Arc::new(file.try_clone()?), range) {
+            let package = chunk.read().await?;
+            self.write(package).await.map_err(|_| file::closed())?;
+        }
 
-        Some(package)
+        Ok(())
     }
 
-    pub async fn write(&self, package: Vec<u8>) -> Result<(), WriteError<Vec<u8>>> {
-        let frame = match self.mode {
-            ContextMode::Raw => Frame::create(self.kind, &package[..]),
+    /// Reports error to the peer, it's returned by the peer's [`read_error()`]
+    ///
+    /// Error frames use the reserved [`ERROR_KIND`] and don't mix with packages.
+    /// `correlation` may carry id of the failed request. Returns [`WriteError::Rejected`]
+    /// if the peer's protocol version doesn't support error frames, see [`Features`]
+    ///
+    /// [`WriteError::Rejected`]: crate::sync::WriteError::Rejected
+    /// [`Features`]: crate::builder::version::Features
+    /// [`read_error()`]: crate::builder::kind_conn::KindConn::read_error
+    /// [`ERROR_KIND`]: crate::builder::peer_error::ERROR_KIND
+    pub async fn send_error(&self, code: u16, message: &str, correlation: Option<u64>) -> Result<(), WriteError<PeerError>> {
+        let error = PeerError {
+            kind: self.kind,
+            code,
+            message: message.to_string(),
+            correlation,
+        };
+        if !self.state.version.features().error_frames {
+            return Err(WriteError::Rejected(error));
+        }
+
+        self.errors()
+            .write(error.encode())
+            .await
+            .map_err(|err| err.map(|_| error))
+    }
+
+    /// Returns the next error sent by the peer with [`send_error()`]
+    ///
+    /// Errors of all kinds of the connection are read by any of its [`KindConn`]s,
+    /// [`PeerError::kind`] tells which one sent it. Malformed error frames are skipped.
+    /// Returns [`None`] if the connection was closed and at once if the protocol
+    /// version doesn't have error frames, see [`Features::error_frames`]
+    ///
+    /// [`send_error()`]: crate::builder::kind_conn::KindConn::send_error
+    /// [`KindConn`]: crate::builder::kind_conn::KindConn
+    /// [`PeerError::kind`]: crate::builder::peer_error::PeerError::kind
+    /// [`None`]: std::option::Option::None
+    /// [`Features::error_frames`]: crate::builder::version::Features::error_frames
+    pub async fn read_error(&self) -> Option<PeerError> {
+        // The kind of error frames may be an application one
+        if !self.state.version.features().error_frames {
+            return None;
+        }
+        let errors = self.errors();
+
+        loop {
+            if let Some(error) = PeerError::decode(&errors.read().await?) {
+                return Some(error);
+            }
+        }
+    }
+
+    // Returns None once the kind is closed by the peer and its packages are read
+    // Rejected package closes the connection
+    async fn read_package(&self) -> Result<Option<Vec<u8>>, ReadError> {
+        let package = match self.read_frame().await {
+            Some(frame) => frame.get_body().to_vec(),
+            None => return Ok(None),
+        };
+        self.touch();
+
+        match self.decode(package) {
+            Ok(package) => Ok(Some(package)),
+            Err(error) => {
+                self.close(ENCRYPTION_ERROR).await;
+                Err(ReadError::Decrypt(error))
+            }
+        }
+    }
+
+    async fn read_frame(&self) -> Option<Frame> {
+        if !self.state.refs.is_application(self.kind) {
+            return self.state.conn.read(self.kind).await;
+        }
+
+        tokio::select! {
+            biased;
+            frame = self.state.conn.read(self.kind) => frame,
+            _ = self.state.refs.peer_closed(self.kind) => self.drain().await,
+        }
+    }
+
+    /// Reads packages of the kind received before the peer closed it
+    async fn drain(&self) -> Option<Frame> {
+        // They are queued by the transport before the notice is handled,
+        // but may still be on their way to the reader of the kind
+        let mut read = Box::pin(self.state.conn.read(self.kind));
+        for _ in 0..DRAIN_ATTEMPTS {
+            if let Poll::Ready(frame) = poll_fn(|cx| Poll::Ready(read.as_mut().poll(cx))).await {
+                return frame;
+            }
+            if self.state.conn.stats().queued_frames == 0 {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        None
+    }
+
+    /// Tells the peer that this side won't write packages of the kind anymore
+    ///
+    /// Packages written before are delivered, then the peer's reads of the kind
+    /// return [`None`], while other kinds stay open. Later writes of the kind are
+    /// rejected with [`WriteError::Rejected`]. Packages of the peer are still read.
+    ///
+    /// Returns [`ErrorKind::InvalidInput`] for kinds of providers,
+    /// [`ErrorKind::Unsupported`] if the peer's protocol version doesn't support
+    /// it (see [`Features`]) and [`ErrorKind::NotConnected`] if the connection was closed
+    ///
+    /// [`None`]: std::option::Option::None
+    /// [`WriteError::Rejected`]: crate::sync::WriteError::Rejected
+    /// [`ErrorKind::InvalidInput`]: std::io::ErrorKind::InvalidInput
+    /// [`ErrorKind::Unsupported`]: std::io::ErrorKind::Unsupported
+    /// [`ErrorKind::NotConnected`]: std::io::ErrorKind::NotConnected
+    /// [`Features`]: crate::builder::version::Features
+    pub async fn close_kind(&self) -> io::Result<()> {
+        if !self.state.refs.is_application(self.kind) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "kinds of providers can't be closed"));
+        }
+        if !self.state.version.features().kind_close {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "peer can't close kinds"));
+        }
+        if !self.state.refs.close(self.kind) {
+            return Ok(());
+        }
+
+        // Notice goes by another kind, so it must not overtake queued packages
+        self.state.conn.flush().await;
+        KindRefs::send(&self.state, ControlMessage::KindClosed { kind: self.kind })
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::NotConnected, "connection is closed"))
+    }
+
+    /// Returns application kinds closed by the peer, see [`close_kind()`]
+    ///
+    /// Kind is reopened once it is reclaimed, see [`open_kind()`]
+    ///
+    /// [`close_kind()`]: crate::builder::kind_conn::KindConn::close_kind
+    /// [`open_kind()`]: crate::builder::kind_conn::KindConn::open_kind
+    pub fn peer_closed_kinds(&self) -> Vec<u8> {
+        self.state.refs.peer_closed_kinds()
+    }
+
+    // Returns true if this side closed the kind
+    fn is_closed(&self) -> bool {
+        self.state.refs.is_application(self.kind) && self.state.refs.is_closed(self.kind)
+    }
+
+    // Error frames are encoded like packages of this connection
+    fn errors(&self) -> KindConn {
+        KindConn::new(ERROR_KIND, self.mode, self.state.clone())
+    }
+
+    // Returns true if packages are written as they are
+    fn passthrough(&self) -> bool {
+        match self.mode {
+            ContextMode::Raw => true,
+            ContextMode::Handle => {
+                self.state.encryption(self.kind).passthrough() && self.state.compression(self.kind).passthrough()
+            }
+        }
+    }
+
+    // Applies encryption and compression to the package
+    pub(crate) fn encode(&self, package: Vec<u8>) -> Vec<u8> {
+        match self.mode {
+            ContextMode::Raw => package,
             ContextMode::Handle => {
                 let package = self.state
-                    .encryption
+                    .encryption(self.kind)
                     .encrypt(package);
+                self.state
+                    .compression(self.kind)
+                    .compress(package)
+            }
+        }
+    }
+
+    // Reverts encode(), raw packages aren't passed through providers
+    pub(crate) fn decode(&self, package: Vec<u8>) -> Result<Vec<u8>, DecryptError> {
+        match self.mode {
+            ContextMode::Raw => Ok(package),
+            ContextMode::Handle => {
                 let package = self.state
-                    .compression
-                    .compress(package);
-                Frame::create(self.kind, &package[..])
+                    .compression(self.kind)
+                    .decompress(package);
+                self.state
+                    .encryption(self.kind)
+                    .try_decrypt(package)
             }
-        };
+        }
+    }
 
-        self.state
-            .conn
-            .write(frame)
+    // Returns None if encoding depends on the connection state
+    pub(crate) fn encoding_key(&self) -> Option<EncodingKey> {
+        match self.mode {
+            ContextMode::Raw => Some((self.kind, None)),
+            ContextMode::Handle => {
+                let encryption = self.state.encryption(self.kind).shared_key()?;
+                let compression = self.state.compression(self.kind).shared_key()?;
+                Some((self.kind, Some((encryption, compression))))
+            }
+        }
+    }
+
+    // Writes package already passed through encode()
+    pub(crate) async fn write_encoded(&self, package: &[u8]) -> Result<(), WriteError<Vec<u8>>> {
+        self.write_frame(Frame::create(self.kind, package), package.len())
             .await
             .map_err(|err| err.map(|frame| frame.get_body().to_vec()))
     }
 
+    // Writes frame carrying encoded package of `len` bytes
+    pub(crate) async fn write_frame(&self, frame: Frame, len: usize) -> Result<(), WriteError<Frame>> {
+        if self.is_closed() {
+            return Err(WriteError::Rejected(frame));
+        }
+        self.record(len);
+        self.state.conn.write(frame).await
+    }
+
+    pub(crate) fn state(&self) -> &Arc<ContextState> {
+        &self.state
+    }
+
+    /// Returns time since a package of application kinds was last read
+    /// or written on the connection
+    ///
+    /// Packages of providers (e.g. pings) don't count, see [`IdleReaper`]
+    ///
+    /// [`IdleReaper`]: crate::server::IdleReaper
+    pub fn idle(&self) -> Duration {
+        self.state.activity.idle()
+    }
+
+    // Provider kinds don't make the connection active
+    pub(crate) fn touch(&self) {
+        if self.kind >= self.state.refs.first_application() {
+            self.state.activity.touch();
+        }
+    }
+
+    // Counts written package for idle tracking and automatic key rotation
+    fn record(&self, len: usize) {
+        self.touch();
+        if let ContextMode::Handle = self.mode {
+            Rekey::record(&self.state, len);
+        }
+    }
+
+    /// Switches encryption keys of both sides
+    ///
+    /// Own key is switched at once, returns when the peer has switched its key.
+    /// Either side may initiate rotation, see [`EncryptionProvider::rotate_keys()`].
+    /// Providers set with [`Builder::set_kind_encryption()`] switch their keys too
+    ///
+    /// Returns [`ErrorKind::Unsupported`] if the encryption provider can't
+    /// rotate keys or the protocol version is 1 and [`ErrorKind::NotConnected`]
+    /// if the connection was closed
+    ///
+    /// [`EncryptionProvider::rotate_keys()`]: crate::builder::builder::EncryptionProvider::rotate_keys
+    /// [`Builder::set_kind_encryption()`]: crate::builder::builder::Builder::set_kind_encryption
+    /// [`ErrorKind::Unsupported`]: std::io::ErrorKind::Unsupported
+    /// [`ErrorKind::NotConnected`]: std::io::ErrorKind::NotConnected
+    pub async fn rotate_keys(&self) -> io::Result<()> {
+        Rekey::rotate(&self.state).await
+    }
+
+    /// Waits until all frames queued so far are written
+    pub async fn flush(&self) {
+        self.state.conn.flush().await
+    }
+
+    /// Poll-based version of [`read()`]
+    ///
+    /// Read started by this method is kept until it completes,
+    /// so it is safe to call it again after [`Poll::Pending`]
+    ///
+    /// [`read()`]: crate::builder::kind_conn::KindConn::read
+    /// [`Poll::Pending`]: std::task::Poll::Pending
+    pub fn poll_read_frame(&self, cx: &mut Context<'_>) -> Poll<Option<Vec<u8>>> {
+        self.read_slot.poll(cx, || {
+            let conn = self.detached();
+            async move { conn.read().await }
+        })
+    }
+
+    /// Returns packages of the kind as a [`Stream`]
+    ///
+    /// Stream ends when the connection or the kind is closed. Packages
+    /// rejected by the encryption provider and transport failures are
+    /// yielded as [`ReadError`] before the end
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::future::poll_fn;
+    /// use std::pin::Pin;
+    ///
+    /// use futures_core::Stream;
+    ///
+    /// use cobra_rs::builder::kind_conn::KindConn;
+    ///
+    /// async fn print_packages(conn: &KindConn) {
+    ///     let mut frames = conn.frames();
+    ///     while let Some(package) = poll_fn(|cx| Pin::new(&mut frames).poll_next(cx)).await {
+    ///         match package {
+    ///             Ok(package) => println!("received {} bytes", package.len()),
+    ///             Err(error) => println!("read failed: {:?}", error),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`Stream`]: futures_core::Stream
+    /// [`ReadError`]: crate::builder::kind_conn::ReadError
+    pub fn frames(&self) -> KindFrames {
+        KindFrames {
+            conn: self.detached(),
+            read_slot: PollSlot::new(),
+            finished: false,
+        }
+    }
+
+    /// Poll-based version of [`write()`]
+    ///
+    /// Package is taken from `package` when the write starts, subsequent
+    /// calls drive the same write until it completes
+    ///
+    /// # Note
+    ///
+    /// Panics if `package` is [`None`] and there is no write in progress
+    ///
+    /// [`write()`]: crate::builder::kind_conn::KindConn::write
+    /// [`None`]: std::option::Option::None
+    pub fn poll_write_frame(&self, cx: &mut Context<'_>, package: &mut Option<Vec<u8>>) -> Poll<Result<(), WriteError<Vec<u8>>>> {
+        self.write_slot.poll(cx, || {
+            let conn = self.detached();
+            let package = package.take().expect("poll_write_frame called without a package");
+            async move { conn.write(package).await }
+        })
+    }
+
+    /// Returns state attached to the connection
+    ///
+    /// Storage is shared by all kinds of the connection
+    /// and its providers
+    pub fn extensions(&self) -> &Extensions {
+        &self.state.extensions
+    }
+
+    /// Returns identity the peer proved during the encryption handshake
+    ///
+    /// [`None`] if the encryption provider doesn't authenticate peers
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn peer_identity(&self) -> Option<PeerIdentity> {
+        self.extensions().get()
+    }
+
+    /// Changes settings of the live connection
+    ///
+    /// Transport settings are applied by the [`ConnProvider`], ping
+    /// intervals are applied if [`DefaultPingProvider`] is used.
+    /// Settings are shared by all kinds of the connection
+    ///
+    /// [`ConnProvider`]: crate::builder::builder::ConnProvider
+    /// [`DefaultPingProvider`]: crate::providers::default_ping_provider::DefaultPingProvider
+    pub fn reconfigure(&self, config: &PartialConfig) {
+        self.state.conn.reconfigure(config);
+
+        if let Some(ping) = &config.ping {
+            let (long_duration, short_duration) = ping.durations();
+            self.extensions().with(|intervals: &PingIntervals| intervals.set(long_duration, short_duration));
+        }
+    }
+
     pub fn local_addr(&self) -> io::Result<SocketAddr> {
         self.state.conn.local_addr()
     }
@@ -85,7 +770,96 @@ impl KindConn {
         self.state.conn.close(code).await
     }
 
+    pub async fn close_with_reason(&self, code: u8, reason: &str) {
+        self.state.conn.close_with_reason(code, reason).await
+    }
+
+    /// Stops writing to the connection, keeps reading
+    /// until the peer closes its write side
+    ///
+    /// See [`ConnProvider::shutdown_write()`]
+    ///
+    /// [`ConnProvider::shutdown_write()`]: crate::builder::builder::ConnProvider::shutdown_write
+    pub async fn shutdown_write(&self) {
+        self.state.conn.shutdown_write().await
+    }
+
     pub async fn is_close(&self) -> Option<u8> {
         self.state.conn.is_close().await
     }
+
+    pub async fn close_reason(&self) -> Option<String> {
+        self.state.conn.close_reason().await
+    }
+
+    /// Returns why the transport was closed, e.g. reset by the peer
+    ///
+    /// See [`ConnProvider::close_cause()`]
+    ///
+    /// [`ConnProvider::close_cause()`]: crate::builder::builder::ConnProvider::close_cause
+    pub async fn close_cause(&self) -> Option<CloseCause> {
+        self.state.conn.close_cause().await
+    }
+}
+
+/// Stream of packages returned by [`KindConn::frames()`]
+///
+/// [`KindConn::frames()`]: crate::builder::kind_conn::KindConn::frames
+pub struct KindFrames {
+    conn: KindConn,
+    read_slot: PollSlot<Result<Option<Vec<u8>>, ReadError>>,
+    finished: bool,
+}
+
+impl Stream for KindFrames {
+    type Item = Result<Bytes, ReadError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+
+        let read = ready!(self.read_slot.poll(cx, || {
+            let conn = self.conn.detached();
+            async move {
+                match conn.read_package().await {
+                    // Failed transport is reported once before the end
+                    Ok(None) => match conn.close_cause().await {
+                        Some(cause @ CloseCause::Io { .. }) => Err(ReadError::Transport(cause)),
+                        _ => Ok(None),
+                    },
+                    read => read,
+                }
+            }
+        }));
+
+        match read {
+            Ok(Some(package)) => Poll::Ready(Some(Ok(package.into()))),
+            Ok(None) => {
+                self.finished = true;
+                Poll::Ready(None)
+            }
+            Err(error) => {
+                self.finished = true;
+                Poll::Ready(Some(Err(error)))
+            }
+        }
+    }
+}
+
+impl Clone for KindConn {
+    /// Returns another handle of the same kind
+    ///
+    /// Operations started by poll-based methods aren't shared
+    fn clone(&self) -> Self {
+        self.detached()
+    }
+}
+
+impl Drop for KindConn {
+    fn drop(&mut self) {
+        if self.state.refs.is_application(self.kind) {
+            KindRefs::release(&self.state, self.kind);
+        }
+    }
 }
@@ -0,0 +1,175 @@
+//! Codes passed to [`close()`] and returned by [`is_close()`]
+//!
+//! Codes are split into ranges, see [`CloseRange`]. Codes of the protocol
+//! and library ranges are assigned by this crate only, the application
+//! may use any code of the application range
+//!
+//! [`close()`]: crate::builder::kind_conn::KindConn::close
+//! [`is_close()`]: crate::builder::kind_conn::KindConn::is_close
+//! [`CloseRange`]: crate::builder::kind_conn::close_code::CloseRange
+
+use std::ops::RangeInclusive;
+
+pub const CLOSED_BY_USER: u8 = CloseCode::ClosedByUser.code();
+pub const NOT_FOUND_PING: u8 = CloseCode::NotFoundPing.code();
+pub const NOT_FOUND_ENCRYPTION: u8 = CloseCode::NotFoundEncryption.code();
+pub const NOT_FOUND_COMPRESSION: u8 = CloseCode::NotFoundCompression.code();
+pub const PING_TIMEOUT: u8 = CloseCode::PingTimeout.code();
+pub const ENCRYPTION_ERROR: u8 = CloseCode::EncryptionError.code();
+pub const COMPRESSION_ERROR: u8 = CloseCode::CompressionError.code();
+pub const IO_ERROR: u8 = CloseCode::IoError.code();
+pub const HANDSHAKE_TIMEOUT: u8 = CloseCode::HandshakeTimeout.code();
+pub const CANCELLED: u8 = CloseCode::Cancelled.code();
+pub const INTERNAL_ERROR: u8 = CloseCode::InternalError.code();
+pub const PROVIDER_PANIC: u8 = CloseCode::ProviderPanic.code();
+pub const IDENTITY_REJECTED: u8 = CloseCode::IdentityRejected.code();
+pub const UNSUPPORTED_VERSION: u8 = CloseCode::UnsupportedVersion.code();
+pub const SLOW_CONSUMER: u8 = CloseCode::SlowConsumer.code();
+pub const IDLE_TIMEOUT: u8 = CloseCode::IdleTimeout.code();
+pub const GOING_AWAY: u8 = CloseCode::GoingAway.code();
+
+/// Range of close codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseRange {
+    /// Codes of the connection protocol, `0..=31`
+    Protocol,
+    /// Codes of the library policies (e.g. server draining), `32..=63`
+    Library,
+    /// Codes free for the application, `64..=255`
+    Application,
+}
+
+/// Close code with its meaning
+///
+/// Converts from and into the `u8` sent in the close frame,
+/// every code survives the round trip unchanged
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::builder::kind_conn::close_code::{CloseCode, CloseRange, PING_TIMEOUT};
+///
+/// assert_eq!(CloseCode::from(PING_TIMEOUT), CloseCode::PingTimeout);
+///
+/// let code = CloseCode::application(100).unwrap();
+/// assert_eq!(code.range(), CloseRange::Application);
+/// assert_eq!(u8::from(code), 100);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseCode {
+    ClosedByUser,
+    NotFoundPing,
+    NotFoundEncryption,
+    NotFoundCompression,
+    PingTimeout,
+    EncryptionError,
+    CompressionError,
+    IoError,
+    HandshakeTimeout,
+    Cancelled,
+    InternalError,
+    ProviderPanic,
+    IdentityRejected,
+    UnsupportedVersion,
+    SlowConsumer,
+    IdleTimeout,
+    GoingAway,
+
+    /// Unassigned code of the protocol or library range,
+    /// e.g. sent by a newer version of the peer
+    Reserved(u8),
+
+    /// Code of the application range
+    Application(u8),
+}
+
+impl CloseRange {
+    /// Returns codes of the range
+    pub const fn codes(self) -> RangeInclusive<u8> {
+        match self {
+            CloseRange::Protocol => 0..=31,
+            CloseRange::Library => 32..=63,
+            CloseRange::Application => 64..=255,
+        }
+    }
+
+    /// Returns range containing the code
+    pub const fn of(code: u8) -> Self {
+        match code {
+            0..=31 => CloseRange::Protocol,
+            32..=63 => CloseRange::Library,
+            _ => CloseRange::Application,
+        }
+    }
+}
+
+impl CloseCode {
+    /// Returns application code, [`None`] if `code` is out of the application range
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn application(code: u8) -> Option<Self> {
+        match CloseRange::of(code) {
+            CloseRange::Application => Some(CloseCode::Application(code)),
+            _ => None,
+        }
+    }
+
+    /// Returns code sent in the close frame
+    pub const fn code(self) -> u8 {
+        match self {
+            CloseCode::ClosedByUser => 1,
+            CloseCode::NotFoundPing => 2,
+            CloseCode::NotFoundEncryption => 3,
+            CloseCode::NotFoundCompression => 4,
+            CloseCode::PingTimeout => 5,
+            CloseCode::EncryptionError => 6,
+            CloseCode::CompressionError => 7,
+            CloseCode::IoError => 8,
+            CloseCode::HandshakeTimeout => 9,
+            CloseCode::Cancelled => 10,
+            CloseCode::InternalError => 11,
+            CloseCode::ProviderPanic => 12,
+            CloseCode::IdentityRejected => 13,
+            CloseCode::UnsupportedVersion => 14,
+            CloseCode::SlowConsumer => 32,
+            CloseCode::IdleTimeout => 33,
+            CloseCode::GoingAway => 34,
+            CloseCode::Reserved(code) | CloseCode::Application(code) => code,
+        }
+    }
+
+    pub const fn range(self) -> CloseRange {
+        CloseRange::of(self.code())
+    }
+}
+
+impl From<u8> for CloseCode {
+    fn from(code: u8) -> Self {
+        match code {
+            1 => CloseCode::ClosedByUser,
+            2 => CloseCode::NotFoundPing,
+            3 => CloseCode::NotFoundEncryption,
+            4 => CloseCode::NotFoundCompression,
+            5 => CloseCode::PingTimeout,
+            6 => CloseCode::EncryptionError,
+            7 => CloseCode::CompressionError,
+            8 => CloseCode::IoError,
+            9 => CloseCode::HandshakeTimeout,
+            10 => CloseCode::Cancelled,
+            11 => CloseCode::InternalError,
+            12 => CloseCode::ProviderPanic,
+            13 => CloseCode::IdentityRejected,
+            14 => CloseCode::UnsupportedVersion,
+            32 => CloseCode::SlowConsumer,
+            33 => CloseCode::IdleTimeout,
+            34 => CloseCode::GoingAway,
+            code => CloseCode::application(code).unwrap_or(CloseCode::Reserved(code)),
+        }
+    }
+}
+
+impl From<CloseCode> for u8 {
+    fn from(code: CloseCode) -> Self {
+        code.code()
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::{BTreeSet, HashMap};
+use std::future::{poll_fn, Future};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::task::Poll;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+use crate::builder::channel::Directory;
+use crate::builder::context::{ContextMode, ContextState, FIRST_APPLICATION_KIND, LAST_APPLICATION_KIND};
+use crate::builder::kind_conn::KindConn;
+use crate::builder::stats::Stats;
+use crate::builder::version::VERSION_KIND;
+use crate::protocol::{ControlMessage, Encoding};
+use crate::runtime;
+use crate::sync::WriteError;
+
+/// How long a kind released by both sides isn't reissued by default
+///
+/// See [`Builder::kind_quarantine()`]
+///
+/// [`Builder::kind_quarantine()`]: crate::builder::builder::Builder::kind_quarantine
+pub const DEFAULT_KIND_QUARANTINE: Duration = Duration::from_secs(5);
+
+/// Lifecycle of an application kind on this side
+#[derive(Default)]
+struct Lifecycle {
+    handles: usize,
+    released: bool,
+    peer_released: bool,
+    quarantined: bool,
+    reclaimed: bool,
+    peer_reclaimed: bool,
+    // Kind was closed by this side or by the peer, see KindConn::close_kind()
+    closed: bool,
+    peer_closed: bool,
+}
+
+#[derive(Default)]
+struct Kinds {
+    used: HashMap<u8, Lifecycle>,
+    // Reclaimed kinds, the lowest one is reissued first
+    free: BTreeSet<u8>,
+    // Kind issued next if there are no reclaimed ones
+    next: u16,
+    // Kind issued next to named channels, they are taken from the top
+    top: u16,
+}
+
+/// Handles of application kinds, releases and reissues them with the peer
+///
+/// Kind is released once the last handle is dropped. When both sides have
+/// released it and the quarantine is over, frames of the kind still queued
+/// are dropped and both sides send [`KindReclaimed`]. Kind is reissued once
+/// it was sent and received, so frames of the old channel never reach the new one
+///
+/// [`KindReclaimed`]: crate::protocol::ControlMessage::KindReclaimed
+pub(crate) struct KindRefs {
+    kinds: Mutex<Kinds>,
+    // Kinds below it belong to providers
+    first: AtomicU8,
+    quarantine: Duration,
+    closed: AtomicBool,
+    peer_closed: Notify,
+}
+
+impl KindRefs {
+    pub(crate) fn new(quarantine: Duration) -> Self {
+        KindRefs {
+            kinds: Mutex::new(Kinds {
+                next: FIRST_APPLICATION_KIND as u16,
+                top: LAST_APPLICATION_KIND as u16,
+                ..Default::default()
+            }),
+            first: AtomicU8::new(FIRST_APPLICATION_KIND),
+            quarantine,
+            closed: AtomicBool::new(false),
+            peer_closed: Notify::new(),
+        }
+    }
+
+    /// Issues kinds from `1` on, providers included, see [`Version::sequential_kinds()`]
+    ///
+    /// [`Version::sequential_kinds()`]: crate::builder::version::Version::sequential_kinds
+    pub(crate) fn sequential(&self) {
+        self.kinds.lock().unwrap().next = 1;
+    }
+
+    /// Marks kinds issued from now on as application ones,
+    /// called once providers are initialized
+    pub(crate) fn start_application(&self) {
+        let next = self.kinds.lock().unwrap().next;
+        self.first.store(next as u8, Ordering::SeqCst);
+    }
+
+    /// Returns true if the kind belongs to the application,
+    /// kinds of frames carrying wide kinds don't
+    pub(crate) fn is_application(&self, kind: u8) -> bool {
+        (self.first_application()..=LAST_APPLICATION_KIND).contains(&kind)
+    }
+
+    pub(crate) fn first_application(&self) -> u8 {
+        self.first.load(Ordering::SeqCst)
+    }
+
+    /// Returns an unused application kind, [`None`] if all of them are used
+    ///
+    /// [`None`]: std::option::Option::None
+    pub(crate) fn allocate(&self) -> Option<u8> {
+        let mut kinds = self.kinds.lock().unwrap();
+
+        let kind = match kinds.free.pop_first() {
+            Some(kind) => kind,
+            None => loop {
+                if kinds.next > kinds.top {
+                    return None;
+                }
+                kinds.next += 1;
+                if !kinds.used.contains_key(&(kinds.next as u8 - 1)) {
+                    break kinds.next as u8 - 1;
+                }
+            },
+        };
+        kinds.used.entry(kind).or_default();
+        Some(kind)
+    }
+
+    /// The same as [`allocate()`], but takes the greatest unused kind
+    ///
+    /// [`allocate()`]: crate::builder::kind_refs::KindRefs::allocate
+    pub(crate) fn allocate_top(&self) -> Option<u8> {
+        let mut kinds = self.kinds.lock().unwrap();
+
+        let kind = match kinds.free.pop_last() {
+            Some(kind) => kind,
+            None => loop {
+                if kinds.next > kinds.top {
+                    return None;
+                }
+                kinds.top -= 1;
+                if !kinds.used.contains_key(&(kinds.top as u8 + 1)) {
+                    break kinds.top as u8 + 1;
+                }
+            },
+        };
+        kinds.used.entry(kind).or_default();
+        Some(kind)
+    }
+
+    /// Marks the kind allocated by the peer as used, so it isn't issued here
+    pub(crate) fn claim(&self, kind: u8) {
+        let mut kinds = self.kinds.lock().unwrap();
+        kinds.free.remove(&kind);
+        kinds.used.entry(kind).or_default();
+    }
+
+    /// Counts a new handle of the kind
+    pub(crate) fn acquire(&self, kind: u8) {
+        self.kinds.lock().unwrap().used.entry(kind).or_default().handles += 1;
+    }
+
+    /// Forgets a handle of the kind, the peer is notified once the last one is dropped
+    pub(crate) fn release(state: &Arc<ContextState>, kind: u8) {
+        {
+            // Handles created before the kind became an application one aren't counted
+            let mut kinds = state.refs.kinds.lock().unwrap();
+            let lifecycle = match kinds.used.get_mut(&kind) {
+                Some(lifecycle) if lifecycle.handles > 0 => lifecycle,
+                _ => return,
+            };
+            lifecycle.handles -= 1;
+            if lifecycle.handles > 0 {
+                return;
+            }
+            lifecycle.released = true;
+        }
+
+        if !state.version.features().kind_release || state.refs.closed.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // Handle may be dropped in the middle of the write, so it's written by a task
+        let state = state.clone();
+        runtime::spawn(async move {
+            let _ = KindRefs::send(&state, ControlMessage::KindReleased { kind }).await;
+            KindRefs::quarantine(state, kind).await;
+        });
+    }
+
+    pub(crate) fn handles(&self, kind: u8) -> usize {
+        self.kinds.lock().unwrap().used.get(&kind).map_or(0, |lifecycle| lifecycle.handles)
+    }
+
+    /// Returns number of application kinds with live handles
+    pub(crate) fn open(&self) -> usize {
+        self.kinds.lock().unwrap().used.values().filter(|lifecycle| lifecycle.handles > 0).count()
+    }
+
+    pub(crate) fn is_released(&self, kind: u8) -> bool {
+        self.kinds.lock().unwrap().used.get(&kind).is_some_and(|lifecycle| lifecycle.peer_released)
+    }
+
+    /// Marks the kind as closed by this side, returns false if it already was
+    pub(crate) fn close(&self, kind: u8) -> bool {
+        let mut kinds = self.kinds.lock().unwrap();
+        let lifecycle = kinds.used.entry(kind).or_default();
+        !std::mem::replace(&mut lifecycle.closed, true)
+    }
+
+    pub(crate) fn is_closed(&self, kind: u8) -> bool {
+        self.kinds.lock().unwrap().used.get(&kind).is_some_and(|lifecycle| lifecycle.closed)
+    }
+
+    pub(crate) fn is_peer_closed(&self, kind: u8) -> bool {
+        self.kinds.lock().unwrap().used.get(&kind).is_some_and(|lifecycle| lifecycle.peer_closed)
+    }
+
+    /// Returns kinds closed by the peer, in ascending order
+    pub(crate) fn peer_closed_kinds(&self) -> Vec<u8> {
+        let kinds = self.kinds.lock().unwrap();
+        let mut closed: Vec<u8> = kinds.used
+            .iter()
+            .filter(|(_, lifecycle)| lifecycle.peer_closed)
+            .map(|(&kind, _)| kind)
+            .collect();
+        closed.sort_unstable();
+        closed
+    }
+
+    /// Waits until the peer closes the kind
+    pub(crate) async fn peer_closed(&self, kind: u8) {
+        loop {
+            let closed = self.peer_closed.notified();
+            if self.is_peer_closed(kind) {
+                return;
+            }
+            closed.await;
+        }
+    }
+
+    /// Handles release notices, channel directory messages
+    /// and stats queries of the peer until the connection is closed
+    pub(crate) async fn serve(state: Arc<ContextState>) {
+        let conn = KindRefs::conn(&state);
+
+        while let Some(message) = conn.read().await {
+            let message = match ControlMessage::decode(&message, Encoding::of(state.version.features())) {
+                Some(message) => message,
+                None => continue,
+            };
+
+            match message {
+                ControlMessage::KindReleased { kind } => {
+                    KindRefs::update(&state, kind, |lifecycle| lifecycle.peer_released = true);
+                    let state = state.clone();
+                    runtime::spawn(KindRefs::quarantine(state, kind));
+                }
+                ControlMessage::KindReclaimed { kind } => KindRefs::update(&state, kind, |lifecycle| lifecycle.peer_reclaimed = true),
+                ControlMessage::KindClosed { kind } => {
+                    KindRefs::update(&state, kind, |lifecycle| lifecycle.peer_closed = true);
+                    state.refs.peer_closed.notify_waiters();
+                }
+                _ => {
+                    Directory::handle(&state, &message).await;
+                    Stats::handle(&state, &message).await;
+                }
+            }
+        }
+
+        state.refs.closed.store(true, Ordering::SeqCst);
+        state.channels.close();
+        state.stats.close();
+    }
+
+    // Waits out the quarantine once the kind is released by both sides
+    async fn quarantine(state: Arc<ContextState>, kind: u8) {
+        {
+            let mut kinds = state.refs.kinds.lock().unwrap();
+            match kinds.used.get_mut(&kind) {
+                Some(lifecycle) if lifecycle.released && lifecycle.peer_released && !lifecycle.quarantined => {
+                    lifecycle.quarantined = true;
+                }
+                _ => return,
+            }
+        }
+
+        runtime::sleep(state.refs.quarantine).await;
+
+        // The peer doesn't write the kind until it receives KIND_RECLAIMED,
+        // so every frame queued now belongs to the old channel
+        loop {
+            let mut read = Box::pin(state.conn.read(kind));
+            match poll_fn(|cx| Poll::Ready(read.as_mut().poll(cx))).await {
+                Poll::Ready(Some(_)) => continue,
+                _ => break,
+            }
+        }
+        if KindRefs::send(&state, ControlMessage::KindReclaimed { kind }).await.is_ok() {
+            KindRefs::update(&state, kind, |lifecycle| lifecycle.reclaimed = true);
+        }
+    }
+
+    // Reissues the kind once both sides have reclaimed it
+    fn update<F: FnOnce(&mut Lifecycle)>(state: &Arc<ContextState>, kind: u8, update: F) {
+        {
+            let mut kinds = state.refs.kinds.lock().unwrap();
+            let lifecycle = kinds.used.entry(kind).or_default();
+            update(lifecycle);
+
+            if !lifecycle.reclaimed || !lifecycle.peer_reclaimed {
+                return;
+            }
+            kinds.used.remove(&kind);
+            kinds.free.insert(kind);
+        }
+
+        // Name may be assigned to another kind now
+        state.channels.forget(kind);
+    }
+
+    /// Writes the message in the encoding of the negotiated version
+    pub(crate) async fn send(state: &Arc<ContextState>, message: ControlMessage) -> Result<(), WriteError<Vec<u8>>> {
+        let message = message.encode(Encoding::of(state.version.features()));
+        KindRefs::conn(state).write(message).await
+    }
+
+    fn conn(state: &Arc<ContextState>) -> KindConn {
+        KindConn::new(VERSION_KIND, ContextMode::Raw, state.clone())
+    }
+}
@@ -1,4 +1,18 @@
+#[allow(clippy::module_inception)]
 pub mod builder;
+pub mod channel;
 pub mod context;
+pub mod dependency;
 pub mod empty_realisations;
+pub mod extensions;
+pub mod handshake;
+pub mod identity;
 pub mod kind_conn;
+pub mod kind_refs;
+pub mod peer_error;
+pub mod profile;
+pub mod rekey;
+pub mod stats;
+pub mod validation;
+pub mod version;
+pub mod wide_kind;
@@ -0,0 +1,76 @@
+use bytes::{Buf, BufMut};
+
+use crate::builder::context::{PROVIDER_KINDS, ProviderSlot};
+
+/// Kind used by error frames, see [`KindConn::send_error()`]
+///
+/// The last kind of the ping provider block,
+/// so ping providers may allocate only `PROVIDER_KINDS - 1` kinds
+///
+/// [`KindConn::send_error()`]: crate::builder::kind_conn::KindConn::send_error
+pub const ERROR_KIND: u8 = ProviderSlot::Ping.first_kind() + PROVIDER_KINDS - 1;
+
+// Payload carries the correlation id
+const HAS_CORRELATION: u8 = 1;
+
+/// Error reported by the peer with [`KindConn::send_error()`]
+///
+/// Payload of the error frame is `[kind: 1 byte][code: 2 bytes][flags: 1 byte]
+/// [correlation: 8 bytes, if flags & 1][message: UTF-8]`, integers are big-endian.
+/// Error frames are encrypted and compressed like packages
+///
+/// [`KindConn::send_error()`]: crate::builder::kind_conn::KindConn::send_error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerError {
+    /// Kind of the connection which sent the error
+    pub kind: u8,
+    /// Application-defined error code
+    pub code: u16,
+    pub message: String,
+    /// Id of the request which failed, if the application uses them
+    pub correlation: Option<u64>,
+}
+
+impl PeerError {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(12 + self.message.len());
+        payload.put_u8(self.kind);
+        payload.put_u16(self.code);
+
+        match self.correlation {
+            Some(correlation) => {
+                payload.put_u8(HAS_CORRELATION);
+                payload.put_u64(correlation);
+            }
+            None => payload.put_u8(0),
+        }
+
+        payload.put_slice(self.message.as_bytes());
+        payload
+    }
+
+    /// Returns [`None`] if the payload is malformed
+    ///
+    /// [`None`]: std::option::Option::None
+    pub(crate) fn decode(mut payload: &[u8]) -> Option<Self> {
+        if payload.remaining() < 4 {
+            return None;
+        }
+        let kind = payload.get_u8();
+        let code = payload.get_u16();
+        let flags = payload.get_u8();
+
+        let correlation = match flags & HAS_CORRELATION {
+            0 => None,
+            _ if payload.remaining() >= 8 => Some(payload.get_u64()),
+            _ => return None,
+        };
+
+        Some(PeerError {
+            kind,
+            code,
+            message: String::from_utf8(payload.to_vec()).ok()?,
+            correlation,
+        })
+    }
+}
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use crate::mem::GrowthPolicy;
+use crate::providers::default_ping_provider::DefaultPingProvider;
+use crate::transport::tcp::ConnConfig;
+
+/// Coherent set of settings for a typical workload
+///
+/// Connection-level settings must be applied when the connection
+/// is created, provider settings are applied by [`Builder::profile()`]
+///
+/// # Example
+///
+/// ```no_run
+/// use cobra_rs::builder::builder::Builder;
+/// use cobra_rs::builder::profile::Profile;
+/// use cobra_rs::transport::tcp::Conn;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let profile = Profile::HighThroughput;
+///     let conn = Conn::connect_with_config("127.0.0.1:5000", profile.conn_config())
+///         .await
+///         .unwrap();
+///
+///     let conn = Builder::new()
+///         .set_conn(conn)
+///         .profile(profile)
+///         .run()
+///         .await
+///         .unwrap();
+/// }
+/// ```
+///
+/// [`Builder::profile()`]: crate::builder::builder::Builder::profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Frames are sent immediately, dead peers are detected fast
+    LowLatency,
+
+    /// Small frames are batched, large buffers are used
+    HighThroughput,
+
+    /// Rare pings, aggressive batching and small buffers
+    Battery,
+
+    /// Slow pings and tiny buffers for serial and Bluetooth links,
+    /// see [`StreamConn`]
+    ///
+    /// [`StreamConn`]: crate::transport::stream::StreamConn
+    LowBandwidth,
+}
+
+impl Profile {
+    /// Returns transport settings of the profile
+    pub fn conn_config(&self) -> ConnConfig {
+        match self {
+            Profile::LowLatency => ConnConfig::new()
+                .set_buffer_policy(GrowthPolicy::new(4 * 1024, 64 * 1024)),
+
+            Profile::HighThroughput => ConnConfig::new()
+                .set_buffer_policy(GrowthPolicy::new(64 * 1024, 256 * 1024))
+                .set_write_coalescing(Duration::from_micros(200)),
+
+            Profile::Battery => ConnConfig::new()
+                .set_buffer_policy(GrowthPolicy::new(1024, 16 * 1024))
+                .set_write_coalescing(Duration::from_millis(2)),
+
+            Profile::LowBandwidth => ConnConfig::new()
+                .set_buffer_policy(GrowthPolicy::new(256, 4 * 1024))
+                .set_recv_high_water_mark(16 * 1024)
+                .set_write_coalescing(Duration::from_millis(5))
+                .set_close_timeout(Duration::from_secs(5)),
+        }
+    }
+
+    /// Returns ping provider configured for the profile
+    pub fn ping(&self) -> DefaultPingProvider {
+        let (long_duration, short_duration) = match self {
+            Profile::LowLatency => (Duration::from_secs(5), Duration::from_secs(2)),
+            Profile::HighThroughput => (Duration::from_secs(30), Duration::from_secs(10)),
+            Profile::Battery => (Duration::from_secs(120), Duration::from_secs(30)),
+            Profile::LowBandwidth => (Duration::from_secs(60), Duration::from_secs(20)),
+        };
+
+        DefaultPingProvider::new(long_duration, short_duration)
+    }
+}
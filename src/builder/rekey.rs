@@ -0,0 +1,135 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use tokio::sync::Notify;
+
+use crate::builder::context::{ContextMode, ContextState, PROVIDER_KINDS, ProviderSlot};
+use crate::builder::kind_conn::KindConn;
+use crate::runtime;
+
+/// Kind used by the key rotation protocol
+///
+/// The last kind of the encryption provider block,
+/// so encryption providers may allocate only `PROVIDER_KINDS - 1` kinds
+pub const REKEY_KIND: u8 = ProviderSlot::Encryption.first_kind() + PROVIDER_KINDS - 1;
+
+/// Peer has switched its key, the receiver switches its key too
+const REKEY: u8 = 0;
+/// Receiver has switched its key
+const REKEY_ACK: u8 = 1;
+
+/// Limits of data encrypted with one key
+///
+/// Keys are rotated automatically when any limit is reached,
+/// see [`Builder::key_rotation()`]
+///
+/// [`Builder::key_rotation()`]: crate::builder::builder::Builder::key_rotation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyRotation {
+    /// Number of written packages
+    pub frames: Option<u64>,
+
+    /// Number of written package bytes
+    pub bytes: Option<u64>,
+}
+
+/// Key rotation state of the connection
+#[derive(Default)]
+pub(crate) struct Rekey {
+    policy: Option<KeyRotation>,
+    frames: AtomicU64,
+    bytes: AtomicU64,
+    rotating: AtomicBool,
+    closed: AtomicBool,
+    ack_notifier: Notify,
+}
+
+impl Rekey {
+    pub(crate) fn new(policy: Option<KeyRotation>) -> Self {
+        Rekey {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Counts written package, starts rotation if a limit is reached
+    pub(crate) fn record(state: &Arc<ContextState>, len: usize) {
+        let rekey = &state.rekey;
+        let policy = match rekey.policy {
+            Some(policy) if !state.version.sequential_kinds() => policy,
+            _ => return,
+        };
+
+        let frames = rekey.frames.fetch_add(1, Ordering::SeqCst) + 1;
+        let bytes = rekey.bytes.fetch_add(len as u64, Ordering::SeqCst) + len as u64;
+        let exceeded = policy.frames.is_some_and(|limit| frames >= limit)
+            || policy.bytes.is_some_and(|limit| bytes >= limit);
+
+        if exceeded && !rekey.rotating.swap(true, Ordering::SeqCst) {
+            let state = state.clone();
+            runtime::spawn(async move {
+                let _ = Rekey::rotate(&state).await;
+                state.rekey.rotating.store(false, Ordering::SeqCst);
+            });
+        }
+    }
+
+    /// Switches own key, asks the peer to switch its key and waits for that
+    pub(crate) async fn rotate(state: &Arc<ContextState>) -> io::Result<()> {
+        let rekey = &state.rekey;
+
+        let acked = rekey.ack_notifier.notified();
+        tokio::pin!(acked);
+        acked.as_mut().enable();
+
+        if rekey.closed.load(Ordering::SeqCst) {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "connection is closed"));
+        }
+        // Peers of version 1 have no kind for rotation requests
+        if state.version.sequential_kinds() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "peer can't rotate keys"));
+        }
+        if !state.rotate_keys() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "encryption provider can't rotate keys"));
+        }
+        rekey.frames.store(0, Ordering::SeqCst);
+        rekey.bytes.store(0, Ordering::SeqCst);
+
+        if Rekey::conn(state).write(vec![REKEY]).await.is_err() {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "connection is closed"));
+        }
+        acked.await;
+
+        if rekey.closed.load(Ordering::SeqCst) {
+            Err(io::Error::new(io::ErrorKind::NotConnected, "connection is closed"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Handles rotation requests of the peer until the connection is closed
+    pub(crate) async fn serve(state: Arc<ContextState>) {
+        let conn = Rekey::conn(&state);
+
+        while let Some(message) = conn.read().await {
+            match message.first() {
+                Some(&REKEY) => {
+                    state.rotate_keys();
+                    if conn.write(vec![REKEY_ACK]).await.is_err() {
+                        break;
+                    }
+                }
+                Some(&REKEY_ACK) => state.rekey.ack_notifier.notify_waiters(),
+                _ => {}
+            }
+        }
+
+        state.rekey.closed.store(true, Ordering::SeqCst);
+        state.rekey.ack_notifier.notify_waiters();
+    }
+
+    fn conn(state: &Arc<ContextState>) -> KindConn {
+        KindConn::new(REKEY_KIND, ContextMode::Raw, state.clone())
+    }
+}
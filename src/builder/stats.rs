@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+use crate::builder::context::ContextState;
+use crate::builder::kind_refs::KindRefs;
+use crate::builder::version::Features;
+use crate::mem::KindWidth;
+use crate::protocol::ControlMessage;
+use crate::runtime;
+
+/// Counters of the transport, see [`ConnProvider::stats()`]
+///
+/// [`ConnProvider::stats()`]: crate::builder::builder::ConnProvider::stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransportStats {
+    /// Received bytes waiting for the application
+    pub queued_bytes: usize,
+
+    /// Received frames waiting for the application
+    pub queued_frames: usize,
+
+    /// Frames queued for write but not yet handed to the kernel
+    pub pending_writes: usize,
+
+    /// Queued frames dropped because they outlived their TTL, see [`ConnConfig::set_frame_ttl()`]
+    ///
+    /// [`ConnConfig::set_frame_ttl()`]: crate::transport::tcp::ConnConfig::set_frame_ttl
+    pub expired_frames: u64,
+
+    /// Smoothed round-trip time, [`None`] if the transport doesn't measure it
+    ///
+    /// [`None`]: std::option::Option::None
+    pub rtt: Option<Duration>,
+}
+
+/// Counters of the compression provider, see [`CompressionProvider::stats()`]
+///
+/// [`CompressionProvider::stats()`]: crate::builder::builder::CompressionProvider::stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Bytes of packages passed to the compression
+    pub input_bytes: u64,
+
+    /// Bytes written after the compression, stored packages included
+    pub output_bytes: u64,
+
+    /// Time spent compressing packages
+    pub compress_time: Duration,
+
+    /// Time spent decompressing packages
+    pub decompress_time: Duration,
+
+    /// False while packages are sent uncompressed
+    pub enabled: bool,
+}
+
+impl CompressionStats {
+    /// Returns output bytes per input byte, `1.0` if nothing was compressed yet
+    pub fn ratio(&self) -> f64 {
+        if self.input_bytes == 0 {
+            1.0
+        } else {
+            self.output_bytes as f64 / self.input_bytes as f64
+        }
+    }
+}
+
+/// Stats of the connection, see [`KindConn::stats()`] and [`KindConn::peer_stats()`]
+///
+/// [`KindConn::stats()`]: crate::builder::kind_conn::KindConn::stats
+/// [`KindConn::peer_stats()`]: crate::builder::kind_conn::KindConn::peer_stats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnStats {
+    pub protocol_version: u8,
+    pub features: Features,
+    pub kind_width: KindWidth,
+
+    /// Time since a package of application kinds was last read or written
+    pub idle: Duration,
+
+    /// Application kinds with live handles
+    pub open_kinds: usize,
+
+    pub transport: TransportStats,
+
+    /// [`None`] if the compression provider doesn't count them
+    ///
+    /// [`None`]: std::option::Option::None
+    pub compression: Option<CompressionStats>,
+}
+
+/// Error returned by [`KindConn::peer_stats()`]
+///
+/// [`KindConn::peer_stats()`]: crate::builder::kind_conn::KindConn::peer_stats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsError {
+    /// Peer's protocol version doesn't support stats queries, see [`Features`]
+    ///
+    /// [`Features`]: crate::builder::version::Features
+    Unsupported,
+
+    /// Peer doesn't expose its stats, see [`Builder::expose_stats()`]
+    ///
+    /// [`Builder::expose_stats()`]: crate::builder::builder::Builder::expose_stats
+    Refused,
+
+    /// Peer didn't answer in time
+    TimedOut,
+
+    /// Connection was closed
+    Closed,
+}
+
+impl ConnStats {
+    pub(crate) fn collect(state: &Arc<ContextState>) -> Self {
+        ConnStats {
+            protocol_version: state.version.get(),
+            features: state.version.features(),
+            kind_width: state.version.kind_width(),
+            idle: state.activity.idle(),
+            open_kinds: state.refs.open(),
+            transport: state.conn.stats(),
+            compression: state.compression.stats(),
+        }
+    }
+}
+
+/// Stats queries of both sides
+///
+/// Queries are carried by [`VERSION_KIND`] after the handshake.
+/// Stats are sent only if they are exposed by [`Builder::expose_stats()`],
+/// otherwise the query is answered with [`StatsRefused`]
+///
+/// [`VERSION_KIND`]: crate::builder::version::VERSION_KIND
+/// [`StatsRefused`]: crate::protocol::ControlMessage::StatsRefused
+/// [`Builder::expose_stats()`]: crate::builder::builder::Builder::expose_stats
+pub(crate) struct Stats {
+    exposed: AtomicBool,
+    closed: AtomicBool,
+    next_id: AtomicU32,
+    // Queries waiting for the peer, Some if it has sent its stats
+    pending: Mutex<HashMap<u32, oneshot::Sender<Option<ConnStats>>>>,
+}
+
+impl Stats {
+    pub(crate) fn new() -> Self {
+        Stats {
+            exposed: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            next_id: AtomicU32::new(0),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn expose(&self, exposed: bool) {
+        self.exposed.store(exposed, Ordering::SeqCst);
+    }
+
+    /// Asks the peer for its stats
+    pub(crate) async fn query(state: &Arc<ContextState>, timeout: Duration) -> Result<ConnStats, StatsError> {
+        if !state.version.features().remote_stats {
+            return Err(StatsError::Unsupported);
+        }
+
+        let id = state.stats.next_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = oneshot::channel();
+        state.stats.pending.lock().unwrap().insert(id, sender);
+        if state.stats.closed.load(Ordering::SeqCst) {
+            state.stats.pending.lock().unwrap().remove(&id);
+            return Err(StatsError::Closed);
+        }
+
+        if KindRefs::send(state, ControlMessage::StatsRequest { id }).await.is_err() {
+            state.stats.pending.lock().unwrap().remove(&id);
+            return Err(StatsError::Closed);
+        }
+
+        let result = match runtime::timeout(timeout, receiver).await {
+            Ok(Ok(Some(stats))) => Ok(stats),
+            Ok(Ok(None)) => Err(StatsError::Refused),
+            Ok(Err(_)) => Err(StatsError::Closed),
+            Err(_) => Err(StatsError::TimedOut),
+        };
+        state.stats.pending.lock().unwrap().remove(&id);
+        result
+    }
+
+    /// Handles stats messages of the peer
+    pub(crate) async fn handle(state: &Arc<ContextState>, message: &ControlMessage) {
+        match *message {
+            ControlMessage::StatsRequest { id } => {
+                let message = if state.stats.exposed.load(Ordering::SeqCst) {
+                    ControlMessage::StatsResponse { id, stats: ConnStats::collect(state) }
+                } else {
+                    ControlMessage::StatsRefused { id }
+                };
+                let _ = KindRefs::send(state, message).await;
+            }
+            // Stats of unknown versions or widths aren't decoded,
+            // so the query times out
+            ControlMessage::StatsResponse { id, stats } => state.stats.answer(id, Some(stats)),
+            ControlMessage::StatsRefused { id } => state.stats.answer(id, None),
+            _ => {}
+        }
+    }
+
+    /// Fails queries once the connection is closed
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.pending.lock().unwrap().clear();
+    }
+
+    fn answer(&self, id: u32, stats: Option<ConnStats>) {
+        if let Some(sender) = self.pending.lock().unwrap().remove(&id) {
+            let _ = sender.send(stats);
+        }
+    }
+}
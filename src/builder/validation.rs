@@ -0,0 +1,75 @@
+use crate::builder::version::Features;
+use crate::mem::KindWidth;
+
+/// Configuration problem found by [`Builder::validate()`] or [`Builder::dry_run()`]
+///
+/// [`Builder::validate()`]: crate::builder::builder::Builder::validate
+/// [`Builder::dry_run()`]: crate::builder::builder::Builder::dry_run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigProblem {
+    /// Transport isn't set, see [`Builder::set_conn()`]
+    ///
+    /// [`Builder::set_conn()`]: crate::builder::builder::Builder::set_conn
+    ConnNotSet,
+
+    /// Handshake timeout is zero, so the handshake always times out
+    ZeroHandshakeTimeout,
+
+    /// Kinds wider than one byte are offered, but the protocol version
+    /// doesn't support them, see [`Features::wide_kinds`]
+    ///
+    /// [`Features::wide_kinds`]: crate::builder::version::Features::wide_kinds
+    WideKindsUnsupported { version: u8 },
+
+    /// Key rotation is set, but packages aren't encrypted
+    KeyRotationWithoutEncryption,
+
+    /// Peer verification is set, but no encryption provider can prove
+    /// the peer identity, so every peer is rejected
+    VerificationWithoutEncryption,
+
+    /// Provider override is set for a kind which doesn't carry
+    /// application packages, so it is never used
+    NotApplicationKind(u8),
+
+    /// Packages are encrypted by one side only
+    EncryptionMismatch,
+
+    /// Packages are compressed by one side only
+    CompressionMismatch,
+
+    /// Provider overrides of the kind are set by one side only
+    KindOverrideMismatch(u8),
+
+    /// Providers depend on each other in a loop, see [`PingProvider::depends_on()`]
+    ///
+    /// [`PingProvider::depends_on()`]: crate::builder::builder::PingProvider::depends_on
+    ProviderCycle,
+}
+
+/// Settings agreed by both sides, returned by [`Builder::dry_run()`]
+///
+/// [`Builder::dry_run()`]: crate::builder::builder::Builder::dry_run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Agreed protocol version
+    pub version: u8,
+
+    /// Features of the agreed version
+    pub features: Features,
+
+    /// Agreed kind width
+    pub kind_width: KindWidth,
+
+    /// Packages are encrypted
+    pub encryption: bool,
+
+    /// Packages are compressed
+    pub compression: bool,
+
+    /// Kinds with their own encryption provider
+    pub kind_encryption: Vec<u8>,
+
+    /// Kinds with their own compression provider
+    pub kind_compression: Vec<u8>,
+}
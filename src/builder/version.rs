@@ -0,0 +1,381 @@
+use std::collections::BTreeMap;
+use std::collections::hash_map::RandomState;
+use std::convert::TryInto;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+use crate::builder::builder::BuildError;
+use crate::builder::context::{ContextMode, ContextState, PROVIDER_KINDS, ProviderSlot};
+use crate::builder::kind_conn::KindConn;
+use crate::builder::kind_conn::close_code::HANDSHAKE_TIMEOUT;
+use crate::mem::KindWidth;
+use crate::protocol::{ControlMessage, Encoding};
+use crate::runtime;
+
+/// The newest protocol version supported by the library
+pub const PROTOCOL_VERSION: u8 = 10;
+
+/// The oldest protocol version supported by the library
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
+/// Kind of the version frame sent at the start of the handshake
+/// and of kind release notices, channel directory and stats messages sent after it
+///
+/// The last kind of the compression provider block,
+/// so compression providers may allocate only `PROVIDER_KINDS - 1` kinds
+pub const VERSION_KIND: u8 = ProviderSlot::Compression.first_kind() + PROVIDER_KINDS - 1;
+
+/// How long the handshake waits for the peer's version frame by default,
+/// see [`Builder::version_timeout()`]
+///
+/// [`Builder::version_timeout()`]: crate::builder::builder::Builder::version_timeout
+pub const DEFAULT_VERSION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Features available with a protocol version
+///
+/// Every version keeps the frame layout `[len: 2 bytes][kind: 1 byte][body]`
+/// and understands control frames of version 1. New versions add fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Features {
+    /// Version frame `[version: 1 byte]` of [`VERSION_KIND`] is sent by both sides
+    /// at the start of the handshake. Providers take kinds of their blocks
+    /// (see [`PROVIDER_KINDS`]), without it kinds are issued one by one from `1`
+    /// in the order of provider initialization, the application kind goes last
+    ///
+    /// [`VERSION_KIND`]: crate::builder::version::VERSION_KIND
+    /// [`PROVIDER_KINDS`]: crate::builder::context::PROVIDER_KINDS
+    pub version_frame: bool,
+
+    /// Error frames of [`ERROR_KIND`] are understood, see [`KindConn::send_error()`]
+    ///
+    /// [`ERROR_KIND`]: crate::builder::peer_error::ERROR_KIND
+    /// [`KindConn::send_error()`]: crate::builder::kind_conn::KindConn::send_error
+    pub error_frames: bool,
+
+    /// Peer is notified when the last handle of a kind is dropped,
+    /// see [`KindConn::is_released()`]
+    ///
+    /// [`KindConn::is_released()`]: crate::builder::kind_conn::KindConn::is_released
+    pub kind_release: bool,
+
+    /// Version frame is followed by the widest kind width supported
+    /// by the sender, see [`Builder::kind_width()`]
+    ///
+    /// [`Builder::kind_width()`]: crate::builder::builder::Builder::kind_width
+    pub wide_kinds: bool,
+
+    /// Channels are opened by name, see [`KindConn::open_channel()`].
+    /// Version frame is followed by a random tie-breaker choosing
+    /// the side assigning kinds of channels
+    ///
+    /// [`KindConn::open_channel()`]: crate::builder::kind_conn::KindConn::open_channel
+    pub named_channels: bool,
+
+    /// Stats of the connection may be queried by the peer,
+    /// see [`KindConn::peer_stats()`]
+    ///
+    /// [`KindConn::peer_stats()`]: crate::builder::kind_conn::KindConn::peer_stats
+    pub remote_stats: bool,
+
+    /// Messages sent on [`VERSION_KIND`] after the handshake are CBOR maps
+    /// with unknown fields skipped, see [`Encoding`]
+    ///
+    /// [`VERSION_KIND`]: crate::builder::version::VERSION_KIND
+    /// [`Encoding`]: crate::protocol::Encoding
+    pub structured_control: bool,
+
+    /// Version frame is followed by [`HandshakeExtensions`] of the sender
+    ///
+    /// [`HandshakeExtensions`]: crate::protocol::ControlMessage::HandshakeExtensions
+    pub handshake_extensions: bool,
+
+    /// Kinds may be closed by one side, see [`KindConn::close_kind()`]
+    ///
+    /// [`KindConn::close_kind()`]: crate::builder::kind_conn::KindConn::close_kind
+    pub kind_close: bool,
+
+    /// [`Close`] and [`ShutdownWrite`] control frames carry CBOR
+    /// [`ControlMessage`]s, see [`ControlFrame::encode_as()`]
+    ///
+    /// [`Close`]: crate::transport::control::ControlFrame::Close
+    /// [`ShutdownWrite`]: crate::transport::control::ControlFrame::ShutdownWrite
+    /// [`ControlMessage`]: crate::protocol::ControlMessage
+    /// [`ControlFrame::encode_as()`]: crate::transport::control::ControlFrame::encode_as
+    pub structured_close: bool,
+}
+
+// Compatibility table, features of version `n` are at `n - 1`
+const FEATURES: [Features; PROTOCOL_VERSION as usize] = [
+    Features { version_frame: false, error_frames: false, kind_release: false, wide_kinds: false, named_channels: false, remote_stats: false, structured_control: false, handshake_extensions: false, kind_close: false, structured_close: false },
+    Features { version_frame: true, error_frames: true, kind_release: false, wide_kinds: false, named_channels: false, remote_stats: false, structured_control: false, handshake_extensions: false, kind_close: false, structured_close: false },
+    Features { version_frame: true, error_frames: true, kind_release: true, wide_kinds: false, named_channels: false, remote_stats: false, structured_control: false, handshake_extensions: false, kind_close: false, structured_close: false },
+    Features { version_frame: true, error_frames: true, kind_release: true, wide_kinds: true, named_channels: false, remote_stats: false, structured_control: false, handshake_extensions: false, kind_close: false, structured_close: false },
+    Features { version_frame: true, error_frames: true, kind_release: true, wide_kinds: true, named_channels: true, remote_stats: false, structured_control: false, handshake_extensions: false, kind_close: false, structured_close: false },
+    Features { version_frame: true, error_frames: true, kind_release: true, wide_kinds: true, named_channels: true, remote_stats: true, structured_control: false, handshake_extensions: false, kind_close: false, structured_close: false },
+    Features { version_frame: true, error_frames: true, kind_release: true, wide_kinds: true, named_channels: true, remote_stats: true, structured_control: true, handshake_extensions: false, kind_close: false, structured_close: false },
+    Features { version_frame: true, error_frames: true, kind_release: true, wide_kinds: true, named_channels: true, remote_stats: true, structured_control: true, handshake_extensions: true, kind_close: false, structured_close: false },
+    Features { version_frame: true, error_frames: true, kind_release: true, wide_kinds: true, named_channels: true, remote_stats: true, structured_control: true, handshake_extensions: true, kind_close: true, structured_close: false },
+    Features { version_frame: true, error_frames: true, kind_release: true, wide_kinds: true, named_channels: true, remote_stats: true, structured_control: true, handshake_extensions: true, kind_close: true, structured_close: true },
+];
+
+impl Features {
+    /// Returns features of the version, [`None`] if it isn't supported
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn of(version: u8) -> Option<Features> {
+        match version {
+            MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION => Some(FEATURES[version as usize - 1]),
+            _ => None,
+        }
+    }
+}
+
+/// Version frame sent by both sides at the start of the handshake
+///
+/// Layout is `[version: 1 byte][kind width: 1 byte][tie-breaker: 8 bytes]`,
+/// fields after the version are present only if [`Features`] of the sender's
+/// version have them
+///
+/// [`Features`]: crate::builder::version::Features
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionFrame {
+    /// The newest version supported by the sender
+    pub version: u8,
+
+    /// The widest kind width supported by the sender,
+    /// [`None`] if it's missing or unknown
+    ///
+    /// [`None`]: std::option::Option::None
+    pub kind_width: Option<KindWidth>,
+
+    /// Tie-breaker choosing the owner of the channel directory,
+    /// encoded only along with the kind width
+    pub tie_breaker: Option<u64>,
+}
+
+impl VersionFrame {
+    /// Encodes the frame body
+    pub fn encode(&self) -> Vec<u8> {
+        let mut frame = vec![self.version];
+        if let Some(width) = self.kind_width {
+            frame.push(width.code());
+
+            if let Some(tie_breaker) = self.tie_breaker {
+                frame.extend_from_slice(&tie_breaker.to_be_bytes());
+            }
+        }
+        frame
+    }
+
+    /// Decodes the frame body
+    ///
+    /// Bytes following known fields are ignored, so newer peers may extend the frame.
+    /// Returns [`BuildError::UnsupportedVersion`] if the body is empty
+    /// or the version is older than [`MIN_PROTOCOL_VERSION`]
+    ///
+    /// [`BuildError::UnsupportedVersion`]: crate::builder::builder::BuildError::UnsupportedVersion
+    /// [`MIN_PROTOCOL_VERSION`]: crate::builder::version::MIN_PROTOCOL_VERSION
+    pub fn decode(frame: &[u8]) -> Result<VersionFrame, BuildError> {
+        let version = *frame.first().ok_or(BuildError::UnsupportedVersion(0))?;
+        if version < MIN_PROTOCOL_VERSION {
+            return Err(BuildError::UnsupportedVersion(version));
+        }
+
+        Ok(VersionFrame {
+            version,
+            kind_width: frame.get(1).copied().and_then(KindWidth::from_code),
+            tie_breaker: frame.get(2..10).map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap())),
+        })
+    }
+}
+
+// Directory owner isn't chosen
+const NO_OWNER: u8 = 0;
+const OWNER: u8 = 1;
+const NOT_OWNER: u8 = 2;
+
+/// Protocol version and kind width agreed with the peer
+pub(crate) struct Version {
+    negotiated: AtomicU8,
+    kind_width: AtomicU8,
+    directory_owner: AtomicU8,
+}
+
+impl Version {
+    pub(crate) fn new() -> Self {
+        Version {
+            negotiated: AtomicU8::new(MIN_PROTOCOL_VERSION),
+            kind_width: AtomicU8::new(KindWidth::U8.code()),
+            directory_owner: AtomicU8::new(NO_OWNER),
+        }
+    }
+
+    pub(crate) fn get(&self) -> u8 {
+        self.negotiated.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn features(&self) -> Features {
+        FEATURES[self.get() as usize - 1]
+    }
+
+    /// Returns true if kinds are issued one by one from `1` like peers of
+    /// version 1 do, so no kinds are reserved for providers and control frames
+    pub(crate) fn sequential_kinds(&self) -> bool {
+        !self.features().version_frame
+    }
+
+    pub(crate) fn kind_width(&self) -> KindWidth {
+        KindWidth::from_code(self.kind_width.load(Ordering::SeqCst)).unwrap_or(KindWidth::U8)
+    }
+
+    /// Returns true if this side assigns kinds of named channels,
+    /// [`None`] if neither side was chosen
+    ///
+    /// [`None`]: std::option::Option::None
+    pub(crate) fn directory_owner(&self) -> Option<bool> {
+        match self.directory_owner.load(Ordering::SeqCst) {
+            OWNER => Some(true),
+            NOT_OWNER => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Returns version and kind width agreed with the peer's version frame
+    ///
+    /// Peers of version 1 send no version frame, it is read as `[1]`
+    pub(crate) fn agree(local: u8, width: KindWidth, remote: &VersionFrame) -> (u8, KindWidth) {
+        if !FEATURES[local as usize - 1].version_frame {
+            return (local, KindWidth::U8);
+        }
+
+        // Unknown widths of newer peers are narrowed down to one byte
+        let version = local.min(remote.version);
+        match FEATURES[version as usize - 1].wide_kinds {
+            true => (version, width.min(remote.kind_width.unwrap_or(KindWidth::U8))),
+            false => (version, KindWidth::U8),
+        }
+    }
+
+    /// Agrees on the newest version supported by both sides
+    ///
+    /// Both sides send their newest version and wait for the peer's one, sides
+    /// pinned to version 1 answer with it. Peers built before versioning never
+    /// answer, so version 1 is agreed if nothing arrives in `timeout`. The frame
+    /// may be late rather than missing, then the sides would be on different
+    /// versions: the connection is closed with [`HANDSHAKE_TIMEOUT`] code if
+    /// a newer version arrives within another `timeout`.
+    /// Since version 4 the narrower of offered kind widths is agreed as well,
+    /// since version 5 the side sending the greater tie-breaker owns the channel directory
+    ///
+    /// [`HANDSHAKE_TIMEOUT`]: crate::builder::kind_conn::close_code::HANDSHAKE_TIMEOUT
+    pub(crate) async fn negotiate(state: Arc<ContextState>, local: u8, width: KindWidth, timeout: Duration) -> Result<(), BuildError> {
+        let result = Version::exchange(&state, local, width, timeout).await;
+
+        // Providers waiting for extensions of the peer get none if it didn't send them
+        state.handshake.set_peer(result.as_ref().cloned().unwrap_or_default());
+        result.map(|_| ())
+    }
+
+    // Newer peers send the version frame to peers of version 1 as well. It's
+    // drained and answered with version 1, so they don't wait for the timeout.
+    // Peers of version 1 send nothing, the handshake doesn't wait for them
+    fn answer(state: Arc<ContextState>, timeout: Duration) {
+        let conn = KindConn::new(VERSION_KIND, ContextMode::Raw, state);
+        runtime::spawn(async move {
+            if let Ok(Some(_)) = runtime::timeout(timeout, conn.read()).await {
+                let frame = VersionFrame { version: MIN_PROTOCOL_VERSION, kind_width: None, tie_breaker: None };
+                let _ = conn.write(frame.encode()).await;
+            }
+        });
+    }
+
+    // The peer which sent its version frame too late agreed on a newer version
+    // with ours, so the connection can't be used
+    fn watch_late(conn: KindConn, timeout: Duration) {
+        runtime::spawn(async move {
+            if let Ok(Some(frame)) = runtime::timeout(timeout, conn.read()).await {
+                if frame.first().is_some_and(|&version| version > MIN_PROTOCOL_VERSION) {
+                    conn.close(HANDSHAKE_TIMEOUT).await;
+                }
+            }
+        });
+    }
+
+    // Returns handshake extensions of the peer
+    async fn exchange(state: &Arc<ContextState>,
+                      local: u8,
+                      width: KindWidth,
+                      timeout: Duration) -> Result<BTreeMap<String, Vec<u8>>, BuildError> {
+        if !FEATURES[local as usize - 1].version_frame {
+            state.version.negotiated.store(local, Ordering::SeqCst);
+            Version::answer(state.clone(), timeout);
+            return Ok(BTreeMap::new());
+        }
+
+        let conn = KindConn::new(VERSION_KIND, ContextMode::Raw, state.clone());
+
+        // Write isn't cancelled if the handshake fails. Closed connection
+        // is reported by the returned connection. Extensions follow the
+        // version frame once the version is agreed
+        let writer = KindConn::new(VERSION_KIND, ContextMode::Raw, state.clone());
+        let features = FEATURES[local as usize - 1];
+        let tie_breaker = RandomState::new().build_hasher().finish();
+        let frame = VersionFrame {
+            version: local,
+            kind_width: features.wide_kinds.then_some(width),
+            tie_breaker: features.named_channels.then_some(tie_breaker),
+        };
+        let (extensions, extensions_rx) = oneshot::channel();
+        runtime::spawn(async move {
+            if writer.write(frame.encode()).await.is_ok() {
+                if let Ok(extensions) = extensions_rx.await {
+                    let _ = writer.write(extensions).await;
+                }
+            }
+        });
+
+        // Closed connection is reported by the returned connection
+        let frame = match runtime::timeout(timeout, conn.read()).await {
+            Ok(Some(frame)) => frame,
+            Ok(None) => vec![MIN_PROTOCOL_VERSION],
+            Err(_) => {
+                Version::watch_late(conn, timeout);
+                return Ok(BTreeMap::new());
+            }
+        };
+        let remote = VersionFrame::decode(&frame)?;
+
+        let (version, width) = Version::agree(local, width, &remote);
+        let features = FEATURES[version as usize - 1];
+        state.version.negotiated.store(version, Ordering::SeqCst);
+        state.version.kind_width.store(width.code(), Ordering::SeqCst);
+        if features.structured_close {
+            state.conn.set_control_encoding(Encoding::Cbor);
+        }
+        if features.named_channels {
+            let owner = match remote.tie_breaker.map(|remote| tie_breaker.cmp(&remote)) {
+                Some(std::cmp::Ordering::Greater) => OWNER,
+                Some(std::cmp::Ordering::Less) => NOT_OWNER,
+                _ => NO_OWNER,
+            };
+            state.version.directory_owner.store(owner, Ordering::SeqCst);
+        }
+        if !features.handshake_extensions {
+            return Ok(BTreeMap::new());
+        }
+
+        // The peer sends its extensions once it has read the version frame
+        let encoding = Encoding::of(features);
+        let message = ControlMessage::HandshakeExtensions { extensions: state.handshake.local().clone() };
+        let _ = extensions.send(message.encode(encoding));
+        match conn.read().await.and_then(|message| ControlMessage::decode(&message, encoding)) {
+            Some(ControlMessage::HandshakeExtensions { extensions }) => Ok(extensions),
+            _ => Ok(BTreeMap::new()),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use crate::builder::context::{ContextMode, ContextState};
+use crate::builder::kind_conn::close_code::ENCRYPTION_ERROR;
+use crate::builder::kind_conn::KindConn;
+use crate::mem::{KindWidth, WideFrame, WIDE_KIND_U16, WIDE_KIND_U32};
+use crate::runtime;
+use crate::sync::{Kind, KindPool, WriteError};
+
+/// Wide frames read from the connection, sorted by their kinds
+#[derive(Default)]
+pub(crate) struct WideKinds {
+    pool: KindPool<u32, WideFrame>,
+    // Frames of every kind are delivered by its own task,
+    // so a kind nobody reads doesn't block others
+    deliveries: Mutex<HashMap<u32, UnboundedSender<WideFrame>>>,
+}
+
+impl WideKinds {
+    pub(crate) fn new() -> Self {
+        WideKinds::default()
+    }
+
+    /// Sorts wide frames by their kinds until the connection is closed
+    pub(crate) async fn serve(state: Arc<ContextState>) {
+        tokio::join!(
+            WideKinds::serve_kind(&state, WIDE_KIND_U16),
+            WideKinds::serve_kind(&state, WIDE_KIND_U32),
+        );
+
+        state.wide.deliveries.lock().unwrap().clear();
+        state.wide.pool.close().await;
+    }
+
+    async fn serve_kind(state: &Arc<ContextState>, escape: u8) {
+        while let Some(frame) = state.conn.read(escape).await {
+            // Malformed frames are skipped
+            if let Some(frame) = WideFrame::from_frame(frame) {
+                state.wide.deliver(frame);
+            }
+        }
+    }
+
+    fn deliver(&self, frame: WideFrame) {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let delivery = deliveries.entry(frame.kind()).or_insert_with(|| {
+            let (sender, mut receiver) = unbounded_channel();
+            let pool = self.pool.clone();
+
+            runtime::spawn(async move {
+                while let Some(frame) = receiver.recv().await {
+                    if pool.write(frame).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            sender
+        });
+
+        let _ = delivery.send(frame);
+    }
+}
+
+/// Connection of a kind wider than one byte
+///
+/// Returned by [`KindConn::wide_kind()`], uses providers of the connection
+/// like [`KindConn`]. Packages are carried by [`WideFrame`]s
+///
+/// [`KindConn::wide_kind()`]: crate::builder::kind_conn::KindConn::wide_kind
+/// [`KindConn`]: crate::builder::kind_conn::KindConn
+/// [`WideFrame`]: crate::mem::WideFrame
+pub struct WideKindConn {
+    kind: u32,
+    conn: KindConn,
+}
+
+impl WideKindConn {
+    pub(crate) fn new(kind: u32, state: Arc<ContextState>) -> Self {
+        let escape = match KindWidth::of(kind) {
+            KindWidth::U32 => WIDE_KIND_U32,
+            _ => WIDE_KIND_U16,
+        };
+
+        WideKindConn {
+            kind,
+            conn: KindConn::new(escape, ContextMode::Handle, state),
+        }
+    }
+
+    pub fn kind(&self) -> u32 {
+        self.kind
+    }
+
+    pub async fn read(&self) -> Option<Vec<u8>> {
+        let package = self.conn.state()
+            .wide
+            .pool
+            .read(self.kind)
+            .await?
+            .accept()
+            .get_body()
+            .to_vec();
+        self.conn.touch();
+
+        match self.conn.decode(package) {
+            Ok(package) => Some(package),
+            Err(_) => {
+                self.conn.close(ENCRYPTION_ERROR).await;
+                None
+            }
+        }
+    }
+
+    pub async fn write(&self, package: Vec<u8>) -> Result<(), WriteError<Vec<u8>>> {
+        let package = self.conn.encode(package);
+        let frame = WideFrame::create(self.kind, &package);
+
+        self.conn
+            .write_frame(frame.into_frame(), package.len())
+            .await
+            .map_err(|err| err.map(|_| package))
+    }
+
+    /// Returns connection of the one-byte kind carrying the frames
+    ///
+    /// Closing it or reading its state affects the whole connection
+    pub fn inner(&self) -> &KindConn {
+        &self.conn
+    }
+}
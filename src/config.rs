@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::builder::builder::Builder;
+use crate::mem::GrowthPolicy;
+use crate::providers::default_ping_provider::DefaultPingProvider;
+use crate::transport::tcp::ConnConfig;
+
+/// Connection settings which can be loaded from a file
+///
+/// Every field is optional, missing ones keep library defaults.
+/// Durations are set in milliseconds
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::config::CobraConfig;
+///
+/// let config: CobraConfig = toml::from_str(r#"
+///     handshake_timeout_ms = 5000
+///     close_timeout_ms = 500
+///
+///     [buffer]
+///     initial = 1024
+///     max = 65536
+///
+///     [ping]
+///     long_ms = 30000
+///     short_ms = 10000
+/// "#).unwrap();
+///
+/// let conn_config = config.conn_config();
+/// ```
+///
+/// # Note
+///
+/// Compression providers don't have tunable settings yet,
+/// so compression is configured through [`Builder::set_compression()`]
+///
+/// [`Builder::set_compression()`]: crate::builder::builder::Builder::set_compression
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CobraConfig {
+    pub handshake_timeout_ms: Option<u64>,
+    pub close_timeout_ms: Option<u64>,
+    pub linger_ms: Option<u64>,
+    pub write_coalescing_us: Option<u64>,
+    pub buffer: Option<BufferConfig>,
+    pub ping: Option<PingConfig>,
+}
+
+/// Settings which can be changed on a live connection
+///
+/// Missing fields keep their current values,
+/// see [`KindConn::reconfigure()`]
+///
+/// # Note
+///
+/// Compression providers don't have tunable settings yet
+///
+/// [`KindConn::reconfigure()`]: crate::builder::kind_conn::KindConn::reconfigure
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PartialConfig {
+    pub close_timeout_ms: Option<u64>,
+    pub linger_ms: Option<u64>,
+    pub write_coalescing_us: Option<u64>,
+    pub ping: Option<PingConfig>,
+}
+
+/// Receive buffer settings, see [`GrowthPolicy`]
+///
+/// [`GrowthPolicy`]: crate::mem::GrowthPolicy
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BufferConfig {
+    pub initial: usize,
+    pub max: usize,
+    pub factor: Option<usize>,
+    pub shrink_after: Option<usize>,
+
+    /// Limits the size of received frames of one kind waiting for the application
+    pub recv_high_water_mark: Option<usize>,
+}
+
+/// Settings of the [`DefaultPingProvider`]
+///
+/// [`DefaultPingProvider`]: crate::providers::default_ping_provider::DefaultPingProvider
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PingConfig {
+    pub long_ms: u64,
+    pub short_ms: u64,
+}
+
+impl CobraConfig {
+    /// Returns transport settings
+    ///
+    /// # Note
+    ///
+    /// Invalid buffer bounds are corrected: `initial` is at least one byte
+    /// and `max` is at least `initial`. Growth `factor` below 2 is ignored
+    pub fn conn_config(&self) -> ConnConfig {
+        let mut config = ConnConfig::new();
+
+        if let Some(timeout) = self.close_timeout_ms {
+            config = config.set_close_timeout(Duration::from_millis(timeout));
+        }
+        if let Some(linger) = self.linger_ms {
+            config = config.set_linger(Duration::from_millis(linger));
+        }
+        if let Some(delay) = self.write_coalescing_us {
+            config = config.set_write_coalescing(Duration::from_micros(delay));
+        }
+        if let Some(buffer) = &self.buffer {
+            config = config.set_buffer_policy(buffer.policy());
+
+            if let Some(mark) = buffer.recv_high_water_mark {
+                config = config.set_recv_high_water_mark(mark);
+            }
+        }
+
+        config
+    }
+
+    /// Returns ping provider if it's configured
+    pub fn ping(&self) -> Option<DefaultPingProvider> {
+        self.ping.as_ref().map(|ping| {
+            let (long_duration, short_duration) = ping.durations();
+            DefaultPingProvider::new(long_duration, short_duration)
+        })
+    }
+
+    /// Returns handshake timeout if it's configured
+    pub fn handshake_timeout(&self) -> Option<Duration> {
+        self.handshake_timeout_ms.map(Duration::from_millis)
+    }
+}
+
+impl PingConfig {
+    pub(crate) fn durations(&self) -> (Duration, Duration) {
+        (Duration::from_millis(self.long_ms), Duration::from_millis(self.short_ms))
+    }
+}
+
+impl BufferConfig {
+    fn policy(&self) -> GrowthPolicy {
+        let initial = self.initial.max(1);
+        let mut policy = GrowthPolicy::new(initial, self.max.max(initial));
+
+        if let Some(factor) = self.factor.filter(|factor| *factor > 1) {
+            policy = policy.set_factor(factor);
+        }
+        if let Some(reads) = self.shrink_after {
+            policy = policy.set_shrink_after(reads);
+        }
+
+        policy
+    }
+}
+
+impl Builder {
+    /// Creates builder with provider settings from the config
+    ///
+    /// Transport settings are applied separately, see [`CobraConfig::conn_config()`]
+    ///
+    /// [`CobraConfig::conn_config()`]: crate::config::CobraConfig::conn_config
+    pub fn from_config(config: &CobraConfig) -> Self {
+        let mut builder = Builder::new();
+
+        if let Some(ping) = config.ping() {
+            builder = builder.set_ping(ping);
+        }
+        if let Some(timeout) = config.handshake_timeout() {
+            builder = builder.handshake_timeout(timeout);
+        }
+
+        builder
+    }
+}
@@ -0,0 +1,256 @@
+use crate::builder::builder::ConnProvider;
+use crate::builder::context::LAST_APPLICATION_KIND;
+use crate::builder::kind_conn::close_code::CloseCode;
+use crate::builder::version::{VersionFrame, VERSION_KIND};
+use crate::mem::{ConcatBuf, Frame, KindWidth, WideFrame, HEADER_BYTES, WIDE_KIND_U16, WIDE_KIND_U32};
+use crate::protocol::Encoding;
+use crate::sync::Kind;
+use crate::transport::control::ControlFrame;
+
+/// Canonical wire representation of a single frame
+///
+/// Alternative implementations must produce exactly [`bytes`] when encoding
+/// [`kind`] and [`body`] and must restore them back when decoding
+///
+/// [`bytes`]: crate::conformance::Vector::bytes
+/// [`kind`]: crate::conformance::Vector::kind
+/// [`body`]: crate::conformance::Vector::body
+#[derive(Debug, Clone)]
+pub struct Vector {
+    pub name: &'static str,
+    pub kind: u8,
+    pub body: &'static [u8],
+    pub bytes: &'static [u8],
+}
+
+/// Canonical wire representation of a transport control frame
+///
+/// Control frames are handled by the transport itself,
+/// so they are validated only locally
+#[derive(Debug, Clone)]
+pub struct ControlVector {
+    pub name: &'static str,
+    pub frame: ControlFrame,
+    /// Close and shutdown frames are CBOR messages since version 10,
+    /// see [`ControlFrame::encode_as()`]
+    ///
+    /// [`ControlFrame::encode_as()`]: crate::transport::control::ControlFrame::encode_as
+    pub encoding: Encoding,
+    pub bytes: &'static [u8],
+}
+
+/// Canonical wire representation of a version frame
+///
+/// Version frames are carried by [`VERSION_KIND`] at the start
+/// of the handshake, so they are validated only locally
+///
+/// [`VERSION_KIND`]: crate::builder::version::VERSION_KIND
+#[derive(Debug, Clone)]
+pub struct VersionVector {
+    pub name: &'static str,
+    pub frame: VersionFrame,
+    pub bytes: &'static [u8],
+}
+
+/// Error returned when implementation doesn't match a vector
+#[derive(Debug)]
+pub enum ConformanceError {
+    /// Encoded bytes differ from canonical ones
+    Encode(&'static str),
+
+    /// Canonical bytes were decoded incorrectly
+    Decode(&'static str),
+
+    /// Remote endpoint closed the connection or returned another frame
+    Remote(&'static str),
+}
+
+/// Returns all canonical vectors
+///
+/// # Note
+///
+/// Ping frames are ordinary empty frames on the kind allocated
+/// by the ping provider (the first one, `1`). Handshake
+/// starts with a version frame, see [`version_vectors()`]. Kinds
+/// [`WIDE_KIND_U16`] and [`WIDE_KIND_U32`] escape wide kinds,
+/// their body starts with the 2- or 4-byte kind, see [`WideFrame`]
+///
+/// [`WIDE_KIND_U16`]: crate::mem::WIDE_KIND_U16
+/// [`WIDE_KIND_U32`]: crate::mem::WIDE_KIND_U32
+/// [`WideFrame`]: crate::mem::WideFrame
+/// [`version_vectors()`]: crate::conformance::version_vectors
+pub fn vectors() -> Vec<Vector> {
+    vec![
+        Vector { name: "empty frame", kind: 1, body: &[], bytes: &[0, 1, 1] },
+        Vector { name: "simple frame", kind: 1, body: &[1, 2, 3], bytes: &[0, 4, 1, 1, 2, 3] },
+        Vector { name: "max kind", kind: LAST_APPLICATION_KIND, body: &[0], bytes: &[0, 2, 253, 0] },
+        Vector { name: "u16 wide kind", kind: WIDE_KIND_U16, body: &[1, 0, 7], bytes: &[0, 4, 254, 1, 0, 7] },
+        Vector { name: "u32 wide kind", kind: WIDE_KIND_U32, body: &[0, 1, 0, 0, 7], bytes: &[0, 6, 255, 0, 1, 0, 0, 7] },
+        Vector { name: "binary body", kind: 2, body: &[0, 255, 0], bytes: &[0, 4, 2, 0, 255, 0] },
+        Vector { name: "ping frame", kind: 1, body: &[], bytes: &[0, 1, 1] },
+    ]
+}
+
+/// Returns all canonical control frame vectors
+///
+/// Close and shutdown frames are listed in both layouts: fixed
+/// one used before version 10 and CBOR messages used since it
+pub fn control_vectors() -> Vec<ControlVector> {
+    vec![
+        ControlVector {
+            name: "close frame (pre-v10)",
+            frame: ControlFrame::Close { code: CloseCode::ClosedByUser, reason: "bye".to_string() },
+            encoding: Encoding::Fixed,
+            bytes: &[0, 6, 0, 1, 1, b'b', b'y', b'e'],
+        },
+        ControlVector {
+            name: "close frame without reason (pre-v10)",
+            frame: ControlFrame::Close { code: CloseCode::PingTimeout, reason: String::new() },
+            encoding: Encoding::Fixed,
+            bytes: &[0, 3, 0, 1, 5],
+        },
+        ControlVector {
+            name: "close frame (v10)",
+            frame: ControlFrame::Close { code: CloseCode::ClosedByUser, reason: "bye".to_string() },
+            encoding: Encoding::Cbor,
+            bytes: &[0, 11, 0, 0xa3, 0, 10, 6, 1, 7, 0x63, b'b', b'y', b'e'],
+        },
+        ControlVector {
+            name: "close frame without reason (v10)",
+            frame: ControlFrame::Close { code: CloseCode::PingTimeout, reason: String::new() },
+            encoding: Encoding::Cbor,
+            bytes: &[0, 8, 0, 0xa3, 0, 10, 6, 5, 7, 0x60],
+        },
+        ControlVector {
+            name: "close ack",
+            frame: ControlFrame::CloseAck,
+            encoding: Encoding::Fixed,
+            bytes: &[0, 2, 0, 2],
+        },
+        ControlVector {
+            name: "shutdown write (pre-v10)",
+            frame: ControlFrame::ShutdownWrite,
+            encoding: Encoding::Fixed,
+            bytes: &[0, 2, 0, 3],
+        },
+        ControlVector {
+            name: "shutdown write (v10)",
+            frame: ControlFrame::ShutdownWrite,
+            encoding: Encoding::Cbor,
+            bytes: &[0, 4, 0, 0xa1, 0, 11],
+        },
+        ControlVector {
+            name: "probe",
+            frame: ControlFrame::Probe { id: 0x01020304 },
+            encoding: Encoding::Fixed,
+            bytes: &[0, 6, 0, 4, 1, 2, 3, 4],
+        },
+        ControlVector {
+            name: "probe ack",
+            frame: ControlFrame::ProbeAck { id: 0x01020304 },
+            encoding: Encoding::Fixed,
+            bytes: &[0, 6, 0, 5, 1, 2, 3, 4],
+        },
+    ]
+}
+
+/// Returns all canonical version frame vectors
+pub fn version_vectors() -> Vec<VersionVector> {
+    vec![
+        VersionVector {
+            name: "version frame",
+            frame: VersionFrame { version: 9, kind_width: Some(KindWidth::U32), tie_breaker: Some(0x0102030405060708) },
+            bytes: &[0, 11, 12, 9, 4, 1, 2, 3, 4, 5, 6, 7, 8],
+        },
+        VersionVector {
+            name: "version frame without kind width",
+            frame: VersionFrame { version: 2, kind_width: None, tie_breaker: None },
+            bytes: &[0, 2, 12, 2],
+        },
+    ]
+}
+
+/// Checks that local implementation matches the vector
+pub fn validate(vector: &Vector) -> Result<(), ConformanceError> {
+    let frame = Frame::create(vector.kind, vector.body);
+    if frame[..] != *vector.bytes {
+        return Err(ConformanceError::Encode(vector.name));
+    }
+
+    let mut buf: ConcatBuf<Frame> = ConcatBuf::default();
+    buf.extend_from_slice(vector.bytes);
+
+    let frame = match buf.try_read_chunk() {
+        Some(frame) if frame.kind() == vector.kind
+            && frame[HEADER_BYTES..] == *vector.body => frame,
+        _ => return Err(ConformanceError::Decode(vector.name)),
+    };
+
+    match vector.kind {
+        WIDE_KIND_U16 | WIDE_KIND_U32 => WideFrame::from_frame(frame)
+            .map(|_| ())
+            .ok_or(ConformanceError::Decode(vector.name)),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that local implementation matches the control vector
+pub fn validate_control(vector: &ControlVector) -> Result<(), ConformanceError> {
+    let frame = vector.frame.encode_as(vector.encoding);
+    if frame[..] != *vector.bytes {
+        return Err(ConformanceError::Encode(vector.name));
+    }
+
+    let mut buf: ConcatBuf<Frame> = ConcatBuf::default();
+    buf.extend_from_slice(vector.bytes);
+
+    match buf.try_read_chunk().as_ref().and_then(ControlFrame::decode) {
+        Some(frame) if frame == vector.frame => Ok(()),
+        _ => Err(ConformanceError::Decode(vector.name)),
+    }
+}
+
+/// Checks that local implementation matches the version vector
+pub fn validate_version(vector: &VersionVector) -> Result<(), ConformanceError> {
+    let frame = Frame::create(VERSION_KIND, &vector.frame.encode());
+    if frame[..] != *vector.bytes {
+        return Err(ConformanceError::Encode(vector.name));
+    }
+
+    let mut buf: ConcatBuf<Frame> = ConcatBuf::default();
+    buf.extend_from_slice(vector.bytes);
+
+    match buf.try_read_chunk() {
+        Some(frame) if frame.kind() == VERSION_KIND
+            && VersionFrame::decode(&frame[HEADER_BYTES..]).ok() == Some(vector.frame) => Ok(()),
+        _ => Err(ConformanceError::Decode(vector.name)),
+    }
+}
+
+/// Checks all vectors against the local implementation
+pub fn validate_all() -> Result<(), ConformanceError> {
+    vectors().iter().try_for_each(validate)?;
+    control_vectors().iter().try_for_each(validate_control)?;
+    version_vectors().iter().try_for_each(validate_version)
+}
+
+/// Runs vectors against an external endpoint
+///
+/// The endpoint must echo every received frame back with the same kind
+pub async fn run_against<T: ConnProvider>(conn: &T) -> Result<(), ConformanceError> {
+    for vector in vectors() {
+        conn.write(Frame::create(vector.kind, vector.body))
+            .await
+            .map_err(|_| ConformanceError::Remote(vector.name))?;
+
+        let frame = conn.read(vector.kind)
+            .await
+            .ok_or(ConformanceError::Remote(vector.name))?;
+
+        if frame[..] != *vector.bytes {
+            return Err(ConformanceError::Remote(vector.name));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,368 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+
+use crate::builder::builder::ConnProvider;
+use crate::builder::stats::TransportStats;
+use crate::config::PartialConfig;
+use crate::mem::{Frame, HEADER_BYTES};
+use crate::protocol::Encoding;
+use crate::sync::{Kind, WriteError};
+use crate::transport::close_cause::CloseCause;
+
+/// First bytes of every recording
+pub const RECORDING_MAGIC: &[u8; 6] = b"CBRREC";
+/// Version of the recording format
+pub const RECORDING_VERSION: u8 = 1;
+
+/// Direction of a recorded frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound = 0,
+    Outbound = 1,
+}
+
+/// Writes every frame passing through the connection to a recording
+///
+/// Attached with [`Builder::record_frames()`]. Frames handled by the
+/// transport itself (close and shutdown frames) aren't recorded
+///
+/// # Format
+///
+/// Recording starts with [`RECORDING_MAGIC`] and [`RECORDING_VERSION`],
+/// followed by records. All numbers are big-endian:
+///
+/// ```text
+/// [timestamp: u64, microseconds since UNIX epoch]
+/// [direction: u8, 0 - inbound, 1 - outbound]
+/// [kind: u8]
+/// [body length: u32]
+/// [payload length: u32, 0 if payloads aren't recorded]
+/// [payload]
+/// ```
+///
+/// [`Builder::record_frames()`]: crate::builder::builder::Builder::record_frames
+/// [`RECORDING_MAGIC`]: crate::debug::RECORDING_MAGIC
+/// [`RECORDING_VERSION`]: crate::debug::RECORDING_VERSION
+#[derive(Clone)]
+pub struct FrameRecorder {
+    output: Arc<Mutex<Box<dyn Write + Send>>>,
+    payload: bool,
+}
+
+/// Frame passed to [`FrameHook`]
+///
+/// [`FrameHook`]: crate::debug::FrameHook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameEvent<'a> {
+    pub direction: Direction,
+    pub kind: u8,
+    /// Length of the frame body
+    pub len: usize,
+    /// Frame body, [`None`] unless enabled by [`FrameHook::with_payload()`]
+    ///
+    /// [`None`]: std::option::Option::None
+    /// [`FrameHook::with_payload()`]: crate::debug::FrameHook::with_payload
+    pub payload: Option<&'a [u8]>,
+}
+
+/// Handler called with every frame passing through the connection
+///
+/// Attached with [`Builder::on_frame()`], lets observability layers sample
+/// traffic without a middleware. Bodies are passed only if asked for, so
+/// handlers can't leak payloads by accident. Frames handled by the
+/// transport itself (close and shutdown frames) aren't passed
+///
+/// Handler is called on reads and writes of the connection, so it must not block
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::builder::builder::Builder;
+/// use cobra_rs::debug::FrameHook;
+///
+/// let hook = FrameHook::new(|event| println!("{:?} {} bytes of kind {}", event.direction, event.len, event.kind));
+/// let builder = Builder::new().on_frame(hook);
+/// ```
+///
+/// [`Builder::on_frame()`]: crate::builder::builder::Builder::on_frame
+#[derive(Clone)]
+pub struct FrameHook {
+    handler: Arc<dyn Fn(&FrameEvent) + Send + Sync>,
+    payload: bool,
+}
+
+/// Frame restored from a recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    pub timestamp: SystemTime,
+    pub direction: Direction,
+    pub kind: u8,
+    pub len: usize,
+    pub payload: Option<Vec<u8>>,
+}
+
+/// Reads frames written by [`FrameRecorder`]
+///
+/// Read frames can be replayed with [`ReplayConn`]
+///
+/// [`FrameRecorder`]: crate::debug::FrameRecorder
+/// [`ReplayConn`]: crate::transport::replay::ReplayConn
+pub struct RecordingReader<R> {
+    input: R,
+}
+
+/// [`ConnProvider`] passing frames of the wrapped connection to hooks
+///
+/// [`ConnProvider`]: crate::builder::builder::ConnProvider
+pub(crate) struct ObservedConn {
+    inner: Arc<dyn ConnProvider>,
+    hooks: Vec<FrameHook>,
+}
+
+impl FrameRecorder {
+    /// Creates recording file, payloads aren't recorded by default
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        FrameRecorder::from_writer(BufWriter::new(File::create(path)?))
+    }
+
+    /// Writes recording to the specified output
+    pub fn from_writer<W: 'static + Write + Send>(mut output: W) -> io::Result<Self> {
+        output.write_all(RECORDING_MAGIC)?;
+        output.write_all(&[RECORDING_VERSION])?;
+        output.flush()?;
+
+        Ok(FrameRecorder {
+            output: Arc::new(Mutex::new(Box::new(output))),
+            payload: false,
+        })
+    }
+
+    /// Enables recording of frame bodies
+    pub fn with_payload(mut self, payload: bool) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Writes record of the frame
+    ///
+    /// Every record is flushed, so the recording stays
+    /// readable if the process crashes
+    pub fn record(&self, direction: Direction, frame: &Frame) -> io::Result<()> {
+        let body = &frame[HEADER_BYTES..];
+        self.write_record(direction, frame.kind(), body.len(), if self.payload { body } else { &[][..] })
+    }
+
+    fn write_record(&self, direction: Direction, kind: u8, len: usize, payload: &[u8]) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+
+        let mut record = Vec::with_capacity(18 + payload.len());
+        record.extend_from_slice(&timestamp.to_be_bytes());
+        record.push(direction as u8);
+        record.push(kind);
+        record.extend_from_slice(&(len as u32).to_be_bytes());
+        record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        record.extend_from_slice(payload);
+
+        let mut output = self.output.lock().unwrap();
+        output.write_all(&record)?;
+        output.flush()
+    }
+}
+
+impl FrameHook {
+    /// Creates hook passing kinds and lengths of frames, bodies aren't passed by default
+    pub fn new<F: 'static + Fn(&FrameEvent) + Send + Sync>(handler: F) -> Self {
+        FrameHook {
+            handler: Arc::new(handler),
+            payload: false,
+        }
+    }
+
+    /// Passes frame bodies to the handler
+    pub fn with_payload(mut self, payload: bool) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    pub(crate) fn call(&self, direction: Direction, frame: &Frame) {
+        let body = &frame[HEADER_BYTES..];
+        (self.handler)(&FrameEvent {
+            direction,
+            kind: frame.kind(),
+            len: body.len(),
+            payload: self.payload.then_some(body),
+        });
+    }
+}
+
+// Recording errors must not break the connection
+impl From<FrameRecorder> for FrameHook {
+    fn from(recorder: FrameRecorder) -> Self {
+        let payload = recorder.payload;
+        FrameHook::new(move |event| {
+            let _ = recorder.write_record(event.direction, event.kind, event.len, event.payload.unwrap_or_default());
+        }).with_payload(payload)
+    }
+}
+
+impl RecordedFrame {
+    /// Restores the frame if its payload was recorded
+    pub fn to_frame(&self) -> Option<Frame> {
+        self.payload.as_ref().map(|payload| Frame::create(self.kind, payload))
+    }
+}
+
+impl RecordingReader<BufReader<File>> {
+    /// Opens recording file
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        RecordingReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> RecordingReader<R> {
+    /// Checks recording header and returns reader of its records
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0; 7];
+        input.read_exact(&mut header)?;
+
+        if header[..6] != RECORDING_MAGIC[..] || header[6] != RECORDING_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a frame recording"));
+        }
+
+        Ok(RecordingReader { input })
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<RecordedFrame>> {
+        let mut header = [0; 18];
+        match self.input.read_exact(&mut header[..1]) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        self.input.read_exact(&mut header[1..])?;
+
+        let number = |range: std::ops::Range<usize>| {
+            header[range].iter().fold(0_u64, |number, byte| number << 8 | *byte as u64)
+        };
+        let direction = match header[8] {
+            0 => Direction::Inbound,
+            1 => Direction::Outbound,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid frame direction")),
+        };
+
+        let mut payload = vec![0; number(14..18) as usize];
+        self.input.read_exact(&mut payload)?;
+
+        Ok(Some(RecordedFrame {
+            timestamp: UNIX_EPOCH + Duration::from_micros(number(0..8)),
+            direction,
+            kind: header[9],
+            len: number(10..14) as usize,
+            payload: if payload.is_empty() && number(10..14) != 0 { None } else { Some(payload) },
+        }))
+    }
+}
+
+impl<R: Read> Iterator for RecordingReader<R> {
+    type Item = io::Result<RecordedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+impl ObservedConn {
+    pub(crate) fn new(inner: Arc<dyn ConnProvider>, hooks: Vec<FrameHook>) -> Self {
+        ObservedConn { inner, hooks }
+    }
+
+    fn record(&self, direction: Direction, frame: &Frame) {
+        for hook in &self.hooks {
+            hook.call(direction, frame);
+        }
+    }
+}
+
+#[async_trait]
+impl ConnProvider for ObservedConn {
+    async fn read(&self, kind: u8) -> Option<Frame> {
+        let frame = self.inner.read(kind).await?;
+        self.record(Direction::Inbound, &frame);
+        Some(frame)
+    }
+
+    async fn write(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        self.record(Direction::Outbound, &frame);
+        self.inner.write(frame).await
+    }
+
+    async fn write_urgent(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        self.record(Direction::Outbound, &frame);
+        self.inner.write_urgent(frame).await
+    }
+
+    async fn flush(&self) {
+        self.inner.flush().await
+    }
+
+    fn stats(&self) -> TransportStats {
+        self.inner.stats()
+    }
+
+    fn handshake_complete(&self) {
+        self.inner.handshake_complete()
+    }
+
+    fn set_control_encoding(&self, encoding: Encoding) {
+        self.inner.set_control_encoding(encoding)
+    }
+
+    fn reconfigure(&self, config: &PartialConfig) {
+        self.inner.reconfigure(config)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    async fn readable(&self) {
+        self.inner.readable().await
+    }
+
+    async fn close(&self, code: u8) {
+        self.inner.close(code).await
+    }
+
+    async fn close_with_reason(&self, code: u8, reason: &str) {
+        self.inner.close_with_reason(code, reason).await
+    }
+
+    async fn shutdown_write(&self) {
+        self.inner.shutdown_write().await
+    }
+
+    async fn is_close(&self) -> Option<u8> {
+        self.inner.is_close().await
+    }
+
+    async fn close_reason(&self) -> Option<String> {
+        self.inner.close_reason().await
+    }
+
+    async fn close_cause(&self) -> Option<CloseCause> {
+        self.inner.close_cause().await
+    }
+}
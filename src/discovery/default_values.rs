@@ -8,4 +8,5 @@ pub const DEFAULT_PORT: u16 = 55669;
 pub const DEFAULT_SEARCH_PACKAGE: [u8; 5] = [8, 100, 193, 210, 19];
 pub const DEFAULT_ANSWER_PACKAGE: [u8; 5] = [65, 238, 212, 64, 80];
 
+#[allow(dead_code)]
 pub const DEFAULT_POOLING_RATE: Duration = Duration::from_secs(5);
@@ -1,15 +1,25 @@
-use std::net::Ipv4Addr;
-use std::sync::Arc;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, RwLock};
 
 use tokio::sync::Notify;
 
 use crate::discovery::default_values::{DEFAULT_ADDRESS, DEFAULT_MULTICAST_ADDRESS, DEFAULT_PORT};
-use crate::discovery::default_values::{DEFAULT_ANSWER_PACKAGE, DEFAULT_SEARCH_PACKAGE};
-use crate::discovery::search_socket::SearchSocket;
+use crate::discovery::default_values::DEFAULT_SEARCH_PACKAGE;
+use crate::discovery::search_socket::{self, SearchSocket};
+use crate::net::PortMapping;
+use crate::runtime;
 
 pub struct Listener {
     close_notifier: Option<Arc<Notify>>,
     socket: Arc<SearchSocket>,
+    endpoint: Arc<RwLock<Endpoint>>,
+}
+
+// Endpoint put into answers
+enum Endpoint {
+    None,
+    Fixed(SocketAddr),
+    Mapped(Arc<RwLock<SocketAddr>>),
 }
 
 impl Listener {
@@ -19,13 +29,34 @@ impl Listener {
 
     pub async fn custom(addr: Ipv4Addr, multi_addr: Ipv4Addr, port: u16) -> std::io::Result<Self> {
         let socket = Arc::new(SearchSocket::new(addr, multi_addr, port).await?);
-        let close_notifier = Self::spawn(socket.clone());
+        let endpoint = Arc::new(RwLock::new(Endpoint::None));
+        let close_notifier = Self::spawn(socket.clone(), endpoint.clone());
         Ok(Listener {
             close_notifier: Some(close_notifier),
             socket,
+            endpoint,
         })
     }
 
+    /// Announces the endpoint peers should connect to in answers
+    ///
+    /// Searchers get it with [`Searcher::scan_answer()`], `None` stops announcing
+    ///
+    /// [`Searcher::scan_answer()`]: crate::discovery::searcher::Searcher::scan_answer
+    pub fn announce(&self, endpoint: Option<SocketAddr>) {
+        *self.endpoint.write().unwrap() = match endpoint {
+            Some(endpoint) => Endpoint::Fixed(endpoint),
+            None => Endpoint::None,
+        };
+    }
+
+    /// Announces the external address of the mapping in answers
+    ///
+    /// The announced address follows the changes after renewals of the mapping
+    pub fn announce_mapping(&self, mapping: &PortMapping) {
+        *self.endpoint.write().unwrap() = Endpoint::Mapped(mapping.shared_external_addr());
+    }
+
     pub fn is_active(&self) -> bool {
         self.close_notifier.is_some()
     }
@@ -38,17 +69,17 @@ impl Listener {
 
     pub fn resume(&mut self) {
         if self.close_notifier.is_none() {
-            self.close_notifier = Some(Self::spawn(self.socket.clone()));
+            self.close_notifier = Some(Self::spawn(self.socket.clone(), self.endpoint.clone()));
         }
     }
 
-    fn spawn(socket: Arc<SearchSocket>) -> Arc<Notify> {
+    fn spawn(socket: Arc<SearchSocket>, endpoint: Arc<RwLock<Endpoint>>) -> Arc<Notify> {
         let close_notifier = Arc::new(Notify::new());
         let out_close_notifier = close_notifier.clone();
-        tokio::spawn(async move {
+        runtime::spawn(async move {
             loop {
                 tokio::select! {
-                    _ = Self::receive_and_answer(&socket) => {}
+                    _ = Self::receive_and_answer(&socket, &endpoint) => {}
                     _ = close_notifier.notified() => { break }
                 }
             }
@@ -56,10 +87,15 @@ impl Listener {
         out_close_notifier
     }
 
-    async fn receive_and_answer(socket: &SearchSocket) {
+    async fn receive_and_answer(socket: &SearchSocket, endpoint: &RwLock<Endpoint>) {
         if let Ok((data, _)) = socket.read().await {
             if data == DEFAULT_SEARCH_PACKAGE {
-                socket.send(DEFAULT_ANSWER_PACKAGE.to_vec()).await.unwrap();
+                let endpoint = match &*endpoint.read().unwrap() {
+                    Endpoint::None => None,
+                    Endpoint::Fixed(endpoint) => Some(*endpoint),
+                    Endpoint::Mapped(external_addr) => Some(*external_addr.read().unwrap()),
+                };
+                socket.send(search_socket::answer(endpoint)).await.unwrap();
             }
         }
     }
@@ -1,11 +1,19 @@
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
 
 use tokio::net::UdpSocket;
 
+use crate::discovery::default_values::DEFAULT_ANSWER_PACKAGE;
+
+// Answer package followed by the announced IPv6 address and port
+const MAX_PACKAGE_LEN: usize = DEFAULT_ANSWER_PACKAGE.len() + 16 + 2;
+
 pub struct SearchSocket {
     socket: UdpSocket,
+    #[allow(dead_code)]
     addr: SocketAddrV4,
     multi_addr: SocketAddrV4,
+    #[allow(dead_code)]
     port: u16,
 }
 
@@ -29,9 +37,10 @@ impl SearchSocket {
     }
 
     pub async fn read(&self) -> std::io::Result<(Vec<u8>, SocketAddr)> {
-        let mut buffer = vec![0; 5];
+        let mut buffer = vec![0; MAX_PACKAGE_LEN];
         self.socket.readable().await.unwrap();
-        let (_, addr) = self.socket.try_recv_from(&mut buffer)?;
+        let (len, addr) = self.socket.try_recv_from(&mut buffer)?;
+        buffer.truncate(len);
         Ok((buffer, addr))
     }
 
@@ -47,3 +56,31 @@ impl SearchSocket {
         Ok(socket)
     }
 }
+
+/// Creates answer package announcing the endpoint
+///
+/// Endpoint follows the package as address and port in network
+/// byte order, so searchers reading only the package still match it
+pub fn answer(endpoint: Option<SocketAddr>) -> Vec<u8> {
+    let mut answer = DEFAULT_ANSWER_PACKAGE.to_vec();
+    if let Some(endpoint) = endpoint {
+        match endpoint.ip() {
+            IpAddr::V4(ip) => answer.extend_from_slice(&ip.octets()),
+            IpAddr::V6(ip) => answer.extend_from_slice(&ip.octets()),
+        }
+        answer.extend_from_slice(&endpoint.port().to_be_bytes());
+    }
+    answer
+}
+
+/// Returns endpoint announced by the answer package, `None` if it's not an answer
+pub fn parse_answer(data: &[u8]) -> Option<Option<SocketAddr>> {
+    let endpoint = data.strip_prefix(&DEFAULT_ANSWER_PACKAGE[..])?;
+    let (ip, port) = match endpoint.len() {
+        0 => return Some(None),
+        6 => (IpAddr::from(<[u8; 4]>::try_from(&endpoint[..4]).unwrap()), &endpoint[4..]),
+        18 => (IpAddr::from(<[u8; 16]>::try_from(&endpoint[..16]).unwrap()), &endpoint[16..]),
+        _ => return None,
+    };
+    Some(Some(SocketAddr::new(ip, u16::from_be_bytes([port[0], port[1]]))))
+}
@@ -1,27 +1,49 @@
 use std::net::{Ipv4Addr, SocketAddr};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 use tokio::sync::{Mutex, Notify};
-use tokio::time::sleep;
 
 use crate::discovery::default_values::{DEFAULT_ADDRESS, DEFAULT_MULTICAST_ADDRESS, DEFAULT_PORT};
-use crate::discovery::default_values::{DEFAULT_ANSWER_PACKAGE, DEFAULT_SEARCH_PACKAGE};
-use crate::discovery::search_socket::SearchSocket;
+use crate::discovery::default_values::DEFAULT_SEARCH_PACKAGE;
+use crate::discovery::search_socket::{self, SearchSocket};
+use crate::retry::Backoff;
+use crate::runtime;
 use crate::sync::Pool;
 
-struct Searcher {
-    pool: Pool<SocketAddr>,
+pub struct Searcher {
+    pool: Pool<Answer>,
     close_notifier: Arc<Notify>,
 }
 
+/// Answer of the peer found by [`Searcher`]
+///
+/// [`Searcher`]: crate::discovery::searcher::Searcher
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Answer {
+    /// Address the answer came from
+    pub addr: SocketAddr,
+
+    /// Endpoint announced by the peer, e.g. external address of its port mapping
+    pub endpoint: Option<SocketAddr>,
+}
+
 impl Searcher {
     pub async fn new(search_ratio: Duration) -> std::io::Result<Self> {
-        Self::custom(
+        Self::with_backoff(Backoff::constant(search_ratio)).await
+    }
+
+    /// Creates searcher sending search packages with delays of the backoff
+    ///
+    /// Backoff is reset once a peer answers. Searching stops when
+    /// attempts are exhausted, answers are still received
+    pub async fn with_backoff(backoff: Backoff) -> std::io::Result<Self> {
+        Self::custom_with_backoff(
             DEFAULT_ADDRESS,
             DEFAULT_MULTICAST_ADDRESS,
             DEFAULT_PORT,
-            search_ratio,
+            backoff,
         )
         .await
     }
@@ -31,9 +53,18 @@ impl Searcher {
         multi_addr: Ipv4Addr,
         port: u16,
         search_ratio: Duration,
+    ) -> std::io::Result<Self> {
+        Self::custom_with_backoff(addr, multi_addr, port, Backoff::constant(search_ratio)).await
+    }
+
+    pub async fn custom_with_backoff(
+        addr: Ipv4Addr,
+        multi_addr: Ipv4Addr,
+        port: u16,
+        backoff: Backoff,
     ) -> std::io::Result<Self> {
         let socket = Arc::new(SearchSocket::new(addr, multi_addr, port).await?);
-        let (pool, close_notifier) = Self::spawn(socket, search_ratio);
+        let (pool, close_notifier) = Self::spawn(socket, backoff);
 
         Ok(Searcher {
             pool,
@@ -42,6 +73,11 @@ impl Searcher {
     }
 
     pub async fn scan(&self) -> SocketAddr {
+        self.scan_answer().await.addr
+    }
+
+    /// Waits for the next answer along with the endpoint announced by the peer
+    pub async fn scan_answer(&self) -> Answer {
         self.pool
             .read()
             .await
@@ -49,50 +85,66 @@ impl Searcher {
             .accept()
     }
 
-    fn spawn(socket: Arc<SearchSocket>, search_ratio: Duration) -> (Pool<SocketAddr>, Arc<Notify>) {
+    fn spawn(socket: Arc<SearchSocket>, backoff: Backoff) -> (Pool<Answer>, Arc<Notify>) {
         let pool = Pool::new();
         let close_notifier = Arc::new(Notify::new());
         let mutex = Arc::new(Mutex::new(()));
+        let found = Arc::new(AtomicBool::new(false));
 
-        tokio::spawn(Self::sender_loop(
+        runtime::spawn(Self::sender_loop(
             socket.clone(),
-            search_ratio,
+            backoff,
             close_notifier.clone(),
             mutex.clone(),
+            found.clone(),
         ));
-        tokio::spawn(Self::receiver_loop(socket, pool.clone(), mutex));
+        runtime::spawn(Self::receiver_loop(socket, pool.clone(), mutex, found));
 
         (pool, close_notifier)
     }
 
     async fn sender_loop(
         socket: Arc<SearchSocket>,
-        search_ratio: Duration,
+        mut backoff: Backoff,
         close_notifier: Arc<Notify>,
         mutex: Arc<Mutex<()>>,
+        found: Arc<AtomicBool>,
     ) {
         loop {
             drop(mutex.lock().await);
             tokio::select! {
-                _ = close_notifier.notified() => {}
+                _ = close_notifier.notified() => return,
                 _ = socket.send(DEFAULT_SEARCH_PACKAGE.to_vec()) => {}
             }
-            sleep(search_ratio).await;
+
+            if found.swap(false, Ordering::SeqCst) {
+                backoff.reset();
+            }
+            let delay = match backoff.next_delay() {
+                Some(delay) => delay,
+                None => return,
+            };
+            tokio::select! {
+                _ = close_notifier.notified() => return,
+                _ = runtime::sleep(delay) => {}
+            }
         }
     }
 
     async fn receiver_loop(
         socket: Arc<SearchSocket>,
-        pool: Pool<SocketAddr>,
+        pool: Pool<Answer>,
         mutex: Arc<Mutex<()>>,
+        found: Arc<AtomicBool>,
     ) {
         loop {
             if let Ok((data, addr)) = socket.read().await {
-                if data == DEFAULT_ANSWER_PACKAGE {
+                if let Some(endpoint) = search_socket::parse_answer(&data) {
                     let lock = mutex.lock().await;
-                    if pool.write(addr).await.is_err() {
+                    if pool.write(Answer { addr, endpoint }).await.is_err() {
                         break;
                     }
+                    found.store(true, Ordering::SeqCst);
                     drop(lock);
                 }
             }
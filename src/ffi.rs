@@ -0,0 +1,158 @@
+use std::slice;
+
+use bytes::Buf;
+
+use crate::builder::version::{MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, VERSION_KIND};
+use crate::mem::{Frame, KindWidth, HEADER_BYTES, HEADER_KIND_BYTES, HEADER_LEN_BYTES, WIDE_KIND_U16, WIDE_KIND_U32};
+use crate::transport::control::CONTROL_KIND;
+
+/// Operation completed successfully
+pub const COBRA_OK: isize = 0;
+/// One of the required pointers is null
+pub const COBRA_ERR_NULL: isize = -1;
+/// Body doesn't fit into a single frame
+pub const COBRA_ERR_TOO_LARGE: isize = -2;
+/// Output buffer is too small to store the frame
+pub const COBRA_ERR_BUFFER: isize = -3;
+/// Input bytes are not a valid frame
+pub const COBRA_ERR_MALFORMED: isize = -4;
+
+/// Kind of frames whose body starts with a 2-byte big-endian kind
+pub const COBRA_WIDE_KIND_U16: u8 = WIDE_KIND_U16;
+/// Kind of frames whose body starts with a 4-byte big-endian kind
+pub const COBRA_WIDE_KIND_U32: u8 = WIDE_KIND_U32;
+
+/// Codes of kind widths sent in the version frame, equal to the number of kind bytes
+pub const COBRA_KIND_WIDTH_U8: u8 = KindWidth::U8.code();
+/// See [`COBRA_KIND_WIDTH_U8`]
+///
+/// [`COBRA_KIND_WIDTH_U8`]: crate::ffi::COBRA_KIND_WIDTH_U8
+pub const COBRA_KIND_WIDTH_U16: u8 = KindWidth::U16.code();
+/// See [`COBRA_KIND_WIDTH_U8`]
+///
+/// [`COBRA_KIND_WIDTH_U8`]: crate::ffi::COBRA_KIND_WIDTH_U8
+pub const COBRA_KIND_WIDTH_U32: u8 = KindWidth::U32.code();
+
+/// Kind of control frames: close handshake, write shutdown and round trip probes
+pub const COBRA_CONTROL_KIND: u8 = CONTROL_KIND;
+/// Kind of the version frame both peers send at the start of the handshake
+pub const COBRA_VERSION_KIND: u8 = VERSION_KIND;
+/// The newest protocol version, peers use the lower of their versions
+pub const COBRA_PROTOCOL_VERSION: u8 = PROTOCOL_VERSION;
+/// The oldest protocol version, peers sending an older one are refused
+pub const COBRA_MIN_PROTOCOL_VERSION: u8 = MIN_PROTOCOL_VERSION;
+
+/// Codes of deflate containers offered at handshake by the gzip compression provider
+pub const COBRA_FLATE_GZIP: u8 = 1;
+/// See [`COBRA_FLATE_GZIP`]
+///
+/// [`COBRA_FLATE_GZIP`]: crate::ffi::COBRA_FLATE_GZIP
+pub const COBRA_FLATE_ZLIB: u8 = 2;
+/// See [`COBRA_FLATE_GZIP`]
+///
+/// [`COBRA_FLATE_GZIP`]: crate::ffi::COBRA_FLATE_GZIP
+pub const COBRA_FLATE_DEFLATE: u8 = 3;
+
+/// Returns number of bytes used to store frame length
+#[no_mangle]
+pub extern "C" fn cobra_frame_len_bytes() -> usize {
+    HEADER_LEN_BYTES
+}
+
+/// Returns number of bytes used to store frame kind
+#[no_mangle]
+pub extern "C" fn cobra_frame_kind_bytes() -> usize {
+    HEADER_KIND_BYTES
+}
+
+/// Returns total frame header size
+#[no_mangle]
+pub extern "C" fn cobra_frame_header_len() -> usize {
+    HEADER_BYTES
+}
+
+/// Returns maximum body length that can be stored inside one frame
+///
+/// The length field covers kind and body, so the body is
+/// [`HEADER_KIND_BYTES`] shorter than the length field allows
+///
+/// [`HEADER_KIND_BYTES`]: crate::mem::HEADER_KIND_BYTES
+#[no_mangle]
+pub extern "C" fn cobra_frame_max_body_len() -> usize {
+    (1 << (8 * HEADER_LEN_BYTES)) - 1 - HEADER_KIND_BYTES
+}
+
+/// Encodes frame into `out` buffer
+///
+/// Returns the number of written bytes or one of the negative
+/// `COBRA_ERR_*` codes
+///
+/// # Safety
+///
+/// `body` must point to `body_len` readable bytes (may be null if `body_len` is 0)
+/// and `out` must point to `out_len` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn cobra_frame_encode(kind: u8,
+                                            body: *const u8,
+                                            body_len: usize,
+                                            out: *mut u8,
+                                            out_len: usize) -> isize {
+    if (body.is_null() && body_len != 0) || out.is_null() {
+        return COBRA_ERR_NULL;
+    }
+    if body_len > cobra_frame_max_body_len() {
+        return COBRA_ERR_TOO_LARGE;
+    }
+    if out_len < HEADER_BYTES + body_len {
+        return COBRA_ERR_BUFFER;
+    }
+
+    let body = if body_len == 0 { &[][..] } else { slice::from_raw_parts(body, body_len) };
+    let frame = Frame::create(kind, body);
+
+    slice::from_raw_parts_mut(out, frame.len()).copy_from_slice(&frame);
+    frame.len() as isize
+}
+
+/// Decodes frame from the start of `data`
+///
+/// On success writes frame kind, body offset and body length into
+/// the output pointers and returns the total frame length.
+/// Returns [`COBRA_OK`] if `data` doesn't contain a complete frame yet
+/// and one of the negative `COBRA_ERR_*` codes on failure
+///
+/// # Safety
+///
+/// `data` must point to `data_len` readable bytes, output pointers must be
+/// valid for writes
+///
+/// [`COBRA_OK`]: crate::ffi::COBRA_OK
+#[no_mangle]
+pub unsafe extern "C" fn cobra_frame_decode(data: *const u8,
+                                            data_len: usize,
+                                            kind: *mut u8,
+                                            body_offset: *mut usize,
+                                            body_len: *mut usize) -> isize {
+    if data.is_null() || kind.is_null() || body_offset.is_null() || body_len.is_null() {
+        return COBRA_ERR_NULL;
+    }
+    if data_len < HEADER_BYTES {
+        return COBRA_OK;
+    }
+
+    let mut data = slice::from_raw_parts(data, data_len);
+    let frame_len = data.get_uint(HEADER_LEN_BYTES) as usize;
+
+    if frame_len < HEADER_KIND_BYTES {
+        return COBRA_ERR_MALFORMED;
+    }
+    if data_len < HEADER_LEN_BYTES + frame_len {
+        return COBRA_OK;
+    }
+
+    *kind = data.get_uint(HEADER_KIND_BYTES) as u8;
+    *body_offset = HEADER_BYTES;
+    *body_len = frame_len - HEADER_KIND_BYTES;
+
+    (HEADER_LEN_BYTES + frame_len) as isize
+}
@@ -2,5 +2,18 @@ pub mod mem;
 pub mod sync;
 pub mod transport;
 pub mod builder;
+pub mod protocol;
+pub mod config;
 pub mod providers;
 pub mod discovery;
+pub mod ffi;
+pub mod conformance;
+pub mod runtime;
+pub mod server;
+pub mod debug;
+pub mod p2p;
+pub mod net;
+pub mod blocking;
+pub mod retry;
+pub mod trace;
+mod rng;
@@ -0,0 +1,171 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::sync::Notify;
+
+static GLOBAL: MemoryBudget = MemoryBudget {
+    receive_capacity: AtomicUsize::new(0),
+    receive_buffers: AtomicUsize::new(0),
+    received_frames: AtomicUsize::new(0),
+    write_queues: AtomicUsize::new(0),
+    limit: AtomicUsize::new(NO_LIMIT),
+    released: Notify::const_new(),
+};
+
+const NO_LIMIT: usize = usize::MAX;
+
+/// Memory held by connections of the process
+///
+/// Every TCP connection reports bytes of incomplete frames in its receive
+/// buffer, received frames waiting for the application and frames waiting
+/// to be written. The budget may cap their sum: once it's exceeded, all
+/// connections pause socket reads until the application catches up
+///
+/// # Note
+///
+/// Writes aren't paused, so the cap must leave room for them. A connection
+/// always completes a partially received frame, so the cap may be exceeded
+/// by up to one frame per connection
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::mem::MemoryBudget;
+///
+/// let budget = MemoryBudget::global();
+/// budget.set_limit(Some(256 * 1024 * 1024));
+///
+/// let snapshot = budget.snapshot();
+/// println!("connections hold {} bytes", snapshot.total());
+/// ```
+pub struct MemoryBudget {
+    receive_capacity: AtomicUsize,
+    receive_buffers: AtomicUsize,
+    received_frames: AtomicUsize,
+    write_queues: AtomicUsize,
+    limit: AtomicUsize,
+    released: Notify,
+}
+
+/// Memory usage at the moment of [`MemoryBudget::snapshot()`]
+///
+/// [`MemoryBudget::snapshot()`]: crate::mem::MemoryBudget::snapshot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemorySnapshot {
+    /// Allocated capacity of receive buffers, not counted in [`total()`]
+    ///
+    /// [`total()`]: crate::mem::MemorySnapshot::total
+    pub receive_capacity: usize,
+    /// Received bytes of incomplete frames
+    pub receive_buffers: usize,
+    /// Received frames waiting for the application
+    pub received_frames: usize,
+    /// Frames waiting to be written
+    pub write_queues: usize,
+    /// Cap of the total, see [`MemoryBudget::set_limit()`]
+    ///
+    /// [`MemoryBudget::set_limit()`]: crate::mem::MemoryBudget::set_limit
+    pub limit: Option<usize>,
+}
+
+/// Part of the memory reported by a connection
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Area {
+    ReceiveCapacity,
+    ReceiveBuffers,
+    ReceivedFrames,
+    WriteQueues,
+}
+
+/// Bytes of one area held by a connection, released when dropped
+pub(crate) struct Usage {
+    area: Area,
+    bytes: usize,
+}
+
+impl MemoryBudget {
+    /// Returns budget shared by all connections
+    pub fn global() -> &'static MemoryBudget {
+        &GLOBAL
+    }
+
+    /// Returns current memory usage
+    pub fn snapshot(&self) -> MemorySnapshot {
+        MemorySnapshot {
+            receive_capacity: self.receive_capacity.load(Ordering::SeqCst),
+            receive_buffers: self.receive_buffers.load(Ordering::SeqCst),
+            received_frames: self.received_frames.load(Ordering::SeqCst),
+            write_queues: self.write_queues.load(Ordering::SeqCst),
+            limit: self.limit(),
+        }
+    }
+
+    /// Sets cap of the total usage, [`None`] removes it
+    ///
+    /// By default there is no cap
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.limit.store(limit.unwrap_or(NO_LIMIT), Ordering::SeqCst);
+        self.released.notify_waiters();
+    }
+
+    /// Returns cap of the total usage
+    pub fn limit(&self) -> Option<usize> {
+        match self.limit.load(Ordering::SeqCst) {
+            NO_LIMIT => None,
+            limit => Some(limit),
+        }
+    }
+
+    /// Waits until the total usage is below the cap
+    pub(crate) async fn wait_below_limit(&self) {
+        loop {
+            let released = self.released.notified();
+            if self.snapshot().total() < self.limit.load(Ordering::SeqCst) {
+                return;
+            }
+            released.await;
+        }
+    }
+
+    fn counter(&self, area: Area) -> &AtomicUsize {
+        match area {
+            Area::ReceiveCapacity => &self.receive_capacity,
+            Area::ReceiveBuffers => &self.receive_buffers,
+            Area::ReceivedFrames => &self.received_frames,
+            Area::WriteQueues => &self.write_queues,
+        }
+    }
+}
+
+impl MemorySnapshot {
+    /// Returns memory limited by the cap
+    pub fn total(&self) -> usize {
+        self.receive_buffers + self.received_frames + self.write_queues
+    }
+}
+
+impl Usage {
+    pub(crate) fn new(area: Area, bytes: usize) -> Self {
+        GLOBAL.counter(area).fetch_add(bytes, Ordering::SeqCst);
+        Usage { area, bytes }
+    }
+
+    /// Replaces the reported number of bytes
+    pub(crate) fn set(&mut self, bytes: usize) {
+        let counter = GLOBAL.counter(self.area);
+        if bytes >= self.bytes {
+            counter.fetch_add(bytes - self.bytes, Ordering::SeqCst);
+        } else {
+            counter.fetch_sub(self.bytes - bytes, Ordering::SeqCst);
+            GLOBAL.released.notify_waiters();
+        }
+        self.bytes = bytes;
+    }
+}
+
+impl Drop for Usage {
+    fn drop(&mut self) {
+        self.set(0);
+    }
+}
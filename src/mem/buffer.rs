@@ -27,6 +27,102 @@ pub trait Chunk: DerefMut<Target=BytesMut> {
     fn max_body_len() -> usize {
         256_usize.pow(Self::header_len() as u32)
     }
+
+    /// Returns minimum data length of a valid chunk
+    ///
+    /// Shorter chunks are malformed and skipped by [`ConcatBuf`]
+    ///
+    /// [`ConcatBuf`]: crate::mem::ConcatBuf
+    fn min_body_len() -> usize {
+        0
+    }
+}
+
+const DEFAULT_INITIAL_CAPACITY: usize = 4 * 1024;
+const DEFAULT_MAX_CAPACITY: usize = 128 * 1024;
+const DEFAULT_GROWTH_FACTOR: usize = 2;
+const DEFAULT_SHRINK_AFTER: usize = 64;
+
+/// Describes how [`ConcatBuf`] capacity changes over time
+///
+/// Buffer starts with `initial` capacity and grows by `factor` every time
+/// a read fills it completely, up to `max`. After `shrink_after` reads
+/// using less than a quarter of the capacity it shrinks back by `factor`
+///
+/// [`ConcatBuf`]: crate::mem::ConcatBuf
+#[derive(Debug, Clone, Copy)]
+pub struct GrowthPolicy {
+    initial: usize,
+    max: usize,
+    factor: usize,
+    shrink_after: usize,
+}
+
+impl GrowthPolicy {
+    /// Creates new policy with default growth factor
+    ///
+    /// # Note
+    ///
+    /// Panics if `initial` is zero or greater than `max`
+    pub fn new(initial: usize, max: usize) -> Self {
+        if initial == 0 || initial > max {
+            panic!("invalid buffer growth bounds")
+        }
+
+        GrowthPolicy {
+            initial,
+            max,
+            factor: DEFAULT_GROWTH_FACTOR,
+            shrink_after: DEFAULT_SHRINK_AFTER,
+        }
+    }
+
+    /// Creates policy which never changes capacity
+    pub fn fixed(capacity: usize) -> Self {
+        GrowthPolicy {
+            initial: capacity,
+            max: capacity,
+            factor: 1,
+            shrink_after: usize::MAX,
+        }
+    }
+
+    /// Sets the multiplier applied to capacity on growth and shrink, 2 by default
+    ///
+    /// # Note
+    ///
+    /// Panics if `factor` isn't greater than 1, use [`fixed()`] for constant capacity
+    ///
+    /// [`fixed()`]: crate::mem::GrowthPolicy::fixed
+    pub fn set_factor(mut self, factor: usize) -> Self {
+        if factor <= 1 {
+            panic!("buffer growth factor must be greater than 1")
+        }
+
+        self.factor = factor;
+        self
+    }
+
+    /// Sets the number of consecutive underused reads before shrinking, 64 by default
+    ///
+    /// Zero acts as 1, [`usize::MAX`] practically disables shrinking
+    ///
+    /// [`usize::MAX`]: std::primitive::usize::MAX
+    pub fn set_shrink_after(mut self, reads: usize) -> Self {
+        self.shrink_after = reads;
+        self
+    }
+
+    /// Returns the hard capacity limit
+    pub fn max(&self) -> usize {
+        self.max
+    }
+}
+
+impl Default for GrowthPolicy {
+    fn default() -> Self {
+        GrowthPolicy::new(DEFAULT_INITIAL_CAPACITY, DEFAULT_MAX_CAPACITY)
+    }
 }
 
 /// A buffer for restoring memory chunks from an undefined byte stream
@@ -39,6 +135,9 @@ pub trait Chunk: DerefMut<Target=BytesMut> {
 pub struct ConcatBuf<T: Chunk> {
     inner: BytesMut,
     partial_chunk: Option<(usize, T)>,
+    policy: GrowthPolicy,
+    capacity: usize,
+    idle_reads: usize,
 }
 
 impl<T: Chunk> ConcatBuf<T> {
@@ -52,12 +151,84 @@ impl<T: Chunk> ConcatBuf<T> {
             panic!("attempt to allocate buffer with insufficient memory")
         }
 
+        ConcatBuf::with_policy(GrowthPolicy::fixed(capacity))
+    }
+
+    /// Creates new buffer which changes its capacity according to the policy
+    ///
+    /// # Note
+    ///
+    /// Unlike [`with_capacity`], buffer doesn't have to fit the whole chunk:
+    /// chunks are restored from several reads
+    ///
+    /// [`with_capacity`]: crate::mem::ConcatBuf::with_capacity
+    pub fn with_policy(policy: GrowthPolicy) -> Self {
+        let capacity = policy.initial.max(T::header_len());
+
         ConcatBuf {
             inner: BytesMut::with_capacity(capacity),
             partial_chunk: None,
+            policy,
+            capacity,
+            idle_reads: 0,
+        }
+    }
+
+    /// Returns current capacity limit of the buffer
+    pub fn limit(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns how many bytes can be read into the buffer
+    /// without exceeding current capacity
+    pub fn remaining_limit(&self) -> usize {
+        self.capacity.saturating_sub(self.inner.len())
+    }
+
+    /// Returns number of received bytes which don't form a complete chunk yet
+    pub fn buffered(&self) -> usize {
+        self.inner.len() + self.partial_chunk.as_ref().map_or(0, |(len, _)| *len)
+    }
+
+    /// Updates buffer capacity according to the growth policy
+    ///
+    /// # Note
+    ///
+    /// You should call this function after each read
+    /// and before reading chunks
+    pub fn adapt_capacity(&mut self) {
+        if self.inner.len() >= self.capacity {
+            self.idle_reads = 0;
+
+            let capacity = (self.capacity * self.policy.factor).min(self.policy.max);
+            if capacity > self.capacity {
+                self.inner.reserve(capacity.saturating_sub(self.inner.len()));
+                self.capacity = capacity;
+            }
+        } else if self.inner.len() < self.capacity / 4 {
+            self.idle_reads = self.idle_reads.saturating_add(1);
+
+            if self.idle_reads >= self.policy.shrink_after && self.capacity > self.policy.initial {
+                self.idle_reads = 0;
+                self.shrink();
+            }
+        } else {
+            self.idle_reads = 0;
         }
     }
 
+    fn shrink(&mut self) {
+        let capacity = (self.capacity / self.policy.factor)
+            .max(self.policy.initial)
+            .max(T::header_len());
+
+        let mut inner = BytesMut::with_capacity(capacity);
+        inner.extend_from_slice(&self.inner);
+
+        self.inner = inner;
+        self.capacity = capacity;
+    }
+
     fn create_chunk(body_len: usize) -> T {
         let capacity = T::header_len() + body_len;
         let mut chunk = T::with_capacity(capacity);
@@ -95,7 +266,14 @@ impl<T: Chunk> ConcatBuf<T> {
             self.inner.copy_to_slice(&mut chunk[current_len..]);
             Some(chunk)
         } else {
-            self.partial_chunk = Some((current_len, chunk));
+            // Moving available bytes to the chunk, so the buffer
+            // doesn't have to fit the rest of the chunk
+            let available = self.inner.len();
+
+            self.inner.copy_to_slice(&mut chunk[current_len..current_len + available]);
+            self.fragment();
+
+            self.partial_chunk = Some((current_len + available, chunk));
             None
         }
     }
@@ -119,30 +297,34 @@ impl<T: Chunk> ConcatBuf<T> {
     }
 
     fn try_read_header(&mut self) -> Option<usize> {
-        if self.inner.len() >= T::header_len() {
-            Some(self.inner.get_uint(T::header_len()) as usize)
-        } else {
-            self.fragment();
-            None
+        while self.inner.len() >= T::header_len() {
+            let body_len = self.inner.get_uint(T::header_len()) as usize;
+
+            // Malformed chunk has nothing to restore, only its header is dropped
+            if body_len >= T::min_body_len() {
+                return Some(body_len);
+            }
         }
+
+        self.fragment();
+        None
     }
 
     fn fragment(&mut self) {
         // This action will move (using memmove) data to the start of the buffer.
         // If there is no data, it will also move the cursor to the start.
-        // Read .reserve() documentation for more details
-        self.inner.reserve(self.inner.capacity() - self.inner.len() + 1);
+        // Unlike .reserve() it never allocates, so repeated calls without
+        // consumed bytes don't grow the buffer. Read .try_reclaim() documentation
+        // for more details
+        let _ = self.inner.try_reclaim(self.inner.capacity() - self.inner.len() + 1);
     }
 }
 
 impl<T: Chunk> Default for ConcatBuf<T> {
     fn default() -> Self {
-        ConcatBuf {
-            inner: BytesMut::with_capacity(
-                (T::header_len() + 256_usize.pow(T::header_len() as u32) - 1) * 2
-            ),
-            partial_chunk: None,
-        }
+        ConcatBuf::with_capacity(
+            (T::header_len() + 256_usize.pow(T::header_len() as u32) - 1) * 2
+        )
     }
 }
 
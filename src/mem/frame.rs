@@ -1,13 +1,37 @@
+use std::io;
 use std::ops::{Deref, DerefMut};
 
-use bytes::{BufMut, BytesMut};
+use bytes::buf::UninitSlice;
+use bytes::{Buf, BufMut, BytesMut};
 
 use crate::mem::Chunk;
 use crate::sync::Kind;
 
-const HEADER_LEN_BYTES: usize = 2;
-const HEADER_KIND_BYTES: usize = 1;
-const HEADER_BYTES: usize = HEADER_LEN_BYTES + HEADER_KIND_BYTES;
+/// Number of bytes used to store frame length
+pub const HEADER_LEN_BYTES: usize = 2;
+/// Number of bytes used to store frame kind
+pub const HEADER_KIND_BYTES: usize = 1;
+/// Total frame header size
+pub const HEADER_BYTES: usize = HEADER_LEN_BYTES + HEADER_KIND_BYTES;
+/// Greatest body length fitting the length field, which counts the kind
+pub const MAX_BODY_LEN: usize = u16::MAX as usize - HEADER_KIND_BYTES;
+
+/// Kind of frames followed by a 2-byte kind, see [`WideFrame`]
+///
+/// [`WideFrame`]: crate::mem::WideFrame
+pub const WIDE_KIND_U16: u8 = 254;
+/// Kind of frames followed by a 4-byte kind, see [`WideFrame`]
+///
+/// [`WideFrame`]: crate::mem::WideFrame
+pub const WIDE_KIND_U32: u8 = 255;
+
+/// Width of frame kinds agreed with the peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KindWidth {
+    U8,
+    U16,
+    U32,
+}
 
 /// Simple stream-based protocol communication unit
 ///
@@ -36,6 +60,41 @@ impl Frame {
         frame
     }
 
+    /// Creates builder writing the body right after the reserved header
+    ///
+    /// Lets serializers encode packages into the frame without copying,
+    /// `capacity` is the expected body length
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::io::Write;
+    ///
+    /// use cobra_rs::mem::Frame;
+    ///
+    /// let mut builder = Frame::builder(1, 5);
+    /// write!(builder, "hello").unwrap();
+    ///
+    /// let frame = builder.finish();
+    /// assert_eq!(&frame[..], b"\x00\x06\x01hello");
+    /// ```
+    pub fn builder(kind: u8, capacity: usize) -> FrameBuilder {
+        let mut inner = BytesMut::with_capacity(HEADER_BYTES + capacity);
+        inner.put_uint(0, HEADER_LEN_BYTES);
+        inner.put_uint(kind as u64, HEADER_KIND_BYTES);
+
+        FrameBuilder { inner }
+    }
+
+    /// Returns header of the frame with the body of `body_len` bytes
+    pub(crate) fn header(kind: u8, body_len: usize) -> [u8; HEADER_BYTES] {
+        let len = ((body_len + HEADER_KIND_BYTES) as u16).to_be_bytes();
+        let mut header = [0; HEADER_BYTES];
+        header[..HEADER_LEN_BYTES].copy_from_slice(&len);
+        header[HEADER_LEN_BYTES] = kind;
+        header
+    }
+
     fn put_header(&mut self, kind: u8) {
         self.inner.put_uint((self.inner.capacity() - HEADER_LEN_BYTES) as u64, HEADER_LEN_BYTES);
         self.inner.put_uint(kind as u64, HEADER_KIND_BYTES);
@@ -56,12 +115,197 @@ impl Frame {
     }
 }
 
+/// Body of the frame being written, returned by [`Frame::builder()`]
+///
+/// Implements [`BufMut`] and [`Write`], both limited to [`MAX_BODY_LEN`]
+/// bytes. [`BufMut`] methods panic past the limit, while [`Write`]
+/// writes as many bytes as fit
+///
+/// [`Frame::builder()`]: crate::mem::Frame::builder
+/// [`BufMut`]: bytes::BufMut
+/// [`Write`]: std::io::Write
+/// [`MAX_BODY_LEN`]: crate::mem::MAX_BODY_LEN
+pub struct FrameBuilder {
+    inner: BytesMut,
+}
+
+impl FrameBuilder {
+    /// Returns the body written so far
+    pub fn body(&self) -> &[u8] {
+        &self.inner[HEADER_BYTES..]
+    }
+
+    /// Lets the body be patched in place, e.g. a length prefix
+    pub fn body_mut(&mut self) -> &mut [u8] {
+        &mut self.inner[HEADER_BYTES..]
+    }
+
+    /// Fills in the length and returns the frame
+    pub fn finish(mut self) -> Frame {
+        let len = (self.inner.len() - HEADER_LEN_BYTES) as u16;
+        self.inner[..HEADER_LEN_BYTES].copy_from_slice(&len.to_be_bytes());
+
+        Frame { inner: self.inner }
+    }
+}
+
+unsafe impl BufMut for FrameBuilder {
+    fn remaining_mut(&self) -> usize {
+        MAX_BODY_LEN - self.body().len()
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        assert!(cnt <= self.remaining_mut(), "frame body is too long");
+        self.inner.advance_mut(cnt)
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        let remaining = self.remaining_mut();
+        let chunk = self.inner.chunk_mut();
+        let len = chunk.len().min(remaining);
+        &mut chunk[..len]
+    }
+}
+
+impl io::Write for FrameBuilder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.remaining_mut());
+        self.inner.put_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 impl Kind<u8> for Frame {
     fn kind(&self) -> u8 {
         self.inner[HEADER_LEN_BYTES]
     }
 }
 
+impl KindWidth {
+    /// Returns number of bytes used to store the kind
+    pub const fn bytes(self) -> usize {
+        match self {
+            KindWidth::U8 => 1,
+            KindWidth::U16 => 2,
+            KindWidth::U32 => 4,
+        }
+    }
+
+    /// Returns the greatest kind of this width
+    pub const fn max_kind(self) -> u32 {
+        match self {
+            KindWidth::U8 => u8::MAX as u32,
+            KindWidth::U16 => u16::MAX as u32,
+            KindWidth::U32 => u32::MAX,
+        }
+    }
+
+    /// Returns the narrowest width fitting the kind
+    pub const fn of(kind: u32) -> Self {
+        match kind {
+            0..=0xFF => KindWidth::U8,
+            0x100..=0xFFFF => KindWidth::U16,
+            _ => KindWidth::U32,
+        }
+    }
+
+    pub(crate) const fn code(self) -> u8 {
+        self.bytes() as u8
+    }
+
+    pub(crate) const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(KindWidth::U8),
+            2 => Some(KindWidth::U16),
+            4 => Some(KindWidth::U32),
+            _ => None,
+        }
+    }
+}
+
+/// Frame with a kind wider than one byte
+///
+/// Layout is `[len: 2 bytes][escape kind: 1 byte][kind: 2 or 4 bytes][body]`,
+/// where the escape kind is [`WIDE_KIND_U16`] or [`WIDE_KIND_U32`],
+/// so transports route it as an ordinary [`Frame`]. Kinds of wide
+/// frames don't overlap with one-byte kinds
+///
+/// [`WIDE_KIND_U16`]: crate::mem::WIDE_KIND_U16
+/// [`WIDE_KIND_U32`]: crate::mem::WIDE_KIND_U32
+/// [`Frame`]: crate::mem::Frame
+pub struct WideFrame {
+    frame: Frame,
+    kind: u32,
+}
+
+impl WideFrame {
+    /// Creates new frame using the narrowest escape kind fitting `kind`
+    pub fn create(kind: u32, body: &[u8]) -> Self {
+        let mut builder = match KindWidth::of(kind) {
+            KindWidth::U8 | KindWidth::U16 => {
+                let mut builder = Frame::builder(WIDE_KIND_U16, 2 + body.len());
+                builder.put_u16(kind as u16);
+                builder
+            }
+            KindWidth::U32 => {
+                let mut builder = Frame::builder(WIDE_KIND_U32, 4 + body.len());
+                builder.put_u32(kind);
+                builder
+            }
+        };
+        builder.put_slice(body);
+
+        WideFrame { frame: builder.finish(), kind }
+    }
+
+    /// Returns [`None`] if the frame isn't a valid wide frame
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn from_frame(frame: Frame) -> Option<Self> {
+        let mut extension = &frame[HEADER_BYTES..];
+        let kind = match frame.kind() {
+            WIDE_KIND_U16 if extension.remaining() >= 2 => extension.get_u16() as u32,
+            WIDE_KIND_U32 if extension.remaining() >= 4 => extension.get_u32(),
+            _ => return None,
+        };
+
+        Some(WideFrame { frame, kind })
+    }
+
+    /// Returns body of frame
+    pub fn get_body(self) -> BytesMut {
+        let width = match self.frame.kind() {
+            WIDE_KIND_U16 => KindWidth::U16,
+            _ => KindWidth::U32,
+        };
+        let mut body = self.frame.get_body();
+        body.advance(width.bytes());
+        body
+    }
+
+    pub fn into_frame(self) -> Frame {
+        self.frame
+    }
+}
+
+impl Kind<u32> for WideFrame {
+    fn kind(&self) -> u32 {
+        self.kind
+    }
+}
+
+impl Deref for WideFrame {
+    type Target = Frame;
+
+    fn deref(&self) -> &Self::Target {
+        &self.frame
+    }
+}
+
 impl Chunk for Frame {
     fn header_len() -> usize {
         HEADER_LEN_BYTES
@@ -70,6 +314,11 @@ impl Chunk for Frame {
     fn with_capacity(capacity: usize) -> Self {
         Frame { inner: BytesMut::with_capacity(capacity) }
     }
+
+    // Length counts the kind byte
+    fn min_body_len() -> usize {
+        HEADER_KIND_BYTES
+    }
 }
 
 impl Deref for Frame {
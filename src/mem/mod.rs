@@ -1,5 +1,9 @@
+pub use budget::{MemoryBudget, MemorySnapshot};
 pub use buffer::*;
 pub use frame::*;
 
+pub(crate) use budget::{Area, Usage};
+
+mod budget;
 mod buffer;
 mod frame;
@@ -0,0 +1,6 @@
+pub use port_mapper::*;
+pub use resolver::*;
+
+mod port_mapper;
+mod resolver;
+mod upnp;
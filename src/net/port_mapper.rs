@@ -0,0 +1,274 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+
+use crate::net::upnp::{self, Service};
+use crate::runtime;
+use crate::transport::tcp::Listener;
+
+/// Port of the NAT-PMP server on the gateway
+pub const NAT_PMP_PORT: u16 = 5351;
+
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+
+// Timeout of the first request, doubled on every retry, see RFC 6886 3.1
+const INITIAL_REQUEST_TIMEOUT: Duration = Duration::from_millis(250);
+const REQUEST_ATTEMPTS: u32 = 4;
+
+const OPCODE_EXTERNAL_ADDRESS: u8 = 0;
+const OPCODE_MAP_TCP: u8 = 2;
+const RESPONSE_OPCODE_OFFSET: u8 = 128;
+
+/// Maps ports of the gateway to the local ports via NAT-PMP (RFC 6886) or UPnP IGD
+///
+/// Lets peers outside of the NAT reach a [`Listener`]. Mappings
+/// are renewed in the background and deleted when dropped. The external
+/// address may be announced to peers with [`discovery::listener::Listener::announce_mapping()`]
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::SocketAddr;
+/// use cobra_rs::net::{PortMapper, NAT_PMP_PORT};
+/// use cobra_rs::transport::tcp::Listener;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let listener = Listener::listen("0.0.0.0:5000").await.unwrap();
+///     let gateway = SocketAddr::from(([192, 168, 1, 1], NAT_PMP_PORT));
+///
+///     let mappings = PortMapper::new(gateway).map_listener(&listener).await.unwrap();
+///     println!("Reachable at {}", mappings[0].external_addr());
+/// }
+/// ```
+///
+/// [`Listener`]: crate::transport::tcp::Listener
+/// [`discovery::listener::Listener::announce_mapping()`]: crate::discovery::listener::Listener::announce_mapping
+#[derive(Debug, Clone)]
+pub struct PortMapper {
+    gateway: Gateway,
+    lifetime: Duration,
+}
+
+#[derive(Debug, Clone)]
+enum Gateway {
+    NatPmp(SocketAddr),
+    Upnp(Service),
+}
+
+/// Port mapping created by [`PortMapper`]
+///
+/// Mapping is deleted when dropped
+///
+/// [`PortMapper`]: crate::net::PortMapper
+pub struct PortMapping {
+    internal_port: u16,
+    external_addr: Arc<RwLock<SocketAddr>>,
+    close_notifier: Arc<Notify>,
+}
+
+impl PortMapper {
+    /// Creates mapper using NAT-PMP server at the specified address
+    ///
+    /// Usually it's the default gateway with [`NAT_PMP_PORT`]
+    ///
+    /// [`NAT_PMP_PORT`]: crate::net::NAT_PMP_PORT
+    pub fn new(gateway: SocketAddr) -> Self {
+        PortMapper {
+            gateway: Gateway::NatPmp(gateway),
+            lifetime: DEFAULT_LIFETIME,
+        }
+    }
+
+    /// Creates mapper using UPnP Internet Gateway Device found via SSDP
+    ///
+    /// External port of UPnP mappings is the same as the local one
+    pub async fn upnp() -> io::Result<Self> {
+        Self::upnp_at(&upnp::search().await?).await
+    }
+
+    /// Creates mapper using UPnP Internet Gateway Device described at `location`
+    ///
+    /// Location is the `http://` URL of the device description,
+    /// its host has to be an IP address
+    pub async fn upnp_at(location: &str) -> io::Result<Self> {
+        Ok(PortMapper {
+            gateway: Gateway::Upnp(Service::from_location(location).await?),
+            lifetime: DEFAULT_LIFETIME,
+        })
+    }
+
+    /// Sets requested lifetime of mappings, 2 hours by default
+    ///
+    /// Mappings are renewed after half of the lifetime.
+    /// Lifetime is rounded up to whole seconds
+    pub fn set_lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// Returns public address of the gateway
+    pub async fn external_ip(&self) -> io::Result<Ipv4Addr> {
+        let gateway = match &self.gateway {
+            Gateway::NatPmp(gateway) => *gateway,
+            Gateway::Upnp(service) => return service.external_ip().await,
+        };
+
+        let response = nat_pmp_request(gateway, &[0, OPCODE_EXTERNAL_ADDRESS], 12).await?;
+        Ok(Ipv4Addr::new(response[8], response[9], response[10], response[11]))
+    }
+
+    /// Maps external TCP port to the local port
+    pub async fn map(&self, internal_port: u16) -> io::Result<PortMapping> {
+        let ip = self.external_ip().await?;
+        let (external_port, lifetime) = self.map_port(internal_port, self.lifetime_secs()).await?;
+
+        let external_addr = Arc::new(RwLock::new(SocketAddr::from((ip, external_port))));
+        let close_notifier = Arc::new(Notify::new());
+
+        runtime::spawn(self.clone().renew_loop(
+            internal_port,
+            lifetime,
+            external_addr.clone(),
+            close_notifier.clone(),
+        ));
+
+        Ok(PortMapping {
+            internal_port,
+            external_addr,
+            close_notifier,
+        })
+    }
+
+    /// Maps ports of all sockets bound by the listener
+    pub async fn map_listener(&self, listener: &Listener) -> io::Result<Vec<PortMapping>> {
+        let mut mappings = Vec::new();
+        for addr in listener.local_addrs()? {
+            mappings.push(self.map(addr.port()).await?);
+        }
+        Ok(mappings)
+    }
+
+    fn lifetime_secs(&self) -> u32 {
+        let secs = self.lifetime.as_secs() + (self.lifetime.subsec_nanos() > 0) as u64;
+        secs.clamp(1, u32::MAX as u64) as u32
+    }
+
+    // Returns mapped external port and granted lifetime in seconds
+    async fn map_port(&self, internal_port: u16, lifetime: u32) -> io::Result<(u16, u32)> {
+        let gateway = match &self.gateway {
+            Gateway::NatPmp(gateway) => *gateway,
+            Gateway::Upnp(service) => {
+                // Zero lease is infinite in UPnP, so the mapping is deleted explicitly
+                match lifetime {
+                    0 => service.delete_mapping(internal_port).await?,
+                    _ => service.add_mapping(internal_port, lifetime).await?,
+                }
+                return Ok((internal_port, lifetime));
+            }
+        };
+
+        // Deletion requires zero external port, see RFC 6886 3.4
+        let suggested_port = match lifetime {
+            0 => 0,
+            _ => internal_port,
+        };
+
+        let mut request = vec![0, OPCODE_MAP_TCP, 0, 0];
+        request.extend_from_slice(&internal_port.to_be_bytes());
+        request.extend_from_slice(&suggested_port.to_be_bytes());
+        request.extend_from_slice(&lifetime.to_be_bytes());
+
+        let response = nat_pmp_request(gateway, &request, 16).await?;
+        let external_port = u16::from_be_bytes([response[10], response[11]]);
+        let lifetime = u32::from_be_bytes([response[12], response[13], response[14], response[15]]);
+
+        Ok((external_port, lifetime))
+    }
+
+    async fn renew_loop(
+        self,
+        internal_port: u16,
+        mut lifetime: u32,
+        external_addr: Arc<RwLock<SocketAddr>>,
+        close_notifier: Arc<Notify>,
+    ) {
+        loop {
+            let renew_after = Duration::from_secs(lifetime as u64).max(Duration::from_secs(1)) / 2;
+
+            tokio::select! {
+                _ = close_notifier.notified() => {
+                    // Zero lifetime deletes the mapping
+                    let _ = self.map_port(internal_port, 0).await;
+                    return;
+                }
+                _ = runtime::sleep(renew_after) => {}
+            }
+
+            if let Ok((external_port, granted)) = self.map_port(internal_port, self.lifetime_secs()).await {
+                // Public address of the gateway may change between renewals
+                if let Ok(ip) = self.external_ip().await {
+                    external_addr.write().unwrap().set_ip(ip.into());
+                }
+                external_addr.write().unwrap().set_port(external_port);
+                lifetime = granted;
+            }
+        }
+    }
+}
+
+impl PortMapping {
+    /// Returns local port of the mapping
+    pub fn internal_port(&self) -> u16 {
+        self.internal_port
+    }
+
+    /// Returns address which peers outside of the NAT should connect to
+    ///
+    /// The address may change after renewal
+    pub fn external_addr(&self) -> SocketAddr {
+        *self.external_addr.read().unwrap()
+    }
+
+    // Shared with the renewal task, so it follows the port changes
+    pub(crate) fn shared_external_addr(&self) -> Arc<RwLock<SocketAddr>> {
+        self.external_addr.clone()
+    }
+}
+
+impl Drop for PortMapping {
+    fn drop(&mut self) {
+        self.close_notifier.notify_one();
+    }
+}
+
+// Sends the request to the NAT-PMP server, retrying with doubled timeouts
+async fn nat_pmp_request(gateway: SocketAddr, request: &[u8], response_len: usize) -> io::Result<Vec<u8>> {
+    let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))).await?;
+    socket.connect(gateway).await?;
+
+    let mut response = vec![0; response_len];
+    let mut timeout = INITIAL_REQUEST_TIMEOUT;
+
+    for _ in 0..REQUEST_ATTEMPTS {
+        socket.send(request).await?;
+
+        if let Ok(received) = runtime::timeout(timeout, socket.recv(&mut response)).await {
+            if received? < response_len || response[1] != request[1] + RESPONSE_OPCODE_OFFSET {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid NAT-PMP response"));
+            }
+
+            return match u16::from_be_bytes([response[2], response[3]]) {
+                0 => Ok(response),
+                code => Err(io::Error::other(format!("NAT-PMP request failed with code {}", code))),
+            };
+        }
+        timeout *= 2;
+    }
+
+    Err(io::Error::new(io::ErrorKind::TimedOut, "NAT-PMP server didn't respond"))
+}
@@ -0,0 +1,66 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::net::lookup_host;
+
+/// Resolves host names to addresses, see [`ConnConfig::set_resolver()`]
+///
+/// Called on every [`Conn::connect_host()`], so addresses are never
+/// cached between connections and reconnects follow DNS changes
+///
+/// # Example
+///
+/// Resolves service names with a static table:
+///
+/// ```
+/// use std::collections::HashMap;
+/// use std::io;
+/// use std::net::SocketAddr;
+///
+/// use async_trait::async_trait;
+/// use cobra_rs::net::Resolver;
+///
+/// struct StaticResolver {
+///     services: HashMap<String, Vec<SocketAddr>>,
+/// }
+///
+/// #[async_trait]
+/// impl Resolver for StaticResolver {
+///     async fn resolve(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+///         self.services.get(host)
+///             .cloned()
+///             .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown service"))
+///     }
+/// }
+/// ```
+///
+/// [`ConnConfig::set_resolver()`]: crate::transport::tcp::ConnConfig::set_resolver
+/// [`Conn::connect_host()`]: crate::transport::tcp::Conn::connect_host
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    /// Returns addresses of `host` in the order they are tried
+    ///
+    /// `host` is passed as given by the application, usually `name:port`
+    async fn resolve(&self, host: &str) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// Resolves hosts with the resolver of the operating system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+#[async_trait]
+impl Resolver for SystemResolver {
+    async fn resolve(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        Ok(lookup_host(host).await?.collect())
+    }
+}
+
+/// Lets one resolver serve several configs
+#[async_trait]
+impl<R: Resolver + ?Sized> Resolver for Arc<R> {
+    async fn resolve(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        self.as_ref().resolve(host).await
+    }
+}
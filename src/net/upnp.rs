@@ -0,0 +1,220 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+use crate::runtime;
+
+const SSDP_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 255, 255, 250)), 1900);
+const GATEWAY_DEVICE: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+// Services of the IGD which map ports
+const CONNECTION_SERVICES: [&str; 2] = ["WANIPConnection", "WANPPPConnection"];
+
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(2);
+const SEARCH_ATTEMPTS: u32 = 2;
+
+/// Connection service of the UPnP Internet Gateway Device
+#[derive(Debug, Clone)]
+pub(crate) struct Service {
+    control_addr: SocketAddr,
+    control_path: String,
+    service_type: String,
+}
+
+/// Finds the gateway via SSDP, returns the URL of its description
+pub(crate) async fn search() -> io::Result<String> {
+    let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))).await?;
+    let request = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\nST: {}\r\n\r\n",
+        SSDP_ADDR,
+        SEARCH_TIMEOUT.as_secs(),
+        GATEWAY_DEVICE,
+    );
+
+    let mut response = vec![0; 2048];
+    for _ in 0..SEARCH_ATTEMPTS {
+        socket.send_to(request.as_bytes(), SSDP_ADDR).await?;
+
+        if let Ok(received) = runtime::timeout(SEARCH_TIMEOUT, socket.recv(&mut response)).await {
+            let response = String::from_utf8_lossy(&response[..received?]);
+            return header(&response, "location")
+                .map(str::to_string)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "SSDP response has no location"));
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::TimedOut, "UPnP gateway wasn't found"))
+}
+
+impl Service {
+    /// Reads the gateway description at `location` and finds its connection service
+    pub(crate) async fn from_location(location: &str) -> io::Result<Self> {
+        let (addr, path) = parse_url(location)?;
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, addr);
+        let description = http(addr, request).await?;
+
+        for service in description.split("<service>").skip(1) {
+            let service_type = match tag(service, "serviceType") {
+                Some(service_type) => service_type,
+                None => continue,
+            };
+            if !CONNECTION_SERVICES.iter().any(|name| service_type.contains(name)) {
+                continue;
+            }
+
+            let control_url = tag(service, "controlURL")
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "UPnP service has no control URL"))?;
+            let (control_addr, control_path) = match control_url.starts_with("http://") {
+                true => parse_url(control_url)?,
+                false => (addr, format!("/{}", control_url.trim_start_matches('/'))),
+            };
+
+            return Ok(Service {
+                control_addr,
+                control_path,
+                service_type: service_type.to_string(),
+            });
+        }
+
+        Err(io::Error::new(io::ErrorKind::InvalidData, "gateway has no connection service"))
+    }
+
+    pub(crate) async fn external_ip(&self) -> io::Result<Ipv4Addr> {
+        let response = self.call("GetExternalIPAddress", "").await?;
+        tag(&response, "NewExternalIPAddress")
+            .and_then(|ip| ip.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid UPnP response"))
+    }
+
+    /// Maps the same external TCP port to the local port
+    ///
+    /// The mapping points to the address this host uses to reach the gateway
+    pub(crate) async fn add_mapping(&self, port: u16, lifetime: u32) -> io::Result<()> {
+        // Connecting UDP socket sends nothing, but picks the local address
+        let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))).await?;
+        socket.connect(self.control_addr).await?;
+        let local_ip = socket.local_addr()?.ip();
+
+        let arguments = format!(
+            "<NewRemoteHost></NewRemoteHost>\
+            <NewExternalPort>{port}</NewExternalPort>\
+            <NewProtocol>TCP</NewProtocol>\
+            <NewInternalPort>{port}</NewInternalPort>\
+            <NewInternalClient>{}</NewInternalClient>\
+            <NewEnabled>1</NewEnabled>\
+            <NewPortMappingDescription>cobra</NewPortMappingDescription>\
+            <NewLeaseDuration>{}</NewLeaseDuration>",
+            local_ip,
+            lifetime,
+            port = port,
+        );
+        self.call("AddPortMapping", &arguments).await.map(|_| ())
+    }
+
+    pub(crate) async fn delete_mapping(&self, port: u16) -> io::Result<()> {
+        let arguments = format!(
+            "<NewRemoteHost></NewRemoteHost>\
+            <NewExternalPort>{}</NewExternalPort>\
+            <NewProtocol>TCP</NewProtocol>",
+            port,
+        );
+        self.call("DeletePortMapping", &arguments).await.map(|_| ())
+    }
+
+    async fn call(&self, action: &str, arguments: &str) -> io::Result<String> {
+        let body = format!(
+            "<?xml version=\"1.0\"?>\
+            <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+            s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+            <s:Body><u:{action} xmlns:u=\"{service}\">{}</u:{action}></s:Body></s:Envelope>",
+            arguments,
+            action = action,
+            service = self.service_type,
+        );
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/xml; charset=\"utf-8\"\r\n\
+            SOAPAction: \"{}#{}\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.control_path,
+            self.control_addr,
+            self.service_type,
+            action,
+            body.len(),
+            body,
+        );
+
+        http(self.control_addr, request).await
+    }
+}
+
+// Sends the request, returns body of the successful response
+async fn http(addr: SocketAddr, request: String) -> io::Result<String> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    let response = String::from_utf8_lossy(&response);
+
+    let (head, body) = response.split_once("\r\n\r\n")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid HTTP response"))?;
+    let status = head.split(' ').nth(1).unwrap_or_default();
+    if status != "200" {
+        return Err(io::Error::other(format!("UPnP request failed with status {}", status)));
+    }
+
+    match header(head, "transfer-encoding") {
+        Some(encoding) if encoding.eq_ignore_ascii_case("chunked") => dechunk(body),
+        _ => Ok(body.to_string()),
+    }
+}
+
+fn dechunk(mut body: &str) -> io::Result<String> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid HTTP chunk");
+    let mut decoded = String::new();
+
+    loop {
+        let (size, rest) = body.split_once("\r\n").ok_or_else(invalid)?;
+        let size = usize::from_str_radix(size.split(';').next().unwrap().trim(), 16).map_err(|_| invalid())?;
+        if size == 0 {
+            return Ok(decoded);
+        }
+
+        decoded.push_str(rest.get(..size).ok_or_else(invalid)?);
+        body = rest[size..].trim_start_matches("\r\n");
+    }
+}
+
+// Returns address and path of the `http://` URL, the host has to be an IP address
+fn parse_url(url: &str) -> io::Result<(SocketAddr, String)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported URL {}", url));
+
+    let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+    let (host, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let addr = match host.parse() {
+        Ok(addr) => addr,
+        Err(_) => SocketAddr::new(host.parse().map_err(|_| invalid())?, 80),
+    };
+
+    Ok((addr, path.to_string()))
+}
+
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+// Returns text of the first element with the name, namespace prefixes are ignored
+fn tag<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let (open, _) = xml.match_indices(&format!("{}>", name))
+        .find(|&(index, _)| matches!(xml[..index].chars().last(), Some('<') | Some(':')))?;
+    let start = open + name.len() + 1;
+    let end = start + xml[start..].find('<')?;
+    Some(xml[start..end].trim())
+}
@@ -0,0 +1,297 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+
+use crate::builder::builder::{BuildError, Builder, ConnProvider};
+use crate::builder::kind_conn::KindConn;
+use crate::discovery::searcher::Searcher;
+use crate::runtime;
+use crate::transport::stream::StreamConn;
+use crate::transport::tcp::{Conn, ConnConfig};
+use crate::transport::udp;
+
+const DEFAULT_PUNCH_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
+// Sent by the initiator over the path it takes
+const PATH_CONFIRMED: u8 = 1;
+
+/// Settings of the peer-to-peer connection
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use cobra_rs::p2p::PunchConfig;
+///
+/// let config = PunchConfig::new()
+///     .set_timeout(Duration::from_secs(5));
+/// ```
+#[derive(Clone)]
+pub struct PunchConfig {
+    timeout: Duration,
+    retry_interval: Duration,
+    initiator: Option<bool>,
+    tcp: bool,
+    udp: bool,
+    conn: ConnConfig,
+}
+
+/// Error returned by [`connect()`]
+///
+/// [`connect()`]: crate::p2p::connect
+#[derive(Debug)]
+pub enum P2pError {
+    /// Peer wasn't reached in time or the socket couldn't be bound
+    Io(io::Error),
+
+    /// Connection was established, but the handshake failed
+    Build(BuildError),
+}
+
+impl PunchConfig {
+    /// Creates config with default settings
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets how long to try reaching the peer, 10 seconds by default
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets delay between connection attempts, 200 ms by default
+    pub fn set_retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// Sets role of this peer
+    ///
+    /// Only the responder accepts connections, so exactly one
+    /// connection is used by both peers. By default the peer with
+    /// the lower address is the initiator, which is consistent
+    /// only if both peers see the same addresses (e.g. in LAN)
+    pub fn set_initiator(mut self, initiator: bool) -> Self {
+        self.initiator = Some(initiator);
+        self
+    }
+
+    /// Enables TCP simultaneous open in [`open()`], enabled by default
+    ///
+    /// [`open()`]: crate::p2p::open
+    pub fn set_tcp(mut self, enabled: bool) -> Self {
+        self.tcp = enabled;
+        self
+    }
+
+    /// Enables UDP hole punching in [`open()`], enabled by default
+    ///
+    /// Frames go over a reliable stream on top of the punched UDP mapping,
+    /// which passes NATs dropping unsolicited TCP segments
+    ///
+    /// [`open()`]: crate::p2p::open
+    pub fn set_udp(mut self, enabled: bool) -> Self {
+        self.udp = enabled;
+        self
+    }
+
+    /// Sets settings of the established connection
+    pub fn set_conn_config(mut self, config: ConnConfig) -> Self {
+        self.conn = config;
+        self
+    }
+}
+
+impl Default for PunchConfig {
+    fn default() -> Self {
+        PunchConfig {
+            timeout: DEFAULT_PUNCH_TIMEOUT,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            initiator: None,
+            tcp: true,
+            udp: true,
+            conn: Default::default(),
+        }
+    }
+}
+
+impl From<io::Error> for P2pError {
+    fn from(error: io::Error) -> Self {
+        P2pError::Io(error)
+    }
+}
+
+impl From<BuildError> for P2pError {
+    fn from(error: BuildError) -> Self {
+        P2pError::Build(error)
+    }
+}
+
+/// Connects two peers which dial each other
+///
+/// Both peers repeatedly connect from `local` to `peer`, so outgoing
+/// packets open mappings in their NATs. The responder also accepts
+/// on `local`, connection is established by whichever path succeeds
+/// first: accepted connection or TCP simultaneous open
+///
+/// # Note
+///
+/// Only TCP is tried, see [`open()`] for UDP hole punching
+///
+/// [`open()`]: crate::p2p::open
+pub async fn simultaneous_open(local: SocketAddr, peer: SocketAddr, config: PunchConfig) -> io::Result<Conn> {
+    let initiator = config.is_initiator(local, peer);
+    let stream = runtime::timeout(config.timeout, open_tcp(local, peer, initiator, config.retry_interval)).await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "peer wasn't reached"))??;
+
+    Ok(Conn::from_raw_with_config(stream, config.conn))
+}
+
+/// Connects two peers by whichever path succeeds first: TCP simultaneous
+/// open (see [`simultaneous_open()`]) or UDP hole punching
+///
+/// For UDP both peers send probes from `local` to `peer` until a probe
+/// of the other peer arrives, then frames go over a reliable stream on
+/// top of the mapping. The initiator takes the first path reached and
+/// confirms it with one byte, the responder takes the path confirmed,
+/// so both peers end up on the same path. Paths are chosen with
+/// [`PunchConfig::set_tcp()`] and [`PunchConfig::set_udp()`]
+///
+/// [`simultaneous_open()`]: crate::p2p::simultaneous_open
+/// [`PunchConfig::set_tcp()`]: crate::p2p::PunchConfig::set_tcp
+/// [`PunchConfig::set_udp()`]: crate::p2p::PunchConfig::set_udp
+pub async fn open(local: SocketAddr, peer: SocketAddr, config: PunchConfig) -> io::Result<Box<dyn ConnProvider>> {
+    let initiator = config.is_initiator(local, peer);
+
+    let tcp = async {
+        if !config.tcp {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "TCP is disabled"));
+        }
+        let mut stream = open_tcp(local, peer, initiator, config.retry_interval).await?;
+        confirm(&mut stream, initiator).await?;
+        Ok(Box::new(Conn::from_raw_with_config(stream, config.conn.clone())) as Box<dyn ConnProvider>)
+    };
+
+    let udp = async {
+        if !config.udp {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "UDP is disabled"));
+        }
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(peer).await?;
+        udp::punch(&socket, config.retry_interval).await;
+
+        let runtime = config.conn.runtime.clone();
+        let mut stream = udp::stream(socket, runtime, config.retry_interval, config.timeout);
+        confirm(&mut stream, initiator).await?;
+        Ok(Box::new(StreamConn::with_config(stream, config.conn.clone())) as Box<dyn ConnProvider>)
+    };
+
+    runtime::timeout(config.timeout, first_ok(tcp, udp)).await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "peer wasn't reached"))?
+}
+
+/// Connects to the peer with [`open()`] and builds the connection
+///
+/// [`open()`]: crate::p2p::open
+pub async fn connect(local: SocketAddr, peer: SocketAddr, config: PunchConfig, builder: Builder) -> Result<KindConn, P2pError> {
+    let conn = open(local, peer, config).await?;
+    Ok(builder.set_conn(conn).run().await?)
+}
+
+/// Waits until [`Searcher`] finds a peer and connects to it with [`connect()`]
+///
+/// The peer is reached at the endpoint it announces (see
+/// [`Listener::announce()`]). Otherwise peers are expected to use the
+/// same port, the address of the peer is the discovered address with
+/// the port of `local`
+///
+/// [`Searcher`]: crate::discovery::searcher::Searcher
+/// [`connect()`]: crate::p2p::connect
+/// [`Listener::announce()`]: crate::discovery::listener::Listener::announce
+pub async fn connect_discovered(searcher: &Searcher, local: SocketAddr, config: PunchConfig, builder: Builder) -> Result<KindConn, P2pError> {
+    let answer = searcher.scan_answer().await;
+    let peer = answer.endpoint.unwrap_or_else(|| SocketAddr::new(answer.addr.ip(), local.port()));
+    connect(local, peer, config, builder).await
+}
+
+impl PunchConfig {
+    // By default the peer with the lower address initiates
+    fn is_initiator(&self, local: SocketAddr, peer: SocketAddr) -> bool {
+        self.initiator.unwrap_or((local.ip(), local.port()) < (peer.ip(), peer.port()))
+    }
+}
+
+// Returns the first connection established or the error of the path failed last
+async fn first_ok<T>(first: impl Future<Output=io::Result<T>>, second: impl Future<Output=io::Result<T>>) -> io::Result<T> {
+    tokio::pin!(first, second);
+    let mut error = None;
+    let (mut first_failed, mut second_failed) = (false, false);
+
+    loop {
+        tokio::select! {
+            result = &mut first, if !first_failed => match result {
+                Ok(conn) => return Ok(conn),
+                Err(failure) => (first_failed, error) = (true, Some(failure)),
+            },
+            result = &mut second, if !second_failed => match result {
+                Ok(conn) => return Ok(conn),
+                Err(failure) => (second_failed, error) = (true, Some(failure)),
+            },
+            else => return Err(error.unwrap()),
+        }
+    }
+}
+
+// Initiator confirms the path it takes, responder waits for the confirmation
+async fn confirm<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, initiator: bool) -> io::Result<()> {
+    if initiator {
+        return stream.write_all(&[PATH_CONFIRMED]).await;
+    }
+
+    let mut confirmation = [0];
+    stream.read_exact(&mut confirmation).await?;
+    match confirmation[0] {
+        PATH_CONFIRMED => Ok(()),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "path wasn't confirmed")),
+    }
+}
+
+async fn open_tcp(local: SocketAddr, peer: SocketAddr, initiator: bool, retry_interval: Duration) -> io::Result<TcpStream> {
+    if initiator {
+        return dial(local, peer, retry_interval).await;
+    }
+
+    let listener = bind(local)?.listen(1)?;
+    tokio::select! {
+        accepted = listener.accept() => accepted.map(|(stream, _)| stream),
+        dialed = dial(local, peer, retry_interval) => dialed,
+    }
+}
+
+fn bind(local: SocketAddr) -> io::Result<TcpSocket> {
+    let socket = match local {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(true)?;
+    #[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+    socket.set_reuseport(true)?;
+    socket.bind(local)?;
+
+    Ok(socket)
+}
+
+async fn dial(local: SocketAddr, peer: SocketAddr, retry_interval: Duration) -> io::Result<TcpStream> {
+    loop {
+        if let Ok(stream) = bind(local)?.connect(peer).await {
+            return Ok(stream);
+        }
+        runtime::sleep(retry_interval).await;
+    }
+}
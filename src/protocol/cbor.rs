@@ -0,0 +1,185 @@
+use std::convert::TryInto;
+
+// Major types used by control messages
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const TAG: u8 = 6;
+const SIMPLE: u8 = 7;
+
+// Additional info of definite lengths encoded in the following bytes
+const ONE_BYTE: u8 = 24;
+const EIGHT_BYTES: u8 = 27;
+
+// Nesting of skipped values, deeper ones are treated as malformed
+const MAX_DEPTH: usize = 16;
+
+/// Writes the subset of CBOR (RFC 8949) used by control messages
+///
+/// Only definite lengths are written, integers use the shortest form
+pub(crate) struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn new() -> Self {
+        Encoder { buf: Vec::new() }
+    }
+
+    fn header(&mut self, major: u8, value: u64) -> &mut Self {
+        let major = major << 5;
+        match value {
+            0..=23 => self.buf.push(major | value as u8),
+            24..=0xFF => self.buf.extend_from_slice(&[major | ONE_BYTE, value as u8]),
+            0x100..=0xFFFF => {
+                self.buf.push(major | (ONE_BYTE + 1));
+                self.buf.extend_from_slice(&(value as u16).to_be_bytes());
+            }
+            0x10000..=0xFFFF_FFFF => {
+                self.buf.push(major | (ONE_BYTE + 2));
+                self.buf.extend_from_slice(&(value as u32).to_be_bytes());
+            }
+            _ => {
+                self.buf.push(major | EIGHT_BYTES);
+                self.buf.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        self
+    }
+
+    pub(crate) fn uint(&mut self, value: u64) -> &mut Self {
+        self.header(UNSIGNED, value)
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.header(BYTES, value.len() as u64);
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub(crate) fn text(&mut self, value: &str) -> &mut Self {
+        self.header(TEXT, value.len() as u64);
+        self.buf.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    pub(crate) fn map(&mut self, len: usize) -> &mut Self {
+        self.header(MAP, len as u64)
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads values written by [`Encoder`] and skips any other definite-length values
+///
+/// Every method returns [`None`] on malformed input, lengths are checked
+/// against the remaining bytes before anything is allocated
+///
+/// [`Encoder`]: crate::protocol::cbor::Encoder
+/// [`None`]: std::option::Option::None
+pub(crate) struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Decoder { buf }
+    }
+
+    // Returns major type and argument of the next value
+    fn header(&mut self) -> Option<(u8, u64)> {
+        let (&initial, rest) = self.buf.split_first()?;
+        let (major, info) = (initial >> 5, initial & 0x1F);
+
+        let len = match info {
+            0..=23 => {
+                self.buf = rest;
+                return Some((major, info as u64));
+            }
+            ONE_BYTE..=EIGHT_BYTES => 1 << (info - ONE_BYTE),
+            // Reserved and indefinite lengths aren't used
+            _ => return None,
+        };
+
+        let bytes = rest.get(..len)?;
+        let mut value = [0; 8];
+        value[8 - len..].copy_from_slice(bytes);
+        self.buf = &rest[len..];
+        Some((major, u64::from_be_bytes(value)))
+    }
+
+    pub(crate) fn uint(&mut self) -> Option<u64> {
+        match self.header()? {
+            (UNSIGNED, value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn bytes(&mut self) -> Option<&'a [u8]> {
+        match self.header()? {
+            (BYTES, len) => self.take(len),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn text(&mut self) -> Option<&'a str> {
+        match self.header()? {
+            (TEXT, len) => std::str::from_utf8(self.take(len)?).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns number of pairs of the map
+    pub(crate) fn map(&mut self) -> Option<u64> {
+        match self.header()? {
+            (MAP, len) => Some(len),
+            _ => None,
+        }
+    }
+
+    /// Skips the next value of any type, used for unknown fields
+    pub(crate) fn skip(&mut self) -> Option<()> {
+        self.skip_nested(0)
+    }
+
+    fn skip_nested(&mut self, depth: usize) -> Option<()> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+
+        match self.header()? {
+            (UNSIGNED, _) | (NEGATIVE, _) | (SIMPLE, _) => {}
+            (BYTES, len) | (TEXT, len) => {
+                self.take(len)?;
+            }
+            (ARRAY, len) => self.skip_items(len, depth)?,
+            (MAP, len) => self.skip_items(len.checked_mul(2)?, depth)?,
+            (TAG, _) => self.skip_nested(depth + 1)?,
+            _ => unreachable!(),
+        }
+        Some(())
+    }
+
+    fn skip_items(&mut self, count: u64, depth: usize) -> Option<()> {
+        // Every value takes at least a byte
+        if count > self.buf.len() as u64 {
+            return None;
+        }
+        for _ in 0..count {
+            self.skip_nested(depth + 1)?;
+        }
+        Some(())
+    }
+
+    fn take(&mut self, len: u64) -> Option<&'a [u8]> {
+        let len: usize = len.try_into().ok()?;
+        let bytes = self.buf.get(..len)?;
+        self.buf = &self.buf[len..];
+        Some(bytes)
+    }
+}
@@ -0,0 +1,469 @@
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::time::Duration;
+
+use crate::builder::kind_conn::close_code::CloseCode;
+use crate::builder::stats::{CompressionStats, ConnStats, TransportStats};
+use crate::builder::version::Features;
+use crate::mem::KindWidth;
+use crate::protocol::cbor::{Decoder, Encoder};
+
+const KIND_RELEASED: u8 = 0;
+const KIND_RECLAIMED: u8 = 1;
+const CHANNEL_REQUEST: u8 = 2;
+const CHANNEL_OPEN: u8 = 3;
+const CHANNEL_EXHAUSTED: u8 = 4;
+const STATS_REQUEST: u8 = 5;
+const STATS_RESPONSE: u8 = 6;
+const STATS_REFUSED: u8 = 7;
+const HANDSHAKE_EXTENSIONS: u8 = 8;
+const KIND_CLOSED: u8 = 9;
+const CLOSE: u8 = 10;
+const SHUTDOWN_WRITE: u8 = 11;
+
+// Keys of message fields, shared by all message types
+const TYPE_KEY: u64 = 0;
+const KIND_KEY: u64 = 1;
+const NAME_KEY: u64 = 2;
+const ID_KEY: u64 = 3;
+const STATS_KEY: u64 = 4;
+const EXTENSIONS_KEY: u64 = 5;
+const CODE_KEY: u64 = 6;
+const REASON_KEY: u64 = 7;
+
+// Keys of stats fields
+const VERSION_KEY: u64 = 0;
+const WIDTH_KEY: u64 = 1;
+const IDLE_KEY: u64 = 2;
+const OPEN_KINDS_KEY: u64 = 3;
+const QUEUED_BYTES_KEY: u64 = 4;
+const QUEUED_FRAMES_KEY: u64 = 5;
+const PENDING_WRITES_KEY: u64 = 6;
+const RTT_KEY: u64 = 7;
+const COMPRESSION_KEY: u64 = 8;
+const EXPIRED_FRAMES_KEY: u64 = 9;
+
+// Keys of compression fields
+const INPUT_BYTES_KEY: u64 = 0;
+const OUTPUT_BYTES_KEY: u64 = 1;
+const COMPRESS_TIME_KEY: u64 = 2;
+const DECOMPRESS_TIME_KEY: u64 = 3;
+const ENABLED_KEY: u64 = 4;
+
+// Sent by fixed layouts instead of the round-trip time the transport doesn't know
+const UNKNOWN_RTT: u64 = u64::MAX;
+
+// Length of stats in fixed layouts
+const STATS_LEN: usize = 1 + 1 + 8 + 4 + 8 + 8 + 8 + 8;
+
+/// How control messages are encoded, chosen by the negotiated protocol version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// `[type: 1 byte][payload]` with a fixed payload layout per type
+    Fixed,
+
+    /// CBOR map of fields keyed by small integers, the type is under key `0`
+    ///
+    /// Unknown keys are skipped, so newer peers may add fields
+    /// without a new message type
+    Cbor,
+}
+
+impl Encoding {
+    /// Returns encoding of the protocol version with the features
+    pub fn of(features: Features) -> Self {
+        if features.structured_control {
+            Encoding::Cbor
+        } else {
+            Encoding::Fixed
+        }
+    }
+}
+
+/// Message sent on [`VERSION_KIND`] after the handshake
+///
+/// Carries kind release notices, channel directory messages and stats queries.
+/// The version frame keeps its fixed layout, it's parsed before the version
+/// is known. [`Close`] and [`ShutdownWrite`] are carried by transport control
+/// frames instead, see [`ControlFrame::encode_as()`]
+///
+/// [`VERSION_KIND`]: crate::builder::version::VERSION_KIND
+/// [`Close`]: crate::protocol::ControlMessage::Close
+/// [`ShutdownWrite`]: crate::protocol::ControlMessage::ShutdownWrite
+/// [`ControlFrame::encode_as()`]: crate::transport::control::ControlFrame::encode_as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlMessage {
+    /// Sender dropped the last handle of the kind
+    KindReleased { kind: u8 },
+
+    /// Sender's quarantine of the released kind is over
+    KindReclaimed { kind: u8 },
+
+    /// Sender won't write packages of the kind anymore
+    KindClosed { kind: u8 },
+
+    /// Sender asks the directory owner for the kind of the channel
+    ChannelRequest { name: String },
+
+    /// Directory owner assigned the kind to the channel
+    ChannelOpen { kind: u8, name: String },
+
+    /// Directory owner has no kinds left for the channel
+    ChannelExhausted { name: String },
+
+    /// Sender asks for stats of the connection
+    StatsRequest { id: u32 },
+
+    /// Stats of the sender
+    StatsResponse { id: u32, stats: ConnStats },
+
+    /// Sender doesn't expose its stats
+    StatsRefused { id: u32 },
+
+    /// Handshake extensions of the sender, sent once after the version frame,
+    /// see [`HandshakeExtension`]
+    ///
+    /// [`HandshakeExtension`]: crate::builder::handshake::HandshakeExtension
+    HandshakeExtensions { extensions: BTreeMap<String, Vec<u8>> },
+
+    /// Sender closes the connection, sent on [`CONTROL_KIND`] since version 10
+    ///
+    /// [`CONTROL_KIND`]: crate::transport::control::CONTROL_KIND
+    Close { code: CloseCode, reason: String },
+
+    /// Sender won't write frames anymore but keeps reading,
+    /// sent on [`CONTROL_KIND`] since version 10
+    ///
+    /// [`CONTROL_KIND`]: crate::transport::control::CONTROL_KIND
+    ShutdownWrite,
+}
+
+impl ControlMessage {
+    /// Encodes the message
+    pub fn encode(&self, encoding: Encoding) -> Vec<u8> {
+        match encoding {
+            Encoding::Fixed => self.encode_fixed(),
+            Encoding::Cbor => self.encode_cbor(),
+        }
+    }
+
+    /// Decodes the message
+    ///
+    /// Returns [`None`] if the message is malformed or of an unknown type
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn decode(message: &[u8], encoding: Encoding) -> Option<ControlMessage> {
+        match encoding {
+            Encoding::Fixed => ControlMessage::decode_fixed(message),
+            Encoding::Cbor => ControlMessage::decode_cbor(message),
+        }
+    }
+
+    fn message_type(&self) -> u8 {
+        match self {
+            ControlMessage::KindReleased { .. } => KIND_RELEASED,
+            ControlMessage::KindReclaimed { .. } => KIND_RECLAIMED,
+            ControlMessage::KindClosed { .. } => KIND_CLOSED,
+            ControlMessage::ChannelRequest { .. } => CHANNEL_REQUEST,
+            ControlMessage::ChannelOpen { .. } => CHANNEL_OPEN,
+            ControlMessage::ChannelExhausted { .. } => CHANNEL_EXHAUSTED,
+            ControlMessage::StatsRequest { .. } => STATS_REQUEST,
+            ControlMessage::StatsResponse { .. } => STATS_RESPONSE,
+            ControlMessage::StatsRefused { .. } => STATS_REFUSED,
+            ControlMessage::HandshakeExtensions { .. } => HANDSHAKE_EXTENSIONS,
+            ControlMessage::Close { .. } => CLOSE,
+            ControlMessage::ShutdownWrite => SHUTDOWN_WRITE,
+        }
+    }
+
+    fn encode_fixed(&self) -> Vec<u8> {
+        let mut message = vec![self.message_type()];
+
+        match self {
+            ControlMessage::KindReleased { kind }
+            | ControlMessage::KindReclaimed { kind }
+            | ControlMessage::KindClosed { kind } => message.push(*kind),
+            ControlMessage::ChannelRequest { name } | ControlMessage::ChannelExhausted { name } => {
+                message.extend_from_slice(name.as_bytes());
+            }
+            ControlMessage::ChannelOpen { kind, name } => {
+                message.push(*kind);
+                message.extend_from_slice(name.as_bytes());
+            }
+            ControlMessage::StatsRequest { id } | ControlMessage::StatsRefused { id } => {
+                message.extend_from_slice(&id.to_be_bytes());
+            }
+            ControlMessage::StatsResponse { id, stats } => {
+                message.extend_from_slice(&id.to_be_bytes());
+                encode_fixed_stats(stats, &mut message);
+            }
+            // Sent only since version 8, the fixed layout carries the same map
+            ControlMessage::HandshakeExtensions { extensions } => {
+                let mut encoder = Encoder::new();
+                encode_cbor_extensions(extensions, &mut encoder);
+                message.extend_from_slice(&encoder.finish());
+            }
+            ControlMessage::Close { code, reason } => {
+                message.push(code.code());
+                message.extend_from_slice(reason.as_bytes());
+            }
+            ControlMessage::ShutdownWrite => {}
+        }
+        message
+    }
+
+    fn decode_fixed(message: &[u8]) -> Option<ControlMessage> {
+        let name = |name: &[u8]| std::str::from_utf8(name).ok().map(str::to_string);
+        let id = |id: &[u8]| id.try_into().ok().map(u32::from_be_bytes);
+
+        match *message {
+            [KIND_RELEASED, kind] => Some(ControlMessage::KindReleased { kind }),
+            [KIND_RECLAIMED, kind] => Some(ControlMessage::KindReclaimed { kind }),
+            [KIND_CLOSED, kind] => Some(ControlMessage::KindClosed { kind }),
+            [CHANNEL_REQUEST, ref rest @ ..] => Some(ControlMessage::ChannelRequest { name: name(rest)? }),
+            [CHANNEL_OPEN, kind, ref rest @ ..] => Some(ControlMessage::ChannelOpen { kind, name: name(rest)? }),
+            [CHANNEL_EXHAUSTED, ref rest @ ..] => Some(ControlMessage::ChannelExhausted { name: name(rest)? }),
+            [STATS_REQUEST, ref rest @ ..] => Some(ControlMessage::StatsRequest { id: id(rest)? }),
+            [STATS_RESPONSE, ref rest @ ..] if rest.len() >= 4 => Some(ControlMessage::StatsResponse {
+                id: id(&rest[..4])?,
+                stats: decode_fixed_stats(&rest[4..])?,
+            }),
+            [STATS_REFUSED, ref rest @ ..] => Some(ControlMessage::StatsRefused { id: id(rest)? }),
+            [HANDSHAKE_EXTENSIONS, ref rest @ ..] => Some(ControlMessage::HandshakeExtensions {
+                extensions: decode_cbor_extensions(&mut Decoder::new(rest))?,
+            }),
+            [CLOSE, code, ref rest @ ..] => Some(ControlMessage::Close { code: CloseCode::from(code), reason: name(rest)? }),
+            [SHUTDOWN_WRITE] => Some(ControlMessage::ShutdownWrite),
+            _ => None,
+        }
+    }
+
+    fn encode_cbor(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+
+        match self {
+            ControlMessage::KindReleased { kind }
+            | ControlMessage::KindReclaimed { kind }
+            | ControlMessage::KindClosed { kind } => {
+                encoder.map(2).uint(TYPE_KEY).uint(self.message_type() as u64);
+                encoder.uint(KIND_KEY).uint(*kind as u64);
+            }
+            ControlMessage::ChannelRequest { name } | ControlMessage::ChannelExhausted { name } => {
+                encoder.map(2).uint(TYPE_KEY).uint(self.message_type() as u64);
+                encoder.uint(NAME_KEY).text(name);
+            }
+            ControlMessage::ChannelOpen { kind, name } => {
+                encoder.map(3).uint(TYPE_KEY).uint(self.message_type() as u64);
+                encoder.uint(KIND_KEY).uint(*kind as u64);
+                encoder.uint(NAME_KEY).text(name);
+            }
+            ControlMessage::StatsRequest { id } | ControlMessage::StatsRefused { id } => {
+                encoder.map(2).uint(TYPE_KEY).uint(self.message_type() as u64);
+                encoder.uint(ID_KEY).uint(*id as u64);
+            }
+            ControlMessage::StatsResponse { id, stats } => {
+                encoder.map(3).uint(TYPE_KEY).uint(self.message_type() as u64);
+                encoder.uint(ID_KEY).uint(*id as u64);
+                encoder.uint(STATS_KEY);
+                encode_cbor_stats(stats, &mut encoder);
+            }
+            ControlMessage::HandshakeExtensions { extensions } => {
+                encoder.map(2).uint(TYPE_KEY).uint(self.message_type() as u64);
+                encoder.uint(EXTENSIONS_KEY);
+                encode_cbor_extensions(extensions, &mut encoder);
+            }
+            ControlMessage::Close { code, reason } => {
+                encoder.map(3).uint(TYPE_KEY).uint(self.message_type() as u64);
+                encoder.uint(CODE_KEY).uint(code.code() as u64);
+                encoder.uint(REASON_KEY).text(reason);
+            }
+            ControlMessage::ShutdownWrite => {
+                encoder.map(1).uint(TYPE_KEY).uint(self.message_type() as u64);
+            }
+        }
+        encoder.finish()
+    }
+
+    fn decode_cbor(message: &[u8]) -> Option<ControlMessage> {
+        let mut decoder = Decoder::new(message);
+        let (mut message_type, mut kind, mut name, mut id, mut stats) = (None, None, None, None, None);
+        let (mut extensions, mut reason) = (None, None);
+        let mut code: Option<u8> = None;
+
+        for _ in 0..decoder.map()? {
+            match decoder.uint()? {
+                TYPE_KEY => message_type = Some(decoder.uint()?),
+                KIND_KEY => kind = Some(decoder.uint()?.try_into().ok()?),
+                NAME_KEY => name = Some(decoder.text()?.to_string()),
+                ID_KEY => id = Some(decoder.uint()?.try_into().ok()?),
+                STATS_KEY => stats = Some(decode_cbor_stats(&mut decoder)?),
+                EXTENSIONS_KEY => extensions = Some(decode_cbor_extensions(&mut decoder)?),
+                CODE_KEY => code = Some(decoder.uint()?.try_into().ok()?),
+                REASON_KEY => reason = Some(decoder.text()?.to_string()),
+                _ => decoder.skip()?,
+            }
+        }
+
+        let message_type: u8 = message_type?.try_into().ok()?;
+        match message_type {
+            KIND_RELEASED => Some(ControlMessage::KindReleased { kind: kind? }),
+            KIND_RECLAIMED => Some(ControlMessage::KindReclaimed { kind: kind? }),
+            KIND_CLOSED => Some(ControlMessage::KindClosed { kind: kind? }),
+            CHANNEL_REQUEST => Some(ControlMessage::ChannelRequest { name: name? }),
+            CHANNEL_OPEN => Some(ControlMessage::ChannelOpen { kind: kind?, name: name? }),
+            CHANNEL_EXHAUSTED => Some(ControlMessage::ChannelExhausted { name: name? }),
+            STATS_REQUEST => Some(ControlMessage::StatsRequest { id: id? }),
+            STATS_RESPONSE => Some(ControlMessage::StatsResponse { id: id?, stats: stats? }),
+            STATS_REFUSED => Some(ControlMessage::StatsRefused { id: id? }),
+            HANDSHAKE_EXTENSIONS => Some(ControlMessage::HandshakeExtensions { extensions: extensions? }),
+            // Reason is optional
+            CLOSE => Some(ControlMessage::Close { code: CloseCode::from(code?), reason: reason.unwrap_or_default() }),
+            SHUTDOWN_WRITE => Some(ControlMessage::ShutdownWrite),
+            _ => None,
+        }
+    }
+}
+
+// Layout is `[version: 1 byte][width: 1 byte][idle ms: 8 bytes][open kinds: 4 bytes]
+// [queued bytes: 8 bytes][queued frames: 8 bytes][pending writes: 8 bytes][rtt us: 8 bytes]`
+fn encode_fixed_stats(stats: &ConnStats, buf: &mut Vec<u8>) {
+    let rtt = stats.transport.rtt.map_or(UNKNOWN_RTT, |rtt| rtt.as_micros().min(UNKNOWN_RTT as u128 - 1) as u64);
+
+    buf.push(stats.protocol_version);
+    buf.push(stats.kind_width.code());
+    buf.extend_from_slice(&(stats.idle.as_millis() as u64).to_be_bytes());
+    buf.extend_from_slice(&(stats.open_kinds as u32).to_be_bytes());
+    buf.extend_from_slice(&(stats.transport.queued_bytes as u64).to_be_bytes());
+    buf.extend_from_slice(&(stats.transport.queued_frames as u64).to_be_bytes());
+    buf.extend_from_slice(&(stats.transport.pending_writes as u64).to_be_bytes());
+    buf.extend_from_slice(&rtt.to_be_bytes());
+}
+
+// Stats of unknown versions or widths aren't decoded
+fn decode_fixed_stats(buf: &[u8]) -> Option<ConnStats> {
+    if buf.len() < STATS_LEN {
+        return None;
+    }
+    let u64_at = |offset: usize| u64::from_be_bytes(buf[offset..offset + 8].try_into().unwrap());
+    let rtt = u64_at(38);
+
+    Some(ConnStats {
+        protocol_version: buf[0],
+        features: Features::of(buf[0])?,
+        kind_width: KindWidth::from_code(buf[1])?,
+        idle: Duration::from_millis(u64_at(2)),
+        open_kinds: u32::from_be_bytes(buf[10..14].try_into().unwrap()) as usize,
+        transport: TransportStats {
+            queued_bytes: u64_at(14) as usize,
+            queued_frames: u64_at(22) as usize,
+            pending_writes: u64_at(30) as usize,
+            expired_frames: 0,
+            rtt: (rtt != UNKNOWN_RTT).then(|| Duration::from_micros(rtt)),
+        },
+        compression: None,
+    })
+}
+
+// Unknown round-trip time and compression are left out
+fn encode_cbor_stats(stats: &ConnStats, encoder: &mut Encoder) {
+    let rtt = stats.transport.rtt.map(|rtt| rtt.as_micros().min(u64::MAX as u128) as u64);
+
+    encoder.map(8 + rtt.is_some() as usize + stats.compression.is_some() as usize);
+    encoder.uint(VERSION_KEY).uint(stats.protocol_version as u64);
+    encoder.uint(WIDTH_KEY).uint(stats.kind_width.code() as u64);
+    encoder.uint(IDLE_KEY).uint(stats.idle.as_millis() as u64);
+    encoder.uint(OPEN_KINDS_KEY).uint(stats.open_kinds as u64);
+    encoder.uint(QUEUED_BYTES_KEY).uint(stats.transport.queued_bytes as u64);
+    encoder.uint(QUEUED_FRAMES_KEY).uint(stats.transport.queued_frames as u64);
+    encoder.uint(PENDING_WRITES_KEY).uint(stats.transport.pending_writes as u64);
+    encoder.uint(EXPIRED_FRAMES_KEY).uint(stats.transport.expired_frames);
+    if let Some(rtt) = rtt {
+        encoder.uint(RTT_KEY).uint(rtt);
+    }
+    if let Some(compression) = &stats.compression {
+        encoder.uint(COMPRESSION_KEY);
+        encode_cbor_compression(compression, encoder);
+    }
+}
+
+fn encode_cbor_compression(compression: &CompressionStats, encoder: &mut Encoder) {
+    let micros = |time: Duration| time.as_micros().min(u64::MAX as u128) as u64;
+
+    encoder.map(5);
+    encoder.uint(INPUT_BYTES_KEY).uint(compression.input_bytes);
+    encoder.uint(OUTPUT_BYTES_KEY).uint(compression.output_bytes);
+    encoder.uint(COMPRESS_TIME_KEY).uint(micros(compression.compress_time));
+    encoder.uint(DECOMPRESS_TIME_KEY).uint(micros(compression.decompress_time));
+    encoder.uint(ENABLED_KEY).uint(compression.enabled as u64);
+}
+
+// Version and width are required, missing counters are zero
+fn decode_cbor_stats(decoder: &mut Decoder) -> Option<ConnStats> {
+    let (mut version, mut width) = (None, None);
+    let mut stats = ConnStats {
+        protocol_version: 0,
+        features: Features::of(1)?,
+        kind_width: KindWidth::U8,
+        idle: Duration::ZERO,
+        open_kinds: 0,
+        transport: TransportStats::default(),
+        compression: None,
+    };
+
+    for _ in 0..decoder.map()? {
+        match decoder.uint()? {
+            VERSION_KEY => version = Some(decoder.uint()?.try_into().ok()?),
+            WIDTH_KEY => width = KindWidth::from_code(decoder.uint()?.try_into().ok()?),
+            IDLE_KEY => stats.idle = Duration::from_millis(decoder.uint()?),
+            OPEN_KINDS_KEY => stats.open_kinds = decoder.uint()?.try_into().ok()?,
+            QUEUED_BYTES_KEY => stats.transport.queued_bytes = decoder.uint()?.try_into().ok()?,
+            QUEUED_FRAMES_KEY => stats.transport.queued_frames = decoder.uint()?.try_into().ok()?,
+            PENDING_WRITES_KEY => stats.transport.pending_writes = decoder.uint()?.try_into().ok()?,
+            EXPIRED_FRAMES_KEY => stats.transport.expired_frames = decoder.uint()?,
+            RTT_KEY => stats.transport.rtt = Some(Duration::from_micros(decoder.uint()?)),
+            COMPRESSION_KEY => stats.compression = Some(decode_cbor_compression(decoder)?),
+            _ => decoder.skip()?,
+        }
+    }
+
+    stats.protocol_version = version?;
+    stats.features = Features::of(stats.protocol_version)?;
+    stats.kind_width = width?;
+    Some(stats)
+}
+
+// Missing counters are zero, compression is enabled unless told otherwise
+fn decode_cbor_compression(decoder: &mut Decoder) -> Option<CompressionStats> {
+    let mut compression = CompressionStats { enabled: true, ..CompressionStats::default() };
+
+    for _ in 0..decoder.map()? {
+        match decoder.uint()? {
+            INPUT_BYTES_KEY => compression.input_bytes = decoder.uint()?,
+            OUTPUT_BYTES_KEY => compression.output_bytes = decoder.uint()?,
+            COMPRESS_TIME_KEY => compression.compress_time = Duration::from_micros(decoder.uint()?),
+            DECOMPRESS_TIME_KEY => compression.decompress_time = Duration::from_micros(decoder.uint()?),
+            ENABLED_KEY => compression.enabled = decoder.uint()? != 0,
+            _ => decoder.skip()?,
+        }
+    }
+
+    Some(compression)
+}
+
+// Map of extension keys to payloads
+fn encode_cbor_extensions(extensions: &BTreeMap<String, Vec<u8>>, encoder: &mut Encoder) {
+    encoder.map(extensions.len());
+    for (key, payload) in extensions {
+        encoder.text(key).bytes(payload);
+    }
+}
+
+fn decode_cbor_extensions(decoder: &mut Decoder) -> Option<BTreeMap<String, Vec<u8>>> {
+    let mut extensions = BTreeMap::new();
+    for _ in 0..decoder.map()? {
+        let key = decoder.text()?.to_string();
+        extensions.insert(key, decoder.bytes()?.to_vec());
+    }
+
+    Some(extensions)
+}
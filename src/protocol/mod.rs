@@ -0,0 +1,4 @@
+pub use message::*;
+
+mod cbor;
+mod message;
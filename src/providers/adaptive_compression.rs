@@ -0,0 +1,244 @@
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use async_trait::async_trait;
+
+use crate::builder::builder::CompressionProvider;
+use crate::builder::context::Context;
+use crate::builder::dependency::Provider;
+use crate::builder::kind_conn::close_code::COMPRESSION_ERROR;
+use crate::builder::kind_conn::KindConn;
+use crate::builder::stats::CompressionStats;
+use crate::runtime;
+
+// Flags prefixing every package
+const STORED: u8 = 0;
+const COMPRESSED: u8 = 1;
+
+pub(crate) type CompressionToggledHandler = Arc<dyn Fn(&CompressionToggled) + Send + Sync>;
+
+/// Event emitted when [`AdaptiveCompression`] stops or resumes compressing packages
+///
+/// [`AdaptiveCompression`]: crate::providers::adaptive_compression::AdaptiveCompression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionToggled {
+    /// True if packages are compressed again
+    pub enabled: bool,
+    /// Ratio of the last window, `1.0` when compression is resumed
+    pub ratio: f64,
+}
+
+#[derive(Default)]
+struct Meter {
+    stats: CompressionStats,
+    window_frames: usize,
+    window_input: u64,
+    window_output: u64,
+    // Packages left to send uncompressed
+    stored_left: usize,
+}
+
+/// Wraps a compression provider and stops compressing packages it can't shrink
+///
+/// Every `window` packages the ratio of compressed to original bytes is
+/// checked, if it reaches `threshold` (already compressed payloads) the
+/// next `backoff` packages are sent as is. Counters are exposed through
+/// [`KindConn::stats()`]
+///
+/// # Note
+///
+/// Every package is prefixed with a flag byte, so both peers must wrap
+/// their providers. Package with an unknown flag closes the connection
+/// with [`COMPRESSION_ERROR`] code
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::providers::adaptive_compression::AdaptiveCompression;
+/// # struct Inner;
+/// # #[async_trait::async_trait]
+/// # impl cobra_rs::builder::builder::CompressionProvider for Inner {
+/// #     async fn init(&self, _: cobra_rs::builder::context::Context) {}
+/// #     fn compress(&self, frame: Vec<u8>) -> Vec<u8> { frame }
+/// #     fn decompress(&self, frame: Vec<u8>) -> Vec<u8> { frame }
+/// # }
+///
+/// let provider = AdaptiveCompression::new(Inner)
+///     .threshold(0.9)
+///     .on_change(|event| println!("compression enabled: {}", event.enabled));
+/// ```
+///
+/// [`KindConn::stats()`]: crate::builder::kind_conn::KindConn::stats
+/// [`COMPRESSION_ERROR`]: crate::builder::kind_conn::close_code::COMPRESSION_ERROR
+pub struct AdaptiveCompression<P: CompressionProvider> {
+    inner: P,
+    window: usize,
+    threshold: f64,
+    backoff: usize,
+    handler: Option<CompressionToggledHandler>,
+    meter: Mutex<Meter>,
+    conn: OnceLock<Arc<KindConn>>,
+}
+
+impl<P: CompressionProvider> AdaptiveCompression<P> {
+    /// Creates wrapper checking every 32 packages, disabling compression
+    /// at ratio 0.95 for 1024 packages
+    pub fn new(inner: P) -> Self {
+        AdaptiveCompression {
+            inner,
+            window: 32,
+            threshold: 0.95,
+            backoff: 1024,
+            handler: None,
+            meter: Mutex::new(Meter {
+                stats: CompressionStats { enabled: true, ..CompressionStats::default() },
+                ..Meter::default()
+            }),
+            conn: OnceLock::new(),
+        }
+    }
+
+    /// Sets number of packages the ratio is checked over
+    ///
+    /// # Note
+    ///
+    /// Panics if `window` is zero
+    pub fn window(mut self, window: usize) -> Self {
+        if window == 0 {
+            panic!("window is zero")
+        }
+
+        self.window = window;
+        self
+    }
+
+    /// Sets ratio at which compression is disabled
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Sets number of packages sent uncompressed before compression is tried again
+    pub fn backoff(mut self, backoff: usize) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets handler called with [`CompressionToggled`] event
+    ///
+    /// Handler is called while a package is encoded, so it must not block
+    ///
+    /// [`CompressionToggled`]: crate::providers::adaptive_compression::CompressionToggled
+    pub fn on_change<F: 'static + Fn(&CompressionToggled) + Send + Sync>(mut self, handler: F) -> Self {
+        self.handler = Some(Arc::new(handler));
+        self
+    }
+
+    fn emit(&self, event: &CompressionToggled) {
+        if let Some(handler) = &self.handler {
+            handler(event);
+        }
+    }
+
+    fn store(&self, frame: Vec<u8>, meter: &mut Meter) -> Vec<u8> {
+        meter.stats.input_bytes += frame.len() as u64;
+        meter.stats.output_bytes += frame.len() as u64 + 1;
+
+        let mut package = Vec::with_capacity(frame.len() + 1);
+        package.push(STORED);
+        package.extend_from_slice(&frame);
+        package
+    }
+
+    fn fail(&self) -> Vec<u8> {
+        if let Some(conn) = self.conn.get().cloned() {
+            runtime::spawn(async move { conn.close(COMPRESSION_ERROR).await });
+        }
+        Vec::new()
+    }
+}
+
+#[async_trait]
+impl<P: CompressionProvider> CompressionProvider for AdaptiveCompression<P> {
+    async fn init(&self, context: Context) {
+        // Taken before the inner provider to get the same kinds on both sides
+        let _ = self.conn.set(Arc::new(context.get_kind_conn().await));
+        self.inner.init(context).await
+    }
+
+    fn compress(&self, frame: Vec<u8>) -> Vec<u8> {
+        let mut meter = self.meter.lock().unwrap();
+        if meter.stored_left > 0 {
+            meter.stored_left -= 1;
+            let package = self.store(frame, &mut meter);
+            if meter.stored_left == 0 {
+                meter.stats.enabled = true;
+                drop(meter);
+                self.emit(&CompressionToggled { enabled: true, ratio: 1.0 });
+            }
+            return package;
+        }
+        // Packages are compressed concurrently
+        drop(meter);
+
+        let input = frame.len() as u64;
+        let started = Instant::now();
+        let compressed = self.inner.compress(frame);
+        let elapsed = started.elapsed();
+
+        if compressed.is_empty() {
+            // Inner provider failed and closes the connection
+            return compressed;
+        }
+
+        let output = compressed.len() as u64 + 1;
+        let mut meter = self.meter.lock().unwrap();
+        meter.stats.compress_time += elapsed;
+        meter.stats.input_bytes += input;
+        meter.stats.output_bytes += output;
+        meter.window_input += input;
+        meter.window_output += output;
+        meter.window_frames += 1;
+
+        if meter.window_frames >= self.window {
+            let ratio = meter.window_output as f64 / meter.window_input.max(1) as f64;
+            meter.window_frames = 0;
+            meter.window_input = 0;
+            meter.window_output = 0;
+
+            if ratio >= self.threshold && self.backoff > 0 {
+                meter.stored_left = self.backoff;
+                meter.stats.enabled = false;
+                drop(meter);
+                self.emit(&CompressionToggled { enabled: false, ratio });
+            }
+        }
+
+        let mut package = Vec::with_capacity(compressed.len() + 1);
+        package.push(COMPRESSED);
+        package.extend_from_slice(&compressed);
+        package
+    }
+
+    fn decompress(&self, frame: Vec<u8>) -> Vec<u8> {
+        match frame.split_first() {
+            Some((&STORED, package)) => package.to_vec(),
+            Some((&COMPRESSED, package)) => {
+                let started = Instant::now();
+                let package = self.inner.decompress(package.to_vec());
+                let elapsed = started.elapsed();
+                self.meter.lock().unwrap().stats.decompress_time += elapsed;
+                package
+            }
+            _ => self.fail(),
+        }
+    }
+
+    fn stats(&self) -> Option<CompressionStats> {
+        Some(self.meter.lock().unwrap().stats)
+    }
+
+    fn depends_on(&self) -> Vec<Provider> {
+        self.inner.depends_on()
+    }
+}
@@ -1,31 +1,61 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_trait::async_trait;
 use tokio::sync::RwLock;
-use tokio::time::timeout;
 
 use crate::builder::builder::PingProvider;
 use crate::builder::context::Context;
 use crate::builder::kind_conn::close_code::PING_TIMEOUT;
 use crate::builder::kind_conn::KindConn;
+use crate::runtime::timeout;
 
 pub struct DefaultPingProvider {
     long_duration: Duration,
     short_duration: Duration,
 }
 
+/// Current ping intervals of the connection
+///
+/// Stored in connection extensions, so intervals
+/// can be changed after the handshake
+#[derive(Clone)]
+pub struct PingIntervals {
+    inner: Arc<Mutex<(Duration, Duration)>>,
+}
+
+impl PingIntervals {
+    fn new(long_duration: Duration, short_duration: Duration) -> Self {
+        PingIntervals {
+            inner: Arc::new(Mutex::new((long_duration, short_duration))),
+        }
+    }
+
+    /// Returns long and short durations
+    pub fn get(&self) -> (Duration, Duration) {
+        *self.inner.lock().unwrap()
+    }
+
+    /// Sets long and short durations, used starting from the next ping cycle
+    pub fn set(&self, long_duration: Duration, short_duration: Duration) {
+        *self.inner.lock().unwrap() = (long_duration, short_duration);
+    }
+}
+
 #[async_trait]
 impl PingProvider for DefaultPingProvider {
     async fn init(&self, context: Context) {
+        let intervals = PingIntervals::new(self.long_duration, self.short_duration);
+        context.extensions().insert(intervals.clone());
+
         let conn = Arc::new(context.get_kind_conn().await);
         let alive = Arc::new(RwLock::new(true));
 
-        tokio::spawn(
+        context.spawn(
             DefaultPingProvider::read_loop(conn.clone(), alive.clone())
         );
-        tokio::spawn(
-            DefaultPingProvider::ping_loop(self.long_duration, self.short_duration, conn, alive)
+        context.spawn(
+            DefaultPingProvider::ping_loop(intervals, conn, alive)
         );
     }
 }
@@ -38,11 +68,12 @@ impl DefaultPingProvider {
         }
     }
 
-    async fn ping_loop(long_duration: Duration,
-                       short_duration: Duration,
+    async fn ping_loop(intervals: PingIntervals,
                        conn: Arc<KindConn>,
                        alive: Arc<RwLock<bool>>) {
         loop {
+            let (long_duration, short_duration) = intervals.get();
+
             // Если ошибка - то прошел таймаут и не было принято пакетов
             if timeout(long_duration, conn.readable()).await.is_err() {
                 *alive.write().await = false;
@@ -0,0 +1,199 @@
+use std::io::{self, Read, Write};
+use std::sync::{Arc, OnceLock};
+
+use async_trait::async_trait;
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+use flate2::Compression;
+
+use crate::builder::builder::CompressionProvider;
+use crate::builder::context::Context;
+use crate::builder::kind_conn::close_code::COMPRESSION_ERROR;
+use crate::builder::kind_conn::KindConn;
+use crate::runtime;
+
+/// Upper bound of a decompressed package, protects from decompression bombs
+const MAX_PACKAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// Container of deflate streams, every package is a complete stream
+///
+/// Chosen at handshake, see [`GzipCompressionProvider`]
+///
+/// [`GzipCompressionProvider`]: crate::providers::gzip_compression_provider::GzipCompressionProvider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlateFormat {
+    /// RFC 1952 with header and CRC32, what `gzip` reads and writes
+    Gzip,
+
+    /// RFC 1950 with header and Adler-32
+    Zlib,
+
+    /// RFC 1951 raw deflate without header
+    Deflate,
+}
+
+impl FlateFormat {
+    /// Formats in order of preference when both peers offer several of them
+    pub const ALL: [FlateFormat; 3] = [FlateFormat::Gzip, FlateFormat::Zlib, FlateFormat::Deflate];
+
+    pub const fn code(self) -> u8 {
+        match self {
+            FlateFormat::Gzip => 1,
+            FlateFormat::Zlib => 2,
+            FlateFormat::Deflate => 3,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        FlateFormat::ALL.iter().copied().find(|format| format.code() == code)
+    }
+}
+
+/// Compresses every package with deflate for peers speaking gzip, zlib or raw deflate
+///
+/// At handshake peers exchange formats they offer and use the common one
+/// coming first in [`FlateFormat::ALL`]. The chosen format is stored in
+/// connection extensions
+///
+/// # Note
+///
+/// Available with the `gzip` feature. Connection without a common format
+/// or with a package which can't be decompressed is closed with
+/// [`COMPRESSION_ERROR`] code
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::providers::gzip_compression_provider::{FlateFormat, GzipCompressionProvider};
+///
+/// // Peer understands only gzip
+/// let provider = GzipCompressionProvider::new(6)
+///     .formats(&[FlateFormat::Gzip]);
+/// ```
+///
+/// [`FlateFormat::ALL`]: crate::providers::gzip_compression_provider::FlateFormat::ALL
+/// [`COMPRESSION_ERROR`]: crate::builder::kind_conn::close_code::COMPRESSION_ERROR
+pub struct GzipCompressionProvider {
+    level: Compression,
+    formats: Vec<FlateFormat>,
+    format: OnceLock<FlateFormat>,
+    conn: OnceLock<Arc<KindConn>>,
+}
+
+impl GzipCompressionProvider {
+    /// Creates provider offering all formats with the compression level from 0 to 9
+    pub fn new(level: u32) -> Self {
+        GzipCompressionProvider {
+            level: Compression::new(level.min(9)),
+            formats: FlateFormat::ALL.to_vec(),
+            format: OnceLock::new(),
+            conn: OnceLock::new(),
+        }
+    }
+
+    /// Offers only the formats to the peer
+    ///
+    /// # Note
+    ///
+    /// Panics if `formats` is empty
+    pub fn formats(mut self, formats: &[FlateFormat]) -> Self {
+        if formats.is_empty() {
+            panic!("no formats offered")
+        }
+
+        self.formats = formats.to_vec();
+        self
+    }
+
+    fn format(&self) -> FlateFormat {
+        // Packages are encoded only after the handshake
+        *self.format.get_or_init(|| FlateFormat::ALL.iter().copied().find(|format| self.formats.contains(format)).unwrap())
+    }
+
+    fn encode(&self, frame: &[u8]) -> io::Result<Vec<u8>> {
+        match self.format() {
+            FlateFormat::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), self.level);
+                encoder.write_all(frame)?;
+                encoder.finish()
+            }
+            FlateFormat::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), self.level);
+                encoder.write_all(frame)?;
+                encoder.finish()
+            }
+            FlateFormat::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), self.level);
+                encoder.write_all(frame)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    fn decode(&self, frame: &[u8]) -> io::Result<Vec<u8>> {
+        let decoder: Box<dyn Read + '_> = match self.format() {
+            FlateFormat::Gzip => Box::new(GzDecoder::new(frame)),
+            FlateFormat::Zlib => Box::new(ZlibDecoder::new(frame)),
+            FlateFormat::Deflate => Box::new(DeflateDecoder::new(frame)),
+        };
+
+        let mut package = Vec::new();
+        decoder.take(MAX_PACKAGE_LEN as u64 + 1).read_to_end(&mut package)?;
+        if package.len() > MAX_PACKAGE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "package is too long"));
+        }
+        Ok(package)
+    }
+
+    fn fail(&self) -> Vec<u8> {
+        if let Some(conn) = self.conn.get().cloned() {
+            runtime::spawn(async move { conn.close(COMPRESSION_ERROR).await });
+        }
+        Vec::new()
+    }
+}
+
+#[async_trait]
+impl CompressionProvider for GzipCompressionProvider {
+    async fn init(&self, context: Context) {
+        let conn = Arc::new(context.get_kind_conn().await);
+        let _ = self.conn.set(conn.clone());
+
+        let offer = self.formats.iter().map(|format| format.code()).collect();
+        let peer_offer = match conn.write(offer).await {
+            Ok(()) => conn.read().await.unwrap_or_default(),
+            Err(_) => Vec::new(),
+        };
+
+        let format = FlateFormat::ALL.iter().copied().find(|format| {
+            self.formats.contains(format) && peer_offer.contains(&format.code())
+        });
+
+        match format {
+            Some(format) => {
+                context.extensions().insert(format);
+                let _ = self.format.set(format);
+            }
+            None => conn.close(COMPRESSION_ERROR).await,
+        }
+    }
+
+    fn compress(&self, frame: Vec<u8>) -> Vec<u8> {
+        match self.encode(&frame) {
+            Ok(package) => package,
+            Err(_) => self.fail(),
+        }
+    }
+
+    fn decompress(&self, frame: Vec<u8>) -> Vec<u8> {
+        match self.decode(&frame) {
+            Ok(package) => package,
+            Err(_) => self.fail(),
+        }
+    }
+
+    fn shared_key(&self) -> Option<u64> {
+        let format = self.format.get()?;
+        Some((format.code() as u64) << 32 | self.level.level() as u64)
+    }
+}
@@ -1 +1,7 @@
-pub mod default_ping_provider;
\ No newline at end of file
+pub mod adaptive_compression;
+pub mod default_ping_provider;
+#[cfg(feature = "gzip")]
+pub mod gzip_compression_provider;
+pub mod nonce;
+#[cfg(feature = "zstd")]
+pub mod zstd_compression_provider;
@@ -0,0 +1,114 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::builder::builder::DecryptError;
+
+/// Number of nonces tracked behind the highest received one
+pub const REPLAY_WINDOW_LEN: u64 = 128;
+
+/// Nonces of packages sent in one direction
+///
+/// Every nonce is used once, so a key is never
+/// used twice with the same nonce
+#[derive(Debug, Default)]
+pub struct NonceCounter {
+    next: AtomicU64,
+}
+
+/// Sliding window of received nonces
+///
+/// Accepts every nonce once. Nonces may arrive out of order (e.g. over
+/// a datagram transport), but not more than [`REPLAY_WINDOW_LEN`]
+/// behind the highest received nonce
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::builder::builder::DecryptError;
+/// use cobra_rs::providers::nonce::ReplayWindow;
+///
+/// let window = ReplayWindow::new();
+///
+/// assert_eq!(window.check(2), Ok(()));
+/// assert_eq!(window.check(1), Ok(()));
+/// assert_eq!(window.check(2), Err(DecryptError::Replayed));
+/// ```
+///
+/// [`REPLAY_WINDOW_LEN`]: crate::providers::nonce::REPLAY_WINDOW_LEN
+#[derive(Debug, Default)]
+pub struct ReplayWindow {
+    state: Mutex<Option<WindowState>>,
+}
+
+#[derive(Debug)]
+struct WindowState {
+    highest: u64,
+    // Bit N is set if nonce `highest - N` was received
+    received: u128,
+}
+
+impl NonceCounter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the next nonce, [`None`] if all nonces are used
+    ///
+    /// Keys must be rotated before that, see [`KindConn::rotate_keys()`]
+    ///
+    /// [`None`]: std::option::Option::None
+    /// [`KindConn::rotate_keys()`]: crate::builder::kind_conn::KindConn::rotate_keys
+    pub fn next(&self) -> Option<u64> {
+        self.next
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |nonce| nonce.checked_add(1))
+            .ok()
+    }
+
+    /// Starts counting from zero, used after key rotation
+    pub fn reset(&self) {
+        self.next.store(0, Ordering::SeqCst);
+    }
+}
+
+impl ReplayWindow {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Accepts the nonce if it wasn't received before
+    pub fn check(&self, nonce: u64) -> Result<(), DecryptError> {
+        let mut state = self.state.lock().unwrap();
+
+        let window = match state.as_mut() {
+            Some(window) => window,
+            None => {
+                *state = Some(WindowState { highest: nonce, received: 1 });
+                return Ok(());
+            }
+        };
+
+        if nonce > window.highest {
+            let shift = nonce - window.highest;
+            window.received = if shift >= REPLAY_WINDOW_LEN { 0 } else { window.received << shift };
+            window.received |= 1;
+            window.highest = nonce;
+            return Ok(());
+        }
+
+        let offset = window.highest - nonce;
+        if offset >= REPLAY_WINDOW_LEN {
+            return Err(DecryptError::TooOld);
+        }
+        if window.received & (1 << offset) != 0 {
+            return Err(DecryptError::Replayed);
+        }
+
+        window.received |= 1 << offset;
+        Ok(())
+    }
+
+    /// Forgets received nonces, used after key rotation
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = None;
+    }
+}
@@ -0,0 +1,220 @@
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use async_trait::async_trait;
+use zstd::bulk::{Compressor, Decompressor};
+use zstd::zstd_safe;
+
+use crate::builder::builder::CompressionProvider;
+use crate::builder::context::Context;
+use crate::builder::kind_conn::close_code::COMPRESSION_ERROR;
+use crate::builder::kind_conn::KindConn;
+use crate::runtime;
+
+/// Length of the decompressed package prefix
+const LEN_BYTES: usize = 4;
+
+/// Upper bound of a decompressed package, protects from decompression bombs
+const MAX_PACKAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// Dictionary improving compression of small similar packages
+///
+/// Both peers must have the same dictionary, it is chosen at
+/// handshake by its ID, see [`ZstdCompressionProvider::add_dictionary()`]
+///
+/// [`ZstdCompressionProvider::add_dictionary()`]: crate::providers::zstd_compression_provider::ZstdCompressionProvider::add_dictionary
+#[derive(Debug, Clone)]
+pub struct ZstdDictionary {
+    id: u32,
+    data: Arc<[u8]>,
+}
+
+/// ID of the dictionary chosen at handshake
+///
+/// Stored in connection extensions if both peers have a common dictionary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZstdDictionaryId(pub u32);
+
+/// Compresses every package with zstd
+///
+/// At handshake peers exchange IDs of their dictionaries and use the
+/// common dictionary with the highest ID, if there is one
+///
+/// # Note
+///
+/// Available with the `zstd` feature. Package which can't be
+/// decompressed closes the connection with [`COMPRESSION_ERROR`] code
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::providers::zstd_compression_provider::{ZstdCompressionProvider, ZstdDictionary};
+///
+/// let samples: Vec<Vec<u8>> = (0..1000)
+///     .map(|i| format!("{{\"user\":{},\"action\":\"move\",\"x\":{}}}", i % 10, i).into_bytes())
+///     .collect();
+/// let dictionary = ZstdDictionary::train(&samples, 4096).unwrap();
+///
+/// let provider = ZstdCompressionProvider::new(3)
+///     .add_dictionary(dictionary);
+/// ```
+///
+/// [`COMPRESSION_ERROR`]: crate::builder::kind_conn::close_code::COMPRESSION_ERROR
+pub struct ZstdCompressionProvider {
+    level: i32,
+    dictionaries: Vec<ZstdDictionary>,
+    codec: OnceLock<Codec>,
+    conn: OnceLock<Arc<KindConn>>,
+}
+
+struct Codec {
+    dictionary: Option<u32>,
+    compressor: Mutex<Compressor<'static>>,
+    decompressor: Mutex<Decompressor<'static>>,
+}
+
+impl ZstdDictionary {
+    /// Trains dictionary of at most `max_size` bytes on sample packages
+    pub fn train<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> io::Result<Self> {
+        ZstdDictionary::from_bytes(zstd::dict::from_samples(samples, max_size)?)
+    }
+
+    /// Loads dictionary saved with [`as_bytes()`]
+    ///
+    /// Returns error if the data has no dictionary ID
+    ///
+    /// [`as_bytes()`]: crate::providers::zstd_compression_provider::ZstdDictionary::as_bytes
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<Self> {
+        let id = zstd_safe::get_dict_id_from_dict(&data)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "dictionary has no ID"))?;
+
+        Ok(ZstdDictionary {
+            id: id.get(),
+            data: data.into(),
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl ZstdCompressionProvider {
+    /// Creates provider with the specified compression level
+    pub fn new(level: i32) -> Self {
+        ZstdCompressionProvider {
+            level,
+            dictionaries: Vec::new(),
+            codec: OnceLock::new(),
+            conn: OnceLock::new(),
+        }
+    }
+
+    /// Offers dictionary to the peer
+    pub fn add_dictionary(mut self, dictionary: ZstdDictionary) -> Self {
+        self.dictionaries.push(dictionary);
+        self
+    }
+
+    fn codec(&self) -> &Codec {
+        // Packages are encoded only after the handshake
+        self.codec.get_or_init(|| Codec::new(self.level, None).expect("zstd context"))
+    }
+
+    fn fail(&self) -> Vec<u8> {
+        if let Some(conn) = self.conn.get().cloned() {
+            runtime::spawn(async move { conn.close(COMPRESSION_ERROR).await });
+        }
+        Vec::new()
+    }
+}
+
+impl Codec {
+    fn new(level: i32, dictionary: Option<&ZstdDictionary>) -> io::Result<Self> {
+        let (compressor, decompressor) = match dictionary {
+            Some(dictionary) => (
+                Compressor::with_dictionary(level, dictionary.as_bytes())?,
+                Decompressor::with_dictionary(dictionary.as_bytes())?,
+            ),
+            None => (Compressor::new(level)?, Decompressor::new()?),
+        };
+
+        Ok(Codec {
+            dictionary: dictionary.map(ZstdDictionary::id),
+            compressor: Mutex::new(compressor),
+            decompressor: Mutex::new(decompressor),
+        })
+    }
+}
+
+#[async_trait]
+impl CompressionProvider for ZstdCompressionProvider {
+    async fn init(&self, context: Context) {
+        let conn = Arc::new(context.get_kind_conn().await);
+        let _ = self.conn.set(conn.clone());
+
+        let offer = self.dictionaries.iter()
+            .flat_map(|dictionary| dictionary.id.to_be_bytes())
+            .collect();
+        let peer_offer = match conn.write(offer).await {
+            Ok(()) => conn.read().await.unwrap_or_default(),
+            Err(_) => Vec::new(),
+        };
+
+        let dictionary = peer_offer
+            .chunks_exact(4)
+            .map(|id| u32::from_be_bytes([id[0], id[1], id[2], id[3]]))
+            .filter_map(|id| self.dictionaries.iter().find(|dictionary| dictionary.id == id))
+            .max_by_key(|dictionary| dictionary.id);
+
+        match Codec::new(self.level, dictionary) {
+            Ok(codec) => {
+                if let Some(id) = codec.dictionary {
+                    context.extensions().insert(ZstdDictionaryId(id));
+                }
+                let _ = self.codec.set(codec);
+            }
+            Err(_) => conn.close(COMPRESSION_ERROR).await,
+        }
+    }
+
+    fn compress(&self, frame: Vec<u8>) -> Vec<u8> {
+        let compressed = self.codec().compressor.lock().unwrap().compress(&frame);
+
+        match compressed {
+            Ok(compressed) => {
+                let mut package = Vec::with_capacity(LEN_BYTES + compressed.len());
+                package.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+                package.extend_from_slice(&compressed);
+                package
+            }
+            Err(_) => self.fail(),
+        }
+    }
+
+    fn decompress(&self, frame: Vec<u8>) -> Vec<u8> {
+        if frame.len() < LEN_BYTES {
+            return self.fail();
+        }
+
+        let len = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize;
+        if len > MAX_PACKAGE_LEN {
+            return self.fail();
+        }
+
+        let decompressed = self.codec().decompressor.lock().unwrap().decompress(&frame[LEN_BYTES..], len);
+        match decompressed {
+            Ok(package) if package.len() == len => package,
+            _ => self.fail(),
+        }
+    }
+
+    fn shared_key(&self) -> Option<u64> {
+        let codec = self.codec.get()?;
+        Some((codec.dictionary.unwrap_or(0) as u64) << 32 | self.level as u32 as u64)
+    }
+}
@@ -0,0 +1,139 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::rng::XorShift;
+use crate::runtime;
+
+const DEFAULT_MULTIPLIER: f64 = 2.0;
+const DEFAULT_JITTER: f64 = 0.5;
+
+/// Exponential backoff with jitter between attempts of an operation
+///
+/// Delay starts at `initial` and is multiplied after every failed attempt
+/// up to `max`. Jitter takes a random part of the delay off, so clients
+/// failing at once don't retry at once. Successful attempt resets the delay
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use cobra_rs::retry::Backoff;
+/// use cobra_rs::transport::tcp::Conn;
+///
+/// # async fn run() {
+/// let backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(10))
+///     .set_max_attempts(8);
+///
+/// let conn = Conn::connect_retry("127.0.0.1:5000", backoff).await.unwrap();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    multiplier: f64,
+    jitter: f64,
+    max_attempts: Option<u32>,
+    failures: u32,
+    rng: XorShift,
+}
+
+impl Backoff {
+    /// Creates backoff doubling the delay from `initial` up to `max`
+    /// with unlimited attempts
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Backoff {
+            initial,
+            max,
+            multiplier: DEFAULT_MULTIPLIER,
+            jitter: DEFAULT_JITTER,
+            max_attempts: None,
+            failures: 0,
+            rng: XorShift::from_clock(),
+        }
+    }
+
+    /// Creates backoff with the same delay between attempts and without jitter
+    pub fn constant(delay: Duration) -> Self {
+        Backoff::new(delay, delay)
+            .set_multiplier(1.0)
+            .set_jitter(0.0)
+    }
+
+    /// Sets factor the delay is multiplied by after every failure, 2 by default
+    pub fn set_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Sets part of the delay which is randomly taken off, 0.5 by default
+    ///
+    /// Clamped to `[0, 1]`, zero turns jitter off
+    pub fn set_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets number of attempts including the first one, unlimited by default
+    pub fn set_max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Returns number of failed attempts since the last reset
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Records failed attempt and returns delay before the next one
+    ///
+    /// Returns [`None`] if attempts are exhausted
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        self.failures = self.failures.saturating_add(1);
+        if self.max_attempts.is_some_and(|attempts| self.failures >= attempts) {
+            return None;
+        }
+
+        let exponent = (self.failures - 1).min(i32::MAX as u32) as i32;
+        let delay = self.initial.as_secs_f64() * self.multiplier.powi(exponent);
+        // Delay overflows after enough failures
+        let delay = if delay.is_finite() {
+            Duration::from_secs_f64(delay.min(self.max.as_secs_f64()))
+        } else {
+            self.max
+        };
+
+        Some(delay.mul_f64(1.0 - self.jitter * self.rng.next_f64()))
+    }
+
+    /// Starts counting delays and attempts from the beginning
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Runs the operation until it succeeds or attempts are exhausted
+    ///
+    /// Waits for [`next_delay()`] between attempts and resets
+    /// the backoff on success. Returns the error of the last attempt
+    ///
+    /// [`next_delay()`]: crate::retry::Backoff::next_delay
+    pub async fn retry<T, E, F, Fut>(&mut self, mut operation: F) -> Result<T, E>
+        where F: FnMut() -> Fut,
+              Fut: Future<Output=Result<T, E>> {
+        loop {
+            match operation().await {
+                Ok(value) => {
+                    self.reset();
+                    return Ok(value);
+                }
+                Err(error) => match self.next_delay() {
+                    Some(delay) => runtime::sleep(delay).await,
+                    None => return Err(error),
+                },
+            }
+        }
+    }
+}
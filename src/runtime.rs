@@ -0,0 +1,440 @@
+use std::any::Any;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::Poll;
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::Notify;
+use tokio::task::LocalSet;
+
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output=T> + Send>>;
+
+/// Async runtime used by the library to spawn worker tasks, create timers and sockets
+///
+/// By default all tasks run on tokio, see [`set_default_runtime()`] and
+/// [`ConnConfig::set_runtime()`] to replace it. With the `async-std` and
+/// `smol` features tasks may run on [`AsyncStdRuntime`] and [`SmolRuntime`]
+///
+/// # Note
+///
+/// Sockets are still driven by a tokio reactor. Runtimes without one create
+/// sockets in a background reactor, see [`connect()`] and [`bind()`]
+///
+/// [`set_default_runtime()`]: crate::runtime::set_default_runtime
+/// [`ConnConfig::set_runtime()`]: crate::transport::tcp::ConnConfig::set_runtime
+/// [`AsyncStdRuntime`]: crate::runtime::AsyncStdRuntime
+/// [`SmolRuntime`]: crate::runtime::SmolRuntime
+/// [`connect()`]: crate::runtime::Runtime::connect
+/// [`bind()`]: crate::runtime::Runtime::bind
+pub trait Runtime: Send + Sync {
+    /// Runs the future in the background
+    fn spawn(&self, future: BoxFuture<()>);
+
+    /// Returns a future that completes after `duration`
+    fn sleep(&self, duration: Duration) -> BoxFuture<()>;
+
+    /// Runs blocking work, e.g. file reads, without stalling the task workers
+    ///
+    /// By default the work runs on a new thread
+    fn spawn_blocking(&self, work: Box<dyn FnOnce() + Send>) {
+        std::thread::spawn(work);
+    }
+
+    /// Connects a socket of [`Conn::connect_with_config()`]
+    ///
+    /// By default the socket is registered in the ambient tokio reactor
+    ///
+    /// [`Conn::connect_with_config()`]: crate::transport::tcp::Conn::connect_with_config
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<io::Result<TcpStream>> {
+        Box::pin(TcpStream::connect(addr))
+    }
+
+    /// Binds a listener of [`Listener::listen_with_config()`]
+    ///
+    /// By default the listener is registered in the ambient tokio reactor
+    ///
+    /// [`Listener::listen_with_config()`]: crate::transport::tcp::Listener::listen_with_config
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<io::Result<TcpListener>> {
+        Box::pin(TcpListener::bind(addr))
+    }
+}
+
+/// [`Runtime`] running tasks on the ambient tokio runtime
+///
+/// [`Runtime`]: crate::runtime::Runtime
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioRuntime;
+
+impl Runtime for TokioRuntime {
+    fn spawn(&self, future: BoxFuture<()>) {
+        tokio::spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    fn spawn_blocking(&self, work: Box<dyn FnOnce() + Send>) {
+        // Files may be written from tasks of other runtimes
+        match Handle::try_current() {
+            Ok(handle) => drop(handle.spawn_blocking(work)),
+            Err(_) => drop(std::thread::spawn(work)),
+        }
+    }
+}
+
+/// [`Runtime`] running tasks on the specified tokio runtime
+///
+/// Lets servers move noisy connections to dedicated worker threads
+/// or run a current-thread runtime per core
+///
+/// # Note
+///
+/// Socket stays registered in the reactor of the runtime which created it.
+/// Sockets of [`Conn::connect_with_config()`] and [`Listener::listen_with_config()`]
+/// are created within the target runtime, so their I/O moves there as well
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use cobra_rs::runtime::HandleRuntime;
+/// use cobra_rs::transport::tcp::ConnConfig;
+///
+/// let dedicated = tokio::runtime::Builder::new_multi_thread()
+///     .worker_threads(1)
+///     .enable_all()
+///     .build()
+///     .unwrap();
+///
+/// let config = ConnConfig::new()
+///     .set_runtime(Arc::new(HandleRuntime::new(dedicated.handle().clone())));
+/// ```
+///
+/// [`Runtime`]: crate::runtime::Runtime
+/// [`Conn::connect_with_config()`]: crate::transport::tcp::Conn::connect_with_config
+/// [`Listener::listen_with_config()`]: crate::transport::tcp::Listener::listen_with_config
+#[derive(Debug, Clone)]
+pub struct HandleRuntime {
+    handle: Handle,
+}
+
+impl HandleRuntime {
+    pub fn new(handle: Handle) -> Self {
+        HandleRuntime { handle }
+    }
+}
+
+impl Runtime for HandleRuntime {
+    fn spawn(&self, future: BoxFuture<()>) {
+        self.handle.spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        // Timer is registered in the runtime of the handle
+        let _enter = self.handle.enter();
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    fn spawn_blocking(&self, work: Box<dyn FnOnce() + Send>) {
+        self.handle.spawn_blocking(work);
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<io::Result<TcpStream>> {
+        let connect = self.handle.spawn(TcpStream::connect(addr));
+        Box::pin(async move { connect.await.map_err(io::Error::other)? })
+    }
+
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<io::Result<TcpListener>> {
+        let bind = self.handle.spawn(TcpListener::bind(addr));
+        Box::pin(async move { bind.await.map_err(io::Error::other)? })
+    }
+}
+
+/// [`Runtime`] running tasks on async-std
+///
+/// Sockets are driven by a background tokio reactor shared with [`SmolRuntime`]
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use cobra_rs::runtime::AsyncStdRuntime;
+/// use cobra_rs::transport::tcp::ConnConfig;
+///
+/// let config = ConnConfig::new().set_runtime(Arc::new(AsyncStdRuntime));
+/// ```
+///
+/// [`Runtime`]: crate::runtime::Runtime
+/// [`SmolRuntime`]: crate::runtime::SmolRuntime
+#[cfg(feature = "async-std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdRuntime;
+
+#[cfg(feature = "async-std")]
+impl Runtime for AsyncStdRuntime {
+    fn spawn(&self, future: BoxFuture<()>) {
+        async_std::task::spawn(in_reactor(future));
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+
+    fn spawn_blocking(&self, work: Box<dyn FnOnce() + Send>) {
+        async_std::task::spawn_blocking(work);
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<io::Result<TcpStream>> {
+        HandleRuntime::new(reactor()).connect(addr)
+    }
+
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<io::Result<TcpListener>> {
+        HandleRuntime::new(reactor()).bind(addr)
+    }
+}
+
+/// [`Runtime`] running tasks on the global smol executor
+///
+/// Sockets are driven by a background tokio reactor shared with [`AsyncStdRuntime`]
+///
+/// [`Runtime`]: crate::runtime::Runtime
+/// [`AsyncStdRuntime`]: crate::runtime::AsyncStdRuntime
+#[cfg(feature = "smol")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmolRuntime;
+
+#[cfg(feature = "smol")]
+impl Runtime for SmolRuntime {
+    fn spawn(&self, future: BoxFuture<()>) {
+        smol::spawn(in_reactor(future)).detach();
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(async move {
+            smol::Timer::after(duration).await;
+        })
+    }
+
+    fn spawn_blocking(&self, work: Box<dyn FnOnce() + Send>) {
+        smol::unblock(work).detach();
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<io::Result<TcpStream>> {
+        HandleRuntime::new(reactor()).connect(addr)
+    }
+
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<io::Result<TcpListener>> {
+        HandleRuntime::new(reactor()).bind(addr)
+    }
+}
+
+// Tokio runtime driving sockets of runtimes without a tokio reactor
+#[cfg(any(feature = "async-std", feature = "smol"))]
+fn reactor() -> Handle {
+    static REACTOR: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+
+    REACTOR.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("cobra-reactor")
+            .enable_all()
+            .build()
+            .expect("failed to start the reactor")
+    }).handle().clone()
+}
+
+// Polls the task within the reactor, so sockets created by it are registered there
+#[cfg(any(feature = "async-std", feature = "smol"))]
+fn in_reactor(mut future: BoxFuture<()>) -> BoxFuture<()> {
+    let reactor = reactor();
+    Box::pin(poll_fn(move |cx| {
+        let _guard = reactor.enter();
+        future.as_mut().poll(cx)
+    }))
+}
+
+/// [`Runtime`] running tasks on the [`LocalSet`]
+///
+/// Tasks may be spawned from any thread, they are sent to the thread
+/// driving the set. Tasks stop once the set is dropped
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use cobra_rs::runtime::LocalSetRuntime;
+/// use cobra_rs::transport::tcp::ConnConfig;
+/// use tokio::task::LocalSet;
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///     let local = LocalSet::new();
+///     let config = ConnConfig::new()
+///         .set_runtime(Arc::new(LocalSetRuntime::new(&local)));
+///
+///     local.run_until(async {
+///         // Connections created with the config run here
+///     }).await;
+/// }
+/// ```
+///
+/// [`Runtime`]: crate::runtime::Runtime
+/// [`LocalSet`]: tokio::task::LocalSet
+#[derive(Debug, Clone)]
+pub struct LocalSetRuntime {
+    sender: UnboundedSender<BoxFuture<()>>,
+}
+
+impl LocalSetRuntime {
+    pub fn new(local: &LocalSet) -> Self {
+        let (sender, mut receiver) = unbounded_channel::<BoxFuture<()>>();
+        local.spawn_local(async move {
+            while let Some(future) = receiver.recv().await {
+                tokio::task::spawn_local(future);
+            }
+        });
+
+        LocalSetRuntime { sender }
+    }
+}
+
+impl Runtime for LocalSetRuntime {
+    fn spawn(&self, future: BoxFuture<()>) {
+        let _ = self.sender.send(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+static DEFAULT_RUNTIME: OnceLock<Arc<dyn Runtime>> = OnceLock::new();
+
+/// Sets runtime used when no runtime was specified explicitly
+///
+/// Can be called only once and before any connection is created,
+/// otherwise returns the runtime back
+pub fn set_default_runtime(runtime: Arc<dyn Runtime>) -> Result<(), Arc<dyn Runtime>> {
+    DEFAULT_RUNTIME.set(runtime)
+}
+
+/// Returns runtime used when no runtime was specified explicitly
+pub fn default_runtime() -> Arc<dyn Runtime> {
+    DEFAULT_RUNTIME.get_or_init(|| Arc::new(TokioRuntime)).clone()
+}
+
+pub(crate) fn spawn<F: Future<Output=()> + Send + 'static>(future: F) {
+    default_runtime().spawn(Box::pin(future));
+}
+
+/// Runs blocking work on the default runtime and awaits its result
+///
+/// Returns [`Interrupted`] if the work panicked
+///
+/// [`Interrupted`]: std::io::ErrorKind::Interrupted
+pub(crate) async fn spawn_blocking<T, F>(work: F) -> io::Result<T>
+    where T: Send + 'static,
+          F: FnOnce() -> io::Result<T> + Send + 'static {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    default_runtime().spawn_blocking(Box::new(move || {
+        let _ = sender.send(work());
+    }));
+
+    receiver.await
+        .map_err(|_| io::Error::new(io::ErrorKind::Interrupted, "blocking work was cancelled"))?
+}
+
+pub(crate) async fn sleep(duration: Duration) {
+    default_runtime().sleep(duration).await
+}
+
+/// Awaits the future for at most `duration` using the specified runtime
+///
+/// Returns `Err(())` if the time is up
+pub(crate) async fn timeout_on<F: Future>(runtime: &dyn Runtime, duration: Duration, future: F) -> Result<F::Output, ()> {
+    tokio::select! {
+        output = future => Ok(output),
+        _ = runtime.sleep(duration) => Err(()),
+    }
+}
+
+/// The same as [`timeout_on()`] but uses the default runtime
+///
+/// [`timeout_on()`]: crate::runtime::timeout_on
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, ()> {
+    timeout_on(default_runtime().as_ref(), duration, future).await
+}
+
+/// Awaits the future, returns panic message if it panicked
+pub(crate) async fn catch_unwind<F: Future>(future: F) -> Result<F::Output, String> {
+    let mut future = Box::pin(future);
+
+    poll_fn(|cx| match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+        Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+        Ok(Poll::Pending) => Poll::Pending,
+        Err(payload) => Poll::Ready(Err(panic_message(payload))),
+    }).await
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
+
+/// Tracks tasks spawned for one owner, so the owner can wait for all of them
+#[derive(Default)]
+pub(crate) struct TaskGroup {
+    running: AtomicUsize,
+    finished: Notify,
+}
+
+// Marks task as finished when it completes or is dropped by the runtime
+struct TaskGuard(Arc<TaskGroup>);
+
+impl TaskGroup {
+    pub(crate) fn spawn(self: &Arc<Self>, runtime: &dyn Runtime, future: BoxFuture<()>) {
+        self.running.fetch_add(1, Ordering::SeqCst);
+        let guard = TaskGuard(self.clone());
+
+        runtime.spawn(Box::pin(async move {
+            let _guard = guard;
+            future.await
+        }));
+    }
+
+    /// Waits until all spawned tasks are finished
+    pub(crate) async fn join(&self) {
+        loop {
+            let finished = self.finished.notified();
+            if self.running.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            finished.await;
+        }
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        if self.0.running.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.finished.notify_waiters();
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt::{Display, Write};
+use std::hash::Hash;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::builder::kind_conn::close_code::CLOSED_BY_USER;
+use crate::builder::kind_conn::KindConn;
+use crate::runtime;
+use crate::server::ConnRegistry;
+
+// Longest command accepted from a client
+const MAX_COMMAND_LEN: u64 = 1024;
+
+/// Unix socket answering operator commands about connections of a registry
+///
+/// Every client sends one command line and reads the text answer until EOF.
+/// Commands are:
+///
+/// * `list` prints a line with the id and stats of every connection
+/// * `stats <id>` prints stats of the connection, see [`KindConn::stats()`]
+/// * `kick <id> [code]` closes the connection, by default with [`CLOSED_BY_USER`]
+///
+/// Answers of failed commands start with `error:`. The `cobra-admin` binary
+/// (the `admin` feature) is a client of the socket
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use cobra_rs::server::{AdminSocket, ConnRegistry};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let registry = Arc::new(ConnRegistry::<u64>::new());
+///     let admin = AdminSocket::bind("/run/cobra/admin.sock", registry).unwrap();
+///
+///     tokio::spawn(async move { admin.run().await });
+/// }
+/// ```
+///
+/// [`KindConn::stats()`]: crate::builder::kind_conn::KindConn::stats
+/// [`CLOSED_BY_USER`]: crate::builder::kind_conn::close_code::CLOSED_BY_USER
+pub struct AdminSocket<I> {
+    listener: UnixListener,
+    registry: Arc<ConnRegistry<I>>,
+}
+
+impl<I> AdminSocket<I>
+    where I: 'static + Eq + Hash + Clone + Ord + Display + FromStr + Send + Sync {
+    /// Listens on the socket path, a stale socket file is replaced
+    pub fn bind<P: AsRef<Path>>(path: P, registry: Arc<ConnRegistry<I>>) -> io::Result<Self> {
+        match std::fs::remove_file(&path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => {}
+        }
+
+        Ok(AdminSocket {
+            listener: UnixListener::bind(path)?,
+            registry,
+        })
+    }
+
+    /// Answers clients until the socket fails
+    pub async fn run(&self) -> io::Result<()> {
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            let registry = self.registry.clone();
+
+            // Slow clients don't block others
+            runtime::spawn(async move {
+                let _ = AdminSocket::serve(stream, &registry).await;
+            });
+        }
+    }
+
+    /// Executes the command and returns the answer sent to the client
+    pub async fn execute(&self, command: &str) -> String {
+        AdminSocket::answer(&self.registry, command).await
+    }
+
+    async fn answer(registry: &ConnRegistry<I>, command: &str) -> String {
+        let mut args = command.split_whitespace();
+
+        match (args.next(), args.next(), args.next()) {
+            (Some("list"), None, _) => {
+                let mut conns = registry.conns();
+                conns.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+                conns.iter().fold(String::new(), |mut answer, (id, conn)| {
+                    let _ = writeln!(answer, "{} {}", id, AdminSocket::<I>::summary(conn));
+                    answer
+                })
+            }
+            (Some("stats"), Some(id), None) => match AdminSocket::find(registry, id) {
+                Ok(conn) => AdminSocket::<I>::stats(&conn),
+                Err(error) => error,
+            },
+            (Some("kick"), Some(id), code) => {
+                let code = match code.map(u8::from_str).transpose() {
+                    Ok(code) => code.unwrap_or(CLOSED_BY_USER),
+                    Err(_) => return "error: invalid close code\n".to_string(),
+                };
+                let id: Option<I> = id.parse().ok();
+                match id {
+                    Some(id) if registry.kick(&id, code).await => "kicked\n".to_string(),
+                    _ => "error: unknown connection\n".to_string(),
+                }
+            }
+            _ => "error: unknown command\n".to_string(),
+        }
+    }
+
+    async fn serve(mut stream: UnixStream, registry: &ConnRegistry<I>) -> io::Result<()> {
+        let mut command = String::new();
+        BufReader::new((&mut stream).take(MAX_COMMAND_LEN)).read_line(&mut command).await?;
+
+        let answer = AdminSocket::answer(registry, &command).await;
+        stream.write_all(answer.as_bytes()).await?;
+        stream.shutdown().await
+    }
+
+    fn find(registry: &ConnRegistry<I>, id: &str) -> Result<Arc<KindConn>, String> {
+        id.parse()
+            .ok()
+            .and_then(|id| registry.get(&id))
+            .ok_or_else(|| "error: unknown connection\n".to_string())
+    }
+
+    fn summary(conn: &KindConn) -> String {
+        let stats = conn.stats();
+        let peer = conn.peer_addr().map_or_else(|_| "-".to_string(), |addr| addr.to_string());
+
+        format!("peer={} version={} idle={:?} kinds={} queued={} pending={}",
+                peer,
+                stats.protocol_version,
+                stats.idle,
+                stats.open_kinds,
+                stats.transport.queued_bytes,
+                stats.transport.pending_writes)
+    }
+
+    fn stats(conn: &KindConn) -> String {
+        let stats = conn.stats();
+        let rtt = stats.transport.rtt.map_or_else(|| "unknown".to_string(), |rtt| format!("{:?}", rtt));
+        let compression = stats.compression.map_or_else(|| "-".to_string(), |compression| {
+            format!("ratio {:.2}, {}", compression.ratio(), if compression.enabled { "enabled" } else { "disabled" })
+        });
+
+        format!("peer: {}\n\
+                 protocol version: {}\n\
+                 kind width: {} bytes\n\
+                 idle: {:?}\n\
+                 open kinds: {}\n\
+                 queued bytes: {}\n\
+                 queued frames: {}\n\
+                 pending writes: {}\n\
+                 rtt: {}\n\
+                 compression: {}\n",
+                conn.peer_addr().map_or_else(|_| "-".to_string(), |addr| addr.to_string()),
+                stats.protocol_version,
+                stats.kind_width.bytes(),
+                stats.idle,
+                stats.open_kinds,
+                stats.transport.queued_bytes,
+                stats.transport.queued_frames,
+                stats.transport.pending_writes,
+                rtt,
+                compression)
+    }
+}
@@ -0,0 +1,139 @@
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::builder::kind_conn::close_code::GOING_AWAY;
+use crate::runtime;
+use crate::server::registry::{join_all, LocalBoxFuture};
+use crate::server::ConnRegistry;
+use crate::transport::tcp::Listener;
+
+type ProgressHandler = Arc<dyn Fn(&DrainProgress) + Send + Sync>;
+
+/// Close reason sent with [`GOING_AWAY`] code
+///
+/// [`GOING_AWAY`]: crate::builder::kind_conn::close_code::GOING_AWAY
+pub const GOING_AWAY_REASON: &str = "server is going away";
+
+/// Progress of [`Server::drain()`]
+///
+/// [`Server::drain()`]: crate::server::Server::drain
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrainProgress {
+    /// Number of connections registered when draining started
+    pub total: usize,
+    /// Connections closed after their queued frames were written
+    pub closed: usize,
+    /// Connections closed at the deadline, their queued frames were dropped
+    pub forced: usize,
+}
+
+/// Listener with the registry of its connections
+///
+/// Connections have to be registered by the application, see [`ConnRegistry`]
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// use cobra_rs::builder::builder::Builder;
+/// use cobra_rs::server::{ConnRegistry, Server};
+/// use cobra_rs::transport::tcp::Listener;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let listener = Listener::listen("127.0.0.1:5000").await.unwrap();
+///     let server = Server::new(listener, Arc::new(ConnRegistry::new()));
+///     server.on_drain_progress(|progress| println!("{:?}", progress));
+///
+///     let (conn, _) = server.listener().accept().await.unwrap();
+///     let conn = Builder::new().set_conn(conn).run().await.unwrap();
+///     server.registry().register(0_u64, Arc::new(conn));
+///
+///     // Rolling deploy, the balancer moves clients to other instances
+///     server.drain(Duration::from_secs(30)).await;
+/// }
+/// ```
+///
+/// [`ConnRegistry`]: crate::server::ConnRegistry
+pub struct Server<I> {
+    listener: Listener,
+    registry: Arc<ConnRegistry<I>>,
+    progress: RwLock<Option<ProgressHandler>>,
+}
+
+impl<I: Eq + Hash + Clone + Send + Sync> Server<I> {
+    pub fn new(listener: Listener, registry: Arc<ConnRegistry<I>>) -> Self {
+        Server {
+            listener,
+            registry,
+            progress: RwLock::new(None),
+        }
+    }
+
+    pub fn listener(&self) -> &Listener {
+        &self.listener
+    }
+
+    pub fn registry(&self) -> &Arc<ConnRegistry<I>> {
+        &self.registry
+    }
+
+    /// Sets handler called by [`drain()`] once it starts and after every closed connection
+    ///
+    /// [`drain()`]: crate::server::Server::drain
+    pub fn on_drain_progress<F: 'static + Fn(&DrainProgress) + Send + Sync>(&self, handler: F) {
+        *self.progress.write().unwrap() = Some(Arc::new(handler));
+    }
+
+    /// Stops accepting connections and closes registered ones
+    ///
+    /// Every connection writes its queued frames and is closed with
+    /// [`GOING_AWAY`] code, so clients reconnect to another instance.
+    /// Connections still writing at the `deadline` are closed anyway.
+    /// Returns once all connections are closed and removed from the registry
+    ///
+    /// [`GOING_AWAY`]: crate::builder::kind_conn::close_code::GOING_AWAY
+    pub async fn drain(&self, deadline: Duration) -> DrainProgress {
+        self.listener.drain();
+
+        let started = Instant::now();
+        let conns = self.registry.conns();
+        let progress = Mutex::new(DrainProgress { total: conns.len(), ..Default::default() });
+        self.report(*progress.lock().unwrap());
+
+        let closes = conns.into_iter()
+            .map(|(id, conn)| {
+                let progress = &progress;
+                Box::pin(async move {
+                    let left = deadline.saturating_sub(started.elapsed());
+                    let flushed = runtime::timeout(left, conn.flush()).await.is_ok();
+                    conn.close_with_reason(GOING_AWAY, GOING_AWAY_REASON).await;
+                    self.registry.unregister(&id);
+
+                    let current = {
+                        let mut progress = progress.lock().unwrap();
+                        match flushed {
+                            true => progress.closed += 1,
+                            false => progress.forced += 1,
+                        }
+                        *progress
+                    };
+                    self.report(current);
+                }) as LocalBoxFuture<_>
+            })
+            .collect();
+        join_all(closes).await;
+
+        progress.into_inner().unwrap()
+    }
+
+    fn report(&self, progress: DrainProgress) {
+        let handler = self.progress.read().unwrap().clone();
+        if let Some(handler) = handler {
+            handler(&progress);
+        }
+    }
+}
@@ -0,0 +1,13 @@
+#[cfg(unix)]
+pub use admin::*;
+pub use drain::*;
+pub use reaper::*;
+pub use registry::*;
+pub use router::*;
+
+#[cfg(unix)]
+mod admin;
+mod drain;
+mod reaper;
+mod registry;
+mod router;
@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::builder::kind_conn::close_code::IDLE_TIMEOUT;
+use crate::runtime;
+use crate::server::ConnRegistry;
+
+/// Closes registered connections without application traffic
+///
+/// Connection is idle while no package of application kinds is read or
+/// written, pings don't count (see [`KindConn::idle()`]). Idle connections
+/// are removed from the registry and closed with [`IDLE_TIMEOUT`] code.
+/// Long-lived connections (e.g. subscriptions waiting for rare events)
+/// may be exempted
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// use cobra_rs::server::{ConnRegistry, IdleReaper};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let registry: Arc<ConnRegistry<u64>> = Arc::new(ConnRegistry::new());
+///     let reaper = Arc::new(IdleReaper::new(registry.clone(), Duration::from_secs(300)));
+///     reaper.exempt(0);
+///
+///     tokio::spawn(async move { reaper.run().await });
+/// }
+/// ```
+///
+/// [`KindConn::idle()`]: crate::builder::kind_conn::KindConn::idle
+/// [`IDLE_TIMEOUT`]: crate::builder::kind_conn::close_code::IDLE_TIMEOUT
+pub struct IdleReaper<I> {
+    registry: Arc<ConnRegistry<I>>,
+    timeout: Duration,
+    interval: Duration,
+    exempt: RwLock<HashSet<I>>,
+}
+
+impl<I: Eq + Hash + Clone + Send + Sync> IdleReaper<I> {
+    /// Creates reaper closing connections idle for `timeout`
+    ///
+    /// By default connections are checked every half of the timeout
+    pub fn new(registry: Arc<ConnRegistry<I>>, timeout: Duration) -> Self {
+        IdleReaper {
+            registry,
+            timeout,
+            interval: timeout / 2,
+            exempt: RwLock::new(HashSet::new()),
+        }
+    }
+
+    /// Sets how often [`run()`] checks connections
+    ///
+    /// [`run()`]: crate::server::IdleReaper::run
+    pub fn set_check_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Marks connection as long-lived, it's never reaped
+    pub fn exempt(&self, id: I) {
+        self.exempt.write().unwrap().insert(id);
+    }
+
+    /// Removes the mark set by [`exempt()`]
+    ///
+    /// [`exempt()`]: crate::server::IdleReaper::exempt
+    pub fn unexempt(&self, id: &I) {
+        self.exempt.write().unwrap().remove(id);
+    }
+
+    /// Returns `true` if the connection is marked as long-lived
+    pub fn is_exempt(&self, id: &I) -> bool {
+        self.exempt.read().unwrap().contains(id)
+    }
+
+    /// Closes connections idle beyond the timeout, returns their ids
+    pub async fn reap(&self) -> Vec<I> {
+        let idle: Vec<_> = self.registry.conns()
+            .into_iter()
+            .filter(|(id, conn)| conn.idle() >= self.timeout && !self.is_exempt(id))
+            .collect();
+
+        let mut reaped = Vec::with_capacity(idle.len());
+        for (id, conn) in idle {
+            // Connection may be replaced since the snapshot
+            if self.registry.get(&id).is_some_and(|current| Arc::ptr_eq(&current, &conn)) {
+                self.registry.unregister(&id);
+                conn.close(IDLE_TIMEOUT).await;
+                reaped.push(id);
+            }
+        }
+
+        reaped
+    }
+
+    /// Calls [`reap()`] periodically, never returns
+    ///
+    /// [`reap()`]: crate::server::IdleReaper::reap
+    pub async fn run(&self) {
+        loop {
+            runtime::sleep(self.interval).await;
+            self.reap().await;
+        }
+    }
+}
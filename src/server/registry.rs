@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+use std::future::{poll_fn, Future};
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::Poll;
+
+use bytes::Bytes;
+
+use crate::builder::kind_conn::{EncodingKey, KindConn};
+use crate::runtime::BoxFuture;
+use crate::sync::WriteError;
+
+// Future borrowing the caller's state
+pub(crate) type LocalBoxFuture<'a, T> = Pin<Box<dyn Future<Output=T> + Send + 'a>>;
+
+/// Tracks live connections of a server by user-assigned ids and tags
+///
+/// Connections closed by the peer are removed on the next [`broadcast()`]
+/// or [`prune()`]
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use cobra_rs::builder::builder::Builder;
+/// use cobra_rs::builder::kind_conn::close_code::CLOSED_BY_USER;
+/// use cobra_rs::server::ConnRegistry;
+/// use cobra_rs::transport::tcp::Listener;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let registry = ConnRegistry::new();
+///     let listener = Listener::listen("127.0.0.1:5000").await.unwrap();
+///
+///     for id in 0..2_u64 {
+///         let (conn, _) = listener.accept().await.unwrap();
+///         let conn = Builder::new().set_conn(conn).run().await.unwrap();
+///
+///         registry.register(id, Arc::new(conn));
+///         registry.tag(&id, "lobby");
+///     }
+///
+///     registry.broadcast("lobby", &[1, 2, 3]).await;
+///     registry.kick(&0, CLOSED_BY_USER).await;
+/// }
+/// ```
+///
+/// [`broadcast()`]: crate::server::ConnRegistry::broadcast
+/// [`prune()`]: crate::server::ConnRegistry::prune
+pub struct ConnRegistry<I> {
+    entries: RwLock<HashMap<I, Entry>>,
+}
+
+struct Entry {
+    conn: Arc<KindConn>,
+    tags: HashSet<String>,
+}
+
+impl<I: Eq + Hash + Clone + Send + Sync> ConnRegistry<I> {
+    /// Creates empty registry
+    pub fn new() -> Self {
+        ConnRegistry {
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Adds connection with the specified id
+    ///
+    /// Connection previously registered with the same id
+    /// is replaced and returned
+    pub fn register(&self, id: I, conn: Arc<KindConn>) -> Option<Arc<KindConn>> {
+        let entry = Entry {
+            conn,
+            tags: HashSet::new(),
+        };
+
+        self.entries.write().unwrap()
+            .insert(id, entry)
+            .map(|entry| entry.conn)
+    }
+
+    /// Removes connection without closing it
+    pub fn unregister(&self, id: &I) -> Option<Arc<KindConn>> {
+        self.entries.write().unwrap()
+            .remove(id)
+            .map(|entry| entry.conn)
+    }
+
+    /// Returns connection with the specified id
+    pub fn get(&self, id: &I) -> Option<Arc<KindConn>> {
+        self.entries.read().unwrap()
+            .get(id)
+            .map(|entry| entry.conn.clone())
+    }
+
+    /// Adds tag to the connection, returns `false` if there is no such connection
+    pub fn tag(&self, id: &I, tag: &str) -> bool {
+        self.entries.write().unwrap()
+            .get_mut(id)
+            .map(|entry| entry.tags.insert(tag.to_string()))
+            .is_some()
+    }
+
+    /// Removes tag from the connection
+    pub fn untag(&self, id: &I, tag: &str) {
+        if let Some(entry) = self.entries.write().unwrap().get_mut(id) {
+            entry.tags.remove(tag);
+        }
+    }
+
+    /// Returns ids of the connections with the specified tag
+    pub fn tagged(&self, tag: &str) -> Vec<I> {
+        self.entries.read().unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.tags.contains(tag))
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    /// Returns snapshot of all registered connections
+    pub fn conns(&self) -> Vec<(I, Arc<KindConn>)> {
+        self.entries.read().unwrap()
+            .iter()
+            .map(|(id, entry)| (id.clone(), entry.conn.clone()))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes package to every connection with the specified tag
+    ///
+    /// The same as [`broadcast_frame()`] but copies the package
+    ///
+    /// [`broadcast_frame()`]: crate::server::ConnRegistry::broadcast_frame
+    pub async fn broadcast(&self, tag: &str, package: &[u8]) -> usize {
+        self.broadcast_frame(tag, Bytes::copy_from_slice(package)).await
+    }
+
+    /// Writes package to every connection with the specified tag
+    ///
+    /// Package is encrypted and compressed once for all connections with
+    /// the same kind and providers settings (see [`EncryptionProvider::shared_key()`]),
+    /// others encode it separately. Writes are performed concurrently, so a slow
+    /// connection doesn't delay others. Closed connections are removed.
+    /// Returns number of connections the package was written to
+    ///
+    /// # Note
+    ///
+    /// Every connection owns its write queue, so the encoded package
+    /// is still copied once into each connection's frame
+    ///
+    /// [`EncryptionProvider::shared_key()`]: crate::builder::builder::EncryptionProvider::shared_key
+    pub async fn broadcast_frame(&self, tag: &str, package: Bytes) -> usize {
+        let recipients: Vec<(I, Arc<KindConn>)> = self.entries.read().unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.tags.contains(tag))
+            .map(|(id, entry)| (id.clone(), entry.conn.clone()))
+            .collect();
+
+        let mut encoded: HashMap<EncodingKey, Bytes> = HashMap::new();
+        let writes = recipients.iter()
+            .map(|(_, conn)| {
+                let encode = || Bytes::from(conn.encode(package.to_vec()));
+                let body = match conn.encoding_key() {
+                    Some(key) => encoded.entry(key).or_insert_with(encode).clone(),
+                    None => encode(),
+                };
+
+                let conn = conn.clone();
+                Box::pin(async move { conn.write_encoded(&body).await }) as BoxFuture<_>
+            })
+            .collect();
+
+        let mut written = 0;
+        for ((id, _), result) in recipients.into_iter().zip(join_all(writes).await) {
+            match result {
+                Ok(()) => written += 1,
+                Err(WriteError::Closed(_)) => {
+                    self.unregister(&id);
+                }
+                Err(WriteError::Rejected(_)) => {}
+            }
+        }
+
+        written
+    }
+
+    /// Removes connection and closes it with the specified code
+    ///
+    /// Returns `false` if there is no such connection
+    pub async fn kick(&self, id: &I, code: u8) -> bool {
+        match self.unregister(id) {
+            Some(conn) => {
+                conn.close(code).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes closed connections
+    pub async fn prune(&self) {
+        for (id, conn) in self.conns() {
+            if conn.is_close().await.is_some() {
+                self.unregister(&id);
+            }
+        }
+    }
+}
+
+impl<I: Eq + Hash + Clone + Send + Sync> Default for ConnRegistry<I> {
+    fn default() -> Self {
+        ConnRegistry::new()
+    }
+}
+
+// Polls all futures until every one completes, keeps their order
+pub(crate) async fn join_all<'a, T>(mut futures: Vec<LocalBoxFuture<'a, T>>) -> Vec<T> {
+    let mut results: Vec<Option<T>> = futures.iter().map(|_| None).collect();
+
+    poll_fn(|cx| {
+        let mut pending = false;
+        for (future, result) in futures.iter_mut().zip(results.iter_mut()) {
+            if result.is_some() {
+                continue;
+            }
+            match future.as_mut().poll(cx) {
+                Poll::Ready(value) => *result = Some(value),
+                Poll::Pending => pending = true,
+            }
+        }
+
+        if pending { Poll::Pending } else { Poll::Ready(()) }
+    }).await;
+
+    results.into_iter().map(Option::unwrap).collect()
+}
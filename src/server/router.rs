@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+
+use crate::builder::kind_conn::KindConn;
+use crate::runtime::{self, BoxFuture};
+use crate::server::registry::{join_all, LocalBoxFuture};
+use crate::sync::WriteError;
+
+type Handler = Arc<dyn Fn(Request) -> BoxFuture<()> + Send + Sync>;
+
+/// Route of packages handled by a [`Router`] handler
+///
+/// Kind routes take all packages of the kind. Named routes take packages
+/// of the served kind starting with the routing header, see [`Router::encode()`]
+///
+/// [`Router`]: crate::server::Router
+/// [`Router::encode()`]: crate::server::Router::encode
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Route {
+    Kind(u8),
+    Name(String),
+}
+
+/// Package received by a route, passed to its handler
+pub struct Request {
+    route: Route,
+    package: Vec<u8>,
+    conn: KindConn,
+}
+
+/// Dispatches packages of served connections to async handlers
+///
+/// Every package is handled by a task of its own. Handlers with a
+/// concurrency limit delay reading the route once the limit is reached,
+/// so a busy handler slows down its clients only. Packages without
+/// a handler are dropped
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use cobra_rs::builder::builder::Builder;
+/// use cobra_rs::server::{Request, Router};
+/// use cobra_rs::transport::tcp::Listener;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let router = Arc::new(Router::new()
+///         .on("echo", |request: Request| async move {
+///             let _ = request.reply(request.package()).await;
+///         })
+///         .limit("echo", 16));
+///
+///     let listener = Listener::listen("127.0.0.1:5000").await.unwrap();
+///     while let Some((conn, _)) = listener.accept().await {
+///         let router = router.clone();
+///         tokio::spawn(async move {
+///             if let Ok(conn) = Builder::new().set_conn(conn).run().await {
+///                 router.serve(conn).await;
+///             }
+///         });
+///     }
+/// }
+/// ```
+#[derive(Default)]
+pub struct Router {
+    routes: HashMap<Route, Entry>,
+}
+
+#[derive(Default)]
+struct Entry {
+    handler: Option<Handler>,
+    limit: Option<Arc<Semaphore>>,
+}
+
+impl Router {
+    /// Creates router without routes
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets handler of the route, replaces the previous one
+    ///
+    /// Kind routes should use application kinds agreed by both sides,
+    /// kinds opened by [`KindConn::open_kind()`] may be reissued
+    ///
+    /// [`KindConn::open_kind()`]: crate::builder::kind_conn::KindConn::open_kind
+    pub fn on<R, F, Fut>(mut self, route: R, handler: F) -> Self
+        where R: Into<Route>,
+              F: 'static + Fn(Request) -> Fut + Send + Sync,
+              Fut: 'static + Future<Output=()> + Send {
+        let handler: Handler = Arc::new(move |request| Box::pin(handler(request)));
+        self.routes.entry(route.into()).or_default().handler = Some(handler);
+        self
+    }
+
+    /// Limits number of packages of the route handled at once
+    /// across all served connections
+    pub fn limit<R: Into<Route>>(mut self, route: R, max: usize) -> Self {
+        self.routes.entry(route.into()).or_default().limit = Some(Arc::new(Semaphore::new(max)));
+        self
+    }
+
+    /// Prepends the routing header of the named route to the body
+    ///
+    /// Returns [`None`] if the name is longer than 255 bytes
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn encode(route: &str, body: &[u8]) -> Option<Vec<u8>> {
+        let len = u8::try_from(route.len()).ok()?;
+
+        let mut package = Vec::with_capacity(1 + route.len() + body.len());
+        package.push(len);
+        package.extend_from_slice(route.as_bytes());
+        package.extend_from_slice(body);
+        Some(package)
+    }
+
+    /// Handles packages of the connection until it is closed
+    ///
+    /// Packages of the connection's kind are routed by their header if
+    /// there are named routes, other kinds are read by their kind routes
+    pub async fn serve(&self, conn: KindConn) {
+        let named = self.routes.keys().any(|route| matches!(route, Route::Name(_)));
+
+        let mut loops: Vec<LocalBoxFuture<()>> = Vec::new();
+        for route in self.routes.keys() {
+            let kind = match route {
+                Route::Kind(kind) if !named || *kind != conn.kind() => *kind,
+                _ => continue,
+            };
+            if let Some(kind_conn) = conn.with_kind(kind) {
+                loops.push(Box::pin(self.read_kind(kind_conn)));
+            }
+        }
+        if named {
+            loops.push(Box::pin(self.read_named(conn)));
+        }
+
+        join_all(loops).await;
+    }
+
+    async fn read_kind(&self, conn: KindConn) {
+        let route = Route::Kind(conn.kind());
+        while let Some(package) = conn.read().await {
+            self.dispatch(Request { route: route.clone(), package, conn: conn.clone() }).await;
+        }
+    }
+
+    async fn read_named(&self, conn: KindConn) {
+        while let Some(package) = conn.read().await {
+            if let Some((route, body)) = decode(&package) {
+                let request = Request { route: Route::Name(route.to_string()), package: body.to_vec(), conn: conn.clone() };
+                self.dispatch(request).await;
+            }
+        }
+    }
+
+    // Waits for a free slot of the handler, so the route isn't read meanwhile
+    async fn dispatch(&self, request: Request) {
+        let entry = match self.routes.get(&request.route) {
+            Some(entry) => entry,
+            None => return,
+        };
+        let handler = match &entry.handler {
+            Some(handler) => handler.clone(),
+            None => return,
+        };
+        let permit = match &entry.limit {
+            // Semaphore is never closed
+            Some(limit) => Some(limit.clone().acquire_owned().await.unwrap()),
+            None => None,
+        };
+
+        runtime::spawn(async move {
+            handler(request).await;
+            drop(permit);
+        });
+    }
+}
+
+impl Request {
+    pub fn route(&self) -> &Route {
+        &self.route
+    }
+
+    /// Returns the package without the routing header
+    pub fn package(&self) -> &[u8] {
+        &self.package
+    }
+
+    pub fn into_package(self) -> Vec<u8> {
+        self.package
+    }
+
+    /// Returns connection the package was received by
+    pub fn conn(&self) -> &KindConn {
+        &self.conn
+    }
+
+    /// Writes the body to the same route of the connection
+    ///
+    /// Returns [`WriteError::Rejected`] if the name of the route is too long
+    ///
+    /// [`WriteError::Rejected`]: crate::sync::WriteError::Rejected
+    pub async fn reply(&self, body: &[u8]) -> Result<(), WriteError<Vec<u8>>> {
+        let package = match &self.route {
+            Route::Kind(_) => body.to_vec(),
+            Route::Name(name) => Router::encode(name, body).ok_or_else(|| WriteError::Rejected(body.to_vec()))?,
+        };
+        self.conn.write(package).await
+    }
+}
+
+impl From<u8> for Route {
+    fn from(kind: u8) -> Self {
+        Route::Kind(kind)
+    }
+}
+
+impl From<&str> for Route {
+    fn from(name: &str) -> Self {
+        Route::Name(name.to_string())
+    }
+}
+
+impl From<String> for Route {
+    fn from(name: String) -> Self {
+        Route::Name(name)
+    }
+}
+
+// Splits the package into the route name and the body
+fn decode(package: &[u8]) -> Option<(&str, &[u8])> {
+    let (&len, rest) = package.split_first()?;
+    if rest.len() < len as usize {
+        return None;
+    }
+
+    let (route, body) = rest.split_at(len as usize);
+    Some((std::str::from_utf8(route).ok()?, body))
+}
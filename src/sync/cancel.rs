@@ -0,0 +1,113 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::sync::Notify;
+
+/// Signals cancellation to a tree of tasks
+///
+/// Cancelling a token cancels all its children, so a whole tree of
+/// connections can be stopped with a single call
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::sync::CancelToken;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let server = CancelToken::new();
+///     let conn = server.child();
+///
+///     server.cancel();
+///
+///     assert!(conn.is_cancelled());
+///     assert_eq!(conn.run(async { 1 }).await, None);
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    inner: Arc<CancelState>,
+}
+
+#[derive(Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    notifier: Notify,
+    children: Mutex<Vec<Weak<CancelState>>>,
+}
+
+impl CancelToken {
+    /// Creates new token
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates token which is cancelled together with this one
+    ///
+    /// Cancelling the child doesn't affect the parent
+    pub fn child(&self) -> Self {
+        let child = CancelToken::new();
+
+        let mut children = self.inner.children.lock().unwrap();
+        if self.is_cancelled() {
+            child.cancel();
+        } else {
+            children.retain(|child| child.strong_count() > 0);
+            children.push(Arc::downgrade(&child.inner));
+        }
+
+        child
+    }
+
+    /// Cancels the token and all its children
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notifier.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Runs future until it completes or the token is cancelled
+    ///
+    /// Returns [`None`] if the token was cancelled first.
+    /// Can be used with any read or write call
+    ///
+    /// [`None`]: std::option::Option::None
+    pub async fn run<T, F: Future<Output=T>>(&self, future: F) -> Option<T> {
+        tokio::select! {
+            biased;
+            _ = self.cancelled() => None,
+            value = future => Some(value),
+        }
+    }
+}
+
+impl CancelState {
+    fn cancel(&self) {
+        let children = {
+            let mut children = self.children.lock().unwrap();
+            if self.cancelled.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            std::mem::take(&mut *children)
+        };
+
+        self.notifier.notify_waiters();
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
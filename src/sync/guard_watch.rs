@@ -0,0 +1,136 @@
+use std::backtrace::Backtrace;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::runtime;
+
+type HeldGuardHandler = Arc<dyn Fn(&HeldGuard) + Send + Sync>;
+
+static WATCH: RwLock<Option<GuardWatch>> = RwLock::new(None);
+
+/// Event emitted when a writer waits for the answer longer than
+/// the threshold of [`GuardWatch`]
+///
+/// [`GuardWatch`]: crate::sync::GuardWatch
+#[derive(Debug, Clone)]
+pub struct HeldGuard {
+    /// Time since the reader took the value
+    pub held: Duration,
+    /// Where the guard was created, [`None`] without the `guard-backtrace` feature
+    ///
+    /// [`None`]: std::option::Option::None
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+/// Detection of [`PoolGuard`]s which are neither accepted nor rejected
+///
+/// Writer of a value stays blocked until its guard answers, so a guard
+/// kept forever silently deadlocks it. Once installed, writers waiting
+/// longer than the threshold report the guard once. By default the report
+/// is printed to stderr. Only debug builds are watched
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use cobra_rs::sync::GuardWatch;
+///
+/// GuardWatch::new(Duration::from_secs(5))
+///     .on_held_guard(|guard| eprintln!("pool guard held for {:?}", guard.held))
+///     .install();
+/// ```
+///
+/// [`PoolGuard`]: crate::sync::PoolGuard
+#[derive(Clone)]
+pub struct GuardWatch {
+    threshold: Duration,
+    handler: HeldGuardHandler,
+}
+
+/// Guard being held, recorded when the reader takes the value
+#[derive(Clone)]
+pub(crate) struct TakenGuard {
+    since: Instant,
+    backtrace: Option<Arc<Backtrace>>,
+}
+
+impl GuardWatch {
+    /// Creates watch reporting guards held longer than `threshold`
+    pub fn new(threshold: Duration) -> Self {
+        GuardWatch {
+            threshold,
+            handler: Arc::new(|guard| eprintln!("cobra: pool guard held for {:?}: {:?}", guard.held, guard.backtrace)),
+        }
+    }
+
+    /// Sets handler called with [`HeldGuard`] event instead of printing it
+    ///
+    /// Handler is called from the blocked writer, so it must not block
+    ///
+    /// [`HeldGuard`]: crate::sync::HeldGuard
+    pub fn on_held_guard<F: 'static + Fn(&HeldGuard) + Send + Sync>(mut self, handler: F) -> Self {
+        self.handler = Arc::new(handler);
+        self
+    }
+
+    /// Watches guards of all pools, replaces the watch installed before
+    ///
+    /// Applies to values taken after the call
+    pub fn install(self) {
+        *WATCH.write().unwrap() = Some(self);
+    }
+
+    /// Stops watching guards
+    pub fn uninstall() {
+        *WATCH.write().unwrap() = None;
+    }
+
+    // Waits for the answer, reports the guard once it is held longer than the threshold
+    pub(crate) async fn watch<F, G>(self, answer: F, taken: G) -> F::Output
+        where F: Future,
+              G: Fn() -> Option<TakenGuard> {
+        tokio::pin!(answer);
+        loop {
+            // The value may wait for a reader, then the guard isn't held yet
+            let wait = match taken() {
+                Some(taken) if taken.since.elapsed() >= self.threshold => {
+                    (self.handler)(&HeldGuard {
+                        held: taken.since.elapsed(),
+                        backtrace: taken.backtrace,
+                    });
+                    return answer.await;
+                }
+                Some(taken) => self.threshold.saturating_sub(taken.since.elapsed()),
+                None => self.threshold,
+            };
+
+            if let Ok(output) = runtime::timeout(wait, &mut answer).await {
+                return output;
+            }
+        }
+    }
+}
+
+/// Returns the installed watch, always [`None`] in release builds
+///
+/// [`None`]: std::option::Option::None
+pub(crate) fn current() -> Option<GuardWatch> {
+    if !cfg!(debug_assertions) || cfg!(cobra_loom) {
+        return None;
+    }
+    WATCH.read().unwrap().clone()
+}
+
+impl TakenGuard {
+    pub(crate) fn capture() -> Self {
+        TakenGuard {
+            since: Instant::now(),
+            #[cfg(feature = "guard-backtrace")]
+            backtrace: Some(Arc::new(Backtrace::force_capture())),
+            #[cfg(not(feature = "guard-backtrace"))]
+            backtrace: None,
+        }
+    }
+}
@@ -1,8 +1,12 @@
 use std::collections::HashMap;
+use std::future::{poll_fn, Future};
 use std::hash::Hash;
+use std::ops::{Range, RangeInclusive};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::task::Poll;
 
-use tokio::sync::RwLock;
+use tokio::sync::{Notify, RwLock};
 
 use crate::sync::{Pool, PoolGuard, WriteError};
 
@@ -85,6 +89,31 @@ pub struct KindPool<K: Eq + Hash, V: Kind<K>> {
 struct KindPoolState<K: Eq + Hash, V: Kind<K>> {
     pools: RwLock<HashMap<K, Pool<V>>>,
     closed: RwLock<bool>,
+    // Kinds created after close_except() are closed
+    sealed: AtomicBool,
+    // Wakes subscriptions when a kind is created or the pool is closed
+    changed: Notify,
+}
+
+/// Kinds read by [`KindSubscription`]
+///
+/// Created from a range of kinds, a predicate or [`KindFilter::any()`]
+///
+/// [`KindSubscription`]: crate::sync::KindSubscription
+/// [`KindFilter::any()`]: crate::sync::KindFilter::any
+pub struct KindFilter<K> {
+    matches: Arc<dyn Fn(&K) -> bool + Send + Sync>,
+}
+
+/// Reader of values with kinds matching the filter,
+/// returned by [`KindPool::subscribe()`]
+///
+/// [`KindPool::subscribe()`]: crate::sync::KindPool::subscribe
+pub struct KindSubscription<K: Eq + Hash, V: Kind<K>> {
+    state: Arc<KindPoolState<K, V>>,
+    filter: KindFilter<K>,
+    // Kind polled first, rotated so busy kinds don't starve others
+    next: AtomicUsize,
 }
 
 impl<K: Eq + Hash, V: Kind<K>> KindPool<K, V> {
@@ -132,10 +161,160 @@ impl<K: Eq + Hash, V: Kind<K>> KindPool<K, V> {
         }
     }
 
+    /// Reads values of all kinds matching the filter with one reader
+    ///
+    /// Kinds written after the subscription was created are read as well
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cobra_rs::sync::{Kind, KindPool};
+    ///
+    /// struct Value(u8);
+    ///
+    /// impl Kind<u8> for Value {
+    ///     fn kind(&self) -> u8 {
+    ///         self.0
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let pool = KindPool::new();
+    ///     let subscription = pool.subscribe(32..=63);
+    ///
+    ///     let writer = pool.clone();
+    ///     tokio::spawn(async move { writer.write(Value(40)).await });
+    ///
+    ///     assert_eq!(subscription.read().await.unwrap().accept().0, 40);
+    /// }
+    /// ```
+    pub fn subscribe<F: Into<KindFilter<K>>>(&self, filter: F) -> KindSubscription<K, V> {
+        KindSubscription {
+            state: self.state.clone(),
+            filter: filter.into(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
     /// Closes the pool
     pub async fn close(&self) {
         self.state.close().await;
     }
+
+    /// Closes all kinds except `keep` including kinds created later,
+    /// the kept ones are closed by [`close_kind()`] or [`close()`]
+    ///
+    /// [`close_kind()`]: crate::sync::KindPool::close_kind
+    /// [`close()`]: crate::sync::KindPool::close
+    pub(crate) async fn close_except(&self, keep: &[K]) where K: Clone {
+        // Kept kinds may have no pool yet, it mustn't be created closed
+        for kind in keep {
+            self.state.get_pool(kind.clone()).await;
+        }
+
+        self.state.sealed.store(true, Ordering::SeqCst);
+        for (kind, pool) in self.state.pools.read().await.iter() {
+            if !keep.contains(kind) {
+                pool.close();
+            }
+        }
+        self.state.changed.notify_waiters();
+    }
+
+    /// Closes one kind, readers of other kinds aren't affected
+    pub(crate) async fn close_kind(&self, kind: K) {
+        self.state.get_pool(kind).await.close();
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Kind<K>> KindSubscription<K, V> {
+    /// Reads value of any matching kind
+    ///
+    /// Returns [`None`] if the pool was closed. Readers of a single kind
+    /// and subscriptions take turns for the values of the kind
+    ///
+    /// # Note
+    ///
+    /// This method is cancel safe: if the future is dropped
+    /// before completion, no value is lost
+    ///
+    /// [`None`]: std::option::Option::None
+    pub async fn read(&self) -> Option<PoolGuard<V>> {
+        loop {
+            let changed = self.state.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            if self.state.is_closed().await {
+                return None;
+            }
+
+            let pools: Vec<Pool<V>> = self.state.pools.read().await
+                .iter()
+                .filter(|(kind, _)| self.filter.matches(kind))
+                .map(|(_, pool)| pool.clone())
+                .collect();
+            let mut reads: Vec<_> = pools.iter().map(|pool| Box::pin(pool.read())).collect();
+            let first = self.next.fetch_add(1, Ordering::Relaxed);
+
+            let read = poll_fn(|cx| {
+                if changed.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+
+                let len = reads.len();
+                for i in 0..len {
+                    if let Poll::Ready(value) = reads[(first + i) % len].as_mut().poll(cx) {
+                        return Poll::Ready(Some(value));
+                    }
+                }
+                Poll::Pending
+            }).await;
+
+            // Pools are listed again after a change
+            if let Some(value) = read {
+                return value;
+            }
+        }
+    }
+}
+
+impl<K> KindFilter<K> {
+    /// Matches every kind
+    pub fn any() -> Self {
+        KindFilter::predicate(|_| true)
+    }
+
+    pub fn predicate<F: Fn(&K) -> bool + Send + Sync + 'static>(predicate: F) -> Self {
+        KindFilter {
+            matches: Arc::new(predicate),
+        }
+    }
+
+    pub fn matches(&self, kind: &K) -> bool {
+        (self.matches)(kind)
+    }
+}
+
+impl<K: PartialOrd + Send + Sync + 'static> From<Range<K>> for KindFilter<K> {
+    fn from(range: Range<K>) -> Self {
+        KindFilter::predicate(move |kind| range.contains(kind))
+    }
+}
+
+impl<K: PartialOrd + Send + Sync + 'static> From<RangeInclusive<K>> for KindFilter<K> {
+    fn from(range: RangeInclusive<K>) -> Self {
+        KindFilter::predicate(move |kind| range.contains(kind))
+    }
+}
+
+impl<K> Clone for KindFilter<K> {
+    fn clone(&self) -> Self {
+        KindFilter {
+            matches: self.matches.clone(),
+        }
+    }
 }
 
 impl<K: Eq + Hash, V: Kind<K>> KindPoolState<K, V> {
@@ -143,14 +322,29 @@ impl<K: Eq + Hash, V: Kind<K>> KindPoolState<K, V> {
         KindPoolState {
             pools: RwLock::new(HashMap::with_capacity(KIND_HASHMAP_CAPACITY)),
             closed: RwLock::new(false),
+            sealed: AtomicBool::new(false),
+            changed: Notify::new(),
         }
     }
 
     async fn get_pool(&self, kind: K) -> Pool<V> {
-        self.pools.write().await
-            .entry(kind)
-            .or_insert_with(Pool::new)
-            .clone()
+        let mut pools = self.pools.write().await;
+        let pool = pools.entry(kind)
+            .or_insert_with(|| {
+                let pool = Pool::new();
+                if self.sealed.load(Ordering::SeqCst) {
+                    pool.close();
+                }
+                self.changed.notify_waiters();
+                pool
+            })
+            .clone();
+
+        // Pool created after close() walked the map is closed here
+        if self.is_closed().await {
+            pool.close();
+        }
+        pool
     }
 
     async fn close(&self) {
@@ -158,6 +352,7 @@ impl<K: Eq + Hash, V: Kind<K>> KindPoolState<K, V> {
         for (_, pool) in self.pools.read().await.iter() {
             pool.close();
         }
+        self.changed.notify_waiters();
     }
 
     async fn is_closed(&self) -> bool {
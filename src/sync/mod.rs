@@ -1,5 +1,11 @@
+pub use cancel::*;
+pub use guard_watch::{GuardWatch, HeldGuard};
 pub use kind_pool::*;
 pub use pool::*;
+pub(crate) use poll_slot::*;
 
+mod cancel;
+mod guard_watch;
 mod pool;
 mod kind_pool;
+mod poll_slot;
@@ -0,0 +1,103 @@
+use std::alloc::{self, Layout};
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::ptr::NonNull;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use crate::runtime::BoxFuture;
+
+/// Keeps a future started by a poll-based method until it completes
+///
+/// Lets `poll_*` methods be called repeatedly with `&self`
+/// without losing the operation in progress. Memory of a completed
+/// future is reused by the next one, so repeated operations don't allocate
+pub(crate) struct PollSlot<T> {
+    slot: Mutex<Slot<T>>,
+}
+
+struct Slot<T> {
+    future: Option<BoxFuture<T>>,
+    spare: Option<Spare>,
+}
+
+// Allocation of a dropped future, its memory is uninitialized
+struct Spare {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// Spare memory is owned by the slot only
+unsafe impl Send for Spare {}
+
+impl<T: 'static> PollSlot<T> {
+    pub(crate) fn new() -> Self {
+        PollSlot {
+            slot: Mutex::new(Slot { future: None, spare: None }),
+        }
+    }
+
+    /// Polls the stored future, starting a new one with `start` if there is none
+    pub(crate) fn poll<Fut, F>(&self, cx: &mut Context<'_>, start: F) -> Poll<T>
+        where Fut: Future<Output=T> + Send + 'static,
+              F: FnOnce() -> Fut {
+        let mut slot = self.slot.lock().unwrap();
+        if slot.future.is_none() {
+            let future = slot.boxed(start());
+            slot.future = Some(future);
+        }
+
+        let result = slot.future.as_mut().unwrap().as_mut().poll(cx);
+        if result.is_ready() {
+            slot.recycle();
+        }
+        result
+    }
+}
+
+impl<T: 'static> Slot<T> {
+    // Moves the future into the spare allocation if it fits, boxes it otherwise
+    fn boxed<Fut: Future<Output=T> + Send + 'static>(&mut self, future: Fut) -> BoxFuture<T> {
+        match self.spare.take() {
+            Some(spare) if spare.layout == Layout::new::<Fut>() => {
+                let ptr = spare.ptr.as_ptr().cast::<Fut>();
+                // The memory is owned by the new Box now
+                mem::forget(spare);
+                // The memory was allocated by a Box with the same layout
+                unsafe {
+                    ptr.write(future);
+                    Pin::new_unchecked(Box::from_raw(ptr))
+                }
+            }
+            spare => {
+                drop(spare);
+                Box::pin(future)
+            }
+        }
+    }
+
+    // Drops the completed future, keeping its allocation
+    fn recycle(&mut self) {
+        let future = match self.future.take() {
+            Some(future) => future,
+            None => return,
+        };
+
+        let layout = Layout::for_value(&*future);
+        // Future is dropped in place and never used again, so it isn't moved
+        let ptr = Box::into_raw(unsafe { Pin::into_inner_unchecked(future) });
+        unsafe { ptr.drop_in_place() };
+
+        self.spare = NonNull::new(ptr.cast::<u8>()).map(|ptr| Spare { ptr, layout });
+    }
+}
+
+impl Drop for Spare {
+    fn drop(&mut self) {
+        // Zero-sized futures aren't allocated
+        if self.layout.size() > 0 {
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) };
+        }
+    }
+}
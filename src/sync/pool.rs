@@ -1,7 +1,16 @@
+use std::collections::VecDeque;
+use std::future::{poll_fn, Future};
+use std::mem;
 use std::ops::Deref;
-use std::sync::Arc;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
 
-use tokio::sync::{Notify, RwLock, Semaphore};
+use crate::sync::guard_watch::{self, TakenGuard};
+
+#[cfg(cobra_loom)]
+use loom::sync::{Arc, Mutex, MutexGuard};
+#[cfg(not(cobra_loom))]
+use std::sync::{Arc, Mutex, MutexGuard};
 
 /// Error returned on [`write`] failure
 ///
@@ -19,7 +28,7 @@ impl<T> WriteError<T> {
     pub fn map<F, O: FnOnce(T) -> F>(self, op: O) -> WriteError<F> {
         match self {
             WriteError::Rejected(e) => WriteError::Rejected(op(e)),
-            WriteError::Closed(e) => WriteError::Rejected(op(e)),
+            WriteError::Closed(e) => WriteError::Closed(op(e)),
         }
     }
 }
@@ -28,6 +37,19 @@ impl<T> WriteError<T> {
 ///
 /// Can be used to atomically transfer data between tasks
 ///
+/// # Guarantees
+///
+/// * Values of one writer are in the pool at a time, other writers wait for them
+///   to be answered. [`write_all()`] keeps the values of a batch together
+/// * Every value is read by at most one reader and every writer gets one result:
+///   `Ok` if its value was accepted, [`WriteError::Rejected`] if it was rejected
+///   and [`WriteError::Closed`] if the pool was closed before a reader took it
+/// * Value taken by a reader before [`close()`] is still answered,
+///   readers and writers coming after it get [`None`] and [`WriteError::Closed`]
+/// * Readers and writers are served in the order they started waiting,
+///   so a busy pool can't starve any of them
+/// * Both [`read()`] and [`write()`] are cancel safe
+///
 /// # Example
 ///
 /// ```
@@ -57,16 +79,77 @@ impl<T> WriteError<T> {
 ///     pool.close();
 /// }
 /// ```
+///
+/// [`WriteError::Rejected`]: crate::sync::WriteError::Rejected
+/// [`WriteError::Closed`]: crate::sync::WriteError::Closed
+/// [`close()`]: crate::sync::Pool::close
+/// [`read()`]: crate::sync::Pool::read
+/// [`write()`]: crate::sync::Pool::write
+/// [`write_all()`]: crate::sync::Pool::write_all
+/// [`None`]: std::option::Option::None
 pub struct Pool<T> {
     state: Arc<PoolState<T>>,
 }
 
 struct PoolState<T> {
-    read_semaphore: Semaphore,
-    write_semaphore: Semaphore,
-    response_notifier: Notify,
-    close_notifier: Notify,
-    store: RwLock<Option<T>>,
+    inner: Mutex<Inner<T>>,
+}
+
+struct Inner<T> {
+    slot: Slot<T>,
+    // Values of the slot's writer offered after the current one
+    batch: VecDeque<T>,
+    closed: bool,
+    readers: Waiters,
+    writers: Waiters,
+    // Writer of the value in the slot waiting for the answer
+    responder: Option<Waker>,
+    // Guard of the taken value, recorded only if guards are watched
+    taken: Option<TakenGuard>,
+}
+
+/// Value being transferred, the slot is emptied by its writer only
+enum Slot<T> {
+    Empty,
+
+    /// Value waits for a reader
+    Offered(T),
+
+    /// Reader holds the value, `abandoned` if the writer was cancelled
+    Taken { abandoned: bool },
+
+    /// Reader has answered, contains the rejected value
+    Answered(Option<T>),
+
+    /// Pool was closed before a reader took the value
+    Withdrawn(T),
+}
+
+/// Tickets of tasks waiting for their turn in arrival order,
+/// only the first one is woken
+#[derive(Default)]
+struct Waiters {
+    queue: VecDeque<(u64, Waker)>,
+    next_id: u64,
+}
+
+#[derive(Copy, Clone)]
+enum Role {
+    Reader,
+    Writer,
+}
+
+// Leaves the queue when the waiting future is dropped
+struct Waiter<'a, T> {
+    state: &'a PoolState<T>,
+    role: Role,
+    id: Option<u64>,
+}
+
+// Frees the slot if the writer is cancelled while its value is in the pool
+struct Response<'a, T> {
+    state: &'a PoolState<T>,
+    done: bool,
 }
 
 /// Value returned by [`read`] method
@@ -88,13 +171,50 @@ impl<T> Pool<T> {
     /// Returns [`PoolGuard`], which can be used to accept or reject
     /// the value and [`None`] if the pool was closed
     ///
+    /// # Note
+    ///
+    /// This method is cancel safe: if the future is dropped
+    /// before completion, no value is lost
+    ///
     /// [`None`]: std::option::Option::None
     /// [`PoolGuard`]: crate::transport::pool::PoolGuard
     pub async fn read(&self) -> Option<PoolGuard<T>> {
-        Some(PoolGuard::new(
-            self.state.read_value().await.ok()?,
-            self.state.clone(),
-        ))
+        let mut waiter = Waiter::new(&self.state, Role::Reader);
+        let value = poll_fn(|cx| self.state.poll_read(cx, &mut waiter)).await?;
+
+        Some(PoolGuard::new(value, self.state.clone()))
+    }
+
+    /// Reads up to `max` values available at once, waits for the first one
+    ///
+    /// Values are accepted, so their writers unlock with [`Ok`]. Returns
+    /// [`None`] if the pool was closed. Batches written by [`write_all()`]
+    /// are drained without waiting for their writer
+    ///
+    /// # Note
+    ///
+    /// This method is cancel safe in the same way as [`read()`]
+    ///
+    /// [`Ok`]: std::result::Result::Ok
+    /// [`None`]: std::option::Option::None
+    /// [`write_all()`]: crate::sync::Pool::write_all
+    /// [`read()`]: crate::sync::Pool::read
+    pub async fn drain(&self, max: usize) -> Option<Vec<T>> {
+        let mut waiter = Waiter::new(&self.state, Role::Reader);
+        let mut values = Vec::new();
+        while values.len() < max {
+            if values.is_empty() {
+                values.push(poll_fn(|cx| self.state.poll_read(cx, &mut waiter)).await?);
+            } else {
+                match self.state.try_read() {
+                    Some(value) => values.push(value),
+                    None => break,
+                }
+            }
+            self.state.answer(None);
+        }
+
+        Some(values)
     }
 
     /// Writes value to the pool
@@ -103,90 +223,420 @@ impl<T> Pool<T> {
     /// Returns [`WriteError`] if the value was rejected by another side or
     /// the pool was closed
     ///
+    /// # Note
+    ///
+    /// This method is cancel safe: if the future is dropped before a reader
+    /// took the value, the value is dropped as well, otherwise the answer is ignored
+    ///
     /// [`WriteError`]: crate::transport::pool::WriteError
     pub async fn write(&self, value: T) -> Result<(), WriteError<T>> {
-        self.state
-            .write_value(value)
-            .await
-            .map_err(WriteError::Closed)?;
+        let mut value = Some(value);
+        let mut waiter = Waiter::new(&self.state, Role::Writer);
+        poll_fn(|cx| self.state.poll_offer(cx, &mut waiter, &mut value, &mut VecDeque::new())).await?;
+
+        let mut response = Response { state: &self.state, done: false };
+        self.watched(poll_fn(|cx| self.state.poll_response(cx, &mut response))).await
+    }
+
+    /// Writes values to the pool one after another in one turn
+    ///
+    /// The next value is offered as soon as the previous one is accepted,
+    /// so other writers can't get in between and the writer is woken once
+    /// for the whole batch. Returns the error of the first value that
+    /// wasn't accepted, values after it are dropped
+    ///
+    /// # Note
+    ///
+    /// This method is cancel safe in the same way as [`write()`],
+    /// values which weren't taken by readers are dropped
+    ///
+    /// [`write()`]: crate::sync::Pool::write
+    pub async fn write_all<I: IntoIterator<Item=T>>(&self, values: I) -> Result<(), WriteError<T>> {
+        let mut batch: VecDeque<T> = values.into_iter().collect();
+        let mut value = match batch.pop_front() {
+            Some(value) => Some(value),
+            None => return Ok(()),
+        };
+
+        let mut waiter = Waiter::new(&self.state, Role::Writer);
+        poll_fn(|cx| self.state.poll_offer(cx, &mut waiter, &mut value, &mut batch)).await?;
+
+        let mut response = Response { state: &self.state, done: false };
+        self.watched(poll_fn(|cx| self.state.poll_response(cx, &mut response))).await
+    }
+
+    /// The same as [`write()`], but the value is withdrawn and dropped
+    /// if `cancelled` completes before a reader takes it
+    ///
+    /// Returns [`None`] if the value was withdrawn. Once a reader has
+    /// taken the value, `cancelled` is ignored and the answer is awaited
+    ///
+    /// [`write()`]: crate::sync::Pool::write
+    /// [`None`]: std::option::Option::None
+    pub async fn write_cancellable<F: Future<Output=()>>(&self, value: T, cancelled: F) -> Option<Result<(), WriteError<T>>> {
+        let mut cancelled = pin!(cancelled);
+        let mut value = Some(value);
+        let mut waiter = Waiter::new(&self.state, Role::Writer);
+
+        let offered = poll_fn(|cx| {
+            if cancelled.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(None);
+            }
+            self.state.poll_offer(cx, &mut waiter, &mut value, &mut VecDeque::new()).map(Some)
+        }).await?;
+        if let Err(err) = offered {
+            return Some(Err(err));
+        }
 
-        self.state
-            .wait_response()
-            .await
-            .map_err(WriteError::Closed)?
-            .map_or(Ok(()), |value| Err(WriteError::Rejected(value)))
+        let mut response = Response { state: &self.state, done: false };
+        let mut cancellable = true;
+        self.watched(poll_fn(|cx| {
+            // Completed future mustn't be polled again
+            if cancellable && cancelled.as_mut().poll(cx).is_ready() {
+                cancellable = false;
+                if self.state.withdraw(&mut response) {
+                    return Poll::Ready(None);
+                }
+            }
+            self.state.poll_response(cx, &mut response).map(Some)
+        })).await
     }
 
     /// Closes the pool
+    ///
+    /// Value offered by a writer is returned to it with [`WriteError::Closed`],
+    /// value already taken by a reader is answered as usual
+    ///
+    /// # Note
+    ///
+    /// Returns immediately and doesn't wait for readers to answer taken values.
+    /// Callers which relied on close blocking until the answer should await
+    /// the pending [`write()`] instead, it completes once the value is answered
+    ///
+    /// [`WriteError::Closed`]: crate::sync::WriteError::Closed
+    /// [`write()`]: crate::sync::Pool::write
     pub fn close(&self) {
         self.state.close();
     }
+
+    // Awaits the answer, reporting its guard if it is held too long, see [`GuardWatch`]
+    async fn watched<F: Future>(&self, answer: F) -> F::Output {
+        match guard_watch::current() {
+            Some(watch) => watch.watch(answer, || self.state.lock().taken.clone()).await,
+            None => answer.await,
+        }
+    }
 }
 
 impl<T> PoolState<T> {
     fn new() -> Self {
         PoolState {
-            read_semaphore: Semaphore::new(0),
-            write_semaphore: Semaphore::new(1),
-            response_notifier: Notify::new(),
-            close_notifier: Notify::new(),
-            store: RwLock::new(None),
+            inner: Mutex::new(Inner {
+                slot: Slot::Empty,
+                batch: VecDeque::new(),
+                closed: false,
+                readers: Waiters::default(),
+                writers: Waiters::default(),
+                responder: None,
+                taken: None,
+            }),
         }
     }
 
-    async fn read_value(&self) -> Result<T, ()> {
-        self.read_semaphore.acquire().await.or(Err(()))?.forget();
+    fn lock(&self) -> MutexGuard<'_, Inner<T>> {
+        self.inner.lock().unwrap()
+    }
 
-        // Always Some()
-        Ok(self.take().await.unwrap())
+    fn poll_read(&self, cx: &mut Context<'_>, waiter: &mut Waiter<'_, T>) -> Poll<Option<T>> {
+        let mut inner = self.lock();
+
+        if inner.closed {
+            waiter.finish(&mut inner);
+            return Poll::Ready(None);
+        }
+
+        let first = inner.readers.register(&mut waiter.id, cx.waker());
+        if !first || !matches!(inner.slot, Slot::Offered(_)) {
+            return Poll::Pending;
+        }
+
+        let value = match mem::replace(&mut inner.slot, Slot::Taken { abandoned: false }) {
+            Slot::Offered(value) => value,
+            _ => unreachable!("value is checked above"),
+        };
+        inner.taken = guard_watch::current().map(|_| TakenGuard::capture());
+        waiter.finish(&mut inner);
+        Poll::Ready(Some(value))
     }
 
-    async fn write_value(&self, value: T) -> Result<(), T> {
-        match self.write_semaphore.acquire().await {
-            Ok(permit) => {
-                permit.forget();
+    // The rest of the batch is offered after the value
+    fn poll_offer(&self,
+                  cx: &mut Context<'_>,
+                  waiter: &mut Waiter<'_, T>,
+                  value: &mut Option<T>,
+                  batch: &mut VecDeque<T>) -> Poll<Result<(), WriteError<T>>> {
+        let mut inner = self.lock();
+
+        if inner.closed {
+            waiter.finish(&mut inner);
+            return Poll::Ready(Err(WriteError::Closed(value.take().unwrap())));
+        }
+        let first = inner.writers.register(&mut waiter.id, cx.waker());
+        if !first || !matches!(inner.slot, Slot::Empty) {
+            return Poll::Pending;
+        }
 
-                self.share(value).await;
-                self.read_semaphore.add_permits(1);
+        inner.slot = Slot::Offered(value.take().unwrap());
+        inner.batch = mem::take(batch);
+        waiter.finish(&mut inner);
+        let reader = inner.readers.first();
+        drop(inner);
 
-                Ok(())
+        wake(reader);
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_response(&self, cx: &mut Context<'_>, response: &mut Response<'_, T>) -> Poll<Result<(), WriteError<T>>> {
+        let mut inner = self.lock();
+
+        let result = match mem::replace(&mut inner.slot, Slot::Empty) {
+            Slot::Answered(None) => Ok(()),
+            Slot::Answered(Some(value)) => Err(WriteError::Rejected(value)),
+            Slot::Withdrawn(value) => Err(WriteError::Closed(value)),
+            slot => {
+                inner.slot = slot;
+                inner.responder = Some(cx.waker().clone());
+                return Poll::Pending;
             }
+        };
+
+        response.done = true;
+        inner.responder = None;
+        let batch = mem::take(&mut inner.batch);
+        let writer = inner.writers.first();
+        drop(inner);
+
+        wake(writer);
+        drop(batch);
+        Poll::Ready(result)
+    }
+
+    // Takes the offered value if no other reader waits for it
+    fn try_read(&self) -> Option<T> {
+        let mut inner = self.lock();
+        if inner.closed || !inner.readers.queue.is_empty() {
+            return None;
+        }
 
-            Err(_) => Err(value),
+        match mem::replace(&mut inner.slot, Slot::Taken { abandoned: false }) {
+            Slot::Offered(value) => {
+                inner.taken = guard_watch::current().map(|_| TakenGuard::capture());
+                Some(value)
+            }
+            slot => {
+                inner.slot = slot;
+                None
+            }
         }
     }
 
-    async fn wait_response(&self) -> Result<Option<T>, T> {
-        let closed = tokio::select! {
-            _ = self.response_notifier.notified() => { false }
-            _ = self.close_notifier.notified() => { true }
+    // Drops the offered value, returns false if a reader has already taken it
+    fn withdraw(&self, response: &mut Response<'_, T>) -> bool {
+        let mut inner = self.lock();
+
+        let value = match mem::replace(&mut inner.slot, Slot::Empty) {
+            Slot::Offered(value) => value,
+            slot => {
+                inner.slot = slot;
+                return false;
+            }
+        };
+
+        response.done = true;
+        inner.responder = None;
+        let writer = inner.writers.first();
+        drop(inner);
+
+        wake(writer);
+        drop(value);
+        true
+    }
+
+    // Returns the value to its writer, or frees the slot if the writer is gone
+    fn answer(&self, rejected: Option<T>) {
+        let mut inner = self.lock();
+
+        let mut batch = VecDeque::new();
+        inner.taken = None;
+        let waker = match inner.slot {
+            Slot::Taken { abandoned: false } => {
+                // Accepted value is followed by the next one of the batch
+                let next = if rejected.is_none() { inner.batch.pop_front() } else { None };
+                match next {
+                    Some(next) if inner.closed => {
+                        inner.slot = Slot::Withdrawn(next);
+                        inner.responder.take()
+                    }
+                    Some(next) => {
+                        inner.slot = Slot::Offered(next);
+                        inner.readers.first()
+                    }
+                    None => {
+                        inner.slot = Slot::Answered(rejected);
+                        inner.responder.take()
+                    }
+                }
+            }
+            Slot::Taken { abandoned: true } => {
+                inner.slot = Slot::Empty;
+                batch = mem::take(&mut inner.batch);
+                inner.writers.first()
+            }
+            _ => unreachable!("pool value answered twice"),
         };
+        drop(inner);
 
-        if closed {
-            Err(self.take().await.unwrap())
-        } else {
-            let value = self.take().await;
-            self.write_semaphore.add_permits(1);
-            Ok(value)
+        wake(waker);
+        drop(batch);
+    }
+
+    fn close(&self) {
+        let mut inner = self.lock();
+        if inner.closed {
+            return;
         }
+        inner.closed = true;
+
+        inner.slot = match mem::replace(&mut inner.slot, Slot::Empty) {
+            Slot::Offered(value) => Slot::Withdrawn(value),
+            slot => slot,
+        };
+        let mut wakers = inner.readers.wake_all();
+        wakers.extend(inner.writers.wake_all());
+        wakers.extend(inner.responder.take());
+        drop(inner);
+
+        wakers.into_iter().for_each(Waker::wake);
     }
+}
 
-    async fn share(&self, value: T) {
-        *self.store.write().await = Some(value);
+impl Waiters {
+    // Takes a ticket on the first poll, later polls keep its place.
+    // Returns true if the waiter is the first in the queue
+    fn register(&mut self, id: &mut Option<u64>, waker: &Waker) -> bool {
+        let registered = id.and_then(|id| self.queue.iter_mut().find(|(waiter, _)| *waiter == id));
+
+        match registered {
+            Some((_, registered)) => {
+                if !registered.will_wake(waker) {
+                    *registered = waker.clone();
+                }
+            }
+            None => {
+                *id = Some(self.next_id);
+                self.queue.push_back((self.next_id, waker.clone()));
+                self.next_id += 1;
+            }
+        }
+
+        self.queue.front().map(|(first, _)| *first) == *id
     }
 
-    async fn take(&self) -> Option<T> {
-        let mut store = self.store.write().await;
-        store.take()
+    // The first waiter is woken without leaving the queue,
+    // it leaves once it has taken its turn
+    fn first(&self) -> Option<Waker> {
+        self.queue.front().map(|(_, waker)| waker.clone())
     }
 
-    fn close(&self) {
-        if let Ok(permit) =  self.read_semaphore.try_acquire() {
-            permit.forget();
-            self.close_notifier.notify_one();
+    fn wake_all(&mut self) -> Vec<Waker> {
+        self.queue.drain(..).map(|(_, waker)| waker).collect()
+    }
+
+    // Removes the waiter, returns true if it was the first
+    fn remove(&mut self, id: u64) -> bool {
+        match self.queue.iter().position(|(waiter, _)| *waiter == id) {
+            Some(position) => {
+                self.queue.remove(position);
+                position == 0
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T> Inner<T> {
+    fn waiters(&mut self, role: Role) -> &mut Waiters {
+        match role {
+            Role::Reader => &mut self.readers,
+            Role::Writer => &mut self.writers,
+        }
+    }
+}
+
+impl<'a, T> Waiter<'a, T> {
+    fn new(state: &'a PoolState<T>, role: Role) -> Self {
+        Waiter { state, role, id: None }
+    }
+
+    // Called once the future has completed under the lock
+    fn finish(&mut self, inner: &mut Inner<T>) {
+        if let Some(id) = self.id.take() {
+            inner.waiters(self.role).remove(id);
         }
-        self.read_semaphore.close();
-        self.write_semaphore.close();
+    }
+}
+
+impl<T> Drop for Waiter<'_, T> {
+    fn drop(&mut self) {
+        let id = match self.id.take() {
+            Some(id) => id,
+            None => return,
+        };
+
+        let mut inner = self.state.lock();
+        let waiters = inner.waiters(self.role);
+
+        // The first waiter may have been woken, so the turn is passed to the next one
+        let next = if waiters.remove(id) { waiters.first() } else { None };
+        drop(inner);
+
+        wake(next);
+    }
+}
+
+impl<T> Drop for Response<'_, T> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        let mut inner = self.state.lock();
+        inner.responder = None;
+
+        // Values of the batch which weren't taken are dropped with the writer
+        let batch = match inner.slot {
+            Slot::Taken { .. } => VecDeque::new(),
+            _ => mem::take(&mut inner.batch),
+        };
+        let (value, writer) = match mem::replace(&mut inner.slot, Slot::Empty) {
+            Slot::Taken { .. } => {
+                inner.slot = Slot::Taken { abandoned: true };
+                (None, None)
+            }
+            Slot::Offered(value) | Slot::Withdrawn(value) | Slot::Answered(Some(value)) => (Some(value), inner.writers.first()),
+            Slot::Answered(None) | Slot::Empty => (None, inner.writers.first()),
+        };
+        drop(inner);
+
+        wake(writer);
+        drop(value);
+        drop(batch);
+    }
+}
+
+fn wake(waker: Option<Waker>) {
+    if let Some(waker) = waker {
+        waker.wake();
     }
 }
 
@@ -209,7 +659,7 @@ impl<T> PoolGuard<T> {
     /// [`Ok`]: std::result::Result::Ok
     /// [`PoolGuard`]: crate::transport::pool::PoolGuard
     pub fn accept(mut self) -> T {
-        self.state.response_notifier.notify_one();
+        self.state.answer(None);
 
         // Always Some()
         self.value.take().unwrap()
@@ -221,9 +671,7 @@ impl<T> PoolGuard<T> {
     ///
     /// [`WriteError::Rejected`]: crate::transport::sync::WriteError
     pub async fn reject(mut self) {
-        self.state.share(self.value.take().unwrap()).await;
-
-        self.state.response_notifier.notify_one();
+        self.state.answer(self.value.take());
     }
 }
 
@@ -254,7 +702,7 @@ impl<T> Deref for PoolGuard<T> {
 impl<T> Drop for PoolGuard<T> {
     fn drop(&mut self) {
         if self.value.take().is_some() {
-            self.state.response_notifier.notify_one();
+            self.state.answer(None);
         }
     }
 }
@@ -0,0 +1,264 @@
+use std::fmt::Write as _;
+use std::future::Future;
+
+use crate::builder::kind_conn::KindConn;
+use crate::rng::XorShift;
+use crate::sync::WriteError;
+
+/// Length of the trace context in the package header
+pub const TRACE_CONTEXT_LEN: usize = 25;
+
+// First byte of the package header
+const NO_CONTEXT: u8 = 0;
+const WITH_CONTEXT: u8 = 1;
+
+// Flag of sampled traces, see W3C Trace Context
+const SAMPLED: u8 = 0x01;
+
+tokio::task_local! {
+    static CURRENT: TraceContext;
+}
+
+/// Identifiers of a distributed trace, see W3C Trace Context
+///
+/// Context is carried by packages of [`TracedConn`] in a compact binary
+/// form and converted to the `traceparent` header with [`traceparent()`]
+///
+/// [`TracedConn`]: crate::trace::TracedConn
+/// [`traceparent()`]: crate::trace::TraceContext::traceparent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    /// Id of the span which sent the package
+    pub span_id: [u8; 8],
+    pub flags: u8,
+}
+
+/// Package read by [`TracedConn`] with the context of its sender
+///
+/// [`TracedConn`]: crate::trace::TracedConn
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracedPackage {
+    /// [`None`] if the sender wasn't traced
+    ///
+    /// [`None`]: std::option::Option::None
+    pub context: Option<TraceContext>,
+    pub package: Vec<u8>,
+}
+
+/// Connection of one kind propagating trace context with its packages
+///
+/// Every package starts with a header carrying the context of the writing
+/// task, see [`TraceContext::scope()`], so both sides of the kind have to
+/// use this wrapper. Handling of a read package is traced by running it
+/// in the scope of [`TraceContext::child()`]. With the `tracing` feature
+/// the context is available as a span, see [`TraceContext::span()`]
+///
+/// # Example
+///
+/// ```no_run
+/// use cobra_rs::builder::kind_conn::KindConn;
+/// use cobra_rs::trace::{TraceContext, TracedConn};
+///
+/// async fn handle(conn: KindConn) {
+///     let conn = TracedConn::new(conn);
+///
+///     while let Some(traced) = conn.read().await {
+///         let context = traced.context
+///             .map(|context| context.child())
+///             .unwrap_or_else(|| TraceContext::new_root(true));
+///
+///         // Packages written in the scope continue the trace
+///         context.scope(conn.write(traced.package)).await.unwrap();
+///     }
+/// }
+/// ```
+///
+/// [`TraceContext::scope()`]: crate::trace::TraceContext::scope
+/// [`TraceContext::child()`]: crate::trace::TraceContext::child
+/// [`TraceContext::span()`]: crate::trace::TraceContext::span
+pub struct TracedConn {
+    conn: KindConn,
+}
+
+impl TraceContext {
+    /// Starts a new trace
+    pub fn new_root(sampled: bool) -> Self {
+        let mut rng = XorShift::from_clock();
+        let mut trace_id = [0; 16];
+        trace_id[..8].copy_from_slice(&rng.next_u64().to_be_bytes());
+        trace_id[8..].copy_from_slice(&rng.next_u64().to_be_bytes());
+
+        TraceContext {
+            trace_id,
+            span_id: rng.next_u64().to_be_bytes(),
+            flags: if sampled { SAMPLED } else { 0 },
+        }
+    }
+
+    /// Returns context of a new span in the same trace
+    pub fn child(&self) -> Self {
+        TraceContext {
+            span_id: XorShift::from_clock().next_u64().to_be_bytes(),
+            ..*self
+        }
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        self.flags & SAMPLED != 0
+    }
+
+    /// Returns context of the current task, see [`scope()`]
+    ///
+    /// [`scope()`]: crate::trace::TraceContext::scope
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(|context| *context).ok()
+    }
+
+    /// Runs the future with the context as the current one
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+
+    /// Returns value of the W3C `traceparent` header
+    pub fn traceparent(&self) -> String {
+        let mut value = String::with_capacity(55);
+        value.push_str("00-");
+        self.trace_id.iter().for_each(|byte| { let _ = write!(value, "{:02x}", byte); });
+        value.push('-');
+        self.span_id.iter().for_each(|byte| { let _ = write!(value, "{:02x}", byte); });
+        let _ = write!(value, "-{:02x}", self.flags);
+        value
+    }
+
+    /// Parses value of the W3C `traceparent` header
+    ///
+    /// Returns [`None`] if the value is malformed or its ids are zero
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn from_traceparent(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let version = parts.next()?;
+        let (trace_id, span_id, flags) = (parts.next()?, parts.next()?, parts.next()?);
+        // Future versions may append fields
+        if version.len() != 2 || version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+
+        let mut context = TraceContext { trace_id: [0; 16], span_id: [0; 8], flags: 0 };
+        decode_hex(trace_id, &mut context.trace_id)?;
+        decode_hex(span_id, &mut context.span_id)?;
+        let mut flag = [0];
+        decode_hex(flags, &mut flag)?;
+        context.flags = flag[0];
+
+        context.is_valid().then_some(context)
+    }
+
+    /// Returns span recording ids of the context
+    #[cfg(feature = "tracing")]
+    pub fn span(&self) -> tracing::Span {
+        tracing::info_span!("cobra.trace", traceparent = %self.traceparent())
+    }
+
+    fn encode(&self) -> [u8; TRACE_CONTEXT_LEN] {
+        let mut encoded = [0; TRACE_CONTEXT_LEN];
+        encoded[..16].copy_from_slice(&self.trace_id);
+        encoded[16..24].copy_from_slice(&self.span_id);
+        encoded[24] = self.flags;
+        encoded
+    }
+
+    fn decode(encoded: &[u8]) -> Option<Self> {
+        if encoded.len() != TRACE_CONTEXT_LEN {
+            return None;
+        }
+
+        let mut context = TraceContext { trace_id: [0; 16], span_id: [0; 8], flags: encoded[24] };
+        context.trace_id.copy_from_slice(&encoded[..16]);
+        context.span_id.copy_from_slice(&encoded[16..24]);
+        context.is_valid().then_some(context)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.trace_id != [0; 16] && self.span_id != [0; 8]
+    }
+}
+
+impl TracedConn {
+    pub fn new(conn: KindConn) -> Self {
+        TracedConn { conn }
+    }
+
+    pub fn get_ref(&self) -> &KindConn {
+        &self.conn
+    }
+
+    /// Writes the package with the context of the current task
+    ///
+    /// See [`KindConn::write()`]
+    ///
+    /// [`KindConn::write()`]: crate::builder::kind_conn::KindConn::write
+    pub async fn write(&self, package: Vec<u8>) -> Result<(), WriteError<Vec<u8>>> {
+        let context = TraceContext::current();
+
+        let mut traced = Vec::with_capacity(1 + TRACE_CONTEXT_LEN + package.len());
+        match context {
+            Some(context) => {
+                #[cfg(feature = "tracing")]
+                tracing::trace!(traceparent = %context.traceparent(), "trace context injected");
+
+                traced.push(WITH_CONTEXT);
+                traced.extend_from_slice(&context.encode());
+            }
+            None => traced.push(NO_CONTEXT),
+        }
+        let header = traced.len();
+        traced.extend_from_slice(&package);
+
+        self.conn.write(traced).await
+            .map_err(|err| err.map(|mut traced| traced.split_off(header)))
+    }
+
+    /// Reads package with the context of its sender
+    ///
+    /// Packages with a malformed header are skipped.
+    /// See [`KindConn::read()`]
+    ///
+    /// [`KindConn::read()`]: crate::builder::kind_conn::KindConn::read
+    pub async fn read(&self) -> Option<TracedPackage> {
+        loop {
+            let mut package = self.conn.read().await?;
+
+            let (context, header) = match package.first() {
+                Some(&NO_CONTEXT) => (None, 1),
+                Some(&WITH_CONTEXT) => match package.get(1..1 + TRACE_CONTEXT_LEN).and_then(TraceContext::decode) {
+                    Some(context) => (Some(context), 1 + TRACE_CONTEXT_LEN),
+                    None => continue,
+                },
+                _ => continue,
+            };
+
+            #[cfg(feature = "tracing")]
+            if let Some(context) = &context {
+                tracing::trace!(traceparent = %context.traceparent(), "trace context extracted");
+            }
+
+            return Some(TracedPackage {
+                context,
+                package: package.split_off(header),
+            });
+        }
+    }
+}
+
+fn decode_hex(hex: &str, bytes: &mut [u8]) -> Option<()> {
+    if hex.len() != bytes.len() * 2 || !hex.is_ascii() {
+        return None;
+    }
+
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(())
+}
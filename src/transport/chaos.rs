@@ -0,0 +1,322 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+
+use crate::builder::builder::ConnProvider;
+use crate::builder::stats::TransportStats;
+use crate::config::PartialConfig;
+use crate::mem::{Frame, HEADER_BYTES};
+use crate::protocol::Encoding;
+use crate::rng::XorShift;
+use crate::runtime;
+use crate::sync::{Kind, WriteError};
+use crate::transport::close_cause::CloseCause;
+
+/// Disturbances injected by [`ChaosConn`]
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use cobra_rs::transport::chaos::ChaosConfig;
+///
+/// let config = ChaosConfig::new()
+///     .set_latency(Duration::from_millis(50))
+///     .set_jitter(Duration::from_millis(10))
+///     .set_drop_rate(0.01);
+/// ```
+///
+/// [`ChaosConn`]: crate::transport::chaos::ChaosConn
+#[derive(Debug, Clone)]
+pub struct ChaosConfig {
+    latency: Duration,
+    jitter: Duration,
+    drop_rate: f64,
+    duplicate_rate: f64,
+    reorder_rate: f64,
+    seed: Option<u64>,
+}
+
+/// [`ConnProvider`] wrapper disturbing written frames
+///
+/// Every written frame is delayed by the latency plus a random jitter,
+/// may be dropped, duplicated or swapped with the next written frame.
+/// Read side isn't touched, wrap both ends of the connection to
+/// disturb both directions
+///
+/// # Note
+///
+/// Writes return as soon as the frame is scheduled, so they don't
+/// wait for the inner connection. [`flush()`] and [`shutdown_write()`]
+/// wait for scheduled frames, [`close()`] drops them like a network would
+///
+/// [`ConnProvider`]: crate::builder::builder::ConnProvider
+/// [`flush()`]: crate::builder::builder::ConnProvider::flush
+/// [`shutdown_write()`]: crate::builder::builder::ConnProvider::shutdown_write
+/// [`close()`]: crate::builder::builder::ConnProvider::close
+pub struct ChaosConn<P: ConnProvider> {
+    inner: Arc<P>,
+    config: ChaosConfig,
+    rng: Mutex<XorShift>,
+    // Frame waiting to be sent after the next one
+    held: Mutex<Option<Frame>>,
+    // Earliest send time of the next frame, keeps order of delayed frames
+    last_deadline: Mutex<Instant>,
+    sender: UnboundedSender<Command>,
+}
+
+enum Command {
+    Write { deadline: Instant, frame: Frame, urgent: bool },
+    Flush(oneshot::Sender<()>),
+}
+
+impl ChaosConfig {
+    /// Creates config without any disturbances
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets delay of every frame
+    pub fn set_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Sets maximum random delay added to the latency
+    pub fn set_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets probability of a frame being dropped
+    pub fn set_drop_rate(mut self, rate: f64) -> Self {
+        self.drop_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets probability of a frame being sent twice
+    pub fn set_duplicate_rate(mut self, rate: f64) -> Self {
+        self.duplicate_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets probability of a frame being sent after the next written frame
+    ///
+    /// Meant for the transports without ordering guarantees,
+    /// stream transports never reorder frames
+    pub fn set_reorder_rate(mut self, rate: f64) -> Self {
+        self.reorder_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets seed of the random generator, so disturbances are reproducible
+    ///
+    /// By default the seed is taken from the current time
+    pub fn set_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        ChaosConfig {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            drop_rate: 0.0,
+            duplicate_rate: 0.0,
+            reorder_rate: 0.0,
+            seed: None,
+        }
+    }
+}
+
+impl<P: 'static + ConnProvider> ChaosConn<P> {
+    /// Wraps the connection
+    pub fn new(inner: P, config: ChaosConfig) -> Self {
+        let inner = Arc::new(inner);
+        let (sender, receiver) = unbounded_channel();
+        let rng = config.seed.map_or_else(XorShift::from_clock, XorShift::new);
+
+        runtime::spawn(ChaosConn::deliver(inner.clone(), receiver));
+
+        ChaosConn {
+            inner,
+            config,
+            rng: Mutex::new(rng),
+            held: Mutex::new(None),
+            last_deadline: Mutex::new(Instant::now()),
+            sender,
+        }
+    }
+
+    /// Returns wrapped connection
+    pub fn get_ref(&self) -> &P {
+        &self.inner
+    }
+
+    async fn deliver(inner: Arc<P>, mut receiver: UnboundedReceiver<Command>) {
+        while let Some(command) = receiver.recv().await {
+            match command {
+                Command::Write { deadline, frame, urgent } => {
+                    let delay = deadline.saturating_duration_since(Instant::now());
+                    if delay > Duration::ZERO {
+                        runtime::sleep(delay).await;
+                    }
+
+                    let result = if urgent {
+                        inner.write_urgent(frame).await
+                    } else {
+                        inner.write(frame).await
+                    };
+                    if result.is_err() {
+                        return;
+                    }
+                }
+                Command::Flush(done) => {
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+
+    fn schedule(&self, frame: Frame, urgent: bool) -> Result<(), WriteError<Frame>> {
+        let (drop, duplicate, reorder, delay) = {
+            let mut rng = self.rng.lock().unwrap();
+            (
+                rng.chance(self.config.drop_rate),
+                rng.chance(self.config.duplicate_rate),
+                rng.chance(self.config.reorder_rate),
+                self.config.latency + self.config.jitter.mul_f64(rng.next_f64()),
+            )
+        };
+
+        if drop {
+            return Ok(());
+        }
+        if reorder {
+            let mut held = self.held.lock().unwrap();
+            if held.is_none() {
+                *held = Some(frame);
+                return Ok(());
+            }
+        }
+
+        let duplicate = duplicate.then(|| Frame::create(frame.kind(), &frame[HEADER_BYTES..]));
+        let held = self.held.lock().unwrap().take();
+
+        for frame in std::iter::once(frame).chain(duplicate).chain(held) {
+            self.send(frame, delay, urgent)?;
+        }
+        Ok(())
+    }
+
+    fn send(&self, frame: Frame, delay: Duration, urgent: bool) -> Result<(), WriteError<Frame>> {
+        let mut last_deadline = self.last_deadline.lock().unwrap();
+        let deadline = (Instant::now() + delay).max(*last_deadline);
+        *last_deadline = deadline;
+
+        self.sender
+            .send(Command::Write { deadline, frame, urgent })
+            .map_err(|error| match error.0 {
+                Command::Write { frame, .. } => WriteError::Closed(frame),
+                Command::Flush(_) => unreachable!(),
+            })
+    }
+
+    // Waits until frames scheduled so far are written to the inner connection
+    async fn drain(&self) {
+        if let Some(frame) = self.held.lock().unwrap().take() {
+            let _ = self.send(frame, self.config.latency, false);
+        }
+
+        let (done, wait) = oneshot::channel();
+        if self.sender.send(Command::Flush(done)).is_ok() {
+            let _ = wait.await;
+        }
+    }
+}
+
+#[async_trait]
+impl<P: 'static + ConnProvider> ConnProvider for ChaosConn<P> {
+    async fn read(&self, kind: u8) -> Option<Frame> {
+        self.inner.read(kind).await
+    }
+
+    async fn write(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        if self.inner.is_close().await.is_some() {
+            return Err(WriteError::Closed(frame));
+        }
+        self.schedule(frame, false)
+    }
+
+    async fn write_urgent(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        if self.inner.is_close().await.is_some() {
+            return Err(WriteError::Closed(frame));
+        }
+        self.schedule(frame, true)
+    }
+
+    async fn flush(&self) {
+        self.drain().await;
+        self.inner.flush().await
+    }
+
+    fn stats(&self) -> TransportStats {
+        self.inner.stats()
+    }
+
+    fn handshake_complete(&self) {
+        self.inner.handshake_complete()
+    }
+
+    fn set_control_encoding(&self, encoding: Encoding) {
+        self.inner.set_control_encoding(encoding)
+    }
+
+    fn reconfigure(&self, config: &PartialConfig) {
+        self.inner.reconfigure(config)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    async fn readable(&self) {
+        self.inner.readable().await
+    }
+
+    async fn close(&self, code: u8) {
+        self.inner.close(code).await
+    }
+
+    async fn close_with_reason(&self, code: u8, reason: &str) {
+        self.inner.close_with_reason(code, reason).await
+    }
+
+    async fn shutdown_write(&self) {
+        self.drain().await;
+        self.inner.shutdown_write().await
+    }
+
+    async fn is_close(&self) -> Option<u8> {
+        self.inner.is_close().await
+    }
+
+    async fn close_reason(&self) -> Option<String> {
+        self.inner.close_reason().await
+    }
+
+    async fn close_cause(&self) -> Option<CloseCause> {
+        self.inner.close_cause().await
+    }
+}
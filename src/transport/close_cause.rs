@@ -0,0 +1,59 @@
+use std::io;
+
+/// Why the connection stopped, returned by [`ConnProvider::close_cause()`]
+///
+/// Lets operators tell a reset from a timeout or a clean EOF, while
+/// the application only sees reads returning [`None`]
+///
+/// # Example
+///
+/// ```no_run
+/// use std::io;
+///
+/// use cobra_rs::builder::builder::ConnProvider;
+/// use cobra_rs::transport::close_cause::CloseCause;
+/// use cobra_rs::transport::tcp::Conn;
+///
+/// async fn report(conn: &Conn) {
+///     match conn.close_cause().await {
+///         Some(CloseCause::Io { kind: io::ErrorKind::ConnectionReset, .. }) => println!("peer reset the connection"),
+///         Some(CloseCause::Io { kind: io::ErrorKind::TimedOut, .. }) => println!("connection timed out"),
+///         Some(CloseCause::Eof) => println!("peer closed the socket"),
+///         Some(cause) => println!("connection closed: {:?}", cause),
+///         None => println!("connection is open"),
+///     }
+/// }
+/// ```
+///
+/// [`ConnProvider::close_cause()`]: crate::builder::builder::ConnProvider::close_cause
+/// [`None`]: std::option::Option::None
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseCause {
+    /// Connection was closed by this side, see [`ConnProvider::is_close()`]
+    /// for the close code
+    ///
+    /// [`ConnProvider::is_close()`]: crate::builder::builder::ConnProvider::is_close
+    Local,
+
+    /// Peer closed the connection with a close frame
+    Peer,
+
+    /// Peer closed the socket without a close frame
+    Eof,
+
+    /// Reading from or writing to the socket failed
+    Io {
+        kind: io::ErrorKind,
+        /// Error code of the operating system, if there is one
+        os_error: Option<i32>,
+    },
+}
+
+impl From<&io::Error> for CloseCause {
+    fn from(error: &io::Error) -> Self {
+        CloseCause::Io {
+            kind: error.kind(),
+            os_error: error.raw_os_error(),
+        }
+    }
+}
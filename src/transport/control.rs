@@ -0,0 +1,160 @@
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::builder::kind_conn::close_code::CloseCode;
+use crate::mem::{Frame, HEADER_BYTES, MAX_BODY_LEN};
+use crate::protocol::{ControlMessage, Encoding};
+use crate::sync::Kind;
+
+/// Kind reserved for transport control frames
+///
+/// Frames of this kind are handled by the transport
+/// and never reach the application
+pub const CONTROL_KIND: u8 = 0;
+
+/// Maximum length of the close reason in bytes, longer reasons are cut
+pub const MAX_CLOSE_REASON_LEN: usize = MAX_BODY_LEN - 2;
+
+const CLOSE: u8 = 1;
+const CLOSE_ACK: u8 = 2;
+const SHUTDOWN_WRITE: u8 = 3;
+const PROBE: u8 = 4;
+const PROBE_ACK: u8 = 5;
+
+// CBOR maps start with a byte of this range, fixed layouts start with their type
+const CBOR_MAPS: std::ops::RangeInclusive<u8> = 0xA0..=0xBF;
+
+/// Transport-level control message
+///
+/// Encoded as a frame of [`CONTROL_KIND`] with the body
+/// `[type: 1 byte][payload]`. Since protocol version 10 [`Close`] and
+/// [`ShutdownWrite`] are CBOR [`ControlMessage`]s, see [`encode_as()`].
+/// Both layouts are decoded in every version
+///
+/// [`CONTROL_KIND`]: crate::transport::control::CONTROL_KIND
+/// [`Close`]: crate::transport::control::ControlFrame::Close
+/// [`ShutdownWrite`]: crate::transport::control::ControlFrame::ShutdownWrite
+/// [`ControlMessage`]: crate::protocol::ControlMessage
+/// [`encode_as()`]: crate::transport::control::ControlFrame::encode_as
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlFrame {
+    /// Sender closes the connection, payload is `[code: 1 byte][reason: UTF-8]`
+    ///
+    /// Reason is cut to [`MAX_CLOSE_REASON_LEN`] bytes on a char boundary
+    ///
+    /// [`MAX_CLOSE_REASON_LEN`]: crate::transport::control::MAX_CLOSE_REASON_LEN
+    Close { code: CloseCode, reason: String },
+
+    /// Acknowledges received [`Close`]
+    ///
+    /// [`Close`]: crate::transport::control::ControlFrame::Close
+    CloseAck,
+
+    /// Sender won't write frames anymore but keeps reading
+    ShutdownWrite,
+
+    /// Sender measures the round trip, payload is `[id: 4 bytes]`
+    ///
+    /// Peers which don't know the message ignore it
+    Probe { id: u32 },
+
+    /// Answers [`Probe`] with its id
+    ///
+    /// [`Probe`]: crate::transport::control::ControlFrame::Probe
+    ProbeAck { id: u32 },
+}
+
+impl ControlFrame {
+    /// Encodes message to a frame of the encoding agreed with the peer
+    ///
+    /// [`Encoding::Cbor`] applies only to [`Close`] and [`ShutdownWrite`],
+    /// other messages keep their fixed layout
+    ///
+    /// [`Encoding::Cbor`]: crate::protocol::Encoding::Cbor
+    /// [`Close`]: crate::transport::control::ControlFrame::Close
+    /// [`ShutdownWrite`]: crate::transport::control::ControlFrame::ShutdownWrite
+    pub fn encode_as(&self, encoding: Encoding) -> Frame {
+        let message = match (self, encoding) {
+            (ControlFrame::Close { code, reason }, Encoding::Cbor) => {
+                ControlMessage::Close { code: *code, reason: close_reason(reason).to_string() }
+            }
+            (ControlFrame::ShutdownWrite, Encoding::Cbor) => ControlMessage::ShutdownWrite,
+            _ => return self.encode(),
+        };
+
+        Frame::create(CONTROL_KIND, &message.encode(Encoding::Cbor))
+    }
+
+    /// Encodes message to a frame of the fixed layout
+    pub fn encode(&self) -> Frame {
+        let mut body = BytesMut::new();
+
+        match self {
+            ControlFrame::Close { code, reason } => {
+                body.put_u8(CLOSE);
+                body.put_u8(code.code());
+                body.put_slice(close_reason(reason).as_bytes());
+            }
+            ControlFrame::CloseAck => body.put_u8(CLOSE_ACK),
+            ControlFrame::ShutdownWrite => body.put_u8(SHUTDOWN_WRITE),
+            ControlFrame::Probe { id } => {
+                body.put_u8(PROBE);
+                body.put_u32(*id);
+            }
+            ControlFrame::ProbeAck { id } => {
+                body.put_u8(PROBE_ACK);
+                body.put_u32(*id);
+            }
+        }
+
+        Frame::create(CONTROL_KIND, &body)
+    }
+
+    /// Decodes message from a frame
+    ///
+    /// Returns [`None`] if the frame isn't a valid control frame
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn decode(frame: &Frame) -> Option<ControlFrame> {
+        let body = frame.get(HEADER_BYTES..)?;
+        if frame.kind() != CONTROL_KIND {
+            return None;
+        }
+        ControlFrame::decode_body(body)
+    }
+
+    fn decode_body(mut body: &[u8]) -> Option<ControlFrame> {
+        if !body.has_remaining() {
+            return None;
+        }
+        if CBOR_MAPS.contains(&body[0]) {
+            return match ControlMessage::decode(body, Encoding::Cbor)? {
+                ControlMessage::Close { code, reason } => Some(ControlFrame::Close { code, reason }),
+                ControlMessage::ShutdownWrite => Some(ControlFrame::ShutdownWrite),
+                _ => None,
+            };
+        }
+
+        match body.get_u8() {
+            CLOSE if body.has_remaining() => {
+                let code = CloseCode::from(body.get_u8());
+                let reason = std::str::from_utf8(body).ok()?.to_owned();
+                Some(ControlFrame::Close { code, reason })
+            }
+            CLOSE_ACK => Some(ControlFrame::CloseAck),
+            SHUTDOWN_WRITE => Some(ControlFrame::ShutdownWrite),
+            PROBE if body.remaining() == 4 => Some(ControlFrame::Probe { id: body.get_u32() }),
+            PROBE_ACK if body.remaining() == 4 => Some(ControlFrame::ProbeAck { id: body.get_u32() }),
+            _ => None,
+        }
+    }
+}
+
+// Cuts the reason to fit into a close frame
+pub(crate) fn close_reason(reason: &str) -> &str {
+    let mut len = reason.len().min(MAX_CLOSE_REASON_LEN);
+    while !reason.is_char_boundary(len) {
+        len -= 1;
+    }
+
+    &reason[..len]
+}
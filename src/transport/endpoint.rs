@@ -0,0 +1,131 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::builder::builder::ConnProvider;
+use crate::transport::tcp::{Conn, ConnConfig};
+
+const TCP_SCHEME: &str = "cobra+tcp";
+const TLS_SCHEME: &str = "cobra+tls";
+const UNIX_SCHEME: &str = "cobra+unix";
+
+/// Error returned by [`Endpoint::parse()`]
+///
+/// [`Endpoint::parse()`]: crate::transport::endpoint::Endpoint::parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointError {
+    /// URI doesn't start with a known scheme followed by `://`
+    UnknownScheme(String),
+
+    /// Host has no port or the socket path isn't absolute
+    InvalidAddress(String),
+}
+
+/// Transport and address of a peer written as a URI
+///
+/// Lets configuration files choose transports uniformly:
+///
+/// - `cobra+tcp://host:port` connects over TCP, the host is resolved
+///   with [`Conn::connect_host()`]
+/// - `cobra+tls://host:port` is reserved for TLS, which isn't implemented yet
+/// - `cobra+unix:///path` connects to the Unix domain socket
+///
+/// # Example
+///
+/// ```no_run
+/// use cobra_rs::builder::builder::Builder;
+/// use cobra_rs::transport::endpoint::Endpoint;
+/// use cobra_rs::transport::tcp::{Conn, ConnConfig};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let endpoint = Endpoint::parse("cobra+unix:///run/chat.sock").unwrap();
+///     let conn = Conn::connect_endpoint(&endpoint, ConnConfig::new()).await.unwrap();
+///
+///     let conn = Builder::new()
+///         .set_conn(conn)
+///         .run()
+///         .await
+///         .unwrap();
+/// }
+/// ```
+///
+/// [`Conn::connect_host()`]: crate::transport::tcp::Conn::connect_host
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Tcp(String),
+    Tls(String),
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    pub fn parse(uri: &str) -> Result<Self, EndpointError> {
+        let (scheme, address) = uri.split_once("://")
+            .ok_or_else(|| EndpointError::UnknownScheme(uri.to_string()))?;
+        let invalid = || EndpointError::InvalidAddress(address.to_string());
+
+        match scheme {
+            TCP_SCHEME | TLS_SCHEME => {
+                // Port is required, IPv6 hosts are bracketed
+                let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+                if host.is_empty() || host.contains('/') || port.parse::<u16>().is_err() {
+                    return Err(invalid());
+                }
+
+                match scheme {
+                    TCP_SCHEME => Ok(Endpoint::Tcp(address.to_string())),
+                    _ => Ok(Endpoint::Tls(address.to_string())),
+                }
+            }
+            UNIX_SCHEME if address.starts_with('/') => Ok(Endpoint::Unix(PathBuf::from(address))),
+            UNIX_SCHEME => Err(invalid()),
+            _ => Err(EndpointError::UnknownScheme(scheme.to_string())),
+        }
+    }
+
+    /// Connects to the endpoint with the transport of its scheme
+    ///
+    /// Fails with [`Unsupported`] error for TLS endpoints and for
+    /// Unix domain sockets on other platforms
+    ///
+    /// [`Unsupported`]: std::io::ErrorKind::Unsupported
+    pub async fn connect(&self, config: ConnConfig) -> io::Result<Box<dyn ConnProvider>> {
+        match self {
+            Endpoint::Tcp(host) => Ok(Box::new(Conn::connect_host(host, config).await?)),
+            Endpoint::Tls(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "TLS transport isn't implemented")),
+            Endpoint::Unix(path) => connect_unix(path, config).await,
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn connect_unix(path: &std::path::Path, config: ConnConfig) -> io::Result<Box<dyn ConnProvider>> {
+    use crate::transport::stream::StreamConn;
+
+    let stream = tokio::net::UnixStream::connect(path).await?;
+    Ok(Box::new(StreamConn::with_config(stream, config)))
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(_: &std::path::Path, _: ConnConfig) -> io::Result<Box<dyn ConnProvider>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Unix domain sockets aren't supported"))
+}
+
+impl FromStr for Endpoint {
+    type Err = EndpointError;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        Endpoint::parse(uri)
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(host) => write!(f, "{}://{}", TCP_SCHEME, host),
+            Endpoint::Tls(host) => write!(f, "{}://{}", TLS_SCHEME, host),
+            Endpoint::Unix(path) => write!(f, "{}://{}", UNIX_SCHEME, path.display()),
+        }
+    }
+}
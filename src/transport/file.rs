@@ -0,0 +1,105 @@
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::builder::builder::ConnProvider;
+use crate::mem::{Frame, HEADER_BYTES};
+use crate::runtime;
+
+/// Maximum number of file bytes carried by one frame
+///
+/// Leaves room for data added by encryption and compression providers
+pub const MAX_FILE_CHUNK: usize = 60 * 1024;
+
+/// Region of the file sent as a body of one frame
+pub(crate) struct FileChunk {
+    pub(crate) kind: u8,
+    pub(crate) file: Arc<File>,
+    pub(crate) range: Range<u64>,
+}
+
+impl FileChunk {
+    /// Splits the region into chunks of at most [`MAX_FILE_CHUNK`] bytes
+    ///
+    /// [`MAX_FILE_CHUNK`]: crate::transport::file::MAX_FILE_CHUNK
+    pub(crate) fn split(kind: u8, file: Arc<File>, range: Range<u64>) -> impl Iterator<Item=FileChunk> {
+        let end = range.end;
+
+        range.step_by(MAX_FILE_CHUNK).map(move |start| FileChunk {
+            kind,
+            file: file.clone(),
+            range: start..end.min(start + MAX_FILE_CHUNK as u64),
+        })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        (self.range.end - self.range.start) as usize
+    }
+
+    /// Returns header of the frame carrying the chunk
+    pub(crate) fn header(&self) -> [u8; HEADER_BYTES] {
+        Frame::header(self.kind, self.len())
+    }
+
+    /// Reads the chunk without moving the file cursor
+    ///
+    /// Returns [`UnexpectedEof`] if the file is shorter than the region
+    ///
+    /// [`UnexpectedEof`]: std::io::ErrorKind::UnexpectedEof
+    pub(crate) async fn read(&self) -> io::Result<Vec<u8>> {
+        let file = self.file.clone();
+        let offset = self.range.start;
+        let mut body = vec![0; self.len()];
+
+        runtime::spawn_blocking(move || {
+            read_exact_at(&file, &mut body, offset)?;
+            Ok(body)
+        }).await
+    }
+}
+
+/// Writes the file region as frames, reading it into memory
+///
+/// Default of [`ConnProvider::write_file()`]
+///
+/// [`ConnProvider::write_file()`]: crate::builder::builder::ConnProvider::write_file
+pub(crate) async fn write_file_frames<C: ConnProvider + ?Sized>(conn: &C,
+                                                               kind: u8,
+                                                               file: &File,
+                                                               range: Range<u64>) -> io::Result<()> {
+    for chunk in FileChunk::split(kind, Arc::new(file.try_clone()?), range) {
+        let body = chunk.read().await?;
+        conn.write(Frame::create(kind, &body)).await.map_err(|_| closed())?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "connection closed")
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+
+    file.read_exact_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            len => {
+                buf = &mut buf[len..];
+                offset += len as u64;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs::File;
+use std::io;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, RwLock};
+
+use async_trait::async_trait;
+use tokio::net::ToSocketAddrs;
+use tokio::sync::Notify;
+
+use crate::builder::builder::ConnProvider;
+use crate::builder::stats::TransportStats;
+use crate::config::PartialConfig;
+use crate::mem::Frame;
+use crate::protocol::Encoding;
+use crate::sync::WriteError;
+use crate::transport::close_cause::CloseCause;
+use crate::transport::tcp::{Conn, ConnConfig};
+
+/// [`ConnProvider`] which moves a live connection to another transport
+///
+/// Providers, kinds and extensions of the built connection are kept,
+/// only the underlying transport is replaced, e.g. when a mobile client
+/// switches from Wi-Fi to cellular. The handle is cloned before it's
+/// passed to [`Builder::set_conn()`] and used for [`migrate()`]
+///
+/// # Note
+///
+/// Both peers must migrate to the same new transport: the server side
+/// has to match the new connection with the session itself (e.g. by
+/// a token sent by the client). Handover is lossless only while the
+/// old transport is alive, frames in flight on a broken transport are lost
+///
+/// # Example
+///
+/// ```no_run
+/// use cobra_rs::builder::builder::Builder;
+/// use cobra_rs::transport::migrate::MigratableConn;
+/// use cobra_rs::transport::tcp::Conn;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let conn = MigratableConn::new(Conn::connect("10.0.0.1:5000").await.unwrap());
+///     let kind_conn = Builder::new()
+///         .set_conn(conn.clone())
+///         .run()
+///         .await
+///         .unwrap();
+///
+///     // Network has changed
+///     conn.migrate_to("10.0.0.1:5000").await.unwrap();
+///     kind_conn.write(b"still here".to_vec()).await.unwrap();
+/// }
+/// ```
+///
+/// [`ConnProvider`]: crate::builder::builder::ConnProvider
+/// [`Builder::set_conn()`]: crate::builder::builder::Builder::set_conn
+/// [`migrate()`]: crate::transport::migrate::MigratableConn::migrate
+#[derive(Clone)]
+pub struct MigratableConn {
+    state: Arc<MigrationState>,
+}
+
+struct MigrationState {
+    current: RwLock<Arc<dyn ConnProvider>>,
+    // Replaced transports which still have frames to read, the oldest goes first
+    draining: Mutex<VecDeque<Draining>>,
+    migrated_notifier: Notify,
+    // Applied to new transports as well
+    control_encoding: Mutex<Encoding>,
+    // Kinds which were ever read, each of them has to be drained
+    read_kinds: Mutex<HashSet<u8>>,
+}
+
+// Replaced transport with kinds already read to the end
+struct Draining {
+    conn: Arc<dyn ConnProvider>,
+    drained: HashSet<u8>,
+}
+
+impl MigratableConn {
+    pub fn new<T: 'static + ConnProvider>(conn: T) -> Self {
+        MigratableConn {
+            state: Arc::new(MigrationState {
+                current: RwLock::new(Arc::new(conn)),
+                draining: Mutex::new(VecDeque::new()),
+                migrated_notifier: Notify::new(),
+                control_encoding: Mutex::new(Encoding::Fixed),
+                read_kinds: Mutex::new(HashSet::new()),
+            }),
+        }
+    }
+
+    /// Moves the connection to the new transport
+    ///
+    /// New frames are written to the new transport. Frames queued on the
+    /// old one are flushed and its write side is shut down. Frames already
+    /// sent by the peer over the old transport are read before the new ones
+    pub async fn migrate<T: 'static + ConnProvider>(&self, conn: T) {
+        let conn: Arc<dyn ConnProvider> = Arc::new(conn);
+        conn.handshake_complete();
+        conn.set_control_encoding(*self.state.control_encoding.lock().unwrap());
+
+        let old = std::mem::replace(&mut *self.state.current.write().unwrap(), conn);
+        self.state.draining.lock().unwrap().push_back(Draining { conn: old.clone(), drained: HashSet::new() });
+        self.state.migrated_notifier.notify_waiters();
+
+        old.flush().await;
+        old.shutdown_write().await;
+    }
+
+    /// Connects to the address over TCP and moves the connection there
+    ///
+    /// See [`migrate()`]
+    ///
+    /// [`migrate()`]: crate::transport::migrate::MigratableConn::migrate
+    pub async fn migrate_to<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        let conn = Conn::connect(addr).await?;
+        self.migrate(conn).await;
+        Ok(())
+    }
+
+    /// Resolves the host again and moves the connection there
+    ///
+    /// Unlike [`migrate_to()`] the host is resolved with the resolver of
+    /// `config`, see [`Conn::connect_host()`]
+    ///
+    /// [`migrate_to()`]: crate::transport::migrate::MigratableConn::migrate_to
+    /// [`Conn::connect_host()`]: crate::transport::tcp::Conn::connect_host
+    pub async fn migrate_to_host(&self, host: &str, config: ConnConfig) -> io::Result<()> {
+        let conn = Conn::connect_host(host, config).await?;
+        self.migrate(conn).await;
+        Ok(())
+    }
+
+    fn current(&self) -> Arc<dyn ConnProvider> {
+        self.state.current.read().unwrap().clone()
+    }
+
+    // Returns transport to read the kind from: the oldest one with unread frames
+    fn reading(&self, kind: u8) -> Arc<dyn ConnProvider> {
+        self.state.draining.lock().unwrap()
+            .iter()
+            .find(|draining| !draining.drained.contains(&kind))
+            .map_or_else(|| self.current(), |draining| draining.conn.clone())
+    }
+
+    // Returns true if the transport was replaced, so reading the kind goes on
+    //
+    // Kinds are drained independently, the transport is forgotten
+    // once every kind which was read reached its end on it
+    fn finish_reading(&self, conn: &Arc<dyn ConnProvider>, kind: u8) -> bool {
+        let read_kinds = self.state.read_kinds.lock().unwrap();
+        let mut draining = self.state.draining.lock().unwrap();
+        if let Some(position) = draining.iter().position(|draining| Arc::ptr_eq(&draining.conn, conn)) {
+            draining[position].drained.insert(kind);
+            if read_kinds.is_subset(&draining[position].drained) {
+                draining.remove(position);
+            }
+            return true;
+        }
+
+        !Arc::ptr_eq(conn, &self.current())
+    }
+
+    fn all(&self) -> Vec<Arc<dyn ConnProvider>> {
+        let mut conns: Vec<_> = self.state.draining.lock().unwrap()
+            .iter()
+            .map(|draining| draining.conn.clone())
+            .collect();
+        conns.push(self.current());
+        conns
+    }
+}
+
+#[async_trait]
+impl ConnProvider for MigratableConn {
+    async fn read(&self, kind: u8) -> Option<Frame> {
+        self.state.read_kinds.lock().unwrap().insert(kind);
+        loop {
+            let conn = self.reading(kind);
+            if let Some(frame) = conn.read(kind).await {
+                return Some(frame);
+            }
+            if !self.finish_reading(&conn, kind) {
+                return None;
+            }
+        }
+    }
+
+    async fn write(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        self.current().write(frame).await
+    }
+
+    async fn write_urgent(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        self.current().write_urgent(frame).await
+    }
+
+    async fn flush(&self) {
+        self.current().flush().await
+    }
+
+    async fn write_file(&self, kind: u8, file: &File, range: Range<u64>) -> io::Result<()> {
+        self.current().write_file(kind, file, range).await
+    }
+
+    fn stats(&self) -> TransportStats {
+        self.current().stats()
+    }
+
+    fn handshake_complete(&self) {
+        self.current().handshake_complete()
+    }
+
+    fn set_control_encoding(&self, encoding: Encoding) {
+        *self.state.control_encoding.lock().unwrap() = encoding;
+        self.current().set_control_encoding(encoding)
+    }
+
+    fn reconfigure(&self, config: &PartialConfig) {
+        self.current().reconfigure(config)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.current().local_addr()
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.current().peer_addr()
+    }
+
+    async fn readable(&self) {
+        let migrated = self.state.migrated_notifier.notified();
+        let conn = self.state.draining.lock().unwrap()
+            .front()
+            .map_or_else(|| self.current(), |draining| draining.conn.clone());
+
+        tokio::select! {
+            _ = conn.readable() => {}
+            _ = migrated => {}
+        }
+    }
+
+    async fn close(&self, code: u8) {
+        for conn in self.all() {
+            conn.close(code).await;
+        }
+    }
+
+    async fn close_with_reason(&self, code: u8, reason: &str) {
+        for conn in self.all() {
+            conn.close_with_reason(code, reason).await;
+        }
+    }
+
+    async fn shutdown_write(&self) {
+        self.current().shutdown_write().await
+    }
+
+    async fn is_close(&self) -> Option<u8> {
+        self.current().is_close().await
+    }
+
+    async fn close_reason(&self) -> Option<String> {
+        self.current().close_reason().await
+    }
+
+    async fn close_cause(&self) -> Option<CloseCause> {
+        self.current().close_cause().await
+    }
+}
@@ -1 +1,14 @@
-pub mod tcp;
\ No newline at end of file
+pub mod chaos;
+pub mod close_cause;
+pub mod control;
+pub mod endpoint;
+pub mod file;
+pub mod migrate;
+pub mod multipath;
+pub mod tcp;
+pub mod replay;
+pub mod scheduler;
+pub mod slow_consumer;
+pub mod stream;
+pub(crate) mod udp;
+pub mod watchdog;
@@ -0,0 +1,416 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::{Mutex as AsyncMutex, Notify};
+
+use crate::builder::builder::ConnProvider;
+use crate::builder::stats::TransportStats;
+use crate::config::PartialConfig;
+use crate::mem::Frame;
+use crate::protocol::Encoding;
+use crate::runtime;
+use crate::sync::{Kind, WriteError};
+use crate::transport::close_cause::CloseCause;
+
+/// Length of the sequence number prepended to every frame body
+pub const SEQUENCE_BYTES: usize = 4;
+
+/// Frames of one kind received ahead of the reader
+const INBOX_CAPACITY: usize = 64;
+
+/// Frames of one kind waiting for a missing one before it's taken for lost
+const MAX_REORDERED: usize = 4 * INBOX_CAPACITY;
+
+/// Weight of the new sample in the smoothed write time, in 1/8
+const SMOOTHING: u64 = 1;
+
+/// [`ConnProvider`] running one logical connection over several transports
+///
+/// Every frame is written to the path which is expected to take it first,
+/// judging by the smoothed write time of the path (which grows with its
+/// latency and shrinks with its bandwidth) and frames in flight on it.
+/// Frames of every kind carry a sequence number, so the receiver restores
+/// their order, unless the kind is set unordered with
+/// [`set_unordered_kinds()`]. A closed path is left out, the connection is
+/// closed when all paths are closed
+///
+/// # Note
+///
+/// Experimental. Both peers must wrap the same set of transports, the
+/// server side matches them with the session itself. Sequence number takes
+/// [`SEQUENCE_BYTES`] of every frame body. Frames lost with a broken path
+/// aren't retransmitted, so reads of their kind stop at the gap. The kind
+/// returns [`None`] once 256 later frames have arrived after the gap
+///
+/// # Example
+///
+/// ```no_run
+/// use cobra_rs::builder::builder::Builder;
+/// use cobra_rs::transport::multipath::MultipathConn;
+/// use cobra_rs::transport::tcp::Conn;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let conn = MultipathConn::new()
+///         .add_path(Conn::connect("192.168.1.10:5000").await.unwrap())
+///         .add_path(Conn::connect("10.64.0.10:5000").await.unwrap());
+///
+///     let conn = Builder::new()
+///         .set_conn(conn)
+///         .run()
+///         .await
+///         .unwrap();
+/// }
+/// ```
+///
+/// [`ConnProvider`]: crate::builder::builder::ConnProvider
+/// [`SEQUENCE_BYTES`]: crate::transport::multipath::SEQUENCE_BYTES
+/// [`set_unordered_kinds()`]: crate::transport::multipath::MultipathConn::set_unordered_kinds
+/// [`None`]: std::option::Option::None
+#[derive(Default)]
+pub struct MultipathConn {
+    paths: Vec<Arc<Path>>,
+    unordered_kinds: HashSet<u8>,
+    sequences: Mutex<HashMap<u8, u32>>,
+    inboxes: Mutex<HashMap<u8, Arc<AsyncMutex<Inbox>>>>,
+    readable_notifier: Arc<Notify>,
+}
+
+struct Path {
+    conn: Arc<dyn ConnProvider>,
+    // Smoothed write time in microseconds
+    write_time: AtomicU64,
+    in_flight: AtomicUsize,
+    closed: AtomicBool,
+}
+
+enum Arrival {
+    Frame(u32, Frame),
+    Closed,
+}
+
+// Reorders frames of one kind received from all paths
+struct Inbox {
+    receiver: Receiver<Arrival>,
+    pending: HashMap<u32, Frame>,
+    next: u32,
+    open_paths: usize,
+    // Frames are returned as they arrive
+    unordered: bool,
+}
+
+// Marks the frame as written when the write completes or is cancelled
+struct InFlight<'a> {
+    path: &'a Path,
+    started: Instant,
+}
+
+impl MultipathConn {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds transport to the connection
+    pub fn add_path<T: 'static + ConnProvider>(mut self, conn: T) -> Self {
+        self.paths.push(Arc::new(Path {
+            conn: Arc::new(conn),
+            write_time: AtomicU64::new(0),
+            in_flight: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+        }));
+        self
+    }
+
+    /// Returns frames of the kinds as soon as they arrive from any path
+    ///
+    /// Frames of other kinds wait for the ones sent before them, so a frame
+    /// delayed on a slow path or lost with a broken one holds back its kind.
+    /// Independent messages (e.g. positions, metrics) don't need that
+    ///
+    /// # Note
+    ///
+    /// Frames are still sequenced, so only the receiving side has to set the kinds
+    pub fn set_unordered_kinds(mut self, kinds: &[u8]) -> Self {
+        self.unordered_kinds = kinds.iter().copied().collect();
+        self
+    }
+
+    /// Returns number of paths which aren't closed
+    pub fn open_paths(&self) -> usize {
+        self.paths.iter().filter(|path| !path.closed.load(Ordering::SeqCst)).count()
+    }
+
+    // Path expected to take the frame first
+    fn schedule(&self) -> Option<&Path> {
+        self.paths.iter()
+            .filter(|path| !path.closed.load(Ordering::SeqCst))
+            .min_by_key(|path| {
+                let write_time = path.write_time.load(Ordering::SeqCst).max(1);
+                write_time * (path.in_flight.load(Ordering::SeqCst) as u64 + 1)
+            })
+            .map(Arc::as_ref)
+    }
+
+    fn sequence(&self, frame: Frame) -> Frame {
+        let kind = frame.kind();
+        let sequence = {
+            let mut sequences = self.sequences.lock().unwrap();
+            let next = sequences.entry(kind).or_insert(0);
+            let sequence = *next;
+            *next = next.wrapping_add(1);
+            sequence
+        };
+
+        let body = frame.get_body();
+        let mut sequenced = Vec::with_capacity(SEQUENCE_BYTES + body.len());
+        sequenced.extend_from_slice(&sequence.to_be_bytes());
+        sequenced.extend_from_slice(&body);
+        Frame::create(kind, &sequenced)
+    }
+
+    async fn send(&self, frame: Frame, urgent: bool) -> Result<(), WriteError<Frame>> {
+        let mut frame = self.sequence(frame);
+
+        // Frame written to a closed path is moved to the next one
+        while let Some(path) = self.schedule() {
+            let written = {
+                let _in_flight = InFlight::start(path);
+                match urgent {
+                    true => path.conn.write_urgent(frame).await,
+                    false => path.conn.write(frame).await,
+                }
+            };
+
+            match written {
+                Err(WriteError::Closed(returned)) => {
+                    path.closed.store(true, Ordering::SeqCst);
+                    frame = returned;
+                }
+                written => return written.map_err(|error| error.map(MultipathConn::strip)),
+            }
+        }
+
+        Err(WriteError::Closed(MultipathConn::strip(frame)))
+    }
+
+    fn strip(frame: Frame) -> Frame {
+        let kind = frame.kind();
+        Frame::create(kind, &frame.get_body()[SEQUENCE_BYTES..])
+    }
+
+    fn inbox(&self, kind: u8) -> Arc<AsyncMutex<Inbox>> {
+        let mut inboxes = self.inboxes.lock().unwrap();
+        if let Some(inbox) = inboxes.get(&kind) {
+            return inbox.clone();
+        }
+
+        let (sender, receiver) = channel(INBOX_CAPACITY);
+        for path in &self.paths {
+            MultipathConn::spawn_pump(path.clone(), kind, sender.clone(), self.readable_notifier.clone());
+        }
+
+        let inbox = Arc::new(AsyncMutex::new(Inbox {
+            receiver,
+            pending: HashMap::new(),
+            next: 0,
+            open_paths: self.paths.len(),
+            unordered: self.unordered_kinds.contains(&kind),
+        }));
+        inboxes.insert(kind, inbox.clone());
+        inbox
+    }
+
+    // Moves frames of the kind from the path to the inbox
+    fn spawn_pump(path: Arc<Path>, kind: u8, sender: Sender<Arrival>, readable_notifier: Arc<Notify>) {
+        runtime::spawn(async move {
+            while let Some(frame) = path.conn.read(kind).await {
+                let body = frame.get_body();
+                if body.len() < SEQUENCE_BYTES {
+                    continue;
+                }
+
+                let sequence = u32::from_be_bytes([body[0], body[1], body[2], body[3]]);
+                let frame = Frame::create(kind, &body[SEQUENCE_BYTES..]);
+                if sender.send(Arrival::Frame(sequence, frame)).await.is_err() {
+                    return;
+                }
+                readable_notifier.notify_waiters();
+            }
+
+            path.closed.store(true, Ordering::SeqCst);
+            let _ = sender.send(Arrival::Closed).await;
+        });
+    }
+
+    fn conns(&self) -> impl Iterator<Item=&Arc<dyn ConnProvider>> {
+        self.paths.iter().map(|path| &path.conn)
+    }
+
+    fn first_open(&self) -> Option<&Arc<dyn ConnProvider>> {
+        self.paths.iter()
+            .find(|path| !path.closed.load(Ordering::SeqCst))
+            .or_else(|| self.paths.first())
+            .map(|path| &path.conn)
+    }
+}
+
+impl<'a> InFlight<'a> {
+    fn start(path: &'a Path) -> Self {
+        path.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight { path, started: Instant::now() }
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.path.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        let sample = self.started.elapsed().as_micros().min(Duration::from_secs(60).as_micros()) as u64;
+        let _ = self.path.write_time.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |smoothed| {
+            Some(if smoothed == 0 { sample } else { (smoothed * (8 - SMOOTHING) + sample * SMOOTHING) / 8 })
+        });
+    }
+}
+
+#[async_trait]
+impl ConnProvider for MultipathConn {
+    async fn read(&self, kind: u8) -> Option<Frame> {
+        let inbox = self.inbox(kind);
+        let mut inbox = inbox.lock().await;
+
+        loop {
+            let next = inbox.next;
+            if let Some(frame) = inbox.pending.remove(&next) {
+                inbox.next = next.wrapping_add(1);
+                return Some(frame);
+            }
+            if inbox.open_paths == 0 {
+                return None;
+            }
+
+            match inbox.receiver.recv().await {
+                Some(Arrival::Frame(_, frame)) if inbox.unordered => return Some(frame),
+                // Reordering can't explain that many frames ahead of the next one,
+                // so it was lost. Closed receiver stops the pumps of the kind
+                Some(Arrival::Frame(_, _)) if inbox.pending.len() >= MAX_REORDERED => {
+                    inbox.pending.clear();
+                    inbox.receiver.close();
+                    inbox.open_paths = 0;
+                }
+                Some(Arrival::Frame(sequence, frame)) => {
+                    inbox.pending.insert(sequence, frame);
+                }
+                Some(Arrival::Closed) | None => inbox.open_paths -= 1,
+            }
+        }
+    }
+
+    async fn write(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        self.send(frame, false).await
+    }
+
+    async fn write_urgent(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        self.send(frame, true).await
+    }
+
+    async fn flush(&self) {
+        for conn in self.conns() {
+            conn.flush().await;
+        }
+    }
+
+    /// Sums counters of all paths, the round-trip time is of the fastest one
+    fn stats(&self) -> TransportStats {
+        self.conns().map(|conn| conn.stats()).fold(TransportStats::default(), |total, path| TransportStats {
+            queued_bytes: total.queued_bytes + path.queued_bytes,
+            queued_frames: total.queued_frames + path.queued_frames,
+            pending_writes: total.pending_writes + path.pending_writes,
+            expired_frames: total.expired_frames + path.expired_frames,
+            rtt: match (total.rtt, path.rtt) {
+                (Some(total), Some(path)) => Some(total.min(path)),
+                (total, path) => total.or(path),
+            },
+        })
+    }
+
+    fn handshake_complete(&self) {
+        for conn in self.conns() {
+            conn.handshake_complete();
+        }
+    }
+
+    fn set_control_encoding(&self, encoding: Encoding) {
+        for conn in self.conns() {
+            conn.set_control_encoding(encoding);
+        }
+    }
+
+    fn reconfigure(&self, config: &PartialConfig) {
+        for conn in self.conns() {
+            conn.reconfigure(config);
+        }
+    }
+
+    /// Returns local address of the first open path
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self.first_open() {
+            Some(conn) => conn.local_addr(),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, "connection has no paths")),
+        }
+    }
+
+    /// Returns peer address of the first open path
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self.first_open() {
+            Some(conn) => conn.peer_addr(),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, "connection has no paths")),
+        }
+    }
+
+    async fn readable(&self) {
+        self.readable_notifier.notified().await;
+    }
+
+    async fn close(&self, code: u8) {
+        for conn in self.conns() {
+            conn.close(code).await;
+        }
+    }
+
+    async fn close_with_reason(&self, code: u8, reason: &str) {
+        for conn in self.conns() {
+            conn.close_with_reason(code, reason).await;
+        }
+    }
+
+    async fn shutdown_write(&self) {
+        for conn in self.conns() {
+            conn.shutdown_write().await;
+        }
+    }
+
+    /// Returns close code of the first path once all paths are closed
+    async fn is_close(&self) -> Option<u8> {
+        let mut code = None;
+        for conn in self.conns() {
+            code = Some(code.unwrap_or(conn.is_close().await?));
+        }
+        code
+    }
+
+    async fn close_reason(&self) -> Option<String> {
+        self.is_close().await?;
+        self.paths.first()?.conn.close_reason().await
+    }
+
+    async fn close_cause(&self) -> Option<CloseCause> {
+        self.is_close().await?;
+        self.paths.first()?.conn.close_cause().await
+    }
+}
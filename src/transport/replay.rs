@@ -0,0 +1,185 @@
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+
+use async_trait::async_trait;
+use tokio::sync::Notify;
+
+use crate::builder::builder::ConnProvider;
+use crate::debug::{Direction, RecordedFrame, RecordingReader};
+use crate::mem::Frame;
+use crate::runtime;
+use crate::sync::{KindPool, WriteError};
+
+/// [`ConnProvider`] replaying inbound frames of a recording
+///
+/// Inbound frames are delivered in the recorded order and with the
+/// recorded intervals divided by the replay speed. Frame is delivered
+/// only after the previous one was read, so a slow reader shifts the
+/// rest of the session. When the recording ends, reads return [`None`]
+///
+/// Written frames aren't sent anywhere, they are kept for
+/// assertions, see [`take_written()`]
+///
+/// # Note
+///
+/// Inbound frames recorded without payload are skipped
+///
+/// # Example
+///
+/// ```no_run
+/// use cobra_rs::builder::builder::Builder;
+/// use cobra_rs::transport::replay::ReplayConn;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let conn = ReplayConn::open("session.rec").unwrap();
+///     let conn = Builder::new()
+///         .set_conn(conn)
+///         .run()
+///         .await
+///         .unwrap();
+/// }
+/// ```
+///
+/// [`ConnProvider`]: crate::builder::builder::ConnProvider
+/// [`None`]: std::option::Option::None
+/// [`take_written()`]: crate::transport::replay::ReplayConn::take_written
+pub struct ReplayConn {
+    state: Arc<ReplayState>,
+}
+
+struct ReplayState {
+    inbound: KindPool<u8, Frame>,
+    written: Mutex<Vec<Frame>>,
+    close: Mutex<Option<(u8, String)>>,
+    readable_notifier: Notify,
+}
+
+impl ReplayConn {
+    /// Replays recording file with the original timing
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let frames = RecordingReader::open(path)?.collect::<io::Result<Vec<_>>>()?;
+        Ok(ReplayConn::new(frames))
+    }
+
+    /// Replays frames with the original timing
+    pub fn new(frames: Vec<RecordedFrame>) -> Self {
+        ReplayConn::with_speed(frames, 1.0)
+    }
+
+    /// Replays frames `speed` times faster than they were recorded
+    ///
+    /// Use [`f64::INFINITY`] to deliver frames without delays
+    ///
+    /// # Panics
+    ///
+    /// Panics if the speed isn't positive
+    ///
+    /// [`f64::INFINITY`]: f64::INFINITY
+    pub fn with_speed(frames: Vec<RecordedFrame>, speed: f64) -> Self {
+        assert!(speed > 0.0, "replay speed must be positive");
+
+        let state = Arc::new(ReplayState {
+            inbound: KindPool::new(),
+            written: Mutex::new(Vec::new()),
+            close: Mutex::new(None),
+            readable_notifier: Notify::new(),
+        });
+
+        runtime::spawn(state.clone().replay(frames, speed));
+        ReplayConn { state }
+    }
+
+    /// Returns frames written since the previous call
+    pub fn take_written(&self) -> Vec<Frame> {
+        std::mem::take(&mut self.state.written.lock().unwrap())
+    }
+}
+
+impl ReplayState {
+    async fn replay(self: Arc<Self>, frames: Vec<RecordedFrame>, speed: f64) {
+        let origin = frames.first().map_or(SystemTime::UNIX_EPOCH, |frame| frame.timestamp);
+        let started = Instant::now();
+
+        for recorded in frames.iter().filter(|frame| frame.direction == Direction::Inbound) {
+            let frame = match recorded.to_frame() {
+                Some(frame) => frame,
+                None => continue,
+            };
+
+            let offset = recorded.timestamp
+                .duration_since(origin)
+                .unwrap_or_default()
+                .div_f64(speed);
+            let elapsed = started.elapsed();
+            if offset > elapsed {
+                runtime::sleep(offset - elapsed).await;
+            }
+
+            self.readable_notifier.notify_waiters();
+            if self.inbound.write(frame).await.is_err() {
+                return;
+            }
+        }
+
+        self.inbound.close().await;
+    }
+}
+
+// Replay task is blocked until the frame is read, so it must be released
+impl Drop for ReplayConn {
+    fn drop(&mut self) {
+        let state = self.state.clone();
+        runtime::spawn(async move { state.inbound.close().await });
+    }
+}
+
+#[async_trait]
+impl ConnProvider for ReplayConn {
+    async fn read(&self, kind: u8) -> Option<Frame> {
+        Some(self.state.inbound.read(kind).await?.accept())
+    }
+
+    async fn write(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        if self.state.close.lock().unwrap().is_some() {
+            return Err(WriteError::Closed(frame));
+        }
+
+        self.state.written.lock().unwrap().push(frame);
+        Ok(())
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Err(io::Error::new(io::ErrorKind::NotConnected, "replayed connection has no address"))
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Err(io::Error::new(io::ErrorKind::NotConnected, "replayed connection has no address"))
+    }
+
+    async fn readable(&self) {
+        self.state.readable_notifier.notified().await;
+    }
+
+    async fn close(&self, code: u8) {
+        self.close_with_reason(code, "").await
+    }
+
+    async fn close_with_reason(&self, code: u8, reason: &str) {
+        self.state.close.lock().unwrap().get_or_insert_with(|| (code, reason.to_string()));
+        self.state.inbound.close().await;
+    }
+
+    async fn shutdown_write(&self) {}
+
+    async fn is_close(&self) -> Option<u8> {
+        self.state.close.lock().unwrap().as_ref().map(|(code, _)| *code)
+    }
+
+    async fn close_reason(&self) -> Option<String> {
+        self.state.close.lock().unwrap().as_ref().map(|(_, reason)| reason.clone())
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+
+use crate::mem::Frame;
+use crate::sync::Kind;
+
+/// Maximum number of frames waiting in the scheduler queue
+///
+/// Writers wait while the queue is full
+pub const SCHEDULER_CAPACITY: usize = 64;
+
+/// Creates scheduler for every connection
+pub(crate) type SchedulerFactory = Arc<dyn Fn() -> Box<dyn Scheduler> + Send + Sync>;
+
+/// Decides which queued frame is written next
+///
+/// Frames written while the socket is busy are queued in the scheduler,
+/// the writer takes them one by one with [`pop()`]. Urgent frames
+/// skip the scheduler. See [`ConnConfig::set_scheduler()`]
+///
+/// # Example
+///
+/// Writes the shortest frame first:
+///
+/// ```
+/// use cobra_rs::mem::Frame;
+/// use cobra_rs::transport::scheduler::Scheduler;
+///
+/// #[derive(Default)]
+/// struct ShortestFirst {
+///     frames: Vec<Frame>,
+/// }
+///
+/// impl Scheduler for ShortestFirst {
+///     fn push(&mut self, frame: Frame) {
+///         self.frames.push(frame);
+///     }
+///
+///     fn pop(&mut self) -> Option<Frame> {
+///         let (shortest, _) = self.frames.iter()
+///             .enumerate()
+///             .min_by_key(|(_, frame)| frame.len())?;
+///         Some(self.frames.remove(shortest))
+///     }
+///
+///     fn len(&self) -> usize {
+///         self.frames.len()
+///     }
+/// }
+/// ```
+///
+/// [`pop()`]: crate::transport::scheduler::Scheduler::pop
+/// [`ConnConfig::set_scheduler()`]: crate::transport::tcp::ConnConfig::set_scheduler
+pub trait Scheduler: Send {
+    /// Queues frame written by the application
+    fn push(&mut self, frame: Frame);
+
+    /// Removes frame which is written next, [`None`] if the queue is empty
+    ///
+    /// [`None`]: std::option::Option::None
+    fn pop(&mut self) -> Option<Frame>;
+
+    /// Returns number of queued frames
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Writes frames in the order they were queued
+#[derive(Default)]
+pub struct FifoScheduler {
+    frames: VecDeque<Frame>,
+}
+
+/// Writes frames of kinds with higher priority first
+///
+/// Frames with equal priority are written in the order they were queued.
+/// Kinds without priority have priority 0
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::transport::scheduler::PriorityScheduler;
+/// use cobra_rs::transport::tcp::ConnConfig;
+///
+/// const CHAT_KIND: u8 = 13;
+/// const FILE_KIND: u8 = 14;
+///
+/// let config = ConnConfig::new().set_scheduler(|| {
+///     PriorityScheduler::new()
+///         .set_priority(CHAT_KIND, 10)
+///         .set_priority(FILE_KIND, 1)
+/// });
+/// ```
+#[derive(Default)]
+pub struct PriorityScheduler {
+    priorities: HashMap<u8, u8>,
+    queues: BTreeMap<u8, VecDeque<Frame>>,
+    len: usize,
+}
+
+impl FifoScheduler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Scheduler for FifoScheduler {
+    fn push(&mut self, frame: Frame) {
+        self.frames.push_back(frame);
+    }
+
+    fn pop(&mut self) -> Option<Frame> {
+        self.frames.pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+impl PriorityScheduler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets priority of the kind, the highest priority is 255
+    pub fn set_priority(mut self, kind: u8, priority: u8) -> Self {
+        self.priorities.insert(kind, priority);
+        self
+    }
+}
+
+impl Scheduler for PriorityScheduler {
+    fn push(&mut self, frame: Frame) {
+        let priority = self.priorities.get(&frame.kind()).copied().unwrap_or(0);
+
+        self.queues.entry(priority).or_default().push_back(frame);
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<Frame> {
+        let mut queue = self.queues.last_entry()?;
+        let frame = queue.get_mut().pop_front();
+        if queue.get().is_empty() {
+            queue.remove();
+        }
+
+        self.len -= 1;
+        frame
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+pub(crate) type SlowConsumerHandler = Arc<dyn Fn(&SlowConsumer) + Send + Sync>;
+
+/// Event emitted when the application hasn't read a received frame
+/// within the window of [`SlowConsumerPolicy`]
+///
+/// [`SlowConsumerPolicy`]: crate::transport::slow_consumer::SlowConsumerPolicy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowConsumer {
+    /// Kind of the frame waiting for a reader
+    pub kind: u8,
+    /// Time the frame has been waiting
+    pub waited: Duration,
+}
+
+/// Detection of kinds whose consumers don't read received frames
+///
+/// Stuck consumers hold received frames and, once the high-water mark is
+/// reached, pause the whole connection. The policy reports such kinds and
+/// may close the connection with [`SLOW_CONSUMER`] code
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use cobra_rs::transport::slow_consumer::SlowConsumerPolicy;
+/// use cobra_rs::transport::tcp::ConnConfig;
+///
+/// let policy = SlowConsumerPolicy::new(Duration::from_secs(30))
+///     .on_slow_consumer(|event| println!("kind {} is stuck", event.kind))
+///     .evict();
+///
+/// let config = ConnConfig::new().set_slow_consumer_policy(policy);
+/// ```
+///
+/// [`SLOW_CONSUMER`]: crate::builder::kind_conn::close_code::SLOW_CONSUMER
+#[derive(Clone)]
+pub struct SlowConsumerPolicy {
+    pub(crate) window: Duration,
+    pub(crate) handler: Option<SlowConsumerHandler>,
+    pub(crate) evict: bool,
+}
+
+impl SlowConsumerPolicy {
+    /// Creates policy detecting frames unread for `window`
+    pub fn new(window: Duration) -> Self {
+        SlowConsumerPolicy {
+            window,
+            handler: None,
+            evict: false,
+        }
+    }
+
+    /// Sets handler called with [`SlowConsumer`] event once per detected frame
+    ///
+    /// Handler is called from the connection worker, so it must not block
+    ///
+    /// [`SlowConsumer`]: crate::transport::slow_consumer::SlowConsumer
+    pub fn on_slow_consumer<F: 'static + Fn(&SlowConsumer) + Send + Sync>(mut self, handler: F) -> Self {
+        self.handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Closes the connection with [`SLOW_CONSUMER`] code after the event
+    ///
+    /// By default the frame keeps waiting for its reader
+    ///
+    /// [`SLOW_CONSUMER`]: crate::builder::kind_conn::close_code::SLOW_CONSUMER
+    pub fn evict(mut self) -> Self {
+        self.evict = true;
+        self
+    }
+
+    pub(crate) fn emit(&self, event: &SlowConsumer) {
+        if let Some(handler) = &self.handler {
+            handler(event);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::io;
+use std::net::{Shutdown, SocketAddr};
+use std::ops::DerefMut;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use bytes::BufMut;
+use tokio::io::{split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::Notify;
+
+use crate::builder::builder::ConnProvider;
+use crate::builder::stats::TransportStats;
+use crate::builder::kind_conn::close_code::CANCELLED;
+use crate::config::PartialConfig;
+use crate::mem::{ConcatBuf, Frame};
+use crate::protocol::Encoding;
+use crate::runtime::Runtime;
+use crate::sync::{Kind, WriteError};
+use crate::transport::close_cause::CloseCause;
+use crate::transport::control::CONTROL_KIND;
+use crate::transport::tcp::closer::{ConnCloser, ShutdownHook};
+use crate::transport::tcp::dispatch::KindQueues;
+use crate::transport::tcp::{collect_batch, ConnConfig, DEFAULT_HIGH_WATER_MARK};
+use crate::transport::watchdog::WorkerState;
+
+/// [`ConnProvider`] carrying frames over any byte stream
+///
+/// Lets cobra run over links which aren't sockets: serial ports
+/// (e.g. `tokio_serial::SerialStream`), Bluetooth RFCOMM, pipes.
+/// Frames and close handshake are the same as in the TCP transport.
+/// For slow links see [`Profile::LowBandwidth`]
+///
+/// # Example
+///
+/// ```no_run
+/// use cobra_rs::builder::builder::Builder;
+/// use cobra_rs::builder::profile::Profile;
+/// use cobra_rs::transport::stream::StreamConn;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (stream, _) = tokio::io::duplex(1024);
+///     let conn = StreamConn::with_config(stream, Profile::LowBandwidth.conn_config());
+///
+///     let conn = Builder::new()
+///         .set_conn(conn)
+///         .profile(Profile::LowBandwidth)
+///         .run()
+///         .await
+///         .unwrap();
+/// }
+/// ```
+///
+/// [`ConnProvider`]: crate::builder::builder::ConnProvider
+/// [`Profile::LowBandwidth`]: crate::builder::profile::Profile::LowBandwidth
+pub struct StreamConn {
+    closer: ConnCloser,
+    readable_notifier: Arc<Notify>,
+}
+
+impl StreamConn {
+    /// Starts I/O workers over the stream
+    pub fn new<S: 'static + AsyncRead + AsyncWrite + Send>(stream: S) -> Self {
+        StreamConn::with_config(stream, ConnConfig::default())
+    }
+
+    /// Starts I/O workers over the stream using custom settings
+    ///
+    /// See [`ConnConfig`] for available settings
+    ///
+    /// [`ConnConfig`]: crate::transport::tcp::ConnConfig
+    pub fn with_config<S: 'static + AsyncRead + AsyncWrite + Send>(stream: S, config: ConnConfig) -> Self {
+        let (read_half, write_half) = split(stream);
+
+        // Stream can't be shut down from outside, so the reader is stopped
+        // and the halves are dropped. Write side is shut down by the writer
+        let stop_notifier = Arc::new(Notify::new());
+        let stop = stop_notifier.clone();
+        let shutdown_hook: ShutdownHook = Arc::new(move |how| {
+            if how == Shutdown::Both {
+                stop.notify_one();
+            }
+        });
+
+        let closer = ConnCloser::with_shutdown_hook(shutdown_hook, Arc::new(RwLock::new(config.clone())));
+        let readable_notifier = Arc::new(Notify::new());
+
+        StreamConn::spawn_reader(read_half, closer.clone(), &config, readable_notifier.clone(), stop_notifier);
+        StreamConn::spawn_writer(write_half, closer.clone(), config.runtime.clone());
+
+        if let Some(policy) = config.watchdog.clone() {
+            let watcher = closer.clone();
+            closer.spawn(async move { watcher.watch(policy).await });
+        }
+
+        if let Some(token) = config.cancel {
+            let watcher = closer.clone();
+            closer.spawn(async move {
+                tokio::select! {
+                    _ = token.cancelled() => watcher.close_with_handshake(CANCELLED, "").await,
+                    _ = watcher.closed() => {}
+                }
+            });
+        }
+
+        StreamConn {
+            closer,
+            readable_notifier,
+        }
+    }
+
+    /// Waits until I/O workers of the connection exit
+    ///
+    /// See [`Conn::join()`]
+    ///
+    /// [`Conn::join()`]: crate::transport::tcp::Conn::join
+    pub async fn join(&self) -> io::Result<()> {
+        self.closer.join().await
+    }
+
+    fn spawn_reader<S: 'static + AsyncRead + Send>(mut inner: ReadHalf<S>,
+                                                   closer: ConnCloser,
+                                                   config: &ConnConfig,
+                                                   readable_notifier: Arc<Notify>,
+                                                   stop_notifier: Arc<Notify>) {
+        let buffer_policy = config.buffer_policy;
+        let high_water_mark = config.recv_high_water_mark.unwrap_or(DEFAULT_HIGH_WATER_MARK);
+        let mut queues = KindQueues::new(closer.reader_pool.clone(), closer.clone(), high_water_mark);
+        let beat = closer.reader_beat.clone();
+
+        closer.clone().spawn(async move {
+            let mut buf: ConcatBuf<Frame> = ConcatBuf::with_policy(buffer_policy);
+            let mut peer_shutdown = false;
+
+            'read: loop {
+                // The same backpressure as in the TCP reader
+                let room = high_water_mark.saturating_sub(buf.len()).max(1);
+                beat.set_state(WorkerState::Backpressure);
+                let room = queues.wait_for_room(room).await;
+                beat.set_state(WorkerState::Budget);
+                queues.wait_for_budget(&buf).await;
+
+                let limit = buf.remaining_limit().min(room);
+
+                beat.set_state(WorkerState::Idle);
+                let read = {
+                    let mut limited = buf.deref_mut().limit(limit);
+                    tokio::select! {
+                        read = inner.read_buf(&mut limited) => Some(read),
+                        _ = stop_notifier.notified() => None,
+                    }
+                };
+                match read {
+                    // Connection was shut down
+                    None => break,
+                    // On EOF or unexpected error closing read worker
+                    Some(Ok(0)) => {
+                        closer.read_failed(None);
+                        break;
+                    }
+                    Some(Err(error)) => {
+                        closer.read_failed(Some(&error));
+                        break;
+                    }
+                    Some(Ok(_len)) => {
+                        beat.beat();
+                        buf.adapt_capacity()
+                    }
+                }
+                readable_notifier.notify_waiters();
+
+                while let Some(frame) = buf.try_read_chunk() {
+                    if frame.kind() == CONTROL_KIND {
+                        beat.set_state(WorkerState::Control);
+                        if !closer.handle_control(frame).await {
+                            peer_shutdown = true;
+                            break 'read;
+                        }
+                        continue;
+                    }
+
+                    if !queues.push(frame) {
+                        break 'read;
+                    }
+                }
+                queues.track_buffer(&buf);
+            }
+            beat.set_state(WorkerState::Idle);
+
+            queues.finish().await;
+
+            if peer_shutdown {
+                closer.finish_read().await;
+            }
+        });
+    }
+
+    fn spawn_writer<S: 'static + AsyncWrite + Send>(mut inner: WriteHalf<S>, closer: ConnCloser, runtime: Arc<dyn Runtime>) {
+        let pool = closer.writer_pool.clone();
+        let urgent_pool = closer.urgent_pool.clone();
+        let pending = closer.pending.clone();
+        let beat = closer.writer_beat.clone();
+
+        closer.clone().spawn(async move {
+            loop {
+                beat.set_state(WorkerState::Idle);
+                let frame = tokio::select! {
+                    biased;
+                    Some(frame) = urgent_pool.read() => frame,
+                    frame = pool.read() => match frame {
+                        Some(frame) => frame,
+                        None => break,
+                    },
+                };
+
+                // Frames are flushed at once, so slow links get fewer small writes
+                let coalescing = closer.config.read().unwrap().write_coalescing;
+                if let Some(delay) = coalescing {
+                    let (batch, _written) = collect_batch(runtime.as_ref(), &pool, &pending, frame, delay).await;
+
+                    beat.set_state(WorkerState::Socket);
+                    let written: io::Result<()> = async {
+                        inner.write_all(&batch).await?;
+                        inner.flush().await
+                    }.await;
+                    if let Err(error) = written {
+                        closer.fail(&error).await;
+                        break;
+                    }
+                    beat.beat();
+                    continue;
+                }
+
+                beat.set_state(WorkerState::Socket);
+                let written: io::Result<()> = async {
+                    inner.write_all(&frame).await?;
+                    inner.flush().await
+                }.await;
+
+                if let Err(error) = written {
+                    frame.reject().await;
+                    closer.fail(&error).await;
+                    break;
+                }
+                beat.beat();
+            }
+            beat.set_state(WorkerState::Idle);
+
+            pool.close();
+            urgent_pool.close();
+            let _ = inner.shutdown().await;
+        });
+    }
+}
+
+#[async_trait]
+impl ConnProvider for StreamConn {
+    async fn read(&self, kind: u8) -> Option<Frame> {
+        Some(self.closer.reader_pool.read(kind).await?.accept())
+    }
+
+    async fn write(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        let _pending = self.closer.pending.start(frame.kind(), frame.len());
+        self.closer.writer_pool.write(frame).await
+    }
+
+    async fn write_urgent(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        let _pending = self.closer.pending.start(frame.kind(), frame.len());
+        self.closer.urgent_pool.write(frame).await
+    }
+
+    async fn flush(&self) {
+        self.closer.pending.flush().await
+    }
+
+    fn stats(&self) -> TransportStats {
+        self.closer.stats()
+    }
+
+    fn set_control_encoding(&self, encoding: Encoding) {
+        self.closer.set_control_encoding(encoding)
+    }
+
+    fn reconfigure(&self, config: &PartialConfig) {
+        self.closer.config.write().unwrap().apply(config);
+    }
+
+    /// Byte streams have no addresses, returns [`ErrorKind::Unsupported`]
+    ///
+    /// [`ErrorKind::Unsupported`]: std::io::ErrorKind::Unsupported
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "byte stream has no address"))
+    }
+
+    /// Byte streams have no addresses, returns [`ErrorKind::Unsupported`]
+    ///
+    /// [`ErrorKind::Unsupported`]: std::io::ErrorKind::Unsupported
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "byte stream has no address"))
+    }
+
+    async fn readable(&self) {
+        self.readable_notifier.notified().await;
+    }
+
+    async fn close(&self, code: u8) {
+        self.close_with_reason(code, "").await
+    }
+
+    async fn close_with_reason(&self, code: u8, reason: &str) {
+        self.closer.close_with_handshake(code, reason).await;
+        let _ = self.closer.join().await;
+    }
+
+    async fn shutdown_write(&self) {
+        self.closer.shutdown_write().await
+    }
+
+    async fn is_close(&self) -> Option<u8> {
+        self.closer.code().await
+    }
+
+    async fn close_reason(&self) -> Option<String> {
+        self.closer.reason().await
+    }
+
+    async fn close_cause(&self) -> Option<CloseCause> {
+        self.closer.cause()
+    }
+}
@@ -0,0 +1,411 @@
+use std::future::Future;
+use std::io;
+use std::net::Shutdown;
+use std::sync::{Arc, Mutex, RwLock as SyncRwLock};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use socket2::SockRef;
+use tokio::net::TcpStream;
+use tokio::sync::{oneshot, Notify, RwLock};
+
+use crate::builder::kind_conn::close_code::{CLOSED_BY_USER, INTERNAL_ERROR, IO_ERROR};
+use crate::builder::stats::TransportStats;
+use crate::mem::Frame;
+use crate::protocol::Encoding;
+use crate::runtime::{self, Runtime, TaskGroup};
+use crate::sync::{CancelToken, KindPool, Pool};
+use crate::transport::close_cause::CloseCause;
+use crate::transport::control::{close_reason, ControlFrame};
+use crate::transport::file::FileChunk;
+use crate::transport::tcp::socket::BytesFrame;
+use crate::transport::tcp::dispatch::QueueUsage;
+use crate::transport::tcp::pending::{PendingWrites, QueuedFrames};
+use crate::transport::tcp::ConnConfig;
+use crate::transport::watchdog::{Heartbeat, StuckWorker, WatchdogPolicy, Worker, WorkerState};
+
+/// Shuts down the underlying stream
+pub(crate) type ShutdownHook = Arc<dyn Fn(Shutdown) + Send + Sync>;
+
+/// Closes both I/O loops and remembers the first close code, reason and cause
+#[derive(Clone)]
+pub(crate) struct ConnCloser {
+    shutdown_hook: ShutdownHook,
+    closed: Arc<RwLock<Option<(u8, String)>>>,
+    cause: Arc<Mutex<Option<CloseCause>>>,
+    ack_notifier: Arc<Notify>,
+    // Probes waiting for acknowledgment by id
+    probes: Arc<Mutex<HashMap<u32, oneshot::Sender<()>>>>,
+    next_probe: Arc<AtomicU32>,
+    shutdown_notifier: Arc<Notify>,
+    write_shutdown: Arc<AtomicBool>,
+    read_shutdown: Arc<AtomicBool>,
+    // Close and shutdown frames are CBOR messages, see ControlFrame::encode_as()
+    structured_close: Arc<AtomicBool>,
+    runtime: Arc<dyn Runtime>,
+    tasks: Arc<TaskGroup>,
+    panic: Arc<Mutex<Option<String>>>,
+    terminated: CancelToken,
+    pub(crate) config: Arc<SyncRwLock<ConnConfig>>,
+    pub(crate) pending: Arc<PendingWrites>,
+    pub(crate) queued_frames: Arc<Mutex<QueuedFrames>>,
+    pub(crate) expired_frames: Arc<AtomicU64>,
+    pub(crate) queue_usage: Arc<QueueUsage>,
+    pub(crate) reader_pool: KindPool<u8, Frame>,
+    pub(crate) writer_pool: Pool<Frame>,
+    pub(crate) urgent_pool: Pool<Frame>,
+    pub(crate) file_pool: Pool<FileChunk>,
+    pub(crate) bytes_pool: Pool<BytesFrame>,
+    pub(crate) reader_beat: Arc<Heartbeat>,
+    pub(crate) writer_beat: Arc<Heartbeat>,
+}
+
+impl ConnCloser {
+    pub(crate) fn new(inner: Arc<TcpStream>, config: Arc<SyncRwLock<ConnConfig>>) -> Self {
+        // Socket may be already closed by the peer
+        let shutdown_hook: ShutdownHook = Arc::new(move |how| {
+            let _ = SockRef::from(inner.as_ref()).shutdown(how);
+        });
+
+        ConnCloser::with_shutdown_hook(shutdown_hook, config)
+    }
+
+    pub(crate) fn with_shutdown_hook(shutdown_hook: ShutdownHook, config: Arc<SyncRwLock<ConnConfig>>) -> Self {
+        let runtime = config.read().unwrap().runtime.clone();
+
+        ConnCloser {
+            shutdown_hook,
+            closed: Arc::new(RwLock::new(None)),
+            cause: Arc::new(Mutex::new(None)),
+            ack_notifier: Arc::new(Notify::new()),
+            probes: Arc::new(Mutex::new(HashMap::new())),
+            next_probe: Arc::new(AtomicU32::new(0)),
+            shutdown_notifier: Arc::new(Notify::new()),
+            write_shutdown: Arc::new(AtomicBool::new(false)),
+            read_shutdown: Arc::new(AtomicBool::new(false)),
+            structured_close: Arc::new(AtomicBool::new(false)),
+            runtime,
+            tasks: Arc::new(TaskGroup::default()),
+            panic: Arc::new(Mutex::new(None)),
+            terminated: CancelToken::new(),
+            config,
+            pending: Arc::new(PendingWrites::default()),
+            queued_frames: Arc::new(Mutex::new(QueuedFrames::default())),
+            expired_frames: Arc::new(AtomicU64::new(0)),
+            queue_usage: Arc::new(QueueUsage::default()),
+            reader_pool: KindPool::new(),
+            writer_pool: Pool::new(),
+            urgent_pool: Pool::new(),
+            file_pool: Pool::new(),
+            bytes_pool: Pool::new(),
+            reader_beat: Arc::new(Heartbeat::default()),
+            writer_beat: Arc::new(Heartbeat::default()),
+        }
+    }
+
+    /// Returns counters of the I/O loops, the round-trip time is left unknown
+    pub(crate) fn stats(&self) -> TransportStats {
+        TransportStats {
+            queued_bytes: self.queue_usage.bytes(),
+            queued_frames: self.queue_usage.frames(),
+            pending_writes: self.pending.count(),
+            expired_frames: self.expired_frames.load(Ordering::SeqCst),
+            rtt: None,
+        }
+    }
+
+    /// Spawns worker owned by the connection
+    ///
+    /// If the worker panics, the connection is closed with [`INTERNAL_ERROR`] code
+    ///
+    /// [`INTERNAL_ERROR`]: crate::builder::kind_conn::close_code::INTERNAL_ERROR
+    pub(crate) fn spawn<F: Future<Output=()> + Send + 'static>(&self, future: F) {
+        let closer = self.clone();
+
+        self.tasks.spawn(self.runtime.as_ref(), Box::pin(async move {
+            if let Err(message) = runtime::catch_unwind(future).await {
+                closer.panic.lock().unwrap().get_or_insert(message);
+                closer.close(INTERNAL_ERROR).await;
+            }
+        }));
+    }
+
+    /// Reports workers without progress until the connection is closed,
+    /// see [`WatchdogPolicy`]
+    ///
+    /// [`WatchdogPolicy`]: crate::transport::watchdog::WatchdogPolicy
+    pub(crate) async fn watch(&self, policy: WatchdogPolicy) {
+        let workers = [(Worker::Reader, &self.reader_beat), (Worker::Writer, &self.writer_beat)];
+        // Progress seen on the last tick and the number of ticks it has been busy since
+        let mut seen = [(0, 0); 2];
+
+        loop {
+            tokio::select! {
+                _ = self.runtime.sleep(policy.period) => {}
+                _ = self.terminated() => return,
+            }
+
+            for ((worker, beat), (last, busy_ticks)) in workers.iter().zip(seen.iter_mut()) {
+                let progress = beat.progress();
+                let busy = beat.state() != WorkerState::Idle
+                    || (*worker == Worker::Writer && self.pending.count() > 0);
+
+                if progress != *last || !busy {
+                    *last = progress;
+                    *busy_ticks = busy as u32;
+                    continue;
+                }
+
+                // Busy on two ticks in a row without progress, so for the whole period
+                *busy_ticks += 1;
+                if *busy_ticks != 2 {
+                    continue;
+                }
+
+                policy.emit(&StuckWorker {
+                    worker: *worker,
+                    stalled: policy.period,
+                    reader: self.reader_beat.state(),
+                    writer: self.writer_beat.state(),
+                    pending_writes: self.pending.count(),
+                    queued_frames: self.queue_usage.frames(),
+                });
+                if policy.force_close {
+                    self.close(INTERNAL_ERROR).await;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Waits until all workers are finished
+    ///
+    /// Returns error with the panic message if one of them panicked
+    pub(crate) async fn join(&self) -> io::Result<()> {
+        self.tasks.join().await;
+
+        match self.panic.lock().unwrap().as_ref() {
+            Some(message) => Err(io::Error::other(format!("worker panicked: {}", message))),
+            None => Ok(()),
+        }
+    }
+
+    /// Closes the connection without notifying the peer
+    pub(crate) async fn close(&self, code: u8) {
+        self.mark(code, "", CloseCause::Local).await;
+        self.shutdown().await;
+    }
+
+    /// Closes the connection with [`IO_ERROR`] code after the socket failed
+    ///
+    /// [`IO_ERROR`]: crate::builder::kind_conn::close_code::IO_ERROR
+    pub(crate) async fn fail(&self, error: &io::Error) {
+        self.set_cause(error.into());
+        self.close(IO_ERROR).await;
+    }
+
+    /// Remembers why the reader stopped, the connection stays open for writing
+    pub(crate) fn read_failed(&self, error: Option<&io::Error>) {
+        self.set_cause(error.map_or(CloseCause::Eof, CloseCause::from));
+    }
+
+    /// Encodes close and shutdown frames with the encoding agreed with the peer
+    pub(crate) fn set_control_encoding(&self, encoding: Encoding) {
+        self.structured_close.store(encoding == Encoding::Cbor, Ordering::SeqCst);
+    }
+
+    fn control_encoding(&self) -> Encoding {
+        match self.structured_close.load(Ordering::SeqCst) {
+            true => Encoding::Cbor,
+            false => Encoding::Fixed,
+        }
+    }
+
+    /// Sends close frame, waits for acknowledgment and closes the connection
+    ///
+    /// If linger is set, waits for queued frames first.
+    /// Waits at most for the linger and close timeouts
+    pub(crate) async fn close_with_handshake(&self, code: u8, reason: &str) {
+        // The reason is kept as the peer receives it
+        let reason = close_reason(reason);
+        if !self.mark(code, reason, CloseCause::Local).await {
+            return;
+        }
+
+        let linger = self.config.read().unwrap().linger;
+        if let Some(linger) = linger {
+            let _ = runtime::timeout_on(self.runtime.as_ref(), linger, self.pending.flush()).await;
+        }
+
+        let handshake = async {
+            let frame = ControlFrame::Close { code: code.into(), reason: reason.to_string() }.encode_as(self.control_encoding());
+            if self.writer_pool.write(frame).await.is_ok() {
+                self.ack_notifier.notified().await;
+            }
+        };
+        let _ = runtime::timeout_on(self.runtime.as_ref(), self.close_timeout(), handshake).await;
+
+        self.shutdown().await;
+    }
+
+    /// Sends shutdown frame after already queued frames and stops writing
+    ///
+    /// Connection is closed once the peer shuts down its write side too
+    pub(crate) async fn shutdown_write(&self) {
+        if self.write_shutdown.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // Nothing may follow the shutdown frame, so other lanes are closed first
+        self.urgent_pool.close();
+        self.file_pool.close();
+        self.bytes_pool.close();
+        let _ = self.writer_pool.write(ControlFrame::ShutdownWrite.encode_as(self.control_encoding())).await;
+        self.writer_pool.close();
+        // Frames may still wait in the scheduler queue
+        self.pending.flush().await;
+        (self.shutdown_hook)(Shutdown::Write);
+
+        if self.read_shutdown.load(Ordering::SeqCst) {
+            self.close(CLOSED_BY_USER).await;
+        }
+    }
+
+    /// Handles control frame received from the peer
+    ///
+    /// Returns `false` if the peer won't send frames anymore, then the reader
+    /// delivers already received frames and calls [`finish_read()`]
+    ///
+    /// [`finish_read()`]: crate::transport::tcp::closer::ConnCloser::finish_read
+    pub(crate) async fn handle_control(&self, frame: Frame) -> bool {
+        match ControlFrame::decode(&frame) {
+            Some(ControlFrame::Close { code, reason }) => {
+                self.mark(code.code(), &reason, CloseCause::Peer).await;
+
+                let ack = ControlFrame::CloseAck.encode();
+                let _ = runtime::timeout_on(self.runtime.as_ref(), self.close_timeout(), self.urgent_pool.write(ack)).await;
+
+                // Peer is closing too, so there is no sense to wait for its acknowledgment
+                self.ack_notifier.notify_one();
+                self.shutdown().await;
+            }
+            Some(ControlFrame::CloseAck) => self.ack_notifier.notify_one(),
+            Some(ControlFrame::ShutdownWrite) => return false,
+            Some(ControlFrame::Probe { id }) => {
+                // Reader doesn't wait for the writer
+                let urgent_pool = self.urgent_pool.clone();
+                self.spawn(async move {
+                    let _ = urgent_pool.write(ControlFrame::ProbeAck { id }.encode()).await;
+                });
+            }
+            Some(ControlFrame::ProbeAck { id }) => {
+                if let Some(sender) = self.probes.lock().unwrap().remove(&id) {
+                    let _ = sender.send(());
+                }
+            }
+            None => {}
+        }
+
+        true
+    }
+
+    /// Sends probe ahead of queued frames and waits for the peer's acknowledgment
+    ///
+    /// Returns the round trip, [`TimedOut`] error if the probe isn't
+    /// acknowledged in time and [`NotConnected`] if the connection is closed
+    ///
+    /// [`TimedOut`]: std::io::ErrorKind::TimedOut
+    /// [`NotConnected`]: std::io::ErrorKind::NotConnected
+    pub(crate) async fn probe(&self, timeout: Duration) -> io::Result<Duration> {
+        let id = self.next_probe.fetch_add(1, Ordering::SeqCst);
+        let (sender, acknowledged) = oneshot::channel();
+        self.probes.lock().unwrap().insert(id, sender);
+
+        let started = Instant::now();
+        let probe = async {
+            self.urgent_pool.write(ControlFrame::Probe { id }.encode()).await.ok()?;
+            tokio::select! {
+                acknowledged = acknowledged => acknowledged.ok(),
+                _ = self.terminated() => None,
+            }
+        };
+        let probed = runtime::timeout_on(self.runtime.as_ref(), timeout, probe).await;
+        self.probes.lock().unwrap().remove(&id);
+
+        match probed {
+            Ok(Some(())) => Ok(started.elapsed()),
+            Ok(None) => Err(io::Error::new(io::ErrorKind::NotConnected, "connection is closed")),
+            Err(()) => Err(io::Error::new(io::ErrorKind::TimedOut, "probe isn't acknowledged")),
+        }
+    }
+
+    /// Called by the reader once frames sent before the peer's shutdown are delivered
+    ///
+    /// Connection is closed if its write side is shut down too
+    pub(crate) async fn finish_read(&self) {
+        self.read_shutdown.store(true, Ordering::SeqCst);
+
+        if self.write_shutdown.load(Ordering::SeqCst) {
+            self.close(CLOSED_BY_USER).await;
+        }
+    }
+
+    /// Waits until the connection is shut down
+    ///
+    /// Only one task may wait at a time
+    pub(crate) async fn closed(&self) {
+        self.shutdown_notifier.notified().await
+    }
+
+    /// The same as [`closed()`], but any number of tasks may wait
+    ///
+    /// [`closed()`]: crate::transport::tcp::closer::ConnCloser::closed
+    pub(crate) async fn terminated(&self) {
+        self.terminated.cancelled().await
+    }
+
+    pub(crate) async fn code(&self) -> Option<u8> {
+        self.closed.read().await.as_ref().map(|(code, _)| *code)
+    }
+
+    pub(crate) async fn reason(&self) -> Option<String> {
+        self.closed.read().await.as_ref().map(|(_, reason)| reason.clone())
+    }
+
+    pub(crate) fn cause(&self) -> Option<CloseCause> {
+        *self.cause.lock().unwrap()
+    }
+
+    fn close_timeout(&self) -> Duration {
+        self.config.read().unwrap().close_timeout
+    }
+
+    // Returns true if the connection wasn't closed before
+    async fn mark(&self, code: u8, reason: &str, cause: CloseCause) -> bool {
+        let mut closed = self.closed.write().await;
+        if closed.is_none() {
+            *closed = Some((code, reason.to_string()));
+            self.set_cause(cause);
+            true
+        } else {
+            false
+        }
+    }
+
+    // The first cause is kept, e.g. the socket fails once the connection is closed
+    fn set_cause(&self, cause: CloseCause) {
+        self.cause.lock().unwrap().get_or_insert(cause);
+    }
+
+    async fn shutdown(&self) {
+        self.urgent_pool.close();
+        self.file_pool.close();
+        self.bytes_pool.close();
+        self.writer_pool.close();
+        self.reader_pool.close().await;
+        (self.shutdown_hook)(Shutdown::Both);
+        self.shutdown_notifier.notify_one();
+        self.terminated.cancel();
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::PartialConfig;
+use crate::mem::{GrowthPolicy, HEADER_BYTES};
+use crate::net::Resolver;
+use crate::runtime::{default_runtime, Runtime};
+use crate::sync::CancelToken;
+use crate::transport::scheduler::{Scheduler, SchedulerFactory};
+use crate::transport::slow_consumer::SlowConsumerPolicy;
+use crate::transport::watchdog::WatchdogPolicy;
+
+const DEFAULT_CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_PROXY_HEADER_LIMIT: usize = 256;
+
+/// Per-connection settings of the TCP transport
+///
+/// # Example
+///
+/// ```
+/// use cobra_rs::mem::GrowthPolicy;
+/// use cobra_rs::transport::tcp::ConnConfig;
+///
+/// let config = ConnConfig::new()
+///     .set_buffer_policy(GrowthPolicy::new(1024, 64 * 1024));
+/// ```
+#[derive(Clone)]
+pub struct ConnConfig {
+    pub(crate) buffer_policy: GrowthPolicy,
+    pub(crate) recv_high_water_mark: Option<usize>,
+    pub(crate) write_coalescing: Option<Duration>,
+    pub(crate) runtime: Arc<dyn Runtime>,
+    pub(crate) close_timeout: Duration,
+    pub(crate) drain_timeout: Duration,
+    pub(crate) linger: Option<Duration>,
+    pub(crate) cancel: Option<CancelToken>,
+    pub(crate) resolver: Option<Arc<dyn Resolver>>,
+    pub(crate) scheduler: Option<SchedulerFactory>,
+    pub(crate) conflated_kinds: Vec<u8>,
+    pub(crate) frame_ttls: HashMap<u8, Duration>,
+    pub(crate) slow_consumer: Option<SlowConsumerPolicy>,
+    pub(crate) watchdog: Option<WatchdogPolicy>,
+    pub(crate) proxy_protocol: bool,
+    pub(crate) proxy_header_limit: usize,
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    pub(crate) uring: bool,
+}
+
+impl ConnConfig {
+    /// Creates config with default settings
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets growth policy of the receive buffer
+    pub fn set_buffer_policy(mut self, policy: GrowthPolicy) -> Self {
+        self.buffer_policy = policy;
+        self
+    }
+
+    /// Sets maximum number of received bytes of one kind that may wait for the application
+    ///
+    /// Frames of every kind wait in their own queue. Bytes of kinds over
+    /// the mark share one more mark of room, socket reads are paused once
+    /// it's used up and resume once the application drains them. So a kind
+    /// without a reader holds at most two marks and doesn't stop other
+    /// kinds before that. By default the mark is 1 MB
+    ///
+    /// # Note
+    ///
+    /// The mark can't be less than a frame header
+    pub fn set_recv_high_water_mark(mut self, bytes: usize) -> Self {
+        self.recv_high_water_mark = Some(bytes.max(HEADER_BYTES));
+        self
+    }
+
+    /// Enables frame batching on write
+    ///
+    /// After the first frame is queued, writer waits up to `delay` for
+    /// other frames and sends them with a single syscall. Trades a tiny
+    /// latency for fewer syscalls and packets when many small frames are written
+    pub fn set_write_coalescing(mut self, delay: Duration) -> Self {
+        self.write_coalescing = Some(delay);
+        self
+    }
+
+    /// Sets runtime used to spawn connection workers
+    ///
+    /// By default the [`default_runtime()`] is used. See [`HandleRuntime`]
+    /// and [`LocalSetRuntime`] to isolate connections on a dedicated runtime
+    ///
+    /// [`default_runtime()`]: crate::runtime::default_runtime
+    /// [`HandleRuntime`]: crate::runtime::HandleRuntime
+    /// [`LocalSetRuntime`]: crate::runtime::LocalSetRuntime
+    pub fn set_runtime(mut self, runtime: Arc<dyn Runtime>) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    /// Sets how long [`close()`] waits for the peer to acknowledge close frame
+    ///
+    /// [`close()`]: crate::builder::builder::ConnProvider::close
+    pub fn set_close_timeout(mut self, timeout: Duration) -> Self {
+        self.close_timeout = timeout;
+        self
+    }
+
+    /// Sets how long frames received before the peer's EOF or shutdown
+    /// wait for readers, 1 second by default
+    ///
+    /// Readers of kinds without received frames get [`None`] at once.
+    /// Frames not read in time are dropped
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn set_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Sets how long [`close()`] waits for already queued frames
+    /// to be written before sending close frame
+    ///
+    /// By default queued frames that didn't fit into the close timeout are dropped
+    ///
+    /// [`close()`]: crate::builder::builder::ConnProvider::close
+    pub fn set_linger(mut self, linger: Duration) -> Self {
+        self.linger = Some(linger);
+        self
+    }
+
+    /// Sets token which cancels connecting and closes the connection
+    ///
+    /// Cancelled connections are closed with [`CANCELLED`] code.
+    /// Listener stops accepting and closes all accepted connections
+    ///
+    /// [`CANCELLED`]: crate::builder::kind_conn::close_code::CANCELLED
+    pub fn set_cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Sets resolver of hosts passed to [`Conn::connect_host()`]
+    ///
+    /// By default hosts are resolved with [`SystemResolver`]
+    ///
+    /// [`Conn::connect_host()`]: crate::transport::tcp::Conn::connect_host
+    /// [`SystemResolver`]: crate::net::SystemResolver
+    pub fn set_resolver<R: 'static + Resolver>(mut self, resolver: R) -> Self {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Sets policy choosing which of the queued frames is written next
+    ///
+    /// `factory` creates scheduler for every connection. Writes complete once
+    /// the frame is queued, up to [`SCHEDULER_CAPACITY`] frames are queued while
+    /// the socket is busy. [`flush()`] and linger still wait for queued frames.
+    /// Frames queued when the connection fails are dropped
+    ///
+    /// By default frames are written one by one in the order of writes
+    ///
+    /// [`SCHEDULER_CAPACITY`]: crate::transport::scheduler::SCHEDULER_CAPACITY
+    /// [`flush()`]: crate::builder::builder::ConnProvider::flush
+    pub fn set_scheduler<S: 'static + Scheduler, F: 'static + Fn() -> S + Send + Sync>(mut self, factory: F) -> Self {
+        self.scheduler = Some(Arc::new(move || Box::new(factory()) as Box<dyn Scheduler>));
+        self
+    }
+
+    /// Keeps only the latest queued frame of the kinds
+    ///
+    /// Frame written while an older frame of the same kind waits in the
+    /// scheduler queue replaces it, the older frame is never sent. The
+    /// replacing frame takes the place of the replaced one in the queue.
+    /// Suits telemetry and state updates, where stale frames are useless
+    ///
+    /// Frames are queued like with [`set_scheduler()`], [`FifoScheduler`]
+    /// is used if no scheduler is set. Urgent frames aren't conflated
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cobra_rs::transport::tcp::ConnConfig;
+    ///
+    /// const POSITION_KIND: u8 = 20;
+    ///
+    /// let config = ConnConfig::new().set_conflated_kinds(&[POSITION_KIND]);
+    /// ```
+    ///
+    /// [`set_scheduler()`]: crate::transport::tcp::ConnConfig::set_scheduler
+    /// [`FifoScheduler`]: crate::transport::scheduler::FifoScheduler
+    pub fn set_conflated_kinds(mut self, kinds: &[u8]) -> Self {
+        self.conflated_kinds = kinds.to_vec();
+        self
+    }
+
+    /// Drops queued frames of the kind which waited longer than `ttl`
+    ///
+    /// Frame is checked when the writer takes it from the scheduler queue,
+    /// an expired frame is never sent and counted in
+    /// [`TransportStats::expired_frames`]. Suits real-time data, where
+    /// a late frame is worse than a lost one
+    ///
+    /// Frames are queued like with [`set_scheduler()`], [`FifoScheduler`]
+    /// is used if no scheduler is set. Urgent frames don't expire
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use cobra_rs::transport::tcp::ConnConfig;
+    ///
+    /// const VOICE_KIND: u8 = 21;
+    ///
+    /// let config = ConnConfig::new().set_frame_ttl(VOICE_KIND, Duration::from_millis(200));
+    /// ```
+    ///
+    /// [`TransportStats::expired_frames`]: crate::builder::stats::TransportStats::expired_frames
+    /// [`set_scheduler()`]: crate::transport::tcp::ConnConfig::set_scheduler
+    /// [`FifoScheduler`]: crate::transport::scheduler::FifoScheduler
+    pub fn set_frame_ttl(mut self, kind: u8, ttl: Duration) -> Self {
+        self.frame_ttls.insert(kind, ttl);
+        self
+    }
+
+    /// Sets detection of kinds whose frames aren't read by the application
+    ///
+    /// Applies to frames received after the call. By default frames wait
+    /// for their readers without a limit
+    pub fn set_slow_consumer_policy(mut self, policy: SlowConsumerPolicy) -> Self {
+        self.slow_consumer = Some(policy);
+        self
+    }
+
+    /// Sets detection of I/O workers which don't make progress
+    ///
+    /// By default workers aren't watched
+    pub fn set_watchdog(mut self, policy: WatchdogPolicy) -> Self {
+        self.watchdog = Some(policy);
+        self
+    }
+
+    /// Expects HAProxy PROXY protocol header (v1 or v2) on accepted connections
+    ///
+    /// Used behind load balancers: [`accept()`] and [`peer_addr()`] return
+    /// the client address from the header instead of the balancer address,
+    /// the accept filter gets it too. Sockets without a valid header are dropped.
+    /// Headers are read concurrently, each for at most 5 seconds,
+    /// see [`set_proxy_header_limit()`]. Applies to listeners only, disabled by default
+    ///
+    /// # Note
+    ///
+    /// Enable it only if all connections come through the balancer,
+    /// otherwise clients can forge their address
+    ///
+    /// [`accept()`]: crate::transport::tcp::Listener::accept
+    /// [`peer_addr()`]: crate::builder::builder::ConnProvider::peer_addr
+    /// [`set_proxy_header_limit()`]: ConnConfig::set_proxy_header_limit
+    pub fn set_proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    /// Limits number of PROXY headers read at once, 256 by default
+    ///
+    /// New sockets aren't accepted while all headers are being read,
+    /// so clients which send nothing can't pile up reading tasks
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero
+    pub fn set_proxy_header_limit(mut self, max: usize) -> Self {
+        assert!(max > 0, "PROXY header limit must be positive");
+        self.proxy_header_limit = max;
+        self
+    }
+
+    /// Enables socket reads and writes over io_uring
+    ///
+    /// Enabled by default with the `uring` feature. Data is copied through
+    /// buffers registered in the kernel once, so operations don't pin user
+    /// pages every time. It isn't zero-copy: frames are copied into the buffers
+    /// on write and out of them on read. While all registered buffers are busy
+    /// heap buffers are used instead. Connection falls back to the tokio
+    /// reactor if the kernel doesn't support io_uring (e.g. it's forbidden in a container)
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    pub fn set_uring(mut self, enabled: bool) -> Self {
+        self.uring = enabled;
+        self
+    }
+
+    // Applies settings which can be changed on a live connection
+    pub(crate) fn apply(&mut self, config: &PartialConfig) {
+        if let Some(timeout) = config.close_timeout_ms {
+            self.close_timeout = Duration::from_millis(timeout);
+        }
+        if let Some(linger) = config.linger_ms {
+            self.linger = Some(Duration::from_millis(linger));
+        }
+        if let Some(delay) = config.write_coalescing_us {
+            self.write_coalescing = Some(Duration::from_micros(delay));
+        }
+    }
+}
+
+impl ConnConfig {
+    // Frames are queued by the scheduled writer
+    pub(crate) fn is_scheduled(&self) -> bool {
+        self.scheduler.is_some() || !self.conflated_kinds.is_empty() || !self.frame_ttls.is_empty()
+    }
+}
+
+impl Default for ConnConfig {
+    fn default() -> Self {
+        ConnConfig {
+            buffer_policy: Default::default(),
+            recv_high_water_mark: None,
+            write_coalescing: None,
+            runtime: default_runtime(),
+            close_timeout: DEFAULT_CLOSE_TIMEOUT,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            linger: None,
+            cancel: None,
+            resolver: None,
+            scheduler: None,
+            conflated_kinds: Vec::new(),
+            frame_ttls: HashMap::new(),
+            slow_consumer: None,
+            watchdog: None,
+            proxy_protocol: false,
+            proxy_header_limit: DEFAULT_PROXY_HEADER_LIMIT,
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            uring: true,
+        }
+    }
+}
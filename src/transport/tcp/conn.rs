@@ -1,33 +1,87 @@
+use std::fs::File;
+use std::future::Future;
 use std::io;
 use std::net::SocketAddr;
-use std::ops::DerefMut;
-use std::sync::Arc;
-use std::time::Duration;
+use std::ops::{DerefMut, Range};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
-use tokio::net::{TcpStream, ToSocketAddrs};
-use tokio::sync::Notify;
-use tokio::time;
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio::net::{lookup_host, TcpStream, ToSocketAddrs};
+use tokio::sync::{oneshot, Notify, OwnedSemaphorePermit};
 use async_trait::async_trait;
 
-use crate::mem::{ConcatBuf, Frame};
-use crate::sync::{KindPool, Pool, WriteError};
+use crate::mem::{ConcatBuf, Frame, HEADER_BYTES, MAX_BODY_LEN};
+use crate::protocol::Encoding;
+use crate::runtime::{self, Runtime};
+use crate::sync::{CancelToken, Kind, KindPool, Pool, PollSlot, PoolGuard, WriteError};
 use crate::builder::builder::ConnProvider;
+use crate::builder::stats::TransportStats;
+use crate::builder::kind_conn::close_code::CANCELLED;
+use crate::config::PartialConfig;
+use crate::net::{Resolver, SystemResolver};
+use crate::retry::Backoff;
+use crate::transport::close_cause::CloseCause;
+use crate::transport::control::CONTROL_KIND;
+use crate::transport::endpoint::Endpoint;
+use crate::transport::file::{self, FileChunk};
+use crate::transport::tcp::closer::ConnCloser;
+use crate::transport::tcp::ConnConfig;
+use crate::transport::tcp::dispatch::KindQueues;
+use crate::transport::tcp::pending::{PendingGuard, PendingKind, PendingWrites, WriteQueue};
+use crate::transport::tcp::socket::{BytesFrame, SocketIo};
+use crate::transport::scheduler::{FifoScheduler, SCHEDULER_CAPACITY};
+use crate::transport::watchdog::WorkerState;
+
+// Upper bound of bytes sent with one syscall when frames are batched
+const MAX_BATCH_LEN: usize = 64 * 1024;
+
+// Received bytes which may wait for the application if the mark isn't set
+pub(crate) const DEFAULT_HIGH_WATER_MARK: usize = 1024 * 1024;
 
 pub struct Conn {
     inner: Arc<TcpStream>,
+    closer: ConnCloser,
+
+    // Client address received in the PROXY header
+    proxied_addr: Option<SocketAddr>,
+
+    // Limits connections being built, see Listener::limit_handshakes()
+    handshake_permit: Mutex<Option<OwnedSemaphorePermit>>,
+
+    // Operations started by poll-based methods
+    read_slots: Mutex<HashMap<u8, Arc<PollSlot<Option<Frame>>>>>,
+    write_slot: PollSlot<Result<(), WriteError<Frame>>>,
 
     // I/O loops
     reader: ConnReader,
     writer: ConnWriter,
 }
 
+/// Frame queued by [`Conn::write_cancellable()`]
+///
+/// Dropping the ticket doesn't cancel the write
+///
+/// [`Conn::write_cancellable()`]: crate::transport::tcp::Conn::write_cancellable
+pub struct WriteTicket {
+    token: CancelToken,
+    written: oneshot::Receiver<Option<Result<(), WriteError<Frame>>>>,
+}
+
 struct ConnReader {
     pool: KindPool<u8, Frame>,
     readable_notifier: Arc<Notify>,
 }
 
+#[derive(Clone)]
 struct ConnWriter {
     pool: Pool<Frame>,
+    urgent_pool: Pool<Frame>,
+    file_pool: Pool<FileChunk>,
+    bytes_pool: Pool<BytesFrame>,
+    pending: Arc<PendingWrites>,
 }
 
 impl Conn {
@@ -40,7 +94,80 @@ impl Conn {
     ///
     /// [`connect_timeout()`]: crate::transport::tcp::Conn::connect_timeout
     pub async fn connect<T: ToSocketAddrs>(addr: T) -> io::Result<Self> {
-        Ok(Conn::from_raw(TcpStream::connect(addr).await?))
+        Conn::connect_with_config(addr, ConnConfig::default()).await
+    }
+
+    /// Tries to connect to the specified address using custom settings
+    ///
+    /// See [`ConnConfig`] for available settings. The socket is connected
+    /// by the runtime of `config`, see [`Runtime::connect()`]
+    ///
+    /// [`ConnConfig`]: crate::transport::tcp::ConnConfig
+    /// [`Runtime::connect()`]: crate::runtime::Runtime::connect
+    pub async fn connect_with_config<T: ToSocketAddrs>(addr: T, config: ConnConfig) -> io::Result<Self> {
+        let connect = connect_any(config.runtime.as_ref(), addr);
+        let tcp_stream = match &config.cancel {
+            Some(token) => token.run(connect)
+                .await
+                .ok_or_else(|| io::Error::new(io::ErrorKind::Interrupted, "connection cancelled"))??,
+            None => connect.await?,
+        };
+
+        Ok(Conn::from_raw_with_config(tcp_stream, config))
+    }
+
+    /// Tries to connect to the specified address until it succeeds
+    /// or attempts of the backoff are exhausted
+    ///
+    /// Returns the error of the last attempt, see [`Backoff`]
+    ///
+    /// [`Backoff`]: crate::retry::Backoff
+    pub async fn connect_retry<T: ToSocketAddrs + Clone>(addr: T, mut backoff: Backoff) -> io::Result<Self> {
+        backoff.retry(|| Conn::connect(addr.clone())).await
+    }
+
+    /// Resolves the host with the resolver of `config` and connects to
+    /// the first address which accepts the connection
+    ///
+    /// The host is resolved again on every call, so reconnects follow
+    /// changes of DNS or service discovery. See [`ConnConfig::set_resolver()`]
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cobra_rs::transport::tcp::{Conn, ConnConfig};
+    ///
+    /// # async fn run() {
+    /// let conn = Conn::connect_host("chat.example.com:5000", ConnConfig::new()).await.unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`ConnConfig::set_resolver()`]: crate::transport::tcp::ConnConfig::set_resolver
+    pub async fn connect_host(host: &str, config: ConnConfig) -> io::Result<Self> {
+        let addrs = match &config.resolver {
+            Some(resolver) => resolver.resolve(host).await?,
+            None => SystemResolver.resolve(host).await?,
+        };
+
+        let mut last_error = None;
+        for addr in addrs {
+            match Conn::connect_with_config(addr, config.clone()).await {
+                Ok(conn) => return Ok(conn),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => return Err(error),
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host has no addresses")))
+    }
+
+    /// Connects to the endpoint with the transport of its scheme
+    ///
+    /// See [`Endpoint`] for supported schemes
+    ///
+    /// [`Endpoint`]: crate::transport::endpoint::Endpoint
+    pub async fn connect_endpoint(endpoint: &Endpoint, config: ConnConfig) -> io::Result<Box<dyn ConnProvider>> {
+        endpoint.connect(config).await
     }
 
     /// Tries to connect to the specified address
@@ -48,71 +175,395 @@ impl Conn {
     ///
     /// [`connect()`]: crate::transport::tcp::Conn::connect()
     pub async fn connect_timeout<T: ToSocketAddrs>(addr: T, timeout: Duration) -> io::Result<Self> {
+        let runtime = runtime::default_runtime();
         Ok(
             Conn::from_raw(
-                time::timeout(timeout, TcpStream::connect(addr)).await
+                runtime::timeout(timeout, connect_any(runtime.as_ref(), addr)).await
                     .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connection timed out"))??
             )
         )
     }
 
     pub(crate) fn from_raw(tcp_stream: TcpStream) -> Self {
+        Conn::from_raw_with_config(tcp_stream, ConnConfig::default())
+    }
+
+    pub(crate) fn from_raw_with_config(tcp_stream: TcpStream, config: ConnConfig) -> Self {
         let inner = Arc::new(tcp_stream);
+        let closer = ConnCloser::new(inner.clone(), Arc::new(RwLock::new(config.clone())));
+        let io = SocketIo::new(inner.clone(), &config);
+        let reader = ConnReader::create(io.clone(), closer.clone(), &config);
+        let writer = ConnWriter::create(io, closer.clone(), &config);
+
+        if let Some(policy) = config.watchdog.clone() {
+            let watcher = closer.clone();
+            closer.spawn(async move { watcher.watch(policy).await });
+        }
+
+        if let Some(token) = config.cancel.clone() {
+            let watcher = closer.clone();
+            closer.spawn(async move {
+                tokio::select! {
+                    _ = token.cancelled() => watcher.close_with_handshake(CANCELLED, "").await,
+                    _ = watcher.closed() => {}
+                }
+            });
+        }
 
         Conn {
-            inner: inner.clone(),
-            reader: ConnReader::create(inner.clone()),
-            writer: ConnWriter::create(inner),
+            inner,
+            closer,
+            proxied_addr: None,
+            handshake_permit: Mutex::new(None),
+            read_slots: Mutex::new(HashMap::new()),
+            write_slot: PollSlot::new(),
+            reader,
+            writer,
         }
     }
+
+    /// Waits until I/O workers of the connection exit
+    ///
+    /// Workers exit once the connection is closed. Returns error with
+    /// the panic message if one of them panicked, such connection
+    /// is closed with [`INTERNAL_ERROR`] code
+    ///
+    /// [`INTERNAL_ERROR`]: crate::builder::kind_conn::close_code::INTERNAL_ERROR
+    pub async fn join(&self) -> io::Result<()> {
+        self.closer.join().await
+    }
+
+    // Replaces peer address with the address of the proxied client
+    pub(crate) fn with_proxied_addr(mut self, addr: SocketAddr) -> Self {
+        self.proxied_addr = Some(addr);
+        self
+    }
+
+    pub(crate) fn hold_handshake_permit(&self, permit: Option<OwnedSemaphorePermit>) {
+        *self.handshake_permit.lock().unwrap() = permit;
+    }
+
+    /// Poll-based version of [`read()`]
+    ///
+    /// Can be used from manually implemented futures. Read started
+    /// by this method is kept until it completes, so it is safe
+    /// to call it again after [`Poll::Pending`]
+    ///
+    /// [`read()`]: crate::builder::builder::ConnProvider::read
+    /// [`Poll::Pending`]: std::task::Poll::Pending
+    pub fn poll_read_frame(&self, cx: &mut Context<'_>, kind: u8) -> Poll<Option<Frame>> {
+        let slot = self.read_slots.lock().unwrap()
+            .entry(kind)
+            .or_insert_with(|| Arc::new(PollSlot::new()))
+            .clone();
+
+        slot.poll(cx, || {
+            let pool = self.reader.pool.clone();
+            async move { Some(pool.read(kind).await?.accept()) }
+        })
+    }
+
+    /// Poll-based version of [`write()`]
+    ///
+    /// Frame is taken from `frame` when the write starts, subsequent
+    /// calls drive the same write until it completes
+    ///
+    /// # Note
+    ///
+    /// Panics if `frame` is [`None`] and there is no write in progress
+    ///
+    /// [`write()`]: crate::builder::builder::ConnProvider::write
+    /// [`None`]: std::option::Option::None
+    pub fn poll_write_frame(&self, cx: &mut Context<'_>, frame: &mut Option<Frame>) -> Poll<Result<(), WriteError<Frame>>> {
+        self.write_slot.poll(cx, || {
+            let writer = self.writer.clone();
+            let frame = frame.take().expect("poll_write_frame called without a frame");
+            async move { writer.write(frame).await }
+        })
+    }
+
+    /// Queues the frame, it can be taken back with the returned ticket
+    /// until the writer starts sending it
+    ///
+    /// Useful for updates superseded by newer ones, e.g. when only
+    /// the latest position matters
+    ///
+    /// # Note
+    ///
+    /// Frame is queued in the background, so it may be sent after
+    /// frames written later by [`write()`]
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cobra_rs::mem::Frame;
+    /// use cobra_rs::transport::tcp::Conn;
+    ///
+    /// # async fn run(conn: Conn) {
+    /// let ticket = conn.write_cancellable(Frame::create(20, b"x=1"));
+    /// // A newer position makes the previous one useless
+    /// ticket.cancel().await;
+    /// let ticket = conn.write_cancellable(Frame::create(20, b"x=2"));
+    /// # }
+    /// ```
+    ///
+    /// [`write()`]: crate::builder::builder::ConnProvider::write
+    pub fn write_cancellable(&self, frame: Frame) -> WriteTicket {
+        let token = CancelToken::new();
+        let (sender, written) = oneshot::channel();
+
+        let writer = self.writer.clone();
+        let cancel = token.clone();
+        self.closer.spawn(async move {
+            let _ = sender.send(writer.write_cancellable(frame, cancel.cancelled()).await);
+        });
+
+        WriteTicket { token, written }
+    }
+
+    /// Writes a frame with the body shared with the application
+    ///
+    /// The header is sent in front of the body with a vectored write, so
+    /// the body isn't copied into a [`Frame`]. Connections with a scheduler
+    /// copy it, since their frames wait in the queue
+    ///
+    /// Returns [`ErrorKind::InvalidInput`] if the body is longer than
+    /// [`MAX_BODY_LEN`] and [`ErrorKind::NotConnected`] if the connection was closed
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bytes::Bytes;
+    /// use cobra_rs::transport::tcp::Conn;
+    ///
+    /// # async fn run(conn: Conn) {
+    /// let avatar = Bytes::from_static(b"...");
+    /// // The same body is sent without copies
+    /// conn.write_bytes(30, avatar.clone()).await.unwrap();
+    /// conn.write_bytes(30, avatar).await.unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`Frame`]: crate::mem::Frame
+    /// [`ErrorKind::InvalidInput`]: std::io::ErrorKind::InvalidInput
+    /// [`MAX_BODY_LEN`]: crate::mem::MAX_BODY_LEN
+    /// [`ErrorKind::NotConnected`]: std::io::ErrorKind::NotConnected
+    pub async fn write_bytes(&self, kind: u8, body: Bytes) -> io::Result<()> {
+        self.write_segments(kind, vec![body]).await
+    }
+
+    /// Writes a frame with the body made of several segments
+    ///
+    /// The same as [`write_bytes()`], but segments are sent one after
+    /// another with one vectored write, so messages built from existing
+    /// buffers, e.g. a header and a cached blob, aren't concatenated
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bytes::Bytes;
+    /// use cobra_rs::transport::tcp::Conn;
+    ///
+    /// # async fn run(conn: Conn, page: Bytes) {
+    /// let header = Bytes::from(format!("len={}\n", page.len()));
+    /// conn.write_segments(31, vec![header, page]).await.unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`write_bytes()`]: crate::transport::tcp::Conn::write_bytes
+    pub async fn write_segments(&self, kind: u8, segments: Vec<Bytes>) -> io::Result<()> {
+        let frame = BytesFrame { kind, segments };
+        if frame.body_len() > MAX_BODY_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame body is too long"));
+        }
+
+        if self.closer.config.read().unwrap().is_scheduled() {
+            let mut builder = Frame::builder(kind, frame.body_len());
+            frame.segments.iter().for_each(|segment| builder.put_slice(segment));
+            return self.write(builder.finish()).await.map_err(|_| file::closed());
+        }
+
+        self.writer.write_bytes(frame).await.map_err(|_| file::closed())
+    }
+
+    /// Returns frames not yet handed to the kernel for every kind which has them
+    ///
+    /// Counts frames queued in the scheduler and writes waiting for the writer,
+    /// urgent ones included, so the application may shed load of a slow peer
+    pub fn pending(&self) -> HashMap<u8, PendingKind> {
+        self.closer.pending.kinds()
+    }
+
+    /// Drops frames of the kind queued in the scheduler, returns their number
+    ///
+    /// Dropped frames are never sent, their writes have already completed.
+    /// Frame of the batch being written isn't dropped
+    ///
+    /// # Note
+    ///
+    /// Only connections with a scheduler queue frames, see
+    /// [`ConnConfig::set_scheduler()`]. Writes waiting for the writer
+    /// can be cancelled with [`write_cancellable()`]
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cobra_rs::transport::scheduler::FifoScheduler;
+    /// use cobra_rs::transport::tcp::{Conn, ConnConfig};
+    ///
+    /// const PREVIEW_KIND: u8 = 22;
+    ///
+    /// # async fn run() {
+    /// let config = ConnConfig::new().set_scheduler(FifoScheduler::new);
+    /// let conn = Conn::connect_with_config("127.0.0.1:5000", config).await.unwrap();
+    ///
+    /// // Previews aren't worth sending to a peer which can't keep up
+    /// if conn.pending().get(&PREVIEW_KIND).map_or(0, |pending| pending.bytes) > 64 * 1024 {
+    ///     conn.clear_pending(PREVIEW_KIND);
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// [`ConnConfig::set_scheduler()`]: crate::transport::tcp::ConnConfig::set_scheduler
+    /// [`write_cancellable()`]: crate::transport::tcp::Conn::write_cancellable
+    pub fn clear_pending(&self, kind: u8) -> usize {
+        self.closer.queued_frames.lock().unwrap().clear(kind)
+    }
+
+    /// Measures round trip to the peer's transport
+    ///
+    /// Probe is written ahead of queued frames and acknowledged by the peer's
+    /// reader, so it works without a ping provider and doesn't reach the
+    /// application. Suits health checks before an important request
+    ///
+    /// Fails with [`TimedOut`] error if the probe isn't acknowledged in time
+    /// (peers of older versions ignore probes) and with [`NotConnected`]
+    /// error if the connection is closed
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use cobra_rs::transport::tcp::Conn;
+    ///
+    /// # async fn run(conn: Conn) {
+    /// match conn.probe_connectivity(Duration::from_secs(1)).await {
+    ///     Ok(rtt) => println!("peer answered in {:?}", rtt),
+    ///     Err(error) => println!("peer is unreachable: {}", error),
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// [`TimedOut`]: std::io::ErrorKind::TimedOut
+    /// [`NotConnected`]: std::io::ErrorKind::NotConnected
+    pub async fn probe_connectivity(&self, timeout: Duration) -> io::Result<Duration> {
+        self.closer.probe(timeout).await
+    }
+}
+
+impl WriteTicket {
+    /// Takes the frame back if the writer hasn't started sending it
+    ///
+    /// Returns true if the frame was removed and won't be sent
+    pub async fn cancel(self) -> bool {
+        self.token.cancel();
+        matches!(self.written.await, Ok(None))
+    }
+
+    /// Waits until the frame is sent
+    ///
+    /// Returns false if the frame was rejected or the connection was closed
+    pub async fn written(self) -> bool {
+        matches!(self.written.await, Ok(Some(Ok(()))))
+    }
 }
 
 impl ConnReader {
-    fn create(inner: Arc<TcpStream>) -> Self {
+    fn create(io: SocketIo, closer: ConnCloser, config: &ConnConfig) -> Self {
         let worker = ConnReader {
-            pool: KindPool::new(),
+            pool: closer.reader_pool.clone(),
             readable_notifier: Arc::new(Notify::new()),
         };
 
-        worker.spawn(inner);
+        worker.spawn(io, closer, config);
         worker
     }
 
-    fn spawn(&self, inner: Arc<TcpStream>) {
-        let pool = self.pool.clone();
+    fn spawn(&self, io: SocketIo, closer: ConnCloser, config: &ConnConfig) {
         let readable_notifier = self.readable_notifier.clone();
+        let buffer_policy = config.buffer_policy;
+        let high_water_mark = config.recv_high_water_mark.unwrap_or(DEFAULT_HIGH_WATER_MARK);
+        let mut queues = KindQueues::new(self.pool.clone(), closer.clone(), high_water_mark);
+        let beat = closer.reader_beat.clone();
 
-        tokio::spawn(async move {
-            let mut buf = ConcatBuf::default();
+        closer.clone().spawn(async move {
+            let mut buf: ConcatBuf<Frame> = ConcatBuf::with_policy(buffer_policy);
+            let mut peer_shutdown = false;
 
-            loop {
-                if inner.readable().await.is_err() {
-                    break;
-                }
-                readable_notifier.notify_waiters();
+            'read: loop {
+                // Complete frames are queued before the next read, so every kind
+                // holds at most high-water mark bytes plus the room shared by
+                // kinds over their marks. A kind without a reader doesn't stop
+                // other kinds until the shared room is used up. A partially
+                // received frame is always completed, even if it exceeds the mark
+                let room = high_water_mark.saturating_sub(buf.len()).max(1);
+                beat.set_state(WorkerState::Backpressure);
+                let room = queues.wait_for_room(room).await;
+                beat.set_state(WorkerState::Budget);
+                queues.wait_for_budget(&buf).await;
 
-                match inner.try_read_buf(buf.deref_mut()) {
+                let limit = buf.remaining_limit().min(room);
+
+                beat.set_state(WorkerState::Idle);
+                match io.read(&mut buf.deref_mut().limit(limit), &readable_notifier).await {
                     // On EOF closing read worker
-                    Ok(0) => break,
+                    Ok(0) => {
+                        closer.read_failed(None);
+                        break;
+                    }
 
                     // Ok
-                    Ok(_len) => {}
+                    Ok(_len) => {
+                        beat.beat();
+                        buf.adapt_capacity()
+                    }
 
                     // Operation can't be completed now and we should retry it
                     Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
 
                     // Closing read worker on unexpected error
-                    Err(_) => break,
+                    Err(error) => {
+                        closer.read_failed(Some(&error));
+                        break;
+                    }
                 }
 
                 while let Some(frame) = buf.try_read_chunk() {
-                    if pool.write(frame).await.is_err() {
-                        break;
+                    if frame.kind() == CONTROL_KIND {
+                        beat.set_state(WorkerState::Control);
+                        if !closer.handle_control(frame).await {
+                            peer_shutdown = true;
+                            break 'read;
+                        }
+                        continue;
+                    }
+
+                    if !queues.push(frame) {
+                        break 'read;
                     }
                 }
+                queues.track_buffer(&buf);
             }
+            beat.set_state(WorkerState::Idle);
+
+            // Frames received before EOF or shutdown are delivered before readers
+            // of their kinds get None, readers of other kinds get None at once
+            queues.finish().await;
 
-            pool.close().await;
+            if peer_shutdown {
+                closer.finish_read().await;
+            }
         });
     }
 
@@ -125,57 +576,302 @@ impl ConnReader {
         self.readable_notifier.notified().await;
     }
 
-    async fn close(&self) {
-        self.pool.close().await
-    }
 }
 
 impl ConnWriter {
-    fn create(inner: Arc<TcpStream>) -> Self {
+    fn create(io: SocketIo, closer: ConnCloser, config: &ConnConfig) -> Self {
         let worker = ConnWriter {
-            pool: Pool::new(),
+            pool: closer.writer_pool.clone(),
+            urgent_pool: closer.urgent_pool.clone(),
+            file_pool: closer.file_pool.clone(),
+            bytes_pool: closer.bytes_pool.clone(),
+            pending: closer.pending.clone(),
         };
 
-        worker.spawn(inner);
+        worker.spawn(io, closer, config);
         worker
     }
 
-    fn spawn(&self, inner: Arc<TcpStream>) {
+    fn spawn(&self, io: SocketIo, closer: ConnCloser, config: &ConnConfig) {
         let pool = self.pool.clone();
+        let urgent_pool = self.urgent_pool.clone();
+        let file_pool = self.file_pool.clone();
+        let bytes_pool = self.bytes_pool.clone();
+        let pending = self.pending.clone();
+        let runtime = config.runtime.clone();
+        let beat = closer.writer_beat.clone();
 
-        tokio::spawn(async move {
-            while let Some(frame) = pool.read().await {
-                let mut wrote_len = 0;
-
-                while wrote_len < frame.len() {
-                    if inner.writable().await.is_err() {
-                        frame.reject().await;
-                        break;
-                    }
-
-                    match inner.try_write(&frame[wrote_len..]) {
-                        // Ok
-                        Ok(len) => wrote_len += len,
+        if config.is_scheduled() {
+            let scheduler = match &config.scheduler {
+                Some(factory) => factory(),
+                None => Box::new(FifoScheduler::new()),
+            };
+            let queue = WriteQueue::new(scheduler, config, closer.queued_frames.clone(), closer.expired_frames.clone());
+            closer.clone().spawn(ConnWriter::run_scheduled(io, closer, queue, runtime));
+            return;
+        }
 
-                        // Operation can't be completed now and we should retry it
-                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+        closer.clone().spawn(async move {
+            loop {
+                beat.set_state(WorkerState::Idle);
 
-                        // Closing write worker on unexpected error
-                        Err(_) => {
+                // Urgent frames are taken first and never wait for a batch
+                let batch = tokio::select! {
+                    biased;
+                    Some(frame) = urgent_pool.read() => {
+                        beat.set_state(WorkerState::Socket);
+                        if let Err(error) = io.write_all(&frame).await {
+                            frame.reject().await;
+                            closer.fail(&error).await;
+                            break;
+                        }
+                        beat.beat();
+                        continue;
+                    },
+                    Some(chunk) = file_pool.read() => {
+                        beat.set_state(WorkerState::Socket);
+                        if let Err(error) = io.send_file(&chunk).await {
+                            chunk.reject().await;
+                            closer.fail(&error).await;
+                            break;
+                        }
+                        beat.beat();
+                        continue;
+                    },
+                    Some(frame) = bytes_pool.read() => {
+                        beat.set_state(WorkerState::Socket);
+                        if let Err(error) = io.send_bytes(&frame).await {
                             frame.reject().await;
+                            closer.fail(&error).await;
                             break;
                         }
+                        beat.beat();
+                        continue;
+                    },
+                    frame = pool.read() => match frame {
+                        Some(frame) => frame,
+                        None => break,
+                    },
+                };
+
+                // Coalescing may be changed on a live connection, see Conn::reconfigure()
+                let coalescing = closer.config.read().unwrap().write_coalescing;
+                if let Some(delay) = coalescing {
+                    let (batch, _written) = collect_batch(runtime.as_ref(), &pool, &pending, batch, delay).await;
+
+                    beat.set_state(WorkerState::Socket);
+                    if let Err(error) = io.write_all(&batch).await {
+                        closer.fail(&error).await;
+                        break;
                     }
+                    beat.beat();
+                    continue;
+                }
+
+                // A partially written frame can't be followed by another one
+                // without corrupting the stream, so any failure closes the connection
+                beat.set_state(WorkerState::Socket);
+                if let Err(error) = io.write_all(&batch).await {
+                    batch.reject().await;
+                    closer.fail(&error).await;
+                    break;
                 }
+                beat.beat();
             }
+            beat.set_state(WorkerState::Idle);
 
             pool.close();
+            urgent_pool.close();
+            file_pool.close();
+            bytes_pool.close();
         });
     }
 
+    // The same as the default loop, but frames are queued in the scheduler
+    // while the socket is busy and written in the order it chooses
+    async fn run_scheduled(io: SocketIo,
+                           closer: ConnCloser,
+                           mut queue: WriteQueue,
+                           runtime: Arc<dyn Runtime>) {
+        let pool = closer.writer_pool.clone();
+        let urgent_pool = closer.urgent_pool.clone();
+        let pending = closer.pending.clone();
+        let beat = closer.writer_beat.clone();
+
+        let mut urgent = None;
+        let (mut closed, mut urgent_closed) = (false, false);
+
+        loop {
+            if urgent.is_none() && queue.is_empty() {
+                beat.set_state(WorkerState::Idle);
+                tokio::select! {
+                    biased;
+                    frame = urgent_pool.read(), if !urgent_closed => match frame {
+                        Some(frame) => urgent = Some(frame),
+                        None => urgent_closed = true,
+                    },
+                    frame = pool.read() => match frame {
+                        Some(frame) => ConnWriter::enqueue(&mut queue, &pending, frame),
+                        None => break,
+                    },
+                }
+                continue;
+            }
+
+            // Urgent frames skip the queue
+            beat.set_state(WorkerState::Socket);
+            if let Some(frame) = urgent.take() {
+                if let Err(error) = io.write_all(&frame).await {
+                    frame.reject().await;
+                    closer.fail(&error).await;
+                    break;
+                }
+                beat.beat();
+                continue;
+            }
+
+            let coalescing = closer.config.read().unwrap().write_coalescing;
+            if let Some(delay) = coalescing {
+                let deadline = Instant::now() + delay;
+                while !closed && queue.len() < SCHEDULER_CAPACITY {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match runtime::timeout_on(runtime.as_ref(), remaining, pool.read()).await {
+                        Ok(Some(frame)) => ConnWriter::enqueue(&mut queue, &pending, frame),
+                        Ok(None) => closed = true,
+                        Err(()) => break,
+                    }
+                }
+            }
+
+            let mut batch = BytesMut::new();
+            while batch.len() < MAX_BATCH_LEN {
+                match queue.pop() {
+                    Some(frame) => batch.extend_from_slice(&frame),
+                    None => break,
+                }
+            }
+
+            // New frames are queued while the batch is written
+            let written = {
+                let write = io.write_all(&batch);
+                tokio::pin!(write);
+
+                loop {
+                    let room = !closed && queue.len() < SCHEDULER_CAPACITY;
+                    tokio::select! {
+                        biased;
+                        written = &mut write => break written,
+                        frame = urgent_pool.read(), if urgent.is_none() && !urgent_closed => match frame {
+                            Some(frame) => urgent = Some(frame),
+                            None => urgent_closed = true,
+                        },
+                        frame = pool.read(), if room => match frame {
+                            Some(frame) => ConnWriter::enqueue(&mut queue, &pending, frame),
+                            None => closed = true,
+                        },
+                    }
+                }
+            };
+
+            if let Err(error) = written {
+                if let Some(frame) = urgent.take() {
+                    frame.reject().await;
+                }
+                closer.fail(&error).await;
+                break;
+            }
+            queue.finish_batch();
+            beat.beat();
+        }
+        beat.set_state(WorkerState::Idle);
+
+        pool.close();
+        urgent_pool.close();
+        closer.file_pool.close();
+        closer.bytes_pool.close();
+    }
+
+    // Queued frames are pending until they are written, see Conn::flush()
+    fn enqueue(queue: &mut WriteQueue, pending: &Arc<PendingWrites>, frame: PoolGuard<Frame>) {
+        let guard = pending.start(frame.kind(), frame.len());
+        queue.push(frame.accept(), guard);
+    }
+
     async fn write(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        let _pending = self.pending.start(frame.kind(), frame.len());
         self.pool.write(frame).await
     }
+
+    async fn write_cancellable<F: Future<Output=()>>(&self, frame: Frame, cancelled: F) -> Option<Result<(), WriteError<Frame>>> {
+        let _pending = self.pending.start(frame.kind(), frame.len());
+        self.pool.write_cancellable(frame, cancelled).await
+    }
+
+    async fn write_urgent(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        let _pending = self.pending.start(frame.kind(), frame.len());
+        self.urgent_pool.write(frame).await
+    }
+
+    async fn write_file(&self, chunk: FileChunk) -> Result<(), WriteError<FileChunk>> {
+        // Body stays in the page cache
+        let _pending = self.pending.start(chunk.kind, HEADER_BYTES);
+        self.file_pool.write(chunk).await
+    }
+
+    async fn write_bytes(&self, frame: BytesFrame) -> Result<(), WriteError<BytesFrame>> {
+        let _pending = self.pending.start(frame.kind, HEADER_BYTES + frame.body_len());
+        self.bytes_pool.write(frame).await
+    }
+
+    async fn flush(&self) {
+        self.pending.flush().await
+    }
+}
+
+// Connects with the runtime to the first resolved address which accepts the connection
+async fn connect_any<T: ToSocketAddrs>(runtime: &dyn Runtime, addr: T) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for addr in lookup_host(addr).await? {
+        match runtime.connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(error) => last_error = Some(error),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any address")))
+}
+
+/// Collects frames offered within `delay` after the first one into one batch
+///
+/// Frames are accepted once they join the batch, so their writers can
+/// offer the next ones while it is collected. They stay pending until
+/// the returned guards are dropped, see [`Conn::flush()`]
+///
+/// [`Conn::flush()`]: crate::builder::builder::ConnProvider::flush
+pub(crate) async fn collect_batch(runtime: &dyn Runtime,
+                                  pool: &Pool<Frame>,
+                                  pending: &Arc<PendingWrites>,
+                                  first: PoolGuard<Frame>,
+                                  delay: Duration) -> (BytesMut, Vec<PendingGuard>) {
+    let deadline = Instant::now() + delay;
+    let mut batch = BytesMut::new();
+    let mut written = Vec::new();
+    let mut next = Some(first);
+
+    while let Some(frame) = next.take() {
+        written.push(pending.start(frame.kind(), frame.len()));
+        batch.extend_from_slice(&frame.accept());
+        if batch.len() >= MAX_BATCH_LEN {
+            break;
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if let Ok(Some(frame)) = runtime::timeout_on(runtime, remaining, pool.read()).await {
+            next = Some(frame);
+        }
+    }
+
+    (batch, written)
 }
 
 impl Drop for Conn {
@@ -219,25 +915,169 @@ impl ConnProvider for Conn {
         self.writer.write(frame).await
     }
 
+    /// Writes a frame ahead of frames queued by [`write()`]
+    ///
+    /// Frame is written right after the batch which is being written now
+    ///
+    /// [`write()`]: crate::builder::builder::ConnProvider::write
+    async fn write_urgent(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        self.writer.write_urgent(frame).await
+    }
+
+    /// Waits until all frames queued so far are handed to the kernel
+    async fn flush(&self) {
+        self.writer.flush().await
+    }
+
+    /// Writes region of the file as frames of the kind
+    ///
+    /// On Linux frame bodies are sent with `sendfile` straight from the page
+    /// cache. Connections with a scheduler read the file into frames
+    async fn write_file(&self, kind: u8, file: &File, range: Range<u64>) -> io::Result<()> {
+        if self.closer.config.read().unwrap().is_scheduled() {
+            return file::write_file_frames(self, kind, file, range).await;
+        }
+
+        // Frame header is sent before the body, so the body can't be cut short
+        if range.end > file.metadata()?.len() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        for chunk in FileChunk::split(kind, Arc::new(file.try_clone()?), range) {
+            self.writer.write_file(chunk).await.map_err(|_| file::closed())?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies write coalescing, close timeout and linger settings
+    ///
+    /// New settings are used by the next batch and the next close
+    fn reconfigure(&self, config: &PartialConfig) {
+        self.closer.config.write().unwrap().apply(config);
+    }
+
+    /// Returns queue depths and the round-trip time estimated by the kernel
+    ///
+    /// The round-trip time is known only on Linux
+    fn stats(&self) -> TransportStats {
+        TransportStats {
+            rtt: tcp_rtt(&self.inner),
+            ..self.closer.stats()
+        }
+    }
+
+    /// Releases the handshake slot of the listener
+    fn handshake_complete(&self) {
+        self.handshake_permit.lock().unwrap().take();
+    }
+
+    fn set_control_encoding(&self, encoding: Encoding) {
+        self.closer.set_control_encoding(encoding)
+    }
+
     /// Returns local address that connection bound to
     fn local_addr(&self) -> io::Result<SocketAddr> {
         self.inner.local_addr()
     }
 
     /// Returns remote address that connection connected to
+    ///
+    /// Returns client address if it was received in the PROXY header,
+    /// see [`ConnConfig::set_proxy_protocol()`]
+    ///
+    /// [`ConnConfig::set_proxy_protocol()`]: crate::transport::tcp::ConnConfig::set_proxy_protocol
     fn peer_addr(&self) -> io::Result<SocketAddr> {
-        self.inner.peer_addr()
+        match self.proxied_addr {
+            Some(addr) => Ok(addr),
+            None => self.inner.peer_addr(),
+        }
     }
 
     async fn readable(&self) {
         self.reader.readable().await;
     }
 
-    async fn close(&self, _code: u8) {
-        todo!()
+    /// Closes the connection with the specified code
+    ///
+    /// The same as [`close_with_reason()`] with an empty reason
+    ///
+    /// [`close_with_reason()`]: crate::builder::builder::ConnProvider::close_with_reason
+    async fn close(&self, code: u8) {
+        self.close_with_reason(code, "").await
     }
 
+    /// Sends close frame with the code and reason to the peer, waits
+    /// for its acknowledgment (see [`ConnConfig::set_close_timeout()`])
+    /// and closes the connection. Returns once I/O workers exit, see [`join()`]
+    ///
+    /// Pending and subsequent reads return [`None`],
+    /// writes return [`WriteError::Closed`]
+    ///
+    /// [`ConnConfig::set_close_timeout()`]: crate::transport::tcp::ConnConfig::set_close_timeout
+    /// [`join()`]: crate::transport::tcp::Conn::join
+    /// [`None`]: std::option::Option::None
+    /// [`WriteError::Closed`]: crate::sync::WriteError::Closed
+    async fn close_with_reason(&self, code: u8, reason: &str) {
+        self.closer.close_with_handshake(code, reason).await;
+        let _ = self.closer.join().await;
+    }
+
+    /// Flushes frames queued before the call, notifies the peer
+    /// and stops writing
+    ///
+    /// Reads are available until the peer shuts down its write
+    /// side or closes the connection. Subsequent writes return [`WriteError::Closed`]
+    ///
+    /// [`WriteError::Closed`]: crate::sync::WriteError::Closed
+    async fn shutdown_write(&self) {
+        self.closer.shutdown_write().await
+    }
+
+    /// Returns close code if the connection was closed
+    ///
+    /// See [`close_code`] for codes used by the library
+    ///
+    /// [`close_code`]: crate::builder::kind_conn::close_code
     async fn is_close(&self) -> Option<u8> {
-        todo!()
+        self.closer.code().await
+    }
+
+    /// Returns close reason if the connection was closed
+    ///
+    /// Contains reason sent by the peer if it closed the connection
+    async fn close_reason(&self) -> Option<String> {
+        self.closer.reason().await
+    }
+
+    /// Returns why the connection was closed or stopped reading
+    ///
+    /// The reader stops on EOF or a socket error, e.g. a reset,
+    /// even if the connection is still open for writing
+    async fn close_cause(&self) -> Option<CloseCause> {
+        self.closer.cause()
     }
 }
+
+// Smoothed round-trip time of the socket, see `tcp(7)`
+#[cfg(target_os = "linux")]
+fn tcp_rtt(stream: &TcpStream) -> Option<Duration> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(stream.as_raw_fd(),
+                         libc::IPPROTO_TCP,
+                         libc::TCP_INFO,
+                         &mut info as *mut libc::tcp_info as *mut libc::c_void,
+                         &mut len)
+    };
+
+    (result == 0).then(|| Duration::from_micros(info.tcpi_rtt as u64))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn tcp_rtt(_stream: &TcpStream) -> Option<Duration> {
+    None
+}
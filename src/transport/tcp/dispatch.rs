@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::Notify;
+
+use crate::builder::kind_conn::close_code::SLOW_CONSUMER;
+use crate::mem::{Area, ConcatBuf, Frame, MemoryBudget, Usage};
+use crate::runtime;
+use crate::sync::{Kind, KindPool};
+use crate::transport::slow_consumer::{SlowConsumer, SlowConsumerPolicy};
+use crate::transport::tcp::closer::ConnCloser;
+
+/// Delivers received frames to the application independently for every kind
+///
+/// Every kind has its own queue drained by its own task, so a kind
+/// without a reader doesn't stop frames of other kinds. Every kind may
+/// queue up to the high-water mark, bytes over it share one more mark
+/// of room. Socket reads are paused once the shared room is used up
+/// or the [`MemoryBudget`] is exceeded
+///
+/// [`MemoryBudget`]: crate::mem::MemoryBudget
+pub(crate) struct KindQueues {
+    pool: KindPool<u8, Frame>,
+    closer: ConnCloser,
+    queues: HashMap<u8, KindQueue>,
+    high_water_mark: usize,
+    usage: Arc<QueueUsage>,
+    buffered: Usage,
+    capacity: Usage,
+}
+
+/// Bytes and frames waiting for the application
+#[derive(Default)]
+pub(crate) struct QueueUsage {
+    bytes: AtomicUsize,
+    frames: AtomicUsize,
+    changed: Notify,
+}
+
+struct KindQueue {
+    sender: UnboundedSender<Queued>,
+    // Bytes queued for the kind
+    bytes: Arc<AtomicUsize>,
+}
+
+// Releases queue space when the frame is delivered or dropped
+struct Queued {
+    frame: Option<Frame>,
+    len: usize,
+    kind_bytes: Arc<AtomicUsize>,
+    usage: Arc<QueueUsage>,
+    _budget: Usage,
+}
+
+impl QueueUsage {
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn frames(&self) -> usize {
+        self.frames.load(Ordering::SeqCst)
+    }
+}
+
+impl KindQueues {
+    pub(crate) fn new(pool: KindPool<u8, Frame>, closer: ConnCloser, high_water_mark: usize) -> Self {
+        KindQueues {
+            pool,
+            usage: closer.queue_usage.clone(),
+            closer,
+            queues: HashMap::new(),
+            high_water_mark,
+            buffered: Usage::new(Area::ReceiveBuffers, 0),
+            capacity: Usage::new(Area::ReceiveCapacity, 0),
+        }
+    }
+
+    /// Waits until kinds over the high-water mark leave some of `room`
+    /// and returns what's left of it
+    pub(crate) async fn wait_for_room(&self, room: usize) -> usize {
+        loop {
+            let changed = self.usage.changed.notified();
+            let over_mark = self.over_mark();
+            if over_mark < room {
+                return room - over_mark;
+            }
+            changed.await;
+        }
+    }
+
+    // Returns number of bytes queued over the high-water mark of their kinds
+    fn over_mark(&self) -> usize {
+        self.queues.values()
+            .map(|queue| queue.bytes.load(Ordering::SeqCst).saturating_sub(self.high_water_mark))
+            .sum()
+    }
+
+    /// Waits until the memory budget allows the next socket read
+    ///
+    /// A partially received frame is always completed
+    pub(crate) async fn wait_for_budget(&self, buf: &ConcatBuf<Frame>) {
+        if buf.buffered() > 0 {
+            return;
+        }
+
+        tokio::select! {
+            _ = MemoryBudget::global().wait_below_limit() => {}
+            _ = self.closer.terminated() => {}
+        }
+    }
+
+    /// Reports memory held by the receive buffer to the budget
+    pub(crate) fn track_buffer(&mut self, buf: &ConcatBuf<Frame>) {
+        self.buffered.set(buf.buffered());
+        self.capacity.set(buf.limit());
+    }
+
+    /// Queues frame for delivery, returns `false` if the pool was closed
+    pub(crate) fn push(&mut self, frame: Frame) -> bool {
+        let kind = frame.kind();
+        let len = frame.len();
+
+        let (pool, closer) = (&self.pool, &self.closer);
+        let queue = self.queues.entry(kind)
+            .or_insert_with(|| KindQueue {
+                sender: KindQueues::spawn_delivery(kind, pool.clone(), closer),
+                bytes: Arc::new(AtomicUsize::new(0)),
+            });
+
+        queue.bytes.fetch_add(len, Ordering::SeqCst);
+        self.usage.bytes.fetch_add(len, Ordering::SeqCst);
+        self.usage.frames.fetch_add(1, Ordering::SeqCst);
+        let queued = Queued {
+            frame: Some(frame),
+            len,
+            kind_bytes: queue.bytes.clone(),
+            usage: self.usage.clone(),
+            _budget: Usage::new(Area::ReceivedFrames, len),
+        };
+
+        queue.sender.send(queued).is_ok()
+    }
+
+    /// Delivers frames queued before EOF or shutdown and closes the pool
+    ///
+    /// Kinds without queued frames are closed at once, other kinds once
+    /// their frames are read. Frames not read within the drain timeout
+    /// are dropped, so a kind without a reader doesn't keep the pool open
+    pub(crate) async fn finish(mut self) {
+        let queued: Vec<u8> = self.queues.keys().copied().collect();
+        self.pool.close_except(&queued).await;
+        // Delivery tasks close their kinds once the queues are drained
+        self.queues.clear();
+
+        let drained = async {
+            loop {
+                let changed = self.usage.changed.notified();
+                if self.usage.frames.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                changed.await;
+            }
+        };
+        let (runtime, timeout) = {
+            let config = self.closer.config.read().unwrap();
+            (config.runtime.clone(), config.drain_timeout)
+        };
+        let _ = runtime::timeout_on(runtime.as_ref(), timeout, drained).await;
+
+        self.pool.close().await;
+    }
+
+    fn spawn_delivery(kind: u8, pool: KindPool<u8, Frame>, closer: &ConnCloser) -> UnboundedSender<Queued> {
+        let (sender, mut receiver) = unbounded_channel::<Queued>();
+        let closer = closer.clone();
+
+        closer.clone().spawn(async move {
+            while let Some(mut queued) = receiver.recv().await {
+                let frame = queued.frame.take().unwrap();
+                let policy = closer.config.read().unwrap().slow_consumer.clone();
+
+                let delivered = match policy {
+                    Some(policy) => KindQueues::deliver_watched(kind, &pool, frame, &policy, &closer).await,
+                    None => pool.write(frame).await.is_ok(),
+                };
+                if !delivered {
+                    break;
+                }
+            }
+            pool.close_kind(kind).await;
+        });
+
+        sender
+    }
+
+    // Reports the frame if it's not read within the window of the policy
+    async fn deliver_watched(kind: u8,
+                             pool: &KindPool<u8, Frame>,
+                             frame: Frame,
+                             policy: &SlowConsumerPolicy,
+                             closer: &ConnCloser) -> bool {
+        let write = pool.write(frame);
+        tokio::pin!(write);
+
+        let runtime = closer.config.read().unwrap().runtime.clone();
+        if let Ok(result) = runtime::timeout_on(runtime.as_ref(), policy.window, &mut write).await {
+            return result.is_ok();
+        }
+
+        policy.emit(&SlowConsumer { kind, waited: policy.window });
+        if policy.evict {
+            closer.close_with_handshake(SLOW_CONSUMER, "slow consumer").await;
+            return false;
+        }
+
+        write.await.is_ok()
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        self.kind_bytes.fetch_sub(self.len, Ordering::SeqCst);
+        self.usage.bytes.fetch_sub(self.len, Ordering::SeqCst);
+        self.usage.frames.fetch_sub(1, Ordering::SeqCst);
+        self.usage.changed.notify_waiters();
+    }
+}
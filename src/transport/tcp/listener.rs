@@ -1,44 +1,461 @@
-use std::io;
-use std::sync::Arc;
+use std::future::{pending, poll_fn};
+use std::{io, net};
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::{env, process};
+#[cfg(unix)]
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
 
-use tokio::net::{TcpListener, ToSocketAddrs};
-use tokio::sync::Notify;
+use futures_core::Stream;
+use socket2::{Domain, SockRef, Socket, Type};
+use tokio::net::{lookup_host, TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
 
-use crate::sync::Pool;
-use crate::transport::tcp::Conn;
+use crate::retry::Backoff;
+use crate::runtime;
+use crate::sync::{PollSlot, Pool};
+use crate::transport::tcp::{Conn, ConnConfig};
+use crate::transport::tcp::proxy::{self, PROXY_HEADER_TIMEOUT};
+
+type AcceptFilter = Arc<dyn Fn(&SocketAddr) -> bool + Send + Sync>;
+type Incoming = io::Result<(Conn, SocketAddr)>;
+
+// Backlog of sockets bound by listen_all()
+const LISTEN_BACKLOG: i32 = 1024;
+
+// Pause after failed accepts, e.g. when file descriptors are exhausted
+const ACCEPT_BACKOFF_INITIAL: Duration = Duration::from_millis(10);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+// First descriptor passed by the service manager, see sd_listen_fds(3)
+#[cfg(unix)]
+const LISTEN_FDS_START: RawFd = 3;
 
 pub struct Listener {
-    connections_pool: Pool<Conn>,
+    tcp_listeners: Arc<[TcpListener]>,
+    connections_pool: Pool<Incoming>,
     close_notifier: Arc<Notify>,
+    hooks: Arc<AcceptHooks>,
+    accept_slot: PollSlot<Option<Incoming>>,
+}
+
+/// Settings of the accept loop which can be changed while listening
+#[derive(Default)]
+struct AcceptHooks {
+    filter: RwLock<Option<AcceptFilter>>,
+    handshakes: RwLock<Option<Arc<Semaphore>>>,
+    drain: Notify,
 }
 
 impl Listener {
     pub async fn listen<T: ToSocketAddrs>(addr: T) -> io::Result<Self> {
-        let tcp_listener = Arc::new(TcpListener::bind(addr).await?);
+        Listener::listen_with_config(addr, ConnConfig::default()).await
+    }
+
+    /// Starts listening, accepted connections will use the specified settings
+    ///
+    /// The listener is bound by the runtime of `config`, see [`Runtime::bind()`]
+    ///
+    /// [`Runtime::bind()`]: crate::runtime::Runtime::bind
+    pub async fn listen_with_config<T: ToSocketAddrs>(addr: T, config: ConnConfig) -> io::Result<Self> {
+        let mut last_error = None;
+        for addr in lookup_host(addr).await? {
+            match config.runtime.bind(addr).await {
+                Ok(tcp_listener) => return Ok(Listener::start(vec![tcp_listener], config)),
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any address")))
+    }
+
+    /// Starts listening on all specified addresses at once
+    ///
+    /// Connections accepted on any of the addresses are returned by the same
+    /// [`accept()`]. Every address is resolved and all resolved addresses are bound
+    ///
+    /// # Note
+    ///
+    /// IPv6 sockets are bound in IPv6-only mode, so `0.0.0.0` and `[::]` with
+    /// the same port can be used together. Addresses with port 0 get the
+    /// port chosen for the first of them, so all sockets share one port
+    ///
+    /// [`accept()`]: crate::transport::tcp::Listener::accept
+    pub async fn listen_all<T: ToSocketAddrs>(addrs: &[T]) -> io::Result<Self> {
+        Listener::listen_all_with_config(addrs, ConnConfig::default()).await
+    }
+
+    /// The same as [`listen_all()`] but accepted connections will use the specified settings
+    ///
+    /// [`listen_all()`]: crate::transport::tcp::Listener::listen_all
+    pub async fn listen_all_with_config<T: ToSocketAddrs>(addrs: &[T], config: ConnConfig) -> io::Result<Self> {
+        let mut tcp_listeners = Vec::new();
+        let mut ephemeral_port = None;
+        for addr in addrs {
+            for mut addr in lookup_host(addr).await? {
+                if addr.port() == 0 {
+                    addr.set_port(ephemeral_port.unwrap_or(0));
+                }
+
+                let tcp_listener = Listener::bind_only(addr)?;
+                if addr.port() == 0 {
+                    ephemeral_port = Some(tcp_listener.local_addr()?.port());
+                }
+                tcp_listeners.push(tcp_listener);
+            }
+        }
+
+        if tcp_listeners.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no addresses to listen on"));
+        }
+
+        Ok(Listener::start(tcp_listeners, config))
+    }
+
+    /// Creates `n` listeners sharing the port via `SO_REUSEPORT`
+    ///
+    /// Every listener has its own socket and accept loop, the kernel spreads
+    /// incoming connections between them. Accept loops are separate tasks,
+    /// so on a multi-threaded runtime they run on different workers. Only
+    /// the first resolved address is bound, port 0 is resolved once and
+    /// shared by all listeners
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cobra_rs::transport::tcp::Listener;
+    ///
+    /// # async fn serve() -> std::io::Result<()> {
+    /// for listener in Listener::listen_reuseport("0.0.0.0:5000", 8).await? {
+    ///     tokio::spawn(async move {
+    ///         while let Some((conn, addr)) = listener.accept().await {
+    ///             // ...
+    ///         }
+    ///     });
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+    pub async fn listen_reuseport<T: ToSocketAddrs>(addr: T, n: usize) -> io::Result<Vec<Self>> {
+        Listener::listen_reuseport_with_config(addr, n, ConnConfig::default()).await
+    }
+
+    /// The same as [`listen_reuseport()`] but accepted connections will use the specified settings
+    ///
+    /// [`listen_reuseport()`]: crate::transport::tcp::Listener::listen_reuseport
+    #[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+    pub async fn listen_reuseport_with_config<T: ToSocketAddrs>(addr: T, n: usize, config: ConnConfig) -> io::Result<Vec<Self>> {
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no listeners requested"));
+        }
+
+        let mut addr = lookup_host(addr).await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no addresses to listen on"))?;
+
+        // All sockets are bound before any accept loop starts
+        let mut tcp_listeners = Vec::with_capacity(n);
+        for _ in 0..n {
+            let tcp_listener = Listener::bind_reuseport(addr)?;
+            addr = tcp_listener.local_addr()?;
+            tcp_listeners.push(tcp_listener);
+        }
+
+        Ok(tcp_listeners.into_iter()
+            .map(|tcp_listener| Listener::start(vec![tcp_listener], config.clone()))
+            .collect())
+    }
+
+    /// Starts accepting connections on an already bound listener
+    ///
+    /// Can be used to take over sockets created by another process
+    pub fn from_std(tcp_listener: net::TcpListener) -> io::Result<Self> {
+        Listener::from_std_with_config(tcp_listener, ConnConfig::default())
+    }
+
+    /// The same as [`from_std()`] but accepted connections will use the specified settings
+    ///
+    /// [`from_std()`]: crate::transport::tcp::Listener::from_std
+    pub fn from_std_with_config(tcp_listener: net::TcpListener, config: ConnConfig) -> io::Result<Self> {
+        tcp_listener.set_nonblocking(true)?;
+        Ok(Listener::start(vec![TcpListener::from_std(tcp_listener)?], config))
+    }
+
+    /// Starts accepting connections on the listening socket descriptor
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open listening TCP socket owned by the caller,
+    /// ownership is transferred to the listener
+    #[cfg(unix)]
+    pub unsafe fn from_raw_fd(fd: RawFd) -> io::Result<Self> {
+        Listener::from_std(net::TcpListener::from_raw_fd(fd))
+    }
+
+    /// Takes sockets passed by the service manager (systemd socket activation)
+    ///
+    /// Honors `LISTEN_PID` and `LISTEN_FDS` variables and removes them, so
+    /// child processes don't take the same sockets. Returns [`None`] if no
+    /// sockets were passed to this process. All passed sockets are served
+    /// by one listener like [`listen_all()`] does
+    ///
+    /// [`None`]: std::option::Option::None
+    /// [`listen_all()`]: crate::transport::tcp::Listener::listen_all
+    #[cfg(unix)]
+    pub fn from_listen_fds(config: ConnConfig) -> io::Result<Option<Self>> {
+        let pid = env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+        let fds = env::var("LISTEN_FDS").ok().and_then(|fds| fds.parse::<RawFd>().ok());
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+
+        let fds = match (pid, fds) {
+            (Some(pid), Some(fds)) if pid == process::id() && fds > 0 => fds,
+            _ => return Ok(None),
+        };
+
+        let mut tcp_listeners = Vec::new();
+        for fd in LISTEN_FDS_START..LISTEN_FDS_START + fds {
+            // Descriptors are passed to this process exclusively, checked above
+            let tcp_listener = unsafe { net::TcpListener::from_raw_fd(fd) };
+            tcp_listener.set_nonblocking(true)?;
+            tcp_listeners.push(TcpListener::from_std(tcp_listener)?);
+        }
+
+        Ok(Some(Listener::start(tcp_listeners, config)))
+    }
+
+    /// Takes over sockets exported by another process, see [`export_fds()`]
+    ///
+    /// # Safety
+    ///
+    /// Every descriptor must be an open listening TCP socket owned by the caller,
+    /// ownership is transferred to the listener
+    ///
+    /// [`export_fds()`]: crate::transport::tcp::Listener::export_fds
+    #[cfg(unix)]
+    pub unsafe fn import_fds(fds: &[RawFd], config: ConnConfig) -> io::Result<Self> {
+        let mut tcp_listeners = Vec::new();
+        for fd in fds {
+            let tcp_listener = net::TcpListener::from_raw_fd(*fd);
+            tcp_listener.set_nonblocking(true)?;
+            tcp_listeners.push(TcpListener::from_std(tcp_listener)?);
+        }
+
+        Ok(Listener::start(tcp_listeners, config))
+    }
+
+    /// Duplicates listening sockets so they can be passed to another process
+    ///
+    /// Returned descriptors are inherited by spawned processes and
+    /// must be closed by the caller. Both processes accept connections
+    /// until [`drain()`] is called on the old one
+    ///
+    /// [`drain()`]: crate::transport::tcp::Listener::drain
+    #[cfg(unix)]
+    pub fn export_fds(&self) -> io::Result<Vec<RawFd>> {
+        self.tcp_listeners.iter()
+            .map(|tcp_listener| {
+                let socket = SockRef::from(tcp_listener).try_clone()?;
+                socket.set_cloexec(false)?;
+                Ok(socket.into_raw_fd())
+            })
+            .collect()
+    }
+
+    /// Stops accepting new connections
+    ///
+    /// Connection accepted before the call is still returned by [`accept()`],
+    /// after that [`accept()`] returns [`None`]. Already accepted connections
+    /// aren't affected, so they can be finished gracefully while another
+    /// process accepts new ones from the exported sockets
+    ///
+    /// [`accept()`]: crate::transport::tcp::Listener::accept
+    /// [`None`]: std::option::Option::None
+    pub fn drain(&self) {
+        self.hooks.drain.notify_one();
+    }
+
+    /// Returns address of the first bound socket
+    ///
+    /// Listener bound to port 0 gets a free port from the OS, which can be
+    /// passed to clients. Sockets are bound before [`listen()`] returns,
+    /// so clients may connect right away
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cobra_rs::transport::tcp::{Conn, Listener};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> std::io::Result<()> {
+    /// let listener = Listener::listen("127.0.0.1:0").await?;
+    /// let addr = listener.local_addr()?;
+    ///
+    /// let client = Conn::connect(addr).await?;
+    /// let (server, peer) = listener.accept().await.unwrap();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`listen()`]: crate::transport::tcp::Listener::listen
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp_listeners.first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no bound sockets"))?
+            .local_addr()
+    }
+
+    /// Returns addresses of the bound sockets
+    pub fn local_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        self.tcp_listeners.iter().map(TcpListener::local_addr).collect()
+    }
+
+    // Binds socket without accepting IPv4 connections on IPv6 addresses
+    fn bind_only(addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        if addr.is_ipv6() {
+            socket.set_only_v6(true)?;
+        }
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(LISTEN_BACKLOG)?;
+
+        TcpListener::from_std(socket.into())
+    }
+
+    #[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+    fn bind_reuseport(addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        socket.set_reuse_address(true)?;
+        socket.set_reuse_port(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(LISTEN_BACKLOG)?;
+
+        TcpListener::from_std(socket.into())
+    }
+
+    fn start(tcp_listeners: Vec<TcpListener>, config: ConnConfig) -> Self {
+        let tcp_listeners: Arc<[TcpListener]> = tcp_listeners.into();
         let connections_pool = Pool::new();
         let close_notifier = Arc::new(Notify::new());
+        let hooks = Arc::new(AcceptHooks::default());
 
-        tokio::spawn(Listener::accept_loop(
-            tcp_listener,
+        config.runtime.clone().spawn(Box::pin(Listener::accept_loop(
+            tcp_listeners.clone(),
             connections_pool.clone(),
             close_notifier.clone(),
-        ));
+            hooks.clone(),
+            config,
+        )));
 
-        Ok(Listener {
+        Listener {
+            tcp_listeners,
             connections_pool,
             close_notifier,
-        })
+            hooks,
+            accept_slot: PollSlot::new(),
+        }
+    }
+
+    // Accepts socket from the first ready listener
+    async fn accept_any(tcp_listeners: &[TcpListener]) -> io::Result<(TcpStream, SocketAddr)> {
+        poll_fn(|cx| {
+            tcp_listeners.iter()
+                .find_map(|tcp_listener| match tcp_listener.poll_accept(cx) {
+                    Poll::Ready(incoming) => Some(Poll::Ready(incoming)),
+                    Poll::Pending => None,
+                })
+                .unwrap_or(Poll::Pending)
+        }).await
     }
 
-    async fn accept_loop(tcp_listener: Arc<TcpListener>,
-                         connections_pool: Pool<Conn>,
-                         close_notifier: Arc<Notify>) {
+    async fn cancelled(config: &ConnConfig) {
+        match &config.cancel {
+            Some(token) => token.cancelled().await,
+            None => pending().await,
+        }
+    }
+
+    async fn accept_loop(tcp_listeners: Arc<[TcpListener]>,
+                         connections_pool: Pool<Incoming>,
+                         close_notifier: Arc<Notify>,
+                         hooks: Arc<AcceptHooks>,
+                         config: ConnConfig) {
         let run = async move {
-            while let Ok((socket, _)) = tcp_listener.accept().await {
-                let conn = Conn::from_raw(socket);
-                if connections_pool.write(conn).await.is_err() {
+            let mut backoff = Backoff::new(ACCEPT_BACKOFF_INITIAL, ACCEPT_BACKOFF_MAX);
+            let header_reads = Arc::new(Semaphore::new(config.proxy_header_limit));
+            loop {
+                // Waiting for a free handshake slot before accepting a socket
+                let handshakes = hooks.handshakes.read().unwrap().clone();
+                let permit = match handshakes {
+                    Some(handshakes) => match handshakes.acquire_owned().await {
+                        Ok(permit) => Some(permit),
+                        Err(_) => break,
+                    },
+                    None => None,
+                };
+                // Silent clients mustn't pile up header reads
+                let header_permit = match config.proxy_protocol {
+                    true => match header_reads.clone().acquire_owned().await {
+                        Ok(permit) => Some(permit),
+                        Err(_) => break,
+                    },
+                    false => None,
+                };
+
+                let accepted = tokio::select! {
+                    accepted = Listener::accept_any(&tcp_listeners) => accepted,
+                    _ = hooks.drain.notified() => break,
+                    _ = Listener::cancelled(&config) => break,
+                };
+
+                // Errors like EMFILE repeat until sockets are closed, so retrying at once would spin
+                let delay = match accepted {
+                    Ok(_) => {
+                        backoff.reset();
+                        None
+                    }
+                    Err(_) => backoff.next_delay(),
+                };
+
+                let incoming = match accepted {
+                    // Slow balancers must not delay other sockets
+                    Ok((socket, addr)) if config.proxy_protocol => {
+                        let proxied = Listener::accept_proxied(socket,
+                                                               addr,
+                                                               permit,
+                                                               header_permit,
+                                                               hooks.clone(),
+                                                               connections_pool.clone(),
+                                                               config.clone());
+                        config.runtime.spawn(Box::pin(proxied));
+                        continue;
+                    }
+                    Ok((socket, addr)) => match Listener::admit(socket, addr, permit, &hooks, &config) {
+                        Some(conn) => Ok((conn, addr)),
+                        None => continue,
+                    },
+                    Err(e) => Err(e),
+                };
+
+                if connections_pool.write(incoming).await.is_err() {
                     break;
                 }
+
+                if let Some(delay) = delay {
+                    tokio::select! {
+                        _ = config.runtime.sleep(delay) => {}
+                        _ = hooks.drain.notified() => break,
+                        _ = Listener::cancelled(&config) => break,
+                    }
+                }
             }
             connections_pool.close();
         };
@@ -48,14 +465,97 @@ impl Listener {
         };
     }
 
-    pub async fn accept(&self) -> Option<Conn> {
-        Some(self.connections_pool
-            .read()
-            .await?
-            .accept())
+    // Reads PROXY header, sockets without a valid one are dropped
+    async fn accept_proxied(mut socket: TcpStream,
+                            addr: SocketAddr,
+                            permit: Option<OwnedSemaphorePermit>,
+                            header_permit: Option<OwnedSemaphorePermit>,
+                            hooks: Arc<AcceptHooks>,
+                            connections_pool: Pool<Incoming>,
+                            config: ConnConfig) {
+        let header = runtime::timeout_on(config.runtime.as_ref(), PROXY_HEADER_TIMEOUT, proxy::read_header(&mut socket));
+        let header = header.await;
+        drop(header_permit);
+        let addr = match header {
+            Ok(Ok(proxied)) => proxied.unwrap_or(addr),
+            _ => return,
+        };
+
+        if let Some(conn) = Listener::admit(socket, addr, permit, &hooks, &config) {
+            let _ = connections_pool.write(Ok((conn.with_proxied_addr(addr), addr))).await;
+        }
+    }
+
+    // Filtered sockets are dropped before any worker is spawned
+    fn admit(socket: TcpStream,
+             addr: SocketAddr,
+             permit: Option<OwnedSemaphorePermit>,
+             hooks: &AcceptHooks,
+             config: &ConnConfig) -> Option<Conn> {
+        let allowed = match hooks.filter.read().unwrap().as_ref() {
+            Some(filter) => filter(&addr),
+            None => true,
+        };
+        if !allowed {
+            return None;
+        }
+
+        let conn = Conn::from_raw_with_config(socket, config.clone());
+        conn.hold_handshake_permit(permit);
+        Some(conn)
+    }
+
+    /// Returns next accepted connection with the peer address
+    ///
+    /// Returns [`None`] if the listener was closed.
+    /// Accept errors are skipped, use the [`Stream`] implementation to observe them.
+    /// After an error accepting pauses for a delay growing up to a second,
+    /// so errors like `EMFILE` don't spin the CPU
+    ///
+    /// [`None`]: std::option::Option::None
+    /// [`Stream`]: futures_core::Stream
+    pub async fn accept(&self) -> Option<(Conn, SocketAddr)> {
+        loop {
+            if let Ok(incoming) = self.connections_pool.read().await?.accept() {
+                return Some(incoming);
+            }
+        }
+    }
+
+    /// Sets filter called for every incoming connection
+    ///
+    /// Connections for which the filter returns `false` are closed immediately,
+    /// before spawning I/O workers and running the handshake
+    pub fn accept_filter<F: 'static + Fn(&SocketAddr) -> bool + Send + Sync>(&self, filter: F) {
+        *self.hooks.filter.write().unwrap() = Some(Arc::new(filter));
+    }
+
+    /// Limits number of accepted connections which haven't finished the handshake yet
+    ///
+    /// New sockets aren't accepted until one of the connections is built
+    /// by [`Builder::run()`] or dropped
+    ///
+    /// [`Builder::run()`]: crate::builder::builder::Builder::run
+    pub fn limit_handshakes(&self, max: usize) {
+        *self.hooks.handshakes.write().unwrap() = Some(Arc::new(Semaphore::new(max)));
     }
 
     pub async fn close_all_connections(&self) {
         self.close_notifier.notify_one();
     }
 }
+
+/// Yields accepted connections with the peer address
+///
+/// Ends when the listener is closed
+impl Stream for Listener {
+    type Item = Incoming;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let pool = self.connections_pool.clone();
+
+        self.accept_slot.poll(cx, || {
+            async move { Some(pool.read().await?.accept()) }
+        })
+    }
+}
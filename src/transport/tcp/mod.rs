@@ -1,5 +1,15 @@
+pub use config::*;
 pub use conn::*;
 pub use listener::*;
+pub use pending::PendingKind;
 
+pub(crate) mod closer;
+mod config;
 mod conn;
+pub(crate) mod dispatch;
 mod listener;
+pub(crate) mod pending;
+mod proxy;
+mod socket;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
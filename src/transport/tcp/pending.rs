@@ -0,0 +1,249 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+use crate::mem::{Area, Frame, Usage};
+use crate::sync::Kind;
+use crate::transport::scheduler::Scheduler;
+use crate::transport::tcp::ConnConfig;
+
+/// Frames of one kind queued for write, see [`Conn::pending()`]
+///
+/// [`Conn::pending()`]: crate::transport::tcp::Conn::pending
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PendingKind {
+    /// Frames not yet handed to the kernel
+    pub frames: usize,
+
+    /// Bytes of the frames, headers included
+    pub bytes: usize,
+}
+
+/// Counts frames queued for write but not yet handed to the kernel
+#[derive(Default)]
+pub(crate) struct PendingWrites {
+    count: AtomicUsize,
+    kinds: Mutex<HashMap<u8, PendingKind>>,
+    drained: Notify,
+}
+
+/// Marks frame as written when dropped, so cancelled writes
+/// don't block [`PendingWrites::flush()`] forever
+pub(crate) struct PendingGuard {
+    pending: Arc<PendingWrites>,
+    kind: u8,
+    len: usize,
+    _usage: Usage,
+}
+
+/// Frames queued by the scheduled writer, shared with [`Conn::clear_pending()`]
+///
+/// [`Conn::clear_pending()`]: crate::transport::tcp::Conn::clear_pending
+#[derive(Default)]
+pub(crate) struct QueuedFrames {
+    kinds: HashMap<u8, VecDeque<QueuedFrame>>,
+    // Cleared frames which are still in the scheduler
+    stale: HashMap<u8, usize>,
+    len: usize,
+}
+
+struct QueuedFrame {
+    queued: Instant,
+    // Latest frame of a conflated kind, the scheduler holds a placeholder
+    frame: Option<Frame>,
+    pending: PendingGuard,
+}
+
+/// Queue of the scheduled writer
+///
+/// Frames are ordered by the scheduler, while their kinds keep the time
+/// they were queued, so frames can be conflated, expired and cleared
+/// whatever scheduler is used. The scheduler is expected to keep the order
+/// of frames of one kind
+pub(crate) struct WriteQueue {
+    scheduler: Box<dyn Scheduler>,
+    conflated: [bool; 256],
+    ttls: HashMap<u8, Duration>,
+    frames: Arc<Mutex<QueuedFrames>>,
+    expired: Arc<AtomicU64>,
+    // Frames of the batch being written
+    written: Vec<PendingGuard>,
+}
+
+impl PendingWrites {
+    /// Marks frame of the kind with `len` bytes as queued, see [`MemoryBudget`]
+    ///
+    /// [`MemoryBudget`]: crate::mem::MemoryBudget
+    pub(crate) fn start(self: &Arc<Self>, kind: u8, len: usize) -> PendingGuard {
+        self.count.fetch_add(1, Ordering::SeqCst);
+
+        let mut kinds = self.kinds.lock().unwrap();
+        let pending = kinds.entry(kind).or_default();
+        pending.frames += 1;
+        pending.bytes += len;
+
+        PendingGuard { pending: self.clone(), kind, len, _usage: Usage::new(Area::WriteQueues, len) }
+    }
+
+    /// Returns number of queued frames
+    pub(crate) fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    /// Returns queued frames of every kind which has them
+    pub(crate) fn kinds(&self) -> HashMap<u8, PendingKind> {
+        self.kinds.lock().unwrap().clone()
+    }
+
+    /// Waits until there are no queued frames
+    pub(crate) async fn flush(&self) {
+        loop {
+            let drained = self.drained.notified();
+            if self.count.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            drained.await;
+        }
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        {
+            let mut kinds = self.pending.kinds.lock().unwrap();
+            if let Some(pending) = kinds.get_mut(&self.kind) {
+                pending.frames -= 1;
+                pending.bytes -= self.len;
+                if pending.frames == 0 {
+                    kinds.remove(&self.kind);
+                }
+            }
+        }
+
+        if self.pending.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.pending.drained.notify_waiters();
+        }
+    }
+}
+
+impl QueuedFrames {
+    /// Drops queued frames of the kind, returns their number
+    pub(crate) fn clear(&mut self, kind: u8) -> usize {
+        let cleared = match self.kinds.remove(&kind) {
+            Some(queue) => queue,
+            None => return 0,
+        };
+
+        // Placeholders of conflated frames are left in the scheduler too
+        *self.stale.entry(kind).or_insert(0) += cleared.len();
+        self.len -= cleared.len();
+        cleared.len()
+    }
+}
+
+impl WriteQueue {
+    pub(crate) fn new(scheduler: Box<dyn Scheduler>,
+                      config: &ConnConfig,
+                      frames: Arc<Mutex<QueuedFrames>>,
+                      expired: Arc<AtomicU64>) -> Self {
+        let mut conflated = [false; 256];
+        for &kind in &config.conflated_kinds {
+            conflated[kind as usize] = true;
+        }
+
+        WriteQueue {
+            scheduler,
+            conflated,
+            ttls: config.frame_ttls.clone(),
+            frames,
+            expired,
+            written: Vec::new(),
+        }
+    }
+
+    /// Queues the frame, frame of a conflated kind replaces the queued one
+    pub(crate) fn push(&mut self, frame: Frame, pending: PendingGuard) {
+        let kind = frame.kind();
+        let conflated = self.conflated[kind as usize];
+
+        let mut frames = self.frames.lock().unwrap();
+        let queue = frames.kinds.entry(kind).or_default();
+        if conflated && !queue.is_empty() {
+            queue[0] = QueuedFrame { queued: Instant::now(), frame: Some(frame), pending };
+            return;
+        }
+
+        let (frame, scheduled) = match conflated {
+            true => (Some(frame), Frame::create(kind, &[])),
+            false => (None, frame),
+        };
+        queue.push_back(QueuedFrame { queued: Instant::now(), frame, pending });
+        frames.len += 1;
+        drop(frames);
+
+        self.scheduler.push(scheduled);
+    }
+
+    /// Removes frame which is written next, skipping cleared and expired ones
+    ///
+    /// Frame stays pending until [`finish_batch()`]
+    ///
+    /// [`finish_batch()`]: crate::transport::tcp::pending::WriteQueue::finish_batch
+    pub(crate) fn pop(&mut self) -> Option<Frame> {
+        loop {
+            let scheduled = self.scheduler.pop()?;
+            let kind = scheduled.kind();
+
+            let mut frames = self.frames.lock().unwrap();
+            if let Some(stale) = frames.stale.get_mut(&kind) {
+                *stale -= 1;
+                if *stale == 0 {
+                    frames.stale.remove(&kind);
+                }
+                continue;
+            }
+
+            let queue = frames.kinds.get_mut(&kind)?;
+            let queued = queue.pop_front()?;
+            if queue.is_empty() {
+                frames.kinds.remove(&kind);
+            }
+            frames.len -= 1;
+            drop(frames);
+
+            match self.ttls.get(&kind) {
+                Some(&ttl) if queued.queued.elapsed() > ttl => {
+                    self.expired.fetch_add(1, Ordering::SeqCst);
+                }
+                _ => {
+                    self.written.push(queued.pending);
+                    return Some(queued.frame.unwrap_or(scheduled));
+                }
+            }
+        }
+    }
+
+    /// Marks frames popped since the last batch as written
+    pub(crate) fn finish_batch(&mut self) {
+        self.written.clear();
+    }
+
+    /// Returns number of queued frames
+    pub(crate) fn len(&self) -> usize {
+        self.frames.lock().unwrap().len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for WriteQueue {
+    fn drop(&mut self) {
+        // Writer is finished, frames left in the queue are never written
+        *self.frames.lock().unwrap() = QueuedFrames::default();
+    }
+}
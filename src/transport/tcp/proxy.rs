@@ -0,0 +1,108 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+/// How long the listener waits for the PROXY header of accepted socket
+pub(crate) const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+const V1_PREFIX: &[u8] = b"PROXY";
+const V1_MAX_LEN: usize = 107;
+
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+const V2_HEADER_LEN: usize = 16;
+const V2_VERSION: u8 = 0x20;
+const V2_LOCAL: u8 = 0x00;
+const V2_PROXY: u8 = 0x01;
+const V2_TCP4: u8 = 0x11;
+const V2_TCP6: u8 = 0x21;
+
+/// Reads PROXY protocol header (v1 or v2) sent by a load balancer
+///
+/// Returns address of the client or [`None`] if the balancer
+/// doesn't provide it (health checks). Bytes after the header are left in the socket
+///
+/// [`None`]: std::option::Option::None
+pub(crate) async fn read_header(socket: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let mut prefix = [0; V1_PREFIX.len()];
+    socket.read_exact(&mut prefix).await?;
+
+    if prefix == V1_PREFIX {
+        read_v1(socket).await
+    } else if prefix == V2_SIGNATURE[..prefix.len()] {
+        read_v2(socket, &prefix).await
+    } else {
+        Err(invalid("not a PROXY header"))
+    }
+}
+
+// Reads the rest of "PROXY TCP4 <src> <dst> <src port> <dst port>\r\n"
+async fn read_v1(socket: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    // Line is read bytewise, so data following it stays in the socket
+    let mut line = Vec::with_capacity(V1_MAX_LEN);
+    while !line.ends_with(b"\r\n") {
+        if V1_PREFIX.len() + line.len() >= V1_MAX_LEN {
+            return Err(invalid("PROXY v1 header is too long"));
+        }
+        line.push(socket.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| invalid("PROXY v1 header isn't ASCII"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+
+    match fields.as_slice() {
+        ["", "UNKNOWN", ..] => Ok(None),
+        ["", "TCP4" | "TCP6", source, _, port, _] => {
+            let ip: IpAddr = source.parse().map_err(|_| invalid("invalid PROXY v1 address"))?;
+            let port: u16 = port.parse().map_err(|_| invalid("invalid PROXY v1 port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("invalid PROXY v1 header")),
+    }
+}
+
+// Reads the rest of the binary header and its address block
+async fn read_v2(socket: &mut TcpStream, prefix: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let mut header = [0; V2_HEADER_LEN];
+    header[..prefix.len()].copy_from_slice(prefix);
+    socket.read_exact(&mut header[prefix.len()..]).await?;
+
+    if &header[..V2_SIGNATURE.len()] != V2_SIGNATURE {
+        return Err(invalid("not a PROXY header"));
+    }
+
+    let (version, command) = (header[12] & 0xf0, header[12] & 0x0f);
+    let family = header[13];
+    let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+
+    let mut block = vec![0; len];
+    socket.read_exact(&mut block).await?;
+
+    if version != V2_VERSION {
+        return Err(invalid("unsupported PROXY version"));
+    }
+
+    match (command, family) {
+        (V2_LOCAL, _) => Ok(None),
+        (V2_PROXY, V2_TCP4) if len >= 12 => {
+            let ip = Ipv4Addr::new(block[0], block[1], block[2], block[3]);
+            let port = u16::from_be_bytes([block[8], block[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        (V2_PROXY, V2_TCP6) if len >= 36 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(&block[..16]);
+            let port = u16::from_be_bytes([block[32], block[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port)))
+        }
+        // Unix sockets and other transports don't have an IP address
+        (V2_PROXY, _) => Ok(None),
+        _ => Err(invalid("invalid PROXY v2 header")),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
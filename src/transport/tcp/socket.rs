@@ -0,0 +1,210 @@
+use std::io::{self, IoSlice};
+#[cfg(all(feature = "uring", target_os = "linux"))]
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+
+use bytes::{BufMut, Bytes};
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+
+use crate::mem::Frame;
+use crate::transport::file::FileChunk;
+use crate::transport::tcp::ConnConfig;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+use crate::transport::tcp::uring::Ring;
+
+/// Frame written from shared body segments, see [`Conn::write_segments()`]
+///
+/// [`Conn::write_segments()`]: crate::transport::tcp::Conn::write_segments
+pub(crate) struct BytesFrame {
+    pub(crate) kind: u8,
+    pub(crate) segments: Vec<Bytes>,
+}
+
+impl BytesFrame {
+    pub(crate) fn body_len(&self) -> usize {
+        self.segments.iter().map(Bytes::len).sum()
+    }
+}
+
+/// Socket I/O of the connection workers
+///
+/// Goes through io_uring if the `uring` feature is on and the kernel
+/// supports it, otherwise through the tokio reactor
+#[derive(Clone)]
+pub(crate) struct SocketIo {
+    inner: Arc<TcpStream>,
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    ring: Option<&'static Ring>,
+}
+
+impl SocketIo {
+    #[allow(unused_variables)]
+    pub(crate) fn new(inner: Arc<TcpStream>, config: &ConnConfig) -> Self {
+        SocketIo {
+            inner,
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            ring: config.uring.then(Ring::get).flatten(),
+        }
+    }
+
+    /// Waits until the socket is readable and reads available bytes
+    ///
+    /// Returns [`WouldBlock`] if the readiness was false and the read should be retried
+    ///
+    /// [`WouldBlock`]: std::io::ErrorKind::WouldBlock
+    pub(crate) async fn read<B: BufMut + Send>(&self, buf: &mut B, readable_notifier: &Notify) -> io::Result<usize> {
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        if let Some(ring) = self.ring {
+            let fd = self.inner.as_raw_fd();
+            ring.poll(fd, libc::POLLIN).await?;
+            readable_notifier.notify_waiters();
+            return ring.read(fd, buf).await;
+        }
+
+        self.inner.readable().await?;
+        readable_notifier.notify_waiters();
+        self.inner.try_read_buf(buf)
+    }
+
+    /// Writes all bytes to the socket
+    pub(crate) async fn write_all(&self, bytes: &[u8]) -> io::Result<()> {
+        let mut wrote_len = 0;
+
+        while wrote_len < bytes.len() {
+            match self.write(&bytes[wrote_len..]).await {
+                // Ok
+                Ok(len) => wrote_len += len,
+
+                // Operation can't be completed now and we should retry it
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+
+                // Closing write worker on unexpected error
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes frame with the shared body segments, the header is prepended by a vectored write
+    pub(crate) async fn send_bytes(&self, frame: &BytesFrame) -> io::Result<()> {
+        let header = Frame::header(frame.kind, frame.body_len());
+
+        let mut parts: Vec<&[u8]> = Vec::with_capacity(1 + frame.segments.len());
+        parts.push(&header);
+        parts.extend(frame.segments.iter().map(|segment| &segment[..]));
+
+        self.write_all_vectored(&parts).await
+    }
+
+    /// Writes all bytes of the parts in order
+    async fn write_all_vectored(&self, parts: &[&[u8]]) -> io::Result<()> {
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        if self.ring.is_some() {
+            for part in parts {
+                self.write_all(part).await?;
+            }
+            return Ok(());
+        }
+
+        // Part being written and the number of its written bytes
+        let (mut part, mut offset) = (0, 0);
+
+        while part < parts.len() {
+            self.inner.writable().await?;
+
+            let slices: Vec<IoSlice<'_>> = std::iter::once(&parts[part][offset..])
+                .chain(parts[part + 1..].iter().copied())
+                .map(IoSlice::new)
+                .collect();
+
+            match self.inner.try_write_vectored(&slices) {
+                // Ok
+                Ok(mut len) => {
+                    while part < parts.len() && len >= parts[part].len() - offset {
+                        len -= parts[part].len() - offset;
+                        part += 1;
+                        offset = 0;
+                    }
+                    offset += len;
+                }
+
+                // Operation can't be completed now and we should retry it
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+
+                // Closing write worker on unexpected error
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes frame with the file chunk as a body
+    ///
+    /// On Linux the body goes from the page cache to the socket with `sendfile`
+    pub(crate) async fn send_file(&self, chunk: &FileChunk) -> io::Result<()> {
+        self.write_all(&chunk.header()).await?;
+
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            let (socket, file) = (self.inner.as_raw_fd(), chunk.file.as_raw_fd());
+            let mut offset = chunk.range.start as libc::off_t;
+            let end = chunk.range.end as libc::off_t;
+
+            while offset < end {
+                self.inner.writable().await?;
+
+                let sent = self.inner.try_io(tokio::io::Interest::WRITABLE, || {
+                    match unsafe { libc::sendfile(socket, file, &mut offset, (end - offset) as usize) } {
+                        -1 => Err(io::Error::last_os_error()),
+                        sent => Ok(sent),
+                    }
+                });
+
+                match sent {
+                    // File was truncated after the header was sent
+                    Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+
+                    // Ok
+                    Ok(_) => {}
+
+                    // Operation can't be completed now and we should retry it
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+
+                    // Closing write worker on unexpected error
+                    Err(e) => return Err(e),
+                }
+            }
+
+            Ok(())
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        self.write_all(&chunk.read().await?).await
+    }
+
+    // Waits until the socket is writable and writes a part of bytes
+    async fn write(&self, bytes: &[u8]) -> io::Result<usize> {
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        if let Some(ring) = self.ring {
+            let fd = self.inner.as_raw_fd();
+            // Written right away like try_write(), so a full socket takes as many
+            // bytes as it can and the writer stalls in the middle of the frame.
+            // Readiness is awaited only then, write_all() retries the write
+            return match ring.write(fd, bytes).await {
+                Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    ring.poll(fd, libc::POLLOUT).await?;
+                    Err(io::ErrorKind::WouldBlock.into())
+                }
+                result => result,
+            };
+        }
+
+        self.inner.writable().await?;
+        self.inner.try_write(bytes)
+    }
+}
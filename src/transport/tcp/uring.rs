@@ -0,0 +1,302 @@
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+
+use bytes::BufMut;
+use io_uring::{opcode, squeue, types, IoUring};
+use tokio::sync::oneshot;
+
+// Maximum number of submissions waiting for the kernel
+const RING_ENTRIES: u32 = 256;
+
+// Registered buffers shared by all connections
+const BUFFER_COUNT: usize = 64;
+const BUFFER_LEN: usize = 64 * 1024;
+
+// Completions with this id belong to cancellations
+const CANCEL_ID: u64 = u64::MAX;
+
+static RING: OnceLock<Option<&'static Ring>> = OnceLock::new();
+
+/// io_uring instance shared by connection workers
+///
+/// Operations are submitted from any thread, completions are reaped
+/// by a dedicated thread and sent back to the waiting workers. Data
+/// is copied through buffers registered in the kernel once, so operations
+/// don't pin user pages every time. Buffers are owned by the ring rather
+/// than by the caller, because the kernel may still use them after
+/// a cancelled worker is gone
+///
+/// # Note
+///
+/// It isn't zero-copy: writes copy bytes into a buffer and reads copy them
+/// out of it. While all registered buffers are busy operations use heap
+/// buffers, so connections never wait for each other's buffers
+pub(crate) struct Ring {
+    ring: IoUring,
+    // SubmissionQueue may be used by one thread at a time
+    submission: Mutex<()>,
+    operations: Mutex<HashMap<u64, Operation>>,
+    next_id: AtomicU64,
+    memory: Box<[UnsafeCell<u8>]>,
+    free: Mutex<Vec<u16>>,
+    fixed: bool,
+}
+
+// Buffer memory is accessed only through Buffer, which is owned either
+// by a single worker or by the operation in flight
+unsafe impl Sync for Ring {}
+
+struct Operation {
+    sender: oneshot::Sender<(i32, Option<Buffer>)>,
+    buffer: Option<Buffer>,
+}
+
+// Registered buffer returned to the ring when dropped,
+// or a heap buffer used while all registered ones are busy
+enum Buffer {
+    Registered {
+        ring: &'static Ring,
+        index: u16,
+    },
+    Heap(Box<[u8]>),
+}
+
+// Cancels the operation if the worker stops waiting for it
+struct CancelGuard {
+    ring: &'static Ring,
+    id: u64,
+    completed: bool,
+}
+
+impl Ring {
+    /// Returns the shared ring, [`None`] if io_uring isn't supported
+    ///
+    /// [`None`]: std::option::Option::None
+    pub(crate) fn get() -> Option<&'static Ring> {
+        *RING.get_or_init(|| Ring::start().ok())
+    }
+
+    fn start() -> io::Result<&'static Ring> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let memory: Box<[UnsafeCell<u8>]> = (0..BUFFER_COUNT * BUFFER_LEN).map(|_| UnsafeCell::new(0)).collect();
+        let base = UnsafeCell::raw_get(memory.as_ptr());
+        let iovecs: Vec<libc::iovec> = (0..BUFFER_COUNT)
+            .map(|index| libc::iovec {
+                iov_base: unsafe { base.add(index * BUFFER_LEN) }.cast(),
+                iov_len: BUFFER_LEN,
+            })
+            .collect();
+
+        // Registration fails if locked memory is limited, plain operations are used then
+        let fixed = unsafe { ring.submitter().register_buffers(&iovecs) }.is_ok();
+
+        let ring: &'static Ring = Box::leak(Box::new(Ring {
+            ring,
+            submission: Mutex::new(()),
+            operations: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            memory,
+            free: Mutex::new((0..BUFFER_COUNT as u16).collect()),
+            fixed,
+        }));
+
+        thread::Builder::new()
+            .name("cobra-uring".to_string())
+            .spawn(move || ring.complete_loop())?;
+
+        Ok(ring)
+    }
+
+    /// Waits until the socket is ready for `events`, e.g. [`POLLIN`]
+    ///
+    /// Operation doesn't hold a buffer, so idle sockets don't starve busy ones
+    ///
+    /// [`POLLIN`]: libc::POLLIN
+    pub(crate) async fn poll(&'static self, fd: RawFd, events: i16) -> io::Result<()> {
+        let entry = opcode::PollAdd::new(types::Fd(fd), events as u32).build();
+        let (result, _) = self.run(entry, None).await?;
+        Ring::check(result).map(|_| ())
+    }
+
+    /// Reads available bytes from the socket into a buffer and copies them to `buf`
+    ///
+    /// Returns [`WouldBlock`] if there is nothing to read
+    ///
+    /// [`WouldBlock`]: std::io::ErrorKind::WouldBlock
+    pub(crate) async fn read<B: BufMut>(&'static self, fd: RawFd, buf: &mut B) -> io::Result<usize> {
+        let len = buf.remaining_mut().min(BUFFER_LEN);
+        let mut buffer = self.acquire(len);
+        let ptr = buffer.as_mut_ptr();
+
+        let entry = match self.fixed_index(&buffer) {
+            Some(index) => opcode::ReadFixed::new(types::Fd(fd), ptr, len as u32, index).build(),
+            None => opcode::Read::new(types::Fd(fd), ptr, len as u32).build(),
+        };
+
+        let (result, buffer) = self.run(entry, Some(buffer)).await?;
+        let len = Ring::check(result)?;
+        if let Some(mut buffer) = buffer {
+            buf.put_slice(buffer.get(len));
+        }
+        Ok(len)
+    }
+
+    /// Copies a part of `bytes` into a buffer and writes it to the socket,
+    /// returns number of written bytes
+    ///
+    /// Doesn't wait for the socket, like a non-blocking `write`, so it writes
+    /// as many bytes as the socket takes. Returns [`WouldBlock`] if the socket is full
+    ///
+    /// [`WouldBlock`]: std::io::ErrorKind::WouldBlock
+    pub(crate) async fn write(&'static self, fd: RawFd, bytes: &[u8]) -> io::Result<usize> {
+        let len = bytes.len().min(BUFFER_LEN);
+        let mut buffer = self.acquire(len);
+        let ptr = buffer.as_mut_ptr();
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, len) };
+
+        // Full socket fails with EAGAIN instead of waiting in the kernel
+        let entry = match self.fixed_index(&buffer) {
+            Some(index) => opcode::WriteFixed::new(types::Fd(fd), ptr, len as u32, index).rw_flags(libc::RWF_NOWAIT).build(),
+            None => opcode::Write::new(types::Fd(fd), ptr, len as u32).rw_flags(libc::RWF_NOWAIT).build(),
+        };
+
+        let (result, _) = self.run(entry, Some(buffer)).await?;
+        Ring::check(result)
+    }
+
+    fn check(result: i32) -> io::Result<usize> {
+        match result {
+            len if len >= 0 => Ok(len as usize),
+            errno => Err(io::Error::from_raw_os_error(-errno)),
+        }
+    }
+
+    // Takes a free registered buffer, allocates `len` bytes if there is none
+    fn acquire(&'static self, len: usize) -> Buffer {
+        match self.free.lock().unwrap().pop() {
+            Some(index) => Buffer::Registered { ring: self, index },
+            None => Buffer::Heap(vec![0; len].into_boxed_slice()),
+        }
+    }
+
+    // Index for fixed operations, heap buffers aren't registered
+    fn fixed_index(&self, buffer: &Buffer) -> Option<u16> {
+        match buffer {
+            Buffer::Registered { index, .. } if self.fixed => Some(*index),
+            _ => None,
+        }
+    }
+
+    // Buffer is kept by the operation until it completes, even if the worker is gone
+    async fn run(&'static self, entry: squeue::Entry, buffer: Option<Buffer>) -> io::Result<(i32, Option<Buffer>)> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = oneshot::channel();
+        self.operations.lock().unwrap().insert(id, Operation { sender, buffer });
+
+        if let Err(error) = self.push(&entry.user_data(id)) {
+            self.operations.lock().unwrap().remove(&id);
+            return Err(error);
+        }
+
+        let mut guard = CancelGuard { ring: self, id, completed: false };
+        let completed = receiver.await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "io_uring has stopped"));
+        guard.completed = true;
+        completed
+    }
+
+    fn push(&self, entry: &squeue::Entry) -> io::Result<()> {
+        let _submission = self.submission.lock().unwrap();
+        let mut queue = unsafe { self.ring.submission_shared() };
+
+        while unsafe { queue.push(entry) }.is_err() {
+            queue.sync();
+            self.ring.submit()?;
+            queue.sync();
+        }
+
+        drop(queue);
+        self.ring.submit()?;
+        Ok(())
+    }
+
+    fn complete_loop(&self) {
+        loop {
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(ref error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(ref error) if error.raw_os_error() == Some(libc::EBUSY) => {}
+                Err(_) => break,
+            }
+
+            // Only this thread reads completions
+            let completed: Vec<_> = unsafe { self.ring.completion_shared() }
+                .map(|entry| (entry.user_data(), entry.result()))
+                .collect();
+
+            for (id, result) in completed {
+                self.complete(id, result);
+            }
+        }
+
+        // Workers must not wait forever
+        let operations: Vec<_> = self.operations.lock().unwrap().drain().collect();
+        for (_, operation) in operations {
+            let _ = operation.sender.send((-libc::EIO, operation.buffer));
+        }
+    }
+
+    fn complete(&self, id: u64, result: i32) {
+        if id == CANCEL_ID {
+            return;
+        }
+
+        let operation = self.operations.lock().unwrap().remove(&id);
+        if let Some(Operation { sender, buffer }) = operation {
+            let _ = sender.send((result, buffer));
+        }
+    }
+}
+
+impl Buffer {
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        match self {
+            Buffer::Registered { ring, index } => {
+                let offset = *index as usize * BUFFER_LEN;
+                unsafe { UnsafeCell::raw_get(ring.memory.as_ptr().add(offset)) }
+            }
+            Buffer::Heap(memory) => memory.as_mut_ptr(),
+        }
+    }
+
+    fn get(&mut self, len: usize) -> &[u8] {
+        let len = match self {
+            Buffer::Registered { .. } => len.min(BUFFER_LEN),
+            Buffer::Heap(memory) => len.min(memory.len()),
+        };
+        unsafe { std::slice::from_raw_parts(self.as_mut_ptr(), len) }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        if let Buffer::Registered { ring, index } = self {
+            ring.free.lock().unwrap().push(*index);
+        }
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if !self.completed {
+            let cancel = opcode::AsyncCancel::new(self.id).build().user_data(CANCEL_ID);
+            let _ = self.ring.push(&cancel);
+        }
+    }
+}
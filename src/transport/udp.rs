@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::{duplex, split, AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::net::UdpSocket;
+
+use crate::runtime::{self, Runtime};
+
+const PROBE: u8 = 0;
+const DATA: u8 = 1;
+const ACK: u8 = 2;
+const FIN: u8 = 3;
+
+// Type and sequence number
+const HEADER_LEN: usize = 5;
+// Fits into the minimal IPv6 MTU along with UDP and IP headers
+const MAX_SEGMENT_LEN: usize = 1200;
+// Segments sent without acknowledgement
+const WINDOW: usize = 64;
+const BUFFER_LEN: usize = WINDOW * MAX_SEGMENT_LEN;
+
+/// Sends probes to the connected peer until a packet of the peer arrives
+///
+/// Outgoing probes open the mapping in our NAT, so the probes of the
+/// peer pass it. Errors of single probes are ignored, e.g. the port of
+/// the peer may be unreachable until the peer binds it
+pub(crate) async fn punch(socket: &UdpSocket, interval: Duration) {
+    let probe = segment(PROBE, 0, &[]);
+    let mut packet = [0; HEADER_LEN + MAX_SEGMENT_LEN];
+
+    loop {
+        let _ = socket.send(&probe).await;
+        if let Ok(Ok(_)) = runtime::timeout(interval, socket.recv(&mut packet)).await {
+            break;
+        }
+    }
+
+    // The peer may still wait for our probe
+    let _ = socket.send(&probe).await;
+}
+
+/// Carries a byte stream over the connected socket
+///
+/// Returns the end used by the application, the other end is served by
+/// a task spawned on `runtime`. Bytes are sent in numbered segments, which
+/// are sent again from the first unacknowledged one every `retransmit`
+/// (go-back-N). The task stops once both sides finished the stream or
+/// the peer is silent for `idle_timeout`, keepalives are sent before that
+pub(crate) fn stream(socket: UdpSocket, runtime: Arc<dyn Runtime>, retransmit: Duration, idle_timeout: Duration) -> DuplexStream {
+    let (stream, remote) = duplex(BUFFER_LEN);
+    let task_runtime = runtime.clone();
+    runtime.spawn(Box::pin(async move {
+        let _ = serve(socket, remote, task_runtime.as_ref(), retransmit, idle_timeout).await;
+    }));
+
+    stream
+}
+
+async fn serve(socket: UdpSocket, stream: DuplexStream, runtime: &dyn Runtime, retransmit: Duration, idle_timeout: Duration) -> io::Result<()> {
+    let (mut reader, mut writer) = split(stream);
+
+    // Sent segments, the first one has number `acked`
+    let mut unacked = VecDeque::new();
+    let mut acked = 0u32;
+    let mut next = 0u32;
+    // Received bytes which aren't taken by the application yet
+    let mut received = Vec::new();
+    let mut expected = 0u32;
+
+    let mut local_done = false;
+    let mut remote_done = false;
+    let mut shut = false;
+
+    let mut last_heard = Instant::now();
+    let mut last_sent = Instant::now();
+    let mut tick = Instant::now() + retransmit;
+    let mut chunk = vec![0; MAX_SEGMENT_LEN];
+    let mut packet = vec![0; HEADER_LEN + MAX_SEGMENT_LEN];
+
+    loop {
+        if remote_done && received.is_empty() && !shut {
+            writer.shutdown().await?;
+            shut = true;
+        }
+        if local_done && unacked.is_empty() && shut {
+            return Ok(());
+        }
+        if last_heard.elapsed() > idle_timeout {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "peer is silent"));
+        }
+
+        tokio::select! {
+            read = reader.read(&mut chunk), if !local_done && unacked.len() < WINDOW => {
+                let segment = match read? {
+                    0 => {
+                        local_done = true;
+                        segment(FIN, next, &[])
+                    }
+                    len => segment(DATA, next, &chunk[..len]),
+                };
+                let _ = socket.send(&segment).await;
+                last_sent = Instant::now();
+                unacked.push_back(segment);
+                next = next.wrapping_add(1);
+            }
+            written = writer.write(&received), if !received.is_empty() => {
+                received.drain(..written?);
+            }
+            len = socket.recv(&mut packet) => {
+                let len = match len {
+                    Ok(len) if len >= HEADER_LEN => len,
+                    _ => continue,
+                };
+                last_heard = Instant::now();
+
+                let seq = u32::from_be_bytes(packet[1..HEADER_LEN].try_into().unwrap());
+                match packet[0] {
+                    ACK => {
+                        let newly_acked = seq.wrapping_sub(acked) as usize;
+                        if newly_acked <= unacked.len() {
+                            unacked.drain(..newly_acked);
+                            acked = seq;
+                        }
+                        continue;
+                    }
+                    // Segments out of order or without room are dropped and sent again by the peer
+                    DATA if seq == expected && received.len() < BUFFER_LEN && !remote_done => {
+                        received.extend_from_slice(&packet[HEADER_LEN..len]);
+                        expected = expected.wrapping_add(1);
+                    }
+                    FIN if seq == expected && !remote_done => {
+                        remote_done = true;
+                        expected = expected.wrapping_add(1);
+                    }
+                    _ => {}
+                }
+
+                let _ = socket.send(&segment(ACK, expected, &[])).await;
+                last_sent = Instant::now();
+            }
+            _ = runtime.sleep(tick.saturating_duration_since(Instant::now())) => {
+                tick = Instant::now() + retransmit;
+                for segment in &unacked {
+                    let _ = socket.send(segment).await;
+                    last_sent = Instant::now();
+                }
+
+                // NATs drop mappings without traffic
+                if last_sent.elapsed() > idle_timeout / 4 {
+                    let _ = socket.send(&segment(ACK, expected, &[])).await;
+                    last_sent = Instant::now();
+                }
+            }
+        }
+    }
+}
+
+fn segment(kind: u8, seq: u32, body: &[u8]) -> Vec<u8> {
+    let mut segment = Vec::with_capacity(HEADER_LEN + body.len());
+    segment.push(kind);
+    segment.extend_from_slice(&seq.to_be_bytes());
+    segment.extend_from_slice(body);
+    segment
+}
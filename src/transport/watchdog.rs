@@ -0,0 +1,152 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::time::Duration;
+
+pub(crate) type StuckWorkerHandler = Arc<dyn Fn(&StuckWorker) + Send + Sync>;
+
+/// I/O worker of the connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Worker {
+    Reader,
+    Writer,
+}
+
+/// What the worker is waiting for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerState {
+    /// Waits for new frames from the peer or the application
+    Idle,
+
+    /// Waits until the socket accepts written bytes
+    Socket,
+
+    /// Reader waits for the application to read queued frames
+    Backpressure,
+
+    /// Reader waits for the global memory budget, see [`MemoryBudget`]
+    ///
+    /// [`MemoryBudget`]: crate::mem::MemoryBudget
+    Budget,
+
+    /// Reader handles a control frame of the transport
+    Control,
+}
+
+/// Event emitted when a worker hasn't made progress despite pending work
+/// within the period of [`WatchdogPolicy`]
+///
+/// [`WatchdogPolicy`]: crate::transport::watchdog::WatchdogPolicy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StuckWorker {
+    /// Worker which hasn't made progress
+    pub worker: Worker,
+    /// Time since the last progress, a multiple of the period
+    pub stalled: Duration,
+    /// State of the reader when the event was emitted
+    pub reader: WorkerState,
+    /// State of the writer when the event was emitted
+    pub writer: WorkerState,
+    /// Writes waiting for the writer or queued in the scheduler
+    pub pending_writes: usize,
+    /// Received frames waiting for the application
+    pub queued_frames: usize,
+}
+
+/// Detection of stuck I/O workers
+///
+/// Worker is stuck if it isn't idle, or the writer has pending writes,
+/// and it hasn't made progress for the whole period, e.g. a socket the peer
+/// doesn't read or a deadlocked pool. The policy reports such workers once
+/// per stall and may close the connection with [`INTERNAL_ERROR`] code
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use cobra_rs::transport::tcp::ConnConfig;
+/// use cobra_rs::transport::watchdog::WatchdogPolicy;
+///
+/// let policy = WatchdogPolicy::new(Duration::from_secs(10))
+///     .on_stuck_worker(|event| println!("{:?} is stuck: {:?}", event.worker, event))
+///     .force_close();
+///
+/// let config = ConnConfig::new().set_watchdog(policy);
+/// ```
+///
+/// [`INTERNAL_ERROR`]: crate::builder::kind_conn::close_code::INTERNAL_ERROR
+#[derive(Clone)]
+pub struct WatchdogPolicy {
+    pub(crate) period: Duration,
+    pub(crate) handler: Option<StuckWorkerHandler>,
+    pub(crate) force_close: bool,
+}
+
+/// Progress and state of a worker, shared with the watchdog
+#[derive(Default)]
+pub(crate) struct Heartbeat {
+    progress: AtomicU64,
+    state: AtomicU8,
+}
+
+impl WatchdogPolicy {
+    /// Creates policy detecting workers without progress for `period`
+    pub fn new(period: Duration) -> Self {
+        WatchdogPolicy {
+            period,
+            handler: None,
+            force_close: false,
+        }
+    }
+
+    /// Sets handler called with [`StuckWorker`] event once per stall
+    ///
+    /// Handler is called from the watchdog task, so it must not block
+    ///
+    /// [`StuckWorker`]: crate::transport::watchdog::StuckWorker
+    pub fn on_stuck_worker<F: 'static + Fn(&StuckWorker) + Send + Sync>(mut self, handler: F) -> Self {
+        self.handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Closes the connection with [`INTERNAL_ERROR`] code after the event
+    ///
+    /// By default the worker is left waiting
+    ///
+    /// [`INTERNAL_ERROR`]: crate::builder::kind_conn::close_code::INTERNAL_ERROR
+    pub fn force_close(mut self) -> Self {
+        self.force_close = true;
+        self
+    }
+
+    pub(crate) fn emit(&self, event: &StuckWorker) {
+        if let Some(handler) = &self.handler {
+            handler(event);
+        }
+    }
+}
+
+impl Heartbeat {
+    /// Records progress of the worker
+    pub(crate) fn beat(&self) {
+        self.progress.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn set_state(&self, state: WorkerState) {
+        self.state.store(state as u8, Ordering::SeqCst);
+    }
+
+    pub(crate) fn progress(&self) -> u64 {
+        self.progress.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn state(&self) -> WorkerState {
+        match self.state.load(Ordering::SeqCst) {
+            1 => WorkerState::Socket,
+            2 => WorkerState::Backpressure,
+            3 => WorkerState::Budget,
+            4 => WorkerState::Control,
+            _ => WorkerState::Idle,
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::io;
+use std::time::Duration;
+
+use cobra_rs::blocking::{Client, ConnectError};
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::builder::kind_conn::close_code::CLOSED_BY_USER;
+use cobra_rs::transport::tcp::Listener;
+
+#[test]
+fn echo() {
+    let server_runtime = tokio::runtime::Runtime::new().unwrap();
+    let listener = server_runtime.block_on(Listener::listen("127.0.0.1:0")).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = server_runtime.spawn(async move {
+        let (conn, _) = listener.accept().await.unwrap();
+        let conn = Builder::new().set_conn(conn).run().await.unwrap();
+        while let Some(package) = conn.read().await {
+            conn.write(package).await.unwrap();
+        }
+        conn.is_close().await
+    });
+
+    let client = Client::connect(addr).unwrap();
+    assert_eq!(client.peer_addr().unwrap(), addr);
+
+    client.write(b"hello".to_vec()).unwrap();
+    assert_eq!(client.read(), Some(b"hello".to_vec()));
+
+    let timed_out = client.read_timeout(Duration::from_millis(50)).unwrap_err();
+    assert_eq!(timed_out.kind(), io::ErrorKind::TimedOut);
+
+    client.close(CLOSED_BY_USER);
+    assert_eq!(client.read(), None);
+    assert_eq!(server_runtime.block_on(server).unwrap(), Some(CLOSED_BY_USER));
+}
+
+#[test]
+fn refused() {
+    match Client::connect("127.0.0.1:1") {
+        Err(ConnectError::Io(_)) => {}
+        _ => panic!("connection wasn't refused"),
+    }
+}
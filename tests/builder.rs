@@ -0,0 +1,415 @@
+mod common;
+
+use std::future::{pending, poll_fn};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_core::Stream;
+use tokio::time::timeout;
+
+use cobra_rs::builder::builder::{BuildError, Builder, CompressionProvider, ConnProvider, EncryptionProvider, PingProvider};
+use cobra_rs::builder::context::Context;
+use cobra_rs::builder::dependency::Provider;
+use cobra_rs::builder::kind_conn::close_code::{CANCELLED, PROVIDER_PANIC};
+use cobra_rs::builder::kind_conn::{DeadlineError, KindFrames, ReadError};
+use cobra_rs::config::{PartialConfig, PingConfig};
+use cobra_rs::sync::CancelToken;
+use cobra_rs::providers::default_ping_provider::{DefaultPingProvider, PingIntervals};
+use cobra_rs::transport::tcp::{Conn, Listener};
+
+use common::pair;
+
+struct StuckEncryption;
+
+#[async_trait]
+impl EncryptionProvider for StuckEncryption {
+    async fn init(&self, _context: Context) -> Result<(), BuildError> {
+        pending().await
+    }
+
+    fn encrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+
+    fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+}
+
+struct PanickingPing {
+    in_task: bool,
+}
+
+#[async_trait]
+impl PingProvider for PanickingPing {
+    async fn init(&self, context: Context) {
+        if self.in_task {
+            context.spawn(async { panic!("ping failed") });
+        } else {
+            panic!("init failed")
+        }
+    }
+}
+
+// Never finishes initialization
+struct StuckPing;
+
+#[async_trait]
+impl PingProvider for StuckPing {
+    async fn init(&self, _context: Context) {
+        pending().await
+    }
+}
+
+// Records when initialization starts and ends
+struct Logged {
+    log: Arc<Mutex<Vec<&'static str>>>,
+}
+
+#[async_trait]
+impl EncryptionProvider for Logged {
+    async fn init(&self, _context: Context) -> Result<(), BuildError> {
+        self.log.lock().unwrap().push("encryption started");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        self.log.lock().unwrap().push("encryption done");
+        Ok(())
+    }
+
+    fn encrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+
+    fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+}
+
+#[async_trait]
+impl CompressionProvider for Logged {
+    async fn init(&self, _context: Context) {
+        self.log.lock().unwrap().push("compression started");
+    }
+
+    fn compress(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+
+    fn decompress(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+
+    fn depends_on(&self) -> Vec<Provider> {
+        vec![Provider::Encryption]
+    }
+}
+
+#[tokio::test]
+async fn handshake_timeout() {
+    const ADDR: &str = "127.0.0.1:5200";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let _server = listener.accept().await.unwrap();
+
+    let result = Builder::new()
+        .set_conn(client)
+        .set_encryption(StuckEncryption)
+        .handshake_timeout(Duration::from_millis(50))
+        .run()
+        .await;
+
+    match result {
+        Err(BuildError::Timeout) => {}
+        _ => panic!("handshake didn't time out"),
+    }
+}
+
+#[tokio::test]
+async fn handshake_timeout_closes_conn() {
+    const ADDR: &str = "127.0.0.1:5201";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let _ = Builder::new()
+        .set_conn(client)
+        .set_encryption(StuckEncryption)
+        .handshake_timeout(Duration::from_millis(50))
+        .run()
+        .await;
+
+    // Peer sees the socket closed
+    assert!(server.read(1).await.is_none());
+}
+
+#[tokio::test]
+async fn concurrent_init_kinds_match() {
+    const ADDR: &str = "127.0.0.1:5202";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let ping = || DefaultPingProvider::new(Duration::from_secs(6), Duration::from_secs(2));
+    let (client, server) = tokio::join!(
+        Builder::new().set_conn(client).set_ping(ping()).run(),
+        Builder::new().set_conn(server).set_ping(ping()).run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    assert!(client.write(vec![1, 2, 3]).await.is_ok());
+    assert_eq!(server.read().await.unwrap(), vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn extensions() {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Rtt(Duration);
+
+    const ADDR: &str = "127.0.0.1:5203";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let _server = listener.accept().await.unwrap();
+
+    // The peer doesn't build the connection, so it can't answer the version frame
+    let conn = Builder::new().protocol_version(1).set_conn(client).run().await.unwrap();
+    assert!(conn.extensions().insert(Rtt(Duration::from_millis(5))).is_none());
+    assert_eq!(conn.extensions().get::<Rtt>(), Some(Rtt(Duration::from_millis(5))));
+    assert_eq!(conn.extensions().remove::<Rtt>(), Some(Rtt(Duration::from_millis(5))));
+    assert!(!conn.extensions().contains::<Rtt>());
+}
+
+async fn next(listener: &mut Listener) -> Option<io::Result<(Conn, SocketAddr)>> {
+    poll_fn(|cx| Pin::new(&mut *listener).poll_next(cx)).await
+}
+
+#[tokio::test]
+async fn listener_handshake_limit() {
+    const ADDR: &str = "127.0.0.1:5204";
+
+    let mut listener = Listener::listen(ADDR).await.unwrap();
+    listener.limit_handshakes(1);
+
+    let _client_a = Conn::connect(ADDR).await.unwrap();
+    let _client_b = Conn::connect(ADDR).await.unwrap();
+
+    let (conn, _) = next(&mut listener).await.unwrap().unwrap();
+
+    // Second connection waits until the first one is built
+    assert!(timeout(Duration::from_millis(50), next(&mut listener)).await.is_err());
+
+    let _conn = Builder::new().protocol_version(1).set_conn(conn).run().await.unwrap();
+    assert!(next(&mut listener).await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn reconfigure_ping() {
+    const ADDR: &str = "127.0.0.1:5205";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let _server = listener.accept().await.unwrap();
+
+    let conn = Builder::new()
+        .protocol_version(1)
+        .set_conn(client)
+        .set_ping(DefaultPingProvider::new(Duration::from_secs(6), Duration::from_secs(2)))
+        .run()
+        .await
+        .unwrap();
+
+    conn.reconfigure(&PartialConfig {
+        ping: Some(PingConfig { long_ms: 1000, short_ms: 500 }),
+        write_coalescing_us: Some(100),
+        ..Default::default()
+    });
+
+    let intervals = conn.extensions().get::<PingIntervals>().unwrap();
+    assert_eq!(intervals.get(), (Duration::from_secs(1), Duration::from_millis(500)));
+    assert!(conn.write(vec![1]).await.is_ok());
+}
+
+#[tokio::test]
+async fn cancel_handshake() {
+    const ADDR: &str = "127.0.0.1:5206";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let token = CancelToken::new();
+    let build = tokio::spawn(
+        Builder::new()
+            .set_conn(client)
+            .set_encryption(StuckEncryption)
+            .cancel_token(token.clone())
+            .run()
+    );
+    tokio::task::yield_now().await;
+    token.cancel();
+
+    assert!(matches!(build.await.unwrap(), Err(BuildError::Cancelled)));
+    assert!(server.read(1).await.is_none());
+    assert_eq!(server.is_close().await, Some(CANCELLED));
+}
+
+#[tokio::test]
+async fn provider_panic_on_init() {
+    const ADDR: &str = "127.0.0.1:5207";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let result = Builder::new()
+        .protocol_version(1)
+        .set_conn(client)
+        .set_ping(PanickingPing { in_task: false })
+        .run()
+        .await;
+
+    assert!(matches!(result, Err(BuildError::ProviderPanic(message)) if message == "init failed"));
+    assert!(server.read(1).await.is_none());
+    assert_eq!(server.is_close().await, Some(PROVIDER_PANIC));
+    assert_eq!(server.close_reason().await.unwrap(), "init failed");
+}
+
+#[tokio::test]
+async fn provider_panic_in_task() {
+    const ADDR: &str = "127.0.0.1:5208";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let conn = Builder::new()
+        .protocol_version(1)
+        .set_conn(client)
+        .set_ping(PanickingPing { in_task: true })
+        .run()
+        .await
+        .unwrap();
+
+    assert!(server.read(1).await.is_none());
+    assert_eq!(server.close_reason().await.unwrap(), "ping failed");
+    assert_eq!(conn.is_close().await, Some(PROVIDER_PANIC));
+    assert_eq!(conn.close_reason().await.unwrap(), "ping failed");
+}
+
+#[tokio::test]
+async fn early_data() {
+    const ADDR: &str = "127.0.0.1:5209";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    // Early packages don't wait for the stuck ping provider
+    let client = tokio::spawn(Builder::new()
+        .set_conn(client)
+        .set_ping(StuckPing)
+        .early_data(b"first".to_vec())
+        .early_data(b"second".to_vec())
+        .run());
+    let server = Builder::new().set_conn(server).run().await.unwrap();
+
+    assert_eq!(server.read().await.unwrap(), b"first");
+    assert_eq!(server.read().await.unwrap(), b"second");
+    assert!(!client.is_finished());
+    client.abort();
+}
+
+#[tokio::test]
+async fn early_data_goes_first() {
+    const ADDR: &str = "127.0.0.1:5210";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let (client, server) = tokio::join!(
+        Builder::new().set_conn(client).early_data(b"early".to_vec()).run(),
+        Builder::new().set_conn(server).run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    client.write(b"late".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"early");
+    assert_eq!(server.read().await.unwrap(), b"late");
+}
+
+#[tokio::test]
+async fn deadlines() {
+    let (client, server) = pair("127.0.0.1:5211", Builder::new(), Builder::new()).await;
+
+    let read = client.read_deadline(Instant::now() + Duration::from_millis(50)).await;
+    assert!(matches!(read, Err(DeadlineError::Elapsed)));
+
+    // Package arriving after the deadline isn't lost
+    server.write(b"late".to_vec()).await.unwrap();
+    let read = client.read_deadline(Instant::now() + Duration::from_secs(5)).await;
+    assert_eq!(read.unwrap(), Some(b"late".to_vec()));
+
+    // Package stays queued even if the deadline has already passed
+    match client.write_deadline(b"queued".to_vec(), Instant::now()).await {
+        Ok(()) | Err(DeadlineError::Elapsed) => {}
+        Err(err) => panic!("unexpected {:?}", err),
+    }
+    assert_eq!(server.read().await.unwrap(), b"queued");
+
+    client.write_deadline(b"in time".to_vec(), Instant::now() + Duration::from_secs(5)).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"in time");
+}
+
+#[tokio::test]
+async fn frames_stream() {
+    let (client, server) = pair("127.0.0.1:5212", Builder::new(), Builder::new()).await;
+
+    for package in [b"one", b"two"] {
+        server.write(package.to_vec()).await.unwrap();
+    }
+
+    let mut frames = client.frames();
+    assert_eq!(next_frame(&mut frames).await.unwrap().unwrap(), &b"one"[..]);
+    assert_eq!(next_frame(&mut frames).await.unwrap().unwrap(), &b"two"[..]);
+
+    // Stream ends once the peer closes the connection
+    server.close(CANCELLED).await;
+    assert!(next_frame(&mut frames).await.is_none());
+    assert!(next_frame(&mut frames).await.is_none());
+}
+
+async fn next_frame(frames: &mut KindFrames) -> Option<Result<Bytes, ReadError>> {
+    poll_fn(|cx| Pin::new(&mut *frames).poll_next(cx)).await
+}
+
+#[tokio::test]
+async fn provider_dependencies() {
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let client = Conn::connect(listener.local_addr().unwrap()).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let builder = |conn, log: &Arc<Mutex<Vec<_>>>| Builder::new()
+        .set_conn(conn)
+        .set_encryption(Logged { log: log.clone() })
+        .set_compression(Logged { log: log.clone() });
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let (client, server) = tokio::join!(
+        builder(client, &log).run(),
+        builder(server, &Arc::new(Mutex::new(Vec::new()))).run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    // Compression waits for the encryption it depends on
+    assert_eq!(*log.lock().unwrap(), ["encryption started", "encryption done", "compression started"]);
+
+    assert!(client.write(vec![1, 2, 3]).await.is_ok());
+    assert_eq!(server.read().await.unwrap(), vec![1, 2, 3]);
+}
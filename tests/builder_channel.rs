@@ -0,0 +1,64 @@
+mod common;
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::builder::channel::{ChannelError, MAX_CHANNEL_NAME_LEN};
+use cobra_rs::builder::context::LAST_APPLICATION_KIND;
+
+use common::pair;
+
+#[tokio::test]
+async fn same_kind_for_name() {
+    let (client, server) = pair("127.0.0.1:5720", Builder::new(), Builder::new()).await;
+
+    // Both sides open channels concurrently and in different order
+    let (client_chat, client_video, server_video, server_chat) = tokio::join!(
+        client.open_channel("chat"),
+        client.open_channel("video"),
+        server.open_channel("video"),
+        server.open_channel("chat"),
+    );
+    let (client_chat, server_chat) = (client_chat.unwrap(), server_chat.unwrap());
+    let (client_video, server_video) = (client_video.unwrap(), server_video.unwrap());
+
+    assert_eq!(client_chat.kind(), server_chat.kind());
+    assert_eq!(client_video.kind(), server_video.kind());
+    assert_ne!(client_chat.kind(), client_video.kind());
+    assert!(client_chat.kind() > LAST_APPLICATION_KIND - 2);
+
+    assert!(client_chat.write(vec![1]).await.is_ok());
+    assert!(server_video.write(vec![2]).await.is_ok());
+    assert_eq!(server_chat.read().await.unwrap(), vec![1]);
+    assert_eq!(client_video.read().await.unwrap(), vec![2]);
+
+    // Known name returns the same channel
+    assert_eq!(client.open_channel("chat").await.unwrap().kind(), client_chat.kind());
+}
+
+#[tokio::test]
+async fn channels_and_kinds_dont_overlap() {
+    let (client, server) = pair("127.0.0.1:5721", Builder::new(), Builder::new()).await;
+
+    let client_kind = client.open_kind().unwrap();
+    let server_kind = server.open_kind().unwrap();
+    let client_chat = client.open_channel("chat").await.unwrap();
+    let server_chat = server.open_channel("chat").await.unwrap();
+
+    assert_eq!(client_kind.kind(), server_kind.kind());
+    assert_ne!(client_kind.kind(), client_chat.kind());
+    assert_eq!(client_chat.kind(), server_chat.kind());
+}
+
+#[tokio::test]
+async fn unsupported() {
+    let (client, _server) = pair("127.0.0.1:5722", Builder::new(), Builder::new().protocol_version(4)).await;
+
+    assert!(matches!(client.open_channel("chat").await, Err(ChannelError::Unsupported)));
+}
+
+#[tokio::test]
+async fn name_too_long() {
+    let (client, _server) = pair("127.0.0.1:5723", Builder::new(), Builder::new()).await;
+
+    let name = "a".repeat(MAX_CHANNEL_NAME_LEN + 1);
+    assert!(matches!(client.open_channel(&name).await, Err(ChannelError::NameTooLong)));
+}
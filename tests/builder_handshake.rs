@@ -0,0 +1,89 @@
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use cobra_rs::builder::builder::{Builder, CompressionProvider};
+use cobra_rs::builder::context::Context;
+use cobra_rs::builder::handshake::HandshakeExtension;
+use cobra_rs::transport::stream::StreamConn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Level(u8);
+
+impl HandshakeExtension for Level {
+    const KEY: &'static str = "test.level";
+
+    fn encode(&self) -> Vec<u8> {
+        vec![self.0]
+    }
+
+    fn decode(payload: &[u8]) -> Option<Self> {
+        Some(Level(u8::from_be_bytes(payload.try_into().ok()?)))
+    }
+}
+
+// Remembers the level offered by the peer
+#[derive(Clone, Default)]
+struct Negotiating {
+    peer: Arc<Mutex<Option<Level>>>,
+}
+
+#[async_trait]
+impl CompressionProvider for Negotiating {
+    async fn init(&self, context: Context) {
+        *self.peer.lock().unwrap() = context.peer_extension::<Level>().await;
+    }
+
+    fn compress(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+
+    fn decompress(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+}
+
+async fn exchange(client: Builder, server: Builder) -> (Option<Level>, Option<Level>) {
+    let (first, second) = tokio::io::duplex(1024);
+    let (client_provider, server_provider) = (Negotiating::default(), Negotiating::default());
+
+    let (client, server) = tokio::join!(
+        client.set_conn(StreamConn::new(first)).set_compression(client_provider.clone()).run(),
+        server.set_conn(StreamConn::new(second)).set_compression(server_provider.clone()).run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    // Extensions don't take application kinds
+    client.write(b"hello".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"hello");
+
+    let client = *client_provider.peer.lock().unwrap();
+    let server = *server_provider.peer.lock().unwrap();
+    (client, server)
+}
+
+#[tokio::test]
+async fn exchange_extensions() {
+    let (client, server) = exchange(
+        Builder::new().handshake_extension(Level(3)).handshake_extension(Level(5)),
+        Builder::new().handshake_extension(Level(9)),
+    ).await;
+
+    assert_eq!(client, Some(Level(9)));
+    assert_eq!(server, Some(Level(5)));
+}
+
+#[tokio::test]
+async fn missing_extension() {
+    let (client, server) = exchange(Builder::new().handshake_extension(Level(3)), Builder::new()).await;
+    assert_eq!(client, None);
+    assert_eq!(server, Some(Level(3)));
+
+    // Older versions don't send extensions
+    let (client, server) = exchange(
+        Builder::new().handshake_extension(Level(3)),
+        Builder::new().handshake_extension(Level(9)).protocol_version(7),
+    ).await;
+    assert_eq!(client, None);
+    assert_eq!(server, None);
+}
@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use cobra_rs::builder::builder::{BuildError, Builder, EncryptionProvider};
+use cobra_rs::builder::context::Context;
+use cobra_rs::builder::identity::PeerIdentity;
+use cobra_rs::builder::kind_conn::close_code::IDENTITY_REJECTED;
+use cobra_rs::transport::tcp::{Conn, Listener};
+
+// Exchanges static keys without encrypting anything
+struct KeyExchange {
+    key: Vec<u8>,
+}
+
+#[async_trait]
+impl EncryptionProvider for KeyExchange {
+    async fn init(&self, context: Context) -> Result<(), BuildError> {
+        let conn = context.get_kind_conn().await;
+        conn.write(self.key.clone()).await.map_err(|_| BuildError::EncryptionInitFailed)?;
+        let peer_key = conn.read().await.ok_or(BuildError::EncryptionInitFailed)?;
+
+        context.extensions().insert(PeerIdentity::StaticKey(peer_key));
+        Ok(())
+    }
+
+    fn encrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+
+    fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+}
+
+async fn accept(addr: &str) -> (Conn, Conn) {
+    let listener = Listener::listen(addr).await.unwrap();
+    let client = Conn::connect(addr).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    (client, server)
+}
+
+#[test]
+fn leaf() {
+    let chain = PeerIdentity::CertificateChain(vec![vec![1], vec![2]]);
+    assert_eq!(chain.leaf(), Some(&[1][..]));
+    assert_eq!(PeerIdentity::CertificateChain(Vec::new()).leaf(), None);
+    assert_eq!(PeerIdentity::StaticKey(vec![3]).leaf(), Some(&[3][..]));
+}
+
+#[tokio::test]
+async fn pinned_identity_accepted() {
+    let (client, server) = accept("127.0.0.1:5550").await;
+
+    let (client, server) = tokio::join!(
+        Builder::new()
+            .set_conn(client)
+            .set_encryption(KeyExchange { key: b"client".to_vec() })
+            .verify_peer(|identity| identity.leaf() == Some(b"server".as_ref()))
+            .run(),
+        Builder::new()
+            .set_conn(server)
+            .set_encryption(KeyExchange { key: b"server".to_vec() })
+            .run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    assert_eq!(client.peer_identity(), Some(PeerIdentity::StaticKey(b"server".to_vec())));
+    assert_eq!(server.peer_identity(), Some(PeerIdentity::StaticKey(b"client".to_vec())));
+
+    client.write(b"hello".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"hello");
+}
+
+#[tokio::test]
+async fn pinned_identity_rejected() {
+    let (client, server) = accept("127.0.0.1:5551").await;
+
+    let (client, server) = tokio::join!(
+        Builder::new()
+            .set_conn(client)
+            .set_encryption(KeyExchange { key: b"client".to_vec() })
+            .verify_peer(|identity| identity.leaf() == Some(b"expected".as_ref()))
+            .run(),
+        Builder::new()
+            .set_conn(server)
+            .set_encryption(KeyExchange { key: b"impostor".to_vec() })
+            .run(),
+    );
+
+    assert!(matches!(client, Err(BuildError::IdentityRejected)));
+
+    let server = server.unwrap();
+    assert!(tokio::time::timeout(Duration::from_secs(1), server.read()).await.unwrap().is_none());
+    assert_eq!(server.is_close().await, Some(IDENTITY_REJECTED));
+}
+
+#[tokio::test]
+async fn missing_identity_rejected() {
+    let (client, server) = accept("127.0.0.1:5552").await;
+
+    let (client, _server) = tokio::join!(
+        Builder::new().set_conn(client).verify_peer(|_| true).run(),
+        Builder::new().set_conn(server).run(),
+    );
+
+    assert!(matches!(client, Err(BuildError::IdentityRejected)));
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use std::io;
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::builder::context::FIRST_APPLICATION_KIND;
+use cobra_rs::sync::WriteError;
+
+use common::pair;
+
+#[tokio::test]
+async fn close_kind() {
+    let (client, server) = pair("127.0.0.1:5724", Builder::new(), Builder::new()).await;
+    let (client_files, server_files) = (client.open_kind().unwrap(), server.open_kind().unwrap());
+
+    client_files.write(b"first".to_vec()).await.unwrap();
+    client_files.write(b"last".to_vec()).await.unwrap();
+    client_files.close_kind().await.unwrap();
+    assert!(matches!(client_files.write(b"late".to_vec()).await, Err(WriteError::Rejected(_))));
+
+    // Packages written before the close are read first
+    assert_eq!(server_files.read().await.unwrap(), b"first");
+    assert_eq!(server_files.read().await.unwrap(), b"last");
+    assert_eq!(server_files.read().await, None);
+    assert_eq!(server.peer_closed_kinds(), [FIRST_APPLICATION_KIND + 1]);
+
+    // The rest of the connection stays open in both directions
+    client.write(b"hello".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"hello");
+    server_files.write(b"reply".to_vec()).await.unwrap();
+    assert_eq!(client_files.read().await.unwrap(), b"reply");
+    assert!(client.peer_closed_kinds().is_empty());
+}
+
+#[tokio::test]
+async fn close_kind_unsupported() {
+    let (client, _server) = pair("127.0.0.1:5725", Builder::new(), Builder::new().protocol_version(8)).await;
+    assert_eq!(client.close_kind().await.unwrap_err().kind(), io::ErrorKind::Unsupported);
+}
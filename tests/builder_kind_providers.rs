@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use cobra_rs::builder::builder::{BuildError, Builder, EncryptionProvider};
+use cobra_rs::builder::context::{Context, FIRST_APPLICATION_KIND};
+use cobra_rs::debug::{Direction, FrameHook};
+use cobra_rs::transport::stream::StreamConn;
+
+struct XorEncryption {
+    key: u8,
+}
+
+#[async_trait]
+impl EncryptionProvider for XorEncryption {
+    // Exchanges keys to check that overrides get the same kinds on both sides
+    async fn init(&self, context: Context) -> Result<(), BuildError> {
+        let conn = context.get_kind_conn().await;
+        conn.write(vec![self.key]).await.map_err(|_| BuildError::EncryptionInitFailed)?;
+        match conn.read().await {
+            Some(key) if key == [self.key] => Ok(()),
+            _ => Err(BuildError::EncryptionInitFailed),
+        }
+    }
+
+    fn encrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame.into_iter().map(|byte| byte ^ self.key).collect()
+    }
+
+    fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        self.encrypt(frame)
+    }
+}
+
+fn builder(conn: StreamConn) -> Builder {
+    Builder::new()
+        .set_conn(conn)
+        .set_encryption(XorEncryption { key: 1 })
+        .set_kind_encryption(FIRST_APPLICATION_KIND + 1, XorEncryption { key: 2 })
+        .set_kind_encryption(FIRST_APPLICATION_KIND + 2, XorEncryption { key: 3 })
+}
+
+#[tokio::test]
+async fn kind_encryption() {
+    let (first, second) = tokio::io::duplex(1024);
+    let (first, second) = (StreamConn::new(first), StreamConn::new(second));
+
+    let written = Arc::new(Mutex::new(HashMap::new()));
+    let hook = {
+        let written = written.clone();
+        FrameHook::new(move |event| {
+            if let (Direction::Outbound, Some(payload)) = (event.direction, event.payload) {
+                written.lock().unwrap().insert(event.kind, payload.to_vec());
+            }
+        }).with_payload(true)
+    };
+
+    let (client, server) = tokio::join!(builder(first).on_frame(hook).run(), builder(second).run());
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    for conn in [&client, &client.open_kind().unwrap(), &client.open_kind().unwrap()] {
+        conn.write(b"hello".to_vec()).await.unwrap();
+    }
+    assert_eq!(server.read().await.unwrap(), b"hello");
+    for conn in [server.open_kind().unwrap(), server.open_kind().unwrap()] {
+        assert_eq!(conn.read().await.unwrap(), b"hello");
+    }
+
+    let written = written.lock().unwrap();
+    for key in 1..=3 {
+        let expected: Vec<u8> = b"hello".iter().map(|byte| byte ^ key).collect();
+        assert_eq!(written[&(FIRST_APPLICATION_KIND + key - 1)], expected);
+    }
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::time::Duration;
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::builder::context::LAST_APPLICATION_KIND;
+
+use common::pair;
+
+#[tokio::test]
+async fn clone_shares_kind() {
+    let (client, server) = pair("127.0.0.1:5700", Builder::new(), Builder::new()).await;
+
+    let cloned = client.clone();
+    assert_eq!(cloned.kind(), client.kind());
+    assert_eq!(client.handles(), 2);
+
+    assert!(client.write(vec![1]).await.is_ok());
+    assert!(cloned.write(vec![2]).await.is_ok());
+    assert_eq!(server.read().await.unwrap(), vec![1]);
+    assert_eq!(server.read().await.unwrap(), vec![2]);
+
+    // The kind is still used by the other handle
+    drop(cloned);
+    assert_eq!(client.handles(), 1);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!server.is_released());
+}
+
+#[tokio::test]
+async fn last_handle_releases_kind() {
+    let (client, server) = pair("127.0.0.1:5701", Builder::new(), Builder::new()).await;
+
+    let cloned = client.clone();
+    drop(client);
+    assert!(cloned.write(vec![1]).await.is_ok());
+    drop(cloned);
+
+    assert_eq!(server.read().await.unwrap(), vec![1]);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(server.is_released());
+    assert_eq!(server.handles(), 1);
+}
+
+#[tokio::test]
+async fn release_unsupported() {
+    let (client, server) = pair("127.0.0.1:5702", Builder::new(), Builder::new().protocol_version(2)).await;
+
+    drop(client);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!server.is_released());
+}
+
+#[tokio::test]
+async fn open_kind() {
+    let (client, server) = pair("127.0.0.1:5703", Builder::new(), Builder::new()).await;
+
+    let client_kind = client.open_kind().unwrap();
+    let server_kind = server.open_kind().unwrap();
+    assert_eq!(client_kind.kind(), client.kind() + 1);
+    assert_eq!(client_kind.kind(), server_kind.kind());
+
+    assert!(client_kind.write(vec![1]).await.is_ok());
+    assert!(client.write(vec![2]).await.is_ok());
+    assert_eq!(server.read().await.unwrap(), vec![2]);
+    assert_eq!(server_kind.read().await.unwrap(), vec![1]);
+
+    let mut opened = vec![client_kind];
+    while let Some(conn) = client.open_kind() {
+        opened.push(conn);
+    }
+    assert_eq!(opened.last().unwrap().kind(), LAST_APPLICATION_KIND);
+}
+
+#[tokio::test]
+async fn reuse_released_kind() {
+    let quarantine = Duration::from_millis(50);
+    let (client, server) = pair(
+        "127.0.0.1:5704",
+        Builder::new().kind_quarantine(quarantine),
+        Builder::new().kind_quarantine(quarantine),
+    ).await;
+
+    let client_kind = client.open_kind().unwrap();
+    let server_kind = server.open_kind().unwrap();
+    let kind = client_kind.kind();
+
+    // Never read, dropped with the old channel
+    assert!(client_kind.write(vec![1]).await.is_ok());
+    drop(client_kind);
+    drop(server_kind);
+
+    // Not reissued until both sides reclaim it
+    assert_ne!(client.open_kind().unwrap().kind(), kind);
+    assert_ne!(server.open_kind().unwrap().kind(), kind);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let client_kind = client.open_kind().unwrap();
+    let server_kind = server.open_kind().unwrap();
+    assert_eq!(client_kind.kind(), kind);
+    assert_eq!(server_kind.kind(), kind);
+
+    assert!(client_kind.write(vec![2]).await.is_ok());
+    assert_eq!(server_kind.read().await.unwrap(), vec![2]);
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::builder::kind_conn::close_code::CLOSED_BY_USER;
+use cobra_rs::builder::peer_error::PeerError;
+
+use common::pair;
+
+#[tokio::test]
+async fn send_error() {
+    let (client, server) = pair("127.0.0.1:5680", Builder::new(), Builder::new()).await;
+
+    assert!(server.write(vec![1]).await.is_ok());
+    assert!(server.send_error(404, "not found", Some(7)).await.is_ok());
+    assert!(server.send_error(500, "", None).await.is_ok());
+    assert!(server.write(vec![2]).await.is_ok());
+
+    // Errors don't mix with packages
+    assert_eq!(client.read().await.unwrap(), vec![1]);
+    assert_eq!(client.read().await.unwrap(), vec![2]);
+
+    let kind = client.kind();
+    assert_eq!(client.read_error().await.unwrap(), PeerError {
+        kind,
+        code: 404,
+        message: "not found".to_string(),
+        correlation: Some(7),
+    });
+    assert_eq!(client.read_error().await.unwrap(), PeerError {
+        kind,
+        code: 500,
+        message: String::new(),
+        correlation: None,
+    });
+
+    server.close(CLOSED_BY_USER).await;
+    assert_eq!(client.read_error().await, None);
+}
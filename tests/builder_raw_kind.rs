@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use cobra_rs::builder::builder::{BuildError, Builder, EncryptionProvider, PingProvider};
+use cobra_rs::builder::context::{Context, RawKindError, FIRST_APPLICATION_KIND};
+use cobra_rs::builder::kind_conn::KindConn;
+use cobra_rs::debug::{Direction, FrameHook};
+use cobra_rs::transport::stream::StreamConn;
+
+struct XorEncryption;
+
+#[async_trait]
+impl EncryptionProvider for XorEncryption {
+    async fn init(&self, _context: Context) -> Result<(), BuildError> {
+        Ok(())
+    }
+
+    fn encrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame.into_iter().map(|byte| byte ^ 0xFF).collect()
+    }
+
+    fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        self.encrypt(frame)
+    }
+}
+
+// Takes a raw kind during initialization and keeps the context
+#[derive(Clone, Default)]
+struct RawChannel {
+    conn: Arc<Mutex<Option<KindConn>>>,
+    context: Arc<Mutex<Option<Context>>>,
+}
+
+#[async_trait]
+impl PingProvider for RawChannel {
+    async fn init(&self, context: Context) {
+        *self.conn.lock().unwrap() = Some(context.raw_kind_conn().unwrap());
+        *self.context.lock().unwrap() = Some(context);
+    }
+}
+
+#[tokio::test]
+async fn raw_kind() {
+    let (first, second) = tokio::io::duplex(1024);
+    let (client_channel, server_channel) = (RawChannel::default(), RawChannel::default());
+
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let hook = {
+        let written = written.clone();
+        FrameHook::new(move |event| {
+            if let (Direction::Outbound, Some(payload)) = (event.direction, event.payload) {
+                written.lock().unwrap().push((event.kind, payload.to_vec()));
+            }
+        }).with_payload(true)
+    };
+
+    let (client, server) = tokio::join!(
+        Builder::new()
+            .set_conn(StreamConn::new(first))
+            .set_encryption(XorEncryption)
+            .set_ping(client_channel.clone())
+            .on_frame(hook)
+            .run(),
+        Builder::new()
+            .set_conn(StreamConn::new(second))
+            .set_encryption(XorEncryption)
+            .set_ping(server_channel.clone())
+            .run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+    let client_raw = client_channel.conn.lock().unwrap().take().unwrap();
+    let server_raw = server_channel.conn.lock().unwrap().take().unwrap();
+
+    assert_eq!(client_raw.kind(), FIRST_APPLICATION_KIND + 1);
+    assert!(client_raw.is_raw());
+    assert!(!client.is_raw());
+
+    client_raw.write(vec![1, 2, 3]).await.unwrap();
+    client.write(vec![1, 2, 3]).await.unwrap();
+    assert_eq!(server_raw.read().await.unwrap(), [1, 2, 3]);
+    assert_eq!(server.read().await.unwrap(), [1, 2, 3]);
+
+    let written = written.lock().unwrap();
+    assert!(written.contains(&(FIRST_APPLICATION_KIND + 1, vec![1, 2, 3])));
+    assert!(written.contains(&(FIRST_APPLICATION_KIND, vec![0xFE, 0xFD, 0xFC])));
+
+    // Kinds opened after the handshake are encrypted
+    let context = client_channel.context.lock().unwrap().take().unwrap();
+    assert!(matches!(context.raw_kind_conn(), Err(RawKindError::EncryptionReady)));
+    assert!(!client.open_kind().unwrap().is_raw());
+}
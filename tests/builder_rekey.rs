@@ -0,0 +1,110 @@
+mod common;
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use cobra_rs::builder::builder::{BuildError, Builder, EncryptionProvider};
+use cobra_rs::builder::context::Context;
+use cobra_rs::builder::kind_conn::KindConn;
+use cobra_rs::builder::rekey::KeyRotation;
+
+use common::pair;
+
+// Every package starts with the generation of its key
+struct XorEncryption {
+    generation: Arc<AtomicU8>,
+}
+
+impl XorEncryption {
+    fn new() -> (Self, Arc<AtomicU8>) {
+        let generation = Arc::new(AtomicU8::new(0));
+        (XorEncryption { generation: generation.clone() }, generation)
+    }
+
+    fn key(generation: u8) -> u8 {
+        generation.wrapping_mul(31).wrapping_add(7)
+    }
+}
+
+#[async_trait]
+impl EncryptionProvider for XorEncryption {
+    async fn init(&self, _context: Context) -> Result<(), BuildError> {
+        Ok(())
+    }
+
+    fn encrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        let generation = self.generation.load(Ordering::SeqCst);
+        let key = XorEncryption::key(generation);
+        std::iter::once(generation).chain(frame.into_iter().map(|byte| byte ^ key)).collect()
+    }
+
+    fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        let key = XorEncryption::key(frame[0]);
+        frame[1..].iter().map(|byte| byte ^ key).collect()
+    }
+
+    fn rotate_keys(&self) -> bool {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        true
+    }
+}
+
+async fn connect(addr: &str, rotation: Option<KeyRotation>) -> (KindConn, Arc<AtomicU8>, KindConn, Arc<AtomicU8>) {
+    let (client_encryption, client_generation) = XorEncryption::new();
+    let (server_encryption, server_generation) = XorEncryption::new();
+
+    let mut client_builder = Builder::new().set_encryption(client_encryption);
+    if let Some(rotation) = rotation {
+        client_builder = client_builder.key_rotation(rotation);
+    }
+
+    let (client, server) = pair(addr, client_builder, Builder::new().set_encryption(server_encryption)).await;
+    (client, client_generation, server, server_generation)
+}
+
+#[tokio::test]
+async fn rotate_keys() {
+    let (client, client_generation, server, server_generation) = connect("127.0.0.1:5530", None).await;
+
+    client.write(b"before".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"before");
+
+    client.rotate_keys().await.unwrap();
+    assert_eq!(client_generation.load(Ordering::SeqCst), 1);
+    assert_eq!(server_generation.load(Ordering::SeqCst), 1);
+
+    client.write(b"after".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"after");
+    server.write(b"reply".to_vec()).await.unwrap();
+    assert_eq!(client.read().await.unwrap(), b"reply");
+}
+
+#[tokio::test]
+async fn rotate_keys_automatically() {
+    let rotation = KeyRotation { frames: Some(3), bytes: None };
+    let (client, client_generation, server, server_generation) = connect("127.0.0.1:5531", Some(rotation)).await;
+
+    for _ in 0..3 {
+        client.write(b"package".to_vec()).await.unwrap();
+        assert_eq!(server.read().await.unwrap(), b"package");
+    }
+
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while server_generation.load(Ordering::SeqCst) == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.unwrap();
+    assert_eq!(client_generation.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn rotation_unsupported() {
+    let (client, _server) = pair("127.0.0.1:5532", Builder::new(), Builder::new()).await;
+
+    let error = client.rotate_keys().await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::time::Duration;
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::builder::stats::StatsError;
+use cobra_rs::builder::version::{Features, PROTOCOL_VERSION};
+
+use common::pair;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[tokio::test]
+async fn local_stats() {
+    let (client, _server) = pair("127.0.0.1:5730", Builder::new(), Builder::new()).await;
+
+    let stats = client.stats();
+    assert_eq!(stats.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(Some(stats.features), Features::of(PROTOCOL_VERSION));
+    assert_eq!(stats.open_kinds, 1);
+
+    let _kind = client.open_kind().unwrap();
+    assert_eq!(client.stats().open_kinds, 2);
+}
+
+#[tokio::test]
+async fn peer_stats() {
+    let (client, server) = pair("127.0.0.1:5731", Builder::new(), Builder::new().expose_stats(true)).await;
+
+    for package in 0..3 {
+        client.write(vec![package]).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let stats = client.peer_stats(TIMEOUT).await.unwrap();
+    assert_eq!(stats.protocol_version, PROTOCOL_VERSION);
+    assert!(stats.transport.queued_frames > 0);
+    assert_eq!(stats.transport.rtt.is_some(), cfg!(target_os = "linux"));
+
+    // Queued packages are still delivered
+    assert_eq!(server.read().await.unwrap(), vec![0]);
+}
+
+#[tokio::test]
+async fn refused_by_default() {
+    let (client, server) = pair("127.0.0.1:5732", Builder::new(), Builder::new()).await;
+
+    assert_eq!(client.peer_stats(TIMEOUT).await.unwrap_err(), StatsError::Refused);
+    assert_eq!(server.peer_stats(TIMEOUT).await.unwrap_err(), StatsError::Refused);
+}
+
+#[tokio::test]
+async fn unsupported() {
+    let (client, _server) = pair(
+        "127.0.0.1:5733",
+        Builder::new(),
+        Builder::new().protocol_version(5).expose_stats(true),
+    ).await;
+
+    assert_eq!(client.peer_stats(TIMEOUT).await.unwrap_err(), StatsError::Unsupported);
+}
+
+#[tokio::test]
+async fn fixed_layout_peer() {
+    let (client, _server) = pair(
+        "127.0.0.1:5760",
+        Builder::new(),
+        Builder::new().protocol_version(6).expose_stats(true),
+    ).await;
+
+    // Version 6 doesn't encode messages with CBOR
+    let stats = client.peer_stats(TIMEOUT).await.unwrap();
+    assert_eq!(stats.protocol_version, 6);
+    assert!(!stats.features.structured_control);
+}
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use cobra_rs::builder::builder::{BuildError, Builder, EncryptionProvider, PingProvider};
+use cobra_rs::builder::context::{Context, FIRST_APPLICATION_KIND};
+use cobra_rs::builder::dependency::Provider;
+use cobra_rs::builder::empty_realisations::EmptyRealisation;
+use cobra_rs::builder::rekey::KeyRotation;
+use cobra_rs::builder::validation::ConfigProblem;
+use cobra_rs::builder::version::{Features, PROTOCOL_VERSION};
+use cobra_rs::mem::KindWidth;
+
+struct Reverse;
+
+#[async_trait]
+impl EncryptionProvider for Reverse {
+    async fn init(&self, _context: Context) -> Result<(), BuildError> {
+        Ok(())
+    }
+
+    fn encrypt(&self, mut frame: Vec<u8>) -> Vec<u8> {
+        frame.reverse();
+        frame
+    }
+
+    fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        self.encrypt(frame)
+    }
+}
+
+// Passthrough providers waiting for the given one
+struct DependsOn(Provider);
+
+#[async_trait]
+impl PingProvider for DependsOn {
+    async fn init(&self, _context: Context) {}
+
+    fn depends_on(&self) -> Vec<Provider> {
+        vec![self.0]
+    }
+}
+
+#[async_trait]
+impl EncryptionProvider for DependsOn {
+    async fn init(&self, _context: Context) -> Result<(), BuildError> {
+        Ok(())
+    }
+
+    fn encrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+
+    fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame
+    }
+
+    fn depends_on(&self) -> Vec<Provider> {
+        vec![self.0]
+    }
+}
+
+#[test]
+fn report_all_problems() {
+    let problems = Builder::new()
+        .handshake_timeout(Duration::ZERO)
+        .protocol_version(3)
+        .kind_width(KindWidth::U16)
+        .key_rotation(KeyRotation { frames: Some(100), bytes: None })
+        .verify_peer(|_| true)
+        .set_kind_compression(1, EmptyRealisation {})
+        .set_kind_encryption(FIRST_APPLICATION_KIND, EmptyRealisation {})
+        .validate()
+        .unwrap_err();
+
+    assert_eq!(problems, [
+        ConfigProblem::ConnNotSet,
+        ConfigProblem::ZeroHandshakeTimeout,
+        ConfigProblem::WideKindsUnsupported { version: 3 },
+        ConfigProblem::KeyRotationWithoutEncryption,
+        ConfigProblem::VerificationWithoutEncryption,
+        ConfigProblem::NotApplicationKind(1),
+    ]);
+}
+
+#[test]
+fn dry_run_negotiates() {
+    let client = Builder::new().kind_width(KindWidth::U32);
+    let server = Builder::new().kind_width(KindWidth::U16);
+
+    let capabilities = client.dry_run(&server).unwrap();
+    assert_eq!(capabilities.version, PROTOCOL_VERSION);
+    assert_eq!(capabilities.kind_width, KindWidth::U16);
+    assert!(!capabilities.encryption);
+
+    let capabilities = client.dry_run(&Builder::new().protocol_version(1)).unwrap();
+    assert_eq!(capabilities.version, 1);
+    assert_eq!(capabilities.features, Features::of(1).unwrap());
+    assert_eq!(capabilities.kind_width, KindWidth::U8);
+}
+
+#[test]
+fn dry_run_mismatch() {
+    let kind = FIRST_APPLICATION_KIND + 1;
+    let client = Builder::new().set_kind_compression(kind, EmptyRealisation {});
+    let server = Builder::new().set_encryption(Reverse);
+
+    assert_eq!(client.dry_run(&server).unwrap_err(), [
+        ConfigProblem::EncryptionMismatch,
+        ConfigProblem::KindOverrideMismatch(kind),
+    ]);
+
+    let capabilities = client.dry_run(&Builder::new().set_kind_compression(kind, EmptyRealisation {})).unwrap();
+    assert_eq!(capabilities.kind_compression, [kind]);
+}
+
+#[tokio::test]
+async fn provider_cycle() {
+    let builder = || Builder::new()
+        .set_ping(DependsOn(Provider::Encryption))
+        .set_encryption(DependsOn(Provider::Ping));
+
+    assert_eq!(builder().validate().unwrap_err(), [
+        ConfigProblem::ConnNotSet,
+        ConfigProblem::ProviderCycle,
+    ]);
+    // Rejected before the connection is needed
+    assert!(matches!(builder().run().await, Err(BuildError::ProviderCycle)));
+
+    let builder = Builder::new().set_ping(DependsOn(Provider::Encryption));
+    assert_eq!(builder.validate().unwrap_err(), [ConfigProblem::ConnNotSet]);
+}
@@ -0,0 +1,223 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use cobra_rs::builder::builder::{BuildError, Builder, ConnProvider, PingProvider};
+use cobra_rs::builder::context::Context;
+use cobra_rs::builder::kind_conn::close_code::{CLOSED_BY_USER, HANDSHAKE_TIMEOUT};
+use cobra_rs::builder::version::{Features, VersionFrame, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, VERSION_KIND};
+use cobra_rs::mem::{Frame, KindWidth};
+use cobra_rs::sync::WriteError;
+use cobra_rs::transport::tcp::{Conn, Listener};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use common::pair;
+
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+// Takes a kind and sends an empty package on it, like pings do
+struct EmptyPing;
+
+#[async_trait]
+impl PingProvider for EmptyPing {
+    async fn init(&self, context: Context) {
+        context.get_kind_conn().await.write(Vec::new()).await.unwrap();
+    }
+}
+
+// Flags in the order of the fields
+fn flags(features: Features) -> [bool; 10] {
+    [
+        features.version_frame,
+        features.error_frames,
+        features.kind_release,
+        features.wide_kinds,
+        features.named_channels,
+        features.remote_stats,
+        features.structured_control,
+        features.handshake_extensions,
+        features.kind_close,
+        features.structured_close,
+    ]
+}
+
+#[test]
+fn features() {
+    assert_eq!(flags(Features::of(1).unwrap()), [false, false, false, false, false, false, false, false, false, false]);
+    assert_eq!(flags(Features::of(2).unwrap()), [true, true, false, false, false, false, false, false, false, false]);
+    assert_eq!(flags(Features::of(3).unwrap()), [true, true, true, false, false, false, false, false, false, false]);
+    assert_eq!(flags(Features::of(4).unwrap()), [true, true, true, true, false, false, false, false, false, false]);
+    assert_eq!(flags(Features::of(5).unwrap()), [true, true, true, true, true, false, false, false, false, false]);
+    assert_eq!(flags(Features::of(6).unwrap()), [true, true, true, true, true, true, false, false, false, false]);
+    assert_eq!(flags(Features::of(7).unwrap()), [true, true, true, true, true, true, true, false, false, false]);
+    assert_eq!(flags(Features::of(8).unwrap()), [true, true, true, true, true, true, true, true, false, false]);
+    assert_eq!(flags(Features::of(9).unwrap()), [true, true, true, true, true, true, true, true, true, false]);
+    assert_eq!(flags(Features::of(10).unwrap()), [true, true, true, true, true, true, true, true, true, true]);
+    assert_eq!(Features::of(MIN_PROTOCOL_VERSION - 1), None);
+    assert_eq!(Features::of(PROTOCOL_VERSION + 1), None);
+}
+
+#[test]
+fn version_frame_encoding() {
+    let frame = VersionFrame { version: 6, kind_width: Some(KindWidth::U16), tie_breaker: Some(7) };
+    assert_eq!(VersionFrame::decode(&frame.encode()).unwrap(), frame);
+
+    // Unknown width and trailing bytes of newer peers
+    let frame = VersionFrame::decode(&[9, 3, 0, 0, 0, 0, 0, 0, 0, 1, 5]).unwrap();
+    assert_eq!(frame, VersionFrame { version: 9, kind_width: None, tie_breaker: Some(1) });
+    assert_eq!(VersionFrame::decode(&[4, 2, 1]).unwrap().tie_breaker, None);
+
+    assert!(matches!(VersionFrame::decode(&[]), Err(BuildError::UnsupportedVersion(0))));
+    assert!(matches!(VersionFrame::decode(&[0, 1]), Err(BuildError::UnsupportedVersion(0))));
+}
+
+#[tokio::test]
+async fn newest_version() {
+    let (client, server) = pair("127.0.0.1:5690", Builder::new(), Builder::new()).await;
+
+    assert_eq!(client.protocol_version(), PROTOCOL_VERSION);
+    assert_eq!(server.protocol_version(), PROTOCOL_VERSION);
+    assert!(client.send_error(1, "error", None).await.is_ok());
+}
+
+#[tokio::test]
+async fn structured_close() {
+    // Close frames are CBOR messages since version 10, older peers get the fixed layout
+    for (addr, version) in [("127.0.0.1:5695", PROTOCOL_VERSION), ("127.0.0.1:5696", 9)] {
+        let (client, server) = pair(addr, Builder::new(), Builder::new().protocol_version(version)).await;
+        server.close_with_reason(CLOSED_BY_USER, "bye").await;
+
+        assert!(client.read().await.is_none());
+        assert_eq!(client.is_close().await, Some(CLOSED_BY_USER));
+        assert_eq!(client.close_reason().await, Some("bye".to_string()));
+    }
+}
+
+#[tokio::test]
+async fn downgrade() {
+    let (client, server) = pair(
+        "127.0.0.1:5691",
+        Builder::new().version_timeout(TIMEOUT),
+        Builder::new().protocol_version(1),
+    ).await;
+
+    assert_eq!(client.protocol_version(), 1);
+    assert_eq!(server.protocol_version(), 1);
+
+    // Peers of version 1 don't understand error frames
+    assert!(matches!(client.send_error(1, "error", None).await, Err(WriteError::Rejected(_))));
+    assert!(matches!(server.send_error(1, "error", None).await, Err(WriteError::Rejected(_))));
+
+    assert!(client.write(vec![1, 2, 3]).await.is_ok());
+    assert_eq!(server.read().await.unwrap(), vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn version_frame() {
+    const ADDR: &str = "127.0.0.1:5692";
+    let listener = Listener::listen(ADDR).await.unwrap();
+
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    let client = tokio::spawn(Builder::new().version_timeout(TIMEOUT).set_conn(client).run());
+    let width = KindWidth::U8.bytes() as u8;
+    let frame = server.read(VERSION_KIND).await.unwrap().get_body();
+    assert_eq!(frame[..2], [PROTOCOL_VERSION, width]);
+    assert_eq!(frame.len(), 10);
+
+    // Version 1 is agreed without an answer, but a late newer version closes the connection
+    let client = client.await.unwrap().unwrap();
+    assert_eq!(client.protocol_version(), 1);
+    assert!(server.write(Frame::create(VERSION_KIND, &[PROTOCOL_VERSION])).await.is_ok());
+    assert!(client.read().await.is_none());
+    assert_eq!(client.is_close().await, Some(HANDSHAKE_TIMEOUT));
+
+    // Version 1 keeps the handshake unchanged
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    let client = Builder::new().protocol_version(1).set_conn(client).run().await.unwrap();
+    assert_eq!(client.protocol_version(), 1);
+    assert!(tokio::time::timeout(TIMEOUT, server.read(VERSION_KIND)).await.is_err());
+}
+
+#[tokio::test]
+async fn version_1_wire() {
+    const ADDR: &str = "127.0.0.1:5693";
+    let listener = TcpListener::bind(ADDR).await.unwrap();
+
+    // Frames are laid out as by peers built before versioning,
+    // the application gets kind 1 if providers take no kinds
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (mut server, _) = listener.accept().await.unwrap();
+    let client = Builder::new().protocol_version(1).set_conn(client).run().await.unwrap();
+    assert_eq!(client.kind(), 1);
+    client.write(vec![1, 2, 3]).await.unwrap();
+
+    let mut bytes = [0; 6];
+    server.read_exact(&mut bytes).await.unwrap();
+    assert_eq!(bytes, [0, 4, 1, 1, 2, 3]);
+    server.write_all(&[0, 3, 1, 4, 5]).await.unwrap();
+    assert_eq!(client.read().await.unwrap(), vec![4, 5]);
+
+    // Providers take kinds in the order of initialization
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (mut server, _) = listener.accept().await.unwrap();
+    let client = Builder::new().protocol_version(1).set_ping(EmptyPing).set_conn(client).run().await.unwrap();
+    assert_eq!(client.kind(), 2);
+    client.write(vec![1, 2, 3]).await.unwrap();
+
+    let mut bytes = [0; 9];
+    server.read_exact(&mut bytes).await.unwrap();
+    assert_eq!(bytes, [0, 1, 1, 0, 4, 2, 1, 2, 3]);
+}
+
+#[tokio::test]
+async fn version_1_wire_peer() {
+    const ADDR: &str = "127.0.0.1:5697";
+    let listener = TcpListener::bind(ADDR).await.unwrap();
+
+    // Peer built before versioning skips the version frame and never answers
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (mut server, _) = listener.accept().await.unwrap();
+    let client = tokio::spawn(Builder::new().version_timeout(TIMEOUT).set_conn(client).run());
+
+    let mut header = [0; 3];
+    server.read_exact(&mut header).await.unwrap();
+    assert_eq!(header[2], VERSION_KIND);
+    let mut frame = vec![0; u16::from_be_bytes([header[0], header[1]]) as usize - 1];
+    server.read_exact(&mut frame).await.unwrap();
+
+    let client = client.await.unwrap().unwrap();
+    assert_eq!(client.protocol_version(), 1);
+    assert_eq!(client.kind(), 1);
+    client.write(vec![1, 2, 3]).await.unwrap();
+
+    let mut bytes = [0; 6];
+    server.read_exact(&mut bytes).await.unwrap();
+    assert_eq!(bytes, [0, 4, 1, 1, 2, 3]);
+    server.write_all(&[0, 3, 1, 4, 5]).await.unwrap();
+    assert_eq!(client.read().await.unwrap(), vec![4, 5]);
+}
+
+#[tokio::test]
+async fn version_1_answers_version_frame() {
+    let started = Instant::now();
+    let (client, server) = pair(
+        "127.0.0.1:5694",
+        Builder::new().set_ping(EmptyPing),
+        Builder::new().protocol_version(1).set_ping(EmptyPing),
+    ).await;
+
+    // The newer side doesn't wait for the version timeout
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert_eq!(client.protocol_version(), 1);
+    assert_eq!((client.kind(), server.kind()), (2, 2));
+
+    assert!(client.write(vec![1, 2, 3]).await.is_ok());
+    assert_eq!(server.read().await.unwrap(), vec![1, 2, 3]);
+    assert!(server.write(vec![4, 5]).await.is_ok());
+    assert_eq!(client.read().await.unwrap(), vec![4, 5]);
+    assert!(client.read_error().await.is_none());
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::mem::KindWidth;
+
+use common::pair;
+
+#[tokio::test]
+async fn narrower_width() {
+    let (client, server) = pair(
+        "127.0.0.1:5710",
+        Builder::new().kind_width(KindWidth::U32),
+        Builder::new().kind_width(KindWidth::U16),
+    ).await;
+
+    assert_eq!(client.kind_width(), KindWidth::U16);
+    assert_eq!(server.kind_width(), KindWidth::U16);
+    assert!(client.wide_kind(70000).is_none());
+
+    let client_kind = client.wide_kind(300).unwrap();
+    let server_kind = server.wide_kind(300).unwrap();
+    assert!(client_kind.write(vec![1, 2, 3]).await.is_ok());
+    assert!(client.write(vec![4]).await.is_ok());
+    assert_eq!(server_kind.read().await.unwrap(), vec![1, 2, 3]);
+    assert_eq!(server.read().await.unwrap(), vec![4]);
+}
+
+#[tokio::test]
+async fn one_byte_by_default() {
+    let (client, _server) = pair("127.0.0.1:5711", Builder::new().kind_width(KindWidth::U32), Builder::new()).await;
+
+    assert_eq!(client.kind_width(), KindWidth::U8);
+    assert!(client.wide_kind(300).is_none());
+}
+
+#[tokio::test]
+async fn kinds_are_independent() {
+    let (client, server) = pair(
+        "127.0.0.1:5712",
+        Builder::new().kind_width(KindWidth::U32),
+        Builder::new().kind_width(KindWidth::U32),
+    ).await;
+
+    let unread = client.wide_kind(100_000).unwrap();
+    assert!(unread.write(vec![1]).await.is_ok());
+
+    // The unread kind doesn't block others
+    for kind in [0, 1, 65535, 65536] {
+        assert!(client.wide_kind(kind).unwrap().write(vec![kind as u8]).await.is_ok());
+        assert_eq!(server.wide_kind(kind).unwrap().read().await.unwrap(), vec![kind as u8]);
+    }
+
+    assert_eq!(server.wide_kind(100_000).unwrap().read().await.unwrap(), vec![1]);
+}
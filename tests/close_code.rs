@@ -0,0 +1,84 @@
+use cobra_rs::builder::kind_conn::close_code::{CloseCode, CloseRange, CLOSED_BY_USER, GOING_AWAY, IDENTITY_REJECTED};
+use cobra_rs::mem::{Chunk, ConcatBuf, Frame};
+use cobra_rs::protocol::Encoding;
+use cobra_rs::transport::control::{ControlFrame, MAX_CLOSE_REASON_LEN};
+
+#[test]
+fn round_trip() {
+    for code in 0..=u8::MAX {
+        assert_eq!(CloseCode::from(code).code(), code);
+        assert_eq!(CloseCode::from(code).range(), CloseRange::of(code));
+    }
+}
+
+#[test]
+fn ranges() {
+    assert_eq!(CloseCode::from(CLOSED_BY_USER), CloseCode::ClosedByUser);
+    assert_eq!(CloseCode::from(IDENTITY_REJECTED).range(), CloseRange::Protocol);
+    assert_eq!(CloseCode::from(GOING_AWAY).range(), CloseRange::Library);
+
+    assert_eq!(CloseCode::from(20), CloseCode::Reserved(20));
+    assert_eq!(CloseCode::from(40), CloseCode::Reserved(40));
+    assert_eq!(CloseCode::from(64), CloseCode::Application(64));
+
+    assert_eq!(CloseCode::application(63), None);
+    assert_eq!(CloseCode::application(255), Some(CloseCode::Application(255)));
+    assert!(CloseRange::Application.codes().contains(&200));
+}
+
+#[test]
+fn close_frame() {
+    for code in [CloseCode::GoingAway, CloseCode::Reserved(0), CloseCode::Application(200)] {
+        let frame = ControlFrame::Close { code, reason: "bye".to_string() };
+
+        let mut buf: ConcatBuf<Frame> = ConcatBuf::default();
+        buf.extend_from_slice(&frame.encode());
+        assert_eq!(buf.try_read_chunk().as_ref().and_then(ControlFrame::decode), Some(frame));
+    }
+}
+
+#[test]
+fn structured_close_frame() {
+    let frames = [
+        ControlFrame::Close { code: CloseCode::GoingAway, reason: "bye".to_string() },
+        ControlFrame::ShutdownWrite,
+        ControlFrame::CloseAck,
+    ];
+
+    for frame in frames {
+        assert_eq!(frame.encode_as(Encoding::Fixed)[..], frame.encode()[..]);
+
+        // Both layouts are decoded regardless of the version
+        let mut buf: ConcatBuf<Frame> = ConcatBuf::default();
+        buf.extend_from_slice(&frame.encode_as(Encoding::Cbor));
+        assert_eq!(buf.try_read_chunk().as_ref().and_then(ControlFrame::decode), Some(frame));
+    }
+
+    // Only close and shutdown frames are CBOR messages
+    assert_ne!(ControlFrame::ShutdownWrite.encode_as(Encoding::Cbor)[..], ControlFrame::ShutdownWrite.encode()[..]);
+    assert_eq!(ControlFrame::CloseAck.encode_as(Encoding::Cbor)[..], ControlFrame::CloseAck.encode()[..]);
+}
+
+#[test]
+fn long_close_reason() {
+    // Two-byte chars after one ASCII char don't fit evenly into the limit
+    let reason = format!("a{}", "й".repeat(MAX_CLOSE_REASON_LEN));
+    let frame = ControlFrame::Close { code: CloseCode::GoingAway, reason: reason.clone() };
+
+    let mut buf: ConcatBuf<Frame> = ConcatBuf::default();
+    buf.extend_from_slice(&frame.encode());
+    let reason = match buf.try_read_chunk().as_ref().and_then(ControlFrame::decode) {
+        Some(ControlFrame::Close { reason, .. }) => reason,
+        frame => panic!("unexpected frame {:?}", frame),
+    };
+    assert_eq!(reason.len(), MAX_CLOSE_REASON_LEN - 1);
+    assert!(reason.ends_with('й'));
+}
+
+#[test]
+fn malformed_close_frame() {
+    assert_eq!(ControlFrame::decode(&Frame::create(0, &[1])), None);
+    assert_eq!(ControlFrame::decode(&Frame::create(0, &[1, 0, 0xFF])), None);
+    assert_eq!(ControlFrame::decode(&Frame::create(0, &[9])), None);
+    assert_eq!(ControlFrame::decode(&Frame::with_capacity(0)), None);
+}
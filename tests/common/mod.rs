@@ -0,0 +1,36 @@
+// Fixtures shared by the test binaries, each of them uses only a part
+#![allow(dead_code)]
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::builder::kind_conn::KindConn;
+use cobra_rs::transport::tcp::{Conn, ConnConfig, Listener};
+
+/// Connects over TCP at `addr` and builds both sides at once
+pub async fn pair(addr: &str, client: Builder, server: Builder) -> (KindConn, KindConn) {
+    pair_with_config(addr, ConnConfig::new(), client, server).await
+}
+
+/// The same as [`pair()`], but both transports use `config`
+pub async fn pair_with_config(addr: &str, config: ConnConfig, client: Builder, server: Builder) -> (KindConn, KindConn) {
+    let listener = Listener::listen_with_config(addr, config.clone()).await.unwrap();
+    let client_conn = Conn::connect_with_config(addr, config).await.unwrap();
+    let (server_conn, _) = listener.accept().await.unwrap();
+
+    let (client, server) = tokio::join!(
+        client.set_conn(client_conn).run(),
+        server.set_conn(server_conn).run(),
+    );
+    (client.unwrap(), server.unwrap())
+}
+
+/// Connects to the listener at `addr` and builds both sides with default settings
+pub async fn pair_on(listener: &Listener, addr: &str) -> (KindConn, KindConn) {
+    let client = Conn::connect(addr).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let (client, server) = tokio::join!(
+        Builder::new().set_conn(client).run(),
+        Builder::new().set_conn(server).run(),
+    );
+    (client.unwrap(), server.unwrap())
+}
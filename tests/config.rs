@@ -0,0 +1,57 @@
+use cobra_rs::config::CobraConfig;
+use std::time::Duration;
+
+#[test]
+fn empty_config() {
+    let config: CobraConfig = toml::from_str("").unwrap();
+
+    assert!(config.ping().is_none());
+    assert!(config.handshake_timeout().is_none());
+}
+
+#[test]
+fn full_config() {
+    let config: CobraConfig = toml::from_str(r#"
+        handshake_timeout_ms = 5000
+        close_timeout_ms = 500
+        linger_ms = 100
+        write_coalescing_us = 200
+
+        [buffer]
+        initial = 1024
+        max = 65536
+        factor = 4
+        recv_high_water_mark = 4096
+
+        [ping]
+        long_ms = 30000
+        short_ms = 10000
+    "#).unwrap();
+
+    assert_eq!(config.handshake_timeout(), Some(Duration::from_secs(5)));
+    assert!(config.ping().is_some());
+
+    let buffer = config.buffer.as_ref().unwrap();
+    assert_eq!(buffer.initial, 1024);
+    assert_eq!(buffer.max, 65536);
+    assert_eq!(buffer.factor, Some(4));
+
+    config.conn_config();
+}
+
+#[test]
+fn invalid_buffer_bounds() {
+    let config: CobraConfig = toml::from_str(r#"
+        [buffer]
+        initial = 0
+        max = 0
+        factor = 1
+    "#).unwrap();
+
+    config.conn_config();
+}
+
+#[test]
+fn unknown_field() {
+    assert!(toml::from_str::<CobraConfig>("unknown = 1").is_err());
+}
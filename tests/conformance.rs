@@ -0,0 +1,26 @@
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::conformance;
+use cobra_rs::transport::tcp::{Conn, Listener};
+
+#[tokio::test]
+async fn local_vectors() {
+    conformance::validate_all().unwrap();
+}
+
+#[tokio::test]
+async fn echo_endpoint() {
+    const ADDR: &str = "127.0.0.1:5100";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+
+    tokio::spawn(async move {
+        let (conn, _) = listener.accept().await.unwrap();
+        for vector in conformance::vectors() {
+            let frame = conn.read(vector.kind).await.unwrap();
+            assert!(conn.write(frame).await.is_ok());
+        }
+    });
+
+    let conn = Conn::connect(ADDR).await.unwrap();
+    conformance::run_against(&conn).await.unwrap();
+}
@@ -0,0 +1,137 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::debug::{Direction, FrameHook, FrameRecorder, RecordingReader};
+use cobra_rs::mem::Frame;
+use cobra_rs::transport::tcp::{Conn, Listener};
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn recording_round_trip() {
+    let buffer = SharedBuffer::default();
+    let recorder = FrameRecorder::from_writer(buffer.clone()).unwrap().with_payload(true);
+
+    recorder.record(Direction::Outbound, &Frame::create(7, b"hello")).unwrap();
+    recorder.record(Direction::Inbound, &Frame::create(8, b"")).unwrap();
+
+    let recording = buffer.0.lock().unwrap().clone();
+    let frames = RecordingReader::new(&recording[..])
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].direction, Direction::Outbound);
+    assert_eq!(frames[0].kind, 7);
+    assert_eq!(frames[0].len, 5);
+    assert_eq!(&frames[0].to_frame().unwrap()[..], &Frame::create(7, b"hello")[..]);
+    assert_eq!(frames[1].direction, Direction::Inbound);
+    assert_eq!(frames[1].len, 0);
+    assert!(frames[0].timestamp <= frames[1].timestamp);
+}
+
+#[test]
+fn recording_without_payload() {
+    let buffer = SharedBuffer::default();
+    let recorder = FrameRecorder::from_writer(buffer.clone()).unwrap();
+
+    recorder.record(Direction::Outbound, &Frame::create(7, b"hello")).unwrap();
+
+    let recording = buffer.0.lock().unwrap().clone();
+    let frame = RecordingReader::new(&recording[..]).unwrap().next().unwrap().unwrap();
+
+    assert_eq!(frame.len, 5);
+    assert_eq!(frame.payload, None);
+    assert!(frame.to_frame().is_none());
+}
+
+#[test]
+fn reject_foreign_recording() {
+    assert!(RecordingReader::new(&b"not a recording"[..]).is_err());
+}
+
+#[tokio::test]
+async fn record_conn_frames() {
+    const ADDR: &str = "127.0.0.1:5400";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let buffer = SharedBuffer::default();
+    let recorder = FrameRecorder::from_writer(buffer.clone()).unwrap().with_payload(true);
+
+    let (client, server) = tokio::join!(
+        Builder::new().set_conn(client).record_frames(recorder).run(),
+        Builder::new().set_conn(server).run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    client.write(b"ping".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"ping");
+    server.write(b"pong".to_vec()).await.unwrap();
+    assert_eq!(client.read().await.unwrap(), b"pong");
+
+    let recording = buffer.0.lock().unwrap().clone();
+    let frames = RecordingReader::new(&recording[..])
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+
+    assert!(frames.iter().any(|frame| {
+        frame.direction == Direction::Outbound && frame.payload.as_deref() == Some(&b"ping"[..])
+    }));
+    assert!(frames.iter().any(|frame| {
+        frame.direction == Direction::Inbound && frame.payload.as_deref() == Some(&b"pong"[..])
+    }));
+}
+
+#[tokio::test]
+async fn frame_hooks() {
+    const ADDR: &str = "127.0.0.1:5764";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    type Seen = Arc<Mutex<Vec<(Direction, usize, Option<Vec<u8>>)>>>;
+    let (redacted, full): (Seen, Seen) = Default::default();
+    let hook = |seen: &Seen| {
+        let seen = seen.clone();
+        FrameHook::new(move |event| seen.lock().unwrap().push((event.direction, event.len, event.payload.map(<[u8]>::to_vec))))
+    };
+
+    let (client, server) = tokio::join!(
+        Builder::new().set_conn(client).on_frame(hook(&redacted)).on_frame(hook(&full).with_payload(true)).run(),
+        Builder::new().set_conn(server).run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    client.write(b"ping".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"ping");
+    server.write(b"pong!".to_vec()).await.unwrap();
+    assert_eq!(client.read().await.unwrap(), b"pong!");
+
+    let redacted = redacted.lock().unwrap();
+    assert!(redacted.iter().all(|(_, _, payload)| payload.is_none()));
+    assert!(redacted.contains(&(Direction::Outbound, 4, None)));
+    assert!(redacted.contains(&(Direction::Inbound, 5, None)));
+
+    let full = full.lock().unwrap();
+    assert_eq!(full.len(), redacted.len());
+    assert!(full.contains(&(Direction::Outbound, 4, Some(b"ping".to_vec()))));
+    assert!(full.contains(&(Direction::Inbound, 5, Some(b"pong!".to_vec()))));
+}
@@ -0,0 +1,96 @@
+use cobra_rs::ffi::*;
+use cobra_rs::mem::{Frame, WideFrame};
+use cobra_rs::sync::Kind;
+
+#[test]
+fn encode_decode() {
+    let body = [1_u8, 2, 3];
+    let mut out = [0_u8; 16];
+
+    let len = unsafe {
+        cobra_frame_encode(1, body.as_ptr(), body.len(), out.as_mut_ptr(), out.len())
+    };
+    assert_eq!(len, 6);
+    assert_eq!(out[..6], [0_u8, 4, 1, 1, 2, 3]);
+
+    let (mut kind, mut offset, mut body_len) = (0_u8, 0_usize, 0_usize);
+    let frame_len = unsafe {
+        cobra_frame_decode(out.as_ptr(), len as usize, &mut kind, &mut offset, &mut body_len)
+    };
+    assert_eq!(frame_len, 6);
+    assert_eq!(kind, 1);
+    assert_eq!(out[offset..offset + body_len], body);
+}
+
+#[test]
+fn decode_partial() {
+    let data = [0_u8, 4, 1, 1];
+    let (mut kind, mut offset, mut body_len) = (0_u8, 0_usize, 0_usize);
+
+    let result = unsafe {
+        cobra_frame_decode(data.as_ptr(), data.len(), &mut kind, &mut offset, &mut body_len)
+    };
+    assert_eq!(result, COBRA_OK);
+}
+
+#[test]
+fn encode_errors() {
+    let body = [1_u8, 2, 3];
+    let mut out = [0_u8; 4];
+
+    assert_eq!(unsafe {
+        cobra_frame_encode(1, body.as_ptr(), body.len(), out.as_mut_ptr(), out.len())
+    }, COBRA_ERR_BUFFER);
+    assert_eq!(unsafe {
+        cobra_frame_encode(1, std::ptr::null(), 3, out.as_mut_ptr(), out.len())
+    }, COBRA_ERR_NULL);
+    assert_eq!(unsafe {
+        cobra_frame_encode(1, body.as_ptr(), cobra_frame_max_body_len() + 1, out.as_mut_ptr(), out.len())
+    }, COBRA_ERR_TOO_LARGE);
+}
+
+#[test]
+fn wide_kind() {
+    let body = [0_u8, 1, 0, 0, 7];
+    let mut out = [0_u8; 16];
+
+    let len = unsafe {
+        cobra_frame_encode(COBRA_WIDE_KIND_U32, body.as_ptr(), body.len(), out.as_mut_ptr(), out.len())
+    };
+    let frame = WideFrame::from_frame(Frame::create(out[2], &out[3..len as usize])).unwrap();
+    assert_eq!(frame.kind(), 0x10000);
+    assert_eq!(frame.get_body()[..], [7]);
+}
+
+#[test]
+fn header_matches() {
+    let header = include_str!("../include/cobra.h");
+    let defines = [
+        ("COBRA_WIDE_KIND_U16", COBRA_WIDE_KIND_U16),
+        ("COBRA_WIDE_KIND_U32", COBRA_WIDE_KIND_U32),
+        ("COBRA_KIND_WIDTH_U8", COBRA_KIND_WIDTH_U8),
+        ("COBRA_KIND_WIDTH_U16", COBRA_KIND_WIDTH_U16),
+        ("COBRA_KIND_WIDTH_U32", COBRA_KIND_WIDTH_U32),
+        ("COBRA_CONTROL_KIND", COBRA_CONTROL_KIND),
+        ("COBRA_VERSION_KIND", COBRA_VERSION_KIND),
+        ("COBRA_PROTOCOL_VERSION", COBRA_PROTOCOL_VERSION),
+        ("COBRA_MIN_PROTOCOL_VERSION", COBRA_MIN_PROTOCOL_VERSION),
+        ("COBRA_FLATE_GZIP", COBRA_FLATE_GZIP),
+        ("COBRA_FLATE_ZLIB", COBRA_FLATE_ZLIB),
+        ("COBRA_FLATE_DEFLATE", COBRA_FLATE_DEFLATE),
+    ];
+
+    for (name, value) in defines {
+        assert!(header.contains(&format!("#define {} {}\n", name, value)), "{} differs", name);
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn flate_codes() {
+    use cobra_rs::providers::gzip_compression_provider::FlateFormat;
+
+    assert_eq!(FlateFormat::Gzip.code(), COBRA_FLATE_GZIP);
+    assert_eq!(FlateFormat::Zlib.code(), COBRA_FLATE_ZLIB);
+    assert_eq!(FlateFormat::Deflate.code(), COBRA_FLATE_DEFLATE);
+}
@@ -0,0 +1,185 @@
+#![cfg(cobra_loom)]
+
+//! Run with
+//!
+//! ```text
+//! LOOM_MAX_PREEMPTIONS=2 RUSTFLAGS="--cfg cobra_loom" cargo test --release --test loom_pool
+//! ```
+
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+use loom::future::block_on;
+use loom::thread;
+
+use cobra_rs::sync::{Pool, WriteError};
+
+#[test]
+fn write_read_accept() {
+    loom::model(|| {
+        let pool: Pool<i32> = Pool::new();
+        let reader = pool.clone();
+
+        let handle = thread::spawn(move || {
+            let value = block_on(reader.read()).unwrap();
+            assert_eq!(value.accept(), 1);
+        });
+
+        assert!(block_on(pool.write(1)).is_ok());
+        handle.join().unwrap();
+    });
+}
+
+#[test]
+fn write_read_reject() {
+    loom::model(|| {
+        let pool: Pool<i32> = Pool::new();
+        let reader = pool.clone();
+
+        let handle = thread::spawn(move || {
+            let value = block_on(reader.read()).unwrap();
+            block_on(value.reject());
+        });
+
+        match block_on(pool.write(1)) {
+            Err(WriteError::Rejected(value)) => assert_eq!(value, 1),
+            _ => panic!("value wasn't rejected"),
+        }
+        handle.join().unwrap();
+    });
+}
+
+#[test]
+fn close_races_write() {
+    loom::model(|| {
+        let pool: Pool<i32> = Pool::new();
+        let reader = pool.clone();
+        let closer = pool.clone();
+
+        let read = thread::spawn(move || {
+            block_on(reader.read()).map(|value| value.accept())
+        });
+        let close = thread::spawn(move || closer.close());
+
+        let written = block_on(pool.write(1));
+        close.join().unwrap();
+
+        // Value is either delivered or returned, never lost
+        match (read.join().unwrap(), written) {
+            (Some(value), Ok(())) => assert_eq!(value, 1),
+            (None, Err(WriteError::Closed(value))) => assert_eq!(value, 1),
+            _ => panic!("reader and writer disagree"),
+        }
+    });
+}
+
+#[test]
+fn close_races_accept() {
+    loom::model(|| {
+        let pool: Pool<i32> = Pool::new();
+        let reader = pool.clone();
+
+        let read = thread::spawn(move || {
+            let value = block_on(reader.read()).unwrap();
+            let closer = reader.clone();
+            let close = thread::spawn(move || closer.close());
+
+            value.accept();
+            close.join().unwrap();
+        });
+
+        // Taken value is answered even if the pool closes meanwhile
+        assert!(block_on(pool.write(1)).is_ok());
+        read.join().unwrap();
+    });
+}
+
+#[test]
+fn cancelled_write() {
+    loom::model(|| {
+        let pool: Pool<i32> = Pool::new();
+        let reader = pool.clone();
+
+        let read = thread::spawn(move || {
+            let mut seen = Vec::new();
+            while seen.last() != Some(&2) {
+                seen.push(block_on(reader.read()).unwrap().accept());
+            }
+            seen
+        });
+
+        {
+            let mut write = pin!(pool.write(1));
+            let mut cx = Context::from_waker(Waker::noop());
+            let _ = write.as_mut().poll(&mut cx);
+        }
+
+        // Cancelled value may reach the reader, but never blocks the next one
+        assert!(block_on(pool.write(2)).is_ok());
+        let seen = read.join().unwrap();
+        assert!(seen == [2] || seen == [1, 2], "{:?}", seen);
+    });
+}
+
+#[test]
+fn two_writers() {
+    loom::model(|| {
+        let pool: Pool<i32> = Pool::new();
+        let writer = pool.clone();
+        let reader = pool.clone();
+
+        let write = thread::spawn(move || block_on(writer.write(1)).is_ok());
+        let read = thread::spawn(move || {
+            let first = block_on(reader.read()).unwrap().accept();
+            let second = block_on(reader.read()).unwrap().accept();
+            first + second
+        });
+
+        assert!(block_on(pool.write(2)).is_ok());
+        assert!(write.join().unwrap());
+        assert_eq!(read.join().unwrap(), 3);
+    });
+}
+
+#[test]
+fn write_all_drain() {
+    loom::model(|| {
+        let pool: Pool<i32> = Pool::new();
+        let reader = pool.clone();
+
+        let read = thread::spawn(move || {
+            let mut seen = Vec::new();
+            while seen.len() < 3 {
+                seen.extend(block_on(reader.drain(3)).unwrap());
+            }
+            seen
+        });
+
+        assert!(block_on(pool.write_all(vec![1, 2, 3])).is_ok());
+        assert_eq!(read.join().unwrap(), [1, 2, 3]);
+    });
+}
+
+#[test]
+fn cancelled_read() {
+    loom::model(|| {
+        let pool: Pool<i32> = Pool::new();
+        let reader = pool.clone();
+
+        let read = thread::spawn(move || {
+            {
+                let mut read = pin!(reader.read());
+                let mut cx = Context::from_waker(Waker::noop());
+                if let Poll::Ready(value) = read.as_mut().poll(&mut cx) {
+                    // Value was already offered
+                    return value.map(|value| value.accept());
+                }
+            }
+            block_on(reader.read()).map(|value| value.accept())
+        });
+
+        assert!(block_on(pool.write(1)).is_ok());
+        assert_eq!(read.join().unwrap(), Some(1));
+    });
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::mem::{Frame, MemoryBudget};
+use cobra_rs::transport::tcp::{Conn, Listener};
+
+const KIND: u8 = 1;
+const FRAMES: usize = 256;
+const BODY_LEN: usize = 1024;
+
+// Budget is shared by all connections of the process, so the steps run sequentially
+#[tokio::test]
+async fn budget() {
+    const ADDR: &str = "127.0.0.1:5630";
+
+    let budget = MemoryBudget::global();
+    assert_eq!(budget.limit(), None);
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    // Received frames are reported until the application reads them
+    assert!(client.write(Frame::create(KIND, &[0; BODY_LEN])).await.is_ok());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let snapshot = budget.snapshot();
+    assert!(snapshot.received_frames >= BODY_LEN);
+    assert!(snapshot.receive_capacity > 0);
+    assert_eq!(snapshot.total(), snapshot.receive_buffers + snapshot.received_frames + snapshot.write_queues);
+
+    assert!(server.read(KIND).await.is_some());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(budget.snapshot().received_frames, 0);
+
+    // Reads are paused while the cap is exceeded
+    budget.set_limit(Some(BODY_LEN));
+    assert_eq!(budget.snapshot().limit, Some(BODY_LEN));
+
+    let writer = tokio::spawn(async move {
+        for _ in 0..FRAMES {
+            assert!(client.write(Frame::create(KIND, &[0; BODY_LEN])).await.is_ok());
+        }
+        client
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let paused = budget.snapshot();
+    assert!(paused.received_frames > 0);
+    assert!(paused.received_frames < FRAMES * BODY_LEN);
+
+    // Reading frames releases the budget and resumes socket reads
+    for _ in 0..FRAMES / 2 {
+        assert!(server.read(KIND).await.is_some());
+    }
+
+    // Removing the cap resumes them as well
+    budget.set_limit(None);
+    for _ in FRAMES / 2..FRAMES {
+        assert!(server.read(KIND).await.is_some());
+    }
+
+    let client = writer.await.unwrap();
+    client.close(0).await;
+    server.close(0).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let snapshot = budget.snapshot();
+    assert_eq!(snapshot.total(), 0);
+    assert_eq!(snapshot.receive_capacity, 0);
+}
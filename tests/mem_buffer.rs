@@ -1,8 +1,11 @@
 use std::ops::{Deref, DerefMut};
 
 use bytes::{BufMut, BytesMut};
+use proptest::collection::vec;
+use proptest::prelude::*;
 
 use cobra_rs::mem::*;
+use cobra_rs::sync::Kind;
 
 #[derive(Debug)]
 struct TestChunk {
@@ -218,3 +221,146 @@ async fn stress_test() {
         assert_eq!(buffer.try_read_chunk().unwrap().as_bytes(), v);
     }
 }
+
+// [0 100](1 .. 100) through 8 byte buffer
+#[tokio::test]
+async fn small_buffer_large_chunk() {
+    let mut buffer: ConcatBuf<TestChunk> = ConcatBuf::with_policy(GrowthPolicy::fixed(8));
+    let mut stream = vec![0_u8, 100];
+    stream.extend(1..=100);
+
+    let mut chunk = None;
+    for byte in stream {
+        assert!(buffer.remaining_limit() > 0);
+        buffer.put_u8(byte);
+        if let Some(value) = buffer.try_read_chunk() {
+            chunk = Some(value);
+        }
+    }
+
+    assert_eq!(chunk.unwrap().as_bytes(), (1..=100).collect::<Vec<u8>>());
+}
+
+#[test]
+#[should_panic(expected = "growth factor")]
+fn growth_factor_must_grow() {
+    GrowthPolicy::new(16, 64).set_factor(1);
+}
+
+#[tokio::test]
+async fn growth_policy() {
+    let policy = GrowthPolicy::new(16, 64).set_shrink_after(2);
+    let mut buffer: ConcatBuf<TestChunk> = ConcatBuf::with_policy(policy);
+
+    buffer.put_slice(&[0; 16]);
+    buffer.adapt_capacity();
+    assert_eq!(buffer.limit(), 32);
+
+    buffer.put_slice(&[0; 16]);
+    buffer.adapt_capacity();
+    buffer.put_slice(&[0; 32]);
+    buffer.adapt_capacity();
+    assert_eq!(buffer.limit(), 64);
+
+    buffer.clear();
+    buffer.adapt_capacity();
+    buffer.adapt_capacity();
+    assert_eq!(buffer.limit(), 32);
+}
+
+// Partial header waiting for the next read doesn't grow the buffer
+#[test]
+fn partial_header_keeps_capacity() {
+    let mut buffer: ConcatBuf<Frame> = ConcatBuf::with_policy(GrowthPolicy::fixed(16));
+    buffer.put_u8(0);
+    let capacity = buffer.capacity();
+
+    for _ in 0..64 {
+        assert!(buffer.try_read_chunk().is_none());
+    }
+    assert_eq!(buffer.capacity(), capacity);
+}
+
+#[test]
+fn overfilled_buffer_grows() {
+    let mut buffer: ConcatBuf<Frame> = ConcatBuf::with_policy(GrowthPolicy::new(16, 64));
+    buffer.put_slice(&[1; 100]);
+    buffer.adapt_capacity();
+    assert_eq!(buffer.limit(), 32);
+}
+
+// Feeds the stream in reads of `cuts` bytes (cycled) like the transport
+// reader does and restores frames after every read
+fn reassemble(mut buffer: ConcatBuf<Frame>, stream: &[u8], cuts: &[usize]) -> Result<Vec<(u8, Vec<u8>)>, TestCaseError> {
+    let mut frames = Vec::new();
+    let mut cuts = cuts.iter().cycle();
+    let mut offset = 0;
+
+    while offset < stream.len() {
+        let len = (*cuts.next().unwrap())
+            .min(buffer.remaining_limit())
+            .min(stream.len() - offset);
+        prop_assert!(len > 0, "buffer has no room for the next read");
+
+        buffer.put_slice(&stream[offset..offset + len]);
+        offset += len;
+        buffer.adapt_capacity();
+
+        while let Some(frame) = buffer.try_read_chunk() {
+            frames.push((frame.kind(), frame.get_body().to_vec()));
+        }
+    }
+
+    prop_assert_eq!(buffer.buffered(), 0);
+    Ok(frames)
+}
+
+fn frames(max_body_len: usize) -> impl Strategy<Value=Vec<(u8, Vec<u8>)>> {
+    vec((any::<u8>(), vec(any::<u8>(), 0..=max_body_len)), 0..24)
+}
+
+fn stream(frames: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    frames.iter()
+        .flat_map(|(kind, body)| Frame::create(*kind, body).to_vec())
+        .collect()
+}
+
+proptest! {
+    #[test]
+    fn reassemble_with_default_capacity(frames in frames(2048), cuts in vec(1..4096_usize, 1..16)) {
+        let restored = reassemble(ConcatBuf::default(), &stream(&frames), &cuts)?;
+        prop_assert_eq!(restored, frames);
+    }
+
+    #[test]
+    fn reassemble_through_growing_buffer(frames in frames(2048),
+                                         cuts in vec(1..512_usize, 1..16),
+                                         initial in 1..64_usize,
+                                         max in 64..1024_usize) {
+        let buffer = ConcatBuf::with_policy(GrowthPolicy::new(initial, max).set_shrink_after(1));
+        let restored = reassemble(buffer, &stream(&frames), &cuts)?;
+        prop_assert_eq!(restored, frames);
+    }
+
+    #[test]
+    fn reassemble_byte_by_byte(frames in frames(64)) {
+        let restored = reassemble(ConcatBuf::with_policy(GrowthPolicy::fixed(4)), &stream(&frames), &[1])?;
+        prop_assert_eq!(restored, frames);
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    // Frames of the greatest length split anywhere, including inside the header
+    #[test]
+    fn reassemble_max_len_frames(kinds in vec(any::<u8>(), 1..4),
+                                 cuts in vec(1..70_000_usize, 1..8)) {
+        // Length field counts the kind byte and is below max_body_len()
+        let frames: Vec<_> = kinds.into_iter()
+            .map(|kind| (kind, vec![kind; Frame::max_body_len() - 2]))
+            .collect();
+        let restored = reassemble(ConcatBuf::default(), &stream(&frames), &cuts)?;
+        prop_assert_eq!(restored, frames);
+    }
+}
@@ -1,4 +1,9 @@
-use cobra_rs::mem::Frame;
+use std::io::{self, Write};
+
+use bytes::BufMut;
+
+use cobra_rs::mem::{ConcatBuf, Frame, KindWidth, WideFrame, MAX_BODY_LEN, WIDE_KIND_U16, WIDE_KIND_U32};
+use cobra_rs::sync::Kind;
 
 #[tokio::test]
 async fn simple_frame() {
@@ -8,3 +13,68 @@ async fn simple_frame() {
     assert_eq!(frame.to_vec(), vec![0_u8, 4, 1, 1, 2, 3]);
     assert_eq!(frame.get_body().to_vec(), vec![1_u8, 2, 3]);
 }
+
+#[test]
+fn frame_builder() {
+    let mut builder = Frame::builder(7, 0);
+    builder.put_u16(0);
+    builder.write_all(&[1, 2, 3]).unwrap();
+    builder.body_mut()[..2].copy_from_slice(&3_u16.to_be_bytes());
+
+    let frame = builder.finish();
+    assert_eq!(frame.kind(), 7);
+    assert_eq!(frame.to_vec(), Frame::create(7, &[0, 3, 1, 2, 3]).to_vec());
+
+    let frame = Frame::builder(1, 10).finish();
+    assert_eq!(frame.to_vec(), vec![0_u8, 1, 1]);
+}
+
+#[test]
+fn frame_builder_limit() {
+    let mut builder = Frame::builder(1, 0);
+    builder.write_all(&vec![0; MAX_BODY_LEN]).unwrap();
+    assert_eq!(builder.remaining_mut(), 0);
+    assert_eq!(builder.write_all(&[0]).unwrap_err().kind(), io::ErrorKind::WriteZero);
+
+    let frame = builder.finish();
+    assert_eq!(&frame[..2], &[0xFF, 0xFF]);
+}
+
+#[test]
+fn wide_frame() {
+    let frame = WideFrame::create(300, &[1, 2, 3]);
+    assert_eq!(frame.kind(), 300);
+    assert_eq!(frame.to_vec(), vec![0_u8, 6, WIDE_KIND_U16, 1, 44, 1, 2, 3]);
+    assert_eq!(frame.get_body().to_vec(), vec![1_u8, 2, 3]);
+
+    let frame = WideFrame::create(70000, &[1]);
+    assert_eq!(frame.to_vec(), vec![0_u8, 6, WIDE_KIND_U32, 0, 1, 17, 112, 1]);
+
+    let frame = WideFrame::from_frame(frame.into_frame()).unwrap();
+    assert_eq!(frame.kind(), 70000);
+    assert_eq!(frame.get_body().to_vec(), vec![1_u8]);
+
+    assert!(WideFrame::from_frame(Frame::create(1, &[0, 1])).is_none());
+    assert!(WideFrame::from_frame(Frame::create(WIDE_KIND_U32, &[0, 1])).is_none());
+}
+
+#[test]
+fn kind_width() {
+    assert_eq!(KindWidth::of(255), KindWidth::U8);
+    assert_eq!(KindWidth::of(256), KindWidth::U16);
+    assert_eq!(KindWidth::of(65536), KindWidth::U32);
+    assert_eq!(KindWidth::U16.max_kind(), 65535);
+    assert!(KindWidth::U8 < KindWidth::U32);
+}
+
+// [0 0] has no kind and is skipped, [0 2](7 1) is read
+#[test]
+fn empty_frame_skipped() {
+    let mut buffer: ConcatBuf<Frame> = ConcatBuf::default();
+    buffer.extend_from_slice(&[0, 0, 0, 0, 0, 2, 7, 1]);
+
+    let frame = buffer.try_read_chunk().unwrap();
+    assert_eq!(frame.kind(), 7);
+    assert_eq!(frame.get_body().to_vec(), vec![1]);
+    assert!(buffer.try_read_chunk().is_none());
+}
@@ -0,0 +1,174 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, UdpSocket};
+
+use cobra_rs::net::PortMapper;
+use cobra_rs::transport::tcp::Listener;
+
+const EXTERNAL_IP: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 7);
+const RENEWED_IP: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 8);
+
+// Answers NAT-PMP requests with the public address, returns it along with
+// suggested external ports and lifetimes of received mapping requests
+async fn fake_gateway(addr: &str) -> (Arc<Mutex<Vec<(u16, u32)>>>, Arc<Mutex<Ipv4Addr>>) {
+    let socket = UdpSocket::bind(addr).await.unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let external_ip = Arc::new(Mutex::new(EXTERNAL_IP));
+    let received = requests.clone();
+    let announced = external_ip.clone();
+
+    tokio::spawn(async move {
+        let mut request = [0; 12];
+        loop {
+            let (_, peer) = socket.recv_from(&mut request).await.unwrap();
+            let mut response = vec![0, request[1] + 128, 0, 0, 0, 0, 0, 1];
+
+            if request[1] == 0 {
+                response.extend_from_slice(&announced.lock().unwrap().octets());
+            } else {
+                let internal = u16::from_be_bytes([request[4], request[5]]);
+                let suggested = u16::from_be_bytes([request[6], request[7]]);
+                let lifetime = u32::from_be_bytes([request[8], request[9], request[10], request[11]]);
+                received.lock().unwrap().push((suggested, lifetime));
+
+                response.extend_from_slice(&request[4..6]);
+                response.extend_from_slice(&(internal + 1000).to_be_bytes());
+                response.extend_from_slice(&lifetime.to_be_bytes());
+            }
+            socket.send_to(&response, peer).await.unwrap();
+        }
+    });
+
+    (requests, external_ip)
+}
+
+const DESCRIPTION: &str = "<root><device><serviceList>\
+    <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+    <controlURL>/l3f</controlURL></service>\
+    <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+    <controlURL>/ctl</controlURL></service>\
+    </serviceList></device></root>";
+
+// Serves UPnP IGD description and control, returns received actions with their lease durations
+async fn fake_igd(addr: &str) -> Arc<Mutex<Vec<String>>> {
+    let listener = TcpListener::bind(addr).await.unwrap();
+    let actions = Arc::new(Mutex::new(Vec::new()));
+    let received = actions.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while !String::from_utf8_lossy(&request).contains("</s:Envelope>")
+                && !String::from_utf8_lossy(&request).starts_with("GET") {
+                let len = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..len]);
+            }
+            let request = String::from_utf8(request).unwrap();
+
+            let response = if request.starts_with("GET /desc.xml") {
+                // Description is chunked like some routers do
+                let (first, second) = DESCRIPTION.split_at(40);
+                format!(
+                    "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                    first.len(), first, second.len(), second,
+                )
+            } else if request.starts_with("POST /ctl") {
+                let action = request.split("WANIPConnection:1#").nth(1).unwrap().split('"').next().unwrap();
+                let lease = request.split("<NewLeaseDuration>").nth(1).map(|rest| rest.split('<').next().unwrap());
+                received.lock().unwrap().push(format!("{} {}", action, lease.unwrap_or("")).trim().to_string());
+
+                let body = format!("<s:Envelope><s:Body><u:{}Response>\
+                    <NewExternalIPAddress>{}</NewExternalIPAddress>\
+                    </u:{}Response></s:Body></s:Envelope>", action, EXTERNAL_IP, action);
+                format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)
+            } else {
+                "HTTP/1.1 404 Not Found\r\n\r\n".to_string()
+            };
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+
+    actions
+}
+
+#[tokio::test]
+async fn map_port() {
+    let (requests, _) = fake_gateway("127.0.0.1:5510").await;
+    let mapper = PortMapper::new("127.0.0.1:5510".parse().unwrap());
+
+    assert_eq!(mapper.external_ip().await.unwrap(), EXTERNAL_IP);
+
+    let mapping = mapper.map(4000).await.unwrap();
+    assert_eq!(mapping.internal_port(), 4000);
+    assert_eq!(mapping.external_addr(), SocketAddr::from((EXTERNAL_IP, 5000)));
+    assert_eq!(*requests.lock().unwrap(), vec![(4000, 7200)]);
+}
+
+#[tokio::test]
+async fn renew_and_delete_mapping() {
+    let (requests, external_ip) = fake_gateway("127.0.0.1:5511").await;
+    let mapper = PortMapper::new("127.0.0.1:5511".parse().unwrap())
+        .set_lifetime(Duration::from_millis(500));
+
+    let mapping = mapper.map(4000).await.unwrap();
+    *external_ip.lock().unwrap() = RENEWED_IP;
+    tokio::time::sleep(Duration::from_millis(700)).await;
+    assert_eq!(*requests.lock().unwrap(), vec![(4000, 1), (4000, 1)]);
+    assert_eq!(mapping.external_addr(), SocketAddr::from((RENEWED_IP, 5000)));
+
+    // Deletion suggests zero external port
+    drop(mapping);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(*requests.lock().unwrap(), vec![(4000, 1), (4000, 1), (0, 0)]);
+}
+
+#[tokio::test]
+async fn map_listener() {
+    fake_gateway("127.0.0.1:5512").await;
+    let listener = Listener::listen("127.0.0.1:5513").await.unwrap();
+    let mapper = PortMapper::new("127.0.0.1:5512".parse().unwrap());
+
+    let mappings = mapper.map_listener(&listener).await.unwrap();
+
+    assert_eq!(mappings.len(), 1);
+    assert_eq!(mappings[0].external_addr(), SocketAddr::from((EXTERNAL_IP, 6513)));
+}
+
+#[tokio::test]
+async fn unreachable_gateway() {
+    let mapper = PortMapper::new("127.0.0.1:5514".parse().unwrap());
+
+    assert!(mapper.external_ip().await.is_err());
+}
+
+#[tokio::test]
+async fn upnp_map_port() {
+    let actions = fake_igd("127.0.0.1:5515").await;
+    let mapper = PortMapper::upnp_at("http://127.0.0.1:5515/desc.xml").await.unwrap();
+
+    assert_eq!(mapper.external_ip().await.unwrap(), EXTERNAL_IP);
+
+    let mapping = mapper.map(4000).await.unwrap();
+    assert_eq!(mapping.external_addr(), SocketAddr::from((EXTERNAL_IP, 4000)));
+
+    drop(mapping);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(*actions.lock().unwrap(), vec![
+        "GetExternalIPAddress",
+        "GetExternalIPAddress",
+        "AddPortMapping 7200",
+        "DeletePortMapping",
+    ]);
+}
+
+#[tokio::test]
+async fn upnp_gateway_without_description() {
+    fake_igd("127.0.0.1:5516").await;
+
+    assert!(PortMapper::upnp_at("http://127.0.0.1:5516/missing.xml").await.is_err());
+}
@@ -0,0 +1,63 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::p2p::{self, P2pError, PunchConfig};
+
+#[tokio::test]
+async fn connect_peers() {
+    let first: SocketAddr = "127.0.0.1:5500".parse().unwrap();
+    let second: SocketAddr = "127.0.0.1:5501".parse().unwrap();
+    let config = PunchConfig::new().set_retry_interval(Duration::from_millis(20));
+
+    let (first, second) = tokio::join!(
+        p2p::connect(first, second, config.clone(), Builder::new()),
+        p2p::connect(second, first, config, Builder::new()),
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+
+    first.write(b"hello".to_vec()).await.unwrap();
+    assert_eq!(second.read().await.unwrap(), b"hello");
+    second.write(b"world".to_vec()).await.unwrap();
+    assert_eq!(first.read().await.unwrap(), b"world");
+}
+
+#[tokio::test]
+async fn unreachable_peer() {
+    let local: SocketAddr = "127.0.0.1:5502".parse().unwrap();
+    let peer: SocketAddr = "127.0.0.1:5503".parse().unwrap();
+    let config = PunchConfig::new()
+        .set_timeout(Duration::from_millis(100))
+        .set_retry_interval(Duration::from_millis(20));
+
+    match p2p::connect(local, peer, config, Builder::new()).await {
+        Err(P2pError::Io(error)) => assert_eq!(error.kind(), std::io::ErrorKind::TimedOut),
+        _ => panic!("unreachable peer was connected"),
+    }
+}
+
+#[tokio::test]
+async fn udp_hole_punching() {
+    let first: SocketAddr = "127.0.0.1:5504".parse().unwrap();
+    let second: SocketAddr = "127.0.0.1:5505".parse().unwrap();
+    let config = PunchConfig::new()
+        .set_tcp(false)
+        .set_retry_interval(Duration::from_millis(20));
+
+    let (first, second) = tokio::join!(
+        p2p::connect(first, second, config.clone(), Builder::new()),
+        p2p::connect(second, first, config, Builder::new()),
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+
+    // Takes more segments than fit into the window
+    let message = vec![7; 60000];
+    for _ in 0..4 {
+        first.write(message.clone()).await.unwrap();
+    }
+    for _ in 0..4 {
+        assert_eq!(second.read().await.unwrap(), message);
+    }
+    second.write(b"world".to_vec()).await.unwrap();
+    assert_eq!(first.read().await.unwrap(), b"world");
+}
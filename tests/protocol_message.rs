@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use cobra_rs::builder::kind_conn::close_code::CloseCode;
+use cobra_rs::builder::stats::{CompressionStats, ConnStats, TransportStats};
+use cobra_rs::builder::version::{Features, PROTOCOL_VERSION};
+use cobra_rs::mem::KindWidth;
+use cobra_rs::protocol::{ControlMessage, Encoding};
+
+fn stats(rtt: Option<Duration>) -> ConnStats {
+    ConnStats {
+        protocol_version: PROTOCOL_VERSION,
+        features: Features::of(PROTOCOL_VERSION).unwrap(),
+        kind_width: KindWidth::U16,
+        idle: Duration::from_millis(1500),
+        open_kinds: 3,
+        transport: TransportStats { queued_bytes: 70000, queued_frames: 2, pending_writes: 1, expired_frames: 0, rtt },
+        compression: None,
+    }
+}
+
+fn messages() -> Vec<ControlMessage> {
+    vec![
+        ControlMessage::KindReleased { kind: 13 },
+        ControlMessage::KindReclaimed { kind: 253 },
+        ControlMessage::KindClosed { kind: 40 },
+        ControlMessage::ChannelRequest { name: "chat".to_string() },
+        ControlMessage::ChannelOpen { kind: 250, name: "видео".to_string() },
+        ControlMessage::ChannelExhausted { name: String::new() },
+        ControlMessage::StatsRequest { id: 0 },
+        ControlMessage::StatsResponse { id: u32::MAX, stats: stats(Some(Duration::from_micros(250))) },
+        ControlMessage::StatsResponse { id: 1, stats: stats(None) },
+        ControlMessage::StatsRefused { id: 70000 },
+        ControlMessage::HandshakeExtensions { extensions: BTreeMap::new() },
+        ControlMessage::HandshakeExtensions {
+            extensions: BTreeMap::from([("zip.level".to_string(), vec![9]), ("empty".to_string(), Vec::new())]),
+        },
+        ControlMessage::Close { code: CloseCode::GoingAway, reason: "bye".to_string() },
+        ControlMessage::Close { code: CloseCode::Application(200), reason: String::new() },
+        ControlMessage::ShutdownWrite,
+    ]
+}
+
+#[test]
+fn round_trip() {
+    for encoding in [Encoding::Fixed, Encoding::Cbor] {
+        for message in messages() {
+            assert_eq!(ControlMessage::decode(&message.encode(encoding), encoding), Some(message));
+        }
+    }
+}
+
+#[test]
+fn compression_stats() {
+    let mut with_compression = stats(None);
+    with_compression.transport.expired_frames = 5;
+    with_compression.compression = Some(CompressionStats {
+        input_bytes: 1 << 40,
+        output_bytes: 1000,
+        compress_time: Duration::from_micros(1500),
+        decompress_time: Duration::ZERO,
+        enabled: false,
+    });
+    let message = ControlMessage::StatsResponse { id: 2, stats: with_compression };
+
+    assert_eq!(ControlMessage::decode(&message.encode(Encoding::Cbor), Encoding::Cbor), Some(message.clone()));
+
+    // Fixed layout has no room for compression and expired frames
+    match ControlMessage::decode(&message.encode(Encoding::Fixed), Encoding::Fixed) {
+        Some(ControlMessage::StatsResponse { stats, .. }) => assert_eq!(stats, self::stats(None)),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn encoding_of_version() {
+    assert_eq!(Encoding::of(Features::of(6).unwrap()), Encoding::Fixed);
+    assert_eq!(Encoding::of(Features::of(7).unwrap()), Encoding::Cbor);
+}
+
+#[test]
+fn fixed_layout() {
+    assert_eq!(ControlMessage::KindReleased { kind: 20 }.encode(Encoding::Fixed), vec![0, 20]);
+    assert_eq!(ControlMessage::ChannelOpen { kind: 250, name: "a".to_string() }.encode(Encoding::Fixed), vec![3, 250, b'a']);
+    assert_eq!(ControlMessage::StatsRequest { id: 1 }.encode(Encoding::Fixed), vec![5, 0, 0, 0, 1]);
+}
+
+#[test]
+fn cbor_layout() {
+    // {0: 0, 1: 20}
+    assert_eq!(ControlMessage::KindReleased { kind: 20 }.encode(Encoding::Cbor), vec![0xA2, 0x00, 0x00, 0x01, 0x14]);
+    // {0: 2, 2: "a"}
+    assert_eq!(ControlMessage::ChannelRequest { name: "a".to_string() }.encode(Encoding::Cbor), vec![0xA2, 0x00, 0x02, 0x02, 0x61, b'a']);
+    // {0: 8, 5: {"a": h'01'}}
+    let extensions = BTreeMap::from([("a".to_string(), vec![1])]);
+    assert_eq!(
+        ControlMessage::HandshakeExtensions { extensions }.encode(Encoding::Cbor),
+        vec![0xA2, 0x00, 0x08, 0x05, 0xA1, 0x61, b'a', 0x41, 0x01],
+    );
+}
+
+#[test]
+fn unknown_fields_skipped() {
+    // {9: [1, {"x": h'00'}], 0: 3, 1: 250, 2: "a", 10: tag(1, -5), 11: 1.5}
+    let message = [
+        0xA6,
+        0x09, 0x82, 0x01, 0xA1, 0x61, b'x', 0x41, 0x00,
+        0x00, 0x03,
+        0x01, 0x18, 0xFA,
+        0x02, 0x61, b'a',
+        0x0A, 0xC1, 0x24,
+        0x0B, 0xF9, 0x3E, 0x00,
+    ];
+    assert_eq!(
+        ControlMessage::decode(&message, Encoding::Cbor),
+        Some(ControlMessage::ChannelOpen { kind: 250, name: "a".to_string() }),
+    );
+}
+
+#[test]
+fn malformed() {
+    let malformed: [&[u8]; 9] = [
+        // Unknown type
+        &[0xA1, 0x00, 0x18, 0x63],
+        // Missing kind
+        &[0xA1, 0x00, 0x00],
+        // Kind doesn't fit a byte
+        &[0xA2, 0x00, 0x00, 0x01, 0x19, 0x01, 0x00],
+        // Text isn't UTF-8
+        &[0xA2, 0x00, 0x02, 0x02, 0x61, 0xFF],
+        // Text is longer than the message
+        &[0xA2, 0x00, 0x02, 0x02, 0x7B, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+        // Map has more pairs than bytes
+        &[0xA2, 0x00, 0x05, 0x09, 0xBA, 0xFF, 0xFF, 0xFF, 0xFF],
+        // Indefinite length
+        &[0xBF, 0x00, 0x05, 0x03, 0x01, 0xFF],
+        // Not a map
+        &[0x80],
+        &[],
+    ];
+    for message in malformed {
+        assert_eq!(ControlMessage::decode(message, Encoding::Cbor), None, "{:?}", message);
+    }
+
+    // Too deeply nested unknown field
+    let mut nested = vec![0xA2, 0x00, 0x05, 0x09];
+    nested.extend_from_slice(&[0x81; 64]);
+    nested.push(0x00);
+    assert_eq!(ControlMessage::decode(&nested, Encoding::Cbor), None);
+
+    assert_eq!(ControlMessage::decode(&[5, 0, 0, 1], Encoding::Fixed), None);
+    assert_eq!(ControlMessage::decode(&[2, 0xFF], Encoding::Fixed), None);
+    assert_eq!(ControlMessage::decode(&[], Encoding::Fixed), None);
+}
@@ -0,0 +1,109 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use cobra_rs::builder::builder::{Builder, CompressionProvider};
+use cobra_rs::builder::context::Context;
+use cobra_rs::providers::adaptive_compression::{AdaptiveCompression, CompressionToggled};
+use cobra_rs::transport::tcp::{Conn, Listener};
+
+// Packages of one repeated byte shrink to five bytes, others are kept as is
+struct RepeatCompression;
+
+#[async_trait]
+impl CompressionProvider for RepeatCompression {
+    async fn init(&self, _: Context) {}
+
+    fn compress(&self, frame: Vec<u8>) -> Vec<u8> {
+        match frame.first() {
+            Some(&byte) if frame.iter().all(|&other| other == byte) => {
+                let mut package = vec![1, byte];
+                package.extend_from_slice(&(frame.len() as u32 - 1).to_be_bytes()[1..]);
+                package
+            }
+            _ => [&[0], &frame[..]].concat(),
+        }
+    }
+
+    fn decompress(&self, frame: Vec<u8>) -> Vec<u8> {
+        match frame[..] {
+            [1, byte, a, b, c] => vec![byte; u32::from_be_bytes([0, a, b, c]) as usize + 1],
+            _ => frame[1..].to_vec(),
+        }
+    }
+}
+
+fn events(provider: AdaptiveCompression<RepeatCompression>) -> (AdaptiveCompression<RepeatCompression>, Arc<Mutex<Vec<bool>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let handler_events = events.clone();
+    let provider = provider.on_change(move |event: &CompressionToggled| handler_events.lock().unwrap().push(event.enabled));
+    (provider, events)
+}
+
+#[test]
+fn disable_incompressible() {
+    let (provider, events) = events(AdaptiveCompression::new(RepeatCompression).window(4).backoff(3));
+    let package: Vec<u8> = (0..100).collect();
+
+    for _ in 0..4 {
+        let compressed = provider.compress(package.clone());
+        assert_eq!(compressed[0], 1);
+        assert_eq!(provider.decompress(compressed), package);
+    }
+    assert_eq!(*events.lock().unwrap(), [false]);
+    assert!(!provider.stats().unwrap().enabled);
+
+    for _ in 0..3 {
+        let stored = provider.compress(package.clone());
+        assert_eq!(stored, [&[0], &package[..]].concat());
+        assert_eq!(provider.decompress(stored), package);
+    }
+    assert_eq!(*events.lock().unwrap(), [false, true]);
+
+    let stats = provider.stats().unwrap();
+    assert!(stats.enabled);
+    assert_eq!(stats.input_bytes, 700);
+    assert_eq!(stats.output_bytes, 4 * 102 + 3 * 101);
+    assert!(stats.ratio() > 1.0);
+}
+
+#[test]
+fn keep_compressible() {
+    let (provider, events) = events(AdaptiveCompression::new(RepeatCompression).window(4));
+
+    for _ in 0..100 {
+        assert_eq!(provider.compress(vec![7; 1000])[0], 1);
+    }
+    assert!(events.lock().unwrap().is_empty());
+
+    let stats = provider.stats().unwrap();
+    assert!(stats.enabled);
+    assert_eq!(stats.ratio(), 0.006);
+}
+
+#[tokio::test]
+async fn stats_of_connection() {
+    let addr = "127.0.0.1:5763";
+    let listener = Listener::listen(addr).await.unwrap();
+    let client_conn = Conn::connect(addr).await.unwrap();
+    let (server_conn, _) = listener.accept().await.unwrap();
+
+    let (client, server) = tokio::join!(
+        Builder::new().set_conn(client_conn).set_compression(AdaptiveCompression::new(RepeatCompression).window(2)).expose_stats(true).run(),
+        Builder::new().set_conn(server_conn).set_compression(AdaptiveCompression::new(RepeatCompression).window(2)).run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    let incompressible: Vec<u8> = (0..=255).collect();
+    for _ in 0..3 {
+        client.write(incompressible.clone()).await.unwrap();
+        assert_eq!(server.read().await.unwrap(), incompressible);
+    }
+
+    let compression = client.stats().compression.unwrap();
+    assert!(!compression.enabled);
+
+    let peer = server.peer_stats(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(peer.compression.map(|compression| compression.enabled), Some(false));
+}
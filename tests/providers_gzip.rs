@@ -0,0 +1,60 @@
+#![cfg(feature = "gzip")]
+
+mod common;
+
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+use cobra_rs::builder::builder::{Builder, CompressionProvider};
+use cobra_rs::providers::gzip_compression_provider::{FlateFormat, GzipCompressionProvider};
+
+use common::pair;
+
+#[test]
+fn compress_without_handshake() {
+    let package = b"hello hello hello hello".to_vec();
+
+    for format in FlateFormat::ALL {
+        let provider = GzipCompressionProvider::new(6).formats(&[format]);
+        assert_eq!(provider.decompress(provider.compress(package.clone())), package);
+        assert_eq!(FlateFormat::from_code(format.code()), Some(format));
+    }
+}
+
+#[test]
+fn gzip_readable() {
+    let provider = GzipCompressionProvider::new(6).formats(&[FlateFormat::Gzip]);
+    let compressed = provider.compress(b"hello gzip".to_vec());
+
+    let mut package = Vec::new();
+    GzDecoder::new(&compressed[..]).read_to_end(&mut package).unwrap();
+    assert_eq!(package, b"hello gzip");
+}
+
+#[tokio::test]
+async fn negotiate_common_format() {
+    let (client, server) = pair(
+        "127.0.0.1:5761",
+        Builder::new().set_compression(GzipCompressionProvider::new(6).formats(&[FlateFormat::Deflate, FlateFormat::Zlib])),
+        Builder::new().set_compression(GzipCompressionProvider::new(1)),
+    ).await;
+
+    assert_eq!(client.extensions().get::<FlateFormat>(), Some(FlateFormat::Zlib));
+    assert_eq!(server.extensions().get::<FlateFormat>(), Some(FlateFormat::Zlib));
+
+    client.write(vec![7; 1000]).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), vec![7; 1000]);
+}
+
+#[tokio::test]
+async fn no_common_format() {
+    let (client, server) = pair(
+        "127.0.0.1:5762",
+        Builder::new().set_compression(GzipCompressionProvider::new(6).formats(&[FlateFormat::Gzip])),
+        Builder::new().set_compression(GzipCompressionProvider::new(6).formats(&[FlateFormat::Deflate])),
+    ).await;
+
+    assert!(client.read().await.is_none());
+    assert!(server.read().await.is_none());
+}
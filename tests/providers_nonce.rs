@@ -0,0 +1,149 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use cobra_rs::builder::builder::{BuildError, Builder, DecryptError, EncryptionProvider};
+use cobra_rs::builder::context::Context;
+use cobra_rs::builder::kind_conn::close_code::ENCRYPTION_ERROR;
+use cobra_rs::providers::nonce::{NonceCounter, ReplayWindow, REPLAY_WINDOW_LEN};
+use cobra_rs::transport::tcp::{Conn, Listener};
+
+// Prefixes every package with its nonce, reuses nonce 0 if `repeat` is set
+struct NonceEncryption {
+    sent: NonceCounter,
+    received: ReplayWindow,
+    repeat: Arc<AtomicBool>,
+}
+
+impl NonceEncryption {
+    fn new() -> (Self, Arc<AtomicBool>) {
+        let repeat = Arc::new(AtomicBool::new(false));
+        let provider = NonceEncryption {
+            sent: NonceCounter::new(),
+            received: ReplayWindow::new(),
+            repeat: repeat.clone(),
+        };
+        (provider, repeat)
+    }
+}
+
+#[async_trait]
+impl EncryptionProvider for NonceEncryption {
+    async fn init(&self, _context: Context) -> Result<(), BuildError> {
+        Ok(())
+    }
+
+    fn encrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        let nonce = if self.repeat.load(Ordering::SeqCst) { 0 } else { self.sent.next().unwrap() };
+        nonce.to_be_bytes().iter().copied().chain(frame).collect()
+    }
+
+    fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame[8..].to_vec()
+    }
+
+    fn try_decrypt(&self, frame: Vec<u8>) -> Result<Vec<u8>, DecryptError> {
+        if frame.len() < 8 {
+            return Err(DecryptError::Corrupted);
+        }
+        let mut nonce = [0; 8];
+        nonce.copy_from_slice(&frame[..8]);
+        self.received.check(u64::from_be_bytes(nonce))?;
+
+        Ok(self.decrypt(frame))
+    }
+}
+
+#[test]
+fn nonce_counter() {
+    let counter = NonceCounter::new();
+
+    assert_eq!(counter.next(), Some(0));
+    assert_eq!(counter.next(), Some(1));
+
+    counter.reset();
+    assert_eq!(counter.next(), Some(0));
+}
+
+#[test]
+fn replay_window_in_order() {
+    let window = ReplayWindow::new();
+
+    for nonce in 0..1000 {
+        assert_eq!(window.check(nonce), Ok(()));
+    }
+    assert_eq!(window.check(999), Err(DecryptError::Replayed));
+}
+
+#[test]
+fn replay_window_out_of_order() {
+    let window = ReplayWindow::new();
+
+    assert_eq!(window.check(10), Ok(()));
+    assert_eq!(window.check(7), Ok(()));
+    assert_eq!(window.check(12), Ok(()));
+    assert_eq!(window.check(8), Ok(()));
+
+    assert_eq!(window.check(7), Err(DecryptError::Replayed));
+    assert_eq!(window.check(10), Err(DecryptError::Replayed));
+    assert_eq!(window.check(9), Ok(()));
+}
+
+#[test]
+fn replay_window_too_old() {
+    let window = ReplayWindow::new();
+
+    assert_eq!(window.check(REPLAY_WINDOW_LEN), Ok(()));
+    assert_eq!(window.check(1), Ok(()));
+    assert_eq!(window.check(0), Err(DecryptError::TooOld));
+
+    // Jump beyond the window forgets everything behind it
+    assert_eq!(window.check(10 * REPLAY_WINDOW_LEN), Ok(()));
+    assert_eq!(window.check(REPLAY_WINDOW_LEN), Err(DecryptError::TooOld));
+    assert_eq!(window.check(10 * REPLAY_WINDOW_LEN - 1), Ok(()));
+}
+
+#[test]
+fn replay_window_reset() {
+    let window = ReplayWindow::new();
+
+    assert_eq!(window.check(5), Ok(()));
+    window.reset();
+    assert_eq!(window.check(5), Ok(()));
+}
+
+#[tokio::test]
+async fn replayed_package_closes_connection() {
+    const ADDR: &str = "127.0.0.1:5540";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let (client_encryption, repeat) = NonceEncryption::new();
+    let (server_encryption, _) = NonceEncryption::new();
+
+    let (client, server) = tokio::join!(
+        Builder::new().set_conn(client).set_encryption(client_encryption).run(),
+        Builder::new().set_conn(server).set_encryption(server_encryption).run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    client.write(b"first".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"first");
+
+    repeat.store(true, Ordering::SeqCst);
+    client.write(b"first".to_vec()).await.unwrap();
+    assert!(server.read().await.is_none());
+    assert_eq!(server.is_close().await, Some(ENCRYPTION_ERROR));
+
+    let closed = tokio::time::timeout(Duration::from_secs(1), async {
+        while client.is_close().await.is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        client.is_close().await
+    }).await.unwrap();
+    assert_eq!(closed, Some(ENCRYPTION_ERROR));
+}
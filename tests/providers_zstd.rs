@@ -0,0 +1,88 @@
+#![cfg(feature = "zstd")]
+
+use cobra_rs::builder::builder::{Builder, CompressionProvider};
+use cobra_rs::providers::zstd_compression_provider::{ZstdCompressionProvider, ZstdDictionary, ZstdDictionaryId};
+use cobra_rs::transport::tcp::{Conn, Listener};
+
+fn samples() -> Vec<Vec<u8>> {
+    (0..1000)
+        .map(|i| format!("{{\"user\":{},\"action\":\"move\",\"x\":{},\"y\":{}}}", i % 10, i, i * 7).into_bytes())
+        .collect()
+}
+
+#[test]
+fn train_dictionary() {
+    let dictionary = ZstdDictionary::train(&samples(), 4096).unwrap();
+    let restored = ZstdDictionary::from_bytes(dictionary.as_bytes().to_vec()).unwrap();
+
+    assert_ne!(dictionary.id(), 0);
+    assert_eq!(restored.id(), dictionary.id());
+    assert!(ZstdDictionary::from_bytes(b"raw content".to_vec()).is_err());
+}
+
+#[test]
+fn compress_without_handshake() {
+    let provider = ZstdCompressionProvider::new(3);
+    let package = b"hello hello hello hello".to_vec();
+
+    assert_eq!(provider.decompress(provider.compress(package.clone())), package);
+}
+
+#[tokio::test]
+async fn negotiate_common_dictionary() {
+    const ADDR: &str = "127.0.0.1:5520";
+
+    let samples = samples();
+    let common = ZstdDictionary::train(&samples, 4096).unwrap();
+    let other = ZstdDictionary::train(&samples[..500], 2048).unwrap();
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let (client, server) = tokio::join!(
+        Builder::new()
+            .set_conn(client)
+            .set_compression(ZstdCompressionProvider::new(3).add_dictionary(common.clone()))
+            .run(),
+        Builder::new()
+            .set_conn(server)
+            .set_compression(ZstdCompressionProvider::new(3).add_dictionary(other).add_dictionary(common.clone()))
+            .run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    assert_eq!(client.extensions().get::<ZstdDictionaryId>(), Some(ZstdDictionaryId(common.id())));
+    assert_eq!(server.extensions().get::<ZstdDictionaryId>(), Some(ZstdDictionaryId(common.id())));
+
+    client.write(samples[42].clone()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), samples[42]);
+}
+
+#[tokio::test]
+async fn compress_without_common_dictionary() {
+    const ADDR: &str = "127.0.0.1:5521";
+
+    let dictionary = ZstdDictionary::train(&samples(), 4096).unwrap();
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let (client, server) = tokio::join!(
+        Builder::new()
+            .set_conn(client)
+            .set_compression(ZstdCompressionProvider::new(3).add_dictionary(dictionary))
+            .run(),
+        Builder::new()
+            .set_conn(server)
+            .set_compression(ZstdCompressionProvider::new(3))
+            .run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    assert_eq!(client.extensions().get::<ZstdDictionaryId>(), None);
+
+    server.write(b"hello".to_vec()).await.unwrap();
+    assert_eq!(client.read().await.unwrap(), b"hello");
+}
@@ -0,0 +1,81 @@
+use std::io;
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+
+use cobra_rs::retry::Backoff;
+use cobra_rs::transport::tcp::Conn;
+
+#[test]
+fn delays_grow_up_to_max() {
+    let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(500))
+        .set_jitter(0.0);
+
+    let delays: Vec<_> = (0..5).map(|_| backoff.next_delay().unwrap()).collect();
+    assert_eq!(delays, [100, 200, 400, 500, 500].map(Duration::from_millis));
+    assert_eq!(backoff.failures(), 5);
+
+    backoff.reset();
+    assert_eq!(backoff.failures(), 0);
+    assert_eq!(backoff.next_delay(), Some(Duration::from_millis(100)));
+}
+
+#[test]
+fn jitter_shortens_delay() {
+    let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(100));
+
+    for _ in 0..100 {
+        let delay = backoff.next_delay().unwrap();
+        assert!(delay > Duration::from_millis(50) && delay <= Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn attempts_exhausted() {
+    let mut backoff = Backoff::constant(Duration::from_millis(10))
+        .set_max_attempts(3);
+
+    assert_eq!(backoff.next_delay(), Some(Duration::from_millis(10)));
+    assert_eq!(backoff.next_delay(), Some(Duration::from_millis(10)));
+    assert_eq!(backoff.next_delay(), None);
+}
+
+#[tokio::test]
+async fn retry_until_success() {
+    let mut backoff = Backoff::constant(Duration::from_millis(1));
+    let mut attempts = 0;
+
+    let result: Result<u32, u32> = backoff.retry(|| {
+        attempts += 1;
+        let attempt = attempts;
+        async move { if attempt < 3 { Err(attempt) } else { Ok(attempt) } }
+    }).await;
+
+    assert_eq!(result, Ok(3));
+    assert_eq!(backoff.failures(), 0);
+
+    let mut backoff = backoff.set_max_attempts(2);
+    let result: Result<(), u32> = backoff.retry(|| async { Err(1) }).await;
+    assert_eq!(result, Err(1));
+    assert_eq!(backoff.failures(), 2);
+}
+
+#[tokio::test]
+async fn connect_retry() {
+    // The port is freed, so the first attempts are refused
+    let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+
+    let accept = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let listener = TcpListener::bind(addr).await.unwrap();
+        listener.accept().await.unwrap()
+    });
+
+    let backoff = Backoff::new(Duration::from_millis(5), Duration::from_millis(20));
+    assert!(Conn::connect_retry(addr, backoff).await.is_ok());
+    accept.await.unwrap();
+
+    let backoff = Backoff::constant(Duration::from_millis(1)).set_max_attempts(2);
+    let error = Conn::connect_retry(addr, backoff).await.err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+}
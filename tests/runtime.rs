@@ -0,0 +1,138 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::mem::Frame;
+use cobra_rs::runtime::{BoxFuture, HandleRuntime, LocalSetRuntime, Runtime, TokioRuntime};
+use cobra_rs::transport::tcp::{Conn, ConnConfig, Listener};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::LocalSet;
+
+const KIND: u8 = 1;
+
+async fn echo(config: ConnConfig, addr: &str) {
+    let listener = Listener::listen_with_config(addr, config.clone()).await.unwrap();
+    let client = Conn::connect_with_config(addr, config).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    assert!(client.write(Frame::create(KIND, b"ping")).await.is_ok());
+    assert_eq!(server.read(KIND).await.unwrap().get_body().to_vec(), b"ping");
+
+    client.close(1).await;
+    assert_eq!(server.is_close().await, Some(1));
+}
+
+#[test]
+fn dedicated_runtime() {
+    let dedicated = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .unwrap();
+    let main = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let config = ConnConfig::new()
+        .set_runtime(Arc::new(HandleRuntime::new(dedicated.handle().clone())));
+    main.block_on(echo(config, "127.0.0.1:5590"));
+}
+
+#[tokio::test]
+async fn local_set() {
+    let local = LocalSet::new();
+    let config = ConnConfig::new().set_runtime(Arc::new(LocalSetRuntime::new(&local)));
+
+    local.run_until(echo(config, "127.0.0.1:5591")).await;
+}
+
+// Counts calls and runs everything on tokio
+#[derive(Default)]
+struct CountingRuntime {
+    spawns: AtomicUsize,
+    connects: AtomicUsize,
+    binds: AtomicUsize,
+}
+
+impl Runtime for CountingRuntime {
+    fn spawn(&self, future: BoxFuture<()>) {
+        self.spawns.fetch_add(1, Ordering::SeqCst);
+        TokioRuntime.spawn(future)
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        TokioRuntime.sleep(duration)
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<io::Result<TcpStream>> {
+        self.connects.fetch_add(1, Ordering::SeqCst);
+        TokioRuntime.connect(addr)
+    }
+
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<io::Result<TcpListener>> {
+        self.binds.fetch_add(1, Ordering::SeqCst);
+        TokioRuntime.bind(addr)
+    }
+}
+
+#[tokio::test]
+async fn custom_runtime() {
+    let runtime = Arc::new(CountingRuntime::default());
+    echo(ConnConfig::new().set_runtime(runtime.clone()), "127.0.0.1:5592").await;
+
+    // Workers of both connections and the accept loop
+    assert!(runtime.spawns.load(Ordering::SeqCst) >= 5);
+    assert_eq!(runtime.connects.load(Ordering::SeqCst), 1);
+    assert_eq!(runtime.binds.load(Ordering::SeqCst), 1);
+}
+
+#[cfg(feature = "async-std")]
+#[test]
+fn async_std_runtime() {
+    use cobra_rs::runtime::AsyncStdRuntime;
+
+    let config = ConnConfig::new().set_runtime(Arc::new(AsyncStdRuntime));
+    async_std::task::block_on(echo(config, "127.0.0.1:5593"));
+}
+
+#[cfg(feature = "smol")]
+#[test]
+fn smol_runtime() {
+    use cobra_rs::runtime::SmolRuntime;
+
+    let config = ConnConfig::new().set_runtime(Arc::new(SmolRuntime));
+    smol::block_on(echo(config, "127.0.0.1:5594"));
+}
+
+#[cfg(feature = "smol")]
+#[test]
+fn smol_write_file() {
+    use std::io::Write;
+
+    use cobra_rs::runtime::SmolRuntime;
+    use cobra_rs::transport::scheduler::FifoScheduler;
+
+    let path = std::env::temp_dir().join(format!("cobra-{}-smol", std::process::id()));
+    std::fs::File::create(&path).unwrap().write_all(&[7; 1000]).unwrap();
+    let file = std::fs::File::open(&path).unwrap();
+
+    // Scheduled connections read the file on the calling task
+    let config = ConnConfig::new()
+        .set_runtime(Arc::new(SmolRuntime))
+        .set_scheduler(FifoScheduler::new);
+
+    smol::block_on(async {
+        let listener = Listener::listen_with_config("127.0.0.1:5595", config.clone()).await.unwrap();
+        let client = Conn::connect_with_config("127.0.0.1:5595", config).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        client.write_file(KIND, &file, 0..1000).await.unwrap();
+        assert_eq!(server.read(KIND).await.unwrap().get_body().to_vec(), vec![7; 1000]);
+    });
+
+    std::fs::remove_file(&path).unwrap();
+}
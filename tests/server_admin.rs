@@ -0,0 +1,46 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::sync::Arc;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+use cobra_rs::server::{AdminSocket, ConnRegistry};
+use cobra_rs::transport::tcp::Listener;
+
+use common::pair_on;
+
+#[tokio::test]
+async fn commands() {
+    const ADDR: &str = "127.0.0.1:5740";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let registry = Arc::new(ConnRegistry::new());
+    let (client, server) = pair_on(&listener, ADDR).await;
+    registry.register(7_u32, Arc::new(server));
+
+    let path = std::env::temp_dir().join(format!("cobra-admin-{}.sock", std::process::id()));
+    let admin = AdminSocket::bind(&path, registry.clone()).unwrap();
+
+    let list = admin.execute("list").await;
+    assert!(list.starts_with("7 peer="), "{}", list);
+    assert_eq!(list.lines().count(), 1);
+    assert!(admin.execute("stats 7").await.contains("protocol version: "));
+    assert!(admin.execute("stats 8").await.starts_with("error:"));
+    assert!(admin.execute("kick 7 x").await.starts_with("error:"));
+    assert!(admin.execute("reboot").await.starts_with("error:"));
+
+    // Commands arrive through the socket
+    tokio::spawn(async move { admin.run().await });
+    let mut stream = UnixStream::connect(&path).await.unwrap();
+    stream.write_all(b"kick 7\n").await.unwrap();
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer).await.unwrap();
+
+    assert_eq!(answer, "kicked\n");
+    assert!(registry.is_empty());
+    assert!(client.read().await.is_none());
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cobra_rs::builder::kind_conn::close_code::GOING_AWAY;
+use cobra_rs::builder::kind_conn::KindConn;
+use cobra_rs::server::{ConnRegistry, DrainProgress, Server, GOING_AWAY_REASON};
+use cobra_rs::transport::tcp::Listener;
+
+use common::pair_on;
+
+async fn server(addr: &str, count: u32) -> (Server<u32>, Vec<KindConn>) {
+    let listener = Listener::listen(addr).await.unwrap();
+    let server = Server::new(listener, Arc::new(ConnRegistry::new()));
+    let mut clients = Vec::new();
+
+    for id in 0..count {
+        let (client, conn) = pair_on(server.listener(), addr).await;
+        server.registry().register(id, Arc::new(conn));
+        clients.push(client);
+    }
+
+    (server, clients)
+}
+
+#[tokio::test]
+async fn drain() {
+    const ADDR: &str = "127.0.0.1:5670";
+
+    let (server, clients) = server(ADDR, 2).await;
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let handler_reports = reports.clone();
+    server.on_drain_progress(move |progress| handler_reports.lock().unwrap().push(*progress));
+
+    assert!(server.registry().get(&0).unwrap().write(vec![1, 2, 3]).await.is_ok());
+    assert_eq!(clients[0].read().await.unwrap(), vec![1, 2, 3]);
+    let progress = server.drain(Duration::from_secs(1)).await;
+
+    assert_eq!(progress, DrainProgress { total: 2, closed: 2, forced: 0 });
+    assert_eq!(reports.lock().unwrap().len(), 3);
+    assert_eq!(reports.lock().unwrap()[0], DrainProgress { total: 2, closed: 0, forced: 0 });
+    assert!(server.registry().is_empty());
+    assert!(server.listener().accept().await.is_none());
+
+    for client in &clients {
+        assert!(client.read().await.is_none());
+        assert_eq!(client.is_close().await, Some(GOING_AWAY));
+        assert_eq!(client.close_reason().await.unwrap(), GOING_AWAY_REASON);
+    }
+}
+
+#[tokio::test]
+async fn deadline() {
+    const ADDR: &str = "127.0.0.1:5671";
+
+    let (server, clients) = server(ADDR, 1).await;
+
+    // Client doesn't read, so the writes can't be flushed
+    let conn = server.registry().get(&0).unwrap();
+    tokio::spawn(async move {
+        while conn.write(vec![0; 60 * 1024]).await.is_ok() {}
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let progress = server.drain(Duration::from_millis(100)).await;
+    assert_eq!(progress, DrainProgress { total: 1, closed: 0, forced: 1 });
+    assert!(server.registry().is_empty());
+    drop(clients);
+}
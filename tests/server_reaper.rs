@@ -0,0 +1,68 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use cobra_rs::builder::kind_conn::close_code::IDLE_TIMEOUT;
+use cobra_rs::builder::kind_conn::KindConn;
+use cobra_rs::server::{ConnRegistry, IdleReaper};
+use cobra_rs::transport::tcp::Listener;
+
+use common::pair_on;
+
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+async fn registered(addr: &str, count: u32) -> (Arc<ConnRegistry<u32>>, Vec<KindConn>) {
+    let listener = Listener::listen(addr).await.unwrap();
+    let registry = Arc::new(ConnRegistry::new());
+    let mut clients = Vec::new();
+
+    for id in 0..count {
+        let (client, server) = pair_on(&listener, addr).await;
+        registry.register(id, Arc::new(server));
+        clients.push(client);
+    }
+
+    (registry, clients)
+}
+
+#[tokio::test]
+async fn reap_idle() {
+    let (registry, clients) = registered("127.0.0.1:5650", 3).await;
+    let reaper = IdleReaper::new(registry.clone(), TIMEOUT);
+    reaper.exempt(2);
+    assert!(reaper.is_exempt(&2));
+
+    assert!(reaper.reap().await.is_empty());
+    tokio::time::sleep(TIMEOUT).await;
+
+    // Traffic of the first connection keeps it alive
+    assert!(clients[0].write(vec![1]).await.is_ok());
+    assert_eq!(registry.get(&0).unwrap().read().await.unwrap(), vec![1]);
+
+    assert_eq!(reaper.reap().await, vec![1]);
+    assert_eq!(registry.len(), 2);
+    assert!(clients[1].read().await.is_none());
+    assert_eq!(clients[1].is_close().await, Some(IDLE_TIMEOUT));
+    assert_eq!(clients[0].is_close().await, None);
+    assert_eq!(clients[2].is_close().await, None);
+
+    reaper.unexempt(&2);
+    assert_eq!(reaper.reap().await, vec![2]);
+}
+
+#[tokio::test]
+async fn run() {
+    let (registry, clients) = registered("127.0.0.1:5651", 1).await;
+    let reaper = Arc::new(IdleReaper::new(registry.clone(), TIMEOUT).set_check_interval(TIMEOUT / 4));
+
+    let task = tokio::spawn({
+        let reaper = reaper.clone();
+        async move { reaper.run().await }
+    });
+
+    assert!(clients[0].read().await.is_none());
+    assert_eq!(clients[0].is_close().await, Some(IDLE_TIMEOUT));
+    assert!(registry.is_empty());
+    task.abort();
+}
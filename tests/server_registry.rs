@@ -0,0 +1,76 @@
+mod common;
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use cobra_rs::builder::kind_conn::close_code::CLOSED_BY_USER;
+use cobra_rs::server::ConnRegistry;
+use cobra_rs::transport::tcp::Listener;
+
+use common::pair_on;
+
+#[tokio::test]
+async fn broadcast_by_tag() {
+    const ADDR: &str = "127.0.0.1:5300";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let registry = ConnRegistry::new();
+    let mut clients = Vec::new();
+
+    for id in 0..3_u32 {
+        let (client, server) = pair_on(&listener, ADDR).await;
+        registry.register(id, Arc::new(server));
+        clients.push(client);
+    }
+    assert!(registry.tag(&0, "lobby"));
+    assert!(registry.tag(&2, "lobby"));
+    assert!(!registry.tag(&5, "lobby"));
+
+    let mut tagged = registry.tagged("lobby");
+    tagged.sort_unstable();
+    assert_eq!(tagged, vec![0, 2]);
+
+    assert_eq!(registry.broadcast("lobby", &[1, 2, 3]).await, 2);
+    assert_eq!(clients[0].read().await.unwrap(), vec![1, 2, 3]);
+    assert_eq!(clients[2].read().await.unwrap(), vec![1, 2, 3]);
+    assert_eq!(registry.len(), 3);
+}
+
+#[tokio::test]
+async fn kick() {
+    const ADDR: &str = "127.0.0.1:5301";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let registry = ConnRegistry::new();
+
+    let (client, server) = pair_on(&listener, ADDR).await;
+    registry.register("alice", Arc::new(server));
+
+    assert!(registry.kick(&"alice", CLOSED_BY_USER).await);
+    assert!(!registry.kick(&"alice", CLOSED_BY_USER).await);
+    assert!(registry.is_empty());
+    assert!(client.read().await.is_none());
+    assert_eq!(client.is_close().await, Some(CLOSED_BY_USER));
+}
+
+#[tokio::test]
+async fn broadcast_shared_frame() {
+    const ADDR: &str = "127.0.0.1:5302";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let registry = ConnRegistry::new();
+    let mut clients = Vec::new();
+
+    for id in 0..4_u32 {
+        let (client, server) = pair_on(&listener, ADDR).await;
+        registry.register(id, Arc::new(server));
+        registry.tag(&id, "all");
+        clients.push(client);
+    }
+
+    assert_eq!(registry.broadcast_frame("all", Bytes::from_static(&[7, 7])).await, 4);
+    for client in clients {
+        assert_eq!(client.read().await.unwrap(), vec![7, 7]);
+    }
+}
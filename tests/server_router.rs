@@ -0,0 +1,79 @@
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use cobra_rs::builder::kind_conn::close_code::CLOSED_BY_USER;
+use cobra_rs::server::{Request, Route, Router};
+use cobra_rs::transport::tcp::Listener;
+
+use common::pair_on;
+
+#[tokio::test]
+async fn routes_by_name_and_kind() {
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let (client, server) = pair_on(&listener, &listener.local_addr().unwrap().to_string()).await;
+    let events = client.open_kind().unwrap();
+
+    let router = Router::new()
+        .on("echo", |request: Request| async move {
+            request.reply(request.package()).await.unwrap();
+        })
+        .on("upper", |request: Request| async move {
+            request.reply(&request.package().to_ascii_uppercase()).await.unwrap();
+        })
+        .on(events.kind(), |request: Request| async move {
+            assert_eq!(request.route(), &Route::Kind(request.conn().kind()));
+            request.reply(&[request.package().len() as u8]).await.unwrap();
+        });
+    let served = tokio::spawn(async move { router.serve(server).await });
+
+    client.write(Router::encode("echo", b"hi").unwrap()).await.unwrap();
+    assert_eq!(client.read().await.unwrap(), Router::encode("echo", b"hi").unwrap());
+
+    // Packages of unknown routes are dropped
+    client.write(Router::encode("missing", b"hi").unwrap()).await.unwrap();
+    client.write(Router::encode("upper", b"hi").unwrap()).await.unwrap();
+    assert_eq!(client.read().await.unwrap(), Router::encode("upper", b"HI").unwrap());
+
+    events.write(vec![0; 3]).await.unwrap();
+    assert_eq!(events.read().await.unwrap(), vec![3]);
+
+    client.close(CLOSED_BY_USER).await;
+    served.await.unwrap();
+}
+
+#[tokio::test]
+async fn concurrency_limit() {
+    const PACKAGES: usize = 6;
+
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let (client, server) = pair_on(&listener, &listener.local_addr().unwrap().to_string()).await;
+
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let (running_handler, max_handler) = (running.clone(), max_running.clone());
+
+    let router = Router::new()
+        .on("slow", move |request: Request| {
+            let (running, max_running) = (running_handler.clone(), max_handler.clone());
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                request.reply(request.package()).await.unwrap();
+            }
+        })
+        .limit("slow", 2);
+    tokio::spawn(async move { router.serve(server).await });
+
+    for i in 0..PACKAGES as u8 {
+        client.write(Router::encode("slow", &[i]).unwrap()).await.unwrap();
+    }
+    for _ in 0..PACKAGES {
+        client.read().await.unwrap();
+    }
+    assert!(max_running.load(Ordering::SeqCst) <= 2);
+}
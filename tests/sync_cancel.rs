@@ -0,0 +1,34 @@
+use std::future::pending;
+
+use cobra_rs::sync::CancelToken;
+
+#[tokio::test]
+async fn cancel_children() {
+    let parent = CancelToken::new();
+    let child = parent.child();
+    let grandchild = child.child();
+
+    child.cancel();
+    assert!(!parent.is_cancelled());
+    assert!(grandchild.is_cancelled());
+
+    let other = parent.child();
+    parent.cancel();
+    assert!(other.is_cancelled());
+    assert!(parent.child().is_cancelled());
+}
+
+#[tokio::test]
+async fn run_until_cancelled() {
+    let token = CancelToken::new();
+    assert_eq!(token.run(async { 1 }).await, Some(1));
+
+    let waiter = {
+        let token = token.clone();
+        tokio::spawn(async move { token.run(pending::<()>()).await })
+    };
+    tokio::task::yield_now().await;
+    token.cancel();
+
+    assert_eq!(waiter.await.unwrap(), None);
+}
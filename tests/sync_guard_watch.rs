@@ -0,0 +1,57 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time;
+
+use cobra_rs::sync::{GuardWatch, HeldGuard, Pool};
+
+// The watch is process-wide, so all cases share one test
+#[tokio::test]
+async fn held_guard_reported_once() {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    let events: Arc<Mutex<Vec<HeldGuard>>> = Default::default();
+    let recorded = events.clone();
+    GuardWatch::new(Duration::from_millis(100))
+        .on_held_guard(move |guard| recorded.lock().unwrap().push(guard.clone()))
+        .install();
+
+    // Value waiting for a reader isn't reported
+    let pool = Pool::new();
+    let writer = pool.clone();
+    let write = tokio::spawn(async move { writer.write(1).await });
+    time::sleep(Duration::from_millis(250)).await;
+    assert!(events.lock().unwrap().is_empty());
+
+    // Guard answered in time isn't reported
+    pool.read().await.unwrap().accept();
+    assert!(write.await.unwrap().is_ok());
+    assert!(events.lock().unwrap().is_empty());
+
+    let writer = pool.clone();
+    let write = tokio::spawn(async move { writer.write(2).await });
+    let guard = pool.read().await.unwrap();
+    time::sleep(Duration::from_millis(350)).await;
+    {
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].held >= Duration::from_millis(100));
+        assert_eq!(events[0].backtrace.is_some(), cfg!(feature = "guard-backtrace"));
+    }
+
+    guard.accept();
+    assert!(write.await.unwrap().is_ok());
+    assert_eq!(events.lock().unwrap().len(), 1);
+
+    // Guards taken after uninstalling aren't watched
+    GuardWatch::uninstall();
+    let writer = pool.clone();
+    let write = tokio::spawn(async move { writer.write(3).await });
+    let guard = pool.read().await.unwrap();
+    time::sleep(Duration::from_millis(250)).await;
+    guard.accept();
+    assert!(write.await.unwrap().is_ok());
+    assert_eq!(events.lock().unwrap().len(), 1);
+}
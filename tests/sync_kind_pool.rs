@@ -1,4 +1,4 @@
-use cobra_rs::sync::{Kind, KindPool};
+use cobra_rs::sync::{Kind, KindFilter, KindPool};
 
 #[derive(Debug)]
 struct TestValue {
@@ -232,3 +232,57 @@ async fn stress_test() {
         assert_eq!(read_pool.read(0).await.unwrap().accept().value, i);
     }
 }
+
+#[tokio::test]
+async fn subscribe_range() {
+    let read_pool = KindPool::new();
+    let write_pool = read_pool.clone();
+    let subscription = read_pool.subscribe(32..64);
+
+    tokio::spawn(async move {
+        for kind in 30..66 {
+            let _ = write_pool.write(TestValue::create(kind, kind as i32)).await;
+        }
+    });
+
+    // Kinds outside of the range wait for their own readers
+    assert_eq!(read_pool.read(30).await.unwrap().accept().value, 30);
+    assert_eq!(read_pool.read(31).await.unwrap().accept().value, 31);
+    for kind in 32..64 {
+        assert_eq!(subscription.read().await.unwrap().accept().key, kind);
+    }
+    assert_eq!(read_pool.read(64).await.unwrap().accept().value, 64);
+}
+
+#[tokio::test]
+async fn subscribe_predicate() {
+    let read_pool = KindPool::new();
+    let write_pool = read_pool.clone();
+    let subscription = read_pool.subscribe(KindFilter::predicate(|kind: &u8| *kind >= 4));
+
+    tokio::spawn(async move {
+        write_pool.write(TestValue::create(3, 0)).await.unwrap();
+    });
+    tokio::task::yield_now().await;
+
+    let other_pool = read_pool.clone();
+    tokio::spawn(async move {
+        other_pool.write(TestValue::create(4, 1)).await.unwrap();
+    });
+
+    assert_eq!(subscription.read().await.unwrap().accept().value, 1);
+    assert_eq!(read_pool.read(3).await.unwrap().accept().value, 0);
+}
+
+#[tokio::test]
+async fn subscribe_any_after_close() {
+    let read_pool: KindPool<u8, TestValue> = KindPool::new();
+    let subscription = read_pool.subscribe(KindFilter::any());
+
+    let close_pool = read_pool.clone();
+    tokio::spawn(async move {
+        close_pool.close().await;
+    });
+
+    assert!(subscription.read().await.is_none());
+}
@@ -179,6 +179,30 @@ async fn write_before_close_test() {
     }
 }
 
+#[tokio::test]
+async fn close_before_reader_runs_test() {
+    let read_pool: Pool<i32> = Pool::new();
+    let write_pool: Pool<i32> = read_pool.clone();
+    let close_pool: Pool<i32> = read_pool.clone();
+
+    let reader = tokio::spawn(async move { read_pool.read().await.map(|value| value.accept()) });
+    tokio::task::yield_now().await;
+
+    // The reader is woken for the value, but closing goes first
+    let writer = tokio::spawn(async move { write_pool.write(1).await });
+    tokio::task::yield_now().await;
+    close_pool.close();
+
+    let written = time::timeout(time::Duration::from_secs(1), writer).await
+        .expect("writer is stranded")
+        .unwrap();
+    match (written, reader.await.unwrap()) {
+        (Ok(()), Some(value)) => assert_eq!(value, 1),
+        (Err(WriteError::Closed(value)), None) => assert_eq!(value, 1),
+        _ => panic!("value is lost or delivered twice"),
+    }
+}
+
 #[tokio::test]
 async fn accept_after_close_test() {
     let read_pool: Pool<i32> = Pool::new();
@@ -208,12 +232,19 @@ async fn accept_after_close_test() {
         });
     });
 
+    // Value taken before the close is still answered
+    let timestamp = time::Instant::now();
     assert!(write_pool.write(1).await.is_ok());
+    if time::Instant::now().sub(timestamp).le(&time::Duration::from_millis(80)) {
+        panic!("write didn't wait for the answer")
+    }
 
+    // Close no longer waits for the answer, the write above does, see Pool::close()
     semaphore_a.acquire().await.unwrap().forget();
-    if result_a.read().await.le(&time::Duration::from_millis(80)) {
-        panic!("close method didn't block")
+    if result_a.read().await.gt(&time::Duration::from_millis(80)) {
+        panic!("close method blocked")
     }
+    assert!(write_pool.write(2).await.is_err());
 }
 
 #[tokio::test]
@@ -245,14 +276,20 @@ async fn reject_after_close_test() {
         });
     });
 
+    // Value taken before the close is still answered
+    let timestamp = time::Instant::now();
     match write_pool.write(1).await.unwrap_err() {
         WriteError::Rejected(value) => assert_eq!(value, 1),
         _ => panic!("wrong write error returned")
     }
+    if time::Instant::now().sub(timestamp).le(&time::Duration::from_millis(80)) {
+        panic!("write didn't wait for the answer")
+    }
 
+    // Close no longer waits for the answer, the write above does, see Pool::close()
     semaphore_a.acquire().await.unwrap().forget();
-    if result_a.read().await.le(&time::Duration::from_millis(80)) {
-        panic!("close method didn't block")
+    if result_a.read().await.gt(&time::Duration::from_millis(80)) {
+        panic!("close method blocked")
     }
 }
 
@@ -271,3 +308,153 @@ async fn stress_test() {
         assert_eq!(read_pool.read().await.unwrap().accept(), i);
     }
 }
+
+#[tokio::test]
+async fn writers_served_in_order() {
+    let pool: Pool<usize> = Pool::new();
+
+    // Every writer waits for the slot before the next one starts
+    for i in 0..8 {
+        let pool = pool.clone();
+        tokio::spawn(async move { pool.write(i).await.unwrap() });
+        tokio::task::yield_now().await;
+    }
+
+    for i in 0..8 {
+        assert_eq!(pool.read().await.unwrap().accept(), i);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn fair_writers_stress_test() {
+    const WRITERS: usize = 8;
+    const WRITES: usize = 5000;
+
+    let pool: Pool<usize> = Pool::new();
+    for writer in 0..WRITERS {
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            for _ in 0..WRITES {
+                pool.write(writer).await.unwrap();
+            }
+        });
+    }
+
+    // Values of other writers read between two values of one writer
+    let mut last = [None; WRITERS];
+    let mut max_wait = 0;
+    for read in 0..WRITERS * WRITES {
+        let writer = pool.read().await.unwrap().accept();
+        if let Some(last) = last[writer] {
+            max_wait = max_wait.max(read - last - 1);
+        }
+        last[writer] = Some(read);
+    }
+
+    // Writer that has just been served waits at most for the others
+    // queued before it, with room for slow scheduling of its task
+    assert!(max_wait < WRITERS * 4, "writer waited for {} values", max_wait);
+}
+
+#[tokio::test]
+async fn write_all_drain() {
+    let read_pool: Pool<i32> = Pool::new();
+    let write_pool: Pool<i32> = read_pool.clone();
+
+    let write = tokio::spawn(async move { write_pool.write_all(0..10).await.is_ok() });
+
+    assert_eq!(read_pool.drain(4).await.unwrap(), [0, 1, 2, 3]);
+    assert_eq!(read_pool.read().await.unwrap().accept(), 4);
+    assert_eq!(read_pool.drain(100).await.unwrap(), [5, 6, 7, 8, 9]);
+    assert!(write.await.unwrap());
+}
+
+#[tokio::test]
+async fn write_all_keeps_batch_together() {
+    let read_pool: Pool<i32> = Pool::new();
+    let batch_pool: Pool<i32> = read_pool.clone();
+    let write_pool: Pool<i32> = read_pool.clone();
+
+    tokio::spawn(async move { batch_pool.write_all(vec![1, 2, 3]).await.unwrap() });
+    tokio::task::yield_now().await;
+    tokio::spawn(async move { write_pool.write(4).await.unwrap() });
+
+    for i in 1..=4 {
+        assert_eq!(read_pool.read().await.unwrap().accept(), i);
+    }
+}
+
+#[tokio::test]
+async fn write_all_rejected() {
+    let read_pool: Pool<i32> = Pool::new();
+    let write_pool: Pool<i32> = read_pool.clone();
+
+    tokio::spawn(async move {
+        read_pool.read().await.unwrap().accept();
+        read_pool.read().await.unwrap().reject().await;
+    });
+
+    match write_pool.write_all(vec![1, 2, 3]).await.unwrap_err() {
+        WriteError::Rejected(value) => assert_eq!(value, 2),
+        _ => panic!("wrong write error returned"),
+    }
+}
+
+#[tokio::test]
+async fn write_all_closed() {
+    let read_pool: Pool<i32> = Pool::new();
+    let write_pool: Pool<i32> = read_pool.clone();
+
+    tokio::spawn(async move {
+        let value = read_pool.read().await.unwrap();
+        read_pool.close();
+        value.accept();
+        assert!(read_pool.drain(10).await.is_none());
+    });
+
+    match write_pool.write_all(vec![1, 2, 3]).await.unwrap_err() {
+        WriteError::Closed(value) => assert_eq!(value, 2),
+        _ => panic!("wrong write error returned"),
+    }
+}
+
+#[tokio::test]
+async fn write_cancellable_withdrawn() {
+    let pool: Pool<i32> = Pool::new();
+    let cancel = Arc::new(Semaphore::new(0));
+
+    let writer = pool.clone();
+    let cancelled = cancel.clone();
+    let write = tokio::spawn(async move {
+        writer.write_cancellable(1, async move { drop(cancelled.acquire().await) }).await
+    });
+
+    // Value is offered but nobody reads it
+    tokio::task::yield_now().await;
+    cancel.add_permits(1);
+    assert!(write.await.unwrap().is_none());
+
+    // Slot is free for the next writer
+    let writer = pool.clone();
+    tokio::spawn(async move { writer.write(2).await.unwrap() });
+    assert_eq!(pool.read().await.unwrap().accept(), 2);
+}
+
+#[tokio::test]
+async fn write_cancellable_taken() {
+    let pool: Pool<i32> = Pool::new();
+    let cancel = Arc::new(Semaphore::new(0));
+
+    let writer = pool.clone();
+    let cancelled = cancel.clone();
+    let write = tokio::spawn(async move {
+        writer.write_cancellable(1, async move { drop(cancelled.acquire().await) }).await
+    });
+
+    // Cancellation after the value is taken waits for the answer
+    let value = pool.read().await.unwrap();
+    cancel.add_permits(1);
+    tokio::task::yield_now().await;
+    assert_eq!(value.accept(), 1);
+    assert!(matches!(write.await.unwrap(), Some(Ok(()))));
+}
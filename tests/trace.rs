@@ -0,0 +1,62 @@
+mod common;
+
+use cobra_rs::builder::builder::Builder;
+use cobra_rs::trace::{TraceContext, TracedConn};
+
+use common::pair;
+
+#[test]
+fn traceparent() {
+    const VALUE: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    let context = TraceContext::from_traceparent(VALUE).unwrap();
+    assert!(context.is_sampled());
+    assert_eq!(context.span_id, [0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7]);
+    assert_eq!(context.traceparent(), VALUE);
+
+    let child = context.child();
+    assert_eq!(child.trace_id, context.trace_id);
+    assert_ne!(child.span_id, context.span_id);
+
+    for malformed in [
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e47zz-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-00",
+    ] {
+        assert_eq!(TraceContext::from_traceparent(malformed), None, "{}", malformed);
+    }
+
+    let root = TraceContext::new_root(false);
+    assert!(!root.is_sampled());
+    assert_eq!(TraceContext::from_traceparent(&root.traceparent()), Some(root));
+}
+
+#[tokio::test]
+async fn context_propagation() {
+    let (client, server) = pair("127.0.0.1:5705", Builder::new(), Builder::new()).await;
+    let (client, server) = (TracedConn::new(client), TracedConn::new(server));
+
+    let context = TraceContext::new_root(true);
+    assert_eq!(TraceContext::current(), None);
+    context.scope(async {
+        assert_eq!(TraceContext::current(), Some(context));
+        client.write(b"traced".to_vec()).await.unwrap();
+    }).await;
+    client.write(b"plain".to_vec()).await.unwrap();
+
+    let traced = server.read().await.unwrap();
+    assert_eq!(traced.context, Some(context));
+    assert_eq!(traced.package, b"traced");
+
+    let plain = server.read().await.unwrap();
+    assert_eq!(plain.context, None);
+    assert_eq!(plain.package, b"plain");
+
+    // Malformed packages written without the wrapper are skipped
+    client.get_ref().write(vec![7, 1, 2]).await.unwrap();
+    client.write(b"after".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap().package, b"after");
+}
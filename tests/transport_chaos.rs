@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::mem::{Frame, HEADER_BYTES};
+use cobra_rs::transport::chaos::{ChaosConfig, ChaosConn};
+use cobra_rs::transport::replay::ReplayConn;
+
+async fn written(config: ChaosConfig, bodies: &[&[u8]]) -> Vec<Vec<u8>> {
+    let conn = ChaosConn::new(ReplayConn::new(Vec::new()), config.set_seed(7));
+
+    for body in bodies {
+        assert!(conn.write(Frame::create(5, body)).await.is_ok());
+    }
+    conn.flush().await;
+
+    conn.get_ref()
+        .take_written()
+        .iter()
+        .map(|frame| frame[HEADER_BYTES..].to_vec())
+        .collect()
+}
+
+#[tokio::test]
+async fn keep_order_by_default() {
+    let written = written(ChaosConfig::new(), &[b"1", b"2", b"3"]).await;
+
+    assert_eq!(written, vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec()]);
+}
+
+#[tokio::test]
+async fn drop_frames() {
+    let written = written(ChaosConfig::new().set_drop_rate(1.0), &[b"1", b"2"]).await;
+
+    assert!(written.is_empty());
+}
+
+#[tokio::test]
+async fn duplicate_frames() {
+    let written = written(ChaosConfig::new().set_duplicate_rate(1.0), &[b"1", b"2"]).await;
+
+    assert_eq!(written, vec![b"1".to_vec(), b"1".to_vec(), b"2".to_vec(), b"2".to_vec()]);
+}
+
+#[tokio::test]
+async fn reorder_frames() {
+    let written = written(ChaosConfig::new().set_reorder_rate(1.0), &[b"1", b"2", b"3"]).await;
+
+    assert_eq!(written, vec![b"2".to_vec(), b"1".to_vec(), b"3".to_vec()]);
+}
+
+#[tokio::test]
+async fn delay_frames() {
+    let config = ChaosConfig::new()
+        .set_latency(Duration::from_millis(50))
+        .set_jitter(Duration::from_millis(20));
+
+    let started = Instant::now();
+    let written = written(config, &[b"1", b"2"]).await;
+
+    assert!(started.elapsed() >= Duration::from_millis(50));
+    assert_eq!(written, vec![b"1".to_vec(), b"2".to_vec()]);
+}
@@ -0,0 +1,61 @@
+use std::io;
+use std::path::PathBuf;
+
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::mem::Frame;
+use cobra_rs::transport::endpoint::{Endpoint, EndpointError};
+use cobra_rs::transport::stream::StreamConn;
+use cobra_rs::transport::tcp::{Conn, ConnConfig, Listener};
+
+#[test]
+fn parse() {
+    assert_eq!(Endpoint::parse("cobra+tcp://localhost:5000"), Ok(Endpoint::Tcp("localhost:5000".to_string())));
+    assert_eq!(Endpoint::parse("cobra+tls://[::1]:443"), Ok(Endpoint::Tls("[::1]:443".to_string())));
+    assert_eq!("cobra+unix:///tmp/chat.sock".parse(), Ok(Endpoint::Unix(PathBuf::from("/tmp/chat.sock"))));
+
+    assert_eq!(Endpoint::parse("cobra+udp://localhost:5000"), Err(EndpointError::UnknownScheme("cobra+udp".to_string())));
+    assert_eq!(Endpoint::parse("localhost:5000"), Err(EndpointError::UnknownScheme("localhost:5000".to_string())));
+    assert_eq!(Endpoint::parse("cobra+tcp://localhost"), Err(EndpointError::InvalidAddress("localhost".to_string())));
+    assert_eq!(Endpoint::parse("cobra+tcp://localhost:http"), Err(EndpointError::InvalidAddress("localhost:http".to_string())));
+    assert_eq!(Endpoint::parse("cobra+unix://chat.sock"), Err(EndpointError::InvalidAddress("chat.sock".to_string())));
+
+    let uri = "cobra+tcp://127.0.0.1:5000";
+    assert_eq!(Endpoint::parse(uri).unwrap().to_string(), uri);
+}
+
+#[tokio::test]
+async fn connect_tcp() {
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let endpoint = Endpoint::parse(&format!("cobra+tcp://{}", listener.local_addr().unwrap())).unwrap();
+
+    let client = Conn::connect_endpoint(&endpoint, ConnConfig::new()).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    assert!(client.write(Frame::create(5, b"hello")).await.is_ok());
+    assert_eq!(server.read(5).await.unwrap().get_body(), &b"hello"[..]);
+}
+
+#[tokio::test]
+async fn connect_unix() {
+    let path = std::env::temp_dir().join(format!("cobra-endpoint-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+    let endpoint = Endpoint::parse(&format!("cobra+unix://{}", path.display())).unwrap();
+    let (client, server) = tokio::join!(Conn::connect_endpoint(&endpoint, ConnConfig::new()), listener.accept());
+    let (client, server) = (client.unwrap(), StreamConn::new(server.unwrap().0));
+
+    assert!(client.write(Frame::create(5, b"hello")).await.is_ok());
+    assert_eq!(server.read(5).await.unwrap().get_body(), &b"hello"[..]);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn tls_unsupported() {
+    let endpoint = Endpoint::parse("cobra+tls://127.0.0.1:5000").unwrap();
+    match Conn::connect_endpoint(&endpoint, ConnConfig::new()).await {
+        Err(err) => assert_eq!(err.kind(), io::ErrorKind::Unsupported),
+        Ok(_) => panic!("TLS endpoint connected"),
+    }
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use cobra_rs::builder::builder::{BuildError, Builder, EncryptionProvider};
+use cobra_rs::builder::context::Context;
+use cobra_rs::builder::kind_conn::KindConn;
+use cobra_rs::transport::file::MAX_FILE_CHUNK;
+use cobra_rs::transport::scheduler::FifoScheduler;
+use cobra_rs::transport::tcp::ConnConfig;
+
+use common::pair_with_config;
+
+struct XorEncryption;
+
+#[async_trait]
+impl EncryptionProvider for XorEncryption {
+    async fn init(&self, _context: Context) -> Result<(), BuildError> {
+        Ok(())
+    }
+
+    fn encrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        frame.into_iter().map(|byte| byte ^ 0x5a).collect()
+    }
+
+    fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        self.encrypt(frame)
+    }
+}
+
+// Keeps the file until the test ends
+struct TempFile {
+    path: PathBuf,
+    file: File,
+    content: Vec<u8>,
+}
+
+impl TempFile {
+    fn create(name: &str, len: usize) -> Self {
+        let path = std::env::temp_dir().join(format!("cobra-{}-{}", std::process::id(), name));
+        let content: Vec<u8> = (0..len).map(|i| (i * 7 % 251) as u8).collect();
+        File::create(&path).unwrap().write_all(&content).unwrap();
+
+        TempFile { file: File::open(&path).unwrap(), path, content }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+async fn transfer(client: &KindConn, server: &KindConn, file: &TempFile) {
+    let range = 1000..150_000;
+    let (written, read) = tokio::join!(
+        client.write_file(&file.file, range.clone()),
+        async {
+            let mut read = Vec::new();
+            while read.len() < range.end as usize - range.start as usize {
+                let package = server.read().await.unwrap();
+                assert!(package.len() <= MAX_FILE_CHUNK);
+                read.extend(package);
+            }
+            read
+        },
+    );
+
+    written.unwrap();
+    assert_eq!(read, &file.content[1000..150_000]);
+
+    // Packages written after the file follow it
+    client.write(b"done".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"done");
+}
+
+#[tokio::test]
+async fn zero_copy() {
+    let file = TempFile::create("zero_copy", 200_000);
+    let (client, server) = pair_with_config("127.0.0.1:5620", ConnConfig::new(), Builder::new(), Builder::new()).await;
+
+    transfer(&client, &server, &file).await;
+}
+
+#[tokio::test]
+async fn encrypted() {
+    let file = TempFile::create("encrypted", 200_000);
+    let (client, server) = pair_with_config(
+        "127.0.0.1:5621",
+        ConnConfig::new(),
+        Builder::new().set_encryption(XorEncryption),
+        Builder::new().set_encryption(XorEncryption),
+    ).await;
+
+    transfer(&client, &server, &file).await;
+}
+
+#[tokio::test]
+async fn scheduled() {
+    let file = TempFile::create("scheduled", 200_000);
+    let config = ConnConfig::new().set_scheduler(FifoScheduler::new);
+    let (client, server) = pair_with_config("127.0.0.1:5622", config, Builder::new(), Builder::new()).await;
+
+    transfer(&client, &server, &file).await;
+}
+
+#[tokio::test]
+async fn region_past_end() {
+    let file = TempFile::create("past_end", 1000);
+    let (client, server) = pair_with_config("127.0.0.1:5623", ConnConfig::new(), Builder::new(), Builder::new()).await;
+
+    let error = client.write_file(&file.file, 500..2000).await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+    // Connection is still usable
+    client.write(b"after".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"after");
+}
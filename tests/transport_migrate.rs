@@ -0,0 +1,177 @@
+use std::io;
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+
+use cobra_rs::builder::builder::{BuildError, Builder, ConnProvider, EncryptionProvider};
+use cobra_rs::builder::context::Context;
+use cobra_rs::mem::Frame;
+use cobra_rs::sync::WriteError;
+use cobra_rs::transport::migrate::MigratableConn;
+use cobra_rs::transport::tcp::{Conn, Listener};
+
+// Keeps a per-connection key, so it must survive the migration
+struct KeyedXor {
+    key: std::sync::Mutex<u8>,
+}
+
+#[async_trait]
+impl EncryptionProvider for KeyedXor {
+    async fn init(&self, context: Context) -> Result<(), BuildError> {
+        let conn = context.get_kind_conn().await;
+        conn.write(vec![0x2a]).await.map_err(|_| BuildError::EncryptionInitFailed)?;
+        let peer = conn.read().await.ok_or(BuildError::EncryptionInitFailed)?;
+        *self.key.lock().unwrap() = peer[0];
+        Ok(())
+    }
+
+    fn encrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        let key = *self.key.lock().unwrap();
+        frame.into_iter().map(|byte| byte ^ key).collect()
+    }
+
+    fn decrypt(&self, frame: Vec<u8>) -> Vec<u8> {
+        self.encrypt(frame)
+    }
+}
+
+fn encryption() -> KeyedXor {
+    KeyedXor { key: std::sync::Mutex::new(0) }
+}
+
+// Transport without counters, like most custom ones
+struct Uncounted(Conn);
+
+#[async_trait]
+impl ConnProvider for Uncounted {
+    async fn read(&self, kind: u8) -> Option<Frame> {
+        self.0.read(kind).await
+    }
+
+    async fn write(&self, frame: Frame) -> Result<(), WriteError<Frame>> {
+        self.0.write(frame).await
+    }
+
+    async fn flush(&self) {
+        self.0.flush().await
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.0.peer_addr()
+    }
+
+    async fn readable(&self) {
+        self.0.readable().await
+    }
+
+    async fn close(&self, code: u8) {
+        self.0.close(code).await
+    }
+
+    async fn shutdown_write(&self) {
+        self.0.shutdown_write().await
+    }
+
+    async fn is_close(&self) -> Option<u8> {
+        self.0.is_close().await
+    }
+}
+
+#[tokio::test]
+async fn migrate() {
+    const FIRST_ADDR: &str = "127.0.0.1:5560";
+    const SECOND_ADDR: &str = "127.0.0.1:5561";
+
+    let first = Listener::listen(FIRST_ADDR).await.unwrap();
+    let second = Listener::listen(SECOND_ADDR).await.unwrap();
+
+    let client = MigratableConn::new(Conn::connect(FIRST_ADDR).await.unwrap());
+    let server = MigratableConn::new(first.accept().await.unwrap().0);
+
+    let (client_conn, server_conn) = tokio::join!(
+        Builder::new().set_conn(client.clone()).set_encryption(encryption()).run(),
+        Builder::new().set_conn(server.clone()).set_encryption(encryption()).run(),
+    );
+    let (client_conn, server_conn) = (client_conn.unwrap(), server_conn.unwrap());
+    let old_addr = client_conn.local_addr().unwrap();
+
+    // Unread frames of the old transport go first
+    client_conn.write(b"before".to_vec()).await.unwrap();
+
+    let (migrated, accepted) = tokio::join!(client.migrate_to(SECOND_ADDR), second.accept());
+    migrated.unwrap();
+    server.migrate(accepted.unwrap().0).await;
+    assert_ne!(client_conn.local_addr().unwrap(), old_addr);
+
+    client_conn.write(b"after".to_vec()).await.unwrap();
+    assert_eq!(server_conn.read().await.unwrap(), b"before");
+    assert_eq!(server_conn.read().await.unwrap(), b"after");
+
+    server_conn.write(b"reply".to_vec()).await.unwrap();
+    assert_eq!(client_conn.read().await.unwrap(), b"reply");
+}
+
+#[tokio::test]
+async fn close_after_migration() {
+    const FIRST_ADDR: &str = "127.0.0.1:5562";
+    const SECOND_ADDR: &str = "127.0.0.1:5563";
+
+    let first = Listener::listen(FIRST_ADDR).await.unwrap();
+    let second = Listener::listen(SECOND_ADDR).await.unwrap();
+
+    let client = MigratableConn::new(Conn::connect(FIRST_ADDR).await.unwrap());
+    let server = MigratableConn::new(first.accept().await.unwrap().0);
+
+    let (client_conn, server_conn) = tokio::join!(
+        Builder::new().set_conn(client.clone()).run(),
+        Builder::new().set_conn(server.clone()).run(),
+    );
+    let (client_conn, server_conn) = (client_conn.unwrap(), server_conn.unwrap());
+
+    let (migrated, accepted) = tokio::join!(client.migrate_to(SECOND_ADDR), second.accept());
+    migrated.unwrap();
+    server.migrate(accepted.unwrap().0).await;
+
+    client_conn.close(42).await;
+    assert!(server_conn.read().await.is_none());
+    assert_eq!(server_conn.is_close().await, Some(42));
+}
+
+#[tokio::test]
+async fn drain_all_kinds() {
+    const FIRST_ADDR: &str = "127.0.0.1:5564";
+    const SECOND_ADDR: &str = "127.0.0.1:5565";
+    const KIND_A: u8 = 1;
+    const KIND_B: u8 = 2;
+
+    let first = Listener::listen(FIRST_ADDR).await.unwrap();
+    let second = Listener::listen(SECOND_ADDR).await.unwrap();
+
+    let old = Conn::connect(FIRST_ADDR).await.unwrap();
+    let server = MigratableConn::new(Uncounted(first.accept().await.unwrap().0));
+
+    for kind in [KIND_A, KIND_B] {
+        assert!(old.write(Frame::create(kind, &[0])).await.is_ok());
+        assert_eq!(&server.read(kind).await.unwrap()[3..], [0]);
+    }
+
+    // Both kinds have unread frames on the old transport
+    assert!(old.write(Frame::create(KIND_A, &[1])).await.is_ok());
+    assert!(old.write(Frame::create(KIND_B, &[2])).await.is_ok());
+    old.flush().await;
+    old.shutdown_write().await;
+
+    let new = Conn::connect(SECOND_ADDR).await.unwrap();
+    server.migrate(second.accept().await.unwrap().0).await;
+    assert!(new.write(Frame::create(KIND_A, &[3])).await.is_ok());
+    assert!(new.write(Frame::create(KIND_B, &[4])).await.is_ok());
+
+    assert_eq!(&server.read(KIND_A).await.unwrap()[3..], [1]);
+    assert_eq!(&server.read(KIND_A).await.unwrap()[3..], [3]);
+    assert_eq!(&server.read(KIND_B).await.unwrap()[3..], [2]);
+    assert_eq!(&server.read(KIND_B).await.unwrap()[3..], [4]);
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use cobra_rs::builder::builder::{Builder, ConnProvider};
+use cobra_rs::mem::Frame;
+use cobra_rs::transport::chaos::{ChaosConfig, ChaosConn};
+use cobra_rs::transport::multipath::MultipathConn;
+use cobra_rs::transport::tcp::{Conn, Listener};
+
+const KIND: u8 = 1;
+
+async fn connect(addrs: &[&str]) -> (Vec<Conn>, Vec<Conn>) {
+    let mut clients = Vec::new();
+    let mut servers = Vec::new();
+
+    for addr in addrs {
+        let listener = Listener::listen(addr).await.unwrap();
+        clients.push(Conn::connect(addr).await.unwrap());
+        servers.push(listener.accept().await.unwrap().0);
+    }
+
+    (clients, servers)
+}
+
+fn bond(conns: Vec<Conn>) -> MultipathConn {
+    conns.into_iter().fold(MultipathConn::new(), MultipathConn::add_path)
+}
+
+#[tokio::test]
+async fn restore_order() {
+    let (clients, servers) = connect(&["127.0.0.1:5570", "127.0.0.1:5571"]).await;
+
+    // Both paths reorder frames on their own
+    let client = clients.into_iter()
+        .enumerate()
+        .fold(MultipathConn::new(), |multipath, (seed, conn)| {
+            let config = ChaosConfig::new()
+                .set_reorder_rate(0.5)
+                .set_jitter(Duration::from_millis(2))
+                .set_seed(seed as u64 + 1);
+            multipath.add_path(ChaosConn::new(conn, config))
+        });
+    let server = bond(servers);
+
+    let writes = async {
+        for i in 0..200u8 {
+            assert!(client.write(Frame::create(KIND, &[i])).await.is_ok());
+        }
+        client.flush().await;
+    };
+    let reads = async {
+        for i in 0..200u8 {
+            assert_eq!(server.read(KIND).await.unwrap().get_body().to_vec(), vec![i]);
+        }
+    };
+    tokio::join!(writes, reads);
+}
+
+#[tokio::test]
+async fn survive_closed_path() {
+    let (clients, mut servers) = connect(&["127.0.0.1:5572", "127.0.0.1:5573"]).await;
+
+    let client = bond(clients);
+    servers.remove(0).close(1).await;
+    let server = bond(servers);
+
+    // Frames written to the closed path are moved to the open one
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    for i in 0..10u8 {
+        assert!(client.write(Frame::create(KIND, &[i])).await.is_ok());
+    }
+    for i in 0..10u8 {
+        assert_eq!(server.read(KIND).await.unwrap().get_body().to_vec(), vec![i]);
+    }
+
+    assert_eq!(client.open_paths(), 1);
+    assert_eq!(client.is_close().await, None);
+}
+
+#[tokio::test]
+async fn close_all_paths() {
+    let (clients, servers) = connect(&["127.0.0.1:5574", "127.0.0.1:5575"]).await;
+    let client = bond(clients);
+    let server = bond(servers);
+
+    client.close(7).await;
+
+    assert!(server.read(KIND).await.is_none());
+    assert_eq!(server.is_close().await, Some(7));
+    assert!(client.write(Frame::create(KIND, &[1])).await.is_err());
+}
+
+#[tokio::test]
+async fn build_over_paths() {
+    let (clients, servers) = connect(&["127.0.0.1:5576", "127.0.0.1:5577"]).await;
+
+    let (client, server) = tokio::join!(
+        Builder::new().set_conn(bond(clients)).run(),
+        Builder::new().set_conn(bond(servers)).run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    for i in 0..50u8 {
+        client.write(vec![i; 100]).await.unwrap();
+    }
+    for i in 0..50u8 {
+        assert_eq!(server.read().await.unwrap(), vec![i; 100]);
+    }
+}
+
+#[tokio::test]
+async fn unordered_kinds() {
+    const UNORDERED: u8 = 2;
+    let (clients, servers) = connect(&["127.0.0.1:5765", "127.0.0.1:5766"]).await;
+    let server = bond(servers).set_unordered_kinds(&[UNORDERED]);
+
+    // Frames with sequence number 0 are lost
+    for sequence in 1..=2u32 {
+        for &kind in &[KIND, UNORDERED] {
+            let body = [&sequence.to_be_bytes()[..], &[sequence as u8]].concat();
+            assert!(clients[1].write(Frame::create(kind, &body)).await.is_ok());
+        }
+    }
+
+    assert_eq!(server.read(UNORDERED).await.unwrap().get_body().to_vec(), vec![1]);
+    assert_eq!(server.read(UNORDERED).await.unwrap().get_body().to_vec(), vec![2]);
+    assert!(tokio::time::timeout(Duration::from_millis(50), server.read(KIND)).await.is_err());
+}
+
+#[tokio::test]
+async fn gap_fails_kind() {
+    let (clients, servers) = connect(&["127.0.0.1:5767", "127.0.0.1:5768"]).await;
+    let server = bond(servers);
+
+    // Frame with sequence number 0 is lost, later ones aren't kept forever
+    let writes = async {
+        for sequence in 1..=400u32 {
+            let body = [&sequence.to_be_bytes()[..], &[sequence as u8]].concat();
+            if clients[1].write(Frame::create(KIND, &body)).await.is_err() {
+                break;
+            }
+        }
+    };
+    let read = tokio::time::timeout(Duration::from_secs(1), server.read(KIND));
+    let (_, read) = tokio::join!(tokio::time::timeout(Duration::from_secs(1), writes), read);
+    assert!(read.unwrap().is_none());
+    assert!(server.read(KIND).await.is_none());
+}
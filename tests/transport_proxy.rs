@@ -0,0 +1,124 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::mem::Frame;
+use cobra_rs::transport::tcp::{ConnConfig, Listener};
+
+const KIND: u8 = 1;
+
+async fn listen(addr: &str) -> Listener {
+    Listener::listen_with_config(addr, ConnConfig::new().set_proxy_protocol(true)).await.unwrap()
+}
+
+// Sends header followed by a frame in one packet
+async fn send(addr: &str, header: &[u8]) -> TcpStream {
+    let mut socket = TcpStream::connect(addr).await.unwrap();
+    let mut bytes = header.to_vec();
+    bytes.extend_from_slice(&Frame::create(KIND, &[1, 2, 3]));
+    socket.write_all(&bytes).await.unwrap();
+    socket
+}
+
+fn v2(command: u8, family: u8, block: &[u8]) -> Vec<u8> {
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    header.extend_from_slice(&[0x20 | command, family]);
+    header.extend_from_slice(&(block.len() as u16).to_be_bytes());
+    header.extend_from_slice(block);
+    header
+}
+
+async fn assert_accepted(listener: &Listener, expected: SocketAddr) {
+    let (conn, addr) = listener.accept().await.unwrap();
+    assert_eq!(addr, expected);
+    assert_eq!(conn.peer_addr().unwrap(), expected);
+    assert_eq!(conn.read(KIND).await.unwrap().get_body()[..], [1, 2, 3]);
+}
+
+#[tokio::test]
+async fn v1() {
+    const ADDR: &str = "127.0.0.1:5660";
+    let listener = listen(ADDR).await;
+
+    let _client = send(ADDR, b"PROXY TCP4 203.0.113.7 127.0.0.1 40000 5660\r\n").await;
+    assert_accepted(&listener, "203.0.113.7:40000".parse().unwrap()).await;
+
+    let _client = send(ADDR, b"PROXY TCP6 2001:db8::1 ::1 40001 5660\r\n").await;
+    assert_accepted(&listener, "[2001:db8::1]:40001".parse().unwrap()).await;
+
+    // Balancer's own connections keep the socket address
+    let client = send(ADDR, b"PROXY UNKNOWN\r\n").await;
+    assert_accepted(&listener, client.local_addr().unwrap()).await;
+}
+
+#[tokio::test]
+async fn v2_addresses() {
+    const ADDR: &str = "127.0.0.1:5661";
+    let listener = listen(ADDR).await;
+
+    let block = [[198, 51, 100, 9], [127, 0, 0, 1]].concat();
+    let header = v2(0x01, 0x11, &[block, 40002_u16.to_be_bytes().to_vec(), 5661_u16.to_be_bytes().to_vec()].concat());
+    let _client = send(ADDR, &header).await;
+    assert_accepted(&listener, "198.51.100.9:40002".parse().unwrap()).await;
+
+    let source: std::net::Ipv6Addr = "2001:db8::2".parse().unwrap();
+    let block = [&source.octets()[..], &[0; 16], &40003_u16.to_be_bytes(), &5661_u16.to_be_bytes()].concat();
+    let _client = send(ADDR, &v2(0x01, 0x21, &block)).await;
+    assert_accepted(&listener, "[2001:db8::2]:40003".parse().unwrap()).await;
+
+    let client = send(ADDR, &v2(0x00, 0x00, &[])).await;
+    assert_accepted(&listener, client.local_addr().unwrap()).await;
+}
+
+#[tokio::test]
+async fn invalid_header() {
+    const ADDR: &str = "127.0.0.1:5662";
+    let listener = listen(ADDR).await;
+
+    let _invalid = send(ADDR, b"GET / HTTP/1.1\r\n").await;
+    let _client = send(ADDR, b"PROXY TCP4 203.0.113.8 127.0.0.1 40004 5662\r\n").await;
+
+    // The first socket is dropped
+    assert_accepted(&listener, "203.0.113.8:40004".parse().unwrap()).await;
+}
+
+#[tokio::test]
+async fn filter_proxied_addr() {
+    const ADDR: &str = "127.0.0.1:5663";
+    let listener = listen(ADDR).await;
+
+    let filtered = Arc::new(Mutex::new(Vec::new()));
+    let seen = filtered.clone();
+    listener.accept_filter(move |addr| {
+        seen.lock().unwrap().push(*addr);
+        addr.port() != 40005
+    });
+
+    let _rejected = send(ADDR, b"PROXY TCP4 203.0.113.9 127.0.0.1 40005 5663\r\n").await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let _client = send(ADDR, b"PROXY TCP4 203.0.113.9 127.0.0.1 40006 5663\r\n").await;
+    assert_accepted(&listener, "203.0.113.9:40006".parse().unwrap()).await;
+
+    let filtered = filtered.lock().unwrap();
+    assert!(filtered.contains(&"203.0.113.9:40005".parse().unwrap()));
+}
+
+#[tokio::test]
+async fn header_limit() {
+    const ADDR: &str = "127.0.0.1:5664";
+    let config = ConnConfig::new().set_proxy_protocol(true).set_proxy_header_limit(1);
+    let listener = Listener::listen_with_config(ADDR, config).await.unwrap();
+
+    // Holds the only header slot
+    let silent = TcpStream::connect(ADDR).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let _client = send(ADDR, b"PROXY TCP4 203.0.113.10 127.0.0.1 40007 5664\r\n").await;
+    assert!(tokio::time::timeout(Duration::from_millis(300), listener.accept()).await.is_err());
+
+    drop(silent);
+    assert_accepted(&listener, "203.0.113.10:40007".parse().unwrap()).await;
+}
@@ -0,0 +1,114 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use cobra_rs::builder::builder::{Builder, ConnProvider};
+use cobra_rs::debug::{Direction, FrameRecorder, RecordedFrame, RecordingReader};
+use cobra_rs::mem::Frame;
+use cobra_rs::transport::replay::ReplayConn;
+use cobra_rs::transport::tcp::{Conn, Listener};
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn recorded(offset: Duration, direction: Direction, kind: u8, payload: &[u8]) -> RecordedFrame {
+    RecordedFrame {
+        timestamp: SystemTime::UNIX_EPOCH + offset,
+        direction,
+        kind,
+        len: payload.len(),
+        payload: Some(payload.to_vec()),
+    }
+}
+
+#[tokio::test]
+async fn replay_inbound_frames() {
+    let conn = ReplayConn::with_speed(vec![
+        recorded(Duration::ZERO, Direction::Inbound, 5, b"first"),
+        recorded(Duration::ZERO, Direction::Outbound, 5, b"ignored"),
+        recorded(Duration::ZERO, Direction::Inbound, 6, b"second"),
+    ], f64::INFINITY);
+
+    assert_eq!(conn.read(5).await.unwrap().get_body(), &b"first"[..]);
+    assert_eq!(conn.read(6).await.unwrap().get_body(), &b"second"[..]);
+    assert!(conn.read(5).await.is_none());
+}
+
+#[tokio::test]
+async fn replay_accelerated_timing() {
+    let conn = ReplayConn::with_speed(vec![
+        recorded(Duration::ZERO, Direction::Inbound, 5, b"first"),
+        recorded(Duration::from_millis(400), Direction::Inbound, 5, b"second"),
+    ], 4.0);
+
+    let started = Instant::now();
+    conn.read(5).await.unwrap();
+    conn.read(5).await.unwrap();
+    let elapsed = started.elapsed();
+
+    assert!(elapsed >= Duration::from_millis(100));
+    assert!(elapsed < Duration::from_millis(400));
+}
+
+#[tokio::test]
+async fn replay_keeps_written_frames() {
+    let conn = ReplayConn::new(Vec::new());
+
+    assert!(conn.write(Frame::create(5, b"hello")).await.is_ok());
+    let written = conn.take_written();
+
+    assert_eq!(written.len(), 1);
+    assert_eq!(&written[0][..], &Frame::create(5, b"hello")[..]);
+    assert!(conn.take_written().is_empty());
+
+    conn.close(1).await;
+    assert_eq!(conn.is_close().await, Some(1));
+    assert!(conn.write(Frame::create(5, b"hello")).await.is_err());
+}
+
+#[tokio::test]
+async fn replay_recorded_session() {
+    const ADDR: &str = "127.0.0.1:5410";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let buffer = SharedBuffer::default();
+    let recorder = FrameRecorder::from_writer(buffer.clone()).unwrap().with_payload(true);
+
+    let (client, server) = tokio::join!(
+        Builder::new().set_conn(client).record_frames(recorder).run(),
+        Builder::new().set_conn(server).run(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    client.write(b"ping".to_vec()).await.unwrap();
+    assert_eq!(server.read().await.unwrap(), b"ping");
+    server.write(b"pong".to_vec()).await.unwrap();
+    assert_eq!(client.read().await.unwrap(), b"pong");
+
+    let recording = buffer.0.lock().unwrap().clone();
+    let frames = RecordingReader::new(&recording[..])
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+
+    let replayed = Builder::new()
+        .set_conn(ReplayConn::with_speed(frames, f64::INFINITY))
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(replayed.read().await.unwrap(), b"pong");
+}
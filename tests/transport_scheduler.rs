@@ -0,0 +1,197 @@
+use std::time::Duration;
+
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::mem::{Frame, HEADER_BYTES};
+use cobra_rs::transport::scheduler::{FifoScheduler, PriorityScheduler, Scheduler};
+use cobra_rs::transport::tcp::{Conn, ConnConfig};
+
+const LOW_KIND: u8 = 1;
+const HIGH_KIND: u8 = 2;
+
+fn drain(scheduler: &mut dyn Scheduler) -> Vec<(u8, u8)> {
+    std::iter::from_fn(|| scheduler.pop())
+        .map(|frame| (frame[HEADER_BYTES - 1], frame[HEADER_BYTES]))
+        .collect()
+}
+
+#[test]
+fn fifo() {
+    let mut scheduler = FifoScheduler::new();
+    scheduler.push(Frame::create(HIGH_KIND, &[1]));
+    scheduler.push(Frame::create(LOW_KIND, &[2]));
+    assert_eq!(scheduler.len(), 2);
+
+    assert_eq!(drain(&mut scheduler), vec![(HIGH_KIND, 1), (LOW_KIND, 2)]);
+    assert!(scheduler.is_empty());
+}
+
+#[test]
+fn priority() {
+    let mut scheduler = PriorityScheduler::new().set_priority(HIGH_KIND, 10);
+    scheduler.push(Frame::create(LOW_KIND, &[1]));
+    scheduler.push(Frame::create(HIGH_KIND, &[2]));
+    scheduler.push(Frame::create(LOW_KIND, &[3]));
+    scheduler.push(Frame::create(HIGH_KIND, &[4]));
+    assert_eq!(scheduler.len(), 4);
+
+    assert_eq!(drain(&mut scheduler), vec![(HIGH_KIND, 2), (HIGH_KIND, 4), (LOW_KIND, 1), (LOW_KIND, 3)]);
+    assert!(scheduler.is_empty());
+}
+
+#[tokio::test]
+async fn scheduled_writes() {
+    const ADDR: &str = "127.0.0.1:5580";
+
+    let listener = TcpListener::bind(ADDR).await.unwrap();
+    // Frames written within the coalescing delay are ordered by the scheduler
+    let config = ConnConfig::new()
+        .set_write_coalescing(Duration::from_millis(50))
+        .set_scheduler(|| PriorityScheduler::new().set_priority(HIGH_KIND, 10));
+    let conn = Conn::connect_with_config(ADDR, config).await.unwrap();
+    let (mut peer, _) = listener.accept().await.unwrap();
+
+    for (kind, body) in [(LOW_KIND, 1), (LOW_KIND, 2), (HIGH_KIND, 3)] {
+        assert!(conn.write(Frame::create(kind, &[body])).await.is_ok());
+    }
+    conn.flush().await;
+
+    let mut bytes = [0; 3 * (HEADER_BYTES + 1)];
+    peer.read_exact(&mut bytes).await.unwrap();
+    let frames: Vec<(u8, u8)> = bytes.chunks(HEADER_BYTES + 1).map(|frame| (frame[2], frame[3])).collect();
+    assert_eq!(frames, vec![(HIGH_KIND, 3), (LOW_KIND, 1), (LOW_KIND, 2)]);
+}
+
+#[tokio::test]
+async fn flush_waits_for_queued_frames() {
+    const ADDR: &str = "127.0.0.1:5581";
+
+    let listener = TcpListener::bind(ADDR).await.unwrap();
+    let config = ConnConfig::new()
+        .set_write_coalescing(Duration::from_millis(100))
+        .set_scheduler(FifoScheduler::new);
+    let conn = Conn::connect_with_config(ADDR, config).await.unwrap();
+    let (mut peer, _) = listener.accept().await.unwrap();
+
+    // Write completes once the frame is queued, flush once it's written
+    let started = std::time::Instant::now();
+    assert!(conn.write(Frame::create(LOW_KIND, &[1])).await.is_ok());
+    assert!(started.elapsed() < Duration::from_millis(100));
+    conn.flush().await;
+    assert!(started.elapsed() >= Duration::from_millis(100));
+
+    let mut bytes = [0; HEADER_BYTES + 1];
+    peer.read_exact(&mut bytes).await.unwrap();
+    assert_eq!(bytes[HEADER_BYTES], 1);
+}
+
+#[tokio::test]
+async fn shutdown_after_queued_frames() {
+    const ADDR: &str = "127.0.0.1:5582";
+
+    let listener = TcpListener::bind(ADDR).await.unwrap();
+    let config = ConnConfig::new()
+        .set_write_coalescing(Duration::from_millis(20))
+        .set_scheduler(FifoScheduler::new);
+    let conn = Conn::connect_with_config(ADDR, config).await.unwrap();
+    let (mut peer, _) = listener.accept().await.unwrap();
+
+    for body in 0..10 {
+        assert!(conn.write(Frame::create(LOW_KIND, &[body])).await.is_ok());
+    }
+    conn.shutdown_write().await;
+
+    let mut bytes = Vec::new();
+    peer.read_to_end(&mut bytes).await.unwrap();
+    let bodies: Vec<u8> = bytes.chunks(HEADER_BYTES + 1).take(10).map(|frame| frame[HEADER_BYTES]).collect();
+    assert_eq!(bodies, (0..10).collect::<Vec<u8>>());
+}
+
+#[tokio::test]
+async fn conflated_kinds() {
+    const STATE_KIND: u8 = 3;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = ConnConfig::new()
+        .set_write_coalescing(Duration::from_millis(50))
+        .set_conflated_kinds(&[STATE_KIND]);
+    let conn = Conn::connect_with_config(listener.local_addr().unwrap(), config).await.unwrap();
+    let (mut peer, _) = listener.accept().await.unwrap();
+
+    for (kind, body) in [(LOW_KIND, 1), (STATE_KIND, 2), (STATE_KIND, 3), (LOW_KIND, 4), (STATE_KIND, 5)] {
+        assert!(conn.write(Frame::create(kind, &[body])).await.is_ok());
+    }
+    // Replaced frames don't hold the flush
+    conn.flush().await;
+
+    // The latest state takes the place of the first queued one
+    let mut bytes = [0; 3 * (HEADER_BYTES + 1)];
+    peer.read_exact(&mut bytes).await.unwrap();
+    let frames: Vec<(u8, u8)> = bytes.chunks(HEADER_BYTES + 1).map(|frame| (frame[2], frame[3])).collect();
+    assert_eq!(frames, vec![(LOW_KIND, 1), (STATE_KIND, 5), (LOW_KIND, 4)]);
+
+    assert!(conn.write(Frame::create(STATE_KIND, &[6])).await.is_ok());
+    conn.flush().await;
+    let mut bytes = [0; HEADER_BYTES + 1];
+    peer.read_exact(&mut bytes).await.unwrap();
+    assert_eq!(bytes[HEADER_BYTES], 6);
+}
+
+#[tokio::test]
+async fn frame_ttl() {
+    const VOICE_KIND: u8 = 4;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    // Every frame waits for the coalescing delay, which outlives the TTL
+    let config = ConnConfig::new()
+        .set_write_coalescing(Duration::from_millis(50))
+        .set_frame_ttl(VOICE_KIND, Duration::from_millis(10));
+    let conn = Conn::connect_with_config(listener.local_addr().unwrap(), config).await.unwrap();
+    let (mut peer, _) = listener.accept().await.unwrap();
+
+    for (kind, body) in [(VOICE_KIND, 1), (LOW_KIND, 2), (VOICE_KIND, 3)] {
+        assert!(conn.write(Frame::create(kind, &[body])).await.is_ok());
+    }
+    // Expired frames don't hold the flush
+    conn.flush().await;
+    assert_eq!(conn.stats().expired_frames, 2);
+    assert_eq!(conn.stats().pending_writes, 0);
+
+    let mut bytes = [0; HEADER_BYTES + 1];
+    peer.read_exact(&mut bytes).await.unwrap();
+    assert_eq!((bytes[2], bytes[3]), (LOW_KIND, 2));
+}
+
+#[tokio::test]
+async fn clear_pending() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = ConnConfig::new()
+        .set_write_coalescing(Duration::from_millis(100))
+        .set_scheduler(FifoScheduler::new);
+    let conn = Conn::connect_with_config(listener.local_addr().unwrap(), config).await.unwrap();
+    let (mut peer, _) = listener.accept().await.unwrap();
+
+    for (kind, body) in [(LOW_KIND, 1), (HIGH_KIND, 2), (LOW_KIND, 3)] {
+        assert!(conn.write(Frame::create(kind, &[body])).await.is_ok());
+    }
+    let pending = conn.pending();
+    assert_eq!(pending.len(), 2);
+    assert_eq!((pending[&LOW_KIND].frames, pending[&LOW_KIND].bytes), (2, 2 * (HEADER_BYTES + 1)));
+    assert_eq!((pending[&HIGH_KIND].frames, pending[&HIGH_KIND].bytes), (1, HEADER_BYTES + 1));
+
+    assert_eq!(conn.clear_pending(LOW_KIND), 2);
+    assert_eq!(conn.clear_pending(LOW_KIND), 0);
+    assert!(!conn.pending().contains_key(&LOW_KIND));
+
+    // Frames queued after the clear are kept
+    assert!(conn.write(Frame::create(LOW_KIND, &[4])).await.is_ok());
+    conn.flush().await;
+    assert!(conn.pending().is_empty());
+
+    let mut bytes = [0; 2 * (HEADER_BYTES + 1)];
+    peer.read_exact(&mut bytes).await.unwrap();
+    let frames: Vec<(u8, u8)> = bytes.chunks(HEADER_BYTES + 1).map(|frame| (frame[2], frame[3])).collect();
+    assert_eq!(frames, vec![(HIGH_KIND, 2), (LOW_KIND, 4)]);
+}
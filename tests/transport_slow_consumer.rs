@@ -0,0 +1,73 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::builder::kind_conn::close_code::SLOW_CONSUMER;
+use cobra_rs::mem::Frame;
+use cobra_rs::transport::slow_consumer::{SlowConsumer, SlowConsumerPolicy};
+use cobra_rs::transport::tcp::{Conn, ConnConfig, Listener};
+
+const WINDOW: Duration = Duration::from_millis(100);
+const READ_KIND: u8 = 1;
+const STUCK_KIND: u8 = 2;
+
+async fn connect(addr: &str, policy: SlowConsumerPolicy) -> (Conn, Conn) {
+    let config = ConnConfig::new().set_slow_consumer_policy(policy);
+    let listener = Listener::listen_with_config(addr, config).await.unwrap();
+    let client = Conn::connect(addr).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    (client, server)
+}
+
+fn recorded() -> (Arc<Mutex<Vec<SlowConsumer>>>, SlowConsumerPolicy) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let handler_events = events.clone();
+    let policy = SlowConsumerPolicy::new(WINDOW)
+        .on_slow_consumer(move |event| handler_events.lock().unwrap().push(*event));
+    (events, policy)
+}
+
+#[tokio::test]
+async fn event() {
+    let (events, policy) = recorded();
+    let (client, server) = connect("127.0.0.1:5640", policy).await;
+
+    assert!(client.write(Frame::create(STUCK_KIND, &[1])).await.is_ok());
+    assert!(client.write(Frame::create(READ_KIND, &[2])).await.is_ok());
+    assert!(server.read(READ_KIND).await.is_some());
+    tokio::time::sleep(WINDOW * 2).await;
+
+    assert_eq!(*events.lock().unwrap(), vec![SlowConsumer { kind: STUCK_KIND, waited: WINDOW }]);
+
+    // Frame keeps waiting for its reader
+    assert_eq!(server.read(STUCK_KIND).await.unwrap().get_body()[..], [1]);
+    assert_eq!(server.is_close().await, None);
+}
+
+#[tokio::test]
+async fn prompt_reader() {
+    let (events, policy) = recorded();
+    let (client, server) = connect("127.0.0.1:5641", policy).await;
+
+    for body in 0..10 {
+        assert!(client.write(Frame::create(READ_KIND, &[body])).await.is_ok());
+        assert!(server.read(READ_KIND).await.is_some());
+    }
+    tokio::time::sleep(WINDOW * 2).await;
+
+    assert!(events.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn evict() {
+    let (events, policy) = recorded();
+    let (client, server) = connect("127.0.0.1:5642", policy.evict()).await;
+
+    assert!(client.write(Frame::create(STUCK_KIND, &[1])).await.is_ok());
+    tokio::time::sleep(WINDOW * 3).await;
+
+    assert_eq!(events.lock().unwrap().len(), 1);
+    assert_eq!(server.is_close().await, Some(SLOW_CONSUMER));
+    assert!(client.read(READ_KIND).await.is_none());
+    assert_eq!(client.is_close().await, Some(SLOW_CONSUMER));
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use cobra_rs::builder::builder::{Builder, ConnProvider};
+use cobra_rs::builder::profile::Profile;
+use cobra_rs::mem::Frame;
+use cobra_rs::transport::stream::StreamConn;
+use cobra_rs::transport::tcp::ConnConfig;
+use tokio::io::AsyncReadExt;
+
+#[tokio::test]
+async fn exchange_frames() {
+    let (first, second) = tokio::io::duplex(64);
+    let (first, second) = (StreamConn::new(first), StreamConn::new(second));
+
+    assert!(first.write(Frame::create(5, b"hello")).await.is_ok());
+    assert_eq!(second.read(5).await.unwrap().get_body(), &b"hello"[..]);
+
+    assert!(second.write(Frame::create(6, &[1; 1000])).await.is_ok());
+    assert_eq!(first.read(6).await.unwrap().get_body(), &[1; 1000][..]);
+}
+
+#[tokio::test]
+async fn build_low_bandwidth_conn() {
+    let (first, second) = tokio::io::duplex(64);
+    let profile = Profile::LowBandwidth;
+
+    let (first, second) = tokio::join!(
+        Builder::new().set_conn(StreamConn::with_config(first, profile.conn_config())).profile(profile).run(),
+        Builder::new().set_conn(StreamConn::with_config(second, profile.conn_config())).profile(profile).run(),
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+
+    first.write(b"ping".to_vec()).await.unwrap();
+    assert_eq!(second.read().await.unwrap(), b"ping");
+}
+
+#[tokio::test]
+async fn close_handshake() {
+    let (first, second) = tokio::io::duplex(64);
+    let (first, second) = (StreamConn::new(first), StreamConn::new(second));
+
+    first.close_with_reason(42, "bye").await;
+
+    assert!(second.read(5).await.is_none());
+    assert_eq!(second.is_close().await, Some(42));
+    assert_eq!(second.close_reason().await.as_deref(), Some("bye"));
+    assert_eq!(first.is_close().await, Some(42));
+    first.join().await.unwrap();
+}
+
+#[tokio::test]
+async fn close_on_eof() {
+    let (first, second) = tokio::io::duplex(64);
+    let first = StreamConn::new(first);
+    drop(second);
+
+    let read = tokio::time::timeout(Duration::from_secs(1), first.read(5)).await.unwrap();
+    assert!(read.is_none());
+}
+
+#[tokio::test]
+async fn write_coalescing() {
+    const DELAY: Duration = Duration::from_millis(50);
+
+    let (first, mut second) = tokio::io::duplex(64);
+    let first = Arc::new(StreamConn::with_config(first, ConnConfig::new().set_write_coalescing(DELAY)));
+
+    let started = Instant::now();
+    let writes: Vec<_> = (0..10_u8)
+        .map(|i| {
+            let first = first.clone();
+            tokio::spawn(async move { first.write(Frame::create(1, &[i])).await.is_ok() })
+        })
+        .collect();
+    for write in writes {
+        assert!(write.await.unwrap());
+    }
+    first.flush().await;
+
+    // Writes don't wait for each other's delay and are sent at once
+    assert!(started.elapsed() < DELAY * 3);
+    let mut bytes = [0; 64];
+    assert_eq!(second.read(&mut bytes).await.unwrap(), 40);
+}
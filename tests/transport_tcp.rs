@@ -1,3 +1,630 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::builder::kind_conn::close_code::{CANCELLED, CLOSED_BY_USER};
+use cobra_rs::mem::{Frame, MAX_BODY_LEN};
+use cobra_rs::net::Resolver;
+use cobra_rs::sync::{CancelToken, WriteError};
+use cobra_rs::transport::close_cause::CloseCause;
+use cobra_rs::transport::control::MAX_CLOSE_REASON_LEN;
+use cobra_rs::transport::scheduler::FifoScheduler;
+use cobra_rs::transport::tcp::{Conn, ConnConfig, Listener};
+use socket2::SockRef;
+use std::convert::TryInto;
+use std::future::poll_fn;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[tokio::test]
+async fn close_with_code() {
+    const ADDR: &str = "127.0.0.1:5010";
+    const KIND_A: u8 = 1;
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let _client = Conn::connect(ADDR).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    assert_eq!(conn.is_close().await, None);
+    conn.close(CLOSED_BY_USER).await;
+
+    assert_eq!(conn.is_close().await, Some(CLOSED_BY_USER));
+    assert!(conn.read(KIND_A).await.is_none());
+    match conn.write(Frame::create(KIND_A, &[1])).await {
+        Err(WriteError::Closed(_)) => {}
+        _ => panic!("write to closed connection succeeded"),
+    }
+}
+
+#[tokio::test]
+async fn write_coalescing() {
+    const ADDR: &str = "127.0.0.1:5011";
+    const KIND_A: u8 = 1;
+    const DELAY: Duration = Duration::from_millis(50);
+
+    let listener = tokio::net::TcpListener::bind(ADDR).await.unwrap();
+    let config = ConnConfig::new().set_write_coalescing(DELAY);
+    let client = Arc::new(Conn::connect_with_config(ADDR, config).await.unwrap());
+    let (mut server, _) = listener.accept().await.unwrap();
+
+    let started = Instant::now();
+    let writes: Vec<_> = (0..10_u8)
+        .map(|i| {
+            let client = client.clone();
+            tokio::spawn(async move { client.write(Frame::create(KIND_A, &[i])).await.is_ok() })
+        })
+        .collect();
+    for write in writes {
+        assert!(write.await.unwrap());
+    }
+    client.flush().await;
+
+    // Writes don't wait for each other's delay
+    assert!(started.elapsed() < DELAY * 3);
+
+    // Frames are sent with one write
+    let mut bytes = [0; 64];
+    let len = server.read(&mut bytes).await.unwrap();
+    assert_eq!(len, 40);
+    let mut sum = 0;
+    for frame in bytes[..len].chunks(4) {
+        assert_eq!(&frame[..3], &[0, 2, KIND_A]);
+        sum += frame[3];
+    }
+    assert_eq!(sum, 45);
+}
+
+#[tokio::test]
+async fn poll_read_write() {
+    const ADDR: &str = "127.0.0.1:5012";
+    const KIND_A: u8 = 1;
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Arc::new(Conn::connect(ADDR).await.unwrap());
+    let (conn, _) = listener.accept().await.unwrap();
+
+    tokio::spawn(async move {
+        let mut frame = Some(Frame::create(KIND_A, &[1, 2, 3]));
+        let result = poll_fn(|cx| client.poll_write_frame(cx, &mut frame)).await;
+        assert!(result.is_ok());
+    });
+
+    let frame = poll_fn(|cx| conn.poll_read_frame(cx, KIND_A)).await.unwrap();
+    assert_eq!(frame.get_body().to_vec(), vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn accept_filter() {
+    const ADDR: &str = "127.0.0.1:5013";
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let counter = AtomicUsize::new(0);
+    listener.accept_filter(move |_| counter.fetch_add(1, Ordering::SeqCst) > 0);
+
+    let _banned = Conn::connect(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (_conn, addr) = listener.accept().await.unwrap();
+    assert_eq!(addr, client.local_addr().unwrap());
+}
+
+#[tokio::test]
+async fn close_handshake_with_reason() {
+    const ADDR: &str = "127.0.0.1:5014";
+    const KIND_A: u8 = 1;
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    conn.close_with_reason(CLOSED_BY_USER, "going home").await;
+
+    assert!(client.read(KIND_A).await.is_none());
+    assert_eq!(client.is_close().await, Some(CLOSED_BY_USER));
+    assert_eq!(client.close_reason().await, Some("going home".to_string()));
+    assert_eq!(conn.close_reason().await, Some("going home".to_string()));
+    assert_eq!(client.close_cause().await, Some(CloseCause::Peer));
+    assert_eq!(conn.close_cause().await, Some(CloseCause::Local));
+}
+
+#[tokio::test]
+async fn close_handshake_with_long_reason() {
+    const KIND_A: u8 = 1;
+
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let client = Conn::connect(listener.local_addr().unwrap()).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    conn.close_with_reason(CLOSED_BY_USER, &"a".repeat(MAX_CLOSE_REASON_LEN + 1)).await;
+
+    // Both sides keep the reason cut to fit into the close frame
+    let reason = Some("a".repeat(MAX_CLOSE_REASON_LEN));
+    assert!(client.read(KIND_A).await.is_none());
+    assert_eq!(client.is_close().await, Some(CLOSED_BY_USER));
+    assert_eq!(client.close_reason().await, reason);
+    assert_eq!(conn.close_reason().await, reason);
+}
+
+#[tokio::test]
+async fn close_cause_of_socket() {
+    const KIND_A: u8 = 1;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // Peer closes the socket without a close frame
+    let client = Conn::connect(addr).await.unwrap();
+    drop(listener.accept().await.unwrap());
+    assert!(client.read(KIND_A).await.is_none());
+    assert_eq!(client.close_cause().await, Some(CloseCause::Eof));
+
+    // Peer resets the connection
+    let client = Conn::connect(addr).await.unwrap();
+    let (stream, _) = listener.accept().await.unwrap();
+    SockRef::from(&stream).set_linger(Some(Duration::ZERO)).unwrap();
+    drop(stream);
+    assert!(client.read(KIND_A).await.is_none());
+    match client.close_cause().await {
+        Some(CloseCause::Io { kind, os_error }) => {
+            assert_eq!(kind, io::ErrorKind::ConnectionReset);
+            assert!(os_error.is_some());
+        }
+        cause => panic!("unexpected close cause: {:?}", cause),
+    }
+}
+
+#[tokio::test]
+async fn half_close() {
+    const ADDR: &str = "127.0.0.1:5015";
+    const KIND_A: u8 = 1;
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    // Request then drain
+    assert!(client.write(Frame::create(KIND_A, &[1])).await.is_ok());
+    client.shutdown_write().await;
+    assert!(client.write(Frame::create(KIND_A, &[2])).await.is_err());
+
+    assert_eq!(conn.read(KIND_A).await.unwrap().get_body().to_vec(), vec![1]);
+    assert!(conn.read(KIND_A).await.is_none());
+
+    assert!(conn.write(Frame::create(KIND_A, &[3])).await.is_ok());
+    assert_eq!(client.read(KIND_A).await.unwrap().get_body().to_vec(), vec![3]);
+
+    conn.shutdown_write().await;
+    assert!(client.read(KIND_A).await.is_none());
+}
+
+#[tokio::test]
+async fn half_close_delivers_queued_frames() {
+    const ADDR: &str = "127.0.0.1:5026";
+    const KIND_A: u8 = 1;
+    const KIND_B: u8 = 2;
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    // Frames are read only after the shutdown has arrived
+    let writes = async {
+        assert!(client.write(Frame::create(KIND_A, &[1])).await.is_ok());
+        assert!(client.write(Frame::create(KIND_B, &[2])).await.is_ok());
+        client.shutdown_write().await;
+    };
+    let reads = async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(conn.read(KIND_B).await.unwrap().get_body().to_vec(), vec![2]);
+        assert_eq!(conn.read(KIND_A).await.unwrap().get_body().to_vec(), vec![1]);
+        assert!(conn.read(KIND_A).await.is_none());
+    };
+    tokio::join!(writes, reads);
+
+    conn.shutdown_write().await;
+    assert!(client.read(KIND_A).await.is_none());
+    assert_eq!(conn.is_close().await, Some(CLOSED_BY_USER));
+}
+
+#[tokio::test]
+async fn flush_and_linger() {
+    const ADDR: &str = "127.0.0.1:5016";
+    const KIND_A: u8 = 1;
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let config = ConnConfig::new()
+        .set_linger(Duration::from_secs(1));
+    let client = Arc::new(Conn::connect_with_config(ADDR, config).await.unwrap());
+    let (conn, _) = listener.accept().await.unwrap();
+
+    for i in 0..5_u8 {
+        let client = client.clone();
+        tokio::spawn(async move {
+            let _ = client.write(Frame::create(KIND_A, &[i])).await;
+        });
+    }
+    tokio::task::yield_now().await;
+
+    let reader = tokio::spawn(async move {
+        let mut count = 0;
+        while conn.read(KIND_A).await.is_some() {
+            count += 1;
+        }
+        count
+    });
+
+    client.flush().await;
+    client.close(CLOSED_BY_USER).await;
+    assert_eq!(reader.await.unwrap(), 5);
+}
+
+#[tokio::test]
+async fn listen_all() {
+    const ADDRS: [&str; 2] = ["127.0.0.1:5017", "127.0.0.1:5018"];
+
+    let listener = Listener::listen_all(&ADDRS).await.unwrap();
+
+    for addr in ADDRS.iter() {
+        let client = Conn::connect(addr).await.unwrap();
+        let (conn, peer_addr) = listener.accept().await.unwrap();
+
+        assert_eq!(conn.local_addr().unwrap().to_string(), *addr);
+        assert_eq!(peer_addr, client.local_addr().unwrap());
+    }
+}
+
+#[tokio::test]
+async fn listen_ephemeral() {
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    assert_ne!(addr.port(), 0);
+
+    let client = Conn::connect(addr).await.unwrap();
+    let (conn, peer_addr) = listener.accept().await.unwrap();
+
+    assert_eq!(conn.local_addr().unwrap(), addr);
+    assert_eq!(peer_addr, client.local_addr().unwrap());
+}
+
+#[tokio::test]
+async fn listen_all_ephemeral() {
+    let listener = Listener::listen_all(&["127.0.0.1:0", "127.0.0.2:0"]).await.unwrap();
+    let addrs = listener.local_addrs().unwrap();
+
+    assert_ne!(addrs[0].port(), 0);
+    assert_eq!(addrs[0].port(), addrs[1].port());
+    assert_eq!(listener.local_addr().unwrap(), addrs[0]);
+
+    for addr in addrs {
+        let _client = Conn::connect(addr).await.unwrap();
+        let (conn, _) = listener.accept().await.unwrap();
+        assert_eq!(conn.local_addr().unwrap(), addr);
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn listen_reuseport() {
+    let listeners = Listener::listen_reuseport("127.0.0.1:0", 4).await.unwrap();
+    let addr = listeners[0].local_addr().unwrap();
+
+    assert_eq!(listeners.len(), 4);
+    assert!(listeners.iter().all(|listener| listener.local_addr().unwrap() == addr));
+
+    let accepted = Arc::new(AtomicUsize::new(0));
+    for listener in listeners {
+        let accepted = accepted.clone();
+        tokio::spawn(async move {
+            while let Some((_conn, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+    }
+
+    let mut clients = Vec::new();
+    for _ in 0..16 {
+        clients.push(Conn::connect(addr).await.unwrap());
+    }
+    while accepted.load(Ordering::SeqCst) < clients.len() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    assert!(Listener::listen_reuseport("127.0.0.1:0", 0).await.is_err());
+}
+
+#[tokio::test]
+async fn listener_from_std() {
+    const ADDR: &str = "127.0.0.1:5019";
+
+    let listener = Listener::from_std(std::net::TcpListener::bind(ADDR).unwrap()).unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (_conn, peer_addr) = listener.accept().await.unwrap();
+
+    assert_eq!(peer_addr, client.local_addr().unwrap());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn listener_without_listen_fds() {
+    assert!(Listener::from_listen_fds(ConnConfig::new()).unwrap().is_none());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn listener_handoff() {
+    const ADDR: &str = "127.0.0.1:5020";
+
+    let old = Listener::listen(ADDR).await.unwrap();
+    let fds = old.export_fds().unwrap();
+    old.drain();
+    assert!(old.accept().await.is_none());
+
+    let new = unsafe { Listener::import_fds(&fds, ConnConfig::new()) }.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (_conn, peer_addr) = new.accept().await.unwrap();
+
+    assert_eq!(peer_addr, client.local_addr().unwrap());
+}
+
+#[tokio::test]
+async fn kinds_dont_block_each_other() {
+    const ADDR: &str = "127.0.0.1:5021";
+    const KIND_A: u8 = 1;
+    const KIND_B: u8 = 2;
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    // Nobody reads KIND_A, but KIND_B frames behind it are still delivered
+    for i in 0..10_u8 {
+        assert!(client.write(Frame::create(KIND_A, &[i])).await.is_ok());
+        assert!(client.write(Frame::create(KIND_B, &[i])).await.is_ok());
+    }
+
+    for i in 0..10_u8 {
+        let frame = tokio::time::timeout(Duration::from_secs(1), conn.read(KIND_B))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&frame[3..], &[i]);
+    }
+    assert_eq!(&conn.read(KIND_A).await.unwrap()[3..], &[0]);
+}
+
+#[tokio::test]
+async fn kinds_have_bounded_latency() {
+    const KIND_A: u8 = 1;
+    const KIND_B: u8 = 2;
+    const FRAMES: u64 = 100;
+    const BOUND: Duration = Duration::from_millis(100);
+
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let client = Conn::connect(listener.local_addr().unwrap()).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    // KIND_B frames carry the time they were written at, while nobody reads KIND_A
+    let started = Instant::now();
+    let writes = async {
+        for _ in 0..FRAMES {
+            assert!(client.write(Frame::create(KIND_A, &[0; 1024])).await.is_ok());
+            let sent = started.elapsed().as_micros() as u64;
+            assert!(client.write(Frame::create(KIND_B, &sent.to_be_bytes())).await.is_ok());
+        }
+    };
+    let reads = async {
+        for _ in 0..FRAMES {
+            let frame = conn.read(KIND_B).await.unwrap();
+            let sent = Duration::from_micros(u64::from_be_bytes(frame[3..].try_into().unwrap()));
+            assert!(started.elapsed() - sent < BOUND);
+        }
+    };
+    tokio::join!(writes, reads);
+}
+
+#[tokio::test]
+async fn unread_kind_past_high_water_mark() {
+    const KIND_A: u8 = 1;
+    const KIND_B: u8 = 2;
+    const MARK: usize = 16 * 1024;
+
+    let config = ConnConfig::new().set_recv_high_water_mark(MARK);
+    let listener = Listener::listen_with_config("127.0.0.1:0", config).await.unwrap();
+    let mut stream = tokio::net::TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    // Nobody reads KIND_A, which gets one and a half marks
+    for _ in 0..MARK * 3 / 2 / 1024 {
+        stream.write_all(&Frame::create(KIND_A, &[0; 1024])).await.unwrap();
+    }
+    stream.write_all(&Frame::create(KIND_B, &[1])).await.unwrap();
+
+    let frame = tokio::time::timeout(Duration::from_millis(500), conn.read(KIND_B))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&frame[3..], &[1]);
+    assert!(conn.stats().queued_bytes > MARK);
+}
+
+#[tokio::test]
+async fn reads_pause_at_high_water_mark() {
+    const KIND_A: u8 = 1;
+    const MARK: usize = 16 * 1024;
+    const FRAMES: usize = 48;
+
+    let config = ConnConfig::new().set_recv_high_water_mark(MARK);
+    let listener = Listener::listen_with_config("127.0.0.1:0", config).await.unwrap();
+    let mut stream = tokio::net::TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    // Three marks are sent, the socket keeps what doesn't fit into two of them
+    let frame = Frame::create(KIND_A, &[0; 1024]);
+    for _ in 0..FRAMES {
+        stream.write_all(&frame).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let paused = conn.stats().queued_bytes;
+    assert!(paused < MARK * 2 + frame.len(), "{} bytes queued", paused);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(conn.stats().queued_bytes, paused);
+
+    // Every read makes room, so the rest of the frames arrives
+    for _ in 0..FRAMES {
+        let frame = tokio::time::timeout(Duration::from_millis(500), conn.read(KIND_A))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.get_body().len(), 1024);
+    }
+}
+
+#[tokio::test]
+async fn unread_kind_doesnt_block_eof() {
+    const KIND_A: u8 = 1;
+    const KIND_B: u8 = 2;
+    const KIND_C: u8 = 3;
+
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let mut stream = tokio::net::TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    stream.write_all(&Frame::create(KIND_A, &[1])).await.unwrap();
+    stream.write_all(&Frame::create(KIND_B, &[2])).await.unwrap();
+    assert_eq!(&conn.read(KIND_B).await.unwrap()[3..], &[2]);
+    drop(stream);
+
+    // Readers of drained kinds aren't held by the unread KIND_A frame
+    let timeout = Duration::from_millis(500);
+    assert!(tokio::time::timeout(timeout, conn.read(KIND_B)).await.unwrap().is_none());
+    assert!(tokio::time::timeout(timeout, conn.read(KIND_C)).await.unwrap().is_none());
+    assert_eq!(conn.close_cause().await, Some(CloseCause::Eof));
+
+    // The frame is still delivered within the drain timeout
+    assert_eq!(&conn.read(KIND_A).await.unwrap()[3..], &[1]);
+    assert!(conn.read(KIND_A).await.is_none());
+}
+
+#[tokio::test]
+async fn unread_frames_dropped_after_drain_timeout() {
+    const KIND_A: u8 = 1;
+
+    let config = ConnConfig::new().set_drain_timeout(Duration::from_millis(50));
+    let listener = Listener::listen_with_config("127.0.0.1:0", config).await.unwrap();
+    let mut stream = tokio::net::TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    stream.write_all(&Frame::create(KIND_A, &[1])).await.unwrap();
+    drop(stream);
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(conn.read(KIND_A).await.is_none());
+    assert_eq!(conn.stats().queued_frames, 0);
+}
+
+#[tokio::test]
+async fn frame_larger_than_high_water_mark() {
+    const ADDR: &str = "127.0.0.1:5022";
+    const KIND_A: u8 = 1;
+
+    let listener = Listener::listen_with_config(ADDR, ConnConfig::new().set_recv_high_water_mark(16))
+        .await
+        .unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    let body = vec![7; 1024];
+    assert!(client.write(Frame::create(KIND_A, &body)).await.is_ok());
+    assert!(client.write(Frame::create(KIND_A, &body)).await.is_ok());
+
+    for _ in 0..2 {
+        assert_eq!(&conn.read(KIND_A).await.unwrap()[3..], &body[..]);
+    }
+}
+
+#[tokio::test]
+async fn urgent_frames_skip_queue() {
+    const ADDR: &str = "127.0.0.1:5023";
+    const KIND_A: u8 = 1;
+    const FRAMES: usize = 50;
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Arc::new(Conn::connect(ADDR).await.unwrap());
+    let (conn, _) = listener.accept().await.unwrap();
+
+    for _ in 0..FRAMES {
+        let client = client.clone();
+        tokio::spawn(async move {
+            let _ = client.write(Frame::create(KIND_A, &[0])).await;
+        });
+    }
+    tokio::task::yield_now().await;
+    assert!(client.write_urgent(Frame::create(KIND_A, &[1])).await.is_ok());
+
+    let mut position = None;
+    for i in 0..=FRAMES {
+        if conn.read(KIND_A).await.unwrap()[3] == 1 {
+            position = Some(i);
+        }
+    }
+    assert!(position.unwrap() < FRAMES / 2);
+}
+
+#[tokio::test]
+async fn urgent_frames_skip_coalescing() {
+    const ADDR: &str = "127.0.0.1:5027";
+    const KIND_A: u8 = 1;
+    const DELAY: Duration = Duration::from_secs(2);
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let config = ConnConfig::new().set_write_coalescing(DELAY);
+    let client = Conn::connect_with_config(ADDR, config).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    let start = Instant::now();
+    assert!(client.write_urgent(Frame::create(KIND_A, &[1])).await.is_ok());
+    assert_eq!(conn.read(KIND_A).await.unwrap()[3], 1);
+    assert!(start.elapsed() < DELAY / 4);
+}
+
+#[tokio::test]
+async fn cancel_listener_tree() {
+    const ADDR: &str = "127.0.0.1:5024";
+
+    let token = CancelToken::new();
+    let listener = Listener::listen_with_config(ADDR, ConnConfig::new().set_cancel_token(token.child()))
+        .await
+        .unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    token.cancel();
+
+    assert!(listener.accept().await.is_none());
+    assert!(client.read(1).await.is_none());
+    assert_eq!(client.is_close().await, Some(CANCELLED));
+    assert_eq!(conn.is_close().await, Some(CANCELLED));
+}
+
+#[tokio::test]
+async fn workers_exit_on_close() {
+    const ADDR: &str = "127.0.0.1:5025";
+    const KIND_A: u8 = 1;
+
+    let listener = Listener::listen(ADDR).await.unwrap();
+    let client = Conn::connect(ADDR).await.unwrap();
+    let (conn, _) = listener.accept().await.unwrap();
+
+    assert!(client.write(Frame::create(KIND_A, &[1])).await.is_ok());
+    assert!(conn.read(KIND_A).await.is_some());
+
+    client.close(CLOSED_BY_USER).await;
+    assert!(client.join().await.is_ok());
+    assert!(conn.join().await.is_ok());
+}
+
 // use cobra_rs::transport::listener::Listener;
 // use cobra_rs::transport::conn::Conn;
 // use cobra_rs::transport::frame::Frame;
@@ -152,3 +779,153 @@
 //
 //     assert!(conn.read(KIND_A).await.is_none());
 // }
+
+#[tokio::test]
+async fn write_cancellable() {
+    const FLOOD_KIND: u8 = 1;
+    const CANCELLED_KIND: u8 = 2;
+    const WRITTEN_KIND: u8 = 3;
+
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let client = Arc::new(Conn::connect(listener.local_addr().unwrap()).await.unwrap());
+    let (server, _) = listener.accept().await.unwrap();
+
+    // Server doesn't read yet, so the writer gets stuck on a full socket
+    let flooder = client.clone();
+    let flood = tokio::spawn(async move {
+        loop {
+            assert!(flooder.write(Frame::create(FLOOD_KIND, &[0; 60000])).await.is_ok());
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let ticket = client.write_cancellable(Frame::create(CANCELLED_KIND, b"stale"));
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(ticket.cancel().await);
+    flood.abort();
+
+    let server = Arc::new(server);
+    let drain = server.clone();
+    tokio::spawn(async move { while drain.read(FLOOD_KIND).await.is_some() {} });
+
+    let ticket = client.write_cancellable(Frame::create(WRITTEN_KIND, b"fresh"));
+    assert!(ticket.written().await);
+    assert_eq!(&server.read(WRITTEN_KIND).await.unwrap().get_body()[..], b"fresh");
+    assert!(tokio::time::timeout(Duration::from_millis(50), server.read(CANCELLED_KIND)).await.is_err());
+}
+
+#[tokio::test]
+async fn write_bytes() {
+    const KIND_A: u8 = 1;
+
+    for config in [ConnConfig::new(), ConnConfig::new().set_scheduler(FifoScheduler::new)] {
+        let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+        let client = Arc::new(Conn::connect_with_config(listener.local_addr().unwrap(), config).await.unwrap());
+        let (server, _) = listener.accept().await.unwrap();
+
+        // Large bodies are written in several parts
+        let body = Bytes::from((0..MAX_BODY_LEN).map(|i| i as u8).collect::<Vec<_>>());
+        let writer = client.clone();
+        let written = body.clone();
+        tokio::spawn(async move {
+            for _ in 0..10 {
+                writer.write_bytes(KIND_A, written.clone()).await.unwrap();
+            }
+            writer.write_bytes(KIND_A, Bytes::new()).await.unwrap();
+        });
+
+        for _ in 0..10 {
+            assert_eq!(server.read(KIND_A).await.unwrap().get_body(), body);
+        }
+        assert!(server.read(KIND_A).await.unwrap().get_body().is_empty());
+
+        let too_long = Bytes::from(vec![0; MAX_BODY_LEN + 1]);
+        assert_eq!(client.write_bytes(KIND_A, too_long).await.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        client.close(CLOSED_BY_USER).await;
+        assert_eq!(client.write_bytes(KIND_A, body).await.unwrap_err().kind(), io::ErrorKind::NotConnected);
+    }
+}
+
+#[tokio::test]
+async fn write_segments() {
+    const KIND_A: u8 = 1;
+
+    for config in [ConnConfig::new(), ConnConfig::new().set_scheduler(FifoScheduler::new)] {
+        let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+        let client = Conn::connect_with_config(listener.local_addr().unwrap(), config).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        let blob = Bytes::from(vec![7; 40000]);
+        let segments = vec![Bytes::from_static(b"head"), Bytes::new(), blob.clone()];
+        client.write_segments(KIND_A, segments).await.unwrap();
+
+        let body = server.read(KIND_A).await.unwrap().get_body();
+        assert_eq!(&body[..4], b"head");
+        assert_eq!(&body[4..], &blob[..]);
+
+        let too_long = vec![blob.clone(), blob];
+        assert_eq!(client.write_segments(KIND_A, too_long).await.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
+
+#[tokio::test]
+async fn probe_connectivity() {
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let client = Conn::connect(listener.local_addr().unwrap()).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    // Probes are answered without application reads
+    let rtt = client.probe_connectivity(Duration::from_secs(1)).await.unwrap();
+    assert!(rtt < Duration::from_secs(1));
+    assert!(server.probe_connectivity(Duration::from_secs(1)).await.is_ok());
+
+    server.close(CLOSED_BY_USER).await;
+    assert_eq!(client.is_close().await, Some(CLOSED_BY_USER));
+    let closed = client.probe_connectivity(Duration::from_secs(1)).await.unwrap_err();
+    assert_eq!(closed.kind(), std::io::ErrorKind::NotConnected);
+
+    // Plain TCP peer never answers
+    let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let client = Conn::connect(silent.local_addr().unwrap()).await.unwrap();
+    let _peer = silent.accept().await.unwrap();
+    let timed_out = client.probe_connectivity(Duration::from_millis(50)).await.unwrap_err();
+    assert_eq!(timed_out.kind(), std::io::ErrorKind::TimedOut);
+}
+
+// Resolves "service:1" to the address, counting lookups
+struct CountingResolver {
+    addr: SocketAddr,
+    lookups: AtomicUsize,
+}
+
+#[async_trait]
+impl Resolver for CountingResolver {
+    async fn resolve(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        if host != "service:1" {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "unknown service"));
+        }
+
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        // Refused address is skipped
+        Ok(vec!["127.0.0.1:1".parse().unwrap(), self.addr])
+    }
+}
+
+#[tokio::test]
+async fn connect_host() {
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let resolver = Arc::new(CountingResolver { addr: listener.local_addr().unwrap(), lookups: AtomicUsize::new(0) });
+
+    let config = ConnConfig::new().set_resolver(resolver.clone());
+    for _ in 0..2 {
+        let client = Conn::connect_host("service:1", config.clone()).await.unwrap();
+        let (_server, _) = listener.accept().await.unwrap();
+        assert_eq!(client.peer_addr().unwrap(), listener.local_addr().unwrap());
+    }
+    // Host isn't cached between connections
+    assert_eq!(resolver.lookups.load(Ordering::SeqCst), 2);
+
+    let unknown = Conn::connect_host("other:1", config).await.err().unwrap();
+    assert_eq!(unknown.kind(), io::ErrorKind::NotFound);
+}
@@ -0,0 +1,50 @@
+#![cfg(target_os = "linux")]
+
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures_core::Stream;
+
+use cobra_rs::transport::tcp::Listener;
+
+fn set_fd_limit(limit: libc::rlim_t) -> libc::rlim_t {
+    let mut rlimit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlimit) };
+    let previous = rlimit.rlim_cur;
+
+    rlimit.rlim_cur = limit;
+    assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &rlimit) }, 0);
+    previous
+}
+
+#[tokio::test]
+async fn accept_errors_back_off() {
+    let mut listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    let _client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+    // Accepted socket doesn't get a descriptor, so accept fails until the limit is raised.
+    // Reading the directory takes one more descriptor, it's free again once counted
+    let open = std::fs::read_dir("/proc/self/fd").unwrap().count() - 1;
+    let previous = set_fd_limit(open as libc::rlim_t);
+
+    let mut errors = 0;
+    let _ = tokio::time::timeout(Duration::from_millis(300), async {
+        while let Some(Err(_)) = poll_fn(|cx| Pin::new(&mut listener).poll_next(cx)).await {
+            errors += 1;
+        }
+    }).await;
+    set_fd_limit(previous);
+
+    assert!((1..20).contains(&errors), "{} accept errors", errors);
+
+    // The socket waits in the backlog and is accepted after the pause
+    loop {
+        let next = poll_fn(|cx| Pin::new(&mut listener).poll_next(cx));
+        match tokio::time::timeout(Duration::from_secs(2), next).await.unwrap() {
+            Some(Ok(_)) => break,
+            Some(Err(_)) => {}
+            None => panic!("listener was closed"),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+#![cfg(all(feature = "uring", target_os = "linux"))]
+
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::mem::Frame;
+use cobra_rs::transport::tcp::{Conn, ConnConfig, Listener};
+
+const KIND: u8 = 1;
+
+async fn connect(addr: &str, config: ConnConfig) -> (Conn, Conn) {
+    let listener = Listener::listen_with_config(addr, config.clone()).await.unwrap();
+    let client = Conn::connect_with_config(addr, config).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    (client, server)
+}
+
+#[tokio::test]
+async fn large_frames() {
+    let (client, server) = connect("127.0.0.1:5600", ConnConfig::new()).await;
+
+    // Frames are larger than a registered buffer
+    let writes = async {
+        for i in 0..20u8 {
+            assert!(client.write(Frame::create(KIND, &vec![i; 60_000])).await.is_ok());
+        }
+    };
+    let reads = async {
+        for i in 0..20u8 {
+            assert_eq!(server.read(KIND).await.unwrap().get_body().to_vec(), vec![i; 60_000]);
+        }
+    };
+    tokio::join!(writes, reads);
+}
+
+#[tokio::test]
+async fn many_connections() {
+    // More connections than registered buffers
+    let listener = Listener::listen("127.0.0.1:5601").await.unwrap();
+    let mut pairs = Vec::new();
+    for _ in 0..100 {
+        let client = Conn::connect("127.0.0.1:5601").await.unwrap();
+        pairs.push((client, listener.accept().await.unwrap().0));
+    }
+
+    for (i, (client, _)) in pairs.iter().enumerate() {
+        assert!(client.write(Frame::create(KIND, &[i as u8])).await.is_ok());
+    }
+    for (i, (_, server)) in pairs.iter().enumerate() {
+        assert_eq!(server.read(KIND).await.unwrap().get_body().to_vec(), vec![i as u8]);
+    }
+}
+
+#[tokio::test]
+async fn busy_buffers() {
+    // Concurrent transfers need more buffers than the ring has registered
+    let listener = Listener::listen("127.0.0.1:5604").await.unwrap();
+    let mut transfers = Vec::new();
+    for i in 0..100u8 {
+        let client = Conn::connect("127.0.0.1:5604").await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        transfers.push(tokio::spawn(async move {
+            let writes = async {
+                for _ in 0..5 {
+                    assert!(client.write(Frame::create(KIND, &vec![i; 60_000])).await.is_ok());
+                }
+            };
+            let reads = async {
+                for _ in 0..5 {
+                    assert_eq!(server.read(KIND).await.unwrap().get_body().to_vec(), vec![i; 60_000]);
+                }
+            };
+            tokio::join!(writes, reads);
+        }));
+    }
+
+    for transfer in transfers {
+        transfer.await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn close() {
+    let (client, server) = connect("127.0.0.1:5602", ConnConfig::new()).await;
+
+    client.close(3).await;
+    assert!(server.read(KIND).await.is_none());
+    assert_eq!(server.is_close().await, Some(3));
+}
+
+#[tokio::test]
+async fn disabled() {
+    let (client, server) = connect("127.0.0.1:5603", ConnConfig::new().set_uring(false)).await;
+
+    assert!(client.write(Frame::create(KIND, b"reactor")).await.is_ok());
+    assert_eq!(server.read(KIND).await.unwrap().get_body().to_vec(), b"reactor");
+}
@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cobra_rs::builder::builder::ConnProvider;
+use cobra_rs::builder::kind_conn::close_code::INTERNAL_ERROR;
+use cobra_rs::mem::Frame;
+use cobra_rs::transport::tcp::{Conn, ConnConfig, Listener};
+use cobra_rs::transport::watchdog::{StuckWorker, WatchdogPolicy, Worker, WorkerState};
+
+const PERIOD: Duration = Duration::from_millis(100);
+const FLOOD_KIND: u8 = 1;
+
+fn recorded() -> (Arc<Mutex<Vec<StuckWorker>>>, WatchdogPolicy) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let handler_events = events.clone();
+    let policy = WatchdogPolicy::new(PERIOD)
+        .on_stuck_worker(move |event| handler_events.lock().unwrap().push(*event));
+    (events, policy)
+}
+
+async fn connect(client: WatchdogPolicy, server: WatchdogPolicy) -> (Arc<Conn>, Conn) {
+    let listener = Listener::listen_with_config("127.0.0.1:0", ConnConfig::new().set_watchdog(server)).await.unwrap();
+    let config = ConnConfig::new().set_watchdog(client);
+    let client = Conn::connect_with_config(listener.local_addr().unwrap(), config).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    (Arc::new(client), server)
+}
+
+// Server doesn't read, so both workers get stuck once the buffers are full
+fn flood(client: &Arc<Conn>) {
+    let flooder = client.clone();
+    tokio::spawn(async move {
+        while flooder.write(Frame::create(FLOOD_KIND, &[0; 60000])).await.is_ok() {}
+    });
+}
+
+async fn wait_for_event(events: &Mutex<Vec<StuckWorker>>) -> StuckWorker {
+    for _ in 0..100 {
+        if let Some(event) = events.lock().unwrap().first() {
+            return *event;
+        }
+        tokio::time::sleep(PERIOD).await;
+    }
+    panic!("no stuck worker detected");
+}
+
+#[tokio::test]
+async fn stuck_workers() {
+    let (client_events, client_policy) = recorded();
+    let (server_events, server_policy) = recorded();
+    let (client, server) = connect(client_policy, server_policy).await;
+    flood(&client);
+
+    let event = wait_for_event(&client_events).await;
+    assert_eq!((event.worker, event.writer), (Worker::Writer, WorkerState::Socket));
+    assert_eq!(event.stalled, PERIOD);
+    assert!(event.pending_writes > 0);
+
+    let event = wait_for_event(&server_events).await;
+    assert_eq!((event.worker, event.reader), (Worker::Reader, WorkerState::Backpressure));
+    assert!(event.queued_frames > 0);
+
+    // Reported once per stall, the connection stays open
+    tokio::time::sleep(PERIOD * 3).await;
+    assert_eq!(client_events.lock().unwrap().len(), 1);
+    assert_eq!(client.is_close().await, None);
+    assert!(server.read(FLOOD_KIND).await.is_some());
+}
+
+#[tokio::test]
+async fn force_close() {
+    let (events, policy) = recorded();
+    let (client, _server) = connect(policy.force_close(), WatchdogPolicy::new(PERIOD)).await;
+    flood(&client);
+
+    wait_for_event(&events).await;
+    tokio::time::sleep(PERIOD).await;
+    assert_eq!(client.is_close().await, Some(INTERNAL_ERROR));
+}
+
+#[tokio::test]
+async fn idle_connection() {
+    let (client_events, client_policy) = recorded();
+    let (server_events, server_policy) = recorded();
+    let (client, server) = connect(client_policy, server_policy).await;
+
+    assert!(client.write(Frame::create(FLOOD_KIND, &[1])).await.is_ok());
+    assert!(server.read(FLOOD_KIND).await.is_some());
+    tokio::time::sleep(PERIOD * 4).await;
+
+    assert!(client_events.lock().unwrap().is_empty());
+    assert!(server_events.lock().unwrap().is_empty());
+}